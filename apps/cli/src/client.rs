@@ -0,0 +1,230 @@
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	future::{ready, Ready},
+	path::Path,
+	sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rspc::internal::jsonrpc::{
+	self, handle_json_rpc, OwnedMpscSender, RequestId, Sender, SubscriptionUpgrade,
+};
+use sd_core::{api::Router, Node};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Runs the procedures of the core API, so the CLI behaves exactly like the apps do
+pub enum Client {
+	/// Against a node started in this process. Only one node should use a data directory at a
+	/// time, so this can't be used while a daemon runs on the same one.
+	Local {
+		node: Arc<Node>,
+		router: Arc<Router>,
+	},
+	/// Against a node running as a daemon, through its REST API
+	Daemon {
+		http: reqwest::Client,
+		url: String,
+		token: String,
+	},
+}
+
+impl Client {
+	pub async fn start(data_dir: &Path) -> Result<Self> {
+		let (node, router) = Node::new(data_dir)
+			.await
+			.with_context(|| format!("unable to start the node at {}", data_dir.display()))?;
+
+		Ok(Self::Local { node, router })
+	}
+
+	pub fn connect(url: String, token: String) -> Self {
+		Self::Daemon {
+			http: reqwest::Client::new(),
+			url: url.trim_end_matches('/').to_string(),
+			token,
+		}
+	}
+
+	pub async fn shutdown(self) {
+		if let Self::Local { node, .. } = self {
+			node.shutdown().await;
+		}
+	}
+
+	pub async fn query<T: DeserializeOwned>(
+		&self,
+		procedure: &str,
+		input: impl Serialize,
+	) -> Result<T> {
+		self.exec("query", procedure, input).await
+	}
+
+	pub async fn mutation<T: DeserializeOwned>(
+		&self,
+		procedure: &str,
+		input: impl Serialize,
+	) -> Result<T> {
+		self.exec("mutation", procedure, input).await
+	}
+
+	/// Same as [`Client::query`], for procedures of a library
+	pub async fn library_query<T: DeserializeOwned>(
+		&self,
+		library_id: Uuid,
+		procedure: &str,
+		arg: impl Serialize,
+	) -> Result<T> {
+		self.query(procedure, json!({ "library_id": library_id, "arg": arg }))
+			.await
+	}
+
+	/// Same as [`Client::mutation`], for procedures of a library
+	pub async fn library_mutation<T: DeserializeOwned>(
+		&self,
+		library_id: Uuid,
+		procedure: &str,
+		arg: impl Serialize,
+	) -> Result<T> {
+		self.mutation(procedure, json!({ "library_id": library_id, "arg": arg }))
+			.await
+	}
+
+	/// Finds the library the user asked for, by id or name. Without one, the only library of
+	/// the node is picked.
+	pub async fn resolve_library(&self, library: Option<&str>) -> Result<Uuid> {
+		let libraries = self.query::<Vec<Value>>("libraries.list", ()).await?;
+
+		let matches = libraries
+			.iter()
+			.filter(|lib| match library {
+				Some(library) => {
+					lib["uuid"].as_str() == Some(library)
+						|| lib["config"]["name"].as_str() == Some(library)
+				}
+				None => true,
+			})
+			.filter_map(|lib| lib["uuid"].as_str().and_then(|id| id.parse().ok()))
+			.collect::<Vec<Uuid>>();
+
+		match (matches.as_slice(), library) {
+			([id], _) => Ok(*id),
+			([], Some(library)) => bail!("no library named or with the id '{library}'"),
+			([], None) => bail!("there are no libraries yet, create one with `library create`"),
+			(_, Some(library)) => bail!("multiple libraries are named '{library}', use its id"),
+			(_, None) => bail!("there are multiple libraries, pick one with `--library`"),
+		}
+	}
+
+	async fn exec<T: DeserializeOwned>(
+		&self,
+		method: &str,
+		procedure: &str,
+		input: impl Serialize,
+	) -> Result<T> {
+		match self {
+			Self::Local { node, router } => {
+				exec_local(node, router, method, procedure, input).await
+			}
+			Self::Daemon { http, url, token } => {
+				let url = format!("{url}/api/{procedure}");
+				let req = match method {
+					"query" => http
+						.get(url)
+						.query(&[("input", serde_json::to_string(&input)?)]),
+					_ => http.post(url).json(&input),
+				};
+
+				let resp = req
+					.bearer_auth(token)
+					.send()
+					.await
+					.context("unable to reach the daemon")?;
+
+				if !resp.status().is_success() {
+					let status = resp.status();
+					let body = resp.text().await.unwrap_or_default();
+					let message = serde_json::from_str::<Value>(&body)
+						.ok()
+						.and_then(|error| error["message"].as_str().map(str::to_string))
+						.unwrap_or(body);
+
+					bail!("{message} ({status})");
+				}
+
+				resp.json()
+					.await
+					.with_context(|| format!("unexpected response to '{procedure}'"))
+			}
+		}
+	}
+}
+
+/// Runs the procedure like the rspc transports would, then unwraps its JSON-RPC response
+async fn exec_local<T: DeserializeOwned>(
+	node: &Arc<Node>,
+	router: &Arc<Router>,
+	method: &str,
+	procedure: &str,
+	input: impl Serialize,
+) -> Result<T> {
+	let request = serde_json::from_value::<jsonrpc::Request>(json!({
+		"jsonrpc": "2.0",
+		"id": null,
+		"method": method,
+		"params": {
+			"path": procedure,
+			"input": input,
+		},
+	}))?;
+
+	let mut resp = None;
+	handle_json_rpc(
+		node.clone(),
+		request,
+		Cow::Borrowed(router),
+		CliSender { resp: &mut resp },
+	)
+	.await;
+
+	let mut result = resp
+		.and_then(|resp| serde_json::to_value(resp).ok())
+		.and_then(|mut resp| resp.get_mut("result").map(Value::take))
+		.ok_or_else(|| anyhow!("no response to '{procedure}'"))?;
+
+	let data = result.get_mut("data").map(Value::take).unwrap_or_default();
+
+	match result.get("type").and_then(Value::as_str) {
+		Some("error") => bail!(
+			"{}",
+			data.get("message")
+				.and_then(Value::as_str)
+				.unwrap_or("unknown error")
+		),
+		_ => serde_json::from_value(data)
+			.with_context(|| format!("unexpected response to '{procedure}'")),
+	}
+}
+
+/// Keeps the response to a single request, as the CLI doesn't use subscriptions
+struct CliSender<'a> {
+	resp: &'a mut Option<jsonrpc::Response>,
+}
+
+impl<'a> Sender<'a> for CliSender<'a> {
+	type SendFut = Ready<()>;
+	type SubscriptionMap = Arc<futures_locks::Mutex<HashMap<RequestId, oneshot::Sender<()>>>>;
+	type OwnedSender = OwnedMpscSender;
+
+	fn subscription(self) -> SubscriptionUpgrade<'a, Self> {
+		SubscriptionUpgrade::Unsupported(self)
+	}
+
+	fn send(self, resp: jsonrpc::Response) -> Self::SendFut {
+		*self.resp = Some(resp);
+		ready(())
+	}
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Subcommand;
+use serde_json::Value;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use crate::client::Client;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Subcommand)]
+pub enum JobsCommand {
+	/// List the recent jobs of the library
+	List,
+	/// Follow the progress of the running jobs until they are all done
+	Watch,
+	/// Pause a running job
+	Pause { job_id: Uuid },
+	/// Resume a paused job
+	Resume { job_id: Uuid },
+	/// Cancel a job
+	Cancel { job_id: Uuid },
+}
+
+impl JobsCommand {
+	pub async fn run(self, client: &Client, library: Option<&str>) -> Result<()> {
+		let library_id = client.resolve_library(library).await?;
+
+		match self {
+			Self::List => {
+				for job in reports(client, library_id).await? {
+					print_job(&job);
+				}
+			}
+			Self::Watch => wait_for_jobs(client, library_id).await?,
+			Self::Pause { job_id } => {
+				client
+					.library_mutation::<()>(library_id, "jobs.pause", job_id)
+					.await?
+			}
+			Self::Resume { job_id } => {
+				client
+					.library_mutation::<()>(library_id, "jobs.resume", job_id)
+					.await?
+			}
+			Self::Cancel { job_id } => {
+				client
+					.library_mutation::<()>(library_id, "jobs.cancel", job_id)
+					.await?
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Prints the progress of the unfinished jobs until none are left
+pub async fn wait_for_jobs(client: &Client, library_id: Uuid) -> Result<()> {
+	loop {
+		let active = reports(client, library_id)
+			.await?
+			.into_iter()
+			.filter(|job| matches!(job["status"].as_str(), Some("Queued" | "Running")))
+			.collect::<Vec<_>>();
+
+		if active.is_empty()
+			&& !client
+				.library_query::<bool>(library_id, "jobs.isActive", ())
+				.await?
+		{
+			println!("All jobs are done");
+			return Ok(());
+		}
+
+		for job in &active {
+			print_job(job);
+		}
+
+		sleep(POLL_INTERVAL).await;
+	}
+}
+
+/// Reports of the recent jobs, flattened out of their groups
+async fn reports(client: &Client, library_id: Uuid) -> Result<Vec<Value>> {
+	let groups = client
+		.library_query::<Vec<Value>>(library_id, "jobs.reports", ())
+		.await?;
+
+	Ok(groups
+		.into_iter()
+		.flat_map(|mut group| match group["jobs"].take() {
+			Value::Array(jobs) => jobs,
+			_ => vec![],
+		})
+		.collect())
+}
+
+fn print_job(job: &Value) {
+	println!(
+		"{}\t{}\t{}\t{}/{}\t{}",
+		job["id"].as_str().unwrap_or_default(),
+		job["name"].as_str().unwrap_or_default(),
+		job["status"].as_str().unwrap_or_default(),
+		job["completed_task_count"],
+		job["task_count"],
+		job["message"].as_str().unwrap_or_default()
+	);
+}
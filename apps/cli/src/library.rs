@@ -0,0 +1,43 @@
+use anyhow::Result;
+use clap::Subcommand;
+use serde_json::{json, Value};
+
+use crate::client::Client;
+
+#[derive(Subcommand)]
+pub enum LibraryCommand {
+	/// List the libraries of this node
+	List,
+	/// Create a new library
+	Create { name: String },
+}
+
+impl LibraryCommand {
+	pub async fn run(self, client: &Client) -> Result<()> {
+		match self {
+			Self::List => {
+				let libraries = client.query::<Vec<Value>>("libraries.list", ()).await?;
+
+				for library in libraries {
+					println!(
+						"{}\t{}",
+						library["uuid"].as_str().unwrap_or_default(),
+						library["config"]["name"].as_str().unwrap_or_default()
+					);
+				}
+			}
+			Self::Create { name } => {
+				let library = client
+					.mutation::<Value>("libraries.create", json!({ "name": name }))
+					.await?;
+
+				println!(
+					"Created library '{name}' with id {}",
+					library["uuid"].as_str().unwrap_or_default()
+				);
+			}
+		}
+
+		Ok(())
+	}
+}
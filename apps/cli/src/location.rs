@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde_json::{json, Value};
+
+use crate::{client::Client, jobs::wait_for_jobs};
+
+#[derive(Subcommand)]
+pub enum LocationCommand {
+	/// List the locations of the library
+	List,
+	/// Add a directory as a location of the library, then scan it
+	Add {
+		path: PathBuf,
+		/// Id of an indexer rule to apply to the location, can be repeated
+		#[arg(long = "rule")]
+		rules: Vec<i32>,
+	},
+	/// Rescan a location, picking up changes made while the node wasn't watching it
+	Scan {
+		location_id: i32,
+		/// Identifies the files again, as if they were new
+		#[arg(long)]
+		reidentify: bool,
+	},
+	/// Remove a location from the library, leaving its files untouched
+	Remove { location_id: i32 },
+}
+
+impl LocationCommand {
+	pub async fn run(self, client: &Client, library: Option<&str>) -> Result<()> {
+		let library_id = client.resolve_library(library).await?;
+
+		match self {
+			Self::List => {
+				let locations = client
+					.library_query::<Vec<Value>>(library_id, "locations.list", ())
+					.await?;
+
+				for location in locations {
+					println!(
+						"{}\t{}\t{}",
+						location["id"],
+						location["name"].as_str().unwrap_or_default(),
+						location["path"].as_str().unwrap_or_default()
+					);
+				}
+			}
+			Self::Add { path, rules } => {
+				// The core resolves paths from its own working directory
+				let path = path
+					.canonicalize()
+					.with_context(|| format!("unable to find {}", path.display()))?;
+
+				client
+					.library_mutation::<()>(
+						library_id,
+						"locations.create",
+						json!({
+							"path": path,
+							"dry_run": false,
+							"indexer_rules_ids": rules,
+						}),
+					)
+					.await?;
+
+				println!("Added location at {}, scanning it", path.display());
+				wait_for_jobs(client, library_id).await?;
+			}
+			Self::Scan {
+				location_id,
+				reidentify,
+			} => {
+				client
+					.library_mutation::<()>(
+						library_id,
+						"locations.fullRescan",
+						json!({
+							"location_id": location_id,
+							"reidentify_objects": reidentify,
+						}),
+					)
+					.await?;
+
+				println!("Scanning location {location_id}");
+				wait_for_jobs(client, library_id).await?;
+			}
+			Self::Remove { location_id } => {
+				client
+					.library_mutation::<()>(library_id, "locations.delete", location_id)
+					.await?;
+
+				println!("Removed location {location_id}");
+			}
+		}
+
+		Ok(())
+	}
+}
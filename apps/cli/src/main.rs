@@ -1,85 +1,102 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use indoc::printdoc;
-use sd_crypto::header::file::FileHeader;
-use std::path::PathBuf;
-use tokio::fs::File;
+use std::{env, path::PathBuf};
 
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use crate::client::Client;
+
+mod client;
+mod header;
+mod jobs;
+mod library;
+mod location;
+#[cfg(unix)]
+mod mount;
+mod search;
+mod token;
+
+/// Manages Spacedrive libraries from a terminal, for servers and NAS without a GUI
 #[derive(Parser)]
+#[command(name = "sd-cli", version)]
 struct Args {
-	#[arg(help = "the file path to get details for")]
-	path: PathBuf,
+	/// Data directory of the node, defaults to `$DATA_DIR` or `./sdserver_data`
+	#[arg(long, global = true)]
+	data_dir: Option<PathBuf>,
+	/// Url of a node running as a daemon, like `http://localhost:8080`, instead of starting one
+	#[arg(long, global = true, env = "SD_DAEMON", requires = "token")]
+	daemon: Option<String>,
+	/// API token issued by the daemon
+	#[arg(long, global = true, env = "SD_TOKEN", hide_env_values = true)]
+	token: Option<String>,
+	/// Id or name of the library to use, only needed when there are several
+	#[arg(long, short, global = true, env = "SD_LIBRARY")]
+	library: Option<String>,
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Manage libraries
+	#[command(subcommand)]
+	Library(library::LibraryCommand),
+	/// Manage the locations of a library
+	#[command(subcommand)]
+	Location(location::LocationCommand),
+	/// Search the files of a library
+	Search(search::SearchArgs),
+	/// Mount the locations and tags of a library as a read-only filesystem, until interrupted
+	#[cfg(unix)]
+	Mount(mount::MountArgs),
+	/// Monitor the jobs of a library
+	#[command(subcommand)]
+	Jobs(jobs::JobsCommand),
+	/// Manage the tokens clients of the node as a daemon authenticate with
+	#[command(subcommand)]
+	Token(token::TokenCommand),
+	/// Print the details of the header of an encrypted file
+	Header {
+		#[arg(help = "the file path to get details for")]
+		path: PathBuf,
+	},
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
 	let args = Args::parse();
 
-	let mut reader = File::open(args.path).await.context("unable to open file")?;
-	let (header, aad) = FileHeader::from_reader(&mut reader).await?;
-	print_crypto_details(&header, &aad);
+	// Inspecting a file doesn't need a node
+	if let Command::Header { path } = &args.command {
+		return header::inspect(path).await;
+	}
 
-	Ok(())
-}
+	let client = match (args.daemon, args.token) {
+		(Some(url), Some(token)) => Client::connect(url, token),
+		_ => {
+			let data_dir = match args.data_dir {
+				Some(data_dir) => data_dir,
+				None => env::var("DATA_DIR")
+					.map(PathBuf::from)
+					.or_else(|_| env::current_dir().map(|dir| dir.join("sdserver_data")))?,
+			};
 
-fn print_crypto_details(header: &FileHeader, aad: &[u8]) {
-	printdoc! {"
-        Header version: {version}
-        Encryption algorithm: {algorithm}
-        AAD (hex): {hex}
-    ",
-		version = header.version,
-		algorithm = header.algorithm,
-		hex = hex::encode(aad)
+			Client::start(&data_dir).await?
+		}
 	};
+	let library = args.library.as_deref();
 
-	header.keyslots.iter().enumerate().for_each(|(i, k)| {
-		printdoc! {"
-            Keyslot {index}:
-              Version: {version}
-              Algorithm: {algorithm}
-              Hashing algorithm: {hashing_algorithm}
-              Salt (hex): {salt}
-              Master Key (hex, encrypted): {master}
-              Master key nonce (hex): {nonce}
-        ",
-			index = i + i,
-			version = k.version,
-			algorithm = k.algorithm,
-			hashing_algorithm = k.hashing_algorithm,
-			salt = hex::encode(&*k.salt),
-			master = hex::encode(&*k.master_key),
-			nonce = hex::encode(k.nonce)
-		};
-	});
+	let res = match args.command {
+		Command::Library(command) => command.run(&client).await,
+		Command::Location(command) => command.run(&client, library).await,
+		Command::Search(search) => search.run(&client, library).await,
+		#[cfg(unix)]
+		Command::Mount(mount) => mount.run(&client, library).await,
+		Command::Jobs(command) => command.run(&client, library).await,
+		Command::Token(command) => command.run(&client).await,
+		Command::Header { .. } => unreachable!("handled before starting the node"),
+	};
 
-	header.metadata.iter().for_each(|m| {
-		printdoc! {"
-            Metadata:
-              Version: {version}
-              Algorithm: {algorithm}
-              Encrypted size: {size}
-              Nonce (hex): {nonce}
-        ",
-			version = m.version,
-			algorithm = m.algorithm,
-			size = m.metadata.len(),
-			nonce = hex::encode(m.metadata_nonce)
-		}
-	});
+	client.shutdown().await;
 
-	header.preview_media.iter().for_each(|p| {
-		printdoc! {"
-            Preview Media:
-              Version: {version}
-              Algorithm: {algorithm}
-              Encrypted size: {size}
-              Nonce (hex): {nonce}
-        ",
-			version = p.version,
-			algorithm = p.algorithm,
-			size = p.media.len(),
-			nonce = hex::encode(p.media_nonce)
-		};
-	});
+	res
 }
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Args;
+use sd_core::mount::mount_library;
+
+use crate::client::Client;
+
+#[derive(Args)]
+pub struct MountArgs {
+	/// Existing, empty directory the library is mounted at
+	mountpoint: PathBuf,
+}
+
+impl MountArgs {
+	pub async fn run(self, client: &Client, library: Option<&str>) -> Result<()> {
+		let Client::Local { node, .. } = client else {
+			bail!("the library can only be mounted by a node started by the CLI, not through a daemon");
+		};
+
+		let library_id = client.resolve_library(library).await?;
+
+		let session = mount_library(node.clone(), library_id, &self.mountpoint).await?;
+
+		println!(
+			"Mounted library '{library_id}' read-only at '{}', press Ctrl+C to unmount it",
+			self.mountpoint.display()
+		);
+
+		tokio::signal::ctrl_c().await?;
+
+		drop(session);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::client::Client;
+
+#[derive(Args)]
+pub struct SearchArgs {
+	/// Words the names of the files must contain
+	query: String,
+	/// Only search in this location
+	#[arg(long)]
+	location: Option<i32>,
+	/// Only search files with this extension
+	#[arg(long)]
+	extension: Option<String>,
+	/// Maximum number of results
+	#[arg(long, default_value_t = 100)]
+	take: i32,
+}
+
+impl SearchArgs {
+	pub async fn run(self, client: &Client, library: Option<&str>) -> Result<()> {
+		let library_id = client.resolve_library(library).await?;
+
+		let results = client
+			.library_query::<Value>(
+				library_id,
+				"search.paths",
+				json!({
+					"take": self.take,
+					"filter": {
+						"search": self.query,
+						"locationId": self.location,
+						"extension": self.extension,
+					},
+				}),
+			)
+			.await?;
+
+		for result in results["items"].as_array().into_iter().flatten() {
+			let file_path = &result["item"];
+
+			let name = match file_path["extension"].as_str() {
+				Some(extension) if !extension.is_empty() => {
+					format!(
+						"{}.{extension}",
+						file_path["name"].as_str().unwrap_or_default()
+					)
+				}
+				_ => file_path["name"].as_str().unwrap_or_default().to_string(),
+			};
+
+			println!(
+				"{}\t{}{name}",
+				file_path["location_id"],
+				file_path["materialized_path"].as_str().unwrap_or_default()
+			);
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use clap::{Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::client::Client;
+
+#[derive(Subcommand)]
+pub enum TokenCommand {
+	/// List the tokens issued to the clients of the node
+	List,
+	/// Issue a token for a new client of the daemon
+	Issue {
+		name: String,
+		/// What the client can do, can be repeated
+		#[arg(long = "scope", value_enum, required = true)]
+		scopes: Vec<Scope>,
+		/// Requests per minute allowed to the client, 600 if not set
+		#[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+		rate_limit: Option<u32>,
+	},
+	/// Revoke a token, so new requests with it are rejected
+	Revoke { token_id: Uuid },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Scope {
+	Read,
+	Write,
+	#[value(name = "files:write")]
+	FilesWrite,
+	#[value(name = "jobs:run")]
+	JobsRun,
+}
+
+impl TokenCommand {
+	pub async fn run(self, client: &Client) -> Result<()> {
+		match self {
+			Self::List => {
+				let tokens = client.query::<Vec<Value>>("apiTokens.list", ()).await?;
+
+				for token in tokens {
+					println!(
+						"{}\t{}\t{}\t{}",
+						token["id"].as_str().unwrap_or_default(),
+						token["name"].as_str().unwrap_or_default(),
+						token["scopes"],
+						token["rate_limit"]
+							.as_u64()
+							.map_or("default rate limit".to_string(), |limit| format!(
+								"{limit}/min"
+							))
+					);
+				}
+			}
+			Self::Issue {
+				name,
+				scopes,
+				rate_limit,
+			} => {
+				let scopes = scopes
+					.into_iter()
+					.map(|scope| match scope {
+						Scope::Read => "read",
+						Scope::Write => "write",
+						Scope::FilesWrite => "files:write",
+						Scope::JobsRun => "jobs:run",
+					})
+					.collect::<Vec<_>>();
+
+				let issued = client
+					.mutation::<Value>(
+						"apiTokens.issue",
+						json!({ "name": name, "scopes": scopes, "rate_limit": rate_limit }),
+					)
+					.await?;
+
+				println!(
+					"Issued token {} for '{name}', it won't be shown again:\n{}",
+					issued["token"]["id"].as_str().unwrap_or_default(),
+					issued["secret"].as_str().unwrap_or_default()
+				);
+			}
+			Self::Revoke { token_id } => {
+				client.mutation::<()>("apiTokens.revoke", token_id).await?;
+
+				println!("Revoked token {token_id}");
+			}
+		}
+
+		Ok(())
+	}
+}
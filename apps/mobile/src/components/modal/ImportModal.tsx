@@ -1,7 +1,7 @@
 import { forwardRef, useCallback } from 'react';
 import { Alert, Text, View } from 'react-native';
 import DocumentPicker from 'react-native-document-picker';
-import { useLibraryMutation } from '@sd/client';
+import { extractInfoRSPCError, useLibraryMutation } from '@sd/client';
 import { Modal, ModalRef } from '~/components/layout/Modal';
 import { Button } from '~/components/primitive/Button';
 import useForwardedRef from '~/hooks/useForwardedRef';
@@ -19,11 +19,11 @@ const ImportModal = forwardRef<ModalRef, unknown>((_, ref) => {
 
 	const createLocation = useLibraryMutation('locations.create', {
 		onError: (error, variables) => {
-			switch (error.message) {
-				case 'NEED_RELINK':
+			switch (extractInfoRSPCError(error)?.coreError?.code) {
+				case 'LOCATION_NEEDS_RELINK':
 					if (!variables.dry_run) relinkLocation.mutate(variables.path);
 					break;
-				case 'ADD_LIBRARY':
+				case 'LOCATION_IN_OTHER_LIBRARY':
 					addLocationToLibrary.mutate(variables);
 					break;
 				default:
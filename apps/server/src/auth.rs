@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use axum::{
+	extract::State,
+	http::{header, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sd_core::{ApiAuthError, ApiToken, ApiTokenScope, Node};
+
+/// Authenticates the clients of the server, which send their token as an
+/// `Authorization: Bearer {token}` header, or a `token` query parameter for websockets. Clients
+/// only supporting basic authentication, like the WebDAV ones of OSes, send it as the password. Tokens
+/// are either the one set through `REST_API_TOKEN`, which has every scope, or issued by the node,
+/// which also rate limits them.
+#[derive(Clone)]
+pub struct Auth {
+	node: Arc<Node>,
+	static_token: Option<Arc<String>>,
+}
+
+impl Auth {
+	pub fn new(node: Arc<Node>, static_token: Option<String>) -> Self {
+		Self {
+			node,
+			static_token: static_token.map(Arc::new),
+		}
+	}
+
+	/// Guard for the endpoints which the clients can only use with all these scopes
+	pub fn require(&self, scopes: &'static [ApiTokenScope]) -> Guard {
+		Guard {
+			auth: self.clone(),
+			scopes,
+		}
+	}
+
+	async fn authenticate(&self, candidate: &str) -> Result<Client, ApiAuthError> {
+		if let Some(token) = &self.static_token {
+			if constant_time_eq(candidate, token) {
+				return Ok(Client(None));
+			}
+		}
+
+		self.node
+			.authorize_api_request(candidate)
+			.await
+			.map(|token| Client(Some(token)))
+	}
+}
+
+#[derive(Clone)]
+pub struct Guard {
+	auth: Auth,
+	scopes: &'static [ApiTokenScope],
+}
+
+/// Client a request was authenticated as, with the token issued to it or `None` for the static
+/// token
+#[derive(Clone)]
+pub struct Client(Option<ApiToken>);
+
+impl Client {
+	fn has_scope(&self, scope: ApiTokenScope) -> bool {
+		self.0.as_ref().map_or(true, |token| token.has_scope(scope))
+	}
+
+	/// Rejects the request unless the token has the scope
+	pub fn require(&self, scope: ApiTokenScope) -> Result<(), Response> {
+		if self.has_scope(scope) {
+			Ok(())
+		} else {
+			Err(forbidden())
+		}
+	}
+
+	/// Rejects the request unless the token allows running the procedure of the core API
+	pub fn require_procedure(&self, procedure: &str, is_mutation: bool) -> Result<(), Response> {
+		match &self.0 {
+			Some(token) if !token.allows(procedure, is_mutation) => Err(forbidden()),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Middleware rejecting the requests without a valid token with the scopes of the guard
+pub async fn require_token<B>(
+	State(guard): State<Guard>,
+	mut req: Request<B>,
+	next: Next<B>,
+) -> Response {
+	let authorization = req
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok());
+
+	let candidate = authorization
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(ToString::to_string)
+		.or_else(|| authorization.and_then(basic_auth_password))
+		.or_else(|| {
+			req.uri().query().and_then(|query| {
+				query
+					.split('&')
+					.find_map(|p| p.strip_prefix("token="))
+					.map(ToString::to_string)
+			})
+		});
+
+	let client = match candidate {
+		Some(candidate) => guard.auth.authenticate(&candidate).await,
+		None => Err(ApiAuthError::InvalidToken),
+	};
+
+	let client = match client {
+		Ok(client) => client,
+		Err(e @ ApiAuthError::InvalidToken) => {
+			return (
+				StatusCode::UNAUTHORIZED,
+				[(header::WWW_AUTHENTICATE, r#"Basic realm="Spacedrive""#)],
+				e.to_string(),
+			)
+				.into_response()
+		}
+		Err(e @ ApiAuthError::RateLimited { retry_after }) => {
+			return (
+				StatusCode::TOO_MANY_REQUESTS,
+				[(
+					header::RETRY_AFTER,
+					retry_after.as_secs().max(1).to_string(),
+				)],
+				e.to_string(),
+			)
+				.into_response()
+		}
+	};
+
+	if !guard.scopes.iter().all(|&scope| client.has_scope(scope)) {
+		return forbidden();
+	}
+
+	req.extensions_mut().insert(client);
+
+	next.run(req).await
+}
+
+/// Password of an `Authorization: Basic {base64 of user:password}` header, the user being ignored
+fn basic_auth_password(authorization: &str) -> Option<String> {
+	let credentials = STANDARD
+		.decode(authorization.strip_prefix("Basic ")?)
+		.ok()
+		.and_then(|credentials| String::from_utf8(credentials).ok())?;
+
+	credentials
+		.split_once(':')
+		.map(|(_, password)| password.to_string())
+}
+
+fn forbidden() -> Response {
+	(
+		StatusCode::FORBIDDEN,
+		"the API token doesn't allow this request",
+	)
+		.into_response()
+}
+
+/// Compares tokens without leaking how much of them matched through timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+	a.len() == b.len()
+		&& a.bytes()
+			.zip(b.bytes())
+			.fold(0, |acc, (a, b)| acc | (a ^ b))
+			== 0
+}
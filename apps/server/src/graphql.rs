@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use async_graphql_axum::GraphQL;
+use axum::{middleware, routing::post_service};
+use sd_core::{graphql::create_graphql_schema, ApiTokenScope, Node};
+
+use crate::auth::{require_token, Auth};
+
+/// Serves the read-only GraphQL schema of the core, which requires an API token like the REST
+/// gateway
+pub fn router(node: Arc<Node>, auth: Auth) -> axum::Router {
+	axum::Router::new()
+		.route("/", post_service(GraphQL::new(create_graphql_schema(node))))
+		.route_layer(middleware::from_fn_with_state(
+			auth.require(&[ApiTokenScope::Read]),
+			require_token,
+		))
+}
@@ -1,10 +1,15 @@
 use std::{env, net::SocketAddr, path::Path};
 
-use axum::routing::get;
-use sd_core::{custom_uri::create_custom_uri_endpoint, Node};
+use axum::{middleware, routing::get};
+use sd_core::{custom_uri::create_custom_uri_endpoint, ApiTokenScope, Node};
 use tracing::info;
 
+mod auth;
+mod graphql;
+mod rest;
+mod upload;
 mod utils;
+mod webdav;
 
 #[cfg(feature = "assets")]
 static ASSETS_DIR: include_dir::Dir<'static> =
@@ -32,6 +37,12 @@ async fn main() {
 		.map(|port| port.parse::<u16>().unwrap_or(8080))
 		.unwrap_or(8080);
 
+	// As a daemon, the node only listens locally and every frontend needs a token issued by it
+	let daemon_mode = env::var("DAEMON_MODE").map_or(false, |v| v == "true" || v == "1");
+	let static_token = env::var("REST_API_TOKEN")
+		.ok()
+		.filter(|token| !token.is_empty());
+
 	let _guard = Node::init_logger(&data_dir);
 
 	let (node, router) = match Node::new(data_dir).await {
@@ -41,14 +52,57 @@ async fn main() {
 		}
 	};
 	let signal = utils::axum_shutdown_signal(node.clone());
+	let auth = auth::Auth::new(node.clone(), static_token.clone());
+
+	if daemon_mode && static_token.is_none() && !node.has_api_tokens().await {
+		let (_, secret) = node
+			.issue_api_token("daemon".to_string(), ApiTokenScope::ALL.to_vec(), None)
+			.await
+			.expect("Error issuing the first API token of the daemon!");
+
+		println!("Issued the first API token of this daemon, it won't be shown again: {secret}");
+	}
+
+	let custom_uri = create_custom_uri_endpoint(node.clone()).axum();
+	let rspc = router.clone().endpoint({
+		let node = node.clone();
+		move || node.clone()
+	});
+	// Files and thumbnails are streamed to any client able to authenticate, like the REST API
+	let custom_uri = if daemon_mode || static_token.is_some() {
+		custom_uri.layer(middleware::from_fn_with_state(
+			auth.require(&[ApiTokenScope::Read]),
+			auth::require_token,
+		))
+	} else {
+		custom_uri
+	};
+	let rspc = if daemon_mode {
+		rspc.axum().layer(middleware::from_fn_with_state(
+			auth.require(&[ApiTokenScope::Read, ApiTokenScope::Write]),
+			auth::require_token,
+		))
+	} else {
+		rspc.axum()
+	};
 
 	let app = axum::Router::new()
 		.route("/health", get(|| async { "OK" }))
-		.nest(
-			"/spacedrive",
-			create_custom_uri_endpoint(node.clone()).axum(),
-		)
-		.nest("/rspc", router.endpoint(move || node.clone()).axum());
+		.nest("/spacedrive", custom_uri);
+
+	// The REST and GraphQL APIs, uploads and WebDAV shares are only served when their clients can
+	// authenticate
+	let app = if daemon_mode || static_token.is_some() {
+		info!("Serving the REST API at /api, the GraphQL API at /graphql, uploads at /upload and WebDAV shares at /webdav");
+		app.nest("/api", rest::router(node.clone(), router, auth.clone()))
+			.nest("/upload", upload::router(node.clone(), auth.clone()))
+			.nest("/webdav", webdav::router(node.clone(), auth.clone()))
+			.nest("/graphql", graphql::router(node, auth))
+	} else {
+		app
+	};
+
+	let app = app.nest("/rspc", rspc);
 
 	#[cfg(feature = "assets")]
 	let app = app
@@ -124,7 +178,11 @@ async fn main() {
 		.route("/", get(|| async { "Spacedrive Server!" }))
 		.fallback(|| async { "404 Not Found: We're past the event horizon..." });
 
-	let mut addr = "[::]:8080".parse::<SocketAddr>().unwrap(); // This listens on IPv6 and IPv4
+	let mut addr = if daemon_mode {
+		"127.0.0.1:8080".parse::<SocketAddr>().unwrap()
+	} else {
+		"[::]:8080".parse::<SocketAddr>().unwrap() // This listens on IPv6 and IPv4
+	};
 	addr.set_port(port);
 	info!("Listening on http://localhost:{}", port);
 	axum::Server::bind(&addr)
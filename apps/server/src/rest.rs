@@ -0,0 +1,162 @@
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	future::{ready, Ready},
+	sync::Arc,
+};
+
+use axum::{
+	body::Bytes,
+	extract::{Path, Query, State},
+	http::StatusCode,
+	middleware,
+	response::{IntoResponse, Response},
+	routing::get,
+	Extension, Json,
+};
+use rspc::internal::jsonrpc::{
+	self, handle_json_rpc, OwnedMpscSender, RequestId, Sender, SubscriptionUpgrade,
+};
+use sd_core::{api::Router, Node};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::auth::{require_token, Auth, Client};
+
+/// Exposes the queries and mutations of the core API as REST endpoints, for scripts and tools
+/// which don't speak rspc:
+/// - `GET /{procedure}?input={json}` runs a query, like `GET /locations.list?input={"library_id":"..."}`
+/// - `POST /{procedure}` runs a mutation, with its input as the JSON body
+///
+/// Inputs are the same as the rspc ones, so library procedures take
+/// `{"library_id": "...", "arg": ...}`. Every request must have an API token, with the `read`
+/// scope for queries and, for mutations, either the `write` scope or the narrower one covering
+/// the procedure, like `files:write`. Subscriptions aren't supported.
+pub fn router(node: Arc<Node>, router: Arc<Router>, auth: Auth) -> axum::Router {
+	axum::Router::new()
+		.route("/:procedure", get(query).post(mutation))
+		.route_layer(middleware::from_fn_with_state(
+			auth.require(&[]),
+			require_token,
+		))
+		.with_state(Gateway { node, router })
+}
+
+#[derive(Clone)]
+struct Gateway {
+	node: Arc<Node>,
+	router: Arc<Router>,
+}
+
+async fn query(
+	State(gateway): State<Gateway>,
+	Extension(client): Extension<Client>,
+	Path(procedure): Path<String>,
+	Query(params): Query<HashMap<String, String>>,
+) -> Response {
+	if let Err(resp) = client.require_procedure(&procedure, false) {
+		return resp;
+	}
+
+	let input = match params.get("input").map(|input| serde_json::from_str(input)) {
+		Some(Ok(input)) => Some(input),
+		Some(Err(e)) => return bad_request(format!("invalid input: {e}")),
+		None => None,
+	};
+
+	gateway.exec("query", procedure, input).await
+}
+
+async fn mutation(
+	State(gateway): State<Gateway>,
+	Extension(client): Extension<Client>,
+	Path(procedure): Path<String>,
+	body: Bytes,
+) -> Response {
+	if let Err(resp) = client.require_procedure(&procedure, true) {
+		return resp;
+	}
+
+	let input = if body.is_empty() {
+		None
+	} else {
+		match serde_json::from_slice(&body) {
+			Ok(input) => Some(input),
+			Err(e) => return bad_request(format!("invalid input: {e}")),
+		}
+	};
+
+	gateway.exec("mutation", procedure, input).await
+}
+
+impl Gateway {
+	/// Runs the procedure like the rspc HTTP transport would, then unwraps its JSON-RPC response
+	async fn exec(&self, method: &str, procedure: String, input: Option<Value>) -> Response {
+		let request = match serde_json::from_value::<jsonrpc::Request>(json!({
+			"jsonrpc": "2.0",
+			"id": null,
+			"method": method,
+			"params": {
+				"path": procedure,
+				"input": input,
+			},
+		})) {
+			Ok(request) => request,
+			Err(e) => return bad_request(format!("invalid request: {e}")),
+		};
+
+		let mut resp = None;
+		handle_json_rpc(
+			self.node.clone(),
+			request,
+			Cow::Borrowed(&self.router),
+			RestSender { resp: &mut resp },
+		)
+		.await;
+
+		let Some(result) = resp
+			.and_then(|resp| serde_json::to_value(resp).ok())
+			.and_then(|mut resp| resp.get_mut("result").map(Value::take))
+		else {
+			return (StatusCode::INTERNAL_SERVER_ERROR, "no response").into_response();
+		};
+
+		match result.get("type").and_then(Value::as_str) {
+			Some("error") => {
+				let error = result.get("data").cloned().unwrap_or_default();
+				let status = error
+					.get("code")
+					.and_then(Value::as_u64)
+					.and_then(|code| StatusCode::from_u16(code as u16).ok())
+					.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+				(status, Json(error)).into_response()
+			}
+			_ => Json(result.get("data").cloned().unwrap_or_default()).into_response(),
+		}
+	}
+}
+
+/// Keeps a response to a single request, as the REST gateway never upgrades to a subscription
+struct RestSender<'a> {
+	resp: &'a mut Option<jsonrpc::Response>,
+}
+
+impl<'a> Sender<'a> for RestSender<'a> {
+	type SendFut = Ready<()>;
+	type SubscriptionMap = Arc<futures_locks::Mutex<HashMap<RequestId, oneshot::Sender<()>>>>;
+	type OwnedSender = OwnedMpscSender;
+
+	fn subscription(self) -> SubscriptionUpgrade<'a, Self> {
+		SubscriptionUpgrade::Unsupported(self)
+	}
+
+	fn send(self, resp: jsonrpc::Response) -> Self::SendFut {
+		*self.resp = Some(resp);
+		ready(())
+	}
+}
+
+fn bad_request(message: String) -> Response {
+	(StatusCode::BAD_REQUEST, message).into_response()
+}
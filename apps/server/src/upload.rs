@@ -0,0 +1,81 @@
+use std::{io, sync::Arc};
+
+use axum::{
+	extract::{BodyStream, DefaultBodyLimit, Path, Query, State},
+	http::StatusCode,
+	middleware,
+	response::{IntoResponse, Response},
+	routing::post,
+	Json,
+};
+use futures::TryStreamExt;
+use sd_core::{ApiTokenScope, Node, UploadError};
+use serde::Deserialize;
+use tokio_util::io::StreamReader;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::{require_token, Auth};
+
+/// Lets remote clients, like the web app on a phone, upload files into a location with
+/// `POST /{library_id}/{location_id}?path={directory}&name={file name}` and the contents of the
+/// file as the body. The file is indexed and identified before the response is sent, which
+/// describes it. The API token must have the `files:write` scope.
+pub fn router(node: Arc<Node>, auth: Auth) -> axum::Router {
+	axum::Router::new()
+		.route("/:library_id/:location_id", post(upload))
+		.route_layer(middleware::from_fn_with_state(
+			auth.require(&[ApiTokenScope::FilesWrite]),
+			require_token,
+		))
+		// Bodies are streamed to disk, so they can be as big as the files of the location
+		.layer(DefaultBodyLimit::disable())
+		.with_state(node)
+}
+
+#[derive(Deserialize)]
+struct UploadParams {
+	/// Directory of the location the file is written into, its root if not set
+	#[serde(default)]
+	path: Option<String>,
+	name: String,
+}
+
+async fn upload(
+	State(node): State<Arc<Node>>,
+	Path((library_id, location_id)): Path<(Uuid, i32)>,
+	Query(params): Query<UploadParams>,
+	body: BodyStream,
+) -> Response {
+	let contents = StreamReader::new(body.map_err(|e| io::Error::new(io::ErrorKind::Other, e)));
+
+	match node
+		.upload_file(
+			library_id,
+			location_id,
+			params.path.as_deref().unwrap_or("/"),
+			&params.name,
+			contents,
+		)
+		.await
+	{
+		Ok(file) => (StatusCode::CREATED, Json(file)).into_response(),
+		Err(e) => {
+			let status = match &e {
+				UploadError::LibraryNotFound(_) | UploadError::LocationNotFound(_) => {
+					StatusCode::NOT_FOUND
+				}
+				UploadError::RemoteLocation(_)
+				| UploadError::InvalidName(_)
+				| UploadError::OutsideLocation(_)
+				| UploadError::FilePath(_) => StatusCode::BAD_REQUEST,
+				_ => {
+					error!("Failed to upload '{}': {e:#?}", params.name);
+					StatusCode::INTERNAL_SERVER_ERROR
+				}
+			};
+
+			(status, e.to_string()).into_response()
+		}
+	}
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::{
+	body::{self, Body},
+	extract::{DefaultBodyLimit, OriginalUri, Path, State},
+	http::{Request, StatusCode},
+	middleware,
+	response::{IntoResponse, Response},
+	routing::any,
+	Extension,
+};
+use dav_server::{fakels::FakeLs, DavHandler};
+use sd_core::{
+	webdav::{library_filesystem, WebDavError},
+	ApiTokenScope, Node,
+};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::{require_token, Auth, Client};
+
+/// Serves the locations of each library as a WebDAV share at `/webdav/{library_id}`, which OS file
+/// managers can mount with an API token as the password. Browsing the share needs the `read`
+/// scope, and changing it the `files:write` one.
+pub fn router(node: Arc<Node>, auth: Auth) -> axum::Router {
+	axum::Router::new()
+		.route("/:library_id", any(webdav))
+		.route("/:library_id/*path", any(webdav))
+		.route_layer(middleware::from_fn_with_state(
+			auth.require(&[ApiTokenScope::Read]),
+			require_token,
+		))
+		// Files are streamed to disk, so they can be as big as the ones of the locations
+		.layer(DefaultBodyLimit::disable())
+		.with_state(node)
+}
+
+async fn webdav(
+	State(node): State<Arc<Node>>,
+	Extension(client): Extension<Client>,
+	Path(params): Path<Vec<(String, String)>>,
+	OriginalUri(uri): OriginalUri,
+	mut req: Request<Body>,
+) -> Response {
+	let Some(library_id) = params
+		.iter()
+		.find(|(key, _)| key == "library_id")
+		.and_then(|(_, id)| Uuid::parse_str(id).ok())
+	else {
+		return (StatusCode::NOT_FOUND, "library not found").into_response();
+	};
+
+	// Methods which don't change the share, the locks of the share only existing in memory
+	if !matches!(
+		req.method().as_str(),
+		"GET" | "HEAD" | "OPTIONS" | "PROPFIND" | "LOCK" | "UNLOCK"
+	) {
+		if let Err(resp) = client.require(ApiTokenScope::FilesWrite) {
+			return resp;
+		}
+	}
+
+	let fs = match library_filesystem(&node, library_id).await {
+		Ok(fs) => fs,
+		Err(e @ WebDavError::LibraryNotFound(_)) => {
+			return (StatusCode::NOT_FOUND, e.to_string()).into_response()
+		}
+		Err(e) => {
+			error!("Failed to open the WebDAV share of library '{library_id}': {e:#?}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+		}
+	};
+
+	// The links of the share are built from the whole path, with the prefix it's nested at
+	*req.uri_mut() = uri;
+
+	DavHandler::builder()
+		.filesystem(fs)
+		.locksystem(FakeLs::new())
+		.strip_prefix(format!("/webdav/{library_id}"))
+		.build_handler()
+		.handle(req)
+		.await
+		.map(body::boxed)
+}
@@ -0,0 +1,34 @@
+-- CreateTable
+CREATE TABLE "custom_field" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "kind" INTEGER,
+    "options" BLOB,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateTable
+CREATE TABLE "custom_field_value" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "value" TEXT,
+    "field_id" INTEGER,
+    "object_id" INTEGER,
+    "date_modified" DATETIME,
+    CONSTRAINT "custom_field_value_field_id_fkey" FOREIGN KEY ("field_id") REFERENCES "custom_field" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "custom_field_value_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "custom_field_pub_id_key" ON "custom_field"("pub_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "custom_field_value_pub_id_key" ON "custom_field_value"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "custom_field_value_field_id_value_idx" ON "custom_field_value"("field_id", "value");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "custom_field_value_field_id_object_id_key" ON "custom_field_value"("field_id", "object_id");
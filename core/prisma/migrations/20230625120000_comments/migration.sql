@@ -0,0 +1,20 @@
+-- CreateTable
+CREATE TABLE "comment" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "content" TEXT,
+    "date_created" DATETIME,
+    "date_modified" DATETIME,
+    "object_id" INTEGER,
+    "author_id" INTEGER,
+    "parent_id" INTEGER,
+    CONSTRAINT "comment_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "comment_author_id_fkey" FOREIGN KEY ("author_id") REFERENCES "node" ("id") ON DELETE SET NULL ON UPDATE CASCADE,
+    CONSTRAINT "comment_parent_id_fkey" FOREIGN KEY ("parent_id") REFERENCES "comment" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "comment_pub_id_key" ON "comment"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "comment_object_id_idx" ON "comment"("object_id");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "rating" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "object_rating_idx" ON "object"("rating");
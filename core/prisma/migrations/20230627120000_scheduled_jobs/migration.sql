@@ -0,0 +1,15 @@
+-- CreateTable
+CREATE TABLE "scheduled_job" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT,
+    "kind" BLOB,
+    "cron" TEXT,
+    "enabled" BOOLEAN,
+    "date_last_run" DATETIME,
+    "date_next_run" DATETIME,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateIndex
+CREATE INDEX "scheduled_job_date_next_run_idx" ON "scheduled_job"("date_next_run");
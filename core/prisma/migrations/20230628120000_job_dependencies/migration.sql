@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "job_dependency" (
+    "job_id" BLOB NOT NULL,
+    "dependency_id" BLOB NOT NULL,
+
+    PRIMARY KEY ("job_id", "dependency_id"),
+    CONSTRAINT "job_dependency_job_id_fkey" FOREIGN KEY ("job_id") REFERENCES "job" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "job_dependency_dependency_id_fkey" FOREIGN KEY ("dependency_id") REFERENCES "job" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "job_dependency_dependency_id_idx" ON "job_dependency"("dependency_id");
@@ -0,0 +1,10 @@
+-- CreateTable
+CREATE TABLE "webhook" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "url" TEXT,
+    "secret" TEXT,
+    "events" BLOB,
+    "enabled" BOOLEAN,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "remote" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "options" BLOB,
+    "credentials" BLOB,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "remote_pub_id_key" ON "remote"("pub_id");
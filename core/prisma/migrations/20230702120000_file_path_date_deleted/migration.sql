@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_deleted" DATETIME;
+
+-- CreateIndex
+CREATE INDEX "file_path_date_deleted_idx" ON "file_path"("date_deleted");
@@ -0,0 +1,19 @@
+-- CreateTable
+CREATE TABLE "audit_log_entry" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "action" TEXT,
+    "details" BLOB,
+    "date_created" DATETIME,
+    "actor_id" INTEGER,
+    CONSTRAINT "audit_log_entry_actor_id_fkey" FOREIGN KEY ("actor_id") REFERENCES "node" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "audit_log_entry_pub_id_key" ON "audit_log_entry"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "audit_log_entry_date_created_idx" ON "audit_log_entry"("date_created");
+
+-- CreateIndex
+CREATE INDEX "audit_log_entry_action_idx" ON "audit_log_entry"("action");
@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "statistics_snapshot" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "date" DATETIME NOT NULL,
+    "location_id" INTEGER,
+    "total_bytes" TEXT NOT NULL DEFAULT '0',
+    "file_count" INTEGER NOT NULL DEFAULT 0,
+    "kinds" BLOB,
+    "date_captured" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "statistics_snapshot_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "statistics_snapshot_location_id_date_idx" ON "statistics_snapshot"("location_id", "date");
@@ -0,0 +1,15 @@
+-- CreateTable
+CREATE TABLE "volume_benchmark" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "volume_id" INTEGER NOT NULL,
+    "file_size" TEXT NOT NULL DEFAULT '0',
+    "sequential_read" TEXT NOT NULL DEFAULT '0',
+    "sequential_write" TEXT NOT NULL DEFAULT '0',
+    "random_read" TEXT NOT NULL DEFAULT '0',
+    "random_write" TEXT NOT NULL DEFAULT '0',
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "volume_benchmark_volume_id_fkey" FOREIGN KEY ("volume_id") REFERENCES "volume" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "volume_benchmark_volume_id_idx" ON "volume_benchmark"("volume_id");
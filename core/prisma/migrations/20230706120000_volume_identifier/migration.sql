@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "volume" ADD COLUMN "identifier" TEXT;
+
+-- CreateIndex
+CREATE INDEX "volume_node_id_identifier_idx" ON "volume"("node_id", "identifier");
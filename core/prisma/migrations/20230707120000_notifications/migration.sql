@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "notification" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "category" TEXT NOT NULL,
+    "severity" INTEGER NOT NULL,
+    "data" BLOB NOT NULL,
+    "read" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "notification_read_idx" ON "notification"("read");
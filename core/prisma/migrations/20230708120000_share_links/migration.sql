@@ -0,0 +1,21 @@
+-- CreateTable
+CREATE TABLE "share_link" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "token" TEXT NOT NULL,
+    "file_path_id" INTEGER NOT NULL,
+    "expires_at" DATETIME,
+    "max_downloads" INTEGER,
+    "downloads" INTEGER NOT NULL DEFAULT 0,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "share_link_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "share_link_pub_id_key" ON "share_link"("pub_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "share_link_token_key" ON "share_link"("token");
+
+-- CreateIndex
+CREATE INDEX "share_link_file_path_id_idx" ON "share_link"("file_path_id");
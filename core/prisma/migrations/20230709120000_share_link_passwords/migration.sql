@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "share_link" ADD COLUMN "password_hash" BLOB;
+ALTER TABLE "share_link" ADD COLUMN "password_salt" BLOB;
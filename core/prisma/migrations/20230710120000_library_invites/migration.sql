@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "library_invite" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "token" TEXT NOT NULL,
+    "role" TEXT NOT NULL,
+    "expires_at" DATETIME NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "redeemed_by" BLOB,
+    "date_redeemed" DATETIME
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "library_invite_pub_id_key" ON "library_invite"("pub_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "library_invite_token_key" ON "library_invite"("token");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_quarantined" DATETIME;
+
+-- CreateIndex
+CREATE INDEX "file_path_date_quarantined_idx" ON "file_path"("date_quarantined");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "scan_for_malware" BOOLEAN;
+
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_scanned" DATETIME;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "sensitive_score" REAL;
+
+-- AlterTable
+ALTER TABLE "share_link" ADD COLUMN "hide_sensitive" BOOLEAN NOT NULL DEFAULT false;
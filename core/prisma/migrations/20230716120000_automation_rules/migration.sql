@@ -0,0 +1,31 @@
+-- CreateTable
+CREATE TABLE "automation_rule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT,
+    "location_id" INTEGER,
+    "pattern" TEXT,
+    "actions" BLOB,
+    "dry_run" BOOLEAN,
+    "enabled" BOOLEAN,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateTable
+CREATE TABLE "automation_run" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "rule_id" INTEGER NOT NULL,
+    "location_id" INTEGER,
+    "file_path_id" INTEGER,
+    "path" TEXT,
+    "outcomes" BLOB,
+    "dry_run" BOOLEAN,
+    "date_created" DATETIME,
+    CONSTRAINT "automation_run_rule_id_fkey" FOREIGN KEY ("rule_id") REFERENCES "automation_rule" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "automation_run_rule_id_idx" ON "automation_run"("rule_id");
+
+-- CreateIndex
+CREATE INDEX "automation_run_date_created_idx" ON "automation_run"("date_created");
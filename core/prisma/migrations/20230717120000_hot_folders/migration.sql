@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "hot_folder" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT,
+    "location_id" INTEGER,
+    "path" TEXT,
+    "pipeline" BLOB,
+    "enabled" BOOLEAN,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateIndex
+CREATE INDEX "hot_folder_location_id_idx" ON "hot_folder"("location_id");
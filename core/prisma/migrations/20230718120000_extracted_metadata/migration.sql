@@ -0,0 +1,16 @@
+-- CreateTable
+CREATE TABLE "extracted_metadata" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "plugin" TEXT,
+    "key" TEXT,
+    "value" TEXT,
+    "object_id" INTEGER,
+    "date_created" DATETIME,
+    CONSTRAINT "extracted_metadata_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "extracted_metadata_object_id_plugin_key_key" ON "extracted_metadata"("object_id", "plugin", "key");
+
+-- CreateIndex
+CREATE INDEX "extracted_metadata_key_value_idx" ON "extracted_metadata"("key", "value");
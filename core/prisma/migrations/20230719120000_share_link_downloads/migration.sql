@@ -0,0 +1,11 @@
+-- CreateTable
+CREATE TABLE "share_link_download" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "share_link_id" INTEGER NOT NULL,
+    "session" TEXT NOT NULL,
+    "path" TEXT NOT NULL,
+    CONSTRAINT "share_link_download_share_link_id_fkey" FOREIGN KEY ("share_link_id") REFERENCES "share_link" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "share_link_download_share_link_id_session_path_key" ON "share_link_download"("share_link_id", "session", "path");
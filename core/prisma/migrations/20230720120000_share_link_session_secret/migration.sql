@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "share_link" ADD COLUMN "session_secret" TEXT;
+
+-- Existing links get their secret here, new ones when they're created
+UPDATE "share_link" SET "session_secret" = lower(hex(randomblob(32)));
@@ -0,0 +1,202 @@
+use crate::{
+	invalidate_query,
+	job::Job,
+	library::Library,
+	object::album::{materializer_job::SmartAlbumMaterializerJobInit, AlbumCreateArgs},
+	prisma::{album, object_in_album},
+	util::MaybeUndefined,
+};
+
+use chrono::Utc;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::Deserialize;
+use specta::Type;
+
+use super::{
+	search::ObjectFilterArgs,
+	utils::{editor, library},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.db.album().find_many(vec![]).exec().await?)
+			})
+		})
+		.procedure("get", {
+			R.with2(library())
+				.query(|(_, library), album_id: album::id::Type| async move {
+					Ok(library
+						.db
+						.album()
+						.find_unique(album::id::equals(album_id))
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("create", {
+			R.with2(editor())
+				.mutation(|(_, library), args: AlbumCreateArgs| async move {
+					let is_smart = args.search_query.is_some();
+					let created_album = args.exec(&library).await?;
+
+					if is_smart {
+						Job::new(SmartAlbumMaterializerJobInit {
+							album_id: created_album.id,
+						})
+						.spawn(&library)
+						.await?;
+					}
+
+					invalidate_query!(library, "albums.list");
+
+					Ok(created_album)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumUpdateArgs {
+				pub id: album::id::Type,
+				pub name: Option<String>,
+				pub search_query: MaybeUndefined<ObjectFilterArgs>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: AlbumUpdateArgs| async move {
+					let Library { db, .. } = &library;
+
+					let search_query_changed =
+						!matches!(args.search_query, MaybeUndefined::Undefined);
+
+					let mut params = vec![album::date_modified::set(Some(Utc::now().into()))];
+
+					if let Some(name) = args.name {
+						params.push(album::name::set(Some(name)));
+					}
+
+					match args.search_query {
+						MaybeUndefined::Undefined => {}
+						MaybeUndefined::Null => {
+							params.push(album::search_query::set(None));
+							params.push(album::date_materialized::set(None));
+						}
+						MaybeUndefined::Value(search_query) => {
+							params.push(album::search_query::set(Some(
+								serde_json::to_vec(&search_query).map_err(|e| {
+									rspc::Error::with_cause(
+										ErrorCode::BadRequest,
+										"Invalid search query".to_string(),
+										e,
+									)
+								})?,
+							)));
+						}
+					}
+
+					let album = db
+						.album()
+						.update(album::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					if search_query_changed && album.search_query.is_some() {
+						Job::new(SmartAlbumMaterializerJobInit { album_id: album.id })
+							.spawn(&library)
+							.await?;
+					}
+
+					invalidate_query!(library, "albums.list");
+
+					Ok(())
+				})
+		})
+		.procedure("assign", {
+			#[derive(Debug, Type, Deserialize)]
+			pub struct AlbumAssignArgs {
+				pub object_ids: Vec<i32>,
+				pub album_id: album::id::Type,
+				pub unassign: bool,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: AlbumAssignArgs| async move {
+					let Library { db, .. } = &library;
+
+					let album = db
+						.album()
+						.find_unique(album::id::equals(args.album_id))
+						.select(album::select!({ search_query }))
+						.exec()
+						.await?
+						.ok_or(rspc::Error::new(
+							ErrorCode::NotFound,
+							"Error finding album in db".into(),
+						))?;
+
+					if album.search_query.is_some() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"The contents of a smart album are defined by its search query".into(),
+						));
+					}
+
+					if args.unassign {
+						db.object_in_album()
+							.delete_many(vec![
+								object_in_album::album_id::equals(args.album_id),
+								object_in_album::object_id::in_vec(args.object_ids),
+							])
+							.exec()
+							.await?;
+					} else {
+						let date_created = Utc::now().into();
+
+						db.object_in_album()
+							.create_many(
+								args.object_ids
+									.iter()
+									.map(|&object_id| object_in_album::CreateUnchecked {
+										album_id: args.album_id,
+										object_id,
+										_params: vec![object_in_album::date_created::set(Some(
+											date_created,
+										))],
+									})
+									.collect(),
+							)
+							.exec()
+							.await?;
+					}
+
+					invalidate_query!(library, "search.objects");
+
+					Ok(())
+				})
+		})
+		.procedure("materialize", {
+			R.with2(editor())
+				.mutation(|(_, library), album_id: album::id::Type| async move {
+					Job::new(SmartAlbumMaterializerJobInit { album_id })
+						.spawn(&library)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), album_id: album::id::Type| async move {
+					library
+						.db
+						.album()
+						.delete(album::id::equals(album_id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "albums.list");
+
+					Ok(())
+				})
+		})
+}
@@ -0,0 +1,69 @@
+use crate::node::AnalyticsSettings;
+
+use std::path::PathBuf;
+
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use tracing::error;
+
+use super::{Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("settings", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.analytics) })
+		})
+		.procedure("setSettings", {
+			R.mutation(|ctx, settings: AnalyticsSettings| async move {
+				ctx.config
+					.write(|mut config| {
+						config.analytics = settings;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				ctx.analytics.set_enabled(settings.enabled);
+
+				Ok(())
+			})
+		})
+		.procedure(
+			"get",
+			R.query(|ctx, _: ()| async move { Ok(ctx.analytics.data()) }),
+		)
+		.procedure("track", {
+			// Counted only if analytics are enabled
+			R.mutation(|ctx, feature: String| async move {
+				ctx.analytics.record_feature(&feature);
+
+				Ok(())
+			})
+		})
+		.procedure("export", {
+			R.mutation(|ctx, path: PathBuf| async move {
+				ctx.analytics.write_to(&path).await.map_err(|e| {
+					rspc::Error::with_cause(
+						ErrorCode::InternalServerError,
+						"failed to export analytics".into(),
+						e,
+					)
+				})
+			})
+		})
+		.procedure("reset", {
+			R.mutation(|ctx, _: ()| async move {
+				ctx.analytics.reset().await.map_err(|e| {
+					rspc::Error::with_cause(
+						ErrorCode::InternalServerError,
+						"failed to reset analytics".into(),
+						e,
+					)
+				})
+			})
+		})
+}
@@ -0,0 +1,90 @@
+use crate::node::{ApiTokenScope, SanitisedApiToken};
+
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::error;
+use uuid::Uuid;
+
+use super::{Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.query(|ctx, _: ()| async move {
+				Ok(ctx
+					.config
+					.get()
+					.await
+					.api_tokens
+					.into_iter()
+					.map(SanitisedApiToken::from)
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("issue", {
+			#[derive(Deserialize, Type)]
+			pub struct IssueApiTokenArgs {
+				pub name: String,
+				pub scopes: Vec<ApiTokenScope>,
+				/// Requests per minute, the default one if not set
+				#[serde(default)]
+				pub rate_limit: Option<u32>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct IssuedApiToken {
+				pub token: SanitisedApiToken,
+				/// Only available now, the node keeps a hash of it
+				pub secret: String,
+			}
+
+			R.mutation(|ctx, args: IssueApiTokenArgs| async move {
+				if args.name.is_empty() || args.scopes.is_empty() {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"a token needs a name and at least one scope".into(),
+					));
+				}
+
+				if args.rate_limit == Some(0) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"a token must allow at least one request per minute".into(),
+					));
+				}
+
+				let (token, secret) = ctx
+					.issue_api_token(args.name, args.scopes, args.rate_limit)
+					.await
+					.map_err(|err| {
+						error!("Failed to issue API token: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(IssuedApiToken {
+					token: token.into(),
+					secret,
+				})
+			})
+		})
+		.procedure("revoke", {
+			R.mutation(|ctx, id: Uuid| async move {
+				ctx.config
+					.write(|mut config| config.api_tokens.retain(|token| token.id != id))
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+}
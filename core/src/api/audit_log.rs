@@ -0,0 +1,76 @@
+use crate::{
+	library::{export_audit_log, list_audit_log, AuditLogFilter, AuditLogItem},
+	object::export::ListingExportFormat,
+	prisma::audit_log_entry,
+};
+
+use std::path::PathBuf;
+
+use rspc::alpha::AlphaRouter;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{utils::library, Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct AuditLogListArgs {
+				#[serde(default)]
+				filter: AuditLogFilter,
+				#[specta(optional)]
+				take: Option<i32>,
+				#[specta(optional)]
+				cursor: Option<audit_log_entry::id::Type>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct AuditLogPage {
+				items: Vec<AuditLogItem>,
+				cursor: Option<audit_log_entry::id::Type>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: AuditLogListArgs| async move {
+					let take = args.take.unwrap_or(100);
+
+					let mut items = list_audit_log(
+						&library,
+						args.filter.into_params(),
+						take as i64 + 1,
+						args.cursor,
+					)
+					.await?;
+
+					let cursor = (items.len() as i32 > take)
+						.then(|| items.pop())
+						.flatten()
+						.map(|item| item.id);
+
+					Ok(AuditLogPage { items, cursor })
+				})
+		})
+		.procedure("export", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct AuditLogExportArgs {
+				#[serde(default)]
+				filter: AuditLogFilter,
+				format: ListingExportFormat,
+				output_path: PathBuf,
+			}
+
+			R.with2(library())
+				.mutation(|(_, library), args: AuditLogExportArgs| async move {
+					Ok(export_audit_log(
+						&library,
+						args.filter.into_params(),
+						args.format,
+						args.output_path,
+					)
+					.await?)
+				})
+		})
+}
@@ -0,0 +1,207 @@
+use crate::{
+	invalidate_query,
+	location::automation::{
+		compile_pattern, AutomationAction, AutomationError, AutomationRule, AutomationRun,
+	},
+	prisma::{automation_rule, automation_run, location, SortOrder},
+};
+
+use chrono::Utc;
+use rspc::alpha::AlphaRouter;
+use serde::Deserialize;
+use specta::Type;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+/// Runs shown by default in the execution log
+const DEFAULT_LOG_TAKE: i64 = 100;
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				library
+					.db
+					.automation_rule()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(AutomationRule::try_from)
+					.collect::<Result<Vec<_>, _>>()
+					.map_err(Into::into)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct AutomationRuleCreateArgs {
+				pub name: String,
+				/// Every location of this node when not given
+				#[specta(optional)]
+				pub location_id: Option<location::id::Type>,
+				pub pattern: String,
+				pub actions: Vec<AutomationAction>,
+				#[serde(default)]
+				pub dry_run: bool,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: AutomationRuleCreateArgs| async move {
+					compile_pattern(&args.pattern)?;
+
+					let rule = library
+						.db
+						.automation_rule()
+						.create(vec![
+							automation_rule::name::set(Some(args.name)),
+							automation_rule::location_id::set(args.location_id),
+							automation_rule::pattern::set(Some(args.pattern)),
+							automation_rule::actions::set(Some(
+								serde_json::to_vec(&args.actions).map_err(AutomationError::from)?,
+							)),
+							automation_rule::dry_run::set(Some(args.dry_run)),
+							automation_rule::enabled::set(Some(true)),
+							automation_rule::date_created::set(Some(Utc::now().into())),
+						])
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "automation.list");
+
+					Ok(AutomationRule::try_from(rule)?)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct AutomationRuleUpdateArgs {
+				pub id: automation_rule::id::Type,
+				pub name: Option<String>,
+				/// Given as `Some(None)` to match every location
+				pub location_id: Option<Option<location::id::Type>>,
+				pub pattern: Option<String>,
+				pub actions: Option<Vec<AutomationAction>>,
+				pub dry_run: Option<bool>,
+				pub enabled: Option<bool>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: AutomationRuleUpdateArgs| async move {
+					library
+						.db
+						.automation_rule()
+						.find_unique(automation_rule::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(AutomationError::IdNotFound(args.id))?;
+
+					let mut params =
+						vec![automation_rule::date_modified::set(Some(Utc::now().into()))];
+
+					if let Some(name) = args.name {
+						params.push(automation_rule::name::set(Some(name)));
+					}
+
+					if let Some(location_id) = args.location_id {
+						params.push(automation_rule::location_id::set(location_id));
+					}
+
+					if let Some(pattern) = args.pattern {
+						compile_pattern(&pattern)?;
+						params.push(automation_rule::pattern::set(Some(pattern)));
+					}
+
+					if let Some(actions) = args.actions {
+						params.push(automation_rule::actions::set(Some(
+							serde_json::to_vec(&actions).map_err(AutomationError::from)?,
+						)));
+					}
+
+					if let Some(dry_run) = args.dry_run {
+						params.push(automation_rule::dry_run::set(Some(dry_run)));
+					}
+
+					if let Some(enabled) = args.enabled {
+						params.push(automation_rule::enabled::set(Some(enabled)));
+					}
+
+					library
+						.db
+						.automation_rule()
+						.update(automation_rule::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "automation.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), id: automation_rule::id::Type| async move {
+					library
+						.db
+						.automation_rule()
+						.delete(automation_rule::id::equals(id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "automation.list");
+					invalidate_query!(library, "automation.log");
+
+					Ok(())
+				})
+		})
+		.procedure("log", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct AutomationLogArgs {
+				/// Runs of every rule when not given
+				#[specta(optional)]
+				pub rule_id: Option<automation_rule::id::Type>,
+				#[specta(optional)]
+				pub take: Option<i32>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: AutomationLogArgs| async move {
+					library
+						.db
+						.automation_run()
+						.find_many(
+							args.rule_id
+								.map(automation_run::rule_id::equals)
+								.into_iter()
+								.collect(),
+						)
+						.order_by(automation_run::id::order(SortOrder::Desc))
+						.take(args.take.map_or(DEFAULT_LOG_TAKE, i64::from))
+						.exec()
+						.await?
+						.into_iter()
+						.map(AutomationRun::try_from)
+						.collect::<Result<Vec<_>, _>>()
+						.map_err(Into::into)
+				})
+		})
+		.procedure("clearLog", {
+			R.with2(editor())
+				.mutation(|(_, library), _: ()| async move {
+					library
+						.db
+						.automation_run()
+						.delete_many(vec![])
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "automation.log");
+
+					Ok(())
+				})
+		})
+}
@@ -0,0 +1,58 @@
+use crate::{
+	invalidate_query,
+	job::Job,
+	library::{list_backups, BackupSettings, LibraryBackupJobInit},
+};
+
+use rspc::alpha::AlphaRouter;
+
+use super::{
+	utils::{library, owner},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("settings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.backups) })
+		})
+		.procedure("setSettings", {
+			R.with2(owner())
+				.mutation(|(ctx, library), settings: BackupSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.backups = settings)
+						.await?;
+
+					invalidate_query!(library, "backups.settings");
+					invalidate_query!(library, "backups.list");
+
+					Ok(())
+				})
+		})
+		.procedure("list", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(list_backups(&library).await?) })
+		})
+		.procedure("backupNow", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					Job::new(LibraryBackupJobInit {})
+						.spawn(&library)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("restore", {
+			R.with2(owner())
+				.mutation(|(ctx, library), name: String| async move {
+					ctx.library_manager
+						.restore_backup(library.id, &name)
+						.await?;
+
+					invalidate_query!(library, "backups.list");
+
+					Ok(())
+				})
+		})
+}
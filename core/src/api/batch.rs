@@ -0,0 +1,220 @@
+use crate::{
+	api::utils::editor,
+	invalidate_query,
+	library::Library,
+	object::xmp::spawn_sidecar_write_back,
+	prisma::{object, tag, tag_on_object},
+	sync,
+};
+
+use std::collections::HashMap;
+
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::Deserialize;
+use serde_json::json;
+use specta::Type;
+
+use super::{Ctx, R};
+
+/// Upper bound of the operations of a batch, so a single transaction can't lock the library
+/// database for too long
+const MAX_BATCH_OPERATIONS: usize = 10_000;
+
+/// A change to an object, like the ones of the `files.setNote`, `files.setFavorite`,
+/// `files.setRating` and `tags.assign` mutations
+#[derive(Debug, Type, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BatchOperation {
+	SetNote {
+		object_id: object::id::Type,
+		note: Option<String>,
+	},
+	SetFavorite {
+		object_id: object::id::Type,
+		favorite: bool,
+	},
+	SetRating {
+		object_id: object::id::Type,
+		rating: Option<i32>,
+	},
+	AssignTag {
+		object_id: object::id::Type,
+		tag_id: tag::id::Type,
+	},
+	UnassignTag {
+		object_id: object::id::Type,
+		tag_id: tag::id::Type,
+	},
+}
+
+impl BatchOperation {
+	fn object_id(&self) -> object::id::Type {
+		match self {
+			Self::SetNote { object_id, .. }
+			| Self::SetFavorite { object_id, .. }
+			| Self::SetRating { object_id, .. }
+			| Self::AssignTag { object_id, .. }
+			| Self::UnassignTag { object_id, .. } => *object_id,
+		}
+	}
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router().procedure("apply", {
+		// Applies the operations in order within a single transaction, so the explorer can change
+		// a large selection in one round trip, and either all of them are applied or none
+		R.with2(editor())
+			.mutation(|(_, library), operations: Vec<BatchOperation>| async move {
+				let Library { db, sync, .. } = &library;
+
+				if operations.len() > MAX_BATCH_OPERATIONS {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						format!("A batch can't have more than {MAX_BATCH_OPERATIONS} operations"),
+					));
+				}
+
+				let has_invalid_rating = operations.iter().any(|op| match op {
+					BatchOperation::SetRating {
+						rating: Some(rating),
+						..
+					} => !(0..=5).contains(rating),
+					_ => false,
+				});
+
+				if has_invalid_rating {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"Rating must be between 0 and 5".into(),
+					));
+				}
+
+				let mut object_ids = operations
+					.iter()
+					.map(BatchOperation::object_id)
+					.collect::<Vec<_>>();
+				object_ids.sort_unstable();
+				object_ids.dedup();
+
+				// Synced changes need the pub_id of their object
+				let pub_ids = db
+					.object()
+					.find_many(vec![object::id::in_vec(object_ids.clone())])
+					.select(object::select!({ id pub_id }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object.pub_id))
+					.collect::<HashMap<_, _>>();
+
+				if let Some(missing) = object_ids.iter().find(|id| !pub_ids.contains_key(id)) {
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						format!("Error finding object {missing} in db"),
+					));
+				}
+
+				let touches_tags = operations.iter().any(|op| {
+					matches!(
+						op,
+						BatchOperation::AssignTag { .. } | BatchOperation::UnassignTag { .. }
+					)
+				});
+				let touches_sidecars = touches_tags
+					|| operations
+						.iter()
+						.any(|op| matches!(op, BatchOperation::SetRating { .. }));
+
+				db._transaction()
+					.run(|tx| async move {
+						for operation in operations {
+							let object_id = operation.object_id();
+							let sync_id = || sync::object::SyncId {
+								pub_id: pub_ids[&object_id].clone(),
+							};
+
+							match operation {
+								BatchOperation::SetNote { note, .. } => {
+									sync.write_op(
+										&tx,
+										sync.shared_update(
+											sync_id(),
+											object::note::NAME,
+											json!(&note),
+										),
+										tx.object().update(
+											object::id::equals(object_id),
+											vec![object::note::set(note)],
+										),
+									)
+									.await?;
+								}
+								BatchOperation::SetFavorite { favorite, .. } => {
+									tx.object()
+										.update(
+											object::id::equals(object_id),
+											vec![object::favorite::set(Some(favorite))],
+										)
+										.exec()
+										.await?;
+								}
+								BatchOperation::SetRating { rating, .. } => {
+									sync.write_op(
+										&tx,
+										sync.shared_update(
+											sync_id(),
+											object::rating::NAME,
+											json!(rating),
+										),
+										tx.object().update(
+											object::id::equals(object_id),
+											vec![object::rating::set(rating)],
+										),
+									)
+									.await?;
+								}
+								BatchOperation::AssignTag { tag_id, .. } => {
+									// Upserting makes assigning idempotent, like `tags.bulkAssign`
+									tx.tag_on_object()
+										.upsert(
+											tag_on_object::tag_id_object_id(tag_id, object_id),
+											tag_on_object::create(
+												tag::id::equals(tag_id),
+												object::id::equals(object_id),
+												vec![],
+											),
+											vec![],
+										)
+										.exec()
+										.await?;
+								}
+								BatchOperation::UnassignTag { tag_id, .. } => {
+									tx.tag_on_object()
+										.delete_many(vec![
+											tag_on_object::tag_id::equals(tag_id),
+											tag_on_object::object_id::equals(object_id),
+										])
+										.exec()
+										.await?;
+								}
+							}
+						}
+
+						Ok::<_, prisma_client_rust::QueryError>(())
+					})
+					.await?;
+
+				invalidate_query!(library, "search.paths");
+				invalidate_query!(library, "search.objects");
+				if touches_tags {
+					invalidate_query!(library, "tags.getForObject");
+				}
+
+				if touches_sidecars {
+					spawn_sidecar_write_back(&library, object_ids);
+				}
+
+				Ok(())
+			})
+	})
+}
@@ -0,0 +1,46 @@
+use crate::{
+	invalidate_query,
+	object::{
+		content_cache::{clear_content_cache, content_cache_stats},
+		preview::{clear_thumbnail_cache, thumbnail_cache_stats},
+	},
+};
+
+use rspc::alpha::AlphaRouter;
+
+use super::{utils::library, Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("stats", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(thumbnail_cache_stats(&library).await?)
+			})
+		})
+		.procedure("clear", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					clear_thumbnail_cache(&library).await?;
+
+					invalidate_query!(library, "cache.stats");
+					invalidate_query!(library, "search.paths");
+
+					Ok(())
+				})
+		})
+		.procedure("contentStats", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(content_cache_stats(&library).await?)
+			})
+		})
+		.procedure("clearContent", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					clear_content_cache(&library).await?;
+
+					invalidate_query!(library, "cache.contentStats");
+
+					Ok(())
+				})
+		})
+}
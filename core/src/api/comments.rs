@@ -0,0 +1,131 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::comment::{comment_with_author, CommentCreateArgs},
+	prisma::{comment, object, SortOrder},
+	sync,
+};
+
+use chrono::Utc;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::Deserialize;
+use serde_json::json;
+use specta::Type;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+/// Finds a comment authored by the current node, comments from other nodes can't be changed
+async fn find_own_comment(
+	library: &Library,
+	comment_id: comment::id::Type,
+) -> Result<comment::pub_id::Type, rspc::Error> {
+	let comment = library
+		.db
+		.comment()
+		.find_unique(comment::id::equals(comment_id))
+		.select(comment::select!({ pub_id author_id }))
+		.exec()
+		.await?
+		.ok_or(rspc::Error::new(
+			ErrorCode::NotFound,
+			"Error finding comment in db".into(),
+		))?;
+
+	if comment.author_id != Some(library.node_local_id) {
+		return Err(rspc::Error::new(
+			ErrorCode::Forbidden,
+			"Only the device that wrote a comment can change it".into(),
+		));
+	}
+
+	Ok(comment.pub_id)
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					Ok(library
+						.db
+						.comment()
+						.find_many(vec![comment::object_id::equals(Some(object_id))])
+						.order_by(comment::date_created::order(SortOrder::Asc))
+						.include(comment_with_author::include())
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("create", {
+			R.with2(editor())
+				.mutation(|(_, library), args: CommentCreateArgs| async move {
+					let comment = args.exec(&library).await?.ok_or(rspc::Error::new(
+						ErrorCode::NotFound,
+						"Error finding the object or comment being replied to".into(),
+					))?;
+
+					invalidate_query!(library, "comments.list");
+
+					Ok(comment)
+				})
+		})
+		.procedure("edit", {
+			#[derive(Type, Deserialize)]
+			pub struct CommentEditArgs {
+				pub id: comment::id::Type,
+				pub content: String,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: CommentEditArgs| async move {
+					let Library { db, sync, .. } = &library;
+
+					let pub_id = find_own_comment(&library, args.id).await?;
+
+					sync.write_ops(
+						db,
+						(
+							vec![sync.shared_update(
+								sync::comment::SyncId { pub_id },
+								comment::content::NAME,
+								json!(&args.content),
+							)],
+							db.comment().update(
+								comment::id::equals(args.id),
+								vec![
+									comment::content::set(Some(args.content)),
+									comment::date_modified::set(Some(Utc::now().into())),
+								],
+							),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "comments.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), comment_id: comment::id::Type| async move {
+					let Library { db, sync, .. } = &library;
+
+					let pub_id = find_own_comment(&library, comment_id).await?;
+
+					sync.write_op(
+						db,
+						sync.shared_delete(sync::comment::SyncId { pub_id }),
+						db.comment().delete(comment::id::equals(comment_id)),
+					)
+					.await?;
+
+					invalidate_query!(library, "comments.list");
+
+					Ok(())
+				})
+		})
+}
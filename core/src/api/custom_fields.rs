@@ -0,0 +1,203 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::custom_field::{
+		parse_field, CustomFieldCreateArgs, CustomFieldError, CustomFieldKind,
+		CustomFieldSetValueArgs,
+	},
+	prisma::{custom_field, custom_field_value, object},
+	sync,
+};
+
+use chrono::Utc;
+use rspc::alpha::AlphaRouter;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+custom_field_value::include!(custom_field_value_with_field { field });
+
+#[derive(Serialize, Type, Debug)]
+pub struct CustomFieldDefinition {
+	id: custom_field::id::Type,
+	name: Option<String>,
+	kind: CustomFieldKind,
+	options: Vec<String>,
+}
+
+impl TryFrom<custom_field::Data> for CustomFieldDefinition {
+	type Error = CustomFieldError;
+
+	fn try_from(field: custom_field::Data) -> Result<Self, Self::Error> {
+		let (kind, options) = parse_field(&field)?;
+
+		Ok(Self {
+			id: field.id,
+			name: field.name,
+			kind,
+			options,
+		})
+	}
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					.custom_field()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(CustomFieldDefinition::try_from)
+					.collect::<Result<Vec<_>, _>>()?)
+			})
+		})
+		.procedure("getForObject", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					Ok(library
+						.db
+						.custom_field_value()
+						.find_many(vec![
+							custom_field_value::object_id::equals(Some(object_id)),
+							custom_field_value::value::not(None),
+						])
+						.include(custom_field_value_with_field::include())
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("create", {
+			R.with2(editor())
+				.mutation(|(_, library), args: CustomFieldCreateArgs| async move {
+					let field = CustomFieldDefinition::try_from(args.exec(&library).await?)?;
+
+					invalidate_query!(library, "customFields.list");
+
+					Ok(field)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct CustomFieldUpdateArgs {
+				pub id: custom_field::id::Type,
+				pub name: Option<String>,
+				pub options: Option<Vec<String>>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: CustomFieldUpdateArgs| async move {
+					let Library { sync, db, .. } = &library;
+
+					let field = db
+						.custom_field()
+						.find_unique(custom_field::id::equals(args.id))
+						.select(custom_field::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(CustomFieldError::IdNotFound(args.id))?;
+
+					let options = args
+						.options
+						.map(|options| serde_json::to_vec(&options))
+						.transpose()
+						.map_err(CustomFieldError::from)?;
+
+					db.custom_field()
+						.update(
+							custom_field::id::equals(args.id),
+							vec![custom_field::date_modified::set(Some(Utc::now().into()))],
+						)
+						.exec()
+						.await?;
+
+					sync.write_ops(
+						db,
+						(
+							[
+								args.name
+									.as_ref()
+									.map(|v| (custom_field::name::NAME, json!(v))),
+								options
+									.as_ref()
+									.map(|v| (custom_field::options::NAME, json!(v))),
+							]
+							.into_iter()
+							.flatten()
+							.map(|(k, v)| {
+								sync.shared_update(
+									sync::custom_field::SyncId {
+										pub_id: field.pub_id.clone(),
+									},
+									k,
+									v,
+								)
+							})
+							.collect(),
+							db.custom_field().update(
+								custom_field::id::equals(args.id),
+								[
+									args.name.map(Some).map(custom_field::name::set),
+									options.map(Some).map(custom_field::options::set),
+								]
+								.into_iter()
+								.flatten()
+								.collect(),
+							),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "customFields.list");
+
+					Ok(())
+				})
+		})
+		.procedure("setValue", {
+			R.with2(editor())
+				.mutation(|(_, library), args: CustomFieldSetValueArgs| async move {
+					args.exec(&library).await?;
+
+					invalidate_query!(library, "customFields.getForObject");
+					invalidate_query!(library, "search.objects");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor()).mutation(
+				|(_, library), field_id: custom_field::id::Type| async move {
+					let Library { db, sync, .. } = &library;
+
+					let field = db
+						.custom_field()
+						.find_unique(custom_field::id::equals(field_id))
+						.select(custom_field::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(CustomFieldError::IdNotFound(field_id))?;
+
+					sync.write_op(
+						db,
+						sync.shared_delete(sync::custom_field::SyncId {
+							pub_id: field.pub_id,
+						}),
+						db.custom_field().delete(custom_field::id::equals(field_id)),
+					)
+					.await?;
+
+					invalidate_query!(library, "customFields.list");
+
+					Ok(())
+				},
+			)
+		})
+}
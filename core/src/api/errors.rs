@@ -0,0 +1,30 @@
+use crate::util::error::{CoreErrorCode, ErrorSeverity};
+
+use rspc::alpha::AlphaRouter;
+use serde::Serialize;
+use specta::Type;
+
+use super::{Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router().procedure("codes", {
+		#[derive(Serialize, Type)]
+		pub struct ErrorCodeInfo {
+			code: CoreErrorCode,
+			severity: ErrorSeverity,
+			hint: Option<String>,
+		}
+
+		// Every code errors can be returned with, for clients to check their translations against
+		R.query(|_, _: ()| async move {
+			Ok(CoreErrorCode::ALL
+				.iter()
+				.map(|&code| ErrorCodeInfo {
+					code,
+					severity: code.severity(),
+					hint: code.hint().map(str::to_string),
+				})
+				.collect::<Vec<_>>())
+		})
+	})
+}
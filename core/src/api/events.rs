@@ -0,0 +1,186 @@
+//! The public event stream of the node, for frontends and integrations to react to what happens
+//! in the core without depending on which queries it invalidates.
+//!
+//! `events.subscribe` yields [`EventEnvelope`]s, each one carrying the [`EVENT_STREAM_VERSION`]
+//! it was produced with. Adding events or fields to them is done without bumping the version,
+//! so clients should ignore what they don't know about. Renaming or removing anything bumps it.
+//!
+//! The stream carries:
+//! - `file`: files of locations created, modified, renamed or deleted, as seen by the watcher
+//! - `library`: events of a library, the same ones its webhooks are notified of
+//! - `notification`: notifications raised in a library
+//! - `jobStatus`: jobs starting, being paused, queued for a retry or being over
+//! - `jobProgress`: progress of running jobs
+//! - `sync`: sync operations created on this node or ingested from other ones
+//! - `p2p`: peers discovered and Spacedrops, which aren't tied to a library
+//!
+//! Sync operations are only streamed for the libraries loaded when subscribing.
+
+use crate::{
+	job::{JobProgressEvent, JobStatusEvent},
+	library::{NotificationItem, WebhookEvent},
+	location::FileEvent,
+	p2p::P2PEvent,
+	sync::SyncMessage,
+};
+
+use chrono::{DateTime, Utc};
+use futures::{
+	stream::{self, BoxStream},
+	StreamExt,
+};
+use rspc::alpha::AlphaRouter;
+use sd_sync::CRDTOperation;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::{CoreEvent, Ctx, R};
+
+/// Bumped on breaking changes to the events
+pub const EVENT_STREAM_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EventEnvelope {
+	pub version: u32,
+	/// The library the event is about, if any
+	pub library_id: Option<Uuid>,
+	pub date: DateTime<Utc>,
+	pub event: NodeEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum NodeEvent {
+	File(FileEvent),
+	Library(WebhookEvent),
+	Notification(NotificationItem),
+	JobStatus(JobStatusEvent),
+	JobProgress(JobProgressEvent),
+	Sync(SyncEvent),
+	P2p(P2PEvent),
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncEvent {
+	/// Whether the operation comes from another node, or was created on this one
+	pub ingested: bool,
+	pub operation: CRDTOperation,
+}
+
+impl EventEnvelope {
+	fn new(library_id: Option<Uuid>, event: NodeEvent) -> Self {
+		Self {
+			version: EVENT_STREAM_VERSION,
+			library_id,
+			date: Utc::now(),
+			event,
+		}
+	}
+
+	/// Core events that aren't part of the public stream, like invalidations, are left out
+	fn from_core_event(event: CoreEvent) -> Option<Self> {
+		let (library_id, event) = match event {
+			CoreEvent::FileEvent(event) => (event.library_id, NodeEvent::File(event)),
+			CoreEvent::LibraryEvent(event) => (event.library_id, NodeEvent::Library(event.event)),
+			CoreEvent::Notification(event) => (
+				event.library_id,
+				NodeEvent::Notification(event.notification),
+			),
+			CoreEvent::JobStatus(event) => (event.library_id, NodeEvent::JobStatus(event)),
+			CoreEvent::JobProgress(event) => (event.library_id, NodeEvent::JobProgress(event)),
+			CoreEvent::NewThumbnail { .. } | CoreEvent::InvalidateOperation(_) => return None,
+		};
+
+		Some(Self::new(Some(library_id), event))
+	}
+
+	fn from_sync_message(library_id: Uuid, message: SyncMessage) -> Self {
+		let (ingested, operation) = match message {
+			SyncMessage::Ingested(op) => (true, op),
+			SyncMessage::Created(op) => (false, op),
+		};
+
+		Self::new(
+			Some(library_id),
+			NodeEvent::Sync(SyncEvent {
+				ingested,
+				operation,
+			}),
+		)
+	}
+}
+
+/// Keeps receiving from a channel after lagging behind, the missed messages being lost
+fn receive<T: Clone + Send + 'static>(mut rx: broadcast::Receiver<T>) -> BoxStream<'static, T> {
+	Box::pin(async_stream::stream! {
+		loop {
+			match rx.recv().await {
+				Ok(message) => yield message,
+				Err(RecvError::Lagged(count)) => {
+					warn!("Event stream subscriber lagged behind, missed {count} events");
+				}
+				Err(RecvError::Closed) => break,
+			}
+		}
+	})
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("version", {
+			R.query(|_, _: ()| async move { Ok(EVENT_STREAM_VERSION) })
+		})
+		.procedure("subscribe", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct EventsSubscribeArgs {
+				/// Only the events of these libraries, along with the ones not tied to any library.
+				/// All of them if none are given.
+				#[serde(default)]
+				#[specta(optional)]
+				library_ids: Option<Vec<Uuid>>,
+			}
+
+			R.subscription(|ctx, args: EventsSubscribeArgs| async move {
+				let mut streams = vec![
+					receive(ctx.event_bus.0.subscribe())
+						.filter_map(|event| async move { EventEnvelope::from_core_event(event) })
+						.boxed(),
+					receive(ctx.p2p.subscribe())
+						.map(|event| EventEnvelope::new(None, NodeEvent::P2p(event)))
+						.boxed(),
+				];
+
+				for library in ctx.library_manager.get_all_libraries().await {
+					if args
+						.library_ids
+						.as_ref()
+						.map_or(true, |ids| ids.contains(&library.id))
+					{
+						let library_id = library.id;
+						streams.push(
+							receive(library.sync.tx.subscribe())
+								.map(move |message| {
+									EventEnvelope::from_sync_message(library_id, message)
+								})
+								.boxed(),
+						);
+					}
+				}
+
+				stream::select_all(streams).filter(move |envelope| {
+					let keep = match (&args.library_ids, envelope.library_id) {
+						(Some(ids), Some(library_id)) => ids.contains(&library_id),
+						_ => true,
+					};
+
+					async move { keep }
+				})
+			})
+		})
+}
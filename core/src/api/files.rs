@@ -1,19 +1,34 @@
 use crate::{
-	api::utils::library,
+	api::utils::{editor, library},
 	invalidate_query,
 	job::Job,
-	library::Library,
+	library::{AuditAction, Library},
 	location::{
 		file_path_helper::{
 			file_path_to_isolate, file_path_to_isolate_with_id, FilePathError, IsolatedFilePathData,
 		},
-		find_location, LocationError,
+		find_location,
+		soft_delete::{purge_deleted_file_paths, restore_file_paths},
+		LocationError,
 	},
-	object::fs::{
-		copy::FileCopierJobInit, cut::FileCutterJobInit, delete::FileDeleterJobInit,
-		erase::FileEraserJobInit,
+	object::{
+		content_cache::{list_offline_pins, materialize, pin_offline, unpin_offline},
+		fs::{
+			copy::FileCopierJobInit, cut::FileCutterJobInit, delete::FileDeleterJobInit,
+			erase::FileEraserJobInit,
+			journal::{
+				interrupted_operations, resolve_interrupted_operation, InterruptedOperationAction,
+			},
+		},
+		preview::{get_text_preview, get_waveform},
+		undo::{FileChange, FileRef, UndoableOperation},
+		validation::quarantine::{
+			quarantined_files, release_quarantined, repair_quarantined_in_background,
+		},
+		xmp::spawn_sidecar_write_back,
 	},
-	prisma::{file_path, location, object},
+	prisma::{file_path, location, object, SortOrder},
+	sync,
 };
 
 use std::path::Path;
@@ -23,9 +38,11 @@ use futures::future::join_all;
 use regex::Regex;
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::Deserialize;
+use serde_json::json;
 use specta::Type;
 use tokio::fs;
 use tracing::error;
+use uuid::Uuid;
 
 use super::{Ctx, R};
 
@@ -47,6 +64,41 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?)
 				})
 		})
+		.procedure("getWaveform", {
+			R.with2(library())
+				.query(|(_, library), cas_id: String| async move {
+					Ok(get_waveform(&library, &cas_id).await?)
+				})
+		})
+		.procedure("getTextPreview", {
+			R.with2(library())
+				.query(|(_, library), cas_id: String| async move {
+					Ok(get_text_preview(&library, &cas_id).await?)
+				})
+		})
+		.procedure("prioritizeThumbnails", {
+			R.with2(editor()).mutation(
+				|(ctx, library), file_path_ids: Vec<file_path::id::Type>| async move {
+					// Files on locations of other nodes can't be thumbnailed here until they're
+					// synced, so their owners are asked for the thumbnails they have
+					if let Err(e) = ctx
+						.p2p
+						.fetch_remote_thumbnails(library.clone(), file_path_ids.clone())
+						.await
+					{
+						error!("Failed to fetch thumbnails from peers: {e:#?}");
+					}
+
+					library
+						.thumbnail_prioritizer
+						.clone()
+						.prioritize(library, file_path_ids)
+						.await;
+
+					Ok(())
+				},
+			)
+		})
 		.procedure("setNote", {
 			#[derive(Type, Deserialize)]
 			pub struct SetNoteArgs {
@@ -54,17 +106,36 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub note: Option<String>,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: SetNoteArgs| async move {
-					library
-						.db
+					let Library { db, sync, .. } = &library;
+
+					let object = db
 						.object()
-						.update(
+						.find_unique(object::id::equals(args.id))
+						.select(object::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(rspc::Error::new(
+							ErrorCode::NotFound,
+							"Error finding object in db".into(),
+						))?;
+
+					sync.write_op(
+						db,
+						sync.shared_update(
+							sync::object::SyncId {
+								pub_id: object.pub_id,
+							},
+							object::note::NAME,
+							json!(&args.note),
+						),
+						db.object().update(
 							object::id::equals(args.id),
 							vec![object::note::set(args.note)],
-						)
-						.exec()
-						.await?;
+						),
+					)
+					.await?;
 
 					invalidate_query!(library, "search.paths");
 					invalidate_query!(library, "search.objects");
@@ -79,7 +150,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub favorite: bool,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: SetFavoriteArgs| async move {
 					library
 						.db
@@ -97,8 +168,65 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("setRating", {
+			#[derive(Type, Deserialize)]
+			pub struct SetRatingArgs {
+				pub object_ids: Vec<object::id::Type>,
+				/// From 0 to 5 stars, `null` clears the rating
+				pub rating: Option<i32>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: SetRatingArgs| async move {
+					let Library { db, sync, .. } = &library;
+
+					if matches!(args.rating, Some(rating) if !(0..=5).contains(&rating)) {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Rating must be between 0 and 5".into(),
+						));
+					}
+
+					let objects = db
+						.object()
+						.find_many(vec![object::id::in_vec(args.object_ids.clone())])
+						.select(object::select!({ pub_id }))
+						.exec()
+						.await?;
+
+					sync.write_ops(
+						db,
+						(
+							objects
+								.into_iter()
+								.map(|object| {
+									sync.shared_update(
+										sync::object::SyncId {
+											pub_id: object.pub_id,
+										},
+										object::rating::NAME,
+										json!(args.rating),
+									)
+								})
+								.collect(),
+							db.object().update_many(
+								vec![object::id::in_vec(args.object_ids.clone())],
+								vec![object::rating::set(args.rating)],
+							),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "search.paths");
+					invalidate_query!(library, "search.objects");
+
+					spawn_sidecar_write_back(&library, args.object_ids);
+
+					Ok(())
+				})
+		})
 		.procedure("updateAccessTime", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), id: i32| async move {
 					library
 						.db
@@ -115,7 +243,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("removeAccessTime", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), object_ids: Vec<i32>| async move {
 					library
 						.db
@@ -131,6 +259,66 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("offlinePins", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(list_offline_pins(&library).await?) })
+		})
+		.procedure("pinOffline", {
+			// Only what this node keeps, so viewers can pin too
+			R.with2(library())
+				.mutation(|(ctx, library), id: file_path::id::Type| async move {
+					Ok(pin_offline(&library, ctx.p2p.clone(), id).await?)
+				})
+		})
+		.procedure("unpinOffline", {
+			R.with2(library())
+				.mutation(|(_, library), id: file_path::id::Type| async move {
+					Ok(unpin_offline(&library, id).await?)
+				})
+		})
+		.procedure("materialize", {
+			// Fetches a file of another node whole, for opening it with another app, returning
+			// where it's kept on this one
+			R.with2(library())
+				.mutation(|(ctx, library), id: file_path::id::Type| async move {
+					Ok(materialize(&library, &ctx.p2p, id).await?)
+				})
+		})
+		.procedure("deleted", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(library
+						.db
+						.file_path()
+						.find_many(vec![
+							file_path::location_id::equals(Some(location_id)),
+							file_path::date_deleted::not(None),
+						])
+						.order_by(file_path::date_deleted::order(SortOrder::Desc))
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("restoreDeleted", {
+			R.with2(editor())
+				.mutation(|(_, library), ids: Vec<file_path::id::Type>| async move {
+					restore_file_paths(&library, ids)
+						.await
+						.map(|_| ())
+						.map_err(Into::into)
+				})
+		})
+		.procedure("purgeDeleted", {
+			R.with2(editor()).mutation(
+				|(_, library), location_id: Option<location::id::Type>| async move {
+					// Removing everything marked as deleted, regardless of the grace period
+					purge_deleted_file_paths(&library, location_id, 0)
+						.await
+						.map(|_| ())
+						.map_err(Into::into)
+				},
+			)
+		})
 		// .procedure("encryptFiles", {
 		// 	R.with2(library())
 		// 		.mutation(|(_, library), args: FileEncryptorJobInit| async move {
@@ -144,33 +332,184 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		// 		})
 		// })
 		.procedure("deleteFiles", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: FileDeleterJobInit| async move {
-					Job::new(args).spawn(&library).await.map_err(Into::into)
+					let action = AuditAction::FilesDeleted {
+						location_id: args.location_id,
+						file_path_ids: args.file_path_ids.clone(),
+					};
+
+					Job::new(args).spawn(&library).await?;
+					library.audit(action).await;
+
+					Ok(())
 				})
 		})
 		.procedure("eraseFiles", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: FileEraserJobInit| async move {
-					Job::new(args).spawn(&library).await.map_err(Into::into)
+					let action = AuditAction::FilesErased {
+						location_id: args.location_id,
+						file_path_ids: args.file_path_ids.clone(),
+					};
+
+					Job::new(args).spawn(&library).await?;
+					library.audit(action).await;
+
+					Ok(())
 				})
 		})
 		.procedure("duplicateFiles", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: FileCopierJobInit| async move {
-					Job::new(args).spawn(&library).await.map_err(Into::into)
+					let action = AuditAction::FilesCopied {
+						source_location_id: args.source_location_id,
+						target_location_id: args.target_location_id,
+						file_path_ids: args.sources_file_path_ids.clone(),
+					};
+
+					Job::new(args).spawn(&library).await?;
+					library.audit(action).await;
+
+					Ok(())
 				})
 		})
 		.procedure("copyFiles", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: FileCopierJobInit| async move {
-					Job::new(args).spawn(&library).await.map_err(Into::into)
+					let action = AuditAction::FilesCopied {
+						source_location_id: args.source_location_id,
+						target_location_id: args.target_location_id,
+						file_path_ids: args.sources_file_path_ids.clone(),
+					};
+
+					Job::new(args).spawn(&library).await?;
+					library.audit(action).await;
+
+					Ok(())
 				})
 		})
 		.procedure("cutFiles", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: FileCutterJobInit| async move {
-					Job::new(args).spawn(&library).await.map_err(Into::into)
+					let action = AuditAction::FilesMoved {
+						source_location_id: args.source_location_id,
+						target_location_id: args.target_location_id,
+						file_path_ids: args.sources_file_path_ids.clone(),
+					};
+
+					let target_dir = args
+						.target_location_relative_directory_path
+						.to_string_lossy()
+						.trim_matches('/')
+						.to_string();
+
+					let files = library
+						.db
+						.file_path()
+						.find_many(vec![file_path::id::in_vec(
+							args.sources_file_path_ids.clone(),
+						)])
+						.select(file_path_to_isolate::select())
+						.exec()
+						.await?
+						.into_iter()
+						.flat_map(IsolatedFilePathData::try_from)
+						.map(|iso_file_path| {
+							let from = FileRef::from_iso_file_path(&iso_file_path);
+							let full_name = iso_file_path.full_name();
+							FileChange {
+								to: FileRef {
+									location_id: args.target_location_id,
+									relative_path: if target_dir.is_empty() {
+										full_name
+									} else {
+										format!("{target_dir}/{full_name}")
+									},
+									is_dir: from.is_dir,
+								},
+								from,
+							}
+						})
+						.collect();
+
+					Job::new(args).spawn(&library).await?;
+					library.audit(action).await;
+					library
+						.undo_history
+						.record(UndoableOperation::Move { files })
+						.await;
+					invalidate_query!(library, "files.undoState");
+
+					Ok(())
+				})
+		})
+		.procedure("undoState", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.undo_history.state().await) })
+		})
+		.procedure("undo", {
+			R.with2(editor())
+				.mutation(|(_, library), _: ()| async move {
+					let operation = library.undo_history.undo(&library).await?;
+
+					invalidate_query!(library, "files.undoState");
+
+					Ok(operation)
+				})
+		})
+		.procedure("redo", {
+			R.with2(editor())
+				.mutation(|(_, library), _: ()| async move {
+					let operation = library.undo_history.redo(&library).await?;
+
+					invalidate_query!(library, "files.undoState");
+
+					Ok(operation)
+				})
+		})
+		.procedure("interruptedOperations", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(interrupted_operations(&library).await?)
+			})
+		})
+		.procedure("resolveInterruptedOperation", {
+			#[derive(Type, Deserialize)]
+			pub struct ResolveInterruptedOperationArgs {
+				pub id: Uuid,
+				pub action: InterruptedOperationAction,
+			}
+
+			R.with2(editor()).mutation(
+				|(_, library), args: ResolveInterruptedOperationArgs| async move {
+					resolve_interrupted_operation(&library, args.id, args.action).await?;
+
+					invalidate_query!(library, "files.interruptedOperations");
+					invalidate_query!(library, "search.paths");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("quarantined", {
+			R.with2(library()).query(
+				|(_, library), location_id: Option<location::id::Type>| async move {
+					Ok(quarantined_files(&library, location_id).await?)
+				},
+			)
+		})
+		.procedure("releaseQuarantined", {
+			R.with2(editor())
+				.mutation(|(_, library), ids: Vec<file_path::id::Type>| async move {
+					Ok(release_quarantined(&library, ids).await?)
+				})
+		})
+		.procedure("repairQuarantined", {
+			// Runs in the background, the file showing up again once it's repaired
+			R.with2(editor())
+				.mutation(|(ctx, library), id: file_path::id::Type| async move {
+					repair_quarantined_in_background(library, ctx.p2p.clone(), id);
+					Ok(())
 				})
 		})
 		.procedure("renameFile", {
@@ -272,6 +611,16 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							)
 						})?;
 
+					let from = FileRef::from_iso_file_path(&iso_file_path);
+					let to = from.renamed(&to);
+
+					library
+						.undo_history
+						.record(UndoableOperation::Rename {
+							files: vec![FileChange { from, to }],
+						})
+						.await;
+
 					Ok(())
 				}
 
@@ -293,7 +642,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						));
 					};
 
-					let errors = join_all(
+					let (renamed, errors): (Vec<_>, Vec<_>) = join_all(
 						library
 							.db
 							.file_path()
@@ -316,6 +665,12 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 
 								to.push(&replaced_full_name);
 
+								let from_ref = FileRef::from_iso_file_path(&iso_file_path);
+								let change = FileChange {
+									to: from_ref.renamed(&replaced_full_name),
+									from: from_ref,
+								};
+
 								async move {
 									if !IsolatedFilePathData::accept_file_name(&replaced_full_name)
 									{
@@ -324,7 +679,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 											"Invalid file name".to_string(),
 										))
 									} else {
-										fs::rename(&from, &to).await.map_err(|e| {
+										fs::rename(&from, &to).await.map(|_| change).map_err(|e| {
 											error!(
 													"Failed to rename file from: '{}' to: '{}'; Error: {e:#?}",
 													from.display(),
@@ -342,14 +697,24 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					)
 					.await
 					.into_iter()
-					.filter_map(Result::err)
-					.collect::<Vec<_>>();
+					.partition(Result::is_ok);
+
+					// The files that were renamed can be undone, even if others failed
+					if !renamed.is_empty() {
+						library
+							.undo_history
+							.record(UndoableOperation::Rename {
+								files: renamed.into_iter().filter_map(Result::ok).collect(),
+							})
+							.await;
+					}
 
 					if !errors.is_empty() {
 						return Err(rspc::Error::new(
 							rspc::ErrorCode::Conflict,
 							errors
 								.into_iter()
+								.filter_map(Result::err)
 								.map(|e| e.to_string())
 								.collect::<Vec<_>>()
 								.join("\n"),
@@ -360,7 +725,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				}
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: RenameFileArgs| async move {
 					let location_path = find_location(&library, args.location_id)
 						.select(location::select!({ path }))
@@ -370,6 +735,11 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.path
 						.ok_or(LocationError::MissingPath(args.location_id))?;
 
+					let file_path_ids = match &args.kind {
+						RenameKind::One(one) => vec![one.from_file_path_id],
+						RenameKind::Many(many) => many.from_file_path_ids.clone(),
+					};
+
 					let res = match args.kind {
 						RenameKind::One(one) => {
 							RenameFileArgs::rename_one(one, location_path, &library).await
@@ -380,6 +750,16 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					};
 
 					invalidate_query!(library, "search.objects");
+					invalidate_query!(library, "files.undoState");
+
+					if res.is_ok() {
+						library
+							.audit(AuditAction::FilesRenamed {
+								location_id: args.location_id,
+								file_path_ids,
+							})
+							.await;
+					}
 
 					res
 				})
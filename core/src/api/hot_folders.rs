@@ -0,0 +1,195 @@
+use crate::{
+	invalidate_query,
+	job::Job,
+	library::Library,
+	location::{
+		find_location,
+		hot_folder::{
+			ingest_job::HotFolderIngestJobInit, normalize_path, validate_pipeline, HotFolder,
+			HotFolderError, IngestStep,
+		},
+		LocationError,
+	},
+	prisma::{hot_folder, location},
+	util::db::maybe_missing,
+};
+
+use std::path::Path;
+
+use chrono::Utc;
+use rspc::alpha::AlphaRouter;
+use serde::Deserialize;
+use specta::Type;
+use tokio::fs;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+/// Makes sure the directory of a hot folder exists, returning its normalized path
+async fn check_directory(
+	library: &Library,
+	location_id: location::id::Type,
+	path: &str,
+) -> Result<String, HotFolderError> {
+	let location = find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let path = normalize_path(path);
+	let location_path = maybe_missing(location.path, "location.path")?;
+	let directory = Path::new(&location_path).join(path.trim_start_matches('/'));
+
+	if !fs::metadata(&directory)
+		.await
+		.map(|metadata| metadata.is_dir())
+		.unwrap_or(false)
+	{
+		return Err(HotFolderError::DirectoryNotFound(path));
+	}
+
+	Ok(path)
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				library
+					.db
+					.hot_folder()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(HotFolder::try_from)
+					.collect::<Result<Vec<_>, _>>()
+					.map_err(Into::into)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct HotFolderCreateArgs {
+				pub name: String,
+				pub location_id: location::id::Type,
+				/// Path of the directory in the location, like `/Imports/`
+				pub path: String,
+				pub pipeline: Vec<IngestStep>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: HotFolderCreateArgs| async move {
+					validate_pipeline(&args.pipeline)?;
+					let path = check_directory(&library, args.location_id, &args.path).await?;
+
+					let hot_folder = library
+						.db
+						.hot_folder()
+						.create(vec![
+							hot_folder::name::set(Some(args.name)),
+							hot_folder::location_id::set(Some(args.location_id)),
+							hot_folder::path::set(Some(path)),
+							hot_folder::pipeline::set(Some(
+								serde_json::to_vec(&args.pipeline).map_err(HotFolderError::from)?,
+							)),
+							hot_folder::enabled::set(Some(true)),
+							hot_folder::date_created::set(Some(Utc::now().into())),
+						])
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "hotFolders.list");
+
+					Ok(HotFolder::try_from(hot_folder)?)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct HotFolderUpdateArgs {
+				pub id: hot_folder::id::Type,
+				pub name: Option<String>,
+				pub path: Option<String>,
+				pub pipeline: Option<Vec<IngestStep>>,
+				pub enabled: Option<bool>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: HotFolderUpdateArgs| async move {
+					let hot_folder = library
+						.db
+						.hot_folder()
+						.find_unique(hot_folder::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(HotFolderError::IdNotFound(args.id))?;
+
+					let mut params = vec![hot_folder::date_modified::set(Some(Utc::now().into()))];
+
+					if let Some(name) = args.name {
+						params.push(hot_folder::name::set(Some(name)));
+					}
+
+					if let Some(path) = args.path {
+						let location_id =
+							maybe_missing(hot_folder.location_id, "hot_folder.location_id")
+								.map_err(HotFolderError::from)?;
+						let path = check_directory(&library, location_id, &path).await?;
+						params.push(hot_folder::path::set(Some(path)));
+					}
+
+					if let Some(pipeline) = args.pipeline {
+						validate_pipeline(&pipeline)?;
+						params.push(hot_folder::pipeline::set(Some(
+							serde_json::to_vec(&pipeline).map_err(HotFolderError::from)?,
+						)));
+					}
+
+					if let Some(enabled) = args.enabled {
+						params.push(hot_folder::enabled::set(Some(enabled)));
+					}
+
+					library
+						.db
+						.hot_folder()
+						.update(hot_folder::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "hotFolders.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), id: hot_folder::id::Type| async move {
+					library
+						.db
+						.hot_folder()
+						.delete(hot_folder::id::equals(id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "hotFolders.list");
+
+					Ok(())
+				})
+		})
+		.procedure("ingest", {
+			// Runs the files already in the hot folder through its pipeline
+			R.with2(editor())
+				.mutation(|(_, library), id: hot_folder::id::Type| async move {
+					Job::new(HotFolderIngestJobInit {
+						hot_folder_id: id,
+						file_path_ids: vec![],
+					})
+					.spawn(&library)
+					.await
+					.map_err(Into::into)
+				})
+		})
+}
@@ -0,0 +1,66 @@
+use crate::{
+	invalidate_query,
+	library::NodeRole,
+	p2p::{create_invite, list_invites, revoke_invite, InviteCode},
+};
+
+use rspc::alpha::AlphaRouter;
+use serde::Deserialize;
+use specta::Type;
+use uuid::Uuid;
+
+use super::{
+	utils::{library, owner},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(list_invites(&library).await?) })
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct CreateInviteArgs {
+				pub role: NodeRole,
+				/// A day if not set
+				#[specta(optional)]
+				pub valid_for_hours: Option<i64>,
+			}
+
+			R.with2(owner())
+				.mutation(|(ctx, library), args: CreateInviteArgs| async move {
+					let invite = create_invite(
+						&library,
+						&ctx.config.get().await,
+						args.role,
+						args.valid_for_hours,
+					)
+					.await?;
+
+					invalidate_query!(library, "invites.list");
+
+					Ok(invite)
+				})
+		})
+		.procedure("revoke", {
+			R.with2(owner())
+				.mutation(|(_, library), id: Uuid| async move {
+					revoke_invite(&library, id).await?;
+
+					invalidate_query!(library, "invites.list");
+
+					Ok(())
+				})
+		})
+		.procedure("redeem", {
+			// Joins the library of the invite, so it isn't scoped to a library of this node
+			R.mutation(|ctx, code: String| async move {
+				let code = code.parse::<InviteCode>()?;
+
+				Ok(ctx.p2p.redeem_invite(ctx.config.get().await, &code).await?)
+			})
+		})
+}
@@ -1,13 +1,27 @@
 use crate::{
 	invalidate_query,
-	job::{job_without_data, Job, JobManager, JobReport, JobStatus},
-	location::{find_location, LocationError},
+	job::{
+		export::export_job_report,
+		job_without_data,
+		retention::{cleanup_job_history, JobRetentionPolicy},
+		throttle::JobThrottlePolicy,
+		Job, JobBuilder, JobConcurrencyLimits, JobManager, JobReport, JobStatus,
+	},
+	library::DatabaseMaintenanceJobInit,
+	location::{find_location, location_with_indexer_rules, LocationError},
 	object::{
+		album::rematerialize_smart_albums,
+		embedded_metadata::writer_job::EmbeddedMetadataWriterJobInit,
 		file_identifier::file_identifier_job::FileIdentifierJobInit,
-		preview::thumbnailer_job::ThumbnailerJobInit,
+		import::{apple_photos::import_apple_photos, importer_job::MetadataImporterJobInit},
+		preview::{
+			regenerator_job::{ThumbnailRegenerationMode, ThumbnailRegeneratorJobInit},
+			thumbnailer_job::ThumbnailerJobInit,
+		},
 		validation::validator_job::ObjectValidatorJobInit,
 	},
-	prisma::{job, location, SortOrder},
+	prisma::{file_path, job, location, SortOrder},
+	util::db::chain_optional_iter,
 };
 
 use std::{
@@ -24,7 +38,10 @@ use tokio::time::{interval, Duration};
 use tracing::{info, trace};
 use uuid::Uuid;
 
-use super::{utils::library, CoreEvent, Ctx, R};
+use super::{
+	utils::{editor, library},
+	CoreEvent, Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -159,13 +176,131 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(groups_vec)
 				})
 		})
+		.procedure("history", {
+			// Paginated access to every job report kept in the library, unlike `reports` which
+			// only returns the most recent ones
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct JobHistoryArgs {
+				#[specta(optional)]
+				name: Option<String>,
+				#[serde(default)]
+				statuses: Vec<JobStatus>,
+				#[specta(optional)]
+				created_after: Option<DateTime<Utc>>,
+				#[specta(optional)]
+				created_before: Option<DateTime<Utc>>,
+				#[specta(optional)]
+				take: Option<i32>,
+				#[specta(optional)]
+				cursor: Option<Uuid>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct JobHistory {
+				items: Vec<JobReport>,
+				cursor: Option<Uuid>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: JobHistoryArgs| async move {
+					let take = args.take.unwrap_or(100);
+
+					let mut query = library
+						.db
+						.job()
+						.find_many(chain_optional_iter(
+							[],
+							[
+								args.name.map(Some).map(job::name::equals),
+								(!args.statuses.is_empty()).then(|| {
+									job::status::in_vec(
+										args.statuses
+											.into_iter()
+											.map(|status| status as i32)
+											.collect(),
+									)
+								}),
+								args.created_after
+									.map(|date| job::date_created::gte(date.into())),
+								args.created_before
+									.map(|date| job::date_created::lte(date.into())),
+							],
+						))
+						.order_by(job::date_created::order(SortOrder::Desc))
+						.take(take as i64 + 1);
+
+					if let Some(cursor) = args.cursor {
+						query = query.cursor(job::id::equals(cursor.as_bytes().to_vec()));
+					}
+
+					let mut items = query
+						.select(job_without_data::select())
+						.exec()
+						.await?
+						.into_iter()
+						.flat_map(JobReport::try_from)
+						.collect::<Vec<_>>();
+
+					let cursor = (items.len() as i32 > take)
+						.then(|| items.pop())
+						.flatten()
+						.map(|report| report.id);
+
+					Ok(JobHistory { items, cursor })
+				})
+		})
+		.procedure("exportReport", {
+			// Full report of a job, with its inputs, per-file results and errors, as a JSON
+			// document users can attach to bug reports or keep as proof of what was done
+			R.with2(library())
+				.query(|(ctx, library), id: Uuid| async move {
+					Ok(export_job_report(&library, &ctx.job_manager, id).await?)
+				})
+		})
+		.procedure("retentionPolicy", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.job_retention) })
+		})
+		.procedure("setRetentionPolicy", {
+			R.mutation(|ctx, policy: JobRetentionPolicy| async move {
+				if policy.max_per_type == Some(0) {
+					return Err(rspc::Error::new(
+						rspc::ErrorCode::BadRequest,
+						"At least one job of each type must be kept".to_string(),
+					));
+				}
+
+				ctx.config
+					.write(|mut config| {
+						config.job_retention = policy;
+					})
+					.await
+					.map_err(|err| {
+						rspc::Error::with_cause(
+							rspc::ErrorCode::InternalServerError,
+							"error updating config".into(),
+							err,
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+		.procedure("cleanupHistory", {
+			R.with2(editor())
+				.mutation(|(ctx, library), _: ()| async move {
+					let policy = ctx.config.get().await.job_retention;
+
+					Ok(cleanup_job_history(&library, policy).await? as i32)
+				})
+		})
 		.procedure("isActive", {
 			R.with2(library()).query(|(ctx, _), _: ()| async move {
 				Ok(ctx.job_manager.has_active_workers().await)
 			})
 		})
 		.procedure("clear", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), id: Uuid| async move {
 					library
 						.db
@@ -179,7 +314,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("clearAll", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), _: ()| async move {
 					info!("Clearing all jobs");
 					library
@@ -200,7 +335,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		})
 		// pause job
 		.procedure("pause", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(ctx, library), id: Uuid| async move {
 					let ret = JobManager::pause(&ctx.job_manager, id)
 						.await
@@ -210,7 +345,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("resume", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(ctx, library), id: Uuid| async move {
 					let ret = JobManager::resume(&ctx.job_manager, id)
 						.await
@@ -220,7 +355,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("cancel", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(ctx, library), id: Uuid| async move {
 					let ret = JobManager::cancel(&ctx.job_manager, id)
 						.await
@@ -229,6 +364,72 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					ret
 				})
 		})
+		.procedure("concurrencyLimits", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.job_manager.concurrency_limits().await) })
+		})
+		.procedure("setConcurrencyLimits", {
+			R.mutation(|ctx, limits: JobConcurrencyLimits| async move {
+				if limits.total == 0
+					|| limits.interactive == 0
+					|| limits.normal == 0
+					|| limits.background == 0
+					|| limits
+						.job_types
+						.values()
+						.any(|limit| limit.total == Some(0) || limit.per_scope == Some(0))
+				{
+					return Err(rspc::Error::new(
+						rspc::ErrorCode::BadRequest,
+						"Concurrency limits must be at least 1".to_string(),
+					));
+				}
+
+				ctx.config
+					.write(|mut config| {
+						config.job_concurrency_limits = limits.clone();
+					})
+					.await
+					.map_err(|err| {
+						rspc::Error::with_cause(
+							rspc::ErrorCode::InternalServerError,
+							"error updating config".into(),
+							err,
+						)
+					})?;
+
+				ctx.job_manager.set_concurrency_limits(limits).await;
+
+				Ok(())
+			})
+		})
+		.procedure("powerState", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.job_manager.power_state().await) })
+		})
+		.procedure("throttlePolicy", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.job_manager.throttle_policy().await) })
+		})
+		.procedure("setThrottlePolicy", {
+			R.mutation(|ctx, policy: JobThrottlePolicy| async move {
+				ctx.config
+					.write(|mut config| {
+						config.job_throttle_policy = policy;
+					})
+					.await
+					.map_err(|err| {
+						rspc::Error::with_cause(
+							rspc::ErrorCode::InternalServerError,
+							"error updating config".into(),
+							err,
+						)
+					})?;
+
+				ctx.job_manager
+					.set_throttle(ctx.job_manager.power_state().await, policy)
+					.await;
+
+				Ok(())
+			})
+		})
 		.procedure("generateThumbsForLocation", {
 			#[derive(Type, Deserialize)]
 			pub struct GenerateThumbsForLocationArgs {
@@ -236,7 +437,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub path: PathBuf,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(editor()).mutation(
 				|(_, library), args: GenerateThumbsForLocationArgs| async move {
 					let Some(location) = find_location(&library, args.id).exec().await? else {
 						return Err(LocationError::IdNotFound(args.id).into());
@@ -245,6 +446,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Job::new(ThumbnailerJobInit {
 						location,
 						sub_path: Some(args.path),
+						verify: false,
 					})
 					.spawn(&library)
 					.await
@@ -252,6 +454,33 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("regenerateThumbnails", {
+			#[derive(Type, Deserialize)]
+			pub struct RegenerateThumbnailsArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+				/// Only these files of the location, instead of every file under `path`
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+				pub mode: ThumbnailRegenerationMode,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: RegenerateThumbnailsArgs| async move {
+					let Some(location) = find_location(&library, args.id).exec().await? else {
+						return Err(LocationError::IdNotFound(args.id).into());
+					};
+
+					Job::new(ThumbnailRegeneratorJobInit {
+						location,
+						sub_path: Some(args.path),
+						file_path_ids: args.file_path_ids,
+						mode: args.mode,
+					})
+					.spawn(&library)
+					.await
+					.map_err(Into::into)
+				})
+		})
 		.procedure("objectValidator", {
 			#[derive(Type, Deserialize)]
 			pub struct ObjectValidatorArgs {
@@ -259,7 +488,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub path: PathBuf,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: ObjectValidatorArgs| async move {
 					let Some(location) =  find_location(&library, args.id)
 						.exec()
@@ -284,21 +513,78 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub path: PathBuf,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: IdentifyUniqueFilesArgs| async move {
 					let Some(location) = find_location(&library, args.id).exec().await? else {
 						return Err(LocationError::IdNotFound(args.id).into());
 					};
 
-					Job::new(FileIdentifierJobInit {
+					let identifier = JobBuilder::new(FileIdentifierJobInit {
 						location,
 						sub_path: Some(args.path),
-					})
-					.spawn(&library)
-					.await
-					.map_err(Into::into)
+					});
+					let identifier_id = identifier.id();
+
+					identifier.build().spawn(&library).await?;
+
+					// New objects may now match the search query of some smart albums
+					rematerialize_smart_albums(&library, Some(identifier_id))
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("importMetadata", {
+			R.with2(editor())
+				.mutation(|(_, library), args: MetadataImporterJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				})
+		})
+		.procedure("importApplePhotos", {
+			#[derive(Type, Deserialize)]
+			pub struct ImportApplePhotosArgs {
+				pub location_id: location::id::Type,
+				/// The `.photoslibrary` package
+				pub library_path: PathBuf,
+				/// Directory of the location the originals are copied into
+				pub target_dir: PathBuf,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: ImportApplePhotosArgs| async move {
+					let Some(location) = find_location(&library, args.location_id)
+						.include(location_with_indexer_rules::include())
+						.exec()
+						.await?
+					else {
+						return Err(LocationError::IdNotFound(args.location_id).into());
+					};
+
+					if location.node_id != Some(library.node_local_id) {
+						return Err(rspc::Error::new(
+							rspc::ErrorCode::BadRequest,
+							"Photos can only be imported into locations of this node".into(),
+						));
+					}
+
+					import_apple_photos(&library, location, args.library_path, args.target_dir)
+						.await
+						.map_err(Into::into)
 				})
 		})
+		.procedure("writeEmbeddedMetadata", {
+			R.with2(editor()).mutation(
+				|(_, library), args: EmbeddedMetadataWriterJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("databaseMaintenance", {
+			R.with2(editor()).mutation(
+				|(_, library), args: DatabaseMaintenanceJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				},
+			)
+		})
 		.procedure("newThumbnail", {
 			R.with2(library())
 				.subscription(|(ctx, _), _: ()| async move {
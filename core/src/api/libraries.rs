@@ -1,11 +1,26 @@
 use crate::{
-	library::{LibraryConfig, LibraryName},
-	prisma::statistics,
+	invalidate_query,
+	job::{Job, LibraryJobSettings},
+	library::{
+		database_health, statistics_history, take_statistics_snapshot, DatabaseSettings,
+		ExplorerSettings, IdentityKey, LibraryConfig, LibraryManagerError, LibraryName,
+	},
+	object::{
+		content_cache::{enforce_content_cache_limit, ContentCacheSettings},
+		preview::{
+			enforce_thumbnail_cache_limit, format_migrator_job::ThumbnailFormatMigratorJobInit,
+			ThumbnailSettings,
+		},
+		xmp::XmpSidecarSettings,
+	},
+	prisma::{location, statistics},
 	util::MaybeUndefined,
 	volume::{get_volumes, save_volume},
 };
 
-use chrono::Utc;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
 use rspc::alpha::AlphaRouter;
 use serde::Deserialize;
 use specta::Type;
@@ -13,7 +28,7 @@ use tracing::debug;
 use uuid::Uuid;
 
 use super::{
-	utils::{get_size, library},
+	utils::{editor, get_size, library, owner},
 	Ctx, R,
 };
 
@@ -24,6 +39,27 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				|ctx, _: ()| async move { ctx.library_manager.get_all_libraries_config().await },
 			)
 		})
+		.procedure("broken", {
+			R.query(|ctx, _: ()| async move { ctx.library_manager.get_broken_libraries().await })
+		})
+		.procedure("repair", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct RepairLibraryArgs {
+				pub id: Uuid,
+				/// Dotted path of the field from the root of the config
+				pub field: String,
+				/// Resets the field to its default if not set
+				pub value: Option<serde_json::Value>,
+			}
+
+			R.mutation(|ctx, args: RepairLibraryArgs| async move {
+				Ok(ctx
+					.library_manager
+					.repair(args.id, &args.field, args.value)
+					.await?)
+			})
+		})
 		.procedure("statistics", {
 			R.with2(library()).query(|(_, library), _: ()| async move {
 				let _statistics = library
@@ -47,20 +83,16 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					}
 				}
 
-				let library_db_size = get_size(
-					library
-						.config()
-						.data_directory()
-						.join("libraries")
-						.join(&format!("{}.db", library.id)),
-				)
+				let library_db_size = get_size(library.config.data_directory.database_in_use(
+					library.id,
+					&library.config().data_directory().join("libraries"),
+				))
 				.await
 				.unwrap_or(0);
 
-				let thumbnail_folder_size =
-					get_size(library.config().data_directory().join("thumbnails"))
-						.await
-						.unwrap_or(0);
+				let thumbnail_folder_size = get_size(library.data_directory().join("thumbnails"))
+					.await
+					.unwrap_or(0);
 
 				use statistics::*;
 				let params = vec![
@@ -87,6 +119,30 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.await?)
 			})
 		})
+		.procedure("statisticsHistory", {
+			#[derive(Deserialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct StatisticsHistoryArgs {
+				/// The whole library if not set
+				#[specta(optional)]
+				location_id: Option<location::id::Type>,
+				#[specta(optional)]
+				from: Option<DateTime<Utc>>,
+				#[specta(optional)]
+				to: Option<DateTime<Utc>>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: StatisticsHistoryArgs| async move {
+					Ok(statistics_history(&library, args.location_id, args.from, args.to).await?)
+				})
+		})
+		.procedure("takeStatisticsSnapshot", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					Ok(take_statistics_snapshot(&library).await?)
+				})
+		})
 		.procedure("create", {
 			#[derive(Deserialize, Type)]
 			pub struct CreateLibraryArgs {
@@ -96,13 +152,16 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			R.mutation(|ctx, args: CreateLibraryArgs| async move {
 				debug!("Creating library");
 
-				let new_library = ctx
-					.library_manager
-					.create(
-						LibraryConfig::new(args.name, ctx.config.get().await.id),
-						ctx.config.get().await,
-					)
-					.await?;
+				let node_config = ctx.config.get().await;
+				let config = LibraryConfig::new(
+					args.name,
+					node_config.id,
+					&IdentityKey::new(&node_config.keypair),
+				)
+				.await
+				.map_err(LibraryManagerError::from)?;
+
+				let new_library = ctx.library_manager.create(config, node_config).await?;
 
 				Ok(new_library)
 			})
@@ -122,6 +181,159 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.await?)
 			})
 		})
+		.procedure("thumbnailSettings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.thumbnails) })
+		})
+		.procedure("setThumbnailSettings", {
+			R.with2(owner())
+				.mutation(|(ctx, library), settings: ThumbnailSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.thumbnails = settings)
+						.await?;
+
+					invalidate_query!(library, "library.thumbnailSettings");
+
+					if let Some(new_library) = ctx.library_manager.get_library(library.id).await {
+						// The cache limit may have been lowered
+						enforce_thumbnail_cache_limit(&new_library).await?;
+						invalidate_query!(new_library, "cache.stats");
+
+						if settings.format != library.config.thumbnails.format {
+							Job::new(ThumbnailFormatMigratorJobInit {})
+								.spawn(&new_library)
+								.await?;
+						}
+					}
+
+					Ok(())
+				})
+		})
+		.procedure("databaseHealth", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(database_health(&library).await?) })
+		})
+		.procedure("databaseSettings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.database) })
+		})
+		.procedure("setDatabaseSettings", {
+			R.with2(owner())
+				.mutation(|(ctx, library), settings: DatabaseSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.database = settings)
+						.await?;
+
+					// The library keeps the same connection, so they can be applied right away
+					settings.apply(&library.db).await?;
+
+					invalidate_query!(library, "library.databaseSettings");
+
+					Ok(())
+				})
+		})
+		.procedure("xmpSidecarSettings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.xmp_sidecars) })
+		})
+		.procedure("setXmpSidecarSettings", {
+			R.with2(owner())
+				.mutation(|(ctx, library), settings: XmpSidecarSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.xmp_sidecars = settings)
+						.await?;
+
+					invalidate_query!(library, "library.xmpSidecarSettings");
+
+					Ok(())
+				})
+		})
+		.procedure("contentCacheSettings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.content_cache) })
+		})
+		.procedure("setContentCacheSettings", {
+			// Only how much this node keeps, so it isn't restricted to owners
+			R.with2(library()).mutation(
+				|(ctx, library), settings: ContentCacheSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.content_cache = settings)
+						.await?;
+
+					invalidate_query!(library, "library.contentCacheSettings");
+
+					if let Some(new_library) = ctx.library_manager.get_library(library.id).await {
+						// The cache limit may have been lowered
+						enforce_content_cache_limit(&new_library).await?;
+						invalidate_query!(new_library, "cache.contentStats");
+					}
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("explorerSettings", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.config.settings.explorer.clone())
+			})
+		})
+		.procedure("setExplorerSettings", {
+			R.with2(editor())
+				.mutation(|(ctx, library), settings: ExplorerSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.settings.explorer = settings)
+						.await?;
+
+					invalidate_query!(library, "library.explorerSettings");
+
+					Ok(())
+				})
+		})
+		.procedure("jobSettings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.jobs) })
+		})
+		.procedure("setJobSettings", {
+			// Tuned for the disks of this node, so it isn't restricted to owners
+			R.with2(library())
+				.mutation(|(ctx, library), settings: LibraryJobSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.jobs = settings)
+						.await?;
+
+					ctx.job_manager
+						.set_library_job_settings(library.id, settings)
+						.await;
+
+					invalidate_query!(library, "library.jobSettings");
+
+					Ok(())
+				})
+		})
+		.procedure("dataDirectory", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.data_directory) })
+		})
+		.procedure("setDataDirectory", {
+			// Only where this node keeps the library, so it isn't restricted to owners
+			R.with2(library())
+				.mutation(|(ctx, library), path: Option<PathBuf>| async move {
+					let job = ctx
+						.library_manager
+						.set_data_directory(library.id, path)
+						.await?;
+
+					invalidate_query!(library, "library.dataDirectory");
+
+					if let (Some(job), Some(new_library)) =
+						(job, ctx.library_manager.get_library(library.id).await)
+					{
+						Job::new(job).spawn(&new_library).await?;
+					}
+
+					Ok(())
+				})
+		})
 		.procedure(
 			"delete",
 			R.mutation(|ctx, id: Uuid| async move { Ok(ctx.library_manager.delete(id).await?) }),
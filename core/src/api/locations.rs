@@ -1,10 +1,16 @@
 use crate::{
 	invalidate_query,
 	location::{
-		delete_location, find_location, indexer::rules::IndexerRuleCreateArgs, light_scan_location,
-		location_with_indexer_rules, relink_location, scan_location, LocationCreateArgs,
-		LocationError, LocationUpdateArgs,
+		delete_location, ensure_online,
+		file_path_helper::{check_file_path_exists, IsolatedFilePathData},
+		find_location,
+		indexer::rules::IndexerRuleCreateArgs,
+		light_scan_location, location_with_indexer_rules, relink_location, scan_location,
+		space::{largest_items, treemap},
+		LocationCreateArgs, LocationError, LocationUpdateArgs,
 	},
+	object::export::{export_file_paths, ListingExportFormat},
+	p2p::{DelegationError, RemoteDirectoryRequest},
 	prisma::{file_path, indexer_rule, indexer_rules_in_location, location, object, SortOrder},
 	util::AbortOnDrop,
 };
@@ -14,9 +20,14 @@ use std::path::PathBuf;
 use rspc::{self, alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tracing::info;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+use uuid::Uuid;
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{editor, library},
+	CoreEvent, Ctx, R,
+};
 
 #[derive(Serialize, Type, Debug)]
 #[serde(tag = "type")]
@@ -82,7 +93,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("create", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: LocationCreateArgs| async move {
 					if let Some(location) = args.create(&library).await? {
 						scan_location(&library, location).await?;
@@ -93,7 +104,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("update", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: LocationUpdateArgs| async move {
 					let ret = args.update(&library).await.map_err(Into::into);
 					invalidate_query!(library, "locations.list");
@@ -101,7 +112,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("delete", {
-			R.with2(library()).mutation(
+			R.with2(editor()).mutation(
 				|(_, library), location_id: location::id::Type| async move {
 					delete_location(&library, location_id).await?;
 					invalidate_query!(library, "locations.list");
@@ -110,7 +121,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			)
 		})
 		.procedure("relink", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), location_path: PathBuf| async move {
 					relink_location(&library, location_path)
 						.await
@@ -118,7 +129,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("addLibrary", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: LocationCreateArgs| async move {
 					if let Some(location) = args.add_library(&library).await? {
 						scan_location(&library, location).await?;
@@ -134,12 +145,19 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub reidentify_objects: bool,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(editor()).mutation(
 				|(_, library),
 				 FullRescanArgs {
 				     location_id,
 				     reidentify_objects,
 				 }| async move {
+					let location = find_location(&library, location_id)
+						.include(location_with_indexer_rules::include())
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+					ensure_online(&library, &location::Data::from(&location)).await?;
+
 					if reidentify_objects {
 						let object_ids = library
 							.db
@@ -166,16 +184,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					}
 
 					// rescan location
-					scan_location(
-						&library,
-						find_location(&library, location_id)
-							.include(location_with_indexer_rules::include())
-							.exec()
-							.await?
-							.ok_or(LocationError::IdNotFound(location_id))?,
-					)
-					.await
-					.map_err(Into::into)
+					scan_location(&library, location).await.map_err(Into::into)
 				},
 			)
 		})
@@ -200,6 +209,192 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(AbortOnDrop(handle))
 				})
 		})
+		.procedure("exportListing", {
+			#[derive(Deserialize, Type, Debug)]
+			pub struct ExportListingArgs {
+				pub location_id: location::id::Type,
+				/// Only export this directory of the location, with everything under it
+				pub sub_path: Option<String>,
+				pub format: ListingExportFormat,
+				pub output_path: PathBuf,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: ExportListingArgs| async move {
+					find_location(&library, args.location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_id))?;
+
+					let mut params = vec![
+						file_path::location_id::equals(Some(args.location_id)),
+						file_path::date_deleted::equals(None),
+						file_path::date_quarantined::equals(None),
+					];
+
+					if let Some(sub_path) = args
+						.sub_path
+						.filter(|sub_path| !sub_path.is_empty() && sub_path != "/")
+					{
+						let iso_file_path =
+							IsolatedFilePathData::from_relative_str(args.location_id, &sub_path);
+
+						if !check_file_path_exists::<LocationError>(&iso_file_path, &library.db)
+							.await?
+						{
+							return Err(rspc::Error::new(
+								ErrorCode::NotFound,
+								"Directory not found".into(),
+							));
+						}
+
+						if let Some(materialized_path) =
+							iso_file_path.materialized_path_for_children()
+						{
+							params
+								.push(file_path::materialized_path::starts_with(materialized_path));
+						}
+					}
+
+					Ok(export_file_paths(
+						&library,
+						params,
+						Some(file_path::materialized_path::order(SortOrder::Asc)),
+						args.format,
+						args.output_path,
+					)
+					.await?)
+				})
+		})
+		.procedure("spaceAnalyzer", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SpaceAnalyzerArgs {
+				pub location_id: location::id::Type,
+				/// Analyze this directory of the location instead of its root
+				#[specta(optional)]
+				pub sub_path: Option<String>,
+				/// Levels of directories whose contents are returned, 1 if not set
+				#[specta(optional)]
+				pub depth: Option<u32>,
+				/// Heaviest items returned for each directory, 20 if not set
+				#[specta(optional)]
+				pub take: Option<u32>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: SpaceAnalyzerArgs| async move {
+					Ok(largest_items(
+						&library,
+						args.location_id,
+						args.sub_path.as_deref(),
+						args.depth.unwrap_or(1).min(8),
+						args.take.unwrap_or(20) as usize,
+					)
+					.await?)
+				})
+		})
+		.procedure("treemap", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct TreemapArgs {
+				pub location_id: location::id::Type,
+				#[specta(optional)]
+				pub sub_path: Option<String>,
+				/// Levels of directories whose contents are returned, 3 if not set
+				#[specta(optional)]
+				pub depth: Option<u32>,
+				/// Share of the whole size below which items are only summed up, 0.001 if not set
+				#[specta(optional)]
+				pub min_size_ratio: Option<f64>,
+				/// Items returned for each directory, 100 if not set
+				#[specta(optional)]
+				pub max_children: Option<u32>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: TreemapArgs| async move {
+					Ok(treemap(
+						&library,
+						args.location_id,
+						args.sub_path.as_deref(),
+						args.depth.unwrap_or(3).min(8),
+						args.min_size_ratio.unwrap_or(0.001),
+						args.max_children.unwrap_or(100) as usize,
+					)
+					.await?)
+				})
+		})
+		.procedure("browseRemote", {
+			// Lists a directory of a location on another node as it is on its disk, even if the
+			// location isn't synced to this one
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct BrowseRemoteArgs {
+				pub location_pub_id: Uuid,
+				/// Lists the root of the location if not set
+				#[specta(optional)]
+				pub sub_path: Option<String>,
+			}
+
+			R.with2(library())
+				.query(|(ctx, library), args: BrowseRemoteArgs| async move {
+					let path = args
+						.sub_path
+						.as_deref()
+						.unwrap_or_default()
+						.trim_matches('/')
+						.into();
+
+					ctx.p2p
+						.browse_remote_directory(
+							library,
+							RemoteDirectoryRequest {
+								location_pub_id: args.location_pub_id,
+								path,
+							},
+						)
+						.await
+						.map_err(|err| match err {
+							DelegationError::Remote(_) => {
+								rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+							}
+							_ => rspc::Error::with_cause(
+								ErrorCode::InternalServerError,
+								"Failed to browse the location's node".to_string(),
+								err,
+							),
+						})
+				})
+		})
+		.procedure("fileEvents", {
+			// Streams the changes the watchers see in the locations of the library, or only in
+			// the given one
+			R.with2(library()).subscription(
+				|(ctx, library), location_id: Option<location::id::Type>| async move {
+					let mut event_bus_rx = ctx.event_bus.0.subscribe();
+
+					async_stream::stream! {
+						loop {
+							match event_bus_rx.recv().await {
+								Ok(CoreEvent::FileEvent(event))
+									if event.library_id == library.id
+										&& location_id.map_or(true, |id| id == event.location_id) =>
+								{
+									yield event;
+								}
+								Ok(_) => {}
+								// Slow subscribers miss some events rather than being disconnected
+								Err(RecvError::Lagged(count)) => {
+									warn!("File events subscriber lagged behind, missed {count} events");
+								}
+								Err(RecvError::Closed) => break,
+							}
+						}
+					}
+				},
+			)
+		})
 		.procedure(
 			"online",
 			R.subscription(|ctx, _: ()| async move {
@@ -224,7 +419,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 fn mount_indexer_rule_routes() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("create", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: IndexerRuleCreateArgs| async move {
 					if args.create(&library).await?.is_some() {
 						invalidate_query!(library, "locations.indexer_rules.list");
@@ -234,7 +429,7 @@ fn mount_indexer_rule_routes() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("delete", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), indexer_rule_id: i32| async move {
 					let indexer_rule_db = library.db.indexer_rule();
 
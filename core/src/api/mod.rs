@@ -1,4 +1,10 @@
-use crate::{job::JobProgressEvent, node::SanitisedNodeConfig, Node};
+use crate::{
+	job::{JobProgressEvent, JobStatusEvent},
+	library::{LibraryEvent, NotificationEvent},
+	location::FileEvent,
+	node::SanitisedNodeConfig,
+	Node,
+};
 use rspc::{alpha::Rspc, Config};
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -17,22 +23,47 @@ pub type Router = rspc::Router<Ctx>;
 pub enum CoreEvent {
 	NewThumbnail { thumb_key: Vec<String> },
 	JobProgress(JobProgressEvent),
+	JobStatus(JobStatusEvent),
 	InvalidateOperation(InvalidateOperationEvent),
+	FileEvent(FileEvent),
+	Notification(NotificationEvent),
+	LibraryEvent(LibraryEvent),
 }
 
+mod albums;
+mod analytics;
+mod api_tokens;
+mod audit_log;
+mod automation;
+mod backups;
+mod batch;
+mod cache;
 mod categories;
+mod comments;
+mod custom_fields;
+mod errors;
+mod events;
 mod files;
+mod hot_folders;
+mod invites;
 mod jobs;
 mod keys;
 mod libraries;
 mod locations;
 mod nodes;
+mod notifications;
 mod p2p;
-mod search;
+mod plugins;
+mod providers;
+mod remotes;
+mod scheduled_jobs;
+pub(crate) mod search;
+mod shares;
 mod sync;
 mod tags;
 pub mod utils;
 pub mod volumes;
+mod webhooks;
 
 #[derive(Serialize, Deserialize, Debug, Type)]
 struct NodeState {
@@ -72,16 +103,37 @@ pub(crate) fn mount() -> Arc<Router> {
 		})
 		.merge("search.", search::mount())
 		.merge("library.", libraries::mount())
+		.merge("backups.", backups::mount())
 		.merge("volumes.", volumes::mount())
+		.merge("cache.", cache::mount())
 		.merge("tags.", tags::mount())
+		.merge("albums.", albums::mount())
+		.merge("customFields.", custom_fields::mount())
+		.merge("comments.", comments::mount())
 		.merge("categories.", categories::mount())
 		// .merge("keys.", keys::mount())
 		.merge("locations.", locations::mount())
 		.merge("files.", files::mount())
+		.merge("batch.", batch::mount())
 		.merge("jobs.", jobs::mount())
+		.merge("scheduledJobs.", scheduled_jobs::mount())
+		.merge("automation.", automation::mount())
+		.merge("hotFolders.", hot_folders::mount())
+		.merge("webhooks.", webhooks::mount())
+		.merge("remotes.", remotes::mount())
+		.merge("shares.", shares::mount())
 		.merge("p2p.", p2p::mount())
+		.merge("plugins.", plugins::mount())
+		.merge("providers.", providers::mount())
+		.merge("invites.", invites::mount())
 		.merge("nodes.", nodes::mount())
+		.merge("apiTokens.", api_tokens::mount())
+		.merge("analytics.", analytics::mount())
+		.merge("auditLog.", audit_log::mount())
+		.merge("notifications.", notifications::mount())
 		.merge("sync.", sync::mount())
+		.merge("events.", events::mount())
+		.merge("errors.", errors::mount())
 		.merge("invalidation.", utils::mount_invalidate())
 		.build(
 			#[allow(clippy::let_and_return)]
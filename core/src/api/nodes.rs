@@ -1,11 +1,27 @@
-use crate::prisma::{location, node};
+use crate::{
+	invalidate_query,
+	library::{Library, NodeRole},
+	location::soft_delete::SoftDeletePolicy,
+	object::{
+		antivirus::AntivirusSettings, preview::ThumbnailerPreferences,
+		sensitive::SensitiveContentSettings,
+	},
+	prisma::{location, node},
+	sync,
+};
 use rspc::{alpha::AlphaRouter, ErrorCode};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use specta::Type;
 use tracing::error;
+use uuid::Uuid;
 
-use super::{locations::ExplorerItem, utils::library, Ctx, R};
+use super::{
+	locations::ExplorerItem,
+	utils::{library, owner},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -42,6 +58,100 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				Ok(())
 			})
 		})
+		.procedure("thumbnailerPreferences", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.thumbnailer) })
+		})
+		.procedure("setThumbnailerPreferences", {
+			R.mutation(|ctx, preferences: ThumbnailerPreferences| async move {
+				ctx.config
+					.write(|mut config| {
+						config.thumbnailer = preferences;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				// Whether the node can thumbnail for its peers depends on them
+				ctx.p2p.update_metadata(&ctx.config).await;
+
+				Ok(())
+			})
+		})
+		.procedure("antivirusSettings", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.antivirus) })
+		})
+		.procedure("setAntivirusSettings", {
+			R.mutation(|ctx, settings: AntivirusSettings| async move {
+				ctx.config
+					.write(|mut config| {
+						config.antivirus = settings;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+		.procedure("sensitiveContentSettings", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.sensitive_content) })
+		})
+		.procedure("setSensitiveContentSettings", {
+			R.mutation(|ctx, settings: SensitiveContentSettings| async move {
+				if !(0.0..=1.0).contains(&settings.threshold) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"the threshold must be between 0 and 1".into(),
+					));
+				}
+
+				ctx.config
+					.write(|mut config| {
+						config.sensitive_content = settings;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+		.procedure("softDeletePolicy", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.soft_delete) })
+		})
+		.procedure("setSoftDeletePolicy", {
+			R.mutation(|ctx, policy: SoftDeletePolicy| async move {
+				ctx.config
+					.write(|mut config| {
+						config.soft_delete = policy;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
 		// TODO: add pagination!! and maybe ordering etc
 		.procedure("listLocations", {
 			R.with2(library())
@@ -79,4 +189,86 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(vec![])
 				})
 		})
+		.procedure("listInLibrary", {
+			#[derive(Serialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct LibraryNode {
+				pub pub_id: Uuid,
+				pub name: String,
+				pub platform: i32,
+				pub role: NodeRole,
+				pub is_current: bool,
+			}
+
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					.node()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.filter_map(|node| {
+						Some(LibraryNode {
+							pub_id: Uuid::from_slice(&node.pub_id).ok()?,
+							role: NodeRole::of_node(node.role.as_deref()),
+							is_current: node.id == library.node_local_id,
+							name: node.name,
+							platform: node.platform,
+						})
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("setRole", {
+			#[derive(Deserialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SetNodeRoleArgs {
+				pub pub_id: Uuid,
+				pub role: NodeRole,
+			}
+
+			R.with2(owner())
+				.mutation(|(_, library), args: SetNodeRoleArgs| async move {
+					let Library { sync, db, .. } = &library;
+
+					let node = db
+						.node()
+						.find_unique(node::pub_id::equals(args.pub_id.as_bytes().to_vec()))
+						.exec()
+						.await?
+						.ok_or_else(|| {
+							rspc::Error::new(ErrorCode::NotFound, "node not found".into())
+						})?;
+
+					// Keeps the library from being left without an owner
+					if node.id == library.node_local_id {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"can't change the role of this node".into(),
+						));
+					}
+
+					// Synced so the node itself learns of its new role
+					sync.write_op(
+						db,
+						sync.shared_update(
+							sync::node::SyncId {
+								pub_id: node.pub_id.clone(),
+							},
+							node::role::NAME,
+							json!(args.role.as_str()),
+						),
+						db.node().update(
+							node::id::equals(node.id),
+							vec![node::role::set(Some(args.role.as_str().to_string()))],
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "nodes.listInLibrary");
+
+					Ok(())
+				})
+		})
 }
@@ -0,0 +1,273 @@
+use crate::{
+	invalidate_query,
+	library::{
+		delete_notifications, list_notifications, set_notifications_read,
+		unread_notifications_count, NotificationItem, NotificationSettings, NotificationSeverity,
+	},
+	node::{
+		deliver_with_provider, NotificationMessage, NotificationProvider,
+		NotificationProviderConfig, NotificationProviderError, SanitisedNotificationProvider,
+	},
+	prisma::notification,
+};
+
+use chrono::Utc;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use super::{utils::library, CoreEvent, Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct NotificationListArgs {
+				#[serde(default)]
+				unread_only: bool,
+				#[specta(optional)]
+				take: Option<i32>,
+				#[specta(optional)]
+				cursor: Option<notification::id::Type>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct NotificationPage {
+				items: Vec<NotificationItem>,
+				cursor: Option<notification::id::Type>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: NotificationListArgs| async move {
+					let take = args.take.unwrap_or(50);
+
+					let mut items = list_notifications(
+						&library,
+						args.unread_only,
+						take as i64 + 1,
+						args.cursor,
+					)
+					.await?;
+
+					let cursor = (items.len() as i32 > take)
+						.then(|| items.pop())
+						.flatten()
+						.map(|item| item.id);
+
+					Ok(NotificationPage { items, cursor })
+				})
+		})
+		.procedure("unreadCount", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(unread_notifications_count(&library).await? as i32)
+			})
+		})
+		.procedure("setRead", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct NotificationSetReadArgs {
+				/// All the notifications if none are given
+				#[specta(optional)]
+				ids: Option<Vec<notification::id::Type>>,
+				read: bool,
+			}
+
+			R.with2(library())
+				.mutation(|(_, library), args: NotificationSetReadArgs| async move {
+					set_notifications_read(&library, args.ids, args.read).await?;
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			// All the read notifications if no ids are given
+			R.with2(library()).mutation(
+				|(_, library), ids: Option<Vec<notification::id::Type>>| async move {
+					delete_notifications(&library, ids).await?;
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("listen", {
+			// Streams the notifications raised in the library as they come
+			R.with2(library())
+				.subscription(|(ctx, library), _: ()| async move {
+					let mut event_bus_rx = ctx.event_bus.0.subscribe();
+
+					async_stream::stream! {
+						loop {
+							match event_bus_rx.recv().await {
+								Ok(CoreEvent::Notification(event)) if event.library_id == library.id => {
+									yield event.notification;
+								}
+								Ok(_) => {}
+								Err(RecvError::Lagged(count)) => {
+									warn!("Notifications subscriber lagged behind, missed {count} events");
+								}
+								Err(RecvError::Closed) => break,
+							}
+						}
+					}
+				})
+		})
+		.procedure("settings", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(library.config.notifications) })
+		})
+		.procedure("setSettings", {
+			R.with2(library()).mutation(
+				|(ctx, library), settings: NotificationSettings| async move {
+					ctx.library_manager
+						.update_config(library.id, |config| config.notifications = settings)
+						.await?;
+
+					invalidate_query!(library, "notifications.settings");
+
+					Ok(())
+				},
+			)
+		})
+		.merge("providers.", mount_providers())
+}
+
+fn config_write_error(err: impl std::fmt::Display) -> rspc::Error {
+	error!("Failed to write config: {}", err);
+	rspc::Error::new(
+		ErrorCode::InternalServerError,
+		"error updating config".into(),
+	)
+}
+
+/// Providers delivering notifications outside of the app, set for the whole node
+fn mount_providers() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.query(|ctx, _: ()| async move {
+				Ok(ctx
+					.config
+					.get()
+					.await
+					.notification_providers
+					.into_iter()
+					.map(SanitisedNotificationProvider::from)
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("create", {
+			#[derive(Deserialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct CreateNotificationProviderArgs {
+				pub name: String,
+				pub min_severity: NotificationSeverity,
+				pub provider: NotificationProvider,
+			}
+
+			R.mutation(|ctx, args: CreateNotificationProviderArgs| async move {
+				let config =
+					NotificationProviderConfig::new(args.name, args.min_severity, args.provider)?;
+				let id = config.id;
+
+				ctx.config
+					.write(|mut node_config| node_config.notification_providers.push(config))
+					.await
+					.map_err(config_write_error)?;
+
+				Ok(id)
+			})
+		})
+		.procedure("update", {
+			#[derive(Deserialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct UpdateNotificationProviderArgs {
+				pub id: Uuid,
+				pub name: String,
+				pub enabled: bool,
+				pub min_severity: NotificationSeverity,
+				/// Secrets left out keep their current value
+				pub provider: NotificationProvider,
+			}
+
+			R.mutation(|ctx, args: UpdateNotificationProviderArgs| async move {
+				let mut result = Err(NotificationProviderError::NotFound(args.id));
+
+				ctx.config
+					.write(|mut node_config| {
+						if let Some(config) = node_config
+							.notification_providers
+							.iter_mut()
+							.find(|config| config.id == args.id)
+						{
+							result = config.update(
+								args.name,
+								args.enabled,
+								args.min_severity,
+								args.provider,
+							);
+						}
+					})
+					.await
+					.map_err(config_write_error)?;
+
+				Ok(result?)
+			})
+		})
+		.procedure("delete", {
+			R.mutation(|ctx, id: Uuid| async move {
+				let mut found = false;
+
+				ctx.config
+					.write(|mut node_config| {
+						let count = node_config.notification_providers.len();
+						node_config
+							.notification_providers
+							.retain(|config| config.id != id);
+						found = node_config.notification_providers.len() != count;
+					})
+					.await
+					.map_err(config_write_error)?;
+
+				if !found {
+					return Err(NotificationProviderError::NotFound(id).into());
+				}
+
+				Ok(())
+			})
+		})
+		.procedure("test", {
+			// Delivers a test notification through the provider, even if it's disabled
+			R.mutation(|ctx, id: Uuid| async move {
+				let config = ctx
+					.config
+					.get()
+					.await
+					.notification_providers
+					.into_iter()
+					.find(|config| config.id == id)
+					.ok_or(NotificationProviderError::NotFound(id))?;
+
+				let message = NotificationMessage {
+					library_id: Uuid::nil(),
+					library_name: "Spacedrive".to_string(),
+					title: "Test notification".to_string(),
+					body: format!("Notifications are delivered through '{}'", config.name),
+					notification: NotificationItem {
+						id: 0,
+						category: "test".to_string(),
+						severity: config.min_severity,
+						data: None,
+						read: false,
+						date_created: Utc::now().into(),
+					},
+				};
+
+				deliver_with_provider(&config.provider, &message).await?;
+
+				Ok(())
+			})
+		})
+}
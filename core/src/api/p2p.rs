@@ -3,11 +3,18 @@ use sd_p2p::PeerId;
 use serde::Deserialize;
 use specta::Type;
 use std::path::PathBuf;
+use tracing::error;
 use uuid::Uuid;
 
-use crate::p2p::P2PEvent;
+use crate::p2p::{
+	CapabilitiesConfig, DelegatedJobRequest, DelegationError, P2PEvent, RelayConfig, RelayError,
+	SanitisedRelayConfig, SpacedropPolicy, SpacedropSettings,
+};
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{editor, owner},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -38,25 +45,27 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		.procedure("spacedrop", {
 			#[derive(Type, Deserialize)]
 			pub struct SpacedropArgs {
-				peer_id: PeerId,
+				peer_ids: Vec<PeerId>,
 				file_path: Vec<String>,
 			}
 
 			R.mutation(|ctx, args: SpacedropArgs| async move {
-				// TODO: Handle multiple files path and error if zero paths
-				ctx.p2p
-					.big_bad_spacedrop(
-						args.peer_id,
-						PathBuf::from(
-							args.file_path
-								.first()
-								.expect("https://linear.app/spacedriveapp/issue/ENG-625/spacedrop-multiple-files"),
-						),
-					)
-					.await
-					.map_err(|_| {
-						rspc::Error::new(ErrorCode::InternalServerError, "todo".to_string())
-					})
+				if args.peer_ids.is_empty() {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"no peer to Spacedrop to".into(),
+					));
+				}
+
+				// TODO: Handle multiple files path
+				let Some(path) = args.file_path.first() else {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"no file to Spacedrop".into(),
+					));
+				};
+
+				Ok(ctx.p2p.spacedrop(args.peer_ids, PathBuf::from(path)).await)
 			})
 		})
 		.procedure("acceptSpacedrop", {
@@ -67,6 +76,86 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				}
 			})
 		})
+		.procedure("spacedropSettings", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.spacedrop) })
+		})
+		.procedure("setSpacedropSettings", {
+			R.mutation(|ctx, settings: SpacedropSettings| async move {
+				if let Some(receive_dir) = &settings.receive_dir {
+					if !receive_dir.is_absolute() || !receive_dir.is_dir() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"the receive directory must be an existing absolute path".into(),
+						));
+					}
+				}
+
+				ctx.config
+					.write(|mut config| {
+						config.spacedrop = settings;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+		.procedure("setPeerSpacedropPolicy", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SetPeerSpacedropPolicyArgs {
+				peer_id: PeerId,
+				/// Falls back to the default policy if not set
+				#[specta(optional)]
+				policy: Option<SpacedropPolicy>,
+			}
+
+			R.mutation(|ctx, args: SetPeerSpacedropPolicyArgs| async move {
+				ctx.config
+					.write(|mut config| {
+						config.spacedrop.set_peer_policy(&args.peer_id, args.policy);
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+		.procedure("capabilities", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.config.get().await.capabilities) })
+		})
+		.procedure("setCapabilities", {
+			R.mutation(|ctx, capabilities: CapabilitiesConfig| async move {
+				ctx.config
+					.write(|mut config| {
+						config.capabilities = capabilities;
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				ctx.p2p.update_metadata(&ctx.config).await;
+
+				Ok(())
+			})
+		})
 		.procedure("spacedropProgress", {
 			R.subscription(|ctx, id: Uuid| async move {
 				ctx.p2p.spacedrop_progress(id).await.ok_or_else(|| {
@@ -75,7 +164,135 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			})
 		})
 		.procedure("pair", {
-			R.with2(library())
+			R.with2(owner())
 				.mutation(|(ctx, lib), id: PeerId| async move { ctx.p2p.pair(id, lib) })
 		})
+		.procedure("delegateJob", {
+			#[derive(Type, Deserialize)]
+			pub struct DelegateJobArgs {
+				/// The discovered node of the library best suited to run the job if not set
+				#[specta(optional)]
+				peer_id: Option<PeerId>,
+				request: DelegatedJobRequest,
+			}
+
+			R.with2(editor())
+				.mutation(|(ctx, library), args: DelegateJobArgs| async move {
+					ctx.p2p
+						.delegate_job(args.peer_id, library, args.request)
+						.await
+						.map_err(|err| match err {
+							DelegationError::Unsupported(_) | DelegationError::NoCapablePeer => {
+								rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+							}
+							_ => rspc::Error::with_cause(
+								ErrorCode::InternalServerError,
+								"Failed to delegate job to peer".to_string(),
+								err,
+							),
+						})
+				})
+		})
+		.merge("relays.", mount_relays())
+}
+
+fn config_write_error(err: impl std::fmt::Display) -> rspc::Error {
+	error!("Failed to write config: {}", err);
+	rspc::Error::new(
+		ErrorCode::InternalServerError,
+		"error updating config".into(),
+	)
+}
+
+/// Self-hosted relays the node registers with to reach peers beyond the local network
+fn mount_relays() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.query(|ctx, _: ()| async move {
+				let mut relays = Vec::new();
+				for relay in ctx.config.get().await.p2p_relays {
+					let status = ctx.p2p.relay_status(relay.id).await;
+					relays.push(SanitisedRelayConfig::new(relay, status));
+				}
+
+				Ok(relays)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct CreateRelayArgs {
+				url: String,
+				#[specta(optional)]
+				token: Option<String>,
+			}
+
+			R.mutation(|ctx, args: CreateRelayArgs| async move {
+				let relay = RelayConfig::new(args.url, args.token)?;
+				let id = relay.id;
+
+				ctx.config
+					.write(|mut config| config.p2p_relays.push(relay))
+					.await
+					.map_err(config_write_error)?;
+
+				ctx.p2p.refresh_relays();
+
+				Ok(id)
+			})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct UpdateRelayArgs {
+				id: Uuid,
+				url: String,
+				/// Keeps the current token if not set
+				#[specta(optional)]
+				token: Option<String>,
+				enabled: bool,
+			}
+
+			R.mutation(|ctx, args: UpdateRelayArgs| async move {
+				let mut result = Err(RelayError::NotFound(args.id));
+
+				ctx.config
+					.write(|mut config| {
+						if let Some(relay) = config
+							.p2p_relays
+							.iter_mut()
+							.find(|relay| relay.id == args.id)
+						{
+							result = relay.update(args.url, args.token, args.enabled);
+						}
+					})
+					.await
+					.map_err(config_write_error)?;
+
+				result?;
+				ctx.p2p.refresh_relays();
+
+				Ok(())
+			})
+		})
+		.procedure("delete", {
+			R.mutation(|ctx, id: Uuid| async move {
+				let mut found = false;
+
+				ctx.config
+					.write(|mut config| {
+						let count = config.p2p_relays.len();
+						config.p2p_relays.retain(|relay| relay.id != id);
+						found = config.p2p_relays.len() != count;
+					})
+					.await
+					.map_err(config_write_error)?;
+
+				if !found {
+					return Err(RelayError::NotFound(id).into());
+				}
+
+				ctx.p2p.refresh_relays();
+
+				Ok(())
+			})
+		})
 }
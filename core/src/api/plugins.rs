@@ -0,0 +1,47 @@
+use crate::prisma::{extracted_metadata, object};
+
+use rspc::alpha::AlphaRouter;
+use serde::Serialize;
+use specta::Type;
+
+use super::{utils::library, Ctx, R};
+
+/// A value extracted from an object by a plugin of this node
+#[derive(Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractedMetadataValue {
+	pub plugin: String,
+	pub key: String,
+	pub value: String,
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.plugins.list()) })
+		})
+		.procedure("reload", {
+			// Picks up the plugins installed or removed since the node started
+			R.mutation(|ctx, _: ()| async move { Ok(ctx.plugins.reload().await) })
+		})
+		.procedure("objectMetadata", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					Ok(library
+						.db
+						.extracted_metadata()
+						.find_many(vec![extracted_metadata::object_id::equals(Some(object_id))])
+						.exec()
+						.await?
+						.into_iter()
+						.filter_map(|metadata| {
+							Some(ExtractedMetadataValue {
+								plugin: metadata.plugin?,
+								key: metadata.key?,
+								value: metadata.value?,
+							})
+						})
+						.collect::<Vec<_>>())
+				})
+		})
+}
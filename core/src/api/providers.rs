@@ -0,0 +1,178 @@
+use crate::{
+	location::{
+		find_location,
+		provider::{check_relative, ProviderEntry, ProviderError},
+		LocationError,
+	},
+	prisma::{file_path, location},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+use tracing::warn;
+
+use super::{utils::editor, Ctx, R};
+
+/// A root of a provider, like `/home/user/Photos` for the `local` one
+#[derive(Type, Deserialize)]
+pub struct ProviderRoot {
+	pub scheme: String,
+	pub root: String,
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.query(|ctx, _: ()| async move { Ok(ctx.location_providers.list()) })
+		})
+		.procedure("browse", {
+			#[derive(Type, Deserialize)]
+			pub struct ProviderBrowseArgs {
+				#[serde(flatten)]
+				pub root: ProviderRoot,
+				#[serde(default)]
+				pub path: PathBuf,
+			}
+
+			#[derive(Type, Serialize)]
+			pub struct ProviderBrowseResult {
+				pub entry: ProviderEntry,
+				/// Children of the entry, for directories
+				pub children: Vec<ProviderEntry>,
+			}
+
+			R.query(|ctx, args: ProviderBrowseArgs| async move {
+				let provider = ctx.location_providers.get(&args.root.scheme)?;
+				let root = args.root.root.as_str();
+
+				let entry = provider.stat(root, &args.path).await?;
+				let children = if entry.is_dir {
+					provider.list(root, &args.path).await?
+				} else {
+					vec![]
+				};
+
+				Ok(ProviderBrowseResult { entry, children })
+			})
+		})
+		.procedure("watch", {
+			R.subscription(|ctx, args: ProviderRoot| async move {
+				let events = match ctx.location_providers.get(&args.scheme) {
+					Ok(provider) => provider.watch(&args.root).await,
+					Err(e) => Err(e),
+				};
+
+				async_stream::stream! {
+					match events {
+						Ok(mut events) => {
+							while let Some(event) = events.next().await {
+								yield event;
+							}
+						}
+						Err(e) => warn!("Failed to watch '{}://{}': {e}", args.scheme, args.root),
+					}
+				}
+			})
+		})
+		.procedure("import", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct ProviderImportArgs {
+				pub from: ProviderRoot,
+				pub path: PathBuf,
+				pub location_id: location::id::Type,
+				/// Directory of the location the file is copied to, relative to its root
+				pub directory: PathBuf,
+			}
+
+			// Copies a file of a provider into a location, where the watcher picks it up
+			R.with2(editor())
+				.mutation(|(ctx, library), args: ProviderImportArgs| async move {
+					check_relative(&args.directory)?;
+					let file_name = args.path.file_name().ok_or_else(|| {
+						rspc::Error::new(ErrorCode::BadRequest, "path has no file name".into())
+					})?;
+
+					let location = find_location(&library, args.location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_id))?;
+					let location_path = maybe_missing(location.path, "location.path")
+						.map_err(LocationError::from)?;
+
+					let target = Path::new(&location_path)
+						.join(&args.directory)
+						.join(file_name);
+					if fs::try_exists(&target).await.unwrap_or(true) {
+						return Err(rspc::Error::new(
+							ErrorCode::Conflict,
+							format!("'{}' already exists", target.display()),
+						));
+					}
+
+					let mut contents = ctx
+						.location_providers
+						.get(&args.from.scheme)?
+						.read(&args.from.root, &args.path)
+						.await?;
+
+					let mut file = fs::File::create(&target)
+						.await
+						.map_err(|e| ProviderError::from(FileIOError::from((&target, e))))?;
+					tokio::io::copy(&mut contents, &mut file)
+						.await
+						.map_err(|e| ProviderError::from(FileIOError::from((&target, e))))?;
+
+					Ok(())
+				})
+		})
+		.procedure("export", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct ProviderExportArgs {
+				pub file_path_id: file_path::id::Type,
+				pub to: ProviderRoot,
+				/// Directory of the root the file is copied to
+				pub directory: PathBuf,
+			}
+
+			// Copies a file of a location of this node to a provider
+			R.with2(editor())
+				.mutation(|(ctx, library), args: ProviderExportArgs| async move {
+					let provider = ctx.location_providers.get(&args.to.scheme)?;
+
+					let path = library
+						.get_file_paths(vec![args.file_path_id])
+						.await?
+						.remove(&args.file_path_id)
+						.flatten()
+						.ok_or_else(|| {
+							rspc::Error::new(
+								ErrorCode::NotFound,
+								"file not found on this node".into(),
+							)
+						})?;
+					let file_name = path.file_name().unwrap_or_default().to_owned();
+
+					let file = fs::File::open(&path)
+						.await
+						.map_err(|e| ProviderError::from(FileIOError::from((&path, e))))?;
+
+					provider
+						.write(
+							&args.to.root,
+							&args.directory.join(file_name),
+							Box::pin(file),
+						)
+						.await
+						.map(|_| ())
+						.map_err(Into::into)
+				})
+		})
+}
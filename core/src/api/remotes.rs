@@ -0,0 +1,219 @@
+use crate::{
+	invalidate_query,
+	job::Job,
+	prisma::remote,
+	remote::{
+		upload_job::RemoteUploaderJobInit, Remote, RemoteCredentials, RemoteError, RemoteOptions,
+	},
+	util::db::maybe_missing,
+};
+
+use chrono::{DateTime, Utc};
+use rspc::alpha::AlphaRouter;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+/// A remote as shown to the frontend, without its credentials
+#[derive(Serialize, Type)]
+pub struct RemoteInfo {
+	pub id: remote::id::Type,
+	pub name: Option<String>,
+	pub options: Option<RemoteOptions>,
+	pub date_created: Option<DateTime<Utc>>,
+}
+
+remote::select!(remote_info { id name options date_created });
+
+impl From<remote_info::Data> for RemoteInfo {
+	fn from(remote: remote_info::Data) -> Self {
+		Self {
+			id: remote.id,
+			name: remote.name,
+			options: remote
+				.options
+				.and_then(|options| serde_json::from_slice(&options).ok()),
+			date_created: remote.date_created.map(Into::into),
+		}
+	}
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					.remote()
+					.find_many(vec![])
+					.select(remote_info::select())
+					.exec()
+					.await?
+					.into_iter()
+					.map(RemoteInfo::from)
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct RemoteCreateArgs {
+				pub name: String,
+				pub options: RemoteOptions,
+				pub credentials: RemoteCredentials,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: RemoteCreateArgs| async move {
+					// Making sure the remote can be reached before saving it
+					Remote::new(&args.options, &args.credentials)?
+						.check()
+						.await?;
+
+					let remote = library
+						.db
+						.remote()
+						.create(
+							Uuid::new_v4().as_bytes().to_vec(),
+							vec![
+								remote::name::set(Some(args.name)),
+								remote::options::set(Some(
+									serde_json::to_vec(&args.options).map_err(RemoteError::from)?,
+								)),
+								remote::credentials::set(Some(
+									serde_json::to_vec(&args.credentials)
+										.map_err(RemoteError::from)?,
+								)),
+								remote::date_created::set(Some(Utc::now().into())),
+							],
+						)
+						.select(remote_info::select())
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "remotes.list");
+
+					Ok(RemoteInfo::from(remote))
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct RemoteUpdateArgs {
+				pub id: remote::id::Type,
+				pub name: Option<String>,
+				pub options: Option<RemoteOptions>,
+				/// The credentials are kept as they are if not set
+				pub credentials: Option<RemoteCredentials>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: RemoteUpdateArgs| async move {
+					let remote = library
+						.db
+						.remote()
+						.find_unique(remote::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(RemoteError::IdNotFound(args.id))?;
+
+					let options = match args.options {
+						Some(options) => options,
+						None => serde_json::from_slice(maybe_missing(
+							&remote.options,
+							"remote.options",
+						)?)
+						.map_err(RemoteError::from)?,
+					};
+					let credentials = match args.credentials {
+						Some(credentials) => credentials,
+						None => remote
+							.credentials
+							.as_deref()
+							.map(serde_json::from_slice)
+							.transpose()
+							.map_err(RemoteError::from)?
+							.unwrap_or_default(),
+					};
+
+					// Making sure the remote can still be reached before saving the changes
+					Remote::new(&options, &credentials)?.check().await?;
+
+					let mut params = vec![
+						remote::options::set(Some(
+							serde_json::to_vec(&options).map_err(RemoteError::from)?,
+						)),
+						remote::credentials::set(Some(
+							serde_json::to_vec(&credentials).map_err(RemoteError::from)?,
+						)),
+						remote::date_modified::set(Some(Utc::now().into())),
+					];
+
+					if let Some(name) = args.name {
+						params.push(remote::name::set(Some(name)));
+					}
+
+					library
+						.db
+						.remote()
+						.update(remote::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "remotes.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), id: remote::id::Type| async move {
+					library
+						.db
+						.remote()
+						.delete(remote::id::equals(id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "remotes.list");
+
+					Ok(())
+				})
+		})
+		.procedure("check", {
+			R.with2(library())
+				.query(|(_, library), id: remote::id::Type| async move {
+					Remote::from_db(&library.db, id)
+						.await?
+						.check()
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("listDir", {
+			#[derive(Type, Deserialize)]
+			pub struct RemoteListDirArgs {
+				pub id: remote::id::Type,
+				/// Relative to the root of the remote
+				pub path: String,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: RemoteListDirArgs| async move {
+					Remote::from_db(&library.db, args.id)
+						.await?
+						.list(&args.path)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("upload", {
+			R.with2(editor())
+				.mutation(|(_, library), args: RemoteUploaderJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				})
+		})
+}
@@ -0,0 +1,170 @@
+use crate::{
+	invalidate_query,
+	job::scheduler::{next_run, ScheduledJobKind, SchedulerError},
+	prisma::scheduled_job,
+};
+
+use chrono::Utc;
+use rspc::alpha::AlphaRouter;
+use serde::Deserialize;
+use specta::Type;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.db.scheduled_job().find_many(vec![]).exec().await?)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct ScheduledJobCreateArgs {
+				pub name: String,
+				pub kind: ScheduledJobKind,
+				pub cron: String,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: ScheduledJobCreateArgs| async move {
+					let now = Utc::now();
+					let next = next_run(&args.cron, now)?;
+
+					let scheduled_job = library
+						.db
+						.scheduled_job()
+						.create(vec![
+							scheduled_job::name::set(Some(args.name)),
+							scheduled_job::kind::set(Some(
+								serde_json::to_vec(&args.kind).map_err(SchedulerError::from)?,
+							)),
+							scheduled_job::cron::set(Some(args.cron)),
+							scheduled_job::enabled::set(Some(true)),
+							scheduled_job::date_next_run::set(next.map(Into::into)),
+							scheduled_job::date_created::set(Some(now.into())),
+						])
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "scheduledJobs.list");
+
+					Ok(scheduled_job)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct ScheduledJobUpdateArgs {
+				pub id: scheduled_job::id::Type,
+				pub name: Option<String>,
+				pub kind: Option<ScheduledJobKind>,
+				pub cron: Option<String>,
+				pub enabled: Option<bool>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: ScheduledJobUpdateArgs| async move {
+					let now = Utc::now();
+
+					let scheduled_job = library
+						.db
+						.scheduled_job()
+						.find_unique(scheduled_job::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(SchedulerError::IdNotFound(args.id))?;
+
+					let mut params = vec![scheduled_job::date_modified::set(Some(now.into()))];
+
+					if let Some(name) = args.name {
+						params.push(scheduled_job::name::set(Some(name)));
+					}
+
+					if let Some(kind) = args.kind {
+						params.push(scheduled_job::kind::set(Some(
+							serde_json::to_vec(&kind).map_err(SchedulerError::from)?,
+						)));
+					}
+
+					// Recomputing the next run, as either the expression changed or the job was
+					// re-enabled and its previous next run is stale
+					if args.cron.is_some() || args.enabled == Some(true) {
+						if let Some(cron) = args.cron.as_ref().or(scheduled_job.cron.as_ref()) {
+							params.push(scheduled_job::date_next_run::set(
+								next_run(cron, now)?.map(Into::into),
+							));
+						}
+					}
+
+					if let Some(cron) = args.cron {
+						params.push(scheduled_job::cron::set(Some(cron)));
+					}
+
+					if let Some(enabled) = args.enabled {
+						params.push(scheduled_job::enabled::set(Some(enabled)));
+					}
+
+					library
+						.db
+						.scheduled_job()
+						.update(scheduled_job::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "scheduledJobs.list");
+
+					Ok(())
+				})
+		})
+		.procedure("runNow", {
+			R.with2(editor())
+				.mutation(|(_, library), id: scheduled_job::id::Type| async move {
+					let scheduled_job = library
+						.db
+						.scheduled_job()
+						.find_unique(scheduled_job::id::equals(id))
+						.exec()
+						.await?
+						.ok_or(SchedulerError::IdNotFound(id))?;
+
+					let kind = serde_json::from_slice::<ScheduledJobKind>(
+						scheduled_job.kind.as_deref().unwrap_or_default(),
+					)
+					.map_err(SchedulerError::from)?;
+
+					kind.spawn(&library).await?;
+
+					library
+						.db
+						.scheduled_job()
+						.update(
+							scheduled_job::id::equals(id),
+							vec![scheduled_job::date_last_run::set(Some(Utc::now().into()))],
+						)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "scheduledJobs.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), id: scheduled_job::id::Type| async move {
+					library
+						.db
+						.scheduled_job()
+						.delete(scheduled_job::id::equals(id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "scheduledJobs.list");
+
+					Ok(())
+				})
+		})
+}
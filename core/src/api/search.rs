@@ -3,23 +3,31 @@ use crate::{
 		locations::{file_path_with_object, object_with_file_paths, ExplorerItem},
 		utils::library,
 	},
-	library::{Category, Library},
+	library::{Category, Library, LibraryName},
 	location::{
 		file_path_helper::{check_file_path_exists, IsolatedFilePathData},
 		find_location, LocationError,
 	},
-	object::preview::get_thumb_key,
-	prisma::{self, file_path, location, object, tag, tag_on_object},
+	object::{
+		custom_field::CustomFieldFilter,
+		export::{export_file_paths, ListingExportFormat},
+		preview::get_thumb_key,
+		sensitive::not_sensitive,
+	},
+	prisma::{self, album, file_path, location, object, object_in_album, tag, tag_on_object},
 	util::db::chain_optional_iter,
 };
 
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, path::PathBuf};
 
 use chrono::{DateTime, FixedOffset, Utc};
+use futures::future::join_all;
 use prisma_client_rust::{operator, or};
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
 
 use super::{Ctx, R};
 
@@ -29,7 +37,7 @@ struct SearchData<T> {
 	items: Vec<T>,
 }
 
-#[derive(Deserialize, Default, Type, Debug)]
+#[derive(Serialize, Deserialize, Default, Type, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 struct OptionalRange<T> {
 	from: Option<T>,
@@ -89,9 +97,9 @@ impl FilePathSearchOrdering {
 	}
 }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
 #[serde(untagged)]
-enum MaybeNot<T> {
+pub(crate) enum MaybeNot<T> {
 	None(T),
 	Not { not: T },
 }
@@ -122,6 +130,70 @@ struct FilePathFilterArgs {
 	object: Option<ObjectFilterArgs>,
 }
 
+impl FilePathFilterArgs {
+	async fn into_params(
+		self,
+		library: &Library,
+	) -> Result<Vec<file_path::WhereParam>, rspc::Error> {
+		let location = if let Some(location_id) = self.location_id {
+			Some(
+				find_location(library, location_id)
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?,
+			)
+		} else {
+			None
+		};
+
+		let directory_materialized_path_str = match (self.path, location) {
+			(Some(path), Some(location)) if !path.is_empty() && path != "/" => {
+				let parent_iso_file_path =
+					IsolatedFilePathData::from_relative_str(location.id, &path);
+				if !check_file_path_exists::<LocationError>(&parent_iso_file_path, &library.db)
+					.await?
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						"Directory not found".into(),
+					));
+				}
+
+				parent_iso_file_path.materialized_path_for_children()
+			}
+			(Some(_empty), _) => Some("/".into()),
+			_ => None,
+		};
+
+		use file_path::*;
+
+		Ok(chain_optional_iter(
+			self.search
+				.unwrap_or_default()
+				.split(' ')
+				.map(str::to_string)
+				.map(name::contains)
+				// File paths marked as deleted are only listed by `files.deleted`, and quarantined
+				// ones by `files.quarantined`
+				.chain([date_deleted::equals(None), date_quarantined::equals(None)]),
+			[
+				self.location_id.map(Some).map(location_id::equals),
+				self.extension.map(Some).map(extension::equals),
+				self.created_at.from.map(|v| date_created::gte(v.into())),
+				self.created_at.to.map(|v| date_created::lte(v.into())),
+				directory_materialized_path_str
+					.map(Some)
+					.map(materialized_path::equals),
+				self.object.and_then(|obj| {
+					let params = obj.into_params();
+
+					(!params.is_empty()).then(|| object::is(params))
+				}),
+			],
+		))
+	}
+}
+
 #[derive(Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 struct FilePathSearchArgs {
@@ -139,12 +211,14 @@ struct FilePathSearchArgs {
 #[serde(rename_all = "camelCase")]
 enum ObjectSearchOrdering {
 	DateAccessed(SortOrder),
+	Rating(SortOrder),
 }
 
 impl ObjectSearchOrdering {
 	fn get_sort_order(&self) -> prisma::SortOrder {
 		(*match self {
 			Self::DateAccessed(v) => v,
+			Self::Rating(v) => v,
 		})
 		.into()
 	}
@@ -154,13 +228,14 @@ impl ObjectSearchOrdering {
 		use object::*;
 		match self {
 			Self::DateAccessed(_) => date_accessed::order(dir),
+			Self::Rating(_) => rating::order(dir),
 		}
 	}
 }
 
-#[derive(Deserialize, Type, Debug, Default, Clone, Copy)]
+#[derive(Serialize, Deserialize, Type, Debug, Default, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
-enum ObjectHiddenFilter {
+pub(crate) enum ObjectHiddenFilter {
 	#[default]
 	Exclude,
 	Include,
@@ -178,35 +253,66 @@ impl ObjectHiddenFilter {
 	}
 }
 
-#[derive(Deserialize, Type, Debug, Default)]
+/// Whether the objects tagged as sensitive are left out, like from slideshows and shared views
+#[derive(Serialize, Deserialize, Type, Debug, Default, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
-struct ObjectFilterArgs {
+pub(crate) enum ObjectSensitiveFilter {
+	#[default]
+	Include,
+	Exclude,
+}
+
+impl ObjectSensitiveFilter {
+	fn to_param(self) -> Option<object::WhereParam> {
+		match self {
+			ObjectSensitiveFilter::Exclude => Some(not_sensitive()),
+			ObjectSensitiveFilter::Include => None,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ObjectFilterArgs {
 	#[specta(optional)]
 	favorite: Option<bool>,
 	#[serde(default)]
 	hidden: ObjectHiddenFilter,
+	#[serde(default)]
+	sensitive: ObjectSensitiveFilter,
 	#[specta(optional)]
 	date_accessed: Option<MaybeNot<Option<chrono::DateTime<FixedOffset>>>>,
 	#[serde(default)]
 	kind: BTreeSet<i32>,
 	#[serde(default)]
+	rating: OptionalRange<i32>,
+	#[serde(default)]
 	tags: Vec<i32>,
 	#[specta(optional)]
 	category: Option<Category>,
+	#[specta(optional)]
+	album: Option<album::id::Type>,
+	#[serde(default)]
+	custom_fields: Vec<CustomFieldFilter>,
 }
 
 impl ObjectFilterArgs {
-	fn into_params(self) -> Vec<object::WhereParam> {
+	pub(crate) fn into_params(self) -> Vec<object::WhereParam> {
 		use object::*;
 
 		chain_optional_iter(
-			[],
+			self.custom_fields
+				.into_iter()
+				.map(CustomFieldFilter::to_where_param),
 			[
 				self.hidden.to_param(),
+				self.sensitive.to_param(),
 				self.favorite.map(Some).map(favorite::equals),
 				self.date_accessed
 					.map(|date| date.into_prisma(date_accessed::equals)),
 				(!self.kind.is_empty()).then(|| kind::in_vec(self.kind.into_iter().collect())),
+				self.rating.from.map(rating::gte),
+				self.rating.to.map(rating::lte),
 				(!self.tags.is_empty()).then(|| {
 					let tags = self.tags.into_iter().map(tag::id::equals).collect();
 					let tags_on_object = tag_on_object::tag::is(vec![operator::or(tags)]);
@@ -214,6 +320,9 @@ impl ObjectFilterArgs {
 					tags::some(vec![tags_on_object])
 				}),
 				self.category.map(Category::to_where_param),
+				self.album.map(|album_id| {
+					albums::some(vec![object_in_album::album_id::equals(album_id)])
+				}),
 			],
 		)
 	}
@@ -232,6 +341,41 @@ struct ObjectSearchArgs {
 	filter: ObjectFilterArgs,
 }
 
+async fn path_items(
+	library: &Library,
+	file_paths: Vec<file_path_with_object::Data>,
+) -> Result<Vec<ExplorerItem>, rspc::Error> {
+	let mut items = Vec::with_capacity(file_paths.len());
+
+	for file_path in file_paths {
+		let thumbnail_exists_locally = if let Some(cas_id) = &file_path.cas_id {
+			library
+				.thumbnail_exists(cas_id)
+				.await
+				.map_err(LocationError::from)?
+		} else {
+			false
+		};
+
+		items.push(ExplorerItem::Path {
+			has_local_thumbnail: thumbnail_exists_locally,
+			thumbnail_key: file_path.cas_id.as_ref().map(|i| get_thumb_key(i)),
+			item: file_path,
+		})
+	}
+
+	Ok(items)
+}
+
+/// A file path found by a search across all the libraries, with the library it's from
+#[derive(Serialize, Type, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LibrarySearchItem {
+	library_id: Uuid,
+	library_name: LibraryName,
+	item: ExplorerItem,
+}
+
 pub fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("paths", {
@@ -245,60 +389,7 @@ pub fn mount() -> AlphaRouter<Ctx> {
 				 }| async move {
 					let Library { db, .. } = &library;
 
-					let location = if let Some(location_id) = filter.location_id {
-						Some(
-							find_location(&library, location_id)
-								.exec()
-								.await?
-								.ok_or(LocationError::IdNotFound(location_id))?,
-						)
-					} else {
-						None
-					};
-
-					let directory_materialized_path_str = match (filter.path, location) {
-						(Some(path), Some(location)) if !path.is_empty() && path != "/" => {
-							let parent_iso_file_path =
-								IsolatedFilePathData::from_relative_str(location.id, &path);
-							if !check_file_path_exists::<LocationError>(&parent_iso_file_path, db)
-								.await?
-							{
-								return Err(rspc::Error::new(
-									ErrorCode::NotFound,
-									"Directory not found".into(),
-								));
-							}
-
-							parent_iso_file_path.materialized_path_for_children()
-						}
-						(Some(_empty), _) => Some("/".into()),
-						_ => None,
-					};
-
-					use file_path::*;
-
-					let params = chain_optional_iter(
-						filter
-							.search
-							.unwrap_or_default()
-							.split(' ')
-							.map(str::to_string)
-							.map(name::contains),
-						[
-							filter.location_id.map(Some).map(location_id::equals),
-							filter.extension.map(Some).map(extension::equals),
-							filter.created_at.from.map(|v| date_created::gte(v.into())),
-							filter.created_at.to.map(|v| date_created::lte(v.into())),
-							directory_materialized_path_str
-								.map(Some)
-								.map(materialized_path::equals),
-							filter.object.and_then(|obj| {
-								let params = obj.into_params();
-
-								(!params.is_empty()).then(|| object::is(params))
-							}),
-						],
-					);
+					let params = filter.into_params(&library).await?;
 
 					let take = take.unwrap_or(100);
 
@@ -326,28 +417,116 @@ pub fn mount() -> AlphaRouter<Ctx> {
 						(paths, cursor)
 					};
 
-					let mut items = Vec::with_capacity(file_paths.len());
-
-					for file_path in file_paths {
-						let thumbnail_exists_locally = if let Some(cas_id) = &file_path.cas_id {
-							library
-								.thumbnail_exists(cas_id)
-								.await
-								.map_err(LocationError::from)?
-						} else {
-							false
-						};
+					Ok(SearchData {
+						items: path_items(&library, file_paths).await?,
+						cursor,
+					})
+				},
+			)
+		})
+		.procedure("allLibraries", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct AllLibrariesSearchArgs {
+				search: String,
+				#[specta(optional)]
+				extension: Option<String>,
+				/// Of all the libraries together
+				#[specta(optional)]
+				take: Option<i32>,
+			}
+
+			R.query(|ctx, args: AllLibrariesSearchArgs| async move {
+				let take = args.take.unwrap_or(100);
+
+				let results = join_all(
+					ctx.library_manager
+						.get_all_libraries()
+						.await
+						.into_iter()
+						.map(|library| {
+							let filter = FilePathFilterArgs {
+								search: Some(args.search.clone()),
+								extension: args.extension.clone(),
+								..Default::default()
+							};
+
+							async move {
+								let items = async {
+									let params = filter.into_params(&library).await?;
+
+									let file_paths = library
+										.db
+										.file_path()
+										.find_many(params)
+										.order_by(file_path::name::order(prisma::SortOrder::Asc))
+										.take(take as i64)
+										.include(file_path_with_object::include())
+										.exec()
+										.await?;
+
+									path_items(&library, file_paths).await
+								}
+								.await;
+
+								(library, items)
+							}
+						}),
+				)
+				.await;
+
+				let mut items = Vec::new();
+				for (library, result) in results {
+					match result {
+						Ok(library_items) => {
+							items.extend(library_items.into_iter().map(|item| LibrarySearchItem {
+								library_id: library.id,
+								library_name: library.config.name.clone(),
+								item,
+							}))
+						}
+						// The other libraries can still be searched
+						Err(e) => warn!("Failed to search library <id='{}'>: {e:#?}", library.id),
+					}
+				}
 
-						items.push(ExplorerItem::Path {
-							has_local_thumbnail: thumbnail_exists_locally,
-							thumbnail_key: file_path.cas_id.as_ref().map(|i| get_thumb_key(i)),
-							item: file_path,
-						})
+				// Each library is ordered by name, so they're merged by name too
+				items.sort_by_cached_key(|LibrarySearchItem { item, .. }| match item {
+					ExplorerItem::Path { item, .. } => {
+						item.name.clone().unwrap_or_default().to_lowercase()
 					}
+					_ => String::new(),
+				});
+				items.truncate(take.max(0) as usize);
 
-					Ok(SearchData { items, cursor })
-				},
-			)
+				Ok(items)
+			})
+		})
+		.procedure("exportPaths", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct ExportPathsArgs {
+				#[specta(optional)]
+				order: Option<FilePathSearchOrdering>,
+				#[serde(default)]
+				filter: FilePathFilterArgs,
+				format: ListingExportFormat,
+				output_path: PathBuf,
+			}
+
+			R.with2(library())
+				.mutation(|(_, library), args: ExportPathsArgs| async move {
+					let params = args.filter.into_params(&library).await?;
+
+					Ok(export_file_paths(
+						&library,
+						params,
+						args.order.map(FilePathSearchOrdering::into_param),
+						args.format,
+						args.output_path,
+					)
+					.await?)
+				})
 		})
 		.procedure("objects", {
 			R.with2(library()).query(
@@ -362,10 +541,14 @@ pub fn mount() -> AlphaRouter<Ctx> {
 
 					let take = take.unwrap_or(100);
 
-					let mut query = db
-						.object()
-						.find_many(filter.into_params())
-						.take(take as i64 + 1);
+					let mut params = filter.into_params();
+					// Leaving out the objects of files that are all marked as deleted or quarantined
+					params.push(object::file_paths::some(vec![
+						file_path::date_deleted::equals(None),
+						file_path::date_quarantined::equals(None),
+					]));
+
+					let mut query = db.object().find_many(params).take(take as i64 + 1);
 
 					if let Some(order) = order {
 						query = query.order_by(order.into_param());
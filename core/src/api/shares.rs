@@ -0,0 +1,193 @@
+use crate::{
+	invalidate_query,
+	share::{
+		create_share_link, list_share_links, revoke_share_link, set_share_link_password,
+		update_share_link_limits, ShareRelayConfig, ShareTarget,
+	},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::error;
+use uuid::Uuid;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library())
+				.query(|(ctx, library), _: ()| async move {
+					Ok(list_share_links(&library, &ctx.config.get().await).await?)
+				})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct CreateShareLinkArgs {
+				pub target: ShareTarget,
+				#[specta(optional)]
+				pub expires_at: Option<DateTime<Utc>>,
+				#[specta(optional)]
+				pub max_downloads: Option<i32>,
+				#[specta(optional)]
+				pub password: Option<String>,
+				#[serde(default)]
+				pub hide_sensitive: bool,
+			}
+
+			R.with2(editor())
+				.mutation(|(ctx, library), args: CreateShareLinkArgs| async move {
+					let link = create_share_link(
+						&library,
+						&ctx.config.get().await,
+						args.target,
+						args.expires_at,
+						args.max_downloads,
+						args.password,
+						args.hide_sensitive,
+					)
+					.await?;
+
+					invalidate_query!(library, "shares.list");
+
+					Ok(link)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct UpdateShareLinkArgs {
+				pub id: Uuid,
+				/// Never expires if not set
+				#[specta(optional)]
+				pub expires_at: Option<DateTime<Utc>>,
+				/// No download limit if not set
+				#[specta(optional)]
+				pub max_downloads: Option<i32>,
+			}
+
+			R.with2(editor())
+				.mutation(|(ctx, library), args: UpdateShareLinkArgs| async move {
+					let link = update_share_link_limits(
+						&library,
+						&ctx.config.get().await,
+						args.id,
+						args.expires_at,
+						args.max_downloads,
+					)
+					.await?;
+
+					invalidate_query!(library, "shares.list");
+
+					Ok(link)
+				})
+		})
+		.procedure("setPassword", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SetShareLinkPasswordArgs {
+				pub id: Uuid,
+				/// Removes the password if not set
+				#[specta(optional)]
+				pub password: Option<String>,
+			}
+
+			R.with2(editor()).mutation(
+				|(ctx, library), args: SetShareLinkPasswordArgs| async move {
+					let link = set_share_link_password(
+						&library,
+						&ctx.config.get().await,
+						args.id,
+						args.password,
+					)
+					.await?;
+
+					invalidate_query!(library, "shares.list");
+
+					Ok(link)
+				},
+			)
+		})
+		.procedure("revoke", {
+			R.with2(editor())
+				.mutation(|(_, library), id: Uuid| async move {
+					revoke_share_link(&library, id).await?;
+
+					invalidate_query!(library, "shares.list");
+
+					Ok(())
+				})
+		})
+		.procedure("relay", {
+			#[derive(Serialize, Type)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SanitisedShareRelay {
+				pub url: String,
+				pub has_token: bool,
+			}
+
+			R.query(|ctx, _: ()| async move {
+				Ok(ctx
+					.config
+					.get()
+					.await
+					.share_relay
+					.map(|relay| SanitisedShareRelay {
+						url: relay.url,
+						has_token: relay.token.is_some(),
+					}))
+			})
+		})
+		.procedure("setRelay", {
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct SetShareRelayArgs {
+				pub url: String,
+				/// The current token is kept if not set
+				#[specta(optional)]
+				pub token: Option<String>,
+			}
+
+			// Share links can't be opened anymore without a relay
+			R.mutation(|ctx, args: Option<SetShareRelayArgs>| async move {
+				if let Some(args) = &args {
+					if !Url::parse(&args.url)
+						.map_or(false, |url| matches!(url.scheme(), "http" | "https"))
+					{
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"the relay url must be an http or https one".into(),
+						));
+					}
+				}
+
+				ctx.config
+					.write(|mut config| {
+						let previous_token =
+							config.share_relay.take().and_then(|relay| relay.token);
+
+						config.share_relay = args.map(|args| ShareRelayConfig {
+							url: args.url,
+							token: args.token.or(previous_token),
+						});
+					})
+					.await
+					.map_err(|err| {
+						error!("Failed to write config: {}", err);
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				Ok(())
+			})
+		})
+}
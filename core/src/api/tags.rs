@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use chrono::Utc;
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::Deserialize;
@@ -7,13 +9,21 @@ use serde_json::json;
 
 use crate::{
 	invalidate_query,
-	library::Library,
-	object::tag::TagCreateArgs,
+	job::Job,
+	library::{AuditAction, Library},
+	object::{
+		tag::{bulk_assign_job::BulkTagAssignJobInit, TagCreateArgs},
+		undo::UndoableOperation,
+		xmp::spawn_sidecar_write_back,
+	},
 	prisma::{tag, tag_on_object},
 	sync,
 };
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -47,12 +57,19 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("create", {
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: TagCreateArgs| async move {
 					let created_tag = args.exec(&library).await?;
 
 					invalidate_query!(library, "tags.list");
 
+					library
+						.audit(AuditAction::TagCreated {
+							tag_id: created_tag.id,
+							name: created_tag.name.clone().unwrap_or_default(),
+						})
+						.await;
+
 					Ok(created_tag)
 				})
 		})
@@ -64,15 +81,36 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub unassign: bool,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: TagAssignArgs| async move {
 					let Library { db, .. } = &library;
 
+					// Only the objects whose tags change are undone later
+					let already_tagged = db
+						.tag_on_object()
+						.find_many(vec![
+							tag_on_object::tag_id::equals(args.tag_id),
+							tag_on_object::object_id::in_vec(args.object_ids.clone()),
+						])
+						.select(tag_on_object::select!({ object_id }))
+						.exec()
+						.await?
+						.into_iter()
+						.map(|tag_on_object| tag_on_object.object_id)
+						.collect::<HashSet<_>>();
+
+					let changed_object_ids = args
+						.object_ids
+						.iter()
+						.copied()
+						.filter(|object_id| already_tagged.contains(object_id) == args.unassign)
+						.collect::<Vec<_>>();
+
 					if args.unassign {
 						db.tag_on_object()
 							.delete_many(vec![
 								tag_on_object::tag_id::equals(args.tag_id),
-								tag_on_object::object_id::in_vec(args.object_ids),
+								tag_on_object::object_id::in_vec(args.object_ids.clone()),
 							])
 							.exec()
 							.await?;
@@ -94,9 +132,37 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 
 					invalidate_query!(library, "tags.getForObject");
 
+					library
+						.audit(AuditAction::TagAssigned {
+							tag_id: args.tag_id,
+							object_ids: args.object_ids.clone(),
+							unassign: args.unassign,
+						})
+						.await;
+
+					if !changed_object_ids.is_empty() {
+						library
+							.undo_history
+							.record(UndoableOperation::Tag {
+								tag_id: args.tag_id,
+								object_ids: changed_object_ids,
+								assigned: !args.unassign,
+							})
+							.await;
+						invalidate_query!(library, "files.undoState");
+					}
+
+					spawn_sidecar_write_back(&library, args.object_ids);
+
 					Ok(())
 				})
 		})
+		.procedure("bulkAssign", {
+			R.with2(editor())
+				.mutation(|(_, library), args: BulkTagAssignJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				})
+		})
 		.procedure("update", {
 			#[derive(Type, Deserialize)]
 			pub struct TagUpdateArgs {
@@ -105,7 +171,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub color: Option<String>,
 			}
 
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), args: TagUpdateArgs| async move {
 					let Library { sync, db, .. } = &library;
 
@@ -149,7 +215,10 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.collect(),
 							db.tag().update(
 								tag::id::equals(args.id),
-								vec![tag::name::set(args.name), tag::color::set(args.color)],
+								vec![
+									tag::name::set(args.name.clone()),
+									tag::color::set(args.color),
+								],
 							),
 						),
 					)
@@ -157,12 +226,19 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 
 					invalidate_query!(library, "tags.list");
 
+					library
+						.audit(AuditAction::TagUpdated {
+							tag_id: args.id,
+							name: args.name,
+						})
+						.await;
+
 					Ok(())
 				})
 		})
 		.procedure(
 			"delete",
-			R.with2(library())
+			R.with2(editor())
 				.mutation(|(_, library), tag_id: i32| async move {
 					library
 						.db
@@ -173,6 +249,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 
 					invalidate_query!(library, "tags.list");
 
+					library.audit(AuditAction::TagDeleted { tag_id }).await;
+
 					Ok(())
 				}),
 		)
@@ -9,7 +9,10 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use specta::Type;
 use uuid::Uuid;
 
-use crate::{api::Ctx, library::Library};
+use crate::{
+	api::Ctx,
+	library::{Library, NodeRole},
+};
 
 /// Can wrap a query argument to require it to contain a `library_id` and provide helpers for working with libraries.
 #[derive(Clone, Serialize, Deserialize, Type)]
@@ -46,3 +49,40 @@ pub(crate) fn library() -> impl MwV3<Ctx, NewCtx = (Ctx, Library)> {
 		Ok(mw.next((ctx, library)))
 	})
 }
+
+/// Like [`library`], but only runs the procedure if this node has at least the role in the library
+fn library_with_role(role: NodeRole) -> impl MwV3<Ctx, NewCtx = (Ctx, Library)> {
+	MwArgMapperMiddleware::<LibraryArgsLike>::new().mount(
+		move |mw, ctx: Ctx, library_id| async move {
+			let library = ctx
+				.library_manager
+				.get_library(library_id)
+				.await
+				.ok_or_else(|| {
+					rspc::Error::new(
+						ErrorCode::BadRequest,
+						"You must specify a valid library to use this operation.".to_string(),
+					)
+				})?;
+
+			if !library.role().await?.grants(role) {
+				return Err(rspc::Error::new(
+					ErrorCode::Forbidden,
+					format!("This node needs to be {role:?} in the library for this operation."),
+				));
+			}
+
+			Ok(mw.next((ctx, library)))
+		},
+	)
+}
+
+/// For the procedures changing the files and metadata of the library
+pub(crate) fn editor() -> impl MwV3<Ctx, NewCtx = (Ctx, Library)> {
+	library_with_role(NodeRole::Editor)
+}
+
+/// For the procedures changing the settings of the library and who is part of it
+pub(crate) fn owner() -> impl MwV3<Ctx, NewCtx = (Ctx, Library)> {
+	library_with_role(NodeRole::Owner)
+}
@@ -1,11 +1,34 @@
 use rspc::alpha::AlphaRouter;
 
-use crate::volume::get_volumes;
+use crate::{
+	job::Job,
+	volume::{get_volumes, get_volumes_health, list_volume_benchmarks, VolumeBenchmarkJobInit},
+};
 
-use super::{Ctx, R};
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
-	R.router().procedure("list", {
-		R.query(|_, _: ()| async move { Ok(get_volumes()?) })
-	})
+	R.router()
+		.procedure("list", {
+			R.query(|_, _: ()| async move { Ok(get_volumes()?) })
+		})
+		.procedure("health", {
+			R.query(|_, _: ()| async move { Ok(get_volumes_health().await?) })
+		})
+		.procedure("benchmark", {
+			R.with2(editor())
+				.mutation(|(_, library), args: VolumeBenchmarkJobInit| async move {
+					Job::new(args).spawn(&library).await.map_err(Into::into)
+				})
+		})
+		.procedure("benchmarks", {
+			// Of every volume of this node if no mount point is given
+			R.with2(library())
+				.query(|(_, library), mount_point: Option<String>| async move {
+					Ok(list_volume_benchmarks(&library, mount_point).await?)
+				})
+		})
 }
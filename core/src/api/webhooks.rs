@@ -0,0 +1,119 @@
+use crate::{
+	invalidate_query,
+	library::{generate_webhook_secret, validate_webhook_url, WebhookError, WebhookEventKind},
+	prisma::webhook,
+};
+
+use chrono::Utc;
+use rspc::alpha::AlphaRouter;
+use serde::Deserialize;
+use specta::Type;
+
+use super::{
+	utils::{editor, library},
+	Ctx, R,
+};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.db.webhook().find_many(vec![]).exec().await?)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct WebhookCreateArgs {
+				pub url: String,
+				/// Events the webhook is notified of, all of them if empty
+				pub events: Vec<WebhookEventKind>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: WebhookCreateArgs| async move {
+					validate_webhook_url(&args.url)?;
+
+					let webhook = library
+						.db
+						.webhook()
+						.create(vec![
+							webhook::url::set(Some(args.url)),
+							webhook::secret::set(Some(generate_webhook_secret())),
+							webhook::events::set(Some(
+								serde_json::to_vec(&args.events).map_err(WebhookError::from)?,
+							)),
+							webhook::enabled::set(Some(true)),
+							webhook::date_created::set(Some(Utc::now().into())),
+						])
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "webhooks.list");
+
+					Ok(webhook)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct WebhookUpdateArgs {
+				pub id: webhook::id::Type,
+				pub url: Option<String>,
+				pub events: Option<Vec<WebhookEventKind>>,
+				pub enabled: Option<bool>,
+			}
+
+			R.with2(editor())
+				.mutation(|(_, library), args: WebhookUpdateArgs| async move {
+					library
+						.db
+						.webhook()
+						.find_unique(webhook::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(WebhookError::IdNotFound(args.id))?;
+
+					let mut params = vec![webhook::date_modified::set(Some(Utc::now().into()))];
+
+					if let Some(url) = args.url {
+						validate_webhook_url(&url)?;
+						params.push(webhook::url::set(Some(url)));
+					}
+
+					if let Some(events) = args.events {
+						params.push(webhook::events::set(Some(
+							serde_json::to_vec(&events).map_err(WebhookError::from)?,
+						)));
+					}
+
+					if let Some(enabled) = args.enabled {
+						params.push(webhook::enabled::set(Some(enabled)));
+					}
+
+					library
+						.db
+						.webhook()
+						.update(webhook::id::equals(args.id), params)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "webhooks.list");
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(editor())
+				.mutation(|(_, library), id: webhook::id::Type| async move {
+					library
+						.db
+						.webhook()
+						.delete(webhook::id::equals(id))
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "webhooks.list");
+
+					Ok(())
+				})
+		})
+}
@@ -1,6 +1,14 @@
 use crate::{
-	location::file_path_helper::{file_path_to_handle_custom_uri, IsolatedFilePathData},
-	prisma::{file_path, location},
+	library::Library,
+	location::file_path_helper::{
+		file_path_to_handle_custom_uri, file_path_to_stream, IsolatedFilePathData,
+	},
+	object::{
+		content_cache::{cached_content, materialize_in_background},
+		preview::{ThumbnailFormat, ThumbnailTier},
+	},
+	p2p::{DelegationError, RemoteFileRequest, MAX_REMOTE_FILE_CHUNK_LEN},
+	prisma::{file_path, location, object},
 	util::{db::*, error::FileIOError},
 	Node,
 };
@@ -24,9 +32,10 @@ use httpz::{
 use mini_moka::sync::Cache;
 use once_cell::sync::Lazy;
 use prisma_client_rust::QueryError;
+use sd_p2p::PeerId;
 use thiserror::Error;
 use tokio::{
-	fs::File,
+	fs::{self, File},
 	io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
 };
 use tracing::error;
@@ -42,6 +51,11 @@ static FILE_METADATA_CACHE: Lazy<Cache<MetadataCacheKey, NameAndExtension>> =
 // TODO: We should listen to events when deleting or moving a location and evict the cache accordingly.
 // TODO: Probs use this cache in rspc queries too!
 
+/// Stops serving a file as soon as it's quarantined, instead of once it leaves the cache
+pub(crate) fn evict_file_path(library_id: Uuid, file_path_id: file_path::id::Type) {
+	FILE_METADATA_CACHE.invalidate(&(library_id, file_path_id));
+}
+
 async fn handler(node: Arc<Node>, req: Request) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
 	let path = req
 		.uri()
@@ -54,6 +68,7 @@ async fn handler(node: Arc<Node>, req: Request) -> Result<Response<Vec<u8>>, Han
 	match path.first() {
 		Some(&"thumbnail") => handle_thumbnail(&node, &path, &req).await,
 		Some(&"file") => handle_file(&node, &path, &req).await,
+		Some(&"object") => handle_object(&node, &path, &req).await,
 		_ => Err(HandleCustomUriError::BadRequest("Invalid operation!")),
 	}
 }
@@ -106,12 +121,44 @@ async fn handle_thumbnail(
 		));
 	}
 
-	let mut thumbnail_path = node.config.data_directory().join("thumbnails");
-	// if we ever wish to support multiple levels of sharding, we need only supply more params here
-	for path_part in &path[1..] {
-		thumbnail_path = thumbnail_path.join(path_part);
+	// The library isn't known here, so the thumbnail is looked for in the data directory of the
+	// node, then in the ones of the libraries that have their own
+	let mut data_directories = vec![node.config.data_directory()];
+	for library in node.library_manager.get_all_libraries().await {
+		if library.config.data_directory.path.is_some() {
+			data_directories.push(library.data_directory());
+		}
 	}
-	let filename = thumbnail_path.with_extension("webp");
+
+	// Thumbnails keep the format they were generated in until they're re-encoded, which is the
+	// library's format, unknown here, so we serve whichever exists
+	let filename = data_directories
+		.into_iter()
+		.flat_map(|data_directory| {
+			let mut thumbnail_path = data_directory.join("thumbnails");
+			// if we ever wish to support multiple levels of sharding, we need only supply more params here
+			for path_part in &path[1..] {
+				thumbnail_path = thumbnail_path.join(path_part);
+			}
+
+			ThumbnailFormat::ALL
+				.into_iter()
+				.map(move |format| thumbnail_path.with_extension(format.extension()))
+		})
+		.find(|path| path.exists())
+		.ok_or(HandleCustomUriError::NotFound("file"))?;
+
+	// Clients can ask for the display size, in pixels, to get the best fitting tier
+	let filename = match req.uri().query().and_then(|query| {
+		query
+			.split('&')
+			.find_map(|pair| pair.strip_prefix("size=")?.parse::<u32>().ok())
+	}) {
+		Some(size) => tokio::task::spawn_blocking(move || pick_thumbnail_tier(filename, size))
+			.await
+			.map_err(|_| HandleCustomUriError::BadRequest("Failed to pick a thumbnail!"))?,
+		None => filename,
+	};
 
 	let file = File::open(&filename).await.map_err(|err| {
 		if err.kind() == io::ErrorKind::NotFound {
@@ -121,6 +168,15 @@ async fn handle_thumbnail(
 		}
 	})?;
 
+	// The modification time is used as the last time the thumbnail was used, to evict the least
+	// recently used ones when the cache is over its limit
+	if let Err(e) = filetime::set_file_mtime(&filename, filetime::FileTime::now()) {
+		error!(
+			"Failed to update the last use of thumbnail {}: {e:#?}",
+			filename.display()
+		);
+	}
+
 	let content_length = file
 		.metadata()
 		.await
@@ -128,7 +184,12 @@ async fn handle_thumbnail(
 		.len();
 
 	Ok(builder
-		.header("Content-Type", "image/webp")
+		.header(
+			"Content-Type",
+			ThumbnailFormat::from_path(&filename)
+				.unwrap_or_default()
+				.mime_type(),
+		)
 		.header("Content-Length", content_length)
 		.status(StatusCode::OK)
 		.body(if method == Method::HEAD {
@@ -140,6 +201,25 @@ async fn handle_thumbnail(
 		})?)
 }
 
+/// Smallest thumbnail tier which is at least `size` pixels big, or the biggest one available
+fn pick_thumbnail_tier(grid_path: PathBuf, size: u32) -> PathBuf {
+	let mut best = None;
+
+	for tier in ThumbnailTier::ALL {
+		let path = tier.path_from_grid(&grid_path);
+		let Ok((width, height)) = image::image_dimensions(&path) else {
+			continue;
+		};
+
+		best = Some(path);
+		if width.max(height) >= size {
+			break;
+		}
+	}
+
+	best.unwrap_or(grid_path)
+}
+
 async fn handle_file(
 	node: &Node,
 	path: &[&str],
@@ -187,7 +267,10 @@ async fn handle_file(
 			let file_path = library
 				.db
 				.file_path()
-				.find_unique(file_path::id::equals(file_path_id))
+				.find_first(vec![
+					file_path::id::equals(file_path_id),
+					file_path::date_quarantined::equals(None),
+				])
 				.select(file_path_to_handle_custom_uri::select())
 				.exec()
 				.await?
@@ -206,17 +289,332 @@ async fn handle_file(
 			lru_entry
 		};
 
-	let file = File::open(&file_path_full_path).await.map_err(|err| {
+	serve_file(req, builder, &file_path_full_path, &extension).await
+}
+
+/// Serves the contents of an object from one of its files, with range support so media can be
+/// seeked without downloading it whole. When none of its files is reachable from this node, the
+/// parts asked for are fetched from a paired node owning one of them.
+async fn handle_object(
+	node: &Node,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let method = req.method();
+	let mut builder = Response::builder();
+	if let Some(response) = cors(method, &mut builder) {
+		return Ok(response?);
+	}
+
+	let library_id = path
+		.get(1)
+		.and_then(|id| Uuid::from_str(id).ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing library_id!")
+		})?;
+
+	let object_id = path
+		.get(2)
+		.and_then(|id| id.parse::<object::id::Type>().ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing object_id!")
+		})?;
+
+	let library = node
+		.library_manager
+		.get_library(library_id)
+		.await
+		.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
+
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::object_id::equals(Some(object_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::date_quarantined::equals(None),
+		])
+		.select(file_path_to_stream::select())
+		.exec()
+		.await?;
+
+	let mut remote = None;
+	for file_path in &file_paths {
+		let Some(location) = &file_path.location else {
+			continue;
+		};
+
+		// Locations of other nodes may be reachable too, like on a network share
+		if let Some(location_path) = &location.path {
+			let full_path = Path::new(location_path)
+				.join(IsolatedFilePathData::try_from((location.id, file_path))?);
+
+			if fs::metadata(&full_path).await.is_ok() {
+				let extension = maybe_missing(&file_path.extension, "file_path.extension")?;
+				return serve_file(req, builder, &full_path, extension).await;
+			}
+		}
+
+		if remote.is_none() && location.node_id != Some(library.node_local_id) {
+			remote = location
+				.node
+				.as_ref()
+				.and_then(|node| node.node_peer_id.as_deref())
+				.and_then(|peer_id| PeerId::from_str(peer_id).ok())
+				.map(|peer_id| (peer_id, file_path));
+		}
+	}
+
+	let (peer_id, file_path) = remote.ok_or(HandleCustomUriError::NotFound("file"))?;
+
+	// Files fetched before are served from the cache, others are fetched whole in the background
+	// while the parts asked for are streamed, so opening them again doesn't need their node
+	if let Ok(file_path_pub_id) = Uuid::from_slice(&file_path.pub_id) {
+		if let Some(cached_path) = cached_content(&library, file_path_pub_id).await {
+			let extension = maybe_missing(&file_path.extension, "file_path.extension")?;
+			return serve_file(req, builder, &cached_path, extension).await;
+		}
+
+		materialize_in_background(
+			library.clone(),
+			node.p2p.clone(),
+			file_path.id,
+			file_path_pub_id,
+		);
+	}
+
+	serve_remote_file(node, &library, req, builder, peer_id, file_path).await
+}
+
+/// Serves a file of a location owned by a peer, fetching only the range asked for. Files are
+/// streamed as is, as converting them for the webview would need all of their contents.
+async fn serve_remote_file(
+	node: &Node,
+	library: &Library,
+	req: &Request,
+	builder: Builder,
+	peer_id: PeerId,
+	file_path: &file_path_to_stream::Data,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let extension = maybe_missing(&file_path.extension, "file_path.extension")?;
+	let mime_type = mime_type(extension).ok_or(HandleCustomUriError::BadRequest(
+		"TODO: This filetype is not supported because of the missing mime type!",
+	))?;
+
+	let file_path_pub_id = Uuid::from_slice(&file_path.pub_id)
+		.map_err(|_| HandleCustomUriError::BadRequest("Invalid file path id!"))?;
+
+	// The size known by the library, the peer tells the actual one along with the bytes
+	let file_size = maybe_missing(&file_path.size_in_bytes_bytes, "file_path.size_in_bytes")?
+		.as_slice()
+		.try_into()
+		.map(u64::from_be_bytes)
+		.map_err(|_| HandleCustomUriError::BadRequest("Invalid file size!"))?;
+
+	let builder = builder
+		.header("Content-type", mime_type)
+		.header("Accept-Ranges", "bytes");
+
+	if req.method() == Method::HEAD {
+		return Ok(builder
+			.header("Content-Length", file_size)
+			.status(StatusCode::OK)
+			.body(vec![])?);
+	}
+
+	let Some(range) = parse_range(req, file_size)? else {
+		// Without a range, the whole file is sent so it's fetched chunk by chunk
+		let mut buf = Vec::with_capacity(file_size as usize);
+		loop {
+			let (file_size, _, chunk) = node
+				.p2p
+				.request_file_chunk(
+					library,
+					peer_id,
+					RemoteFileRequest {
+						file_path_pub_id,
+						start: buf.len() as u64,
+						length: MAX_REMOTE_FILE_CHUNK_LEN,
+					},
+				)
+				.await?;
+
+			buf.extend_from_slice(&chunk);
+			if chunk.is_empty() || buf.len() as u64 >= file_size {
+				break;
+			}
+		}
+
+		return Ok(builder
+			.header("Content-Length", buf.len())
+			.status(StatusCode::OK)
+			.body(buf)?);
+	};
+
+	// Less than asked for may come back, which players handle by asking for the rest
+	let (file_size, start, chunk) = node
+		.p2p
+		.request_file_chunk(
+			library,
+			peer_id,
+			RemoteFileRequest {
+				file_path_pub_id,
+				start: range.start,
+				length: range.length,
+			},
+		)
+		.await?;
+
+	if chunk.is_empty() {
+		return Err(HandleCustomUriError::RangeNotSatisfiable(
+			"Range is past the end of the file!",
+		));
+	}
+
+	Ok(builder
+		.header("Connection", "Keep-Alive")
+		.header(
+			"Content-Range",
+			format!(
+				"bytes {}-{}/{}",
+				start,
+				start + chunk.len() as u64 - 1,
+				file_size
+			),
+		)
+		.header("Content-Length", chunk.len())
+		.status(StatusCode::PARTIAL_CONTENT)
+		.body(chunk)?)
+}
+
+/// Serves a file reachable from this node, or the part of it asked for by a range request
+async fn serve_file(
+	req: &Request,
+	mut builder: Builder,
+	file_path_full_path: &Path,
+	extension: &str,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let method = req.method();
+
+	if let Some(jpeg) = convert_image_for_webview(file_path_full_path, extension).await? {
+		return Ok(builder
+			.header("Content-type", "image/jpeg")
+			.header("Content-Length", jpeg.len())
+			.status(StatusCode::OK)
+			.body(if method == Method::HEAD { vec![] } else { jpeg })?);
+	}
+
+	let file = File::open(file_path_full_path).await.map_err(|err| {
 		if err.kind() == io::ErrorKind::NotFound {
 			HandleCustomUriError::NotFound("file")
 		} else {
-			FileIOError::from((&file_path_full_path, err)).into()
+			FileIOError::from((file_path_full_path, err)).into()
 		}
 	})?;
 
-	// TODO: This should be determined from magic bytes when the file is indexed and stored it in the DB on the file path
-	// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
-	let mime_type = match extension.as_str() {
+	let mime_type = mime_type(extension).ok_or(HandleCustomUriError::BadRequest(
+		"TODO: This filetype is not supported because of the missing mime type!",
+	))?;
+
+	let mut content_lenght = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((file_path_full_path, e)))?
+		.len();
+
+	let range = parse_range(req, content_lenght)?;
+
+	let mut status_code = 200;
+	let buf = match range {
+		Some(range) => {
+			let file_size = content_lenght;
+			content_lenght = range.length;
+
+			// TODO: For some reason webkit2gtk doesn't like this at all.
+			// It causes it to only stream random pieces of any given audio file.
+			// TODO: This causes macOS to freeze streaming mp4
+			#[cfg(windows)]
+			// prevent max_length;
+			// specially on webview2
+			if mime_type != "application/pdf" && range.length > file_size / 3 {
+				// max size sent (400kb / request)
+				// as it's local file system we can afford to read more often
+				content_lenght = min(file_size - range.start, 1024 * 400);
+			}
+
+			// last byte we are reading, the length of the range include the last byte
+			// who should be skipped on the header
+			let last_byte = range.start + content_lenght - 1;
+
+			// if the webview sent a range header, we need to send a 206 in return
+			status_code = 206;
+
+			// macOS and Windows supports audio and video, linux only supports audio
+			builder = builder
+				.header("Connection", "Keep-Alive")
+				.header("Accept-Ranges", "bytes")
+				.header(
+					"Content-Range",
+					format!("bytes {}-{}/{}", range.start, last_byte, file_size),
+				);
+
+			// FIXME: Add ETag support (caching on the webview)
+
+			read_file(file, content_lenght, Some(range.start))
+				.await
+				.map_err(|e| FileIOError::from((file_path_full_path, e)))?
+		}
+		_ if method == Method::HEAD => {
+			builder = builder.header("Accept-Ranges", "bytes");
+			vec![]
+		}
+		_ => read_file(file, content_lenght, None)
+			.await
+			.map_err(|e| FileIOError::from((file_path_full_path, e)))?,
+	};
+
+	Ok(builder
+		.header("Content-type", mime_type)
+		.header("Content-Length", content_lenght)
+		.status(status_code)
+		.body(buf)?)
+}
+
+/// The single range asked for by a GET request, if any
+fn parse_range(req: &Request, file_size: u64) -> Result<Option<HttpRange>, HandleCustomUriError> {
+	// GET is the only method for which range handling is defined, according to the spec
+	// https://httpwg.org/specs/rfc9110.html#field.range
+	Ok(if req.method() == Method::GET {
+		if let Some(range) = req.headers().get("range") {
+			range
+				.to_str()
+				.ok()
+				.and_then(|range| HttpRange::parse(range, file_size).ok())
+				.ok_or_else(|| {
+					HandleCustomUriError::RangeNotSatisfiable("Error decoding range header!")
+				})
+				.and_then(|range| {
+					// Let's support only 1 range for now
+					if range.len() > 1 {
+						Err(HandleCustomUriError::RangeNotSatisfiable(
+							"Multiple ranges are not supported!",
+						))
+					} else {
+						Ok(range.first().cloned())
+					}
+				})?
+		} else {
+			None
+		}
+	} else {
+		None
+	})
+}
+
+// TODO: This should be determined from magic bytes when the file is indexed and stored it in the DB on the file path
+// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
+pub(crate) fn mime_type(extension: &str) -> Option<&'static str> {
+	let mime_type = match extension {
 		// AAC audio
 		"aac" => "audio/aac",
 		// Musical Instrument Digital Interface (MIDI)
@@ -278,101 +676,62 @@ async fn handle_file(
 		"heic" | "heics" => "image/heic,image/heic-sequence",
 		// AVIF images
 		"avif" | "avci" | "avcs" => "image/avif",
-		_ => {
-			return Err(HandleCustomUriError::BadRequest(
-				"TODO: This filetype is not supported because of the missing mime type!",
-			));
-		}
-	};
-
-	let mut content_lenght = file
-		.metadata()
-		.await
-		.map_err(|e| FileIOError::from((&file_path_full_path, e)))?
-		.len();
-
-	// GET is the only method for which range handling is defined, according to the spec
-	// https://httpwg.org/specs/rfc9110.html#field.range
-	let range = if method == Method::GET {
-		if let Some(range) = req.headers().get("range") {
-			range
-				.to_str()
-				.ok()
-				.and_then(|range| HttpRange::parse(range, content_lenght).ok())
-				.ok_or_else(|| {
-					HandleCustomUriError::RangeNotSatisfiable("Error decoding range header!")
-				})
-				.and_then(|range| {
-					// Let's support only 1 range for now
-					if range.len() > 1 {
-						Err(HandleCustomUriError::RangeNotSatisfiable(
-							"Multiple ranges are not supported!",
-						))
-					} else {
-						Ok(range.first().cloned())
-					}
-				})?
-		} else {
-			None
-		}
-	} else {
-		None
+		_ => return None,
 	};
 
-	let mut status_code = 200;
-	let buf = match range {
-		Some(range) => {
-			let file_size = content_lenght;
-			content_lenght = range.length;
-
-			// TODO: For some reason webkit2gtk doesn't like this at all.
-			// It causes it to only stream random pieces of any given audio file.
-			// TODO: This causes macOS to freeze streaming mp4
-			#[cfg(windows)]
-			// prevent max_length;
-			// specially on webview2
-			if mime_type != "application/pdf" && range.length > file_size / 3 {
-				// max size sent (400kb / request)
-				// as it's local file system we can afford to read more often
-				content_lenght = min(file_size - range.start, 1024 * 400);
-			}
-
-			// last byte we are reading, the length of the range include the last byte
-			// who should be skipped on the header
-			let last_byte = range.start + content_lenght - 1;
+	Some(mime_type)
+}
 
-			// if the webview sent a range header, we need to send a 206 in return
-			status_code = 206;
+/// Webviews can't display RAW photos, nor HEIC/HEIF outside of macOS, so these are served as
+/// the JPEG they'd be shown as. `None` means the file can be served as is.
+#[allow(unused_variables)]
+async fn convert_image_for_webview(
+	path: &Path,
+	extension: &str,
+) -> Result<Option<Vec<u8>>, HandleCustomUriError> {
+	let extension = extension.to_lowercase();
+
+	#[allow(unused_mut)]
+	let mut convert: Option<
+		fn(&Path) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>,
+	> = None;
+
+	#[cfg(feature = "raw")]
+	if crate::object::preview::RAW_EXTENSIONS.contains(&extension.as_str()) {
+		convert = Some(|path| Ok(sd_raw::raw_to_jpeg(path)?));
+	}
 
-			// macOS and Windows supports audio and video, linux only supports audio
-			builder = builder
-				.header("Connection", "Keep-Alive")
-				.header("Accept-Ranges", "bytes")
-				.header(
-					"Content-Range",
-					format!("bytes {}-{}/{}", range.start, last_byte, file_size),
-				);
+	#[cfg(all(feature = "heif", not(target_os = "macos")))]
+	if crate::object::preview::HEIF_EXTENSIONS.contains(&extension.as_str()) {
+		convert = Some(|path| {
+			let mut jpeg = io::Cursor::new(vec![]);
+			sd_heif::heif_to_dynamic_image(path)?
+				.write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(90))?;
 
-			// FIXME: Add ETag support (caching on the webview)
+			Ok(jpeg.into_inner())
+		});
+	}
 
-			read_file(file, content_lenght, Some(range.start))
-				.await
-				.map_err(|e| FileIOError::from((&file_path_full_path, e)))?
-		}
-		_ if method == Method::HEAD => {
-			builder = builder.header("Accept-Ranges", "bytes");
-			vec![]
-		}
-		_ => read_file(file, content_lenght, None)
-			.await
-			.map_err(|e| FileIOError::from((&file_path_full_path, e)))?,
+	let Some(convert) = convert else {
+		return Ok(None);
 	};
 
-	Ok(builder
-		.header("Content-type", mime_type)
-		.header("Content-Length", content_lenght)
-		.status(status_code)
-		.body(buf)?)
+	let path = path.to_path_buf();
+	tokio::task::spawn_blocking(move || {
+		convert(&path).map_err(|e| {
+			error!(
+				"Failed to convert image {} for the webview: {e:#?}",
+				path.display()
+			);
+		})
+	})
+	.await
+	.ok()
+	.and_then(Result::ok)
+	.map(Some)
+	.ok_or(HandleCustomUriError::BadRequest(
+		"Failed to convert the image for the webview!",
+	))
 }
 
 pub fn create_custom_uri_endpoint(node: Arc<Node>) -> Endpoint<impl HttpEndpoint> {
@@ -402,6 +761,8 @@ pub enum HandleCustomUriError {
 	NotFound(&'static str),
 	#[error("HandleCustomUriError::MissingField - '{0}'")]
 	MissingField(#[from] MissingFieldError),
+	#[error("HandleCustomUriError::Remote - {0}")]
+	Remote(#[from] DelegationError),
 }
 
 impl From<HandleCustomUriError> for Response<Vec<u8>> {
@@ -450,6 +811,12 @@ impl From<HandleCustomUriError> for Response<Vec<u8>> {
 					.status(StatusCode::INTERNAL_SERVER_ERROR)
 					.body(b"Internal Server Error".to_vec())
 			}
+			HandleCustomUriError::Remote(err) => {
+				error!("Error fetching file from peer: {:#?}", err);
+				builder
+					.status(StatusCode::BAD_GATEWAY)
+					.body(b"Bad Gateway".to_vec())
+			}
 		})
 		// SAFETY: This unwrap is ok as we have an hardcoded the response builders.
 		.expect("internal error building hardcoded HTTP error response")
@@ -0,0 +1,499 @@
+//! Read-only GraphQL schema over the data of the libraries, for integrations and dashboards
+//! which need more flexible queries than the rspc procedures offer.
+
+use crate::{
+	library::Library,
+	prisma::{file_path, job, location, object, tag, tag_on_object, SortOrder},
+	Node,
+};
+
+use std::sync::Arc;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema};
+use chrono::{DateTime, FixedOffset};
+use uuid::Uuid;
+
+/// Upper bound of the items returned by a single list field
+const MAX_TAKE: i64 = 1000;
+/// Nested selections beyond this are rejected, as every level queries the database again
+const MAX_QUERY_DEPTH: usize = 8;
+
+pub type GraphQLSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn create_graphql_schema(node: Arc<Node>) -> GraphQLSchema {
+	Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+		.data(node)
+		.limit_depth(MAX_QUERY_DEPTH)
+		.finish()
+}
+
+fn clamp_take(take: Option<i64>) -> i64 {
+	take.unwrap_or(100).clamp(0, MAX_TAKE)
+}
+
+fn uuid_from_bytes(bytes: &[u8]) -> Option<Uuid> {
+	Uuid::from_slice(bytes).ok()
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+	async fn libraries(&self, ctx: &Context<'_>) -> Vec<GqlLibrary> {
+		let node = ctx.data_unchecked::<Arc<Node>>();
+
+		let mut libraries = vec![];
+		for config in node.library_manager.get_all_libraries_config().await {
+			if let Some(library) = node.library_manager.get_library(config.uuid).await {
+				libraries.push(GqlLibrary(library));
+			}
+		}
+
+		libraries
+	}
+
+	async fn library(&self, ctx: &Context<'_>, id: Uuid) -> Option<GqlLibrary> {
+		ctx.data_unchecked::<Arc<Node>>()
+			.library_manager
+			.get_library(id)
+			.await
+			.map(GqlLibrary)
+	}
+}
+
+pub struct GqlLibrary(Library);
+
+#[Object(name = "Library")]
+impl GqlLibrary {
+	async fn id(&self) -> Uuid {
+		self.0.id
+	}
+
+	async fn name(&self) -> &str {
+		&self.0.config.name
+	}
+
+	async fn locations(&self) -> Result<Vec<GqlLocation>> {
+		Ok(self
+			.0
+			.db
+			.location()
+			.find_many(vec![])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|location| GqlLocation(self.0.clone(), location))
+			.collect())
+	}
+
+	async fn location(&self, id: i32) -> Result<Option<GqlLocation>> {
+		Ok(self
+			.0
+			.db
+			.location()
+			.find_unique(location::id::equals(id))
+			.exec()
+			.await?
+			.map(|location| GqlLocation(self.0.clone(), location)))
+	}
+
+	async fn file_paths(
+		&self,
+		location_id: Option<i32>,
+		extension: Option<String>,
+		cas_id: Option<String>,
+		skip: Option<i64>,
+		take: Option<i64>,
+	) -> Result<Vec<GqlFilePath>> {
+		let mut params = vec![
+			file_path::date_deleted::equals(None),
+			file_path::date_quarantined::equals(None),
+		];
+		if let Some(location_id) = location_id {
+			params.push(file_path::location_id::equals(Some(location_id)));
+		}
+		if let Some(extension) = extension {
+			params.push(file_path::extension::equals(Some(extension)));
+		}
+		if let Some(cas_id) = cas_id {
+			params.push(file_path::cas_id::equals(Some(cas_id)));
+		}
+
+		Ok(self
+			.0
+			.db
+			.file_path()
+			.find_many(params)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.skip(skip.unwrap_or(0))
+			.take(clamp_take(take))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| GqlFilePath(self.0.clone(), file_path))
+			.collect())
+	}
+
+	async fn objects(
+		&self,
+		kind: Option<i32>,
+		favorite: Option<bool>,
+		skip: Option<i64>,
+		take: Option<i64>,
+	) -> Result<Vec<GqlObject>> {
+		let mut params = vec![];
+		if let Some(kind) = kind {
+			params.push(object::kind::equals(Some(kind)));
+		}
+		if let Some(favorite) = favorite {
+			params.push(object::favorite::equals(Some(favorite)));
+		}
+
+		Ok(self
+			.0
+			.db
+			.object()
+			.find_many(params)
+			.order_by(object::id::order(SortOrder::Asc))
+			.skip(skip.unwrap_or(0))
+			.take(clamp_take(take))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|object| GqlObject(self.0.clone(), object))
+			.collect())
+	}
+
+	async fn tags(&self) -> Result<Vec<GqlTag>> {
+		Ok(self
+			.0
+			.db
+			.tag()
+			.find_many(vec![])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| GqlTag(self.0.clone(), tag))
+			.collect())
+	}
+
+	/// Most recent jobs first
+	async fn jobs(&self, take: Option<i64>) -> Result<Vec<GqlJob>> {
+		Ok(self
+			.0
+			.db
+			.job()
+			.find_many(vec![])
+			.order_by(job::date_created::order(SortOrder::Desc))
+			.take(clamp_take(take))
+			.exec()
+			.await?
+			.into_iter()
+			.map(GqlJob)
+			.collect())
+	}
+}
+
+pub struct GqlLocation(Library, location::Data);
+
+#[Object(name = "Location")]
+impl GqlLocation {
+	async fn id(&self) -> i32 {
+		self.1.id
+	}
+
+	async fn pub_id(&self) -> Option<Uuid> {
+		uuid_from_bytes(&self.1.pub_id)
+	}
+
+	async fn name(&self) -> Option<&str> {
+		self.1.name.as_deref()
+	}
+
+	async fn path(&self) -> Option<&str> {
+		self.1.path.as_deref()
+	}
+
+	async fn is_archived(&self) -> Option<bool> {
+		self.1.is_archived
+	}
+
+	async fn hidden(&self) -> Option<bool> {
+		self.1.hidden
+	}
+
+	async fn date_created(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_created
+	}
+
+	async fn file_paths(&self, skip: Option<i64>, take: Option<i64>) -> Result<Vec<GqlFilePath>> {
+		Ok(self
+			.0
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(self.1.id)),
+				file_path::date_deleted::equals(None),
+				file_path::date_quarantined::equals(None),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.skip(skip.unwrap_or(0))
+			.take(clamp_take(take))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| GqlFilePath(self.0.clone(), file_path))
+			.collect())
+	}
+}
+
+pub struct GqlFilePath(Library, file_path::Data);
+
+#[Object(name = "FilePath")]
+impl GqlFilePath {
+	async fn id(&self) -> i32 {
+		self.1.id
+	}
+
+	async fn pub_id(&self) -> Option<Uuid> {
+		uuid_from_bytes(&self.1.pub_id)
+	}
+
+	async fn is_dir(&self) -> Option<bool> {
+		self.1.is_dir
+	}
+
+	async fn cas_id(&self) -> Option<&str> {
+		self.1.cas_id.as_deref()
+	}
+
+	async fn materialized_path(&self) -> Option<&str> {
+		self.1.materialized_path.as_deref()
+	}
+
+	async fn name(&self) -> Option<&str> {
+		self.1.name.as_deref()
+	}
+
+	async fn extension(&self) -> Option<&str> {
+		self.1.extension.as_deref()
+	}
+
+	/// As a string, since GraphQL integers are only 32 bits
+	async fn size_in_bytes(&self) -> Option<String> {
+		self.1
+			.size_in_bytes_bytes
+			.as_deref()
+			.and_then(|bytes| bytes.try_into().ok())
+			.map(|bytes| u64::from_be_bytes(bytes).to_string())
+	}
+
+	async fn date_created(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_created
+	}
+
+	async fn date_modified(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_modified
+	}
+
+	async fn date_indexed(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_indexed
+	}
+
+	async fn location(&self) -> Result<Option<GqlLocation>> {
+		let Some(location_id) = self.1.location_id else {
+			return Ok(None);
+		};
+
+		Ok(self
+			.0
+			.db
+			.location()
+			.find_unique(location::id::equals(location_id))
+			.exec()
+			.await?
+			.map(|location| GqlLocation(self.0.clone(), location)))
+	}
+
+	async fn object(&self) -> Result<Option<GqlObject>> {
+		let Some(object_id) = self.1.object_id else {
+			return Ok(None);
+		};
+
+		Ok(self
+			.0
+			.db
+			.object()
+			.find_unique(object::id::equals(object_id))
+			.exec()
+			.await?
+			.map(|object| GqlObject(self.0.clone(), object)))
+	}
+}
+
+pub struct GqlObject(Library, object::Data);
+
+#[Object(name = "Object")]
+impl GqlObject {
+	async fn id(&self) -> i32 {
+		self.1.id
+	}
+
+	async fn pub_id(&self) -> Option<Uuid> {
+		uuid_from_bytes(&self.1.pub_id)
+	}
+
+	/// See `ObjectKind` for the meaning of each value
+	async fn kind(&self) -> Option<i32> {
+		self.1.kind
+	}
+
+	async fn hidden(&self) -> Option<bool> {
+		self.1.hidden
+	}
+
+	async fn favorite(&self) -> Option<bool> {
+		self.1.favorite
+	}
+
+	async fn important(&self) -> Option<bool> {
+		self.1.important
+	}
+
+	async fn rating(&self) -> Option<i32> {
+		self.1.rating
+	}
+
+	async fn note(&self) -> Option<&str> {
+		self.1.note.as_deref()
+	}
+
+	async fn date_created(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_created
+	}
+
+	async fn date_accessed(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_accessed
+	}
+
+	async fn file_paths(&self) -> Result<Vec<GqlFilePath>> {
+		Ok(self
+			.0
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::object_id::equals(Some(self.1.id)),
+				file_path::date_deleted::equals(None),
+				file_path::date_quarantined::equals(None),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| GqlFilePath(self.0.clone(), file_path))
+			.collect())
+	}
+
+	async fn tags(&self) -> Result<Vec<GqlTag>> {
+		Ok(self
+			.0
+			.db
+			.tag()
+			.find_many(vec![tag::tag_objects::some(vec![
+				tag_on_object::object_id::equals(self.1.id),
+			])])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| GqlTag(self.0.clone(), tag))
+			.collect())
+	}
+}
+
+pub struct GqlTag(Library, tag::Data);
+
+#[Object(name = "Tag")]
+impl GqlTag {
+	async fn id(&self) -> i32 {
+		self.1.id
+	}
+
+	async fn pub_id(&self) -> Option<Uuid> {
+		uuid_from_bytes(&self.1.pub_id)
+	}
+
+	async fn name(&self) -> Option<&str> {
+		self.1.name.as_deref()
+	}
+
+	async fn color(&self) -> Option<&str> {
+		self.1.color.as_deref()
+	}
+
+	async fn date_created(&self) -> Option<DateTime<FixedOffset>> {
+		self.1.date_created
+	}
+
+	async fn objects(&self, skip: Option<i64>, take: Option<i64>) -> Result<Vec<GqlObject>> {
+		Ok(self
+			.0
+			.db
+			.object()
+			.find_many(vec![object::tags::some(vec![
+				tag_on_object::tag_id::equals(self.1.id),
+			])])
+			.order_by(object::id::order(SortOrder::Asc))
+			.skip(skip.unwrap_or(0))
+			.take(clamp_take(take))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|object| GqlObject(self.0.clone(), object))
+			.collect())
+	}
+}
+
+pub struct GqlJob(job::Data);
+
+#[Object(name = "Job")]
+impl GqlJob {
+	async fn id(&self) -> Option<Uuid> {
+		uuid_from_bytes(&self.0.id)
+	}
+
+	async fn name(&self) -> Option<&str> {
+		self.0.name.as_deref()
+	}
+
+	async fn action(&self) -> Option<&str> {
+		self.0.action.as_deref()
+	}
+
+	/// See `JobStatus` for the meaning of each value
+	async fn status(&self) -> Option<i32> {
+		self.0.status
+	}
+
+	async fn errors_text(&self) -> Option<&str> {
+		self.0.errors_text.as_deref()
+	}
+
+	async fn task_count(&self) -> Option<i32> {
+		self.0.task_count
+	}
+
+	async fn completed_task_count(&self) -> Option<i32> {
+		self.0.completed_task_count
+	}
+
+	async fn date_created(&self) -> Option<DateTime<FixedOffset>> {
+		self.0.date_created
+	}
+
+	async fn date_started(&self) -> Option<DateTime<FixedOffset>> {
+		self.0.date_started
+	}
+
+	async fn date_completed(&self) -> Option<DateTime<FixedOffset>> {
+		self.0.date_completed
+	}
+}
@@ -1,12 +1,20 @@
 use crate::{
-	location::{indexer::IndexerError, LocationError},
+	library::{is_busy, BackupError, DatabaseQueryError},
+	location::{hot_folder::HotFolderError, indexer::IndexerError, LocationError},
 	object::{
-		file_identifier::FileIdentifierJobError, fs::error::FileSystemJobsError,
-		preview::ThumbnailerError, validation::ValidatorError,
+		antivirus::ScanError, file_identifier::FileIdentifierJobError,
+		fs::error::FileSystemJobsError, preview::ThumbnailerError, validation::ValidatorError,
 	},
-	util::{db::MissingFieldError, error::FileIOError},
+	remote::RemoteError,
+	util::{
+		db::MissingFieldError,
+		error::{coded_error, CoreErrorCode, FileIOError},
+	},
+	volume::VolumeError,
 };
 
+use std::io;
+
 use prisma_client_rust::QueryError;
 use rmp_serde::{decode::Error as DecodeError, encode::Error as EncodeError};
 use sd_crypto::Error as CryptoError;
@@ -19,6 +27,8 @@ pub enum JobError {
 	// General errors
 	#[error("database error: {0}")]
 	Database(#[from] QueryError),
+	#[error(transparent)]
+	DatabaseQuery(#[from] DatabaseQueryError),
 	#[error("Failed to join Tokio spawn blocking: {0}")]
 	JoinTask(#[from] tokio::task::JoinError),
 	#[error("job state encode error: {0}")]
@@ -54,9 +64,19 @@ pub enum JobError {
 	#[error(transparent)]
 	Validator(#[from] ValidatorError),
 	#[error(transparent)]
+	MalwareScanner(#[from] ScanError),
+	#[error(transparent)]
 	FileSystemJobsError(#[from] FileSystemJobsError),
 	#[error(transparent)]
 	CryptoError(#[from] CryptoError),
+	#[error(transparent)]
+	Remote(#[from] RemoteError),
+	#[error(transparent)]
+	Backup(#[from] BackupError),
+	#[error(transparent)]
+	Volume(#[from] VolumeError),
+	#[error(transparent)]
+	HotFolder(#[from] HotFolderError),
 	#[error("missing-field: {0}")]
 	MissingField(#[from] MissingFieldError),
 	#[error("item of type '{0}' with id '{1}' is missing from the db")]
@@ -73,6 +93,59 @@ pub enum JobError {
 	Paused(Vec<u8>, oneshot::Sender<()>),
 	#[error("job canceled")]
 	Canceled(oneshot::Sender<()>),
+	#[error("job step stopped due to cancellation")]
+	StepCanceled,
+}
+
+/// Classes of errors that a [`RetryPolicy`](super::RetryPolicy) can opt into retrying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobErrorClass {
+	/// IO errors that may go away on their own, like a network share timing out
+	TransientIo,
+	/// Database errors that may go away on their own, like the database being busy. Constraint
+	/// violations or missing records fail the same way on every attempt, so they aren't retried.
+	Database,
+}
+
+impl JobError {
+	/// Classifies the error by looking for its root cause along the source chain,
+	/// returns `None` for errors that shouldn't be retried
+	pub fn class(&self) -> Option<JobErrorClass> {
+		let mut current: Option<&(dyn std::error::Error + 'static)> = Some(self);
+
+		while let Some(err) = current {
+			if let Some(io_err) = err.downcast_ref::<io::Error>() {
+				return matches!(
+					io_err.kind(),
+					io::ErrorKind::TimedOut
+						| io::ErrorKind::Interrupted
+						| io::ErrorKind::WouldBlock
+						| io::ErrorKind::UnexpectedEof
+						| io::ErrorKind::BrokenPipe
+						| io::ErrorKind::NotConnected
+						| io::ErrorKind::ConnectionReset
+						| io::ErrorKind::ConnectionAborted
+						| io::ErrorKind::ConnectionRefused
+				)
+				.then_some(JobErrorClass::TransientIo);
+			}
+
+			if let Some(db_err) = err.downcast_ref::<DatabaseQueryError>() {
+				return match db_err {
+					DatabaseQueryError::TimedOut(_) => Some(JobErrorClass::Database),
+					DatabaseQueryError::Query(e) => is_busy(e).then_some(JobErrorClass::Database),
+				};
+			}
+
+			if let Some(query_err) = err.downcast_ref::<QueryError>() {
+				return is_busy(query_err).then_some(JobErrorClass::Database);
+			}
+
+			current = err.source();
+		}
+
+		None
+	}
 }
 
 #[derive(Error, Debug)]
@@ -92,27 +165,13 @@ pub enum JobManagerError {
 
 impl From<JobManagerError> for rspc::Error {
 	fn from(value: JobManagerError) -> Self {
-		match value {
-			JobManagerError::AlreadyRunningJob { .. } => Self::with_cause(
-				rspc::ErrorCode::BadRequest,
-				"Tried to spawn a job that is already running!".to_string(),
-				value,
-			),
-			JobManagerError::Database(_) => Self::with_cause(
-				rspc::ErrorCode::InternalServerError,
-				"Error accessing the database".to_string(),
-				value,
-			),
-			JobManagerError::NotFound(_) => Self::with_cause(
-				rspc::ErrorCode::NotFound,
-				"Job not found".to_string(),
-				value,
-			),
-			JobManagerError::MissingField(_) => Self::with_cause(
-				rspc::ErrorCode::InternalServerError,
-				"Missing field".to_string(),
-				value,
-			),
-		}
+		let code = match value {
+			JobManagerError::AlreadyRunningJob { .. } => CoreErrorCode::JobAlreadyRunning,
+			JobManagerError::Database(_) => CoreErrorCode::Database,
+			JobManagerError::NotFound(_) => CoreErrorCode::JobNotFound,
+			JobManagerError::MissingField(_) => CoreErrorCode::Internal,
+		};
+
+		coded_error(code, value)
 	}
 }
@@ -0,0 +1,112 @@
+use crate::{
+	library::Library,
+	prisma::{job, job_dependency, SortOrder},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use specta::Type;
+use uuid::Uuid;
+
+use super::{job_without_data, JobManager, JobManagerError, JobReport};
+
+/// Bumped whenever the layout of [`JobReportExport`] changes, so tools reading exported
+/// reports can tell them apart
+const JOB_REPORT_EXPORT_VERSION: u32 = 1;
+
+/// Self contained snapshot of a job report, meant to be saved as JSON and attached to bug
+/// reports or kept for audits
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobReportExport {
+	pub version: u32,
+	pub core_version: String,
+	pub exported_at: DateTime<Utc>,
+	pub report: JobReport,
+	/// Arguments the job was started with, only available once the job finished
+	pub input: Option<serde_json::Value>,
+	/// What happened to each file, for file operation jobs
+	pub files: Option<serde_json::Value>,
+	pub errors: Vec<String>,
+	/// Reports of the jobs queued after this one, like the identifier after an indexer
+	pub children: Vec<JobReport>,
+}
+
+pub async fn export_job_report(
+	library: &Library,
+	job_manager: &JobManager,
+	job_id: Uuid,
+) -> Result<JobReportExport, JobManagerError> {
+	let active_reports = job_manager.get_active_reports_with_id().await;
+
+	let mut report = find_report(library, job_id)
+		.await?
+		.ok_or(JobManagerError::NotFound(job_id))?;
+
+	report.dependencies = library
+		.db
+		.job_dependency()
+		.find_many(vec![job_dependency::job_id::equals(
+			job_id.as_bytes().to_vec(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|dependency| Uuid::from_slice(&dependency.dependency_id).expect("corrupted database"))
+		.collect();
+
+	// Running jobs have a fresher report in memory than in the db
+	if let Some(active_report) = active_reports.get(&job_id) {
+		report = JobReport {
+			dependencies: report.dependencies,
+			..active_report.clone()
+		};
+	}
+
+	let children = library
+		.db
+		.job()
+		.find_many(vec![job::parent_id::equals(Some(
+			job_id.as_bytes().to_vec(),
+		))])
+		.order_by(job::date_created::order(SortOrder::Asc))
+		.select(job_without_data::select())
+		.exec()
+		.await?
+		.into_iter()
+		.flat_map(JobReport::try_from)
+		.map(|child| active_reports.get(&child.id).cloned().unwrap_or(child))
+		.collect();
+
+	let output = report
+		.metadata
+		.as_ref()
+		.and_then(|metadata| metadata.get("output"));
+
+	Ok(JobReportExport {
+		version: JOB_REPORT_EXPORT_VERSION,
+		core_version: env!("CARGO_PKG_VERSION").to_string(),
+		exported_at: Utc::now(),
+		input: output.and_then(|output| output.get("init")).cloned(),
+		files: output.and_then(|output| output.get("files")).cloned(),
+		errors: report.errors_text.clone(),
+		children,
+		report,
+	})
+}
+
+async fn find_report(
+	library: &Library,
+	job_id: Uuid,
+) -> Result<Option<JobReport>, JobManagerError> {
+	library
+		.db
+		.job()
+		.find_unique(job::id::equals(job_id.as_bytes().to_vec()))
+		.select(job_without_data::select())
+		.exec()
+		.await?
+		.map(JobReport::try_from)
+		.transpose()
+		.map_err(Into::into)
+}
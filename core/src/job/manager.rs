@@ -1,60 +1,255 @@
 use crate::{
+	invalidate_query,
 	job::{worker::Worker, DynJob, Job, JobError},
-	library::Library,
-	location::indexer::indexer_job::IndexerJobInit,
+	library::{DatabaseMaintenanceJobInit, Library, LibraryBackupJobInit, LibraryDataMoveJobInit},
+	location::{
+		hot_folder::ingest_job::HotFolderIngestJobInit, indexer::indexer_job::IndexerJobInit,
+	},
 	object::{
+		album::materializer_job::SmartAlbumMaterializerJobInit,
+		antivirus::scanner_job::MalwareScannerJobInit,
+		embedded_metadata::writer_job::EmbeddedMetadataWriterJobInit,
 		file_identifier::file_identifier_job::FileIdentifierJobInit,
 		fs::{
 			copy::FileCopierJobInit, cut::FileCutterJobInit, delete::FileDeleterJobInit,
 			erase::FileEraserJobInit,
 		},
-		preview::thumbnailer_job::ThumbnailerJobInit,
+		import::{importer_job::MetadataImporterJobInit, ingest_job::ApplePhotosIngestJobInit},
+		preview::{
+			format_migrator_job::ThumbnailFormatMigratorJobInit,
+			regenerator_job::ThumbnailRegeneratorJobInit, thumbnailer_job::ThumbnailerJobInit,
+		},
+		sensitive::classifier_job::SensitiveContentClassifierJobInit,
+		tag::bulk_assign_job::BulkTagAssignJobInit,
 		validation::validator_job::ObjectValidatorJobInit,
 	},
 	prisma::job,
+	remote::upload_job::RemoteUploaderJobInit,
+	volume::VolumeBenchmarkJobInit,
 };
 
 use std::{
+	cmp::Reverse,
 	collections::{HashMap, HashSet, VecDeque},
 	sync::Arc,
+	time::{Duration, Instant},
 };
 
 use futures::future::join_all;
 use prisma_client_rust::operator::or;
-use tokio::sync::{mpsc, oneshot, RwLock};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	sync::{mpsc, oneshot, RwLock},
+	time::sleep,
+};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use super::{JobManagerError, JobReport, JobStatus, StatefulJob};
+use super::{
+	throttle::{JobThrottlePolicy, PowerState, ThrottleAction},
+	JobManagerError, JobPriority, JobReport, JobStatus, StatefulJob,
+};
+
+/// Maximum amount of jobs of each priority class that can run at the same time,
+/// further restricted for some job types.
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct JobConcurrencyLimits {
+	/// Maximum amount of jobs running at the same time, whatever their priority. Background jobs
+	/// preempted by an interactive one don't count against it for that interactive job.
+	#[serde(default = "default_total_concurrency")]
+	pub total: u32,
+	pub interactive: u32,
+	pub normal: u32,
+	pub background: u32,
+	/// Limits by job name, like `"thumbnailer"`
+	#[serde(default)]
+	pub job_types: HashMap<String, JobTypeConcurrencyLimit>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct JobTypeConcurrencyLimit {
+	/// Maximum amount of jobs of this type running at the same time
+	pub total: Option<u32>,
+	/// Maximum amount of jobs of this type running at the same time on the same scope,
+	/// eg: the location being indexed. See [`StatefulJob::concurrency_scope`]
+	pub per_scope: Option<u32>,
+}
+
+fn default_total_concurrency() -> u32 {
+	// db is single threaded, nerd
+	1
+}
+
+impl Default for JobConcurrencyLimits {
+	fn default() -> Self {
+		Self {
+			total: default_total_concurrency(),
+			interactive: 1,
+			normal: 1,
+			background: 1,
+			job_types: HashMap::from([
+				(
+					IndexerJobInit::NAME.to_string(),
+					JobTypeConcurrencyLimit {
+						total: None,
+						per_scope: Some(1),
+					},
+				),
+				(
+					ThumbnailerJobInit::NAME.to_string(),
+					JobTypeConcurrencyLimit {
+						total: Some(4),
+						per_scope: None,
+					},
+				),
+			]),
+		}
+	}
+}
+
+impl JobConcurrencyLimits {
+	/// Checks if another job of the given priority fits under the total limit, given the
+	/// priorities of every job already running
+	pub fn fits_total(
+		&self,
+		priority: JobPriority,
+		running: impl IntoIterator<Item = JobPriority>,
+	) -> bool {
+		running
+			.into_iter()
+			.filter(|&running_priority| {
+				priority != JobPriority::Interactive || running_priority != JobPriority::Background
+			})
+			.count() < self.total as usize
+	}
+
+	pub fn get(&self, priority: JobPriority) -> usize {
+		(match priority {
+			JobPriority::Interactive => self.interactive,
+			JobPriority::Normal => self.normal,
+			JobPriority::Background => self.background,
+		}) as usize
+	}
+
+	/// Checks if another job of the given type and scope fits under the limit of its job type,
+	/// given the type and scope of every job already running
+	pub fn fits_job_type<'a>(
+		&self,
+		name: &str,
+		scope: Option<&str>,
+		running: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+	) -> bool {
+		let Some(limit) = self.job_types.get(name) else {
+			return true;
+		};
+
+		let (mut total, mut in_scope) = (0, 0);
+		for (running_name, running_scope) in running {
+			if running_name == name {
+				total += 1;
+				if scope.is_some() && running_scope == scope {
+					in_scope += 1;
+				}
+			}
+		}
+
+		limit.total.map_or(true, |max| total < max as usize)
+			&& (scope.is_none() || limit.per_scope.map_or(true, |max| in_scope < max as usize))
+	}
+}
+
+/// Job settings of a library, to tune it down when its data is on a slow disk or up when it's on a
+/// fast one. They come on top of the [`JobConcurrencyLimits`] of the node.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryJobSettings {
+	/// Maximum amount of jobs of the library running at the same time, only bound by the limits
+	/// of the node if not set
+	pub max_running_jobs: Option<u32>,
+	/// Maximum amount of files read or written at the same time by a job step, like the files
+	/// hashed by the file identifier, unbounded if not set
+	pub io_parallelism: Option<u32>,
+}
+
+impl LibraryJobSettings {
+	fn fits(&self, running: usize) -> bool {
+		self.max_running_jobs
+			.map_or(true, |max| running < max as usize)
+	}
 
-// db is single threaded, nerd
-const MAX_WORKERS: usize = 1;
+	/// For [`buffer_unordered`](futures::StreamExt::buffer_unordered) and the like
+	pub fn io_parallelism(&self) -> usize {
+		self.io_parallelism
+			.map_or(usize::MAX, |parallelism| parallelism.max(1) as usize)
+	}
+}
 
 pub enum JobManagerEvent {
 	IngestJob(Library, Box<dyn DynJob>),
 	Shutdown(oneshot::Sender<()>),
 }
+
+/// A failed job waiting for its retry backoff to elapse
+struct RetryingJob {
+	library: Library,
+	job: Box<dyn DynJob>,
+	due: Instant,
+	/// Paused jobs aren't dispatched once their backoff elapses, but when they're resumed
+	paused: bool,
+}
+
+/// Restores a job of a type registered by an extension from its report
+pub type JobResumer =
+	fn(JobReport, Option<VecDeque<Box<dyn DynJob>>>) -> Result<Box<dyn DynJob>, JobError>;
+
 /// JobManager handles queueing and executing jobs using the `DynJob`
 /// Handling persisting JobReports to the database, pause/resuming, and
 ///
 pub struct JobManager {
 	current_jobs_hashes: RwLock<HashSet<u64>>,
-	job_queue: RwLock<VecDeque<Box<dyn DynJob>>>,
+	job_queue: RwLock<VecDeque<(Library, Box<dyn DynJob>)>>,
+	/// Jobs that can't be queued yet, as some of their dependencies haven't completed
+	waiting_jobs: RwLock<VecDeque<(Library, Box<dyn DynJob>)>>,
 	running_workers: RwLock<HashMap<Uuid, Worker>>,
+	/// Jobs that failed and wait for their retry backoff to elapse, by id
+	retrying_jobs: RwLock<HashMap<Uuid, RetryingJob>>,
+	preempted_workers: RwLock<HashSet<Uuid>>,
+	/// Workers paused because of the power conditions of the device
+	throttled_workers: RwLock<HashSet<Uuid>>,
+	concurrency_limits: RwLock<JobConcurrencyLimits>,
+	library_job_settings: RwLock<HashMap<Uuid, LibraryJobSettings>>,
+	power_state: RwLock<PowerState>,
+	throttle_policy: RwLock<JobThrottlePolicy>,
 	internal_sender: mpsc::UnboundedSender<JobManagerEvent>,
+	/// Job types registered by the crate embedding the core, by name
+	extension_jobs: HashMap<&'static str, JobResumer>,
 }
 
 impl JobManager {
 	/// Initializes the JobManager and spawns the internal event loop to listen for ingest.
-	pub fn new() -> Arc<Self> {
+	pub fn new(
+		concurrency_limits: JobConcurrencyLimits,
+		throttle_policy: JobThrottlePolicy,
+		extension_jobs: HashMap<&'static str, JobResumer>,
+	) -> Arc<Self> {
 		// allow the job manager to control its workers
 		let (internal_sender, mut internal_receiver) = mpsc::unbounded_channel();
 
 		let this = Arc::new(Self {
 			current_jobs_hashes: RwLock::new(HashSet::new()),
 			job_queue: RwLock::new(VecDeque::new()),
+			waiting_jobs: RwLock::new(VecDeque::new()),
 			running_workers: RwLock::new(HashMap::new()),
+			retrying_jobs: RwLock::new(HashMap::new()),
+			preempted_workers: RwLock::new(HashSet::new()),
+			throttled_workers: RwLock::new(HashSet::new()),
+			concurrency_limits: RwLock::new(concurrency_limits),
+			library_job_settings: RwLock::new(HashMap::new()),
+			power_state: RwLock::new(PowerState::default()),
+			throttle_policy: RwLock::new(throttle_policy),
 			internal_sender,
+			extension_jobs,
 		});
 
 		let this2 = this.clone();
@@ -108,15 +303,52 @@ impl JobManager {
 		Ok(())
 	}
 
-	/// Dispatches a job to a worker if under MAX_WORKERS limit, queues it otherwise.
+	/// Dispatches a job to a worker if under the concurrency limit of its priority class,
+	/// queues it otherwise.
 	async fn dispatch(self: Arc<Self>, library: &Library, mut job: Box<dyn DynJob>) {
+		if let Some(report) = job.report() {
+			match dependencies_state(library, report).await {
+				Ok(DependenciesState::Completed) => {}
+				Ok(DependenciesState::Pending) => {
+					self.wait_for_dependencies(library, job).await;
+					return;
+				}
+				Ok(DependenciesState::Failed(dependency_id)) => {
+					self.cancel_for_failed_dependency(library, job, dependency_id)
+						.await;
+					return;
+				}
+				Err(e) => error!("Failed to check job dependencies, running it anyway: {e:#?}"),
+			}
+		}
+
 		let mut running_workers = self.running_workers.write().await;
 		let mut job_report = job
 			.report_mut()
 			.take()
 			.expect("critical error: missing job on worker");
 
-		if running_workers.len() < MAX_WORKERS {
+		let priority = job.priority();
+		let throttle_action = self.throttle_action(priority).await;
+		let limits = self.concurrency_limits.read().await;
+		let library_settings = self.library_job_settings(library.id).await;
+
+		// Background jobs wait for interactive ones to finish, as they would be preempted anyway
+		let can_run = throttle_action != ThrottleAction::Pause
+			&& library_settings.fits(running_library_workers_count(&running_workers, library.id))
+			&& running_workers_count(&running_workers, priority) < limits.get(priority)
+			&& limits.fits_total(priority, running_workers.values().map(Worker::priority))
+			&& !(priority == JobPriority::Background
+				&& running_workers_count(&running_workers, JobPriority::Interactive) > 0)
+			&& limits.fits_job_type(
+				job.name(),
+				job.concurrency_scope().as_deref(),
+				running_workers_types(&running_workers),
+			);
+
+		drop(limits);
+
+		if can_run {
 			info!("Running job: {:?}", job.name());
 
 			let worker_id = job_report.parent_id.unwrap_or(job_report.id);
@@ -128,27 +360,252 @@ impl JobManager {
 						error!("Error spawning worker: {:#?}", e);
 					},
 					|worker| {
+						worker.set_throttled(throttle_action == ThrottleAction::Slow);
 						running_workers.insert(worker_id, worker);
 					},
 				);
+
+			if priority == JobPriority::Interactive {
+				self.preempt_background_workers(&running_workers).await;
+			}
 		} else {
 			debug!(
 				"Queueing job: <name='{}', hash='{}'>",
 				job.name(),
 				job.hash()
 			);
-			if let Err(e) = job_report.create(library).await {
-				// It's alright to just log here, as will try to create the report on run if it wasn't created before
-				error!("Error creating job report: {:#?}", e);
+			// Persisting the initial state, so queued jobs survive an app restart
+			match job.serialize_state() {
+				Ok(state) => job_report.data = Some(state),
+				Err(e) => error!("Error serializing queued job state: {:#?}", e),
+			}
+
+			if job_report.created_at.is_none() {
+				if let Err(e) = job_report.create(library).await {
+					// It's alright to just log here, as will try to create the report on run if it wasn't created before
+					error!("Error creating job report: {:#?}", e);
+				}
+			} else if let Err(e) = job_report.update(library).await {
+				error!("Error updating job report: {:#?}", e);
 			}
 
 			// Put the report back, or it will be lost forever
 			*job.report_mut() = Some(job_report);
 
-			self.job_queue.write().await.push_back(job);
+			self.job_queue
+				.write()
+				.await
+				.push_back((library.clone(), job));
 		}
 	}
 
+	async fn wait_for_dependencies(&self, library: &Library, mut job: Box<dyn DynJob>) {
+		debug!(
+			"Job <name='{}', hash='{}'> is waiting for its dependencies",
+			job.name(),
+			job.hash()
+		);
+
+		let state = job.serialize_state();
+		if let Some(job_report) = job.report_mut() {
+			if job_report.created_at.is_none() {
+				// Persisting the initial state, so waiting jobs survive an app restart
+				match state {
+					Ok(state) => job_report.data = Some(state),
+					Err(e) => error!("Error serializing waiting job state: {:#?}", e),
+				}
+
+				if let Err(e) = job_report.create(library).await {
+					error!("Error creating job report: {:#?}", e);
+				}
+			}
+		}
+
+		self.waiting_jobs
+			.write()
+			.await
+			.push_back((library.clone(), job));
+	}
+
+	async fn cancel_for_failed_dependency(
+		&self,
+		library: &Library,
+		mut job: Box<dyn DynJob>,
+		dependency_id: Uuid,
+	) {
+		warn!(
+			"Canceling job <name='{}'> as its dependency <id='{dependency_id}'> didn't complete",
+			job.name()
+		);
+
+		self.current_jobs_hashes.write().await.remove(&job.hash());
+
+		if let Some(job_report) = job.report_mut() {
+			job_report.status = JobStatus::Canceled;
+			job_report.data = None;
+			job_report.errors_text.push(format!(
+				"Dependency job <id='{dependency_id}'> didn't complete"
+			));
+
+			let res = if job_report.created_at.is_none() {
+				job_report.create(library).await
+			} else {
+				job_report.update(library).await
+			};
+			if let Err(e) = res {
+				error!("Error updating job report: {:#?}", e);
+			}
+		}
+
+		if let Err(e) = job.cancel_children(library).await {
+			error!("Failed to cancel children jobs: {e:#?}");
+		}
+
+		invalidate_query!(library, "jobs.reports");
+
+		// Jobs depending on this one must be canceled as well
+		self.dispatch_waiting().await;
+	}
+
+	/// Sends every job waiting on dependencies back to dispatch, which runs or queues the ones
+	/// whose dependencies completed and keeps the others waiting.
+	async fn dispatch_waiting(&self) {
+		for (library, job) in self.waiting_jobs.write().await.drain(..) {
+			// We can't directly execute `self.dispatch` here because it would cause an async cycle.
+			self.internal_sender
+				.send(JobManagerEvent::IngestJob(library, job))
+				.unwrap_or_else(|_| {
+					error!("Failed to ingest job!");
+				});
+		}
+	}
+
+	/// Pauses running background jobs, so interactive jobs don't have to compete with them.
+	async fn preempt_background_workers(&self, running_workers: &HashMap<Uuid, Worker>) {
+		let mut preempted_workers = self.preempted_workers.write().await;
+
+		for (worker_id, worker) in running_workers {
+			if worker.priority() == JobPriority::Background && !worker.is_paused() {
+				debug!("Preempting job: {:?}", worker.report().name);
+				worker.pause().await;
+				preempted_workers.insert(*worker_id);
+			}
+		}
+	}
+
+	/// Resumes the preempted background jobs once no interactive job is running anymore.
+	async fn resume_preempted_workers(&self, running_workers: &HashMap<Uuid, Worker>) {
+		if running_workers_count(running_workers, JobPriority::Interactive) > 0 {
+			return;
+		}
+
+		let throttled_workers = self.throttled_workers.read().await;
+
+		for worker_id in self.preempted_workers.write().await.drain() {
+			// Throttled workers stay paused until the power conditions allow them to run
+			if throttled_workers.contains(&worker_id) {
+				continue;
+			}
+
+			if let Some(worker) = running_workers.get(&worker_id) {
+				debug!("Resuming preempted job: {:?}", worker.report().name);
+				worker.resume().await;
+			}
+		}
+	}
+
+	/// Sends to dispatch every queued job that fits under the concurrency limits,
+	/// highest priority first.
+	async fn dispatch_queued(&self) {
+		let running_workers = self.running_workers.read().await;
+		let limits = self.concurrency_limits.read().await.clone();
+		let library_job_settings = self.library_job_settings.read().await.clone();
+		let power_state = *self.power_state.read().await;
+		let throttle_policy = *self.throttle_policy.read().await;
+		let mut job_queue = self.job_queue.write().await;
+
+		let mut available_slots = [
+			JobPriority::Interactive,
+			JobPriority::Normal,
+			JobPriority::Background,
+		]
+		.into_iter()
+		.map(|priority| {
+			(
+				priority,
+				limits
+					.get(priority)
+					.saturating_sub(running_workers_count(&running_workers, priority)),
+			)
+		})
+		.collect::<HashMap<_, _>>();
+
+		// Stable sort, so jobs keep their queue order inside each priority class
+		job_queue
+			.make_contiguous()
+			.sort_by_key(|(_, job)| Reverse(job.priority()));
+
+		// Type, scope and priority of the jobs dispatched in this pass, which will be running soon
+		let mut dispatched_types = vec![];
+		let mut dispatched_priorities = vec![];
+		let mut dispatched_libraries = HashMap::<Uuid, usize>::new();
+
+		let mut still_queued = VecDeque::with_capacity(job_queue.len());
+		for (library, job) in job_queue.drain(..) {
+			let scope = job.concurrency_scope();
+
+			let fits_job_type = limits.fits_job_type(
+				job.name(),
+				scope.as_deref(),
+				running_workers_types(&running_workers).chain(dispatched_types.iter().map(
+					|(name, scope): &(&'static str, Option<String>)| (*name, scope.as_deref()),
+				)),
+			);
+
+			let fits_library = library_job_settings
+				.get(&library.id)
+				.copied()
+				.unwrap_or_default()
+				.fits(
+					running_library_workers_count(&running_workers, library.id)
+						+ dispatched_libraries.get(&library.id).copied().unwrap_or(0),
+				);
+
+			let paused_by_throttle =
+				throttle_policy.get(job.priority()).action(&power_state) == ThrottleAction::Pause;
+
+			let fits_total = limits.fits_total(
+				job.priority(),
+				running_workers
+					.values()
+					.map(Worker::priority)
+					.chain(dispatched_priorities.iter().copied()),
+			);
+
+			match available_slots.get_mut(&job.priority()) {
+				Some(slots)
+					if *slots > 0
+						&& fits_total && fits_job_type
+						&& fits_library && !paused_by_throttle =>
+				{
+					*slots -= 1;
+					dispatched_priorities.push(job.priority());
+					dispatched_types.push((job.name(), scope));
+					*dispatched_libraries.entry(library.id).or_default() += 1;
+					// We can't directly execute `self.dispatch` here because it would cause an async cycle.
+					self.internal_sender
+						.send(JobManagerEvent::IngestJob(library, job))
+						.unwrap_or_else(|_| {
+							error!("Failed to ingest job!");
+						});
+				}
+				_ => still_queued.push_back((library, job)),
+			}
+		}
+
+		*job_queue = still_queued;
+	}
+
 	pub async fn complete(
 		self: Arc<Self>,
 		library: &Library,
@@ -158,22 +615,209 @@ impl JobManager {
 	) {
 		// remove worker from running workers and from current jobs hashes
 		self.current_jobs_hashes.write().await.remove(&job_hash);
-		self.running_workers.write().await.remove(&worker_id);
-		// continue queue
-		let job = if next_job.is_some() {
-			next_job
-		} else {
-			self.job_queue.write().await.pop_front()
-		};
+		self.release_worker(worker_id).await;
+
+		self.dispatch_waiting().await;
 
-		if let Some(job) = job {
+		// continue queue
+		if let Some(job) = next_job {
 			// We can't directly execute `self.ingest` here because it would cause an async cycle.
 			self.internal_sender
 				.send(JobManagerEvent::IngestJob(library.clone(), job))
 				.unwrap_or_else(|_| {
 					error!("Failed to ingest job!");
 				});
+		} else {
+			self.dispatch_queued().await;
+		}
+	}
+
+	/// Dispatches a failed job again once its backoff elapses. The job keeps its hash meanwhile,
+	/// so the same job can't be ingested again while waiting to be retried.
+	pub(super) async fn retry(
+		self: Arc<Self>,
+		library: &Library,
+		worker_id: Uuid,
+		job: Box<dyn DynJob>,
+		backoff: Duration,
+	) {
+		self.release_worker(worker_id).await;
+
+		self.dispatch_waiting().await;
+		self.dispatch_queued().await;
+
+		let job_id = job.id();
+		self.retrying_jobs.write().await.insert(
+			job_id,
+			RetryingJob {
+				library: library.clone(),
+				job,
+				due: Instant::now() + backoff,
+				paused: false,
+			},
+		);
+
+		tokio::spawn(async move {
+			sleep(backoff).await;
+
+			self.dispatch_retrying(job_id).await;
+		});
+	}
+
+	/// Dispatches a job once its retry backoff elapsed, unless it was paused or canceled meanwhile
+	async fn dispatch_retrying(&self, job_id: Uuid) {
+		let mut retrying_jobs = self.retrying_jobs.write().await;
+		if retrying_jobs
+			.get(&job_id)
+			.map_or(true, |retrying| retrying.paused)
+		{
+			return;
+		}
+		let Some(RetryingJob { library, job, .. }) = retrying_jobs.remove(&job_id) else {
+			return;
+		};
+		drop(retrying_jobs);
+
+		debug!(
+			"Retrying job: <name='{}', hash='{}'>",
+			job.name(),
+			job.hash()
+		);
+
+		self.internal_sender
+			.send(JobManagerEvent::IngestJob(library, job))
+			.unwrap_or_else(|_| {
+				error!("Failed to ingest job!");
+			});
+	}
+
+	/// Pauses or resumes a job waiting to be retried, which is dispatched on resume if its
+	/// backoff elapsed while it was paused
+	async fn set_retrying_paused(&self, job_id: Uuid, paused: bool) -> Result<(), JobManagerError> {
+		let mut retrying_jobs = self.retrying_jobs.write().await;
+		let retrying = retrying_jobs
+			.get_mut(&job_id)
+			.ok_or(JobManagerError::NotFound(job_id))?;
+
+		debug!(
+			"{} job waiting to be retried: {:#?}",
+			if paused { "Pausing" } else { "Resuming" },
+			retrying.job.report()
+		);
+
+		retrying.paused = paused;
+		if let Some(job_report) = retrying.job.report_mut() {
+			job_report.status = if paused {
+				JobStatus::Paused
+			} else {
+				JobStatus::Queued
+			};
+			if let Err(e) = job_report.update(&retrying.library).await {
+				error!("Error updating job report: {:#?}", e);
+			}
+		}
+		invalidate_query!(retrying.library, "jobs.reports");
+
+		let due = !paused && retrying.due <= Instant::now();
+		drop(retrying_jobs);
+
+		if due {
+			self.dispatch_retrying(job_id).await;
+		}
+
+		Ok(())
+	}
+
+	async fn release_worker(&self, worker_id: Uuid) {
+		self.preempted_workers.write().await.remove(&worker_id);
+		self.throttled_workers.write().await.remove(&worker_id);
+
+		let mut running_workers = self.running_workers.write().await;
+		running_workers.remove(&worker_id);
+		self.resume_preempted_workers(&running_workers).await;
+	}
+
+	pub async fn concurrency_limits(&self) -> JobConcurrencyLimits {
+		self.concurrency_limits.read().await.clone()
+	}
+
+	/// Changes the concurrency limits at runtime, dispatching queued jobs that now fit.
+	/// Lowering a limit doesn't stop jobs that are already running.
+	pub async fn set_concurrency_limits(&self, limits: JobConcurrencyLimits) {
+		*self.concurrency_limits.write().await = limits;
+		self.dispatch_queued().await;
+	}
+
+	async fn library_job_settings(&self, library_id: Uuid) -> LibraryJobSettings {
+		self.library_job_settings
+			.read()
+			.await
+			.get(&library_id)
+			.copied()
+			.unwrap_or_default()
+	}
+
+	/// Sets the job settings of a library, when it's loaded or they're changed, dispatching its
+	/// queued jobs that now fit. Lowering the limit doesn't stop jobs that are already running.
+	pub async fn set_library_job_settings(&self, library_id: Uuid, settings: LibraryJobSettings) {
+		self.library_job_settings
+			.write()
+			.await
+			.insert(library_id, settings);
+		self.dispatch_queued().await;
+	}
+
+	async fn throttle_action(&self, priority: JobPriority) -> ThrottleAction {
+		self.throttle_policy
+			.read()
+			.await
+			.get(priority)
+			.action(&*self.power_state.read().await)
+	}
+
+	pub async fn power_state(&self) -> PowerState {
+		*self.power_state.read().await
+	}
+
+	pub async fn throttle_policy(&self) -> JobThrottlePolicy {
+		*self.throttle_policy.read().await
+	}
+
+	/// Applies the throttle policy to every running job under the given power conditions,
+	/// pausing or slowing them down, and resuming the ones we paused once they're allowed to run.
+	/// Jobs resumed by the user while throttled aren't paused again.
+	pub async fn set_throttle(&self, power_state: PowerState, policy: JobThrottlePolicy) {
+		*self.power_state.write().await = power_state;
+		*self.throttle_policy.write().await = policy;
+
+		{
+			let running_workers = self.running_workers.read().await;
+			let mut throttled_workers = self.throttled_workers.write().await;
+			let preempted_workers = self.preempted_workers.read().await;
+
+			for (worker_id, worker) in running_workers.iter() {
+				let action = policy.get(worker.priority()).action(&power_state);
+
+				worker.set_throttled(action == ThrottleAction::Slow);
+
+				if action == ThrottleAction::Pause {
+					if !worker.is_paused() && throttled_workers.insert(*worker_id) {
+						debug!("Pausing job due to power conditions: {:?}", worker.name());
+						worker.pause().await;
+					}
+				} else if throttled_workers.remove(worker_id)
+					&& !preempted_workers.contains(worker_id)
+				{
+					debug!(
+						"Resuming job throttled by power conditions: {:?}",
+						worker.name()
+					);
+					worker.resume().await;
+				}
+			}
 		}
+
+		self.dispatch_queued().await;
 	}
 
 	/// Shutdown the job manager, signaled by core on shutdown.
@@ -201,7 +845,7 @@ impl JobManager {
 
 			Ok(())
 		} else {
-			Err(JobManagerError::NotFound(job_id))
+			self.set_retrying_paused(job_id, true).await
 		}
 	}
 	/// Resume a specific job.
@@ -215,7 +859,7 @@ impl JobManager {
 
 			Ok(())
 		} else {
-			Err(JobManagerError::NotFound(job_id))
+			self.set_retrying_paused(job_id, false).await
 		}
 	}
 
@@ -228,10 +872,44 @@ impl JobManager {
 			// Set the cancel signal in the worker.
 			worker.cancel().await;
 
-			Ok(())
-		} else {
-			Err(JobManagerError::NotFound(job_id))
+			return Ok(());
+		}
+
+		// The job may also be waiting on its dependencies, or for its retry backoff to elapse
+		let mut waiting_jobs = self.waiting_jobs.write().await;
+		let waiting = waiting_jobs
+			.iter()
+			.position(|(_, job)| job.id() == job_id)
+			.and_then(|index| waiting_jobs.remove(index));
+		drop(waiting_jobs);
+		let retrying = self
+			.retrying_jobs
+			.write()
+			.await
+			.remove(&job_id)
+			.map(|RetryingJob { library, job, .. }| (library, job));
+
+		let Some((library, mut job)) = waiting.or(retrying) else {
+			return Err(JobManagerError::NotFound(job_id));
+		};
+
+		debug!("Canceling waiting job: {:#?}", job.report());
+
+		self.current_jobs_hashes.write().await.remove(&job.hash());
+
+		if let Some(job_report) = job.report_mut() {
+			job_report.status = JobStatus::Canceled;
+			job_report.data = None;
+			if let Err(e) = job_report.update(&library).await {
+				error!("Error updating job report: {:#?}", e);
+			}
 		}
+		invalidate_query!(library, "jobs.reports");
+
+		// Jobs depending on this one must be canceled as well
+		self.dispatch_waiting().await;
+
+		Ok(())
 	}
 
 	/// This is called at startup to resume all paused jobs or jobs that were running
@@ -256,11 +934,22 @@ impl JobManager {
 			.map(JobReport::try_from);
 
 		for job in all_jobs {
-			let job = job?;
+			let mut job = job?;
+
+			if let Err(e) = job.load_dependencies(library).await {
+				error!(
+					"Failed to load dependencies of job <id='{}'>: {e:#?}",
+					job.id
+				);
+			}
 
-			match initialize_resumable_job(job.clone(), None) {
+			match initialize_resumable_job(job.clone(), None, &self.extension_jobs) {
 				Ok(resumable_job) => {
 					info!("Resuming job: {} with uuid {}", job.name, job.id);
+					self.current_jobs_hashes
+						.write()
+						.await
+						.insert(resumable_job.hash());
 					Arc::clone(&self).dispatch(library, resumable_job).await;
 				}
 				Err(err) => {
@@ -324,6 +1013,76 @@ impl JobManager {
 	}
 }
 
+enum DependenciesState {
+	Completed,
+	Pending,
+	Failed(Uuid),
+}
+
+async fn dependencies_state(
+	library: &Library,
+	report: &JobReport,
+) -> Result<DependenciesState, JobManagerError> {
+	if report.dependencies.is_empty() {
+		return Ok(DependenciesState::Completed);
+	}
+
+	let dependencies = library
+		.db
+		.job()
+		.find_many(vec![job::id::in_vec(
+			report
+				.dependencies
+				.iter()
+				.map(|id| id.as_bytes().to_vec())
+				.collect(),
+		)])
+		.select(job::select!({ id status }))
+		.exec()
+		.await?;
+
+	// Dependencies missing from the db were cleared after they finished
+	let mut state = DependenciesState::Completed;
+	for dependency in dependencies {
+		match dependency.status.map(JobStatus::try_from) {
+			Some(Ok(JobStatus::Completed | JobStatus::CompletedWithErrors)) => {}
+			Some(Ok(JobStatus::Canceled | JobStatus::Failed)) => {
+				return Ok(DependenciesState::Failed(
+					Uuid::from_slice(&dependency.id).expect("corrupted database"),
+				));
+			}
+			_ => state = DependenciesState::Pending,
+		}
+	}
+
+	Ok(state)
+}
+
+fn running_workers_types(
+	running_workers: &HashMap<Uuid, Worker>,
+) -> impl Iterator<Item = (&'static str, Option<&str>)> {
+	running_workers
+		.values()
+		.map(|worker| (worker.name(), worker.concurrency_scope()))
+}
+
+fn running_library_workers_count(
+	running_workers: &HashMap<Uuid, Worker>,
+	library_id: Uuid,
+) -> usize {
+	running_workers
+		.values()
+		.filter(|worker| worker.library_id() == library_id)
+		.count()
+}
+
+fn running_workers_count(running_workers: &HashMap<Uuid, Worker>, priority: JobPriority) -> usize {
+	running_workers
+		.values()
+		.filter(|worker| worker.priority() == priority)
+		.count()
+}
+
 #[macro_use]
 mod macros {
 	macro_rules! dispatch_call_to_job_by_name {
@@ -342,11 +1101,16 @@ mod macros {
 fn initialize_resumable_job(
 	job_report: JobReport,
 	next_jobs: Option<VecDeque<Box<dyn DynJob>>>,
+	extension_jobs: &HashMap<&'static str, JobResumer>,
 ) -> Result<Box<dyn DynJob>, JobError> {
 	dispatch_call_to_job_by_name!(
 		job_report.name.as_str(),
 		T -> Job::<T>::new_from_report(job_report, next_jobs),
 		default = {
+			if let Some(resume) = extension_jobs.get(job_report.name.as_str()).copied() {
+				return resume(job_report, next_jobs);
+			}
+
 			error!(
 				"Unknown job type: {}, id: {}",
 				job_report.name, job_report.id
@@ -358,10 +1122,42 @@ fn initialize_resumable_job(
 			IndexerJobInit,
 			FileIdentifierJobInit,
 			ObjectValidatorJobInit,
+			MalwareScannerJobInit,
+			SensitiveContentClassifierJobInit,
+			HotFolderIngestJobInit,
 			FileCutterJobInit,
 			FileCopierJobInit,
 			FileDeleterJobInit,
 			FileEraserJobInit,
+			SmartAlbumMaterializerJobInit,
+			BulkTagAssignJobInit,
+			ThumbnailFormatMigratorJobInit,
+			ThumbnailRegeneratorJobInit,
+			RemoteUploaderJobInit,
+			MetadataImporterJobInit,
+			EmbeddedMetadataWriterJobInit,
+			ApplePhotosIngestJobInit,
+			LibraryBackupJobInit,
+			LibraryDataMoveJobInit,
+			DatabaseMaintenanceJobInit,
+			VolumeBenchmarkJobInit,
 		]
 	)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn runs_one_job_at_a_time_by_default() {
+		let limits = JobConcurrencyLimits::default();
+
+		assert!(limits.fits_total(JobPriority::Normal, []));
+		assert!(!limits.fits_total(JobPriority::Normal, [JobPriority::Background]));
+		assert!(!limits.fits_total(JobPriority::Background, [JobPriority::Interactive]));
+		// Preempting the background job
+		assert!(limits.fits_total(JobPriority::Interactive, [JobPriority::Background]));
+		assert!(!limits.fits_total(JobPriority::Interactive, [JobPriority::Normal]));
+	}
+}
@@ -6,17 +6,27 @@ use std::{
 	hash::{Hash, Hasher},
 	mem,
 	sync::Arc,
-	time::Instant,
+	time::{Duration, Instant},
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tokio::{select, sync::mpsc};
+use specta::Type;
+use tokio::{
+	select,
+	sync::mpsc,
+	task::JoinHandle,
+	time::{sleep, timeout},
+};
 use tracing::{debug, info, trace, warn};
 use uuid::Uuid;
 
 mod error;
+pub mod export;
 mod manager;
 mod report;
+pub mod retention;
+pub mod scheduler;
+pub mod throttle;
 mod worker;
 
 pub use error::*;
@@ -24,6 +34,13 @@ pub use manager::*;
 pub use report::*;
 pub use worker::*;
 
+/// How often a running job persists its state, so it can be resumed from this checkpoint
+/// if the app is closed or crashes mid-job.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a running step has to stop by itself once its job is canceled, before being aborted
+const CANCELLATION_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 pub type JobResult = Result<JobMetadata, JobError>;
 pub type JobMetadata = Option<serde_json::Value>;
 
@@ -52,6 +69,55 @@ impl JobRunMetadata for () {
 	fn update(&mut self, _new_data: Self) {}
 }
 
+/// Priority class of a job. Each class has its own concurrency limit on the job manager, and
+/// interactive jobs preempt background ones while they run.
+#[derive(
+	Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum JobPriority {
+	Background,
+	Normal,
+	Interactive,
+}
+
+/// Declares how the job manager retries a failed job
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+	/// Total amount of runs, including the first one
+	pub max_attempts: u32,
+	/// Waiting time before the first retry, doubling on each following one
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+	/// Only errors of these classes are retried
+	pub retry_on: &'static [JobErrorClass],
+}
+
+impl RetryPolicy {
+	pub const NEVER: Self = Self {
+		max_attempts: 1,
+		initial_backoff: Duration::ZERO,
+		max_backoff: Duration::ZERO,
+		retry_on: &[],
+	};
+
+	/// For jobs that can safely run again from their last step, like indexing,
+	/// so hiccups of network shares or a busy database don't fail the whole run
+	pub const TRANSIENT: Self = Self {
+		max_attempts: 5,
+		initial_backoff: Duration::from_secs(2),
+		max_backoff: Duration::from_secs(60),
+		retry_on: &[JobErrorClass::TransientIo, JobErrorClass::Database],
+	};
+
+	/// Waiting time before the given retry, starting at 1
+	pub fn backoff(&self, retry: u32) -> Duration {
+		self.initial_backoff
+			.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+			.min(self.max_backoff)
+	}
+}
+
 #[async_trait::async_trait]
 pub trait StatefulJob:
 	Serialize + DeserializeOwned + Hash + fmt::Debug + Send + Sync + Sized + 'static
@@ -63,6 +129,18 @@ pub trait StatefulJob:
 	/// The name of the job is a unique human readable identifier for the job.
 	const NAME: &'static str;
 	const IS_BACKGROUND: bool = false;
+	const PRIORITY: JobPriority = if Self::IS_BACKGROUND {
+		JobPriority::Background
+	} else {
+		JobPriority::Normal
+	};
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::NEVER;
+
+	/// Jobs with the same scope, like the location they work on, can be limited to run one
+	/// at a time through the job type concurrency limits
+	fn concurrency_scope(&self) -> Option<String> {
+		None
+	}
 
 	/// initialize the steps for the job
 	async fn init(
@@ -88,6 +166,18 @@ pub trait StatefulJob:
 		run_metadata: &Self::RunMetadata,
 	) -> JobResult;
 
+	/// is called when the job is canceled, before reporting it as such, to remove artifacts
+	/// left behind like partially copied files. `interrupted_step` is the step that was running
+	/// and didn't complete, if any.
+	async fn cleanup(
+		&self,
+		_ctx: &WorkerContext,
+		_data: Option<&Self::Data>,
+		_interrupted_step: Option<&Self::Step>,
+	) -> Result<(), JobError> {
+		Ok(())
+	}
+
 	fn hash(&self) -> u64 {
 		let mut s = DefaultHasher::new();
 		Self::NAME.hash(&mut s);
@@ -103,6 +193,8 @@ pub trait DynJob: Send + Sync {
 	fn report(&self) -> &Option<JobReport>;
 	fn report_mut(&mut self) -> &mut Option<JobReport>;
 	fn name(&self) -> &'static str;
+	fn priority(&self) -> JobPriority;
+	fn concurrency_scope(&self) -> Option<String>;
 	async fn run(
 		&mut self,
 		ctx: WorkerContext,
@@ -111,6 +203,9 @@ pub trait DynJob: Send + Sync {
 	fn hash(&self) -> u64;
 	fn set_next_jobs(&mut self, next_jobs: VecDeque<Box<dyn DynJob>>);
 	fn serialize_state(&self) -> Result<Vec<u8>, JobError>;
+	/// Returns how long to wait before retrying the job after the given error, if its retry
+	/// policy allows it, counting the new attempt
+	fn retry_backoff(&mut self, error: &JobError) -> Option<Duration>;
 	async fn register_children(&mut self, library: &Library) -> Result<(), JobError>;
 	async fn pause_children(&mut self, library: &Library) -> Result<(), JobError>;
 	async fn cancel_children(&mut self, library: &Library) -> Result<(), JobError>;
@@ -136,6 +231,7 @@ impl<SJob: StatefulJob> JobBuilder<SJob> {
 				run_metadata: Default::default(),
 			}),
 			next_jobs: VecDeque::new(),
+			attempt: 1,
 		})
 	}
 
@@ -148,6 +244,10 @@ impl<SJob: StatefulJob> JobBuilder<SJob> {
 		}
 	}
 
+	pub fn id(&self) -> Uuid {
+		self.id
+	}
+
 	pub fn with_action(mut self, action: impl AsRef<str>) -> Self {
 		self.report_builder = self.report_builder.with_action(action);
 		self
@@ -162,6 +262,12 @@ impl<SJob: StatefulJob> JobBuilder<SJob> {
 		self.report_builder = self.report_builder.with_metadata(metadata);
 		self
 	}
+
+	/// The job will only run once the job with the given id completes, and is canceled if it fails
+	pub fn with_dependency(mut self, job_id: Uuid) -> Self {
+		self.report_builder = self.report_builder.with_dependency(job_id);
+		self
+	}
 }
 
 pub struct Job<SJob: StatefulJob> {
@@ -171,6 +277,7 @@ pub struct Job<SJob: StatefulJob> {
 	state: Option<JobState<SJob>>,
 	// stateful_job: Option<SJob>,
 	next_jobs: VecDeque<Box<dyn DynJob>>,
+	attempt: u32,
 }
 
 impl<SJob: StatefulJob> Job<SJob> {
@@ -216,6 +323,7 @@ impl<SJob: StatefulJob> Job<SJob> {
 			state: Some(state),
 			report: Some(report),
 			next_jobs: next_jobs.unwrap_or_default(),
+			attempt: 1,
 		}))
 	}
 
@@ -255,6 +363,17 @@ impl<'de, Job: StatefulJob> Deserialize<'de> for JobState<Job> {
 	}
 }
 
+/// Borrowed view of a [`JobState`], serialized to the exact same format, used to checkpoint a
+/// running job without having to take back ownership of its state.
+#[derive(Serialize)]
+struct JobStateCheckpoint<'a, Job: StatefulJob> {
+	init: &'a Job,
+	data: Option<&'a Job::Data>,
+	steps: &'a VecDeque<Job::Step>,
+	step_number: usize,
+	run_metadata: &'a Job::RunMetadata,
+}
+
 /// This is a workaround for a serde bug.
 /// Both these generics on this type should point to the same type.
 ///
@@ -411,6 +530,16 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		<SJob as StatefulJob>::NAME
 	}
 
+	fn priority(&self) -> JobPriority {
+		<SJob as StatefulJob>::PRIORITY
+	}
+
+	fn concurrency_scope(&self) -> Option<String> {
+		self.state
+			.as_ref()
+			.and_then(|state| state.init.concurrency_scope())
+	}
+
 	async fn run(
 		&mut self,
 		ctx: WorkerContext,
@@ -445,13 +574,13 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		} else {
 			// Job init phase
 			let inner_ctx = Arc::clone(&ctx);
-			let stateful_job = Arc::clone(&stateful_job);
+			let inner_stateful_job = Arc::clone(&stateful_job);
 
 			let init_time = Instant::now();
 
 			let mut init_handle = tokio::spawn(async move {
 				let mut new_data = None;
-				let res = stateful_job.init(&inner_ctx, &mut new_data).await;
+				let res = inner_stateful_job.init(&inner_ctx, &mut new_data).await;
 
 				if let Ok(res) = res.as_ref() {
 					inner_ctx.progress(vec![JobReportUpdate::TaskCount(res.steps.len())]);
@@ -507,7 +636,9 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 											);
 										}
 										WorkerCommand::Cancel(when, signal_tx) => {
+											ctx.cancel();
 											init_handle.abort();
+											let _ = init_handle.await;
 											debug!(
 												"Canceling Job at init phase <id='{job_id}', name='{job_name}'> \
 												 took {:?} after running for {:?}",
@@ -518,6 +649,7 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 												"Total paused time {:?} Job <id='{job_id}', name='{job_name}'>",
 												paused_time.elapsed()
 											);
+											cleanup_canceled_job(&*stateful_job, &ctx, None, None).await;
 											return Err(JobError::Canceled(signal_tx));
 										}
 										WorkerCommand::Pause(_) => {
@@ -547,13 +679,16 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 								);
 							}
 							WorkerCommand::Cancel(when, signal_tx) => {
+								ctx.cancel();
 								init_handle.abort();
+								let _ = init_handle.await;
 								debug!(
 									"Canceling Job at init phase <id='{job_id}', name='{job_name}'> took {:?} \
 									 after running for {:?}",
 									when.elapsed(),
 									init_time.elapsed()
 								);
+								cleanup_canceled_job(&*stateful_job, &ctx, None, None).await;
 								return Err(JobError::Canceled(signal_tx));
 							}
 						}
@@ -577,7 +712,19 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 								job_should_run = false;
 								info!("{e}");
 							}
-							Err(other) => return Err(other),
+							Err(other) => {
+								// Giving back the state, so the job can be retried
+								self.state = Some(JobState {
+									init: Arc::try_unwrap(stateful_job)
+										.expect("init already ran, no more refs"),
+									data: None,
+									steps,
+									step_number,
+									run_metadata,
+								});
+
+								return Err(other);
+							}
 						}
 
 						break new_data;
@@ -589,6 +736,7 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		// Run the job until it's done or we get a command
 		let data = if let Some(working_data) = working_data {
 			let working_data_arc = Arc::new(working_data);
+			let mut last_checkpoint = Instant::now();
 
 			// Job run phase
 			while job_should_run && !steps.is_empty() {
@@ -690,8 +838,8 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 												);
 											}
 											WorkerCommand::Cancel(when, signal_tx) => {
-												job_step_handle.abort();
-												let _ = job_step_handle.await;
+												let step_completed =
+													stop_canceled_step(&ctx, &mut job_step_handle).await;
 												debug!(
 													"Canceling Job <id='{job_id}', name='{job_name}'> \
 													 took {:?} after running for {:?}",
@@ -702,6 +850,13 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 													"Total paused time {:?} Job <id='{job_id}', name='{job_name}'>",
 													paused_time.elapsed(),
 												);
+												cleanup_canceled_job(
+													&*stateful_job,
+													&ctx,
+													Some(&*working_data_arc),
+													(!step_completed).then_some(&*step_arc),
+												)
+												.await;
 												return Err(JobError::Canceled(signal_tx));
 											}
 											WorkerCommand::Pause(_) => {
@@ -752,14 +907,21 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 									);
 								}
 								WorkerCommand::Cancel(when, signal_tx) => {
-									job_step_handle.abort();
-									let _ = job_step_handle.await;
+									let step_completed =
+										stop_canceled_step(&ctx, &mut job_step_handle).await;
 									debug!(
 										"Canceling Job <id='{job_id}', name='{job_name}'> took {:?} \
 										 after running for {:?}",
 										when.elapsed(),
 										job_time.elapsed(),
 									);
+									cleanup_canceled_job(
+										&*stateful_job,
+										&ctx,
+										Some(&*working_data_arc),
+										(!step_completed).then_some(&*step_arc),
+									)
+									.await;
 									return Err(JobError::Canceled(signal_tx));
 								}
 							}
@@ -814,7 +976,27 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 									info!("{e}");
 									break;
 								}
-								Err(e) => return Err(e),
+								Err(e) => {
+									// Giving back the state, so the job can be retried from the failed step
+									steps.push_front(
+										Arc::try_unwrap(step_arc)
+											.expect("step already ran, no more refs"),
+									);
+
+									self.state = Some(JobState {
+										init: Arc::try_unwrap(stateful_job)
+											.expect("step already ran, no more refs"),
+										data: Some(
+											Arc::try_unwrap(working_data_arc)
+												.expect("step already ran, no more refs"),
+										),
+										steps,
+										step_number,
+										run_metadata,
+									});
+
+									return Err(e);
+								}
 							}
 							// remove the step from the queue
 							step_number += 1;
@@ -823,6 +1005,30 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 						}
 					}
 				}
+
+				if job_should_run
+					&& !steps.is_empty()
+					&& last_checkpoint.elapsed() > CHECKPOINT_INTERVAL
+				{
+					match rmp_serde::to_vec_named(&JobStateCheckpoint::<SJob> {
+						init: &*stateful_job,
+						data: Some(&*working_data_arc),
+						steps: &steps,
+						step_number,
+						run_metadata: &run_metadata,
+					}) {
+						Ok(state) => ctx.checkpoint(state),
+						Err(e) => warn!(
+							"Failed to checkpoint Job <id='{job_id}', name='{job_name}'>: {e:#?}"
+						),
+					}
+
+					last_checkpoint = Instant::now();
+				}
+
+				if job_should_run && !steps.is_empty() && ctx.is_throttled() {
+					sleep(throttle::THROTTLED_STEP_DELAY).await;
+				}
 			}
 
 			debug!(
@@ -867,6 +1073,22 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		rmp_serde::to_vec_named(&self.state).map_err(Into::into)
 	}
 
+	fn retry_backoff(&mut self, error: &JobError) -> Option<Duration> {
+		let policy = <SJob as StatefulJob>::RETRY_POLICY;
+
+		// Without its state back, the job can't run again
+		if self.state.is_none() || self.attempt >= policy.max_attempts {
+			return None;
+		}
+
+		let class = error.class()?;
+		policy.retry_on.contains(&class).then(|| {
+			let backoff = policy.backoff(self.attempt);
+			self.attempt += 1;
+			backoff
+		})
+	}
+
 	async fn register_children(&mut self, library: &Library) -> Result<(), JobError> {
 		for next_job in self.next_jobs.iter_mut() {
 			if let Some(next_job_report) = next_job.report_mut() {
@@ -920,3 +1142,36 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		Ok(())
 	}
 }
+
+/// Signals the running step that its job was canceled, giving it some time to stop at a safe
+/// point before aborting it. Returns if the step completed anyway.
+async fn stop_canceled_step<T>(
+	ctx: &WorkerContext,
+	job_step_handle: &mut JoinHandle<Result<T, JobError>>,
+) -> bool {
+	ctx.cancel();
+
+	match timeout(CANCELLATION_GRACE_PERIOD, &mut *job_step_handle).await {
+		Ok(Ok(Ok(_))) => true,
+		Ok(_) => false,
+		Err(_) => {
+			job_step_handle.abort();
+			let _ = job_step_handle.await;
+			false
+		}
+	}
+}
+
+async fn cleanup_canceled_job<SJob: StatefulJob>(
+	stateful_job: &SJob,
+	ctx: &WorkerContext,
+	data: Option<&SJob::Data>,
+	interrupted_step: Option<&SJob::Step>,
+) {
+	if let Err(e) = stateful_job.cleanup(ctx, data, interrupted_step).await {
+		warn!(
+			"Failed to clean up canceled Job <name='{}'>: {e:#?}",
+			SJob::NAME
+		);
+	}
+}
@@ -1,13 +1,17 @@
 use crate::{
 	library::Library,
-	prisma::{job, node},
+	prisma::{job, job_dependency, node},
 	util::db::{chain_optional_iter, maybe_missing, MissingFieldError},
 };
 
-use std::fmt::{Display, Formatter};
+use std::{
+	fmt::{Display, Formatter},
+	path::PathBuf,
+};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
 use tracing::error;
 use uuid::Uuid;
@@ -19,6 +23,32 @@ pub enum JobReportUpdate {
 	TaskCount(usize),
 	CompletedTaskCount(usize),
 	Message(String),
+	/// Progress of the file currently being processed by the job, `None` once it's done
+	FileProgress(Option<FileProgress>),
+}
+
+/// Bytes processed of a single file, for jobs that take a while on each file like copies
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Type, Clone)]
+pub struct FileProgress {
+	pub path: PathBuf,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub bytes_done: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub bytes_total: u64,
+}
+
+impl FileProgress {
+	/// Fraction of the file that was already processed, between 0 and 1
+	pub fn fraction(&self) -> f64 {
+		if self.bytes_total == 0 {
+			1.0
+		} else {
+			(self.bytes_done as f64 / self.bytes_total as f64).min(1.0)
+		}
+	}
 }
 
 job::select!(job_without_data {
@@ -52,12 +82,16 @@ pub struct JobReport {
 	pub completed_at: Option<DateTime<Utc>>,
 
 	pub parent_id: Option<Uuid>,
+	/// Jobs that must complete before this one can run
+	pub dependencies: Vec<Uuid>,
 
 	pub status: JobStatus,
 	pub task_count: i32,
 	pub completed_task_count: i32,
 
 	pub message: String,
+	/// Only kept in memory while the job is running
+	pub current_file: Option<FileProgress>,
 	pub estimated_completion: DateTime<Utc>,
 }
 
@@ -98,11 +132,14 @@ impl TryFrom<job::Data> for JobReport {
 			parent_id: data
 				.parent_id
 				.map(|id| Uuid::from_slice(&id).expect("corrupted database")),
+			// Dependencies live on their own table, see `JobReport::load_dependencies`
+			dependencies: vec![],
 			status: JobStatus::try_from(maybe_missing(data.status, "job.status")?)
 				.expect("corrupted database"),
 			task_count: data.task_count.unwrap_or(0),
 			completed_task_count: data.completed_task_count.unwrap_or(0),
 			message: String::new(),
+			current_file: None,
 			estimated_completion: data
 				.date_estimated_completion
 				.map_or(Utc::now(), DateTime::into),
@@ -139,12 +176,15 @@ impl TryFrom<job_without_data::Data> for JobReport {
 			parent_id: data
 				.parent_id
 				.map(|id| Uuid::from_slice(&id).expect("corrupted database")),
+			// Dependencies live on their own table, see `JobReport::load_dependencies`
+			dependencies: vec![],
 			status: JobStatus::try_from(maybe_missing(data.status, "job.status")?)
 				.expect("corrupted database"),
 			task_count: data.task_count.unwrap_or(0),
 			completed_task_count: data.completed_task_count.unwrap_or(0),
 
 			message: String::new(),
+			current_file: None,
 			estimated_completion: data
 				.date_estimated_completion
 				.map_or(Utc::now(), DateTime::into),
@@ -168,8 +208,10 @@ impl JobReport {
 			data: None,
 			metadata: None,
 			parent_id: None,
+			dependencies: vec![],
 			completed_task_count: 0,
 			message: String::new(),
+			current_file: None,
 			estimated_completion: Utc::now(),
 		}
 	}
@@ -223,12 +265,50 @@ impl JobReport {
 			.exec()
 			.await?;
 
+		if !self.dependencies.is_empty() {
+			library
+				.db
+				.job_dependency()
+				.create_many(
+					self.dependencies
+						.iter()
+						.map(|dependency_id| {
+							job_dependency::create_unchecked(
+								self.id.as_bytes().to_vec(),
+								dependency_id.as_bytes().to_vec(),
+								vec![],
+							)
+						})
+						.collect(),
+				)
+				.exec()
+				.await?;
+		}
+
 		// Only setting created_at after we successfully created the job in DB
 		self.created_at = Some(now);
 
 		Ok(())
 	}
 
+	pub async fn load_dependencies(&mut self, library: &Library) -> Result<(), JobError> {
+		self.dependencies = library
+			.db
+			.job_dependency()
+			.find_many(vec![job_dependency::job_id::equals(
+				self.id.as_bytes().to_vec(),
+			)])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|dependency| {
+				Uuid::from_slice(&dependency.dependency_id).expect("corrupted database")
+			})
+			.collect();
+
+		Ok(())
+	}
+
 	pub async fn update(&mut self, library: &Library) -> Result<(), JobError> {
 		library
 			.db
@@ -302,6 +382,7 @@ pub struct JobReportBuilder {
 	pub action: Option<String>,
 	pub metadata: Option<serde_json::Value>,
 	pub parent_id: Option<Uuid>,
+	pub dependencies: Vec<Uuid>,
 }
 
 impl JobReportBuilder {
@@ -320,8 +401,10 @@ impl JobReportBuilder {
 			data: None,
 			metadata: self.metadata,
 			parent_id: self.parent_id,
+			dependencies: self.dependencies,
 			completed_task_count: 0,
 			message: String::new(),
+			current_file: None,
 			estimated_completion: Utc::now(),
 		}
 	}
@@ -333,6 +416,7 @@ impl JobReportBuilder {
 			action: None,
 			metadata: None,
 			parent_id: None,
+			dependencies: vec![],
 		}
 	}
 
@@ -350,4 +434,9 @@ impl JobReportBuilder {
 		self.parent_id = Some(parent_id);
 		self
 	}
+
+	pub fn with_dependency(mut self, job_id: Uuid) -> Self {
+		self.dependencies.push(job_id);
+		self
+	}
 }
@@ -0,0 +1,141 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	prisma::{job, SortOrder},
+};
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+use prisma_client_rust::{or, QueryError};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error};
+
+use super::JobStatus;
+
+/// How often finished job reports are checked against the retention policy
+const RETENTION_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long finished job reports are kept around before being deleted.
+/// Running, queued and paused jobs are never touched.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRetentionPolicy {
+	/// Deletes finished jobs that completed more than this many days ago
+	pub max_age_days: Option<u32>,
+	/// Keeps only the most recent finished jobs of each job type
+	pub max_per_type: Option<u32>,
+}
+
+impl Default for JobRetentionPolicy {
+	fn default() -> Self {
+		Self {
+			max_age_days: Some(30),
+			max_per_type: Some(100),
+		}
+	}
+}
+
+job::select!(job_retention_data { id name });
+
+/// Statuses of the job reports that are subject to retention
+pub(crate) fn retained_statuses() -> job::WhereParam {
+	or![
+		job::status::equals(Some(JobStatus::Canceled as i32)),
+		job::status::equals(Some(JobStatus::Failed as i32)),
+		job::status::equals(Some(JobStatus::Completed as i32)),
+		job::status::equals(Some(JobStatus::CompletedWithErrors as i32)),
+	]
+}
+
+/// Deletes every finished job report of the library that falls out of the retention policy,
+/// returning how many were deleted
+pub async fn cleanup_job_history(
+	library: &Library,
+	policy: JobRetentionPolicy,
+) -> Result<i64, QueryError> {
+	let mut deleted = 0;
+
+	if let Some(max_age_days) = policy.max_age_days {
+		let cutoff = Utc::now() - chrono::Duration::days(max_age_days as i64);
+
+		deleted += library
+			.db
+			.job()
+			.delete_many(vec![
+				retained_statuses(),
+				job::date_completed::lt(cutoff.into()),
+			])
+			.exec()
+			.await?;
+	}
+
+	if let Some(max_per_type) = policy.max_per_type {
+		let mut kept_per_type = HashMap::<String, u32>::new();
+
+		let expired_ids = library
+			.db
+			.job()
+			.find_many(vec![retained_statuses()])
+			.order_by(job::date_created::order(SortOrder::Desc))
+			.select(job_retention_data::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|job| {
+				let kept = kept_per_type
+					.entry(job.name.unwrap_or_default())
+					.or_default();
+				if *kept < max_per_type {
+					*kept += 1;
+					None
+				} else {
+					Some(job.id)
+				}
+			})
+			.collect::<Vec<_>>();
+
+		if !expired_ids.is_empty() {
+			deleted += library
+				.db
+				.job()
+				.delete_many(vec![job::id::in_vec(expired_ids)])
+				.exec()
+				.await?;
+		}
+	}
+
+	if deleted > 0 {
+		invalidate_query!(library, "jobs.reports");
+		invalidate_query!(library, "jobs.history");
+	}
+
+	Ok(deleted)
+}
+
+/// Spawns a loop that periodically applies the node's job retention policy to the library
+pub fn spawn_retention_cleanup(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(RETENTION_CLEANUP_INTERVAL);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			tick.tick().await;
+
+			let policy = library.config().get().await.job_retention;
+
+			match cleanup_job_history(&library, policy).await {
+				Ok(deleted) => debug!(
+					"Deleted {deleted} job reports from library <id='{}'> history",
+					library.id
+				),
+				Err(e) => error!(
+					"Failed to clean up job history of library <id='{}'>: {e:#?}",
+					library.id
+				),
+			}
+		}
+	});
+}
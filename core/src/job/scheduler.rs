@@ -0,0 +1,286 @@
+use crate::{
+	library::{DatabaseMaintenanceJobInit, Library, LibraryBackupJobInit},
+	location::{find_location, location_with_indexer_rules, scan_location, LocationError},
+	object::{
+		album::rematerialize_smart_albums, preview::thumbnailer_job::ThumbnailerJobInit,
+		validation::validator_job::ObjectValidatorJobInit,
+	},
+	prisma::{location, scheduled_job},
+	util::db::{maybe_missing, MissingFieldError},
+};
+
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error, info};
+
+use super::{Job, JobManagerError};
+
+/// How often the scheduler looks for scheduled jobs that are due
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+
+/// Maintenance jobs that can be scheduled to run periodically
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum ScheduledJobKind {
+	/// Full rescan of a location, indexing, identifying and generating thumbnails
+	Rescan {
+		location_id: location::id::Type,
+	},
+	/// Checksum every file of a location, to detect silent corruption
+	Scrub {
+		location_id: location::id::Type,
+	},
+	/// Generates missing thumbnails of a location
+	Thumbnails {
+		location_id: location::id::Type,
+	},
+	RematerializeSmartAlbums,
+	/// Removes objects that don't have any file path anymore
+	RemoveOrphanObjects,
+	/// Backs up the library to the target of its backup settings
+	Backup,
+	/// Checks, reindexes or vacuums the library database
+	DatabaseMaintenance {
+		integrity_check: bool,
+		reindex: bool,
+		vacuum: bool,
+	},
+}
+
+#[derive(Error, Debug)]
+pub enum SchedulerError {
+	#[error("scheduled job not found <id='{0}'>")]
+	IdNotFound(scheduled_job::id::Type),
+	#[error("invalid cron expression '{expression}': {source}")]
+	InvalidCron {
+		expression: String,
+		source: cron::error::Error,
+	},
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to (de)serialize scheduled job kind: {0}")]
+	Kind(#[from] serde_json::Error),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	JobManager(#[from] JobManagerError),
+	#[error("missing-field: {0}")]
+	MissingField(#[from] MissingFieldError),
+}
+
+impl From<SchedulerError> for rspc::Error {
+	fn from(err: SchedulerError) -> Self {
+		match err {
+			SchedulerError::IdNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			SchedulerError::InvalidCron { .. } => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			SchedulerError::Location(err) => err.into(),
+			SchedulerError::JobManager(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+impl ScheduledJobKind {
+	pub async fn spawn(&self, library: &Library) -> Result<(), SchedulerError> {
+		match *self {
+			Self::Rescan { location_id } => scan_location(
+				library,
+				find_location(library, location_id)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?,
+			)
+			.await
+			.map_err(Into::into),
+			Self::Scrub { location_id } => Job::new(ObjectValidatorJobInit {
+				location: find_location(library, location_id)
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?,
+				sub_path: None,
+				verify: true,
+			})
+			.spawn(library)
+			.await
+			.map_err(Into::into),
+			Self::Thumbnails { location_id } => Job::new(ThumbnailerJobInit {
+				location: find_location(library, location_id)
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?,
+				sub_path: None,
+			})
+			.spawn(library)
+			.await
+			.map_err(Into::into),
+			Self::RematerializeSmartAlbums => rematerialize_smart_albums(library, None)
+				.await
+				.map_err(Into::into),
+			Self::RemoveOrphanObjects => {
+				library.orphan_remover.invoke().await;
+				Ok(())
+			}
+			Self::Backup => Job::new(LibraryBackupJobInit {})
+				.spawn(library)
+				.await
+				.map_err(Into::into),
+			Self::DatabaseMaintenance {
+				integrity_check,
+				reindex,
+				vacuum,
+			} => Job::new(DatabaseMaintenanceJobInit {
+				integrity_check,
+				reindex,
+				vacuum,
+			})
+			.spawn(library)
+			.await
+			.map_err(Into::into),
+		}
+	}
+}
+
+/// Parses a cron expression, returning the next time it fires after `after`
+pub fn next_run(
+	expression: &str,
+	after: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, SchedulerError> {
+	Schedule::from_str(expression)
+		.map(|schedule| schedule.after(&after).next())
+		.map_err(|source| SchedulerError::InvalidCron {
+			expression: expression.to_string(),
+			source,
+		})
+}
+
+/// Spawns the scheduler loop of a library, which spawns every enabled scheduled job once it's due.
+/// Jobs that were due while the app was closed run once on startup.
+pub fn spawn_scheduler(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(SCHEDULER_TICK);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			tick.tick().await;
+
+			if let Err(e) = run_due_jobs(&library).await {
+				error!(
+					"Failed to run scheduled jobs for library <id='{}'>: {e:#?}",
+					library.id
+				);
+			}
+		}
+	});
+}
+
+async fn run_due_jobs(library: &Library) -> Result<(), SchedulerError> {
+	let now = Utc::now();
+
+	let due_jobs = library
+		.db
+		.scheduled_job()
+		.find_many(vec![
+			scheduled_job::enabled::equals(Some(true)),
+			scheduled_job::date_next_run::lte(now.into()),
+		])
+		.exec()
+		.await?;
+
+	for scheduled_job in due_jobs {
+		// Scheduling the next run before spawning, so a failing job doesn't get retried every tick
+		let next = maybe_missing(scheduled_job.cron.as_deref(), "scheduled_job.cron")
+			.map_err(Into::into)
+			.and_then(|expression| next_run(expression, now));
+
+		library
+			.db
+			.scheduled_job()
+			.update(
+				scheduled_job::id::equals(scheduled_job.id),
+				vec![
+					scheduled_job::date_last_run::set(Some(now.into())),
+					scheduled_job::date_next_run::set(
+						next.as_ref().ok().copied().flatten().map(Into::into),
+					),
+				],
+			)
+			.exec()
+			.await?;
+
+		if let Err(e) = next {
+			error!(
+				"Scheduled job <id='{}'> won't run again: {e}",
+				scheduled_job.id
+			);
+		}
+
+		let kind = match maybe_missing(scheduled_job.kind.as_deref(), "scheduled_job.kind")
+			.map_err(SchedulerError::from)
+			.and_then(|kind| serde_json::from_slice::<ScheduledJobKind>(kind).map_err(Into::into))
+		{
+			Ok(kind) => kind,
+			Err(e) => {
+				error!("Invalid scheduled job <id='{}'>: {e}", scheduled_job.id);
+				continue;
+			}
+		};
+
+		info!(
+			"Running scheduled job <id='{}', kind='{kind:?}'>",
+			scheduled_job.id
+		);
+
+		match kind.spawn(library).await {
+			Ok(()) => {}
+			Err(SchedulerError::JobManager(JobManagerError::AlreadyRunningJob { .. })) => {
+				debug!(
+					"Scheduled job <id='{}'> is still running from a previous run",
+					scheduled_job.id
+				);
+			}
+			Err(e) => error!(
+				"Failed to spawn scheduled job <id='{}'>: {e}",
+				scheduled_job.id
+			),
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use chrono::TimeZone;
+
+	#[test]
+	fn next_run_of_cron_expressions() {
+		let after = Utc.with_ymd_and_hms(2023, 6, 27, 12, 30, 0).unwrap();
+
+		assert_eq!(
+			next_run("0 0 3 * * *", after).unwrap(),
+			Some(Utc.with_ymd_and_hms(2023, 6, 28, 3, 0, 0).unwrap())
+		);
+		assert_eq!(
+			next_run("0 */15 * * * *", after).unwrap(),
+			Some(Utc.with_ymd_and_hms(2023, 6, 27, 12, 45, 0).unwrap())
+		);
+		assert!(matches!(
+			next_run("every day", after),
+			Err(SchedulerError::InvalidCron { .. })
+		));
+	}
+}
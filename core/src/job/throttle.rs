@@ -0,0 +1,299 @@
+use crate::node::NodeConfigManager;
+
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use sysinfo::{ComponentExt, System, SystemExt};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error};
+
+use super::{JobManager, JobPriority};
+
+/// How often the power source and temperatures are checked
+const POWER_MONITOR_TICK: Duration = Duration::from_secs(30);
+
+/// Delay between each step of a slowed down job
+pub(super) const THROTTLED_STEP_DELAY: Duration = Duration::from_millis(500);
+
+/// Battery charge, in percent, under which we consider the device to be in low power mode
+const LOW_BATTERY_PERCENT: u8 = 20;
+
+/// Temperature considered as thermal pressure for sensors that don't report a critical temperature
+const THERMAL_PRESSURE_CELSIUS: f32 = 90.0;
+
+/// How close to its critical temperature a sensor must be to be considered under thermal pressure
+const THERMAL_PRESSURE_MARGIN_CELSIUS: f32 = 10.0;
+
+/// What to do with the jobs of a priority class under some power condition
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum ThrottleAction {
+	Run,
+	/// Adds a delay between each step of the job
+	Slow,
+	Pause,
+}
+
+/// Actions taken on jobs of a priority class for each power condition.
+/// When multiple conditions apply, the most restrictive action wins.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerPolicy {
+	pub on_battery: ThrottleAction,
+	pub low_power: ThrottleAction,
+	pub thermal_pressure: ThrottleAction,
+}
+
+impl PowerPolicy {
+	const UNRESTRICTED: Self = Self {
+		on_battery: ThrottleAction::Run,
+		low_power: ThrottleAction::Run,
+		thermal_pressure: ThrottleAction::Run,
+	};
+
+	pub fn action(&self, state: &PowerState) -> ThrottleAction {
+		[
+			(state.on_battery, self.on_battery),
+			(state.low_power, self.low_power),
+			(state.thermal_pressure, self.thermal_pressure),
+		]
+		.into_iter()
+		.filter_map(|(active, action)| active.then_some(action))
+		.max()
+		.unwrap_or(ThrottleAction::Run)
+	}
+}
+
+/// Power policies of each job priority class
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct JobThrottlePolicy {
+	pub interactive: PowerPolicy,
+	pub normal: PowerPolicy,
+	pub background: PowerPolicy,
+}
+
+impl Default for JobThrottlePolicy {
+	fn default() -> Self {
+		Self {
+			// Jobs the user is waiting on are never throttled
+			interactive: PowerPolicy::UNRESTRICTED,
+			normal: PowerPolicy {
+				on_battery: ThrottleAction::Run,
+				low_power: ThrottleAction::Slow,
+				thermal_pressure: ThrottleAction::Slow,
+			},
+			background: PowerPolicy {
+				on_battery: ThrottleAction::Slow,
+				low_power: ThrottleAction::Pause,
+				thermal_pressure: ThrottleAction::Pause,
+			},
+		}
+	}
+}
+
+impl JobThrottlePolicy {
+	pub fn get(&self, priority: JobPriority) -> &PowerPolicy {
+		match priority {
+			JobPriority::Interactive => &self.interactive,
+			JobPriority::Normal => &self.normal,
+			JobPriority::Background => &self.background,
+		}
+	}
+}
+
+/// Power conditions of the device running the node
+#[derive(Debug, Clone, Copy, Default, Serialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerState {
+	pub on_battery: bool,
+	/// Low power mode is enabled or the battery is almost empty
+	pub low_power: bool,
+	pub thermal_pressure: bool,
+}
+
+impl PowerState {
+	/// Detects the current power conditions. This is blocking, as it may spawn processes.
+	pub fn detect() -> Self {
+		let battery = battery_status();
+
+		Self {
+			on_battery: battery.map_or(false, |(discharging, _)| discharging),
+			low_power: low_power_mode()
+				|| battery.map_or(false, |(discharging, percent)| {
+					discharging && percent.map_or(false, |percent| percent < LOW_BATTERY_PERCENT)
+				}),
+			thermal_pressure: thermal_pressure(),
+		}
+	}
+}
+
+/// Returns if the battery is discharging and its charge percent, `None` if there is no battery
+#[cfg(target_os = "linux")]
+fn battery_status() -> Option<(bool, Option<u8>)> {
+	let mut battery = None;
+
+	for entry in std::fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+		let path = entry.path();
+		let read = |file: &str| {
+			std::fs::read_to_string(path.join(file))
+				.map(|content| content.trim().to_string())
+				.ok()
+		};
+
+		match read("type").as_deref() {
+			// An external power supply being online means we aren't running on battery
+			Some("Mains") if read("online").as_deref() == Some("1") => return Some((false, None)),
+			Some("Battery") => {
+				battery = Some((
+					read("status").as_deref() == Some("Discharging"),
+					read("capacity").and_then(|capacity| capacity.parse().ok()),
+				));
+			}
+			_ => {}
+		}
+	}
+
+	battery
+}
+
+#[cfg(target_os = "macos")]
+fn battery_status() -> Option<(bool, Option<u8>)> {
+	let output = std::process::Command::new("pmset")
+		.args(["-g", "batt"])
+		.output()
+		.ok()?;
+	let output = String::from_utf8(output.stdout).ok()?;
+
+	// eg: "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1234)	85%; discharging;"
+	let percent = output
+		.split_once('\t')
+		.and_then(|(_, status)| status.split_once('%'))
+		.and_then(|(percent, _)| percent.trim().parse().ok());
+
+	percent.map(|percent| (output.contains("'Battery Power'"), Some(percent)))
+}
+
+#[cfg(target_os = "windows")]
+fn battery_status() -> Option<(bool, Option<u8>)> {
+	let output = std::process::Command::new("cmd")
+		.args([
+			"/C",
+			"wmic path Win32_Battery get BatteryStatus,EstimatedChargeRemaining",
+		])
+		.output()
+		.ok()?;
+	let output = String::from_utf8(output.stdout).ok()?;
+
+	// First line is the header, eg: "BatteryStatus  EstimatedChargeRemaining\r\r\n1  85"
+	let mut values = output.lines().nth(1)?.split_whitespace();
+	let status = values.next()?.parse::<u8>().ok()?;
+
+	// Status 1 is "Other", which is the status reported when discharging
+	Some((
+		status == 1,
+		values.next().and_then(|percent| percent.parse().ok()),
+	))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn battery_status() -> Option<(bool, Option<u8>)> {
+	None
+}
+
+#[cfg(target_os = "linux")]
+fn low_power_mode() -> bool {
+	std::fs::read_to_string("/sys/firmware/acpi/platform_profile")
+		.map_or(false, |profile| profile.trim() == "low-power")
+}
+
+#[cfg(target_os = "macos")]
+fn low_power_mode() -> bool {
+	std::process::Command::new("pmset")
+		.arg("-g")
+		.output()
+		.ok()
+		.and_then(|output| String::from_utf8(output.stdout).ok())
+		.map_or(false, |output| {
+			output.lines().any(|line| {
+				let mut words = line.split_whitespace();
+				words.next() == Some("lowpowermode") && words.next() == Some("1")
+			})
+		})
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn low_power_mode() -> bool {
+	false
+}
+
+fn thermal_pressure() -> bool {
+	let mut system = System::new();
+	system.refresh_components_list();
+
+	system.components().iter().any(|component| {
+		let temperature = component.temperature();
+		match component.critical() {
+			Some(critical) if critical > 0.0 => {
+				temperature >= critical - THERMAL_PRESSURE_MARGIN_CELSIUS
+			}
+			_ => temperature >= THERMAL_PRESSURE_CELSIUS,
+		}
+	})
+}
+
+/// Spawns a loop that watches the power conditions of the device, throttling jobs
+/// following the node's policy when they change
+pub fn spawn_power_monitor(job_manager: Arc<JobManager>, config: Arc<NodeConfigManager>) {
+	tokio::spawn(async move {
+		let mut tick = interval(POWER_MONITOR_TICK);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			tick.tick().await;
+
+			let state = match tokio::task::spawn_blocking(PowerState::detect).await {
+				Ok(state) => state,
+				Err(e) => {
+					error!("Failed to detect power state: {e:#?}");
+					continue;
+				}
+			};
+
+			if state != job_manager.power_state().await {
+				debug!("Power state changed: {state:?}");
+			}
+
+			job_manager
+				.set_throttle(state, config.get().await.job_throttle_policy)
+				.await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn most_restrictive_action_wins() {
+		let policy = JobThrottlePolicy::default().background;
+
+		assert_eq!(policy.action(&PowerState::default()), ThrottleAction::Run);
+		assert_eq!(
+			policy.action(&PowerState {
+				on_battery: true,
+				..Default::default()
+			}),
+			ThrottleAction::Slow
+		);
+		assert_eq!(
+			policy.action(&PowerState {
+				on_battery: true,
+				low_power: true,
+				thermal_pressure: false,
+			}),
+			ThrottleAction::Pause
+		);
+	}
+}
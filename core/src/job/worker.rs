@@ -1,4 +1,8 @@
-use crate::{api::CoreEvent, invalidate_query, library::Library};
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	library::{Library, NotificationKind, WebhookEvent},
+};
 
 use std::{
 	fmt,
@@ -22,22 +26,44 @@ use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
 use super::{
-	DynJob, JobError, JobManager, JobReport, JobReportUpdate, JobRunErrors, JobRunOutput, JobStatus,
+	DynJob, FileProgress, JobError, JobManager, JobPriority, JobReport, JobReportUpdate,
+	JobRunErrors, JobRunOutput, JobStatus,
 };
 
 #[derive(Debug, Clone, Serialize, Type)]
 pub struct JobProgressEvent {
+	pub library_id: Uuid,
 	pub id: Uuid,
 	pub task_count: i32,
 	pub completed_task_count: i32,
 	pub message: String,
+	pub current_file: Option<FileProgress>,
 	pub estimated_completion: DateTime<Utc>,
 }
 
+/// A job started, was paused, queued for a retry or is over
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct JobStatusEvent {
+	pub library_id: Uuid,
+	pub id: Uuid,
+	pub name: String,
+	pub status: JobStatus,
+}
+
+fn emit_status(library: &Library, report: &JobReport) {
+	library.emit(CoreEvent::JobStatus(JobStatusEvent {
+		library_id: library.id,
+		id: report.id,
+		name: report.name.clone(),
+		status: report.status,
+	}));
+}
+
 // used to update the worker state from inside the worker thread
 #[derive(Debug)]
 pub enum WorkerEvent {
 	Progressed(Vec<JobReportUpdate>),
+	Checkpointed(Vec<u8>),
 	Stop,
 }
 
@@ -53,6 +79,8 @@ pub enum WorkerCommand {
 pub struct WorkerContext {
 	pub library: Library,
 	pub(super) events_tx: mpsc::UnboundedSender<WorkerEvent>,
+	throttled: Arc<AtomicBool>,
+	canceled: AtomicBool,
 }
 
 impl fmt::Debug for WorkerContext {
@@ -84,6 +112,32 @@ impl WorkerContext {
 			})
 			.ok();
 	}
+
+	/// If the job should slow down, due to the power conditions of the device
+	pub fn is_throttled(&self) -> bool {
+		self.throttled.load(Ordering::Relaxed)
+	}
+
+	/// If the job was canceled. Long running steps should check it regularly and stop as soon as
+	/// it's safe, leaving what's needed for [`StatefulJob::cleanup`](super::StatefulJob::cleanup)
+	/// to remove their artifacts
+	pub fn is_canceled(&self) -> bool {
+		self.canceled.load(Ordering::Relaxed)
+	}
+
+	pub(super) fn cancel(&self) {
+		self.canceled.store(true, Ordering::Relaxed);
+	}
+
+	/// Persists the serialized job state, so the job is resumed from here after a restart
+	pub(super) fn checkpoint(&self, state: Vec<u8>) {
+		self.events_tx
+			.send(WorkerEvent::Checkpointed(state))
+			.map_err(|err| {
+				tracing::error!("Error sending worker context checkpoint event: {}", err);
+			})
+			.ok();
+	}
 }
 
 // a worker is a dedicated thread that runs a single job
@@ -93,6 +147,11 @@ pub struct Worker {
 	report_watch_tx: Arc<watch::Sender<JobReport>>,
 	report_watch_rx: watch::Receiver<JobReport>,
 	paused: AtomicBool,
+	throttled: Arc<AtomicBool>,
+	priority: JobPriority,
+	name: &'static str,
+	concurrency_scope: Option<String>,
+	library_id: Uuid,
 }
 
 impl Worker {
@@ -106,6 +165,9 @@ impl Worker {
 		let (commands_tx, commands_rx) = mpsc::channel(8);
 
 		let job_hash = job.hash();
+		let priority = job.priority();
+		let name = job.name();
+		let concurrency_scope = job.concurrency_scope();
 
 		let start_time = Utc::now();
 
@@ -125,10 +187,14 @@ impl Worker {
 		job.register_children(&library).await?;
 
 		invalidate_queries(&library);
+		emit_status(&library, &report);
 
 		let (report_watch_tx, report_watch_rx) = watch::channel(report.clone());
 		let report_watch_tx = Arc::new(report_watch_tx);
 
+		let throttled = Arc::new(AtomicBool::new(false));
+		let library_id = library.id;
+
 		// spawn task to handle running the job
 		tokio::spawn(Self::do_work(
 			id,
@@ -141,6 +207,7 @@ impl Worker {
 			Arc::clone(&report_watch_tx),
 			start_time,
 			commands_rx,
+			Arc::clone(&throttled),
 			library,
 		));
 
@@ -149,6 +216,11 @@ impl Worker {
 			report_watch_tx,
 			report_watch_rx,
 			paused: AtomicBool::new(false),
+			throttled,
+			priority,
+			name,
+			concurrency_scope,
+			library_id,
 		})
 	}
 
@@ -218,6 +290,26 @@ impl Worker {
 		self.paused.load(Ordering::Relaxed)
 	}
 
+	pub fn set_throttled(&self, throttled: bool) {
+		self.throttled.store(throttled, Ordering::Relaxed);
+	}
+
+	pub fn priority(&self) -> JobPriority {
+		self.priority
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	pub fn concurrency_scope(&self) -> Option<&str> {
+		self.concurrency_scope.as_deref()
+	}
+
+	pub fn library_id(&self) -> Uuid {
+		self.library_id
+	}
+
 	fn track_progress(
 		report: &mut JobReport,
 		last_report_watch_update: &mut Instant,
@@ -244,18 +336,28 @@ impl Worker {
 					trace!("job {} message: {}", report.id, message);
 					report.message = message;
 				}
+				JobReportUpdate::FileProgress(file_progress) => {
+					report.current_file = file_progress;
+				}
 			}
 		}
 
 		// Calculate elapsed time
 		let elapsed = Utc::now() - start_time;
 
-		// Calculate remaining time
-		let task_count = report.task_count as usize;
-		let completed_task_count = report.completed_task_count as usize;
-		let remaining_task_count = task_count.saturating_sub(completed_task_count);
-		let remaining_time_per_task = elapsed / (completed_task_count + 1) as i32; // Adding 1 to avoid division by zero
-		let remaining_time = remaining_time_per_task * remaining_task_count as i32;
+		// Calculate remaining time, counting the already processed part of the current file
+		// so the estimate keeps moving while a single big file is being processed
+		let task_count = report.task_count as f64;
+		let completed_task_count = report.completed_task_count as f64
+			+ report
+				.current_file
+				.as_ref()
+				.map_or(0.0, FileProgress::fraction);
+		let remaining_task_count = (task_count - completed_task_count).max(0.0);
+		let remaining_time_per_task =
+			elapsed.num_milliseconds() as f64 / (completed_task_count + 1.0); // Adding 1 to avoid division by zero
+		let remaining_time =
+			chrono::Duration::milliseconds((remaining_time_per_task * remaining_task_count) as i64);
 
 		// Update the report with estimated remaining time
 		report.estimated_completion = Utc::now()
@@ -269,20 +371,41 @@ impl Worker {
 				old.completed_task_count = report.completed_task_count;
 				old.estimated_completion = report.estimated_completion;
 				old.message = report.message.clone();
+				old.current_file = report.current_file.clone();
 			});
 			*last_report_watch_update = Instant::now();
 		}
 
 		// emit a CoreEvent
 		library.emit(CoreEvent::JobProgress(JobProgressEvent {
+			library_id: library.id,
 			id: report.id,
 			task_count: report.task_count,
 			completed_task_count: report.completed_task_count,
 			estimated_completion: report.estimated_completion,
 			message: report.message.clone(),
+			current_file: report.current_file.clone(),
 		}));
 	}
 
+	async fn checkpoint(report: &mut JobReport, state: Vec<u8>, library: &Library) {
+		// A checkpoint arriving after the job was stopped would override its final state
+		if report.status != JobStatus::Running {
+			return;
+		}
+
+		trace!(
+			"Checkpointing Job<id='{}', name='{}'>",
+			report.id,
+			report.name
+		);
+
+		report.data = Some(state);
+		if let Err(e) = report.update(library).await {
+			error!("failed to checkpoint job report: {:#?}", e);
+		}
+	}
+
 	async fn do_work(
 		worker_id: Uuid,
 		JobWorkTable {
@@ -294,6 +417,7 @@ impl Worker {
 		report_watch_tx: Arc<watch::Sender<JobReport>>,
 		start_time: DateTime<Utc>,
 		commands_rx: mpsc::Receiver<WorkerCommand>,
+		throttled: Arc<AtomicBool>,
 		library: Library,
 	) {
 		let (events_tx, mut events_rx) = mpsc::unbounded_channel();
@@ -302,6 +426,8 @@ impl Worker {
 			WorkerContext {
 				library: library.clone(),
 				events_tx,
+				throttled,
+				canceled: AtomicBool::new(false),
 			},
 			commands_rx,
 		);
@@ -327,6 +453,9 @@ impl Worker {
 										&library
 									);
 								}
+								WorkerEvent::Checkpointed(state) => {
+									Self::checkpoint(&mut report, state, &library).await;
+								}
 								WorkerEvent::Stop => {
 									break 'job job_result;
 								},
@@ -348,6 +477,9 @@ impl Worker {
 								&library
 							)
 						}
+						WorkerEvent::Checkpointed(state) => {
+							Self::checkpoint(&mut report, state, &library).await;
+						}
 						WorkerEvent::Stop => {events_ended = true;},
 					}
 				}
@@ -357,9 +489,77 @@ impl Worker {
 		// Need this drop here to sinalize to borrowchecker that we're done with our `&mut job` borrow for `run` method
 		drop(job_future);
 
+		// Transient failures are retried by the job manager, following the job's retry policy
+		if let Err(e) = &job_result {
+			if let Some(backoff) = job.retry_backoff(e) {
+				warn!(
+					"Job<id='{}', name='{}'> failed with error: {e:#?}; retrying in {backoff:?}",
+					report.id, report.name
+				);
+
+				report.status = JobStatus::Queued;
+				report.message = format!("Retrying after error: {e}");
+				// So the retry isn't lost if the app is closed in the meantime
+				match job.serialize_state() {
+					Ok(state) => report.data = Some(state),
+					Err(e) => error!("failed to serialize job state for retry: {:#?}", e),
+				}
+				if let Err(e) = report.update(&library).await {
+					error!("failed to update job report: {:#?}", e);
+				}
+
+				report_watch_tx.send(report.clone()).ok();
+				invalidate_queries(&library);
+				emit_status(&library, &report);
+
+				*job.report_mut() = Some(report);
+
+				manager.retry(&library, worker_id, job, backoff).await;
+				return;
+			}
+		}
+
+		let priority = job.priority();
 		let next_job = Self::process_job_output(job, job_result, &mut report, &library).await;
 
 		report_watch_tx.send(report.clone()).ok();
+		emit_status(&library, &report);
+
+		// Paused jobs will complete later on
+		if matches!(
+			report.status,
+			JobStatus::Completed | JobStatus::CompletedWithErrors | JobStatus::Failed
+		) {
+			if let Some(started_at) = report.started_at {
+				library.node_context.analytics.record_job(
+					&report.name,
+					report.status,
+					(report.completed_at.unwrap_or_else(Utc::now) - started_at)
+						.to_std()
+						.unwrap_or_default(),
+				);
+			}
+
+			library.webhooks.dispatch(WebhookEvent::JobCompleted {
+				job_id: report.id,
+				name: report.name.clone(),
+				status: report.status,
+			});
+
+			// Background jobs and the ones a chain continues with aren't worth notifying about,
+			// unless they failed
+			if report.status == JobStatus::Failed
+				|| (next_job.is_none() && priority != JobPriority::Background)
+			{
+				library
+					.notify(NotificationKind::JobCompleted {
+						job_id: report.id,
+						name: report.name.clone(),
+						status: report.status,
+					})
+					.await;
+			}
+		}
 
 		debug!(
 			"Worker completed Job<id='{}', name='{}'>",
@@ -2,13 +2,27 @@
 
 use crate::{
 	api::{CoreEvent, Router},
-	job::JobManager,
+	job::{throttle::spawn_power_monitor, JobManager},
 	library::LibraryManager,
-	location::{LocationManager, LocationManagerError},
-	node::NodeConfigManager,
+	location::{provider::LocationProviders, LocationManager, LocationManagerError},
+	node::{Analytics, ApiRateLimiter, NodeConfigManager},
 	p2p::P2PManager,
+	plugin::PluginManager,
 };
 
+pub use job::{
+	CurrentStep, Job, JobError, JobInitOutput, JobManagerError, JobPriority, JobReportUpdate,
+	JobResult, JobRunErrors, JobRunMetadata, JobStepOutput, RetryPolicy, StatefulJob,
+	WorkerContext,
+};
+pub use location::{
+	provider::{
+		LocationProvider, ProviderEntry, ProviderError, ProviderEvent, ProviderEvents,
+		ProviderReader,
+	},
+	UploadError, UploadedFile,
+};
+pub use node::{ApiAuthError, ApiToken, ApiTokenScope, NodeExtensions};
 pub use sd_prisma::*;
 
 use std::{
@@ -17,25 +31,35 @@ use std::{
 };
 
 use thiserror::Error;
-use tokio::{fs, sync::broadcast};
+use tokio::{fs, io::AsyncRead, sync::broadcast};
 use tracing::{debug, error, info, warn};
 use tracing_appender::{
 	non_blocking::{NonBlocking, WorkerGuard},
 	rolling::{RollingFileAppender, Rotation},
 };
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use uuid::Uuid;
 
 pub mod api;
 pub mod custom_uri;
+#[cfg(feature = "graphql")]
+pub mod graphql;
 pub(crate) mod job;
 pub mod library;
 pub(crate) mod location;
+#[cfg(all(feature = "fuse", unix))]
+pub mod mount;
 pub(crate) mod node;
 pub(crate) mod object;
 pub(crate) mod p2p;
+pub(crate) mod plugin;
+pub mod remote;
+pub(crate) mod share;
 pub(crate) mod sync;
 pub(crate) mod util;
 pub(crate) mod volume;
+#[cfg(feature = "webdav")]
+pub mod webdav;
 
 #[derive(Clone)]
 pub struct NodeContext {
@@ -43,6 +67,9 @@ pub struct NodeContext {
 	pub job_manager: Arc<JobManager>,
 	pub location_manager: Arc<LocationManager>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
+	pub analytics: Arc<Analytics>,
+	pub plugins: Arc<PluginManager>,
+	pub location_providers: Arc<LocationProviders>,
 }
 
 pub struct Node {
@@ -53,11 +80,23 @@ pub struct Node {
 	job_manager: Arc<JobManager>,
 	p2p: Arc<P2PManager>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
+	api_rate_limiter: ApiRateLimiter,
+	analytics: Arc<Analytics>,
+	plugins: Arc<PluginManager>,
+	location_providers: Arc<LocationProviders>,
 	// peer_request: tokio::sync::Mutex<Option<PeerRequest>>,
 }
 
 impl Node {
 	pub async fn new(data_dir: impl AsRef<Path>) -> Result<(Arc<Node>, Arc<Router>), NodeError> {
+		Self::with_extensions(data_dir, NodeExtensions::default()).await
+	}
+
+	/// Starts a node with what the crate embedding the core adds to it
+	pub async fn with_extensions(
+		data_dir: impl AsRef<Path>,
+		mut extensions: NodeExtensions,
+	) -> Result<(Arc<Node>, Arc<Router>), NodeError> {
 		let data_dir = data_dir.as_ref();
 
 		info!("Starting core with data directory '{}'", data_dir.display());
@@ -74,10 +113,26 @@ impl Node {
 			.map_err(NodeError::FailedToInitializeConfig)?;
 		debug!("Initialised 'NodeConfigManager'...");
 
-		let job_manager = JobManager::new();
+		let job_manager = {
+			let config = config.get().await;
+			JobManager::new(
+				config.job_concurrency_limits,
+				config.job_throttle_policy,
+				extensions.take_jobs()?,
+			)
+		};
+		spawn_power_monitor(job_manager.clone(), config.clone());
 
 		debug!("Initialised 'JobManager'...");
 
+		let analytics = Analytics::new(data_dir, config.get().await.analytics).await;
+
+		let plugins = PluginManager::new(data_dir).await;
+		debug!("Initialised 'PluginManager'...");
+
+		let location_providers = LocationProviders::new(extensions.location_providers)?;
+		debug!("Initialised 'LocationProviders'...");
+
 		let location_manager = LocationManager::new();
 		debug!("Initialised 'LocationManager'...");
 		let library_manager = LibraryManager::new(
@@ -88,12 +143,16 @@ impl Node {
 				location_manager: location_manager.clone(),
 				// p2p: p2p.clone(),
 				event_bus_tx: event_bus.0.clone(),
+				analytics: analytics.clone(),
+				plugins: plugins.clone(),
+				location_providers: location_providers.clone(),
 			},
 		)
 		.await?;
 		debug!("Initialised 'LibraryManager'...");
 		let p2p = P2PManager::new(config.clone(), library_manager.clone()).await?;
 		debug!("Initialised 'P2PManager'...");
+		share::spawn_share_relay_client(config.clone(), library_manager.clone());
 
 		#[cfg(debug_assertions)]
 		if let Some(init_data) = init_data {
@@ -111,6 +170,10 @@ impl Node {
 			job_manager,
 			p2p,
 			event_bus,
+			api_rate_limiter: ApiRateLimiter::default(),
+			analytics,
+			plugins,
+			location_providers,
 			// peer_request: tokio::sync::Mutex::new(None),
 		};
 
@@ -196,10 +259,70 @@ impl Node {
 		guard
 	}
 
+	/// Finds the token issued to a client with this secret, when the node runs as a daemon, and
+	/// counts the request against its rate limit
+	pub async fn authorize_api_request(&self, secret: &str) -> Result<ApiToken, ApiAuthError> {
+		let token = self
+			.config
+			.get()
+			.await
+			.api_tokens
+			.into_iter()
+			.find(|token| token.matches(secret))
+			.ok_or(ApiAuthError::InvalidToken)?;
+
+		self.api_rate_limiter.check(&token)?;
+
+		Ok(token)
+	}
+
+	pub async fn has_api_tokens(&self) -> bool {
+		!self.config.get().await.api_tokens.is_empty()
+	}
+
+	/// Issues a token for a new client, returning it along with its secret, which isn't stored
+	pub async fn issue_api_token(
+		&self,
+		name: String,
+		scopes: Vec<ApiTokenScope>,
+		rate_limit: Option<u32>,
+	) -> Result<(ApiToken, String), NodeError> {
+		let (token, secret) = ApiToken::issue(name, scopes, rate_limit);
+
+		self.config
+			.write(|mut config| config.api_tokens.push(token.clone()))
+			.await
+			.map_err(NodeError::FailedToWriteConfig)?;
+
+		Ok((token, secret))
+	}
+
+	/// Writes a file uploaded by a remote client into a location, indexing and identifying it
+	/// before returning
+	pub async fn upload_file(
+		&self,
+		library_id: Uuid,
+		location_id: prisma::location::id::Type,
+		sub_path: impl AsRef<Path>,
+		name: &str,
+		contents: impl AsyncRead + Unpin,
+	) -> Result<UploadedFile, UploadError> {
+		let library = self
+			.library_manager
+			.get_library(library_id)
+			.await
+			.ok_or(UploadError::LibraryNotFound(library_id))?;
+
+		location::upload_file(&library, location_id, sub_path, name, contents).await
+	}
+
 	pub async fn shutdown(&self) {
 		info!("Spacedrive shutting down...");
 		self.job_manager.shutdown().await;
 		self.p2p.shutdown().await;
+		if let Err(e) = self.analytics.save().await {
+			error!("Failed to save analytics: {e:#?}");
+		}
 		info!("Spacedrive Core shutdown successful!");
 	}
 
@@ -224,12 +347,18 @@ impl Node {
 pub enum NodeError {
 	#[error("NodeError::FailedToInitializeConfig({0})")]
 	FailedToInitializeConfig(util::migrator::MigratorError),
+	#[error("failed to write node config: {0}")]
+	FailedToWriteConfig(util::migrator::MigratorError),
 	#[error("failed to initialize library manager: {0}")]
 	FailedToInitializeLibraryManager(#[from] library::LibraryManagerError),
 	#[error("failed to initialize location manager: {0}")]
 	LocationManager(#[from] LocationManagerError),
 	#[error("failed to initialize p2p manager: {0}")]
 	P2PManager(#[from] sd_p2p::ManagerError),
+	#[error("failed to register job types: {0}")]
+	Extension(#[from] node::ExtensionError),
+	#[error("failed to register location providers: {0}")]
+	LocationProviders(#[from] ProviderError),
 	#[error("invalid platform integer: {0}")]
 	InvalidPlatformInt(u8),
 	#[cfg(debug_assertions)]
@@ -0,0 +1,475 @@
+//! Append-only log of the significant actions done on a library, like file operations, tag changes,
+//! device pairings and setting changes, along with the node that did them. Entries are synced, so
+//! every node of a shared library sees what the others did.
+
+use crate::{
+	invalidate_query,
+	object::export::ListingExportFormat,
+	prisma::{audit_log_entry, file_path, location, node, object, tag, SortOrder},
+	sync,
+	util::error::FileIOError,
+};
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncWriteExt, BufWriter},
+};
+use tracing::error;
+use uuid::Uuid;
+
+use super::Library;
+
+/// How many entries are fetched from the database at a time while exporting
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AuditAction {
+	FilesDeleted {
+		location_id: location::id::Type,
+		file_path_ids: Vec<file_path::id::Type>,
+	},
+	FilesErased {
+		location_id: location::id::Type,
+		file_path_ids: Vec<file_path::id::Type>,
+	},
+	FilesCopied {
+		source_location_id: location::id::Type,
+		target_location_id: location::id::Type,
+		file_path_ids: Vec<file_path::id::Type>,
+	},
+	FilesMoved {
+		source_location_id: location::id::Type,
+		target_location_id: location::id::Type,
+		file_path_ids: Vec<file_path::id::Type>,
+	},
+	FilesRenamed {
+		location_id: location::id::Type,
+		file_path_ids: Vec<file_path::id::Type>,
+	},
+	TagCreated {
+		tag_id: tag::id::Type,
+		name: String,
+	},
+	TagUpdated {
+		tag_id: tag::id::Type,
+		name: Option<String>,
+	},
+	TagDeleted {
+		tag_id: tag::id::Type,
+	},
+	TagAssigned {
+		tag_id: tag::id::Type,
+		object_ids: Vec<object::id::Type>,
+		unassign: bool,
+	},
+	DevicePaired {
+		node_pub_id: Uuid,
+		name: String,
+	},
+	/// Names of the library settings that changed
+	SettingsChanged {
+		settings: Vec<String>,
+	},
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditActionKind {
+	FilesDeleted,
+	FilesErased,
+	FilesCopied,
+	FilesMoved,
+	FilesRenamed,
+	TagCreated,
+	TagUpdated,
+	TagDeleted,
+	TagAssigned,
+	DevicePaired,
+	SettingsChanged,
+}
+
+impl AuditAction {
+	pub fn kind(&self) -> AuditActionKind {
+		match self {
+			Self::FilesDeleted { .. } => AuditActionKind::FilesDeleted,
+			Self::FilesErased { .. } => AuditActionKind::FilesErased,
+			Self::FilesCopied { .. } => AuditActionKind::FilesCopied,
+			Self::FilesMoved { .. } => AuditActionKind::FilesMoved,
+			Self::FilesRenamed { .. } => AuditActionKind::FilesRenamed,
+			Self::TagCreated { .. } => AuditActionKind::TagCreated,
+			Self::TagUpdated { .. } => AuditActionKind::TagUpdated,
+			Self::TagDeleted { .. } => AuditActionKind::TagDeleted,
+			Self::TagAssigned { .. } => AuditActionKind::TagAssigned,
+			Self::DevicePaired { .. } => AuditActionKind::DevicePaired,
+			Self::SettingsChanged { .. } => AuditActionKind::SettingsChanged,
+		}
+	}
+}
+
+impl AuditActionKind {
+	/// Value of the `action` column of the entries of this kind
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::FilesDeleted => "filesDeleted",
+			Self::FilesErased => "filesErased",
+			Self::FilesCopied => "filesCopied",
+			Self::FilesMoved => "filesMoved",
+			Self::FilesRenamed => "filesRenamed",
+			Self::TagCreated => "tagCreated",
+			Self::TagUpdated => "tagUpdated",
+			Self::TagDeleted => "tagDeleted",
+			Self::TagAssigned => "tagAssigned",
+			Self::DevicePaired => "devicePaired",
+			Self::SettingsChanged => "settingsChanged",
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum AuditLogError {
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	Csv(#[from] csv::Error),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+}
+
+impl From<AuditLogError> for rspc::Error {
+	fn from(err: AuditLogError) -> Self {
+		rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err)
+	}
+}
+
+audit_log_entry::include!(audit_log_entry_with_actor { actor: select { pub_id name } });
+
+/// An entry of the audit log, as listed and exported
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogItem {
+	pub id: audit_log_entry::id::Type,
+	pub action: Option<String>,
+	/// The serialized [`AuditAction`], kept as is so entries written by newer versions of the app
+	/// can still be listed
+	pub details: Option<serde_json::Value>,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub actor_pub_id: Option<Uuid>,
+	pub actor_name: Option<String>,
+}
+
+impl From<audit_log_entry_with_actor::Data> for AuditLogItem {
+	fn from(entry: audit_log_entry_with_actor::Data) -> Self {
+		Self {
+			id: entry.id,
+			action: entry.action,
+			details: entry
+				.details
+				.and_then(|details| serde_json::from_slice(&details).ok()),
+			date_created: entry.date_created,
+			actor_pub_id: entry
+				.actor
+				.as_ref()
+				.and_then(|actor| Uuid::from_slice(&actor.pub_id).ok()),
+			actor_name: entry.actor.map(|actor| actor.name),
+		}
+	}
+}
+
+#[derive(Debug, Default, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogFilter {
+	/// Every action is matched when empty
+	#[serde(default)]
+	pub actions: Vec<AuditActionKind>,
+	#[specta(optional)]
+	pub actor_id: Option<node::id::Type>,
+	#[specta(optional)]
+	pub from: Option<DateTime<Utc>>,
+	#[specta(optional)]
+	pub to: Option<DateTime<Utc>>,
+}
+
+impl AuditLogFilter {
+	pub fn into_params(self) -> Vec<audit_log_entry::WhereParam> {
+		let mut params = vec![];
+
+		if !self.actions.is_empty() {
+			params.push(audit_log_entry::action::in_vec(
+				self.actions
+					.iter()
+					.map(|kind| kind.as_str().to_string())
+					.collect(),
+			));
+		}
+		if let Some(actor_id) = self.actor_id {
+			params.push(audit_log_entry::actor_id::equals(Some(actor_id)));
+		}
+		if let Some(from) = self.from {
+			params.push(audit_log_entry::date_created::gte(from.into()));
+		}
+		if let Some(to) = self.to {
+			params.push(audit_log_entry::date_created::lte(to.into()));
+		}
+
+		params
+	}
+}
+
+impl Library {
+	/// Appends an entry to the audit log, done by this node. Failing to record an entry is only
+	/// logged, as the action was already done.
+	pub async fn audit(&self, action: AuditAction) {
+		if let Err(e) = self.record_audit_entry(&action).await {
+			error!("Failed to record {action:?} in the audit log: {e:#?}");
+		}
+	}
+
+	async fn record_audit_entry(&self, action: &AuditAction) -> Result<(), AuditLogError> {
+		let Library { db, sync, .. } = self;
+
+		let pub_id = Uuid::new_v4().as_bytes().to_vec();
+		let kind = action.kind().as_str();
+		let details = serde_json::to_vec(action)?;
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+		let actor_pub_id = self.config.node_id.as_bytes().to_vec();
+
+		sync.write_op(
+			db,
+			sync.unique_shared_create(
+				sync::audit_log_entry::SyncId {
+					pub_id: pub_id.clone(),
+				},
+				[
+					(audit_log_entry::action::NAME, json!(kind)),
+					(audit_log_entry::details::NAME, json!(&details)),
+					(
+						audit_log_entry::date_created::NAME,
+						json!(&date_created.to_rfc3339()),
+					),
+					(
+						audit_log_entry::actor::NAME,
+						json!({ "pub_id": actor_pub_id }),
+					),
+				],
+			),
+			db.audit_log_entry().create(
+				pub_id,
+				vec![
+					audit_log_entry::action::set(Some(kind.to_string())),
+					audit_log_entry::details::set(Some(details)),
+					audit_log_entry::date_created::set(Some(date_created)),
+					audit_log_entry::actor::connect(node::id::equals(self.node_local_id)),
+				],
+			),
+		)
+		.await?;
+
+		invalidate_query!(self, "auditLog.list");
+
+		Ok(())
+	}
+}
+
+/// Entries matching `params`, newest first
+pub async fn list_audit_log(
+	library: &Library,
+	params: Vec<audit_log_entry::WhereParam>,
+	take: i64,
+	cursor: Option<audit_log_entry::id::Type>,
+) -> Result<Vec<AuditLogItem>, AuditLogError> {
+	let mut query = library
+		.db
+		.audit_log_entry()
+		.find_many(params)
+		.order_by(audit_log_entry::id::order(SortOrder::Desc))
+		.take(take);
+
+	if let Some(cursor) = cursor {
+		query = query.cursor(audit_log_entry::id::equals(cursor));
+	}
+
+	Ok(query
+		.include(audit_log_entry_with_actor::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct AuditLogExportSummary {
+	pub output_path: PathBuf,
+	pub exported_entries: u32,
+}
+
+/// Writes every entry matching `params` to `output_path`, oldest first and in batches. The CSV
+/// has the details of each entry as a JSON column, while the JSON is an array of [`AuditLogItem`].
+pub async fn export_audit_log(
+	library: &Library,
+	params: Vec<audit_log_entry::WhereParam>,
+	format: ListingExportFormat,
+	output_path: impl AsRef<Path>,
+) -> Result<AuditLogExportSummary, AuditLogError> {
+	let output_path = output_path.as_ref();
+
+	let mut output = BufWriter::new(
+		fs::File::create(output_path)
+			.await
+			.map_err(|e| FileIOError::from((output_path, e)))?,
+	);
+
+	let mut exported_entries = 0;
+
+	match format {
+		ListingExportFormat::Csv => {
+			let mut header = csv::Writer::from_writer(vec![]);
+			header.write_record([
+				"date_created",
+				"actor_pub_id",
+				"actor_name",
+				"action",
+				"details",
+			])?;
+			write(&mut output, output_path, &into_inner(header)?).await?;
+		}
+		ListingExportFormat::Json => write(&mut output, output_path, b"[").await?,
+	}
+
+	loop {
+		let entries = library
+			.db
+			.audit_log_entry()
+			.find_many(params.clone())
+			.order_by(audit_log_entry::id::order(SortOrder::Asc))
+			.skip(exported_entries as i64)
+			.take(EXPORT_BATCH_SIZE)
+			.include(audit_log_entry_with_actor::include())
+			.exec()
+			.await?;
+
+		if entries.is_empty() {
+			break;
+		}
+
+		let batch_len = entries.len();
+
+		let bytes = match format {
+			ListingExportFormat::Csv => {
+				let mut writer = csv::Writer::from_writer(vec![]);
+				for entry in entries.into_iter().map(AuditLogItem::from) {
+					writer.write_record([
+						entry
+							.date_created
+							.map(|date| date.to_rfc3339())
+							.unwrap_or_default(),
+						entry
+							.actor_pub_id
+							.map(|pub_id| pub_id.to_string())
+							.unwrap_or_default(),
+						entry.actor_name.unwrap_or_default(),
+						entry.action.unwrap_or_default(),
+						entry
+							.details
+							.map(|details| details.to_string())
+							.unwrap_or_default(),
+					])?;
+				}
+				into_inner(writer)?
+			}
+			ListingExportFormat::Json => {
+				let mut bytes = vec![];
+				for (i, entry) in entries.into_iter().map(AuditLogItem::from).enumerate() {
+					if exported_entries > 0 || i > 0 {
+						bytes.push(b',');
+					}
+					bytes.push(b'\n');
+					serde_json::to_writer(&mut bytes, &entry)?;
+				}
+				bytes
+			}
+		};
+
+		write(&mut output, output_path, &bytes).await?;
+
+		exported_entries += batch_len as u32;
+
+		if batch_len < EXPORT_BATCH_SIZE as usize {
+			break;
+		}
+	}
+
+	if let ListingExportFormat::Json = format {
+		write(&mut output, output_path, b"\n]\n").await?;
+	}
+
+	output
+		.flush()
+		.await
+		.map_err(|e| FileIOError::from((output_path, e)))?;
+
+	Ok(AuditLogExportSummary {
+		output_path: output_path.to_path_buf(),
+		exported_entries,
+	})
+}
+
+async fn write(
+	output: &mut BufWriter<fs::File>,
+	output_path: &Path,
+	bytes: &[u8],
+) -> Result<(), FileIOError> {
+	output
+		.write_all(bytes)
+		.await
+		.map_err(|e| FileIOError::from((output_path, e)))
+}
+
+fn into_inner(writer: csv::Writer<Vec<u8>>) -> Result<Vec<u8>, csv::Error> {
+	writer
+		.into_inner()
+		.map_err(|e| csv::Error::from(e.into_error()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn action_kinds_match_their_serialized_tag() {
+		let actions = [
+			AuditAction::FilesDeleted {
+				location_id: 1,
+				file_path_ids: vec![1],
+			},
+			AuditAction::TagAssigned {
+				tag_id: 1,
+				object_ids: vec![1, 2],
+				unassign: false,
+			},
+			AuditAction::SettingsChanged {
+				settings: vec!["thumbnails".to_string()],
+			},
+		];
+
+		for action in actions {
+			let serialized = serde_json::to_value(&action).unwrap();
+			assert_eq!(serialized["type"], action.kind().as_str());
+			assert_eq!(
+				serde_json::to_value(action.kind()).unwrap(),
+				action.kind().as_str()
+			);
+		}
+	}
+}
@@ -0,0 +1,597 @@
+//! Snapshots of the database and config of a library, stored in a directory of this node or on a
+//! remote. Backups are taken by [`LibraryBackupJobInit`], usually from a scheduled job, and only
+//! the last few of them are kept in the target.
+
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobStepOutput, StatefulJob, WorkerContext,
+	},
+	location::LocationError,
+	prisma::{location, remote},
+	remote::{Remote, RemoteError},
+	util::{
+		db::{self, maybe_missing, MissingFieldError},
+		error::{coded_error, CoreErrorCode, FileIOError, NonUtf8PathError},
+		migrator::{Migrate, MigratorError},
+	},
+};
+
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use prisma_client_rust::{raw, PrismaValue};
+use rusqlite::{backup::Progress, Connection, DatabaseName};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{IdentityKey, Library, LibraryConfig, LibraryManagerError};
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const DB_EXTENSION: &str = "db";
+const CONFIG_EXTENSION: &str = "sdlibrary";
+
+/// Where the backups of a library are stored and how many are kept
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct BackupSettings {
+	/// Backups can't be taken until a target is set
+	pub target: Option<BackupTarget>,
+	/// Number of backups kept in the target, older ones are deleted after each backup. Every
+	/// backup is kept if 0.
+	pub keep_last: u32,
+}
+
+impl Default for BackupSettings {
+	fn default() -> Self {
+		Self {
+			target: None,
+			keep_last: 7,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum BackupTarget {
+	/// Directory of this node
+	Directory { path: PathBuf },
+	/// Directory of a location of this node
+	Location {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+	},
+	/// Directory of a remote, relative to its root
+	Remote {
+		remote_id: remote::id::Type,
+		dir: String,
+	},
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+	#[error("no backup target is set for this library")]
+	NoTarget,
+	#[error("backup not found <name='{0}'>")]
+	NotFound(String),
+	#[error("backups can't be stored in location <id='{0}'>, it's on another node")]
+	RemoteLocation(location::id::Type),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to restore the database: {0}")]
+	Sqlite(#[from] rusqlite::Error),
+	#[error("failed to migrate the backed up database: {0}")]
+	DatabaseMigration(#[from] db::MigrationError),
+	#[error("failed to migrate the backed up config: {0}")]
+	ConfigMigration(#[from] MigratorError),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	Remote(#[from] RemoteError),
+	#[error(transparent)]
+	LibraryManager(#[from] LibraryManagerError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	NonUtf8Path(#[from] NonUtf8PathError),
+	#[error("missing-field: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("Failed to join Tokio spawn blocking: {0}")]
+	JoinTask(#[from] tokio::task::JoinError),
+}
+
+impl From<BackupError> for rspc::Error {
+	fn from(err: BackupError) -> Self {
+		let code = match err {
+			BackupError::NoTarget | BackupError::RemoteLocation(_) => CoreErrorCode::InvalidInput,
+			BackupError::NotFound(_) => CoreErrorCode::NotFound,
+			BackupError::DatabaseMigration(_) | BackupError::ConfigMigration(_) => {
+				CoreErrorCode::DbMigrationFailed
+			}
+			BackupError::Location(err) => return err.into(),
+			BackupError::LibraryManager(err) => return err.into(),
+			_ => CoreErrorCode::BackupFailed,
+		};
+
+		coded_error(code, err)
+	}
+}
+
+/// A backup stored in the target of a library, as a database file and a config file sharing the
+/// same name
+#[derive(Debug, Serialize, Type)]
+pub struct Backup {
+	pub name: String,
+	pub date_created: DateTime<Utc>,
+}
+
+impl Backup {
+	fn new(library_id: Uuid, date_created: DateTime<Utc>) -> Self {
+		Self {
+			name: format!("{library_id}-{}", date_created.format(DATE_FORMAT)),
+			date_created,
+		}
+	}
+
+	/// Parses the name of a database file of the target, if it's a backup of the library
+	fn from_file_name(library_id: Uuid, file_name: &str) -> Option<Self> {
+		let name = file_name.strip_suffix(&format!(".{DB_EXTENSION}"))?;
+		let date = name.strip_prefix(&format!("{library_id}-"))?;
+
+		NaiveDateTime::parse_from_str(date, DATE_FORMAT)
+			.ok()
+			.map(|date| Self {
+				name: name.to_string(),
+				date_created: Utc.from_utc_datetime(&date),
+			})
+	}
+
+	fn db_file_name(&self) -> String {
+		format!("{}.{DB_EXTENSION}", self.name)
+	}
+
+	fn config_file_name(&self) -> String {
+		format!("{}.{CONFIG_EXTENSION}", self.name)
+	}
+}
+
+/// Storage of a backup target
+enum BackupStore {
+	Directory(PathBuf),
+	Remote { remote: Remote, dir: String },
+}
+
+impl BackupStore {
+	async fn open(library: &Library) -> Result<Self, BackupError> {
+		let target = library
+			.config
+			.backups
+			.target
+			.clone()
+			.ok_or(BackupError::NoTarget)?;
+
+		Ok(match target {
+			BackupTarget::Directory { path } => Self::Directory(path),
+			BackupTarget::Location {
+				location_id,
+				sub_path,
+			} => {
+				let location = library
+					.db
+					.location()
+					.find_unique(location::id::equals(location_id))
+					.select(location::select!({ path node_id }))
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?;
+
+				if location.node_id != Some(library.node_local_id) {
+					return Err(BackupError::RemoteLocation(location_id));
+				}
+
+				let path = PathBuf::from(maybe_missing(location.path, "location.path")?);
+				Self::Directory(match sub_path {
+					Some(sub_path) => path.join(sub_path),
+					None => path,
+				})
+			}
+			BackupTarget::Remote { remote_id, dir } => Self::Remote {
+				remote: Remote::from_db(&library.db, remote_id).await?,
+				dir: dir.trim_matches('/').to_string(),
+			},
+		})
+	}
+
+	fn remote_path(dir: &str, file_name: &str) -> String {
+		if dir.is_empty() {
+			file_name.to_string()
+		} else {
+			format!("{dir}/{file_name}")
+		}
+	}
+
+	async fn file_names(&self) -> Result<Vec<String>, BackupError> {
+		match self {
+			Self::Directory(path) => {
+				let mut read_dir = match fs::read_dir(path).await {
+					Ok(read_dir) => read_dir,
+					// Nothing was backed up yet
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+					Err(e) => return Err(FileIOError::from((path, e)).into()),
+				};
+
+				let mut file_names = vec![];
+				while let Some(entry) = read_dir
+					.next_entry()
+					.await
+					.map_err(|e| FileIOError::from((path, e)))?
+				{
+					if let Some(file_name) = entry.file_name().to_str() {
+						file_names.push(file_name.to_string());
+					}
+				}
+
+				Ok(file_names)
+			}
+			Self::Remote { remote, dir } => Ok(remote
+				.list(dir)
+				.await?
+				.into_iter()
+				.filter(|entry| !entry.is_dir)
+				.map(|entry| entry.name)
+				.collect()),
+		}
+	}
+
+	async fn put(&self, source: &Path, file_name: &str) -> Result<(), BackupError> {
+		match self {
+			Self::Directory(path) => {
+				fs::create_dir_all(path)
+					.await
+					.map_err(|e| FileIOError::from((path, e)))?;
+
+				let target = path.join(file_name);
+				fs::copy(source, &target)
+					.await
+					.map_err(|e| FileIOError::from((target, e)))?;
+			}
+			Self::Remote { remote, dir } => {
+				remote.create_dir(dir).await?;
+				remote
+					.upload_file(source, &Self::remote_path(dir, file_name), |_| {})
+					.await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn get(&self, file_name: &str, target: &Path) -> Result<(), BackupError> {
+		match self {
+			Self::Directory(path) => {
+				let source = path.join(file_name);
+				fs::copy(&source, target)
+					.await
+					.map_err(|e| FileIOError::from((source, e)))?;
+			}
+			Self::Remote { remote, dir } => {
+				remote
+					.download_file(&Self::remote_path(dir, file_name), target)
+					.await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn delete(&self, file_name: &str) -> Result<(), BackupError> {
+		match self {
+			Self::Directory(path) => {
+				let path = path.join(file_name);
+				fs::remove_file(&path)
+					.await
+					.map_err(|e| FileIOError::from((path, e)))?;
+			}
+			Self::Remote { remote, dir } => {
+				remote.delete(&Self::remote_path(dir, file_name)).await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Backups of the library in the store, newest first
+	async fn backups(&self, library_id: Uuid) -> Result<Vec<Backup>, BackupError> {
+		let mut backups = self
+			.file_names()
+			.await?
+			.iter()
+			.filter_map(|file_name| Backup::from_file_name(library_id, file_name))
+			.collect::<Vec<_>>();
+
+		backups.sort_by(|a, b| b.date_created.cmp(&a.date_created));
+
+		Ok(backups)
+	}
+}
+
+/// Backups of a library in its target, newest first
+pub async fn list_backups(library: &Library) -> Result<Vec<Backup>, BackupError> {
+	BackupStore::open(library).await?.backups(library.id).await
+}
+
+/// Directory where backups are written to or read from before they're moved to their target
+fn staging_dir(backup: &Backup) -> PathBuf {
+	std::env::temp_dir().join(format!("spacedrive-backup-{}", backup.name))
+}
+
+/// Replaces the database of a library, at `db_path`, by the one of a backup, returning the config
+/// of the backup for the caller to save. The database is restored in place, through the SQLite
+/// backup API, so the connections of the library see the restored data without being reopened.
+pub(super) async fn restore_backup(
+	library: &Library,
+	name: &str,
+	db_path: &Path,
+) -> Result<LibraryConfig, BackupError> {
+	let store = BackupStore::open(library).await?;
+
+	let backup = store
+		.backups(library.id)
+		.await?
+		.into_iter()
+		.find(|backup| backup.name == name)
+		.ok_or_else(|| BackupError::NotFound(name.to_string()))?;
+
+	let staging_dir = staging_dir(&backup);
+	fs::create_dir_all(&staging_dir)
+		.await
+		.map_err(|e| FileIOError::from((&staging_dir, e)))?;
+
+	let result = async {
+		let db_snapshot = staging_dir.join(backup.db_file_name());
+		let config_snapshot = staging_dir.join(backup.config_file_name());
+
+		store.get(&backup.db_file_name(), &db_snapshot).await?;
+		store
+			.get(&backup.config_file_name(), &config_snapshot)
+			.await?;
+
+		// Backups can be older than the app, so they're migrated before replacing anything
+		let snapshot_db = Arc::new(
+			db::load_and_migrate(&format!(
+				"file:{}",
+				db_snapshot
+					.to_str()
+					.ok_or_else(|| { NonUtf8PathError(db_snapshot.clone().into_boxed_path()) })?
+			))
+			.await?,
+		);
+
+		let node_config = library.config().get().await;
+		let config = LibraryConfig::load_and_migrate(
+			&config_snapshot,
+			&(
+				node_config.id,
+				node_config.keypair.peer_id(),
+				snapshot_db.clone(),
+				IdentityKey::new(&node_config.keypair),
+			),
+		)
+		.await?;
+		drop(node_config);
+		drop(snapshot_db);
+
+		let db_path = db_path.to_path_buf();
+		spawn_blocking(move || {
+			Connection::open(db_path)?.restore(
+				DatabaseName::Main,
+				db_snapshot,
+				None::<fn(Progress)>,
+			)
+		})
+		.await??;
+
+		Ok(config)
+	}
+	.await;
+
+	if let Err(e) = fs::remove_dir_all(&staging_dir).await {
+		warn!(
+			"Failed to remove the staging directory of backup '{}': {e}",
+			backup.name
+		);
+	}
+
+	if result.is_ok() {
+		info!(
+			"Restored library <id='{}'> from backup '{name}'",
+			library.id
+		);
+	}
+
+	result
+}
+
+/// Takes a backup of the library into its target, then deletes the backups over the limit
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct LibraryBackupJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LibraryBackupJobData {
+	name: String,
+	date_created: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LibraryBackupJobStep {
+	Snapshot,
+	Store,
+	Prune,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryBackupJobInit {
+	type Data = LibraryBackupJobData;
+	type Step = LibraryBackupJobStep;
+	type RunMetadata = ();
+
+	const NAME: &'static str = "library_backup";
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		// Failing early if the target can't be reached
+		BackupStore::open(&ctx.library).await?;
+
+		let backup = Backup::new(ctx.library.id, Utc::now());
+
+		*data = Some(LibraryBackupJobData {
+			name: backup.name,
+			date_created: backup.date_created,
+		});
+
+		Ok(vec![
+			LibraryBackupJobStep::Snapshot,
+			LibraryBackupJobStep::Store,
+			LibraryBackupJobStep::Prune,
+		]
+		.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let library = &ctx.library;
+		let backup = Backup {
+			name: data.name.clone(),
+			date_created: data.date_created,
+		};
+		let staging_dir = staging_dir(&backup);
+		let db_snapshot = staging_dir.join(backup.db_file_name());
+		let config_snapshot = staging_dir.join(backup.config_file_name());
+
+		match step {
+			LibraryBackupJobStep::Snapshot => {
+				ctx.progress_msg("Taking a snapshot of the library".to_string());
+
+				fs::create_dir_all(&staging_dir)
+					.await
+					.map_err(|e| FileIOError::from((&staging_dir, e)))?;
+
+				// Unlike copying the file, this gives a consistent snapshot while the library is
+				// in use, and leaves out the free pages
+				library
+					.db
+					._execute_raw(raw!(
+						"VACUUM INTO {}",
+						PrismaValue::String(
+							db_snapshot
+								.to_str()
+								.ok_or_else(|| NonUtf8PathError(
+									db_snapshot.clone().into_boxed_path()
+								))
+								.map_err(BackupError::from)?
+								.to_string()
+						)
+					))
+					.exec()
+					.await?;
+
+				library
+					.config
+					.save(&config_snapshot)
+					.map_err(BackupError::from)?;
+			}
+			LibraryBackupJobStep::Store => {
+				ctx.progress_msg("Storing the backup".to_string());
+
+				let store = BackupStore::open(library).await?;
+				store.put(&db_snapshot, &backup.db_file_name()).await?;
+				// The config goes last, so a backup is complete once both files are there
+				store
+					.put(&config_snapshot, &backup.config_file_name())
+					.await?;
+			}
+			LibraryBackupJobStep::Prune => {
+				let keep_last = library.config.backups.keep_last as usize;
+				if keep_last > 0 {
+					ctx.progress_msg("Deleting old backups".to_string());
+
+					let store = BackupStore::open(library).await?;
+					for old_backup in store.backups(library.id).await?.into_iter().skip(keep_last) {
+						store.delete(&old_backup.config_file_name()).await?;
+						store.delete(&old_backup.db_file_name()).await?;
+					}
+				}
+			}
+		}
+
+		Ok(().into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		data: &Option<Self::Data>,
+		_: &Self::RunMetadata,
+	) -> JobResult {
+		let data = data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let staging_dir = staging_dir(&Backup {
+			name: data.name.clone(),
+			date_created: data.date_created,
+		});
+		if let Err(e) = fs::remove_dir_all(&staging_dir).await {
+			warn!(
+				"Failed to remove the staging directory of backup '{}': {e}",
+				data.name
+			);
+		}
+
+		info!(
+			"Backed up library <id='{}'> as '{}'",
+			ctx.library.id, data.name
+		);
+
+		invalidate_query!(ctx.library, "backups.list");
+
+		Ok(Some(json!({ "name": data.name })))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_backup_names() {
+		let library_id = Uuid::new_v4();
+		let backup = Backup::new(
+			library_id,
+			Utc.with_ymd_and_hms(2023, 7, 2, 3, 0, 0).unwrap(),
+		);
+
+		let parsed = Backup::from_file_name(library_id, &backup.db_file_name()).unwrap();
+		assert_eq!(parsed.name, backup.name);
+		assert_eq!(parsed.date_created, backup.date_created);
+
+		assert!(Backup::from_file_name(library_id, &backup.config_file_name()).is_none());
+		assert!(Backup::from_file_name(Uuid::new_v4(), &backup.db_file_name()).is_none());
+		assert!(Backup::from_file_name(library_id, &format!("{library_id}-latest.db")).is_none());
+	}
+}
@@ -1,24 +1,36 @@
 use crate::{
+	job::LibraryJobSettings,
+	object::{
+		content_cache::ContentCacheSettings, preview::ThumbnailSettings, xmp::XmpSidecarSettings,
+	},
 	prisma::{file_path, indexer_rule, PrismaClient},
 	util::{
 		db::{maybe_missing, uuid_to_bytes},
-		migrator::{Migrate, MigratorError},
+		migrator::{Migrate, Migration, MigrationCost, MigratorError},
 	},
 };
 
-use sd_p2p::{spacetunnel::Identity, PeerId};
+use sd_crypto::{
+	crypto::{Decryptor, Encryptor},
+	types::{Algorithm, Key, Nonce},
+};
+use sd_p2p::{spacetunnel::Identity, Keypair, PeerId};
 use sd_prisma::prisma::node;
 
 use std::{path::PathBuf, sync::Arc};
 
+use futures::future::BoxFuture;
 use prisma_client_rust::not;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use specta::Type;
 use tracing::error;
 use uuid::Uuid;
 
-use super::name::LibraryName;
+use super::{
+	name::LibraryName, BackupSettings, DataDirectorySettings, DatabaseSettings, LibrarySettings,
+	NotificationSettings,
+};
 
 /// LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
 #[derive(Debug, Serialize, Deserialize, Clone)] // If you are adding `specta::Type` on this your probably about to leak the P2P private key
@@ -27,10 +39,37 @@ pub struct LibraryConfig {
 	pub name: LibraryName,
 	/// description is a user set description of the library. This is used in the UI and is set by the user.
 	pub description: Option<String>,
-	/// P2P identity of this library.
+	/// P2P identity of this library, encrypted with the [`IdentityKey`] of the node
 	pub identity: Vec<u8>,
 	/// Id of the current node
 	pub node_id: Uuid,
+	/// Resolutions and quality of the thumbnails generated for this library
+	#[serde(default)]
+	pub thumbnails: ThumbnailSettings,
+	/// Whether the XMP sidecars of files are read and written
+	#[serde(default)]
+	pub xmp_sidecars: XmpSidecarSettings,
+	/// Where and how many backups of the library are kept
+	#[serde(default)]
+	pub backups: BackupSettings,
+	/// SQLite settings of the library database, applied when it's opened
+	#[serde(default)]
+	pub database: DatabaseSettings,
+	/// Severities of the categories of notifications raised in the library
+	#[serde(default)]
+	pub notifications: NotificationSettings,
+	/// How much of the files fetched from other nodes is kept on this one
+	#[serde(default)]
+	pub content_cache: ContentCacheSettings,
+	/// Where the database, thumbnails and caches of the library are kept on this node
+	#[serde(default)]
+	pub data_directory: DataDirectorySettings,
+	/// Library-wide preferences, like the defaults of the explorer
+	#[serde(default)]
+	pub settings: LibrarySettings,
+	/// How many jobs of the library run at once on this node, and how much IO they do in parallel
+	#[serde(default)]
+	pub jobs: LibraryJobSettings,
 	// /// is_encrypted is a flag that is set to true if the library is encrypted.
 	// #[serde(default)]
 	// pub is_encrypted: bool,
@@ -53,147 +92,317 @@ impl From<LibraryConfig> for SanitisedLibraryConfig {
 	}
 }
 
+/// Context the [`IdentityKey`] is derived from the node's keypair with
+const IDENTITY_KEY_CONTEXT: &str = "spacedrive 2023-07-12 library identity encryption";
+
+const IDENTITY_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+/// Encrypts the identities of the libraries in their config files, so reading one, or syncing it
+/// to another device, doesn't give away the P2P private key of the library. It's derived from
+/// the keypair of the node, so it never leaves it, and a config file copied to another node
+/// can't be loaded there.
+#[derive(Clone)]
+pub struct IdentityKey(Key);
+
+impl IdentityKey {
+	pub fn new(keypair: &Keypair) -> Self {
+		Self(Key::new(blake3::derive_key(
+			IDENTITY_KEY_CONTEXT,
+			&keypair.secret(),
+		)))
+	}
+
+	/// The nonce followed by the encrypted identity
+	pub async fn encrypt(&self, identity: &Identity) -> Result<Vec<u8>, sd_crypto::Error> {
+		let nonce = Nonce::generate(IDENTITY_ALGORITHM)?;
+
+		let mut bytes = nonce.to_vec();
+		bytes.extend(
+			Encryptor::encrypt_bytes(
+				self.0.clone(),
+				nonce,
+				IDENTITY_ALGORITHM,
+				&identity.to_bytes(),
+				&[],
+			)
+			.await?,
+		);
+
+		Ok(bytes)
+	}
+
+	pub async fn decrypt(&self, bytes: &[u8]) -> Result<Identity, sd_crypto::Error> {
+		let nonce_len = IDENTITY_ALGORITHM.nonce_len();
+		if bytes.len() < nonce_len {
+			return Err(sd_crypto::Error::Decrypt);
+		}
+
+		let nonce = Nonce::try_from(bytes[..nonce_len].to_vec())?;
+		let identity = Decryptor::decrypt_bytes(
+			self.0.clone(),
+			nonce,
+			IDENTITY_ALGORITHM,
+			&bytes[nonce_len..],
+			&[],
+		)
+		.await?;
+
+		Identity::from_bytes(identity.expose()).map_err(|_| sd_crypto::Error::Decrypt)
+	}
+}
+
 impl LibraryConfig {
-	pub fn new(name: LibraryName, node_id: Uuid) -> Self {
-		Self {
+	/// With a new identity, encrypted with the key of the node
+	pub async fn new(
+		name: LibraryName,
+		node_id: Uuid,
+		key: &IdentityKey,
+	) -> Result<Self, sd_crypto::Error> {
+		Ok(Self {
 			name,
 			description: None,
-			identity: Identity::new().to_bytes().to_vec(),
+			identity: key.encrypt(&Identity::new()).await?,
 			node_id,
-		}
+			thumbnails: ThumbnailSettings::default(),
+			xmp_sidecars: XmpSidecarSettings::default(),
+			backups: BackupSettings::default(),
+			database: DatabaseSettings::default(),
+			notifications: NotificationSettings::default(),
+			content_cache: ContentCacheSettings::default(),
+			data_directory: DataDirectorySettings::default(),
+			settings: LibrarySettings::default(),
+			jobs: LibraryJobSettings::default(),
+		})
+	}
+
+	/// Decrypts the P2P identity of the library with the key of the node
+	pub async fn identity(&self, key: &IdentityKey) -> Result<Identity, sd_crypto::Error> {
+		key.decrypt(&self.identity).await
 	}
 }
 
+type MigrationCtx = (Uuid, PeerId, Arc<PrismaClient>, IdentityKey);
+
 #[async_trait::async_trait]
 impl Migrate for LibraryConfig {
-	const CURRENT_VERSION: u32 = 5;
+	type Ctx = MigrationCtx;
 
-	type Ctx = (Uuid, PeerId, Arc<PrismaClient>);
+	const MIGRATIONS: &'static [Migration<Self::Ctx>] = &[
+		Migration {
+			name: "indexer_rules_pub_ids",
+			description: "Gives the default indexer rules fixed public ids",
+			cost: MigrationCost::Quick,
+			run: indexer_rules_pub_ids,
+		},
+		Migration {
+			name: "library_identity",
+			description: "Generates the P2P identity of the library",
+			cost: MigrationCost::Instant,
+			run: library_identity,
+		},
+		Migration {
+			name: "node_id",
+			description: "Stores the id of the node in the config and its database",
+			cost: MigrationCost::Quick,
+			run: node_id,
+		},
+		Migration {
+			name: "noop",
+			description: "Nothing to migrate",
+			cost: MigrationCost::Instant,
+			run: noop,
+		},
+		Migration {
+			name: "file_path_sizes_as_bytes",
+			description: "Stores the sizes of the file paths as bytes rather than strings",
+			cost: MigrationCost::Slow,
+			run: file_path_sizes_as_bytes,
+		},
+		Migration {
+			name: "encrypt_identity",
+			description: "Encrypts the P2P identity of the library with a key of the node",
+			cost: MigrationCost::Instant,
+			run: encrypt_identity,
+		},
+	];
 
 	fn default(path: PathBuf) -> Result<Self, MigratorError> {
 		Err(MigratorError::ConfigFileMissing(path))
 	}
+}
 
-	async fn migrate(
-		to_version: u32,
-		config: &mut serde_json::Map<String, serde_json::Value>,
-		(node_id, peer_id, db): &Self::Ctx,
-	) -> Result<(), MigratorError> {
-		match to_version {
-			0 => {}
-			1 => {
-				let rules = vec![
-					format!("No OS protected"),
-					format!("No Hidden"),
-					format!("No Git"),
-					format!("Only Images"),
-				];
-
-				db._batch(
-					rules
-						.into_iter()
-						.enumerate()
-						.map(|(i, name)| {
-							db.indexer_rule().update_many(
-								vec![indexer_rule::name::equals(Some(name))],
-								vec![indexer_rule::pub_id::set(uuid_to_bytes(Uuid::from_u128(
-									i as u128,
-								)))],
-							)
-						})
-						.collect::<Vec<_>>(),
-				)
-				.await?;
-			}
-			2 => {
-				config.insert(
-					"identity".into(),
-					Value::Array(
-						Identity::new()
-							.to_bytes()
-							.into_iter()
-							.map(|v| v.into())
-							.collect(),
-					),
-				);
-			}
-			// The fact I have to migrate this hurts my soul
-			3 => {
-				if db.node().count(vec![]).exec().await? != 1 {
-					return Err(MigratorError::Custom(
-						"Ummm, there are too many nodes in the database, this should not happen!"
-							.into(),
-					));
-				}
+fn indexer_rules_pub_ids<'a>(
+	_: &'a mut Map<String, Value>,
+	(_, _, db, _): &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async move {
+		let rules = vec![
+			format!("No OS protected"),
+			format!("No Hidden"),
+			format!("No Git"),
+			format!("Only Images"),
+		];
 
-				db.node()
-					.update_many(
-						vec![],
-						vec![
-							node::pub_id::set(node_id.as_bytes().to_vec()),
-							node::node_peer_id::set(Some(peer_id.to_string())),
-						],
+		db._batch(
+			rules
+				.into_iter()
+				.enumerate()
+				.map(|(i, name)| {
+					db.indexer_rule().update_many(
+						vec![indexer_rule::name::equals(Some(name))],
+						vec![indexer_rule::pub_id::set(uuid_to_bytes(Uuid::from_u128(
+							i as u128,
+						)))],
 					)
-					.exec()
-					.await?;
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		Ok(())
+	})
+}
+
+fn library_identity<'a>(
+	config: &'a mut Map<String, Value>,
+	_: &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async move {
+		config.insert(
+			"identity".into(),
+			Value::Array(
+				Identity::new()
+					.to_bytes()
+					.into_iter()
+					.map(|v| v.into())
+					.collect(),
+			),
+		);
+
+		Ok(())
+	})
+}
+
+// The fact I have to migrate this hurts my soul
+fn node_id<'a>(
+	config: &'a mut Map<String, Value>,
+	(node_id, peer_id, db, _): &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async move {
+		if db.node().count(vec![]).exec().await? != 1 {
+			return Err(MigratorError::Custom(
+				"Ummm, there are too many nodes in the database, this should not happen!".into(),
+			));
+		}
+
+		db.node()
+			.update_many(
+				vec![],
+				vec![
+					node::pub_id::set(node_id.as_bytes().to_vec()),
+					node::node_peer_id::set(Some(peer_id.to_string())),
+				],
+			)
+			.exec()
+			.await?;
+
+		config.insert("node_id".into(), Value::String(node_id.to_string()));
+
+		Ok(())
+	})
+}
+
+// -_-
+fn noop<'a>(
+	_: &'a mut Map<String, Value>,
+	_: &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async { Ok(()) })
+}
+
+fn file_path_sizes_as_bytes<'a>(
+	_: &'a mut Map<String, Value>,
+	(_, _, db, _): &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async move {
+		loop {
+			let paths = db
+				.file_path()
+				.find_many(vec![not![file_path::size_in_bytes::equals(None)]])
+				.take(500)
+				.select(file_path::select!({ id size_in_bytes }))
+				.exec()
+				.await?;
 
-				config.insert("node_id".into(), Value::String(node_id.to_string()));
+			if paths.is_empty() {
+				break;
 			}
-			4 => {} // -_-
-			5 => loop {
-				let paths = db
-					.file_path()
-					.find_many(vec![not![file_path::size_in_bytes::equals(None)]])
-					.take(500)
-					.select(file_path::select!({ id size_in_bytes }))
-					.exec()
-					.await?;
-
-				if paths.is_empty() {
-					break;
-				}
-
-				db._batch(
-					paths
-						.into_iter()
-						.filter_map(|path| {
-							maybe_missing(path.size_in_bytes, "file_path.size_in_bytes")
-								.map_or_else(
-									|e| {
-										error!("{e:#?}");
-										None
-									},
-									Some,
+
+			db._batch(
+				paths
+					.into_iter()
+					.filter_map(|path| {
+						maybe_missing(path.size_in_bytes, "file_path.size_in_bytes")
+							.map_or_else(
+								|e| {
+									error!("{e:#?}");
+									None
+								},
+								Some,
+							)
+							.map(|size_in_bytes| {
+								let size = if let Ok(size) = size_in_bytes.parse::<u64>() {
+									Some(size.to_be_bytes().to_vec())
+								} else {
+									error!(
+										"File path <id='{}'> had invalid size: '{}'",
+										path.id, size_in_bytes
+									);
+									None
+								};
+
+								db.file_path().update(
+									file_path::id::equals(path.id),
+									vec![
+										file_path::size_in_bytes_bytes::set(size),
+										file_path::size_in_bytes::set(None),
+									],
 								)
-								.map(|size_in_bytes| {
-									let size = if let Ok(size) = size_in_bytes.parse::<u64>() {
-										Some(size.to_be_bytes().to_vec())
-									} else {
-										error!(
-											"File path <id='{}'> had invalid size: '{}'",
-											path.id, size_in_bytes
-										);
-										None
-									};
-
-									db.file_path().update(
-										file_path::id::equals(path.id),
-										vec![
-											file_path::size_in_bytes_bytes::set(size),
-											file_path::size_in_bytes::set(None),
-										],
-									)
-								})
-						})
-						.collect::<Vec<_>>(),
-				)
-				.await?;
-			},
-			v => unreachable!("Missing migration for library version {}", v),
+							})
+					})
+					.collect::<Vec<_>>(),
+			)
+			.await?;
 		}
 
 		Ok(())
-	}
+	})
+}
+
+fn encrypt_identity<'a>(
+	config: &'a mut Map<String, Value>,
+	(_, _, _, identity_key): &'a MigrationCtx,
+) -> BoxFuture<'a, Result<(), MigratorError>> {
+	Box::pin(async move {
+		let identity = config
+			.get("identity")
+			.cloned()
+			.map(serde_json::from_value::<Vec<u8>>)
+			.transpose()?
+			.ok_or_else(|| MigratorError::Custom("Library has no identity!".into()))?;
+
+		let identity = Identity::from_bytes(&identity)
+			.map_err(|e| MigratorError::Custom(format!("Invalid library identity: {e}")))?;
+
+		config.insert(
+			"identity".into(),
+			serde_json::to_value(identity_key.encrypt(&identity).await.map_err(|e| {
+				MigratorError::Custom(format!("Failed to encrypt library identity: {e}"))
+			})?)?,
+		);
+
+		Ok(())
+	})
 }
 
 // used to return to the frontend with uuid context
@@ -202,3 +411,100 @@ pub struct LibraryConfigWrapped {
 	pub uuid: Uuid,
 	pub config: SanitisedLibraryConfig,
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use crate::{
+		node::Platform,
+		util::{db, migrator::MigrationFixture},
+	};
+
+	use chrono::Local;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn identity_is_only_decrypted_by_its_node() {
+		let keypair = Keypair::generate();
+		let key = IdentityKey::new(&keypair);
+		let identity = Identity::new();
+
+		let encrypted = key.encrypt(&identity).await.unwrap();
+		assert_ne!(encrypted, identity.to_bytes());
+		assert_eq!(
+			key.decrypt(&encrypted).await.unwrap().to_bytes(),
+			identity.to_bytes()
+		);
+
+		let other_key = IdentityKey::new(&Keypair::generate());
+		assert!(other_key.decrypt(&encrypted).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn migrates_fixtures() {
+		for fixture in MigrationFixture::all("sdlibrary") {
+			let database_path = fixture
+				.database_path()
+				.map(ToOwned::to_owned)
+				.unwrap_or_else(|| fixture.empty_database_path());
+			let db = Arc::new(
+				db::load_and_migrate(&format!("file:{}", database_path.display()))
+					.await
+					.unwrap_or_else(|e| {
+						panic!(
+							"failed to migrate the database of fixture '{}': {e}",
+							fixture.name()
+						)
+					}),
+			);
+
+			if fixture.database_path().is_some() {
+				// The rows of the snapshot are kept, and the tables of the latest migrations added
+				assert_ne!(
+					db.file_path().count(vec![]).exec().await.unwrap(),
+					0,
+					"fixture '{}'",
+					fixture.name()
+				);
+				db.share_link_download().count(vec![]).exec().await.unwrap();
+			}
+
+			// Libraries always had their node in their database
+			if db.node().count(vec![]).exec().await.unwrap() == 0 {
+				db.node()
+					.create(
+						Uuid::new_v4().as_bytes().to_vec(),
+						"Old node".to_string(),
+						Platform::current() as i32,
+						Local::now().into(),
+						vec![],
+					)
+					.exec()
+					.await
+					.unwrap();
+			}
+
+			let node_id = Uuid::new_v4();
+			let keypair = Keypair::generate();
+			let identity_key = IdentityKey::new(&keypair);
+			let from_version = fixture.version();
+
+			let config = fixture
+				.migrate::<LibraryConfig>(&(node_id, keypair.peer_id(), db, identity_key.clone()))
+				.await;
+
+			config.identity(&identity_key).await.unwrap_or_else(|e| {
+				panic!(
+					"identity of fixture '{}' isn't encrypted with the key of the node: {e}",
+					fixture.name()
+				)
+			});
+
+			// The node id was only stored from version 3 on
+			if from_version < 3 {
+				assert_eq!(config.node_id, node_id, "fixture '{}'", fixture.name());
+			}
+		}
+	}
+}
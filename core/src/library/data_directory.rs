@@ -0,0 +1,351 @@
+//! Where the database, thumbnails and caches of a library are kept. They're in the data directory
+//! of the node by default, but a library can have its own, like a secondary disk for a huge media
+//! library. Its config file stays with the other libraries of the node, so it's always found.
+//!
+//! Thumbnails and caches are moved by [`LibraryDataMoveJobInit`] while the library is in use, but
+//! the database can't be while it's open, so it's moved the next time the library is loaded.
+
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobStepOutput, StatefulJob, WorkerContext,
+	},
+	object::{
+		content_cache::CONTENT_CACHE_DIR_NAME,
+		preview::{
+			get_shard_hex, init_thumbnail_dir, ThumbnailFormat, ThumbnailTier,
+			TEXT_PREVIEW_EXTENSION, THUMBNAIL_CACHE_DIR_NAME, WAVEFORM_EXTENSION,
+		},
+	},
+	prisma::{file_path, SortOrder},
+	util::error::FileIOError,
+};
+
+use std::{
+	collections::HashSet,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use specta::Type;
+use tokio::fs;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Number of file paths whose thumbnails are moved in each step
+const THUMBNAILS_BATCH_SIZE: i64 = 1000;
+
+/// Where the data of a library is kept on this node
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDirectorySettings {
+	/// The data directory of the node if not set
+	pub path: Option<PathBuf>,
+	/// Database still in use at its previous place, moved to `path` when the library is next
+	/// loaded
+	pub pending_database_move: Option<PathBuf>,
+}
+
+impl DataDirectorySettings {
+	/// Reads the settings from the config file of a library, as they're needed to find its
+	/// database, before the config can be loaded
+	pub(super) fn read_from_config(config_path: &Path) -> Self {
+		std::fs::read(config_path)
+			.ok()
+			.and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+			.and_then(|mut config| config.get_mut("data_directory").map(Value::take))
+			.and_then(|settings| serde_json::from_value(settings).ok())
+			.unwrap_or_default()
+	}
+
+	/// Where the database of the library belongs, next to its config file if there's no data
+	/// directory set
+	pub(crate) fn database_path(&self, library_id: Uuid, libraries_dir: &Path) -> PathBuf {
+		self.path
+			.as_deref()
+			.unwrap_or(libraries_dir)
+			.join(format!("{library_id}.db"))
+	}
+
+	/// The database the library has open, which is still at its previous place if its move is
+	/// pending
+	pub(crate) fn database_in_use(&self, library_id: Uuid, libraries_dir: &Path) -> PathBuf {
+		self.pending_database_move
+			.clone()
+			.unwrap_or_else(|| self.database_path(library_id, libraries_dir))
+	}
+}
+
+/// Moves a database that isn't open, along with its write-ahead log. The database is moved last,
+/// so a move that was interrupted is finished by running it again.
+pub(super) async fn move_database(from: &Path, to: &Path) -> Result<(), FileIOError> {
+	if from == to {
+		return Ok(());
+	}
+
+	if let Some(parent) = to.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e)))?;
+	}
+
+	let wal_path = |path: &Path| {
+		let mut wal_path = path.as_os_str().to_owned();
+		wal_path.push("-wal");
+		PathBuf::from(wal_path)
+	};
+
+	move_file(&wal_path(from), &wal_path(to)).await?;
+	if move_file(from, to).await? {
+		info!(
+			"Moved library database from '{}' to '{}'",
+			from.display(),
+			to.display()
+		);
+	}
+
+	Ok(())
+}
+
+/// Renames a file, or copies it then removes the original if they're on different disks. Returns
+/// whether there was a file to move.
+async fn move_file(from: &Path, to: &Path) -> Result<bool, FileIOError> {
+	match fs::metadata(from).await {
+		Ok(_) => {}
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+		Err(e) => return Err(FileIOError::from((from, e))),
+	}
+
+	if fs::rename(from, to).await.is_ok() {
+		return Ok(true);
+	}
+
+	// Copied under another name first, so a partial copy is never taken for the file
+	let mut part_path = to.as_os_str().to_owned();
+	part_path.push(".part");
+	let part_path = PathBuf::from(part_path);
+
+	fs::copy(from, &part_path)
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?;
+	fs::File::open(&part_path)
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?
+		.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?;
+	fs::rename(&part_path, to)
+		.await
+		.map_err(|e| FileIOError::from((to, e)))?;
+	fs::remove_file(from)
+		.await
+		.map_err(|e| FileIOError::from((from, e)))?;
+
+	Ok(true)
+}
+
+/// Copies a file, unless it's already at the destination
+async fn copy_file(from: &Path, to: &Path) -> Result<bool, FileIOError> {
+	if fs::metadata(to).await.is_ok() {
+		return Ok(false);
+	}
+
+	match fs::copy(from, to).await {
+		Ok(_) => Ok(true),
+		Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+		Err(e) => Err(FileIOError::from((to, e))),
+	}
+}
+
+/// Moves the thumbnails and caches of a library from its previous data directory to the one now
+/// set in its config. Thumbnails in the data directory of the node can be shared with other
+/// libraries, so they're copied rather than moved.
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct LibraryDataMoveJobInit {
+	pub from: PathBuf,
+	pub to: PathBuf,
+	/// Whether `from` is the data directory of the node
+	pub from_node_directory: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LibraryDataMoveJobStep {
+	Thumbnails { skip: i64 },
+	ContentCache,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryDataMoveJobInit {
+	type Data = ();
+	type Step = LibraryDataMoveJobStep;
+	type RunMetadata = ();
+
+	const NAME: &'static str = "library_data_move";
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		init_thumbnail_dir(self.to.clone()).await?;
+
+		let file_paths_count = ctx
+			.library
+			.db
+			.file_path()
+			.count(vec![file_path::cas_id::not(None)])
+			.exec()
+			.await?;
+
+		*data = Some(());
+
+		Ok((0..file_paths_count)
+			.step_by(THUMBNAILS_BATCH_SIZE as usize)
+			.map(|skip| LibraryDataMoveJobStep::Thumbnails { skip })
+			.chain([LibraryDataMoveJobStep::ContentCache])
+			.collect::<Vec<_>>()
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		match step {
+			LibraryDataMoveJobStep::Thumbnails { skip } => {
+				ctx.progress_msg("Moving thumbnails".to_string());
+
+				let cas_ids = ctx
+					.library
+					.db
+					.file_path()
+					.find_many(vec![file_path::cas_id::not(None)])
+					.order_by(file_path::id::order(SortOrder::Asc))
+					.skip(*skip)
+					.take(THUMBNAILS_BATCH_SIZE)
+					.select(file_path::select!({ cas_id }))
+					.exec()
+					.await?
+					.into_iter()
+					.filter_map(|file_path| file_path.cas_id)
+					.collect::<HashSet<_>>();
+
+				let from_dir = self.from.join(THUMBNAIL_CACHE_DIR_NAME);
+				let to_dir = self.to.join(THUMBNAIL_CACHE_DIR_NAME);
+
+				for cas_id in cas_ids {
+					let shard = get_shard_hex(&cas_id);
+					let from_shard_dir = from_dir.join(&shard);
+					let to_shard_dir = to_dir.join(&shard);
+
+					// Every tier and format of the thumbnail, with the other previews of the file
+					let file_names = ThumbnailFormat::ALL
+						.into_iter()
+						.flat_map(|format| {
+							let grid_path = from_shard_dir
+								.join(&cas_id)
+								.with_extension(format.extension());
+							ThumbnailTier::ALL
+								.into_iter()
+								.map(move |tier| tier.path_from_grid(&grid_path))
+						})
+						.filter_map(|path| path.file_name().map(ToOwned::to_owned))
+						.chain(
+							[TEXT_PREVIEW_EXTENSION, WAVEFORM_EXTENSION]
+								.map(|extension| format!("{cas_id}.{extension}").into()),
+						);
+
+					let mut created_shard_dir = false;
+					for file_name in file_names {
+						let from_path = from_shard_dir.join(&file_name);
+						if fs::metadata(&from_path).await.is_err() {
+							continue;
+						}
+
+						if !created_shard_dir {
+							fs::create_dir_all(&to_shard_dir)
+								.await
+								.map_err(|e| FileIOError::from((&to_shard_dir, e)))?;
+							created_shard_dir = true;
+						}
+
+						let to_path = to_shard_dir.join(&file_name);
+						if self.from_node_directory {
+							copy_file(&from_path, &to_path).await?;
+						} else {
+							move_file(&from_path, &to_path).await?;
+						}
+					}
+				}
+			}
+			LibraryDataMoveJobStep::ContentCache => {
+				ctx.progress_msg("Moving cached files".to_string());
+
+				let library_id = ctx.library.id.to_string();
+				let from_dir = self.from.join(CONTENT_CACHE_DIR_NAME).join(&library_id);
+				let to_dir = self.to.join(CONTENT_CACHE_DIR_NAME).join(&library_id);
+
+				let mut read_dir = match fs::read_dir(&from_dir).await {
+					Ok(read_dir) => read_dir,
+					Err(e) if e.kind() == ErrorKind::NotFound => return Ok(().into()),
+					Err(e) => return Err(FileIOError::from((&from_dir, e)).into()),
+				};
+
+				fs::create_dir_all(&to_dir)
+					.await
+					.map_err(|e| FileIOError::from((&to_dir, e)))?;
+
+				while let Some(entry) = read_dir
+					.next_entry()
+					.await
+					.map_err(|e| FileIOError::from((&from_dir, e)))?
+				{
+					let from_path = entry.path();
+					// Files still being fetched are left behind
+					if from_path.extension().map_or(false, |ext| ext == "part") {
+						continue;
+					}
+
+					move_file(&from_path, &to_dir.join(entry.file_name())).await?;
+				}
+
+				if let Err(e) = fs::remove_dir(&from_dir).await {
+					warn!(
+						"Failed to remove the previous content cache directory '{}': {e}",
+						from_dir.display()
+					);
+				}
+			}
+		}
+
+		Ok(().into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		_: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Moved the data of library <id='{}'> from '{}' to '{}'",
+			ctx.library.id,
+			self.from.display(),
+			self.to.display()
+		);
+
+		invalidate_query!(ctx.library, "library.dataDirectory");
+		invalidate_query!(ctx.library, "cache.stats");
+		invalidate_query!(ctx.library, "cache.contentStats");
+
+		Ok(Some(json!({
+			"from": self.from,
+			"to": self.to,
+		})))
+	}
+}
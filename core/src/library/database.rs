@@ -0,0 +1,206 @@
+use crate::{prisma::PrismaClient, util::error::NonUtf8PathError};
+
+use std::{future::Future, path::Path, time::Duration};
+
+use prisma_client_rust::{raw, QueryError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+use thiserror::Error;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, warn};
+
+/// Waiting time before retrying a query the database was too busy for, doubling on each retry
+const BUSY_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// SQLite settings of a library database, for the data directories on slow disks or network
+/// shares where the defaults don't behave well.
+///
+/// Most of them only last as long as the connection they're set on, so libraries are opened with
+/// a single connection by default, which is kept open, and they're applied to it once connected.
+/// With more connections, the cache size and synchronous mode only apply to one of them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DatabaseSettings {
+	/// Left as it is in the database if not set
+	pub journal_mode: Option<JournalMode>,
+	/// Number of pages the write-ahead log can grow to before it's checkpointed into the database,
+	/// or `0` to only checkpoint when the database is closed
+	pub wal_autocheckpoint: Option<u32>,
+	/// How long a query waits for the database to be unlocked before failing
+	pub busy_timeout_secs: u32,
+	/// Memory used to cache the pages of the database
+	pub cache_size_kib: Option<u32>,
+	pub synchronous: Option<SynchronousMode>,
+	/// Connections opened to the database, letting jobs read while another one writes
+	pub connection_limit: u32,
+	/// How long a query waits for a connection of the pool before failing
+	pub pool_timeout_secs: u32,
+	/// How long the queries of jobs can run before they're given up on, unlimited if not set
+	pub statement_timeout_secs: Option<u32>,
+	/// How many times the queries of jobs are retried when the database is locked by another
+	/// connection, or another process, even after waiting for `busy_timeout_secs`
+	pub busy_retries: u32,
+}
+
+impl Default for DatabaseSettings {
+	fn default() -> Self {
+		Self {
+			journal_mode: None,
+			wal_autocheckpoint: None,
+			busy_timeout_secs: 15,
+			cache_size_kib: None,
+			synchronous: None,
+			connection_limit: 1,
+			pool_timeout_secs: 10,
+			statement_timeout_secs: None,
+			busy_retries: 3,
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum DatabaseQueryError {
+	#[error("database error: {0}")]
+	Query(#[from] QueryError),
+	#[error("query timed out after {0} seconds")]
+	TimedOut(u32),
+}
+
+/// Whether the query failed because another connection, or process, held a lock on the database
+pub(crate) fn is_busy(e: &QueryError) -> bool {
+	let message = e.to_string();
+	message.contains("database is locked")
+		|| message.contains("database is busy")
+		|| message.contains("Timed out during query execution")
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum JournalMode {
+	/// Lets reads run while writing, but needs shared memory, which network shares often lack
+	Wal,
+	Delete,
+	Truncate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum SynchronousMode {
+	Off,
+	Normal,
+	Full,
+	Extra,
+}
+
+impl DatabaseSettings {
+	/// Reads the settings from the config file of a library, which can't be loaded before its
+	/// database is opened, as migrating it may need the database. Falls back to the defaults if
+	/// the file has none or they're invalid.
+	pub(super) fn read_from_config(config_path: &Path) -> Self {
+		std::fs::read(config_path)
+			.ok()
+			.and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+			.and_then(|mut config| config.get_mut("database").map(Value::take))
+			.and_then(|settings| {
+				serde_json::from_value(settings)
+					.map_err(|e| {
+						warn!(
+							"Invalid database settings in '{}', using the defaults: {e}",
+							config_path.display()
+						)
+					})
+					.ok()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Connection url of the database at `db_path`
+	pub(super) fn db_url(&self, db_path: &Path) -> Result<String, NonUtf8PathError> {
+		Ok(format!(
+			"file:{}?socket_timeout={}&connection_limit={}&pool_timeout={}&max_idle_connection_lifetime=0",
+			db_path
+				.to_str()
+				.ok_or_else(|| NonUtf8PathError(db_path.into()))?,
+			self.busy_timeout_secs,
+			self.connection_limit.max(1),
+			self.pool_timeout_secs
+		))
+	}
+
+	/// Runs a query within the statement timeout, running it again when the database is busy.
+	/// `query` is called for each attempt, as queries are consumed when they're run.
+	pub async fn run<T, Fut>(&self, mut query: impl FnMut() -> Fut) -> Result<T, DatabaseQueryError>
+	where
+		Fut: Future<Output = Result<T, QueryError>>,
+	{
+		let mut retry = 0;
+		loop {
+			let res = match self.statement_timeout_secs {
+				Some(secs) => timeout(Duration::from_secs(secs.into()), query())
+					.await
+					.map_err(|_| DatabaseQueryError::TimedOut(secs))?,
+				None => query().await,
+			};
+
+			match res {
+				Err(e) if retry < self.busy_retries && is_busy(&e) => {
+					retry += 1;
+					debug!(
+						"Database busy, retrying query ({retry}/{})",
+						self.busy_retries
+					);
+					sleep(BUSY_RETRY_BACKOFF.saturating_mul(2u32.saturating_pow(retry - 1))).await;
+				}
+				res => return res.map_err(Into::into),
+			}
+		}
+	}
+
+	/// Sets the settings on the connection of a library database
+	pub async fn apply(&self, db: &PrismaClient) -> Result<(), QueryError> {
+		let mut pragmas = vec![format!(
+			"PRAGMA busy_timeout = {}",
+			u64::from(self.busy_timeout_secs) * 1000
+		)];
+
+		if let Some(journal_mode) = self.journal_mode {
+			pragmas.push(format!(
+				"PRAGMA journal_mode = {}",
+				match journal_mode {
+					JournalMode::Wal => "WAL",
+					JournalMode::Delete => "DELETE",
+					JournalMode::Truncate => "TRUNCATE",
+				}
+			));
+		}
+
+		if let Some(pages) = self.wal_autocheckpoint {
+			pragmas.push(format!("PRAGMA wal_autocheckpoint = {pages}"));
+		}
+
+		if let Some(cache_size_kib) = self.cache_size_kib {
+			// Negative sizes are in KiB instead of pages
+			pragmas.push(format!("PRAGMA cache_size = -{cache_size_kib}"));
+		}
+
+		if let Some(synchronous) = self.synchronous {
+			pragmas.push(format!(
+				"PRAGMA synchronous = {}",
+				match synchronous {
+					SynchronousMode::Off => "OFF",
+					SynchronousMode::Normal => "NORMAL",
+					SynchronousMode::Full => "FULL",
+					SynchronousMode::Extra => "EXTRA",
+				}
+			));
+		}
+
+		for pragma in pragmas {
+			// Some of them return the new value, which `_execute_raw` would reject
+			db._query_raw::<Value>(raw!(&pragma)).exec().await?;
+		}
+
+		Ok(())
+	}
+}
@@ -5,8 +5,12 @@ use crate::{
 		LocationManager,
 	},
 	node::NodeConfigManager,
-	object::{orphan_remover::OrphanRemoverActor, preview::get_thumbnail_path},
-	prisma::{file_path, location, PrismaClient},
+	object::{
+		orphan_remover::OrphanRemoverActor,
+		preview::{get_thumbnail_path, ThumbnailFormat, ThumbnailPrioritizerActor},
+		undo::UndoHistory,
+	},
+	prisma::{file_path, location, node, PrismaClient},
 	sync::SyncManager,
 	util::{db::maybe_missing, error::FileIOError},
 	NodeContext,
@@ -15,16 +19,18 @@ use crate::{
 use std::{
 	collections::HashMap,
 	fmt::{Debug, Formatter},
+	future::Future,
 	path::{Path, PathBuf},
 	sync::Arc,
 };
 
 use sd_p2p::spacetunnel::Identity;
-use tokio::{fs, io};
+use tokio::{fs, io, select};
+use tokio_util::sync::CancellationToken;
 use tracing::warn;
 use uuid::Uuid;
 
-use super::{LibraryConfig, LibraryManagerError};
+use super::{LibraryConfig, LibraryManagerError, NodeRole, WebhookDispatcherActor};
 
 /// LibraryContext holds context for a library which can be passed around the application.
 #[derive(Clone)]
@@ -47,6 +53,14 @@ pub struct Library {
 	/// p2p identity
 	pub identity: Arc<Identity>,
 	pub orphan_remover: OrphanRemoverActor,
+	/// generates the thumbnails of the files visible in the explorer first
+	pub thumbnail_prioritizer: ThumbnailPrioritizerActor,
+	/// notifies the webhooks of this library of its events
+	pub webhooks: WebhookDispatcherActor,
+	/// moves, renames and tag assignments that can be undone
+	pub undo_history: UndoHistory,
+	/// stops the tasks of the library when it's deleted
+	pub(crate) tasks_cancel_token: CancellationToken,
 }
 
 impl Debug for Library {
@@ -63,6 +77,18 @@ impl Debug for Library {
 }
 
 impl Library {
+	/// Spawns a task running in the background for as long as the library isn't deleted
+	pub(crate) fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+		let cancel_token = self.tasks_cancel_token.clone();
+
+		tokio::spawn(async move {
+			select! {
+				_ = cancel_token.cancelled() => {}
+				_ = task => {}
+			}
+		});
+	}
+
 	pub(crate) fn emit(&self, event: CoreEvent) {
 		if let Err(e) = self.node_context.event_bus_tx.send(event) {
 			warn!("Error sending event to event bus: {e:?}");
@@ -73,18 +99,48 @@ impl Library {
 		self.node_context.config.clone()
 	}
 
+	/// Where the thumbnails and caches of the library are kept on this node
+	pub fn data_directory(&self) -> PathBuf {
+		self.config
+			.data_directory
+			.path
+			.clone()
+			.unwrap_or_else(|| self.config().data_directory())
+	}
+
 	pub(crate) fn location_manager(&self) -> &Arc<LocationManager> {
 		&self.node_context.location_manager
 	}
 
+	/// Role of this node in the library, a viewer if its record is missing
+	pub async fn role(&self) -> Result<NodeRole, prisma_client_rust::QueryError> {
+		Ok(self
+			.db
+			.node()
+			.find_unique(node::id::equals(self.node_local_id))
+			.select(node::select!({ role }))
+			.exec()
+			.await?
+			.map_or(NodeRole::Viewer, |node| {
+				NodeRole::of_node(node.role.as_deref())
+			}))
+	}
+
+	/// Thumbnails in another format than the library's one still count, until they're re-encoded
 	pub async fn thumbnail_exists(&self, cas_id: &str) -> Result<bool, FileIOError> {
 		let thumb_path = get_thumbnail_path(self, cas_id);
 
-		match fs::metadata(&thumb_path).await {
-			Ok(_) => Ok(true),
-			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
-			Err(e) => Err(FileIOError::from((thumb_path, e))),
+		for format in ThumbnailFormat::ALL {
+			let thumb_path = thumb_path.with_extension(format.extension());
+
+			match fs::metadata(&thumb_path).await {
+				Ok(_) => return Ok(true),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(FileIOError::from((thumb_path, e))),
+			}
 		}
+
+		Ok(false)
 	}
 
 	/// Returns the full path of a file
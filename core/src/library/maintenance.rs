@@ -0,0 +1,250 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobRunMetadata, JobStatus,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+	prisma::{job, SortOrder},
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{operator::or, raw, QueryError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::{info, warn};
+
+use super::Library;
+
+/// Checks the library database for corruption, rebuilds its indexes and reclaims its free space.
+/// The database is locked while it's rebuilt or vacuumed, so this is better scheduled for when the
+/// app is idle.
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone)]
+pub struct DatabaseMaintenanceJobInit {
+	/// Runs `PRAGMA integrity_check`, which reads the whole database
+	pub integrity_check: bool,
+	/// Rebuilds every index, which fixes the ones an integrity check found inconsistent
+	pub reindex: bool,
+	/// Rewrites the database without its free pages, shrinking the file
+	pub vacuum: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum DatabaseMaintenanceJobStep {
+	IntegrityCheck,
+	Reindex,
+	Vacuum,
+}
+
+/// Outcome of a maintenance job, kept in its report
+#[derive(Serialize, Deserialize, Type, Default, Debug, Clone)]
+pub struct DatabaseMaintenanceReport {
+	/// `None` if the integrity wasn't checked
+	pub integrity_ok: Option<bool>,
+	pub integrity_errors: Vec<String>,
+	pub reindexed: bool,
+	pub vacuumed: bool,
+	#[specta(type = String)]
+	pub reclaimed_bytes: u64,
+}
+
+impl JobRunMetadata for DatabaseMaintenanceReport {
+	fn update(&mut self, new_data: Self) {
+		if new_data.integrity_ok.is_some() {
+			self.integrity_ok = new_data.integrity_ok;
+		}
+		self.integrity_errors.extend(new_data.integrity_errors);
+		self.reindexed |= new_data.reindexed;
+		self.vacuumed |= new_data.vacuumed;
+		self.reclaimed_bytes += new_data.reclaimed_bytes;
+	}
+}
+
+/// State of the library database, along with the outcome of its last maintenance
+#[derive(Serialize, Type, Debug)]
+pub struct DatabaseHealth {
+	#[specta(type = String)]
+	pub size_in_bytes: u64,
+	/// Space taken by free pages, which a vacuum would reclaim
+	#[specta(type = String)]
+	pub free_bytes: u64,
+	pub last_maintenance: Option<DateTime<Utc>>,
+	pub last_report: Option<DatabaseMaintenanceReport>,
+}
+
+#[derive(Deserialize)]
+struct DatabaseSize {
+	size: i64,
+	free: i64,
+}
+
+#[derive(Deserialize)]
+struct IntegrityCheckRow {
+	integrity_check: String,
+}
+
+async fn database_size(library: &Library) -> Result<DatabaseSize, QueryError> {
+	library
+		.db
+		._query_raw::<DatabaseSize>(raw!(
+			"SELECT page_count * page_size AS size, freelist_count * page_size AS free \
+				FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()"
+		))
+		.exec()
+		.await
+		.map(|mut rows| rows.pop().unwrap_or(DatabaseSize { size: 0, free: 0 }))
+}
+
+pub async fn database_health(library: &Library) -> Result<DatabaseHealth, QueryError> {
+	let size = database_size(library).await?;
+
+	let last_job = library
+		.db
+		.job()
+		.find_first(vec![
+			job::name::equals(Some(DatabaseMaintenanceJobInit::NAME.to_string())),
+			or(vec![
+				job::status::equals(Some(JobStatus::Completed as i32)),
+				job::status::equals(Some(JobStatus::CompletedWithErrors as i32)),
+			]),
+		])
+		.order_by(job::date_completed::order(SortOrder::Desc))
+		.exec()
+		.await?;
+
+	let last_report = last_job
+		.as_ref()
+		.and_then(|job| job.metadata.as_deref())
+		.and_then(|metadata| {
+			serde_json::from_slice::<serde_json::Value>(metadata)
+				.map_err(|e| warn!("Invalid metadata of a database maintenance job: {e}"))
+				.ok()
+		})
+		.and_then(|metadata| {
+			serde_json::from_value(metadata["output"]["run_metadata"].clone()).ok()
+		});
+
+	Ok(DatabaseHealth {
+		size_in_bytes: size.size as u64,
+		free_bytes: size.free as u64,
+		last_maintenance: last_job.and_then(|job| job.date_completed).map(Into::into),
+		last_report,
+	})
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DatabaseMaintenanceJobInit {
+	type Data = ();
+	type Step = DatabaseMaintenanceJobStep;
+	type RunMetadata = DatabaseMaintenanceReport;
+
+	const NAME: &'static str = "database_maintenance";
+
+	async fn init(
+		&self,
+		_: &WorkerContext,
+		_: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+
+		// Checking first, as rebuilding and vacuuming a corrupted database can make it worse
+		let steps = [
+			(
+				init.integrity_check,
+				DatabaseMaintenanceJobStep::IntegrityCheck,
+			),
+			(init.reindex, DatabaseMaintenanceJobStep::Reindex),
+			(init.vacuum, DatabaseMaintenanceJobStep::Vacuum),
+		]
+		.into_iter()
+		.filter_map(|(enabled, step)| enabled.then_some(step))
+		.collect::<Vec<_>>();
+
+		if steps.is_empty() {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "no maintenance task was selected".to_string(),
+			});
+		}
+
+		Ok((DatabaseMaintenanceReport::default(), steps).into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let db = &ctx.library.db;
+		let mut new_metadata = DatabaseMaintenanceReport::default();
+
+		match step {
+			DatabaseMaintenanceJobStep::IntegrityCheck => {
+				ctx.progress_msg("Checking the database integrity".to_string());
+
+				// Reporting at most 100 problems, the database is corrupted anyway past a few
+				let rows = db
+					._query_raw::<IntegrityCheckRow>(raw!("PRAGMA integrity_check(100)"))
+					.exec()
+					.await?;
+
+				let errors = rows
+					.into_iter()
+					.map(|row| row.integrity_check)
+					.filter(|message| message != "ok")
+					.collect::<Vec<_>>();
+
+				new_metadata.integrity_ok = Some(errors.is_empty());
+				new_metadata.integrity_errors = errors;
+			}
+			DatabaseMaintenanceJobStep::Reindex => {
+				if run_metadata.integrity_ok == Some(false) {
+					warn!("Rebuilding the indexes of a database that failed its integrity check");
+				}
+
+				ctx.progress_msg("Rebuilding the database indexes".to_string());
+
+				db._execute_raw(raw!("REINDEX")).exec().await?;
+				new_metadata.reindexed = true;
+			}
+			DatabaseMaintenanceJobStep::Vacuum => {
+				if run_metadata.integrity_ok == Some(false) {
+					return Ok(JobRunErrors(vec![
+						"Skipped the vacuum, as the database failed its integrity check"
+							.to_string(),
+					])
+					.into());
+				}
+
+				ctx.progress_msg("Vacuuming the database".to_string());
+
+				let before = database_size(&ctx.library).await?;
+				db._execute_raw(raw!("VACUUM")).exec().await?;
+				let after = database_size(&ctx.library).await?;
+
+				new_metadata.vacuumed = true;
+				new_metadata.reclaimed_bytes = (before.size - after.size).max(0) as u64;
+			}
+		}
+
+		Ok(new_metadata.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Database maintenance of library <id='{}'> finished: {run_metadata:?}",
+			ctx.library.id
+		);
+
+		invalidate_query!(ctx.library, "library.databaseHealth");
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
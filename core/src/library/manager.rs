@@ -1,16 +1,21 @@
 use crate::{
 	invalidate_query,
-	location::{indexer, LocationManagerError},
+	job::{retention::spawn_retention_cleanup, scheduler::spawn_scheduler},
+	location::{indexer, soft_delete::spawn_deleted_file_paths_purge, LocationManagerError},
 	node::{NodeConfig, Platform},
-	object::{orphan_remover::OrphanRemoverActor, tag},
+	object::{
+		fs::journal, orphan_remover::OrphanRemoverActor, preview::ThumbnailPrioritizerActor, tag,
+		undo::UndoHistory,
+	},
 	prisma::{location, node},
 	sync::{SyncManager, SyncMessage},
 	util::{
 		db::{self, MissingFieldError},
-		error::{FileIOError, NonUtf8PathError},
+		error::{coded_error, CoreErrorCode, FileIOError, NonUtf8PathError},
 		migrator::{Migrate, MigratorError},
 		MaybeUndefined,
 	},
+	volume::{spawn_drive_health_monitor, spawn_volume_watcher},
 	NodeContext,
 };
 
@@ -28,10 +33,16 @@ use tokio::{
 	sync::{broadcast, RwLock},
 	try_join,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use super::{Library, LibraryConfig, LibraryConfigWrapped, LibraryName};
+use super::{
+	move_database, restore_backup, set_config_field, spawn_statistics_snapshots, AuditAction,
+	BackupError, BrokenLibrary, DataDirectorySettings, DatabaseSettings, IdentityKey, Library,
+	LibraryConfig, LibraryConfigWrapped, LibraryDataMoveJobInit, LibraryName, NodeRole,
+	NotificationKind, WebhookDispatcherActor,
+};
 
 pub enum SubscriberEvent {
 	Load(Uuid, Arc<Identity>, broadcast::Receiver<SyncMessage>),
@@ -60,6 +71,8 @@ pub struct LibraryManager {
 	node_context: NodeContext,
 	/// on load subscribers
 	subscribers: RwLock<Vec<Box<dyn SubscriberFn>>>,
+	/// libraries found on the node that failed to load, until they're repaired
+	broken_libraries: RwLock<Vec<BrokenLibrary>>,
 }
 
 #[derive(Error, Debug)]
@@ -92,6 +105,8 @@ pub enum LibraryManagerError {
 	LocationWatcher(#[from] LocationManagerError),
 	#[error("failed to parse library p2p identity: {0}")]
 	Identity(#[from] IdentityErr),
+	#[error("failed to decrypt library p2p identity, the library may come from another node: {0}")]
+	IdentityCrypto(#[from] sd_crypto::Error),
 	#[error("current node with id '{0}' was not found in the database")]
 	CurrentNodeNotFound(String),
 	#[error("missing-field: {0}")]
@@ -100,11 +115,23 @@ pub enum LibraryManagerError {
 
 impl From<LibraryManagerError> for rspc::Error {
 	fn from(error: LibraryManagerError) -> Self {
-		rspc::Error::with_cause(
-			rspc::ErrorCode::InternalServerError,
-			error.to_string(),
-			error,
-		)
+		let code = match error {
+			LibraryManagerError::LibraryNotFound => CoreErrorCode::LibraryNotFound,
+			LibraryManagerError::Json(_)
+			| LibraryManagerError::Migration(_)
+			| LibraryManagerError::InvalidConfig(_) => CoreErrorCode::LibraryConfigInvalid,
+			LibraryManagerError::MigratorError(_) | LibraryManagerError::MigrationError(_) => {
+				CoreErrorCode::DbMigrationFailed
+			}
+			LibraryManagerError::Identity(_) | LibraryManagerError::IdentityCrypto(_) => {
+				CoreErrorCode::LibraryIdentityInvalid
+			}
+			LibraryManagerError::Database(_) => CoreErrorCode::Database,
+			LibraryManagerError::FileIO(_) => CoreErrorCode::FileSystem,
+			_ => CoreErrorCode::Internal,
+		};
+
+		coded_error(code, error)
 	}
 }
 
@@ -118,6 +145,7 @@ impl LibraryManager {
 			.map_err(|e| FileIOError::from((&libraries_dir, e)))?;
 
 		let mut libraries = Vec::new();
+		let mut broken_libraries = Vec::new();
 		let subscribers = RwLock::new(Vec::new());
 		let mut read_dir = fs::read_dir(&libraries_dir)
 			.await
@@ -140,14 +168,28 @@ impl LibraryManager {
 					.is_file()
 			{
 				let Some(Ok(library_id)) = config_path
-				.file_stem()
-				.and_then(|v| v.to_str().map(Uuid::from_str))
-			else {
-				warn!("Attempted to load library from path '{}' but it has an invalid filename. Skipping...", config_path.display());
+					.file_stem()
+					.and_then(|v| v.to_str().map(Uuid::from_str))
+				else {
+					warn!("Attempted to load library from path '{}' but it has an invalid filename. Skipping...", config_path.display());
 					continue;
-			};
+				};
+
+				let data_directory = DataDirectorySettings::read_from_config(&config_path);
+				let db_path = data_directory.database_path(library_id, &libraries_dir);
+				if let Some(previous_db_path) = &data_directory.pending_database_move {
+					// Left pending, so the move is tried again on the next start or when repairing
+					if let Err(e) = move_database(previous_db_path, &db_path).await {
+						error!("Failed to move the database of library '{library_id}', it needs repair: {e}");
+						broken_libraries.push(BrokenLibrary::new(
+							library_id,
+							config_path,
+							&e.into(),
+						));
+						continue;
+					}
+				}
 
-				let db_path = config_path.with_extension("db");
 				match fs::metadata(&db_path).await {
 					Ok(_) => {}
 					Err(e) if e.kind() == io::ErrorKind::NotFound => {
@@ -160,17 +202,23 @@ impl LibraryManager {
 					Err(e) => return Err(FileIOError::from((db_path, e)).into()),
 				}
 
-				libraries.push(
-					Self::load(
-						library_id,
-						&db_path,
-						config_path,
-						node_context.clone(),
-						&subscribers,
-						None,
-					)
-					.await?,
-				);
+				match Self::load(
+					library_id,
+					&db_path,
+					config_path.clone(),
+					node_context.clone(),
+					&subscribers,
+					None,
+				)
+				.await
+				{
+					Ok(library) => libraries.push(library),
+					// The other libraries can still be loaded, and this one repaired
+					Err(e) => {
+						error!("Failed to load library '{library_id}', it needs repair: {e}");
+						broken_libraries.push(BrokenLibrary::new(library_id, config_path, &e));
+					}
+				}
 			}
 		}
 
@@ -179,6 +227,7 @@ impl LibraryManager {
 			libraries_dir,
 			node_context,
 			subscribers,
+			broken_libraries: RwLock::new(broken_libraries),
 		}))
 	}
 
@@ -227,7 +276,7 @@ impl LibraryManager {
 
 		let library = Self::load(
 			id,
-			self.libraries_dir.join(format!("{id}.db")),
+			config.data_directory.database_path(id, &self.libraries_dir),
 			config_path,
 			self.node_context.clone(),
 			&self.subscribers,
@@ -237,8 +286,18 @@ impl LibraryManager {
 				platform: Platform::current() as i32,
 				date_created: Local::now().into(),
 				_params: vec![
-					node::identity::set(Some(config.identity.clone())),
+					// Only the public key, as the config only keeps the identity encrypted
+					node::identity::set(Some(
+						config
+							.identity(&IdentityKey::new(&node_cfg.keypair))
+							.await?
+							.to_remote_identity()
+							.to_bytes()
+							.to_vec(),
+					)),
 					node::node_peer_id::set(Some(node_cfg.keypair.peer_id().to_string())),
+					// Invites joining the library override it with the role they grant
+					node::role::set(Some(NodeRole::Owner.as_str().to_string())),
 				],
 			}),
 		)
@@ -276,11 +335,106 @@ impl LibraryManager {
 			.collect()
 	}
 
+	pub(crate) async fn get_all_libraries(&self) -> Vec<Library> {
+		self.libraries.read().await.clone()
+	}
+
+	pub(crate) async fn get_broken_libraries(&self) -> Vec<BrokenLibrary> {
+		self.broken_libraries.read().await.clone()
+	}
+
+	/// Sets a field of the config of a library that failed to load, or resets it to its default
+	/// if no value is given, then tries to load it again. Returns why it still fails, if it does.
+	pub(crate) async fn repair(
+		&self,
+		id: Uuid,
+		field: &str,
+		value: Option<serde_json::Value>,
+	) -> Result<Option<BrokenLibrary>, LibraryManagerError> {
+		let config_path = self
+			.broken_libraries
+			.read()
+			.await
+			.iter()
+			.find(|broken| broken.id == id)
+			.map(|broken| broken.config_path.clone())
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		set_config_field(&config_path, field, value)?;
+
+		let data_directory = DataDirectorySettings::read_from_config(&config_path);
+		let db_path = data_directory.database_path(id, &self.libraries_dir);
+
+		let moved = match &data_directory.pending_database_move {
+			Some(previous_db_path) => move_database(previous_db_path, &db_path)
+				.await
+				.map_err(Into::into),
+			None => Ok(()),
+		};
+
+		let result = match moved {
+			Ok(()) => {
+				Self::load(
+					id,
+					&db_path,
+					config_path.clone(),
+					self.node_context.clone(),
+					&self.subscribers,
+					None,
+				)
+				.await
+			}
+			Err(e) => Err(e),
+		};
+
+		let mut broken_libraries = self.broken_libraries.write().await;
+		broken_libraries.retain(|broken| broken.id != id);
+
+		match result {
+			Ok(library) => {
+				info!("Repaired library '{id}'");
+
+				invalidate_query!(library, "library.list");
+				invalidate_query!(library, "library.broken");
+
+				self.libraries.write().await.push(library);
+
+				Ok(None)
+			}
+			Err(e) => {
+				let broken = BrokenLibrary::new(id, config_path, &e);
+				broken_libraries.push(broken.clone());
+
+				Ok(Some(broken))
+			}
+		}
+	}
+
 	pub(crate) async fn edit(
 		&self,
 		id: Uuid,
 		name: Option<LibraryName>,
 		description: MaybeUndefined<String>,
+	) -> Result<(), LibraryManagerError> {
+		self.update_config(id, |config| {
+			if let Some(name) = name {
+				config.name = name;
+			}
+			match description {
+				MaybeUndefined::Undefined => {}
+				MaybeUndefined::Null => config.description = None,
+				MaybeUndefined::Value(description) => config.description = Some(description),
+			}
+		})
+		.await
+	}
+
+	/// Updates and saves the config of a library, then hands the updated library to the
+	/// location manager, so the watchers don't keep using the old config
+	pub(crate) async fn update_config(
+		&self,
+		id: Uuid,
+		update: impl FnOnce(&mut LibraryConfig),
 	) -> Result<(), LibraryManagerError> {
 		// check library is valid
 		let mut libraries = self.libraries.write().await;
@@ -289,15 +443,9 @@ impl LibraryManager {
 			.find(|lib| lib.id == id)
 			.ok_or(LibraryManagerError::LibraryNotFound)?;
 
-		// update the library
-		if let Some(name) = name {
-			library.config.name = name;
-		}
-		match description {
-			MaybeUndefined::Undefined => {}
-			MaybeUndefined::Null => library.config.description = None,
-			MaybeUndefined::Value(description) => library.config.description = Some(description),
-		}
+		let previous = serde_json::to_value(&library.config).ok();
+
+		update(&mut library.config);
 
 		LibraryConfig::save(
 			&library.config,
@@ -306,6 +454,23 @@ impl LibraryManager {
 
 		invalidate_query!(library, "library.list");
 
+		// Recording the top level fields of the config that changed, like `thumbnails`
+		if let (Some(serde_json::Value::Object(previous)), Ok(serde_json::Value::Object(current))) =
+			(previous, serde_json::to_value(&library.config))
+		{
+			let settings = current
+				.into_iter()
+				.filter(|(key, value)| previous.get(key) != Some(value))
+				.map(|(key, _)| key)
+				.collect::<Vec<_>>();
+
+			if !settings.is_empty() {
+				library
+					.audit(AuditAction::SettingsChanged { settings })
+					.await;
+			}
+		}
+
 		for library in libraries.iter() {
 			for location in library
 				.db
@@ -342,7 +507,10 @@ impl LibraryManager {
 			.find(|l| l.id == id)
 			.ok_or(LibraryManagerError::LibraryNotFound)?;
 
-		let db_path = self.libraries_dir.join(format!("{}.db", library.id));
+		let db_path = library
+			.config
+			.data_directory
+			.database_in_use(library.id, &self.libraries_dir);
 		let sd_lib_path = self.libraries_dir.join(format!("{}.sdlibrary", library.id));
 
 		try_join!(
@@ -358,6 +526,8 @@ impl LibraryManager {
 			},
 		)?;
 
+		library.tasks_cancel_token.cancel();
+
 		invalidate_query!(library, "library.list");
 
 		self.libraries.write().await.retain(|l| l.id != id);
@@ -365,6 +535,101 @@ impl LibraryManager {
 		Ok(())
 	}
 
+	/// Replaces the database and config of a library by those of one of its backups, while it's
+	/// loaded
+	pub(crate) async fn restore_backup(&self, id: Uuid, name: &str) -> Result<(), BackupError> {
+		let library = self
+			.get_library(id)
+			.await
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		let db_path = library
+			.config
+			.data_directory
+			.database_in_use(id, &self.libraries_dir);
+		let restored_config = restore_backup(&library, name, &db_path).await?;
+
+		// The node of the backup can be another one, if the library was moved since, the identity
+		// of the backup is encrypted with the key of the node that made it, the backup settings
+		// are the ones that led to this backup and the data directory is where the data is now
+		let node_id = library.config.node_id;
+		let identity = library.config.identity.clone();
+		let backups = library.config.backups.clone();
+		let data_directory = library.config.data_directory.clone();
+		self.update_config(id, |config| {
+			*config = LibraryConfig {
+				node_id,
+				identity,
+				backups,
+				data_directory,
+				..restored_config
+			}
+		})
+		.await?;
+
+		invalidate_query!(library, "locations.list");
+		invalidate_query!(library, "search.paths");
+		invalidate_query!(library, "search.objects");
+		invalidate_query!(library, "tags.list");
+
+		Ok(())
+	}
+
+	/// Sets where the database, thumbnails and caches of a library are kept on this node. Returns
+	/// the job moving the thumbnails and caches there, if they aren't already, while the database
+	/// is moved when the library is next loaded.
+	pub(crate) async fn set_data_directory(
+		&self,
+		id: Uuid,
+		path: Option<PathBuf>,
+	) -> Result<Option<LibraryDataMoveJobInit>, LibraryManagerError> {
+		if let Some(path) = &path {
+			if !path.is_absolute() {
+				return Err(LibraryManagerError::InvalidConfig(
+					"the data directory must be an absolute path".to_string(),
+				));
+			}
+
+			fs::create_dir_all(path)
+				.await
+				.map_err(|e| FileIOError::from((path, e)))?;
+		}
+
+		let library = self
+			.get_library(id)
+			.await
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		if library.config.data_directory.path == path {
+			return Ok(None);
+		}
+
+		let from = library.data_directory();
+		let node_data_directory = library.config().data_directory();
+
+		self.update_config(id, |config| {
+			let settings = &mut config.data_directory;
+			let db_in_use = settings.database_in_use(id, &self.libraries_dir);
+			settings.path = path;
+			// Moving back to where the database still is cancels its move
+			settings.pending_database_move =
+				(db_in_use != settings.database_path(id, &self.libraries_dir)).then_some(db_in_use);
+		})
+		.await?;
+
+		let to = self
+			.get_library(id)
+			.await
+			.ok_or(LibraryManagerError::LibraryNotFound)?
+			.data_directory();
+
+		Ok((from != to).then(|| LibraryDataMoveJobInit {
+			from_node_directory: from == node_data_directory,
+			from,
+			to,
+		}))
+	}
+
 	// get_ctx will return the library context for the given library id.
 	pub async fn get_library(&self, library_id: Uuid) -> Option<Library> {
 		self.libraries
@@ -385,25 +650,32 @@ impl LibraryManager {
 		create: Option<node::Create>,
 	) -> Result<Library, LibraryManagerError> {
 		let db_path = db_path.as_ref();
-		let db_url = format!(
-			"file:{}?socket_timeout=15",
-			db_path.as_os_str().to_str().ok_or_else(|| {
-				LibraryManagerError::NonUtf8Path(NonUtf8PathError(db_path.into()))
-			})?
-		);
-		let db = Arc::new(db::load_and_migrate(&db_url).await?);
+		let database_settings = DatabaseSettings::read_from_config(&config_path);
+		let db = Arc::new(db::load_and_migrate(&database_settings.db_url(db_path)?).await?);
+		database_settings.apply(&db).await?;
 
 		if let Some(create) = create {
 			create.to_query(&db).exec().await?;
 		}
 
 		let node_config = node_context.config.get().await;
-		let config = LibraryConfig::load_and_migrate(
+		let identity_key = IdentityKey::new(&node_config.keypair);
+		let mut config = LibraryConfig::load_and_migrate(
 			&config_path,
-			&(node_config.id, node_config.keypair.peer_id(), db.clone()),
+			&(
+				node_config.id,
+				node_config.keypair.peer_id(),
+				db.clone(),
+				identity_key.clone(),
+			),
 		)
 		.await?;
-		let identity = Arc::new(Identity::from_bytes(&config.identity)?);
+		let identity = Arc::new(config.identity(&identity_key).await?);
+
+		// The database was moved to the data directory before being opened
+		if config.data_directory.pending_database_move.take().is_some() {
+			config.save(&config_path)?;
+		}
 
 		let node_data = db
 			.node()
@@ -472,6 +744,14 @@ impl LibraryManager {
 			// key_manager,
 			sync: Arc::new(sync_manager),
 			orphan_remover: OrphanRemoverActor::spawn(db.clone()),
+			thumbnail_prioritizer: ThumbnailPrioritizerActor::spawn(),
+			webhooks: WebhookDispatcherActor::spawn(
+				id,
+				db.clone(),
+				node_context.event_bus_tx.clone(),
+			),
+			undo_history: UndoHistory::default(),
+			tasks_cancel_token: CancellationToken::new(),
 			db,
 			node_local_id: node_data.id,
 			node_context,
@@ -497,6 +777,26 @@ impl LibraryManager {
 			};
 		}
 
+		library
+			.node_context
+			.job_manager
+			.set_library_job_settings(id, library.config.jobs)
+			.await;
+
+		// Before jobs are resumed, as their file operations go to the same journal
+		match journal::set_aside_interrupted_operations(&library).await {
+			Ok(0) => {}
+			Ok(count) => {
+				warn!("Library <id='{id}'> has {count} interrupted file operations");
+				library
+					.notify(NotificationKind::InterruptedFileOperations {
+						count: count as u32,
+					})
+					.await;
+			}
+			Err(e) => error!("Failed to check the file operations journal: {e:#?}"),
+		}
+
 		if let Err(e) = library
 			.node_context
 			.job_manager
@@ -507,6 +807,13 @@ impl LibraryManager {
 			error!("Failed to resume jobs for library. {:#?}", e);
 		}
 
+		spawn_scheduler(library.clone());
+		spawn_retention_cleanup(library.clone());
+		spawn_deleted_file_paths_purge(library.clone());
+		spawn_statistics_snapshots(library.clone());
+		spawn_drive_health_monitor(library.clone());
+		spawn_volume_watcher(library.clone());
+
 		Ok(library)
 	}
 }
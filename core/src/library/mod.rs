@@ -1,12 +1,34 @@
+mod audit;
+mod backup;
 pub(crate) mod cat;
 mod config;
+mod data_directory;
+mod database;
 #[allow(clippy::module_inception)]
 mod library;
+mod maintenance;
 mod manager;
 mod name;
+mod notifications;
+mod repair;
+mod roles;
+mod settings;
+mod statistics;
+mod webhooks;
 
+pub use audit::*;
+pub use backup::*;
 pub use cat::*;
 pub use config::*;
+pub use data_directory::*;
+pub use database::*;
 pub use library::*;
+pub use maintenance::*;
 pub use manager::*;
 pub use name::*;
+pub use notifications::*;
+pub use repair::*;
+pub use roles::*;
+pub use settings::*;
+pub use statistics::*;
+pub use webhooks::*;
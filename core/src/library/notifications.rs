@@ -0,0 +1,466 @@
+//! Notifications of what happened in a library that users should know about, like a job finishing
+//! or a drive running out of space. They're kept in the library database until deleted, with
+//! their read state, and streamed to the clients as they're raised.
+
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	job::JobStatus,
+	node::{deliver_notification, NotificationMessage},
+	prisma::{location, notification, SortOrder},
+};
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tracing::error;
+use uuid::Uuid;
+
+use super::Library;
+
+#[derive(
+	Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "camelCase")]
+#[repr(i32)]
+pub enum NotificationSeverity {
+	Info = 0,
+	Warning = 1,
+	Error = 2,
+}
+
+impl NotificationSeverity {
+	fn from_i32(value: i32) -> Self {
+		match value {
+			2 => Self::Error,
+			1 => Self::Warning,
+			_ => Self::Info,
+		}
+	}
+}
+
+#[derive(
+	Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationCategory {
+	JobCompleted,
+	JobFailed,
+	DeviceOnline,
+	LowDiskSpace,
+	IntegrityFailure,
+	DriveHealth,
+	LocationOffline,
+	InterruptedFileOperations,
+	MalwareDetected,
+}
+
+impl NotificationCategory {
+	/// Value of the `category` column of the notifications of this category
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::JobCompleted => "jobCompleted",
+			Self::JobFailed => "jobFailed",
+			Self::DeviceOnline => "deviceOnline",
+			Self::LowDiskSpace => "lowDiskSpace",
+			Self::IntegrityFailure => "integrityFailure",
+			Self::DriveHealth => "driveHealth",
+			Self::LocationOffline => "locationOffline",
+			Self::InterruptedFileOperations => "interruptedFileOperations",
+			Self::MalwareDetected => "malwareDetected",
+		}
+	}
+
+	pub fn default_severity(&self) -> NotificationSeverity {
+		match self {
+			Self::JobCompleted | Self::DeviceOnline => NotificationSeverity::Info,
+			Self::LowDiskSpace | Self::LocationOffline | Self::InterruptedFileOperations => {
+				NotificationSeverity::Warning
+			}
+			Self::JobFailed
+			| Self::IntegrityFailure
+			| Self::DriveHealth
+			| Self::MalwareDetected => NotificationSeverity::Error,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum NotificationKind {
+	JobCompleted {
+		job_id: Uuid,
+		name: String,
+		status: JobStatus,
+	},
+	/// A device paired with this library was found on the network
+	DeviceOnline {
+		node_pub_id: Uuid,
+		name: String,
+	},
+	LowDiskSpace {
+		mount_point: String,
+		#[specta(type = String)]
+		available_bytes: u64,
+		#[specta(type = String)]
+		total_bytes: u64,
+	},
+	/// The content of a file doesn't match its checksum anymore, without it being modified. The
+	/// file is quarantined until it's repaired or released.
+	IntegrityFailure {
+		location_id: location::id::Type,
+		path: String,
+		/// Whether another node has a good copy it can be repaired from
+		#[serde(default)]
+		repairable: bool,
+	},
+	/// The drive backing a location shows pre-failure indicators
+	DriveHealthWarning {
+		location_id: location::id::Type,
+		device: String,
+		warnings: Vec<String>,
+	},
+	LocationOffline {
+		location_id: location::id::Type,
+	},
+	/// File operations were cut short by a crash, and are waiting to be finished or rolled back
+	InterruptedFileOperations {
+		count: u32,
+	},
+	/// The antivirus scanner flagged a file added to a location
+	MalwareDetected {
+		location_id: location::id::Type,
+		path: String,
+		/// Name of what the scanner found, like `Eicar-Signature`
+		signature: String,
+	},
+}
+
+impl NotificationKind {
+	pub fn category(&self) -> NotificationCategory {
+		match self {
+			Self::JobCompleted {
+				status: JobStatus::Failed,
+				..
+			} => NotificationCategory::JobFailed,
+			Self::JobCompleted { .. } => NotificationCategory::JobCompleted,
+			Self::DeviceOnline { .. } => NotificationCategory::DeviceOnline,
+			Self::LowDiskSpace { .. } => NotificationCategory::LowDiskSpace,
+			Self::IntegrityFailure { .. } => NotificationCategory::IntegrityFailure,
+			Self::DriveHealthWarning { .. } => NotificationCategory::DriveHealth,
+			Self::LocationOffline { .. } => NotificationCategory::LocationOffline,
+			Self::InterruptedFileOperations { .. } => {
+				NotificationCategory::InterruptedFileOperations
+			}
+			Self::MalwareDetected { .. } => NotificationCategory::MalwareDetected,
+		}
+	}
+
+	/// Short summary of the notification, for where it's shown outside of the app
+	pub fn title(&self) -> String {
+		match self {
+			Self::JobCompleted {
+				status: JobStatus::Failed,
+				..
+			} => "Job failed".to_string(),
+			Self::JobCompleted { .. } => "Job completed".to_string(),
+			Self::DeviceOnline { name, .. } => format!("{name} is online"),
+			Self::LowDiskSpace { .. } => "Low disk space".to_string(),
+			Self::IntegrityFailure { .. } => "Integrity check failed".to_string(),
+			Self::DriveHealthWarning { .. } => "Drive health warning".to_string(),
+			Self::LocationOffline { .. } => "Location offline".to_string(),
+			Self::InterruptedFileOperations { .. } => "Interrupted file operations".to_string(),
+			Self::MalwareDetected { .. } => "Malware detected".to_string(),
+		}
+	}
+
+	pub fn body(&self) -> String {
+		match self {
+			Self::JobCompleted { name, status, .. } => {
+				format!("Job '{name}' finished with status {status:?}")
+			}
+			Self::DeviceOnline { name, .. } => format!("Device '{name}' was found on the network"),
+			Self::LowDiskSpace {
+				mount_point,
+				available_bytes,
+				total_bytes,
+			} => format!(
+				"Only {} MiB of {} MiB are left on '{mount_point}'",
+				available_bytes / 1024 / 1024,
+				total_bytes / 1024 / 1024
+			),
+			Self::IntegrityFailure {
+				location_id,
+				path,
+				repairable,
+			} => format!(
+				"The content of '{path}' in location {location_id} doesn't match its checksum{}",
+				if *repairable {
+					", it can be repaired from a good copy on another device"
+				} else {
+					""
+				}
+			),
+			Self::DriveHealthWarning {
+				location_id,
+				device,
+				warnings,
+			} => format!(
+				"Drive '{device}' backing location {location_id} shows pre-failure indicators: {}",
+				warnings.join(", ")
+			),
+			Self::LocationOffline { location_id } => {
+				format!("Location {location_id} can't be reached anymore")
+			}
+			Self::InterruptedFileOperations { count } => format!(
+				"{count} file operations were interrupted, they can be finished or rolled back"
+			),
+			Self::MalwareDetected {
+				location_id,
+				path,
+				signature,
+			} => format!("'{path}' in location {location_id} was flagged as '{signature}'"),
+		}
+	}
+}
+
+/// Severities chosen by the user for some categories, the others keeping their default one
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationSettings {
+	#[serde(default)]
+	pub severities: BTreeMap<NotificationCategory, NotificationSeverity>,
+}
+
+impl NotificationSettings {
+	pub fn severity(&self, category: NotificationCategory) -> NotificationSeverity {
+		self.severities
+			.get(&category)
+			.copied()
+			.unwrap_or_else(|| category.default_severity())
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum NotificationError {
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+}
+
+impl From<NotificationError> for rspc::Error {
+	fn from(err: NotificationError) -> Self {
+		rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationItem {
+	pub id: notification::id::Type,
+	pub category: String,
+	pub severity: NotificationSeverity,
+	/// The serialized [`NotificationKind`], kept as is so notifications of categories this
+	/// version of the app doesn't know can still be listed
+	pub data: Option<serde_json::Value>,
+	pub read: bool,
+	pub date_created: DateTime<FixedOffset>,
+}
+
+impl From<notification::Data> for NotificationItem {
+	fn from(notification: notification::Data) -> Self {
+		Self {
+			id: notification.id,
+			category: notification.category,
+			severity: NotificationSeverity::from_i32(notification.severity),
+			data: serde_json::from_slice(&notification.data).ok(),
+			read: notification.read,
+			date_created: notification.date_created,
+		}
+	}
+}
+
+/// A notification raised in a library, as streamed to the clients
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationEvent {
+	pub library_id: Uuid,
+	pub notification: NotificationItem,
+}
+
+impl Library {
+	/// Raises a notification, with the severity of its category in the library settings. Failing
+	/// to raise it is only logged, as whatever it's about already happened.
+	pub async fn notify(&self, kind: NotificationKind) {
+		if let Err(e) = self.create_notification(&kind).await {
+			error!("Failed to raise notification {kind:?}: {e:#?}");
+		}
+	}
+
+	async fn create_notification(&self, kind: &NotificationKind) -> Result<(), NotificationError> {
+		let category = kind.category();
+		let severity = self.config.notifications.severity(category);
+
+		let notification = self
+			.db
+			.notification()
+			.create(
+				category.as_str().to_string(),
+				severity as i32,
+				serde_json::to_vec(kind)?,
+				vec![],
+			)
+			.exec()
+			.await?;
+
+		invalidate_query!(self, "notifications.list");
+		invalidate_query!(self, "notifications.unreadCount");
+
+		let notification = NotificationItem::from(notification);
+
+		// Delivered outside of the app in the background, as providers can be slow to respond
+		tokio::spawn(deliver_notification(
+			self.config().get().await.notification_providers,
+			NotificationMessage {
+				library_id: self.id,
+				library_name: self.config.name.to_string(),
+				title: kind.title(),
+				body: kind.body(),
+				notification: notification.clone(),
+			},
+		));
+
+		self.emit(CoreEvent::Notification(NotificationEvent {
+			library_id: self.id,
+			notification,
+		}));
+
+		Ok(())
+	}
+}
+
+/// Notifications of the library, newest first
+pub async fn list_notifications(
+	library: &Library,
+	unread_only: bool,
+	take: i64,
+	cursor: Option<notification::id::Type>,
+) -> Result<Vec<NotificationItem>, NotificationError> {
+	let mut params = vec![];
+	if unread_only {
+		params.push(notification::read::equals(false));
+	}
+
+	let mut query = library
+		.db
+		.notification()
+		.find_many(params)
+		.order_by(notification::id::order(SortOrder::Desc))
+		.take(take);
+
+	if let Some(cursor) = cursor {
+		query = query.cursor(notification::id::equals(cursor));
+	}
+
+	Ok(query.exec().await?.into_iter().map(Into::into).collect())
+}
+
+pub async fn unread_notifications_count(library: &Library) -> Result<i64, NotificationError> {
+	Ok(library
+		.db
+		.notification()
+		.count(vec![notification::read::equals(false)])
+		.exec()
+		.await?)
+}
+
+/// Marks the given notifications as read or unread, or all of them if no ids are given
+pub async fn set_notifications_read(
+	library: &Library,
+	ids: Option<Vec<notification::id::Type>>,
+	read: bool,
+) -> Result<i64, NotificationError> {
+	let updated = library
+		.db
+		.notification()
+		.update_many(
+			ids.map(|ids| vec![notification::id::in_vec(ids)])
+				.unwrap_or_default(),
+			vec![notification::read::set(read)],
+		)
+		.exec()
+		.await?;
+
+	invalidate_query!(library, "notifications.list");
+	invalidate_query!(library, "notifications.unreadCount");
+
+	Ok(updated)
+}
+
+/// Deletes the given notifications, or all the read ones if no ids are given
+pub async fn delete_notifications(
+	library: &Library,
+	ids: Option<Vec<notification::id::Type>>,
+) -> Result<i64, NotificationError> {
+	let deleted = library
+		.db
+		.notification()
+		.delete_many(vec![match ids {
+			Some(ids) => notification::id::in_vec(ids),
+			None => notification::read::equals(true),
+		}])
+		.exec()
+		.await?;
+
+	invalidate_query!(library, "notifications.list");
+	invalidate_query!(library, "notifications.unreadCount");
+
+	Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn severity_of_category() {
+		let settings = NotificationSettings {
+			severities: BTreeMap::from([(
+				NotificationCategory::JobCompleted,
+				NotificationSeverity::Warning,
+			)]),
+		};
+
+		assert_eq!(
+			settings.severity(NotificationCategory::JobCompleted),
+			NotificationSeverity::Warning
+		);
+		assert_eq!(
+			settings.severity(NotificationCategory::IntegrityFailure),
+			NotificationSeverity::Error
+		);
+	}
+
+	#[test]
+	fn failed_jobs_have_their_own_category() {
+		let kind = |status| NotificationKind::JobCompleted {
+			job_id: Uuid::nil(),
+			name: "indexer".to_string(),
+			status,
+		};
+
+		assert_eq!(
+			kind(JobStatus::Failed).category(),
+			NotificationCategory::JobFailed
+		);
+		assert_eq!(
+			kind(JobStatus::Completed).category(),
+			NotificationCategory::JobCompleted
+		);
+	}
+}
@@ -0,0 +1,108 @@
+//! Libraries that failed to load are still listed, so they can be repaired instead of silently
+//! disappearing. When their config has an invalid field, the field and its value are reported,
+//! and it can be set to a valid value, or reset to its default, before loading them again.
+
+use crate::util::{
+	error::FileIOError,
+	migrator::{Migrate, MigrationInfo, MigratorError},
+};
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+use specta::Type;
+use uuid::Uuid;
+
+use super::{LibraryConfig, LibraryManagerError};
+
+/// A library found on this node that failed to load
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenLibrary {
+	pub id: Uuid,
+	pub config_path: PathBuf,
+	pub error: String,
+	/// Set if loading failed because of a field of the config
+	pub invalid_field: Option<InvalidConfigField>,
+	/// Migrations its config still has to go through, the first of them being the one that failed
+	/// if loading failed while migrating
+	pub pending_migrations: Vec<MigrationInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct InvalidConfigField {
+	/// Dotted path of the field from the root of the config, like `thumbnails.format`
+	pub field: String,
+	/// `null` if the field is missing
+	pub value: Value,
+}
+
+impl BrokenLibrary {
+	pub(super) fn new(id: Uuid, config_path: PathBuf, error: &LibraryManagerError) -> Self {
+		let invalid_field = match error {
+			LibraryManagerError::MigratorError(MigratorError::InvalidField {
+				field,
+				value,
+				..
+			}) if !field.is_empty() => Some(InvalidConfigField {
+				field: field.clone(),
+				value: value.clone(),
+			}),
+			_ => None,
+		};
+
+		Self {
+			id,
+			pending_migrations: LibraryConfig::pending_migrations(&config_path).unwrap_or_default(),
+			config_path,
+			error: error.to_string(),
+			invalid_field,
+		}
+	}
+}
+
+/// Sets a field of the config file of a library, given as a dotted path from its root, or removes
+/// it to fall back to its default
+pub(super) fn set_config_field(
+	config_path: &Path,
+	field: &str,
+	value: Option<Value>,
+) -> Result<(), LibraryManagerError> {
+	// Set by the migrator only
+	if field == "version" {
+		return Err(LibraryManagerError::InvalidConfig(
+			"the version of the config can't be changed".to_string(),
+		));
+	}
+
+	let mut config = serde_json::from_slice::<Value>(
+		&std::fs::read(config_path).map_err(|e| FileIOError::from((config_path, e)))?,
+	)?;
+
+	let not_found =
+		|| LibraryManagerError::InvalidConfig(format!("field '{field}' not found in the config"));
+
+	let (parent_path, key) = field.rsplit_once('.').unwrap_or(("", field));
+	let parent = if parent_path.is_empty() {
+		Some(&mut config)
+	} else {
+		config.pointer_mut(&format!("/{}", parent_path.replace('.', "/")))
+	}
+	.and_then(Value::as_object_mut)
+	.ok_or_else(not_found)?;
+
+	match value {
+		Some(value) => {
+			parent.insert(key.to_string(), value);
+		}
+		None => {
+			parent.remove(key);
+		}
+	}
+
+	std::fs::write(config_path, serde_json::to_vec(&config)?)
+		.map_err(|e| FileIOError::from((config_path, e)))?;
+
+	Ok(())
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// What a node can do in a library
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeRole {
+	/// Manages the library, inviting other nodes and changing their roles
+	Owner,
+	/// Changes the files and metadata of the library
+	Editor,
+	/// Only browses, previews and searches the library
+	Viewer,
+}
+
+impl NodeRole {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Owner => "owner",
+			Self::Editor => "editor",
+			Self::Viewer => "viewer",
+		}
+	}
+
+	pub fn parse(s: &str) -> Option<Self> {
+		match s {
+			"owner" => Some(Self::Owner),
+			"editor" => Some(Self::Editor),
+			"viewer" => Some(Self::Viewer),
+			_ => None,
+		}
+	}
+
+	/// Role of a node from its record, only nodes paired before roles existed having none
+	/// and being owners. A role that can't be parsed only grants viewing.
+	pub fn of_node(role: Option<&str>) -> Self {
+		role.map_or(Self::Owner, |role| {
+			Self::parse(role).unwrap_or(Self::Viewer)
+		})
+	}
+
+	/// Whether a node with this role can do what the `required` one can
+	pub fn grants(self, required: Self) -> bool {
+		let rank = |role| match role {
+			Self::Owner => 2,
+			Self::Editor => 1,
+			Self::Viewer => 0,
+		};
+
+		rank(self) >= rank(required)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn roles_grant_the_lower_ones() {
+		assert!(NodeRole::Owner.grants(NodeRole::Editor));
+		assert!(NodeRole::Editor.grants(NodeRole::Editor));
+		assert!(!NodeRole::Viewer.grants(NodeRole::Editor));
+		assert!(!NodeRole::Editor.grants(NodeRole::Owner));
+		assert_eq!(NodeRole::of_node(None), NodeRole::Owner);
+		assert_eq!(NodeRole::of_node(Some("viewer")), NodeRole::Viewer);
+		assert_eq!(NodeRole::of_node(Some("admin")), NodeRole::Viewer);
+	}
+}
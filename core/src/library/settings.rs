@@ -0,0 +1,76 @@
+//! Library-wide preferences, kept in its config so they follow the library to every client and
+//! node instead of living in the local storage of one of them. Clients use them as the defaults
+//! of views that don't have their own.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibrarySettings {
+	#[serde(default)]
+	pub explorer: ExplorerSettings,
+}
+
+/// How the explorer lists the files of the library, until changed in a view
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExplorerSettings {
+	pub layout_mode: ExplorerLayoutMode,
+	pub order_by: ExplorerOrdering,
+	pub order_direction: ExplorerOrderDirection,
+	/// Width of the items in the grid layout, in pixels
+	pub grid_item_size: u16,
+	/// Height of the rows in the list layout, in pixels
+	pub list_item_size: u16,
+	pub show_bytes_in_grid_view: bool,
+	/// Number of items in each row of the media layout
+	pub media_columns: u8,
+	/// Whether the media layout crops the items to squares, rather than keeping their aspect ratio
+	pub media_aspect_square: bool,
+}
+
+impl Default for ExplorerSettings {
+	fn default() -> Self {
+		Self {
+			layout_mode: ExplorerLayoutMode::Grid,
+			order_by: ExplorerOrdering::DateCreated,
+			order_direction: ExplorerOrderDirection::Desc,
+			grid_item_size: 110,
+			list_item_size: 40,
+			show_bytes_in_grid_view: true,
+			media_columns: 8,
+			media_aspect_square: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExplorerLayoutMode {
+	Grid,
+	Rows,
+	Columns,
+	Media,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExplorerOrdering {
+	Name,
+	SizeInBytes,
+	DateCreated,
+	DateModified,
+	DateIndexed,
+	DateAccessed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExplorerOrderDirection {
+	// Configs written before the rename have them capitalized
+	#[serde(alias = "Asc")]
+	Asc,
+	#[serde(alias = "Desc")]
+	Desc,
+}
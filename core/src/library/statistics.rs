@@ -0,0 +1,279 @@
+//! Daily snapshots of how many bytes and files a library and each of its locations hold, broken
+//! down by kind, so their growth can be charted over time.
+
+use crate::{
+	invalidate_query,
+	prisma::{file_path, location, statistics_snapshot, SortOrder},
+};
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	time::Duration,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use sd_file_ext::kind::ObjectKind;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error};
+
+use super::Library;
+
+/// How often it's checked whether today's snapshot was taken
+const SNAPSHOT_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How many file paths are read from the database at a time while counting
+const SNAPSHOT_BATCH_SIZE: i64 = 10_000;
+
+/// Files and bytes of a kind of file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct KindStatistics {
+	/// Enum: sd_file_ext::kind::ObjectKind
+	pub kind: i32,
+	pub file_count: u32,
+	#[specta(type = String)]
+	pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct StatisticsSnapshot {
+	pub date: DateTime<Utc>,
+	pub location_id: Option<location::id::Type>,
+	#[specta(type = String)]
+	pub total_bytes: u64,
+	pub file_count: i32,
+	pub kinds: Vec<KindStatistics>,
+}
+
+impl From<statistics_snapshot::Data> for StatisticsSnapshot {
+	fn from(snapshot: statistics_snapshot::Data) -> Self {
+		Self {
+			date: snapshot.date.into(),
+			location_id: snapshot.location_id,
+			total_bytes: snapshot.total_bytes.parse().unwrap_or_default(),
+			file_count: snapshot.file_count,
+			kinds: snapshot
+				.kinds
+				.and_then(|kinds| serde_json::from_slice(&kinds).ok())
+				.unwrap_or_default(),
+		}
+	}
+}
+
+#[derive(Default)]
+struct Totals {
+	total_bytes: u64,
+	file_count: u32,
+	kinds: BTreeMap<i32, KindStatistics>,
+}
+
+impl Totals {
+	fn add(&mut self, kind: i32, size_in_bytes: u64) {
+		self.total_bytes += size_in_bytes;
+		self.file_count += 1;
+
+		let kind_statistics = self.kinds.entry(kind).or_insert_with(|| KindStatistics {
+			kind,
+			..Default::default()
+		});
+		kind_statistics.total_bytes += size_in_bytes;
+		kind_statistics.file_count += 1;
+	}
+}
+
+/// Midnight UTC of today, which snapshots are dated with
+fn today() -> DateTime<Utc> {
+	Utc.from_utc_datetime(
+		&Utc::now()
+			.date_naive()
+			.and_hms_opt(0, 0, 0)
+			.expect("midnight is a valid time"),
+	)
+}
+
+file_path::select!(file_path_for_statistics {
+	id
+	location_id
+	size_in_bytes_bytes
+	object: select { kind }
+});
+
+/// Counts the files of the library and of each of its locations, replacing today's snapshots
+pub async fn take_statistics_snapshot(library: &Library) -> prisma_client_rust::Result<()> {
+	let db = &library.db;
+
+	// Totals of the whole library are kept under `None`
+	let mut totals = HashMap::<Option<location::id::Type>, Totals>::new();
+	totals.insert(None, Totals::default());
+
+	let mut cursor = 0;
+	loop {
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::id::gt(cursor),
+				file_path::is_dir::equals(Some(false)),
+				file_path::date_deleted::equals(None),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(SNAPSHOT_BATCH_SIZE)
+			.select(file_path_for_statistics::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		for file_path in file_paths {
+			let size_in_bytes = file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.and_then(|bytes| bytes.try_into().ok())
+				.map_or(0, u64::from_be_bytes);
+			let kind = file_path
+				.object
+				.and_then(|object| object.kind)
+				.unwrap_or(ObjectKind::Unknown as i32);
+
+			totals.entry(None).or_default().add(kind, size_in_bytes);
+			if let Some(location_id) = file_path.location_id {
+				totals
+					.entry(Some(location_id))
+					.or_default()
+					.add(kind, size_in_bytes);
+			}
+		}
+	}
+
+	// Locations without any file get a snapshot too, so their charts don't have holes
+	for location in db
+		.location()
+		.find_many(vec![])
+		.select(location::select!({ id }))
+		.exec()
+		.await?
+	{
+		totals.entry(Some(location.id)).or_default();
+	}
+
+	let date = today();
+
+	for (location_id, totals) in totals {
+		let kinds = serde_json::to_vec(&totals.kinds.into_values().collect::<Vec<_>>())
+			.expect("statistics of kinds are always serializable");
+
+		db._batch((
+			db.statistics_snapshot().delete_many(vec![
+				statistics_snapshot::date::equals(date.into()),
+				statistics_snapshot::location_id::equals(location_id),
+			]),
+			db.statistics_snapshot().create(
+				date.into(),
+				vec![
+					statistics_snapshot::location_id::set(location_id),
+					statistics_snapshot::total_bytes::set(totals.total_bytes.to_string()),
+					statistics_snapshot::file_count::set(totals.file_count as i32),
+					statistics_snapshot::kinds::set(Some(kinds)),
+				],
+			),
+		))
+		.await?;
+	}
+
+	invalidate_query!(library, "library.statisticsHistory");
+
+	Ok(())
+}
+
+/// Snapshots of the library, or of one of its locations, oldest first
+pub async fn statistics_history(
+	library: &Library,
+	location_id: Option<location::id::Type>,
+	from: Option<DateTime<Utc>>,
+	to: Option<DateTime<Utc>>,
+) -> prisma_client_rust::Result<Vec<StatisticsSnapshot>> {
+	let mut params = vec![statistics_snapshot::location_id::equals(location_id)];
+	if let Some(from) = from {
+		params.push(statistics_snapshot::date::gte(from.into()));
+	}
+	if let Some(to) = to {
+		params.push(statistics_snapshot::date::lte(to.into()));
+	}
+
+	Ok(library
+		.db
+		.statistics_snapshot()
+		.find_many(params)
+		.order_by(statistics_snapshot::date::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+/// Spawns a loop that takes the snapshots of the library once a day
+pub fn spawn_statistics_snapshots(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(SNAPSHOT_CHECK_INTERVAL);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			tick.tick().await;
+
+			let taken_today = library
+				.db
+				.statistics_snapshot()
+				.count(vec![
+					statistics_snapshot::date::equals(today().into()),
+					statistics_snapshot::location_id::equals(None),
+				])
+				.exec()
+				.await;
+
+			match taken_today {
+				Ok(0) => match take_statistics_snapshot(&library).await {
+					Ok(()) => debug!(
+						"Took the statistics snapshot of library <id='{}'>",
+						library.id
+					),
+					Err(e) => error!(
+						"Failed to take the statistics snapshot of library <id='{}'>: {e:#?}",
+						library.id
+					),
+				},
+				Ok(_) => {}
+				Err(e) => error!(
+					"Failed to check the statistics snapshots of library <id='{}'>: {e:#?}",
+					library.id
+				),
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn totals_by_kind() {
+		let mut totals = Totals::default();
+		totals.add(ObjectKind::Image as i32, 100);
+		totals.add(ObjectKind::Image as i32, 50);
+		totals.add(ObjectKind::Video as i32, 1000);
+
+		assert_eq!(totals.total_bytes, 1150);
+		assert_eq!(totals.file_count, 3);
+		assert_eq!(
+			totals.kinds[&(ObjectKind::Image as i32)],
+			KindStatistics {
+				kind: ObjectKind::Image as i32,
+				file_count: 2,
+				total_bytes: 150,
+			}
+		);
+	}
+}
@@ -0,0 +1,320 @@
+use crate::{
+	api::CoreEvent,
+	job::JobStatus,
+	prisma::{location, webhook, PrismaClient},
+	volume::DriveHealthStatus,
+};
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode, Url};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	sync::{broadcast, mpsc},
+	time::sleep,
+};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+/// Deliveries are attempted this many times before being dropped
+const MAX_DELIVERY_ATTEMPTS: u32 = 6;
+/// Delay before the first retry, doubled on each following one
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events of a library that webhooks can be notified of
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WebhookEvent {
+	JobCompleted {
+		job_id: Uuid,
+		name: String,
+		status: JobStatus,
+	},
+	/// A location of this node can't be reached anymore, like an unmounted drive
+	LocationOffline {
+		location_id: location::id::Type,
+		location_pub_id: Uuid,
+	},
+	DevicePaired {
+		node_pub_id: Uuid,
+		name: String,
+	},
+	/// The content of a file doesn't match its checksum anymore, without it being modified
+	IntegrityFailure {
+		location_id: location::id::Type,
+		path: String,
+	},
+	/// The drive backing a location of this node shows pre-failure indicators
+	DriveHealthWarning {
+		location_id: location::id::Type,
+		location_pub_id: Uuid,
+		device: String,
+		status: DriveHealthStatus,
+		warnings: Vec<String>,
+	},
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookEventKind {
+	JobCompleted,
+	LocationOffline,
+	DevicePaired,
+	IntegrityFailure,
+	DriveHealthWarning,
+}
+
+/// An event of a library, as published on the event bus of the node
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEvent {
+	pub library_id: Uuid,
+	pub event: WebhookEvent,
+}
+
+impl WebhookEvent {
+	pub fn kind(&self) -> WebhookEventKind {
+		match self {
+			Self::JobCompleted { .. } => WebhookEventKind::JobCompleted,
+			Self::LocationOffline { .. } => WebhookEventKind::LocationOffline,
+			Self::DevicePaired { .. } => WebhookEventKind::DevicePaired,
+			Self::IntegrityFailure { .. } => WebhookEventKind::IntegrityFailure,
+			Self::DriveHealthWarning { .. } => WebhookEventKind::DriveHealthWarning,
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+	#[error("webhook not found <id='{0}'>")]
+	IdNotFound(webhook::id::Type),
+	#[error("invalid webhook url '{0}', it must be an http or https one")]
+	InvalidUrl(String),
+	#[error(transparent)]
+	Serialization(#[from] serde_json::Error),
+}
+
+impl From<WebhookError> for rspc::Error {
+	fn from(err: WebhookError) -> Self {
+		match err {
+			WebhookError::IdNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			WebhookError::InvalidUrl(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Makes sure payloads are only ever posted to http(s) urls
+pub fn validate_webhook_url(url: &str) -> Result<(), WebhookError> {
+	match Url::parse(url) {
+		Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+		_ => Err(WebhookError::InvalidUrl(url.to_string())),
+	}
+}
+
+/// Generates the secret the payloads of a new webhook are signed with
+pub fn generate_webhook_secret() -> String {
+	format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Signs `{timestamp}.{body}` with the secret of the webhook, so receivers can check that the
+/// payload comes from this library and isn't replayed
+fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+	mac.update(timestamp.to_string().as_bytes());
+	mac.update(b".");
+	mac.update(body);
+
+	format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// Actor that delivers the events of a library to its webhooks, retrying with an exponential
+// backoff the deliveries that failed. Events are also published on the event bus, for the
+// clients of the node.
+#[derive(Clone)]
+pub struct WebhookDispatcherActor {
+	library_id: Uuid,
+	tx: mpsc::Sender<WebhookEvent>,
+	event_bus_tx: broadcast::Sender<CoreEvent>,
+}
+
+impl WebhookDispatcherActor {
+	pub fn spawn(
+		library_id: Uuid,
+		db: Arc<PrismaClient>,
+		event_bus_tx: broadcast::Sender<CoreEvent>,
+	) -> Self {
+		let (tx, mut rx) = mpsc::channel::<WebhookEvent>(256);
+
+		tokio::spawn(async move {
+			let client = match Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+				Ok(client) => client,
+				Err(e) => {
+					error!("Failed to build the webhooks HTTP client: {e:#?}");
+					return;
+				}
+			};
+
+			while let Some(event) = rx.recv().await {
+				let webhooks = match db
+					.webhook()
+					.find_many(vec![webhook::enabled::equals(Some(true))])
+					.exec()
+					.await
+				{
+					Ok(webhooks) => webhooks,
+					Err(e) => {
+						error!("Failed to fetch webhooks: {e:#?}");
+						continue;
+					}
+				};
+
+				let kind = event.kind();
+				let body = match serde_json::to_vec(&json!({
+					"id": Uuid::new_v4(),
+					"libraryId": library_id,
+					"date": Utc::now(),
+					"event": event,
+				})) {
+					Ok(body) => Arc::new(body),
+					Err(e) => {
+						error!("Failed to serialize webhook event: {e:#?}");
+						continue;
+					}
+				};
+
+				for webhook in webhooks {
+					let events = webhook
+						.events
+						.as_deref()
+						.and_then(|events| {
+							serde_json::from_slice::<Vec<WebhookEventKind>>(events).ok()
+						})
+						.unwrap_or_default();
+					if !events.is_empty() && !events.contains(&kind) {
+						continue;
+					}
+
+					let (Some(url), Some(secret)) = (webhook.url, webhook.secret) else {
+						continue;
+					};
+
+					tokio::spawn(deliver(client.clone(), url, secret, kind, body.clone()));
+				}
+			}
+		});
+
+		Self {
+			library_id,
+			tx,
+			event_bus_tx,
+		}
+	}
+
+	/// Queues the event for delivery, without waiting for it
+	pub fn dispatch(&self, event: WebhookEvent) {
+		// No one listening to the event bus isn't an error
+		self.event_bus_tx
+			.send(CoreEvent::LibraryEvent(LibraryEvent {
+				library_id: self.library_id,
+				event: event.clone(),
+			}))
+			.ok();
+
+		if let Err(e) = self.tx.try_send(event) {
+			warn!("Dropping webhook event, as the dispatcher is overwhelmed or gone: {e}");
+		}
+	}
+}
+
+async fn deliver(
+	client: Client,
+	url: String,
+	secret: String,
+	kind: WebhookEventKind,
+	body: Arc<Vec<u8>>,
+) {
+	let mut retry_delay = INITIAL_RETRY_DELAY;
+	let event_name = json!(kind).as_str().unwrap_or_default().to_string();
+
+	for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+		// Signed on each attempt, so receivers can reject stale timestamps
+		let timestamp = Utc::now().timestamp();
+
+		let res = client
+			.post(&url)
+			.header("Content-Type", "application/json")
+			.header("X-Spacedrive-Event", &event_name)
+			.header("X-Spacedrive-Timestamp", timestamp)
+			.header("X-Spacedrive-Signature", sign(&secret, timestamp, &body))
+			.body(body.to_vec())
+			.send()
+			.await;
+
+		match res {
+			Ok(res) if res.status().is_success() => {
+				debug!("Delivered {kind:?} webhook to {url}");
+				return;
+			}
+			// Other client errors won't go away by retrying
+			Ok(res)
+				if res.status().is_client_error()
+					&& res.status() != StatusCode::TOO_MANY_REQUESTS
+					&& res.status() != StatusCode::REQUEST_TIMEOUT =>
+			{
+				warn!(
+					"Webhook {url} rejected {kind:?} with status {}, giving up",
+					res.status()
+				);
+				return;
+			}
+			Ok(res) => warn!(
+				"Webhook {url} failed to receive {kind:?} with status {} (attempt {attempt})",
+				res.status()
+			),
+			Err(e) => warn!("Failed to deliver {kind:?} to webhook {url} (attempt {attempt}): {e}"),
+		}
+
+		if attempt < MAX_DELIVERY_ATTEMPTS {
+			sleep(retry_delay).await;
+			retry_delay *= 2;
+		}
+	}
+
+	error!("Giving up delivering {kind:?} to webhook {url} after {MAX_DELIVERY_ATTEMPTS} attempts");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_validate_webhook_url() {
+		assert!(validate_webhook_url("https://example.com/hooks/spacedrive").is_ok());
+		assert!(validate_webhook_url("http://localhost:8080").is_ok());
+		assert!(validate_webhook_url("file:///etc/passwd").is_err());
+		assert!(validate_webhook_url("not a url").is_err());
+	}
+
+	#[test]
+	fn test_sign() {
+		// Same as `printf '1687000000.{}' | openssl dgst -sha256 -hmac secret`
+		assert_eq!(
+			sign("secret", 1687000000, b"{}"),
+			"sha256=97f1b4533fe06e8a13c676b3ee220ee4e062e72d4d05735b5e96a97914417c59"
+		);
+	}
+}
@@ -0,0 +1,429 @@
+//! Rules acting on the files that appear in the locations of this node: "when a file matching
+//! `**/*.pdf` appears in Downloads, tag it and move it to Documents". They're evaluated from the
+//! events of the location watchers, and each file a rule matches is written to an execution log,
+//! along with what each action did. Rules in dry run only log what they would have done.
+
+use crate::{
+	invalidate_query,
+	job::{
+		scheduler::{ScheduledJobKind, SchedulerError},
+		Job, JobManagerError,
+	},
+	library::Library,
+	object::fs::cut::FileCutterJobInit,
+	prisma::{
+		automation_rule, automation_run, file_path, location, object, tag, tag_on_object, SortOrder,
+	},
+};
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use globset::{Glob, GlobMatcher};
+use prisma_client_rust::or;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tracing::{debug, error};
+
+use super::{FileEvent, FileEventKind};
+
+/// Only the latest runs are kept in the log
+const MAX_LOGGED_RUNS: i32 = 1000;
+
+/// What a rule does with the files it matches, in the order of its actions
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AutomationAction {
+	/// Moves the file into a directory of a location, given relative to the root of the location
+	MoveTo {
+		location_id: location::id::Type,
+		directory: PathBuf,
+	},
+	ApplyTag {
+		tag_id: tag::id::Type,
+	},
+	RunJob {
+		job: ScheduledJobKind,
+	},
+}
+
+/// What an action did to a file, as kept in the execution log
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationActionOutcome {
+	pub action: AutomationAction,
+	/// Why the action failed, if it did
+	pub error: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum AutomationError {
+	#[error("automation rule not found <id='{0}'>")]
+	IdNotFound(automation_rule::id::Type),
+	#[error("invalid pattern '{pattern}': {source}")]
+	InvalidPattern {
+		pattern: String,
+		source: globset::Error,
+	},
+	#[error("the file has no object to be tagged yet")]
+	NoObject,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to (de)serialize automation actions: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error(transparent)]
+	JobManager(#[from] JobManagerError),
+	#[error(transparent)]
+	Scheduler(#[from] SchedulerError),
+}
+
+impl From<AutomationError> for rspc::Error {
+	fn from(err: AutomationError) -> Self {
+		match err {
+			AutomationError::IdNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			AutomationError::InvalidPattern { .. } => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A rule as shown to the frontend, its actions deserialized
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationRule {
+	pub id: automation_rule::id::Type,
+	pub name: String,
+	pub location_id: Option<location::id::Type>,
+	pub pattern: String,
+	pub actions: Vec<AutomationAction>,
+	pub dry_run: bool,
+	pub enabled: bool,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub date_modified: Option<DateTime<FixedOffset>>,
+}
+
+impl TryFrom<automation_rule::Data> for AutomationRule {
+	type Error = AutomationError;
+
+	fn try_from(rule: automation_rule::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: rule.id,
+			name: rule.name.unwrap_or_default(),
+			location_id: rule.location_id,
+			pattern: rule.pattern.unwrap_or_default(),
+			actions: rule
+				.actions
+				.as_deref()
+				.map(serde_json::from_slice)
+				.transpose()?
+				.unwrap_or_default(),
+			dry_run: rule.dry_run.unwrap_or(false),
+			enabled: rule.enabled.unwrap_or(false),
+			date_created: rule.date_created,
+			date_modified: rule.date_modified,
+		})
+	}
+}
+
+/// An entry of the execution log
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationRun {
+	pub id: automation_run::id::Type,
+	pub rule_id: automation_rule::id::Type,
+	pub location_id: Option<location::id::Type>,
+	pub file_path_id: Option<file_path::id::Type>,
+	pub path: String,
+	pub outcomes: Vec<AutomationActionOutcome>,
+	pub dry_run: bool,
+	pub date_created: Option<DateTime<FixedOffset>>,
+}
+
+impl TryFrom<automation_run::Data> for AutomationRun {
+	type Error = AutomationError;
+
+	fn try_from(run: automation_run::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: run.id,
+			rule_id: run.rule_id,
+			location_id: run.location_id,
+			file_path_id: run.file_path_id,
+			path: run.path.unwrap_or_default(),
+			outcomes: run
+				.outcomes
+				.as_deref()
+				.map(serde_json::from_slice)
+				.transpose()?
+				.unwrap_or_default(),
+			dry_run: run.dry_run.unwrap_or(false),
+			date_created: run.date_created,
+		})
+	}
+}
+
+/// Makes sure the pattern of a rule is a valid glob, returning its matcher
+pub fn compile_pattern(pattern: &str) -> Result<GlobMatcher, AutomationError> {
+	Glob::new(pattern)
+		.map(|glob| glob.compile_matcher())
+		.map_err(|source| AutomationError::InvalidPattern {
+			pattern: pattern.to_string(),
+			source,
+		})
+}
+
+impl AutomationRule {
+	/// Whether a file that appeared in a location, at `path` in it, is one the rule acts on
+	fn matches(&self, matcher: &GlobMatcher, location_id: location::id::Type, path: &str) -> bool {
+		if self.location_id.map_or(false, |id| id != location_id) {
+			return false;
+		}
+
+		let path = path.trim_start_matches('/');
+		if !matcher.is_match(path) {
+			return false;
+		}
+
+		// Files already moved by the rule appear in their new directory, where they'd match again
+		!self.actions.iter().any(|action| match action {
+			AutomationAction::MoveTo {
+				location_id: target_location_id,
+				directory,
+			} => {
+				*target_location_id == location_id
+					&& Path::new(path).parent()
+						== Some(directory.strip_prefix("/").unwrap_or(directory.as_path()))
+			}
+			_ => false,
+		})
+	}
+}
+
+/// Runs the rules matching a file that appeared in a location, without blocking the watcher
+pub(crate) fn run_rules_in_background(library: &Library, event: FileEvent) {
+	if event.is_dir || !matches!(event.kind, FileEventKind::Created) {
+		return;
+	}
+
+	let library = library.clone();
+	tokio::spawn(async move {
+		if let Err(e) = run_rules(&library, &event).await {
+			error!(
+				"Failed to run automation rules on '{}' of location {}: {e:#?}",
+				event.path, event.location_id
+			);
+		}
+	});
+}
+
+async fn run_rules(library: &Library, event: &FileEvent) -> Result<(), AutomationError> {
+	let db = &library.db;
+
+	let rules = db
+		.automation_rule()
+		.find_many(vec![
+			automation_rule::enabled::equals(Some(true)),
+			or![
+				automation_rule::location_id::equals(None),
+				automation_rule::location_id::equals(Some(event.location_id))
+			],
+		])
+		.exec()
+		.await?;
+
+	let mut ran_any = false;
+
+	for rule in rules {
+		let rule = AutomationRule::try_from(rule)?;
+		let matcher = match compile_pattern(&rule.pattern) {
+			Ok(matcher) => matcher,
+			Err(e) => {
+				error!("Skipping automation rule <id='{}'>: {e}", rule.id);
+				continue;
+			}
+		};
+
+		if !rule.matches(&matcher, event.location_id, &event.path) {
+			continue;
+		}
+
+		debug!(
+			"Automation rule <id='{}'> matched '{}'{}",
+			rule.id,
+			event.path,
+			if rule.dry_run { ", in dry run" } else { "" }
+		);
+
+		let mut outcomes = Vec::with_capacity(rule.actions.len());
+		for action in rule.actions {
+			let error = if rule.dry_run {
+				None
+			} else {
+				run_action(library, event, &action)
+					.await
+					.err()
+					.map(|e| e.to_string())
+			};
+
+			outcomes.push(AutomationActionOutcome { action, error });
+		}
+
+		db.automation_run()
+			.create(
+				automation_rule::id::equals(rule.id),
+				vec![
+					automation_run::location_id::set(Some(event.location_id)),
+					automation_run::file_path_id::set(Some(event.file_path_id)),
+					automation_run::path::set(Some(event.path.clone())),
+					automation_run::outcomes::set(Some(serde_json::to_vec(&outcomes)?)),
+					automation_run::dry_run::set(Some(rule.dry_run)),
+					automation_run::date_created::set(Some(Utc::now().into())),
+				],
+			)
+			.exec()
+			.await?;
+
+		ran_any = true;
+	}
+
+	if ran_any {
+		prune_log(library).await?;
+		invalidate_query!(library, "automation.log");
+	}
+
+	Ok(())
+}
+
+async fn run_action(
+	library: &Library,
+	event: &FileEvent,
+	action: &AutomationAction,
+) -> Result<(), AutomationError> {
+	match action {
+		AutomationAction::MoveTo {
+			location_id,
+			directory,
+		} => Job::new(FileCutterJobInit {
+			source_location_id: event.location_id,
+			target_location_id: *location_id,
+			sources_file_path_ids: vec![event.file_path_id],
+			target_location_relative_directory_path: directory.clone(),
+		})
+		.spawn(library)
+		.await
+		.map_err(Into::into),
+		AutomationAction::ApplyTag { tag_id } => {
+			let object_id = library
+				.db
+				.file_path()
+				.find_unique(file_path::id::equals(event.file_path_id))
+				.select(file_path::select!({ object_id }))
+				.exec()
+				.await?
+				.and_then(|file_path| file_path.object_id)
+				.ok_or(AutomationError::NoObject)?;
+
+			library
+				.db
+				.tag_on_object()
+				.upsert(
+					tag_on_object::tag_id_object_id(*tag_id, object_id),
+					tag_on_object::create(
+						tag::id::equals(*tag_id),
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![],
+				)
+				.exec()
+				.await?;
+
+			invalidate_query!(library, "tags.getForObject");
+
+			Ok(())
+		}
+		AutomationAction::RunJob { job } => job.spawn(library).await.map_err(Into::into),
+	}
+}
+
+async fn prune_log(library: &Library) -> Result<(), AutomationError> {
+	let db = &library.db;
+
+	let Some(oldest_kept) = db
+		.automation_run()
+		.find_many(vec![])
+		.order_by(automation_run::id::order(SortOrder::Desc))
+		.skip(MAX_LOGGED_RUNS as i64 - 1)
+		.take(1)
+		.select(automation_run::select!({ id }))
+		.exec()
+		.await?
+		.pop()
+	else {
+		return Ok(());
+	};
+
+	db.automation_run()
+		.delete_many(vec![automation_run::id::lt(oldest_kept.id)])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rule(
+		location_id: Option<location::id::Type>,
+		actions: Vec<AutomationAction>,
+	) -> AutomationRule {
+		AutomationRule {
+			id: 1,
+			name: String::new(),
+			location_id,
+			pattern: "**/*.pdf".to_string(),
+			actions,
+			dry_run: false,
+			enabled: true,
+			date_created: None,
+			date_modified: None,
+		}
+	}
+
+	#[test]
+	fn matches_new_files() {
+		let matcher = compile_pattern("**/*.pdf").unwrap();
+
+		let any_location = rule(None, vec![]);
+		assert!(any_location.matches(&matcher, 1, "/Downloads/invoice.pdf"));
+		assert!(!any_location.matches(&matcher, 1, "/Downloads/photo.jpg"));
+
+		let one_location = rule(Some(2), vec![]);
+		assert!(!one_location.matches(&matcher, 1, "/Downloads/invoice.pdf"));
+		assert!(one_location.matches(&matcher, 2, "/Downloads/invoice.pdf"));
+	}
+
+	#[test]
+	fn skips_files_already_moved() {
+		let matcher = compile_pattern("**/*.pdf").unwrap();
+		let moving = rule(
+			None,
+			vec![AutomationAction::MoveTo {
+				location_id: 1,
+				directory: PathBuf::from("/Documents/Invoices"),
+			}],
+		);
+
+		assert!(moving.matches(&matcher, 1, "/Downloads/invoice.pdf"));
+		assert!(!moving.matches(&matcher, 1, "/Documents/Invoices/invoice.pdf"));
+		// The same directory of another location isn't where the rule moves files to
+		assert!(moving.matches(&matcher, 2, "/Documents/Invoices/invoice.pdf"));
+	}
+}
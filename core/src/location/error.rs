@@ -1,11 +1,13 @@
 use crate::{
 	prisma::location,
-	util::{db::MissingFieldError, error::FileIOError},
+	util::{
+		db::MissingFieldError,
+		error::{coded_error, CoreErrorCode, FileIOError},
+	},
 };
 
 use std::path::PathBuf;
 
-use rspc::{self, ErrorCode};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -49,6 +51,8 @@ pub enum LocationError {
 	LocationAlreadyExists(PathBuf),
 	#[error("nested location currently not supported <path='{}'>", .0.display())]
 	NestedLocation(PathBuf),
+	#[error("location is offline <id='{0}'>")]
+	Offline(location::id::Type),
 
 	// Internal Errors
 	#[error(transparent)]
@@ -77,31 +81,28 @@ pub enum LocationError {
 
 impl From<LocationError> for rspc::Error {
 	fn from(err: LocationError) -> Self {
-		match err {
-			// Not found errors
+		let code = match err {
 			LocationError::PathNotFound(_)
 			| LocationError::UuidNotFound(_)
-			| LocationError::IdNotFound(_) => {
-				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
-			}
-
-			// User's fault errors
-			LocationError::NotDirectory(_)
-			| LocationError::NestedLocation(_)
-			| LocationError::LocationAlreadyExists(_) => {
-				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
-			}
-
-			// Custom error message is used to differenciate these errors in the frontend
-			// TODO: A better solution would be for rspc to support sending custom data alongside errors
-			LocationError::NeedRelink { .. } => {
-				rspc::Error::with_cause(ErrorCode::Conflict, "NEED_RELINK".to_owned(), err)
-			}
-			LocationError::AddLibraryToMetadata(_) => {
-				rspc::Error::with_cause(ErrorCode::Conflict, "ADD_LIBRARY".to_owned(), err)
-			}
+			| LocationError::IdNotFound(_) => CoreErrorCode::LocationNotFound,
+			LocationError::DirectoryNotFound(_) => CoreErrorCode::NotFound,
+			LocationError::NotDirectory(_) => CoreErrorCode::LocationNotADirectory,
+			LocationError::NeedRelink { .. } => CoreErrorCode::LocationNeedsRelink,
+			LocationError::AddLibraryToMetadata(_) => CoreErrorCode::LocationInOtherLibrary,
+			LocationError::LocationAlreadyExists(_) => CoreErrorCode::LocationAlreadyExists,
+			LocationError::NestedLocation(_) => CoreErrorCode::LocationNested,
+			LocationError::Offline(_) => CoreErrorCode::LocationOffline,
+			LocationError::MetadataNotFound(_)
+			| LocationError::MissingMetadataFile(_)
+			| LocationError::LocationMetadata(_) => CoreErrorCode::LocationMetadataInvalid,
+			LocationError::VolumeReadError(_) => CoreErrorCode::VolumeUnavailable,
+			LocationError::Database(_) => CoreErrorCode::Database,
+			LocationError::FileRead(_)
+			| LocationError::FileIO(_)
+			| LocationError::LocationPathFilesystemMetadataAccess(_) => CoreErrorCode::FileSystem,
+			_ => CoreErrorCode::Internal,
+		};
 
-			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
-		}
+		coded_error(code, err)
 	}
 }
@@ -0,0 +1,81 @@
+use crate::{
+	api::CoreEvent,
+	library::Library,
+	prisma::{file_path, location},
+};
+
+use serde::Serialize;
+use specta::Type;
+use uuid::Uuid;
+
+use super::{
+	automation::run_rules_in_background, file_path_helper::IsolatedFilePathData,
+	hot_folder::ingest_in_background,
+};
+
+/// A change to a file or directory of a location, as seen by its watcher. Frontends and
+/// integrations can react to these instead of refetching on every `search.paths` invalidation.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct FileEvent {
+	pub library_id: Uuid,
+	pub location_id: location::id::Type,
+	pub file_path_id: file_path::id::Type,
+	/// Path of the file in its location, like `/photos/cat.jpg`
+	pub path: String,
+	pub is_dir: bool,
+	pub kind: FileEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FileEventKind {
+	Created,
+	/// The content of the file changed
+	Modified,
+	/// The file was renamed or moved within its location. For directories, only one event is sent,
+	/// not one for each of their children.
+	Renamed {
+		old_path: String,
+	},
+	Deleted,
+}
+
+impl FileEventKind {
+	pub(crate) fn renamed(old_iso_file_path: &IsolatedFilePathData<'_>) -> Self {
+		Self::Renamed {
+			old_path: path_in_location(old_iso_file_path),
+		}
+	}
+}
+
+impl FileEvent {
+	pub(crate) fn emit(
+		library: &Library,
+		file_path_id: file_path::id::Type,
+		iso_file_path: &IsolatedFilePathData<'_>,
+		kind: FileEventKind,
+	) {
+		let event = Self {
+			library_id: library.id,
+			location_id: iso_file_path.location_id,
+			file_path_id,
+			path: path_in_location(iso_file_path),
+			is_dir: iso_file_path.is_dir,
+			kind,
+		};
+
+		library.emit(CoreEvent::FileEvent(event.clone()));
+
+		// New files are what hot folders and automation rules act on
+		ingest_in_background(library, &event);
+		run_rules_in_background(library, event);
+	}
+}
+
+fn path_in_location(iso_file_path: &IsolatedFilePathData<'_>) -> String {
+	format!(
+		"{}{}",
+		iso_file_path.materialized_path,
+		iso_file_path.full_name()
+	)
+}
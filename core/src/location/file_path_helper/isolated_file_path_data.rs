@@ -14,9 +14,10 @@ use regex::RegexSet;
 use serde::{Deserialize, Serialize};
 
 use super::{
-	file_path_for_file_identifier, file_path_for_object_validator, file_path_for_thumbnailer,
-	file_path_to_full_path, file_path_to_handle_custom_uri, file_path_to_isolate,
-	file_path_to_isolate_with_id, file_path_with_object, FilePathError,
+	file_path_for_file_identifier, file_path_for_object_validator, file_path_for_quarantine,
+	file_path_for_thumbnailer, file_path_to_full_path, file_path_to_handle_custom_uri,
+	file_path_to_isolate, file_path_to_isolate_with_id, file_path_to_stream, file_path_with_object,
+	FilePathError,
 };
 
 static FORBIDDEN_FILE_NAMES: OnceLock<RegexSet> = OnceLock::new();
@@ -86,6 +87,10 @@ impl<'a> IsolatedFilePathData<'a> {
 		self.location_id
 	}
 
+	pub fn is_dir(&self) -> bool {
+		self.is_dir
+	}
+
 	pub fn is_root(&self) -> bool {
 		self.is_dir
 			&& self.materialized_path == "/"
@@ -453,9 +458,11 @@ impl_from_db!(
 impl_from_db_without_location_id!(
 	file_path_for_file_identifier,
 	file_path_to_full_path,
+	file_path_for_quarantine,
 	file_path_for_thumbnailer,
 	file_path_for_object_validator,
-	file_path_to_handle_custom_uri
+	file_path_to_handle_custom_uri,
+	file_path_to_stream
 );
 
 fn extract_relative_path(
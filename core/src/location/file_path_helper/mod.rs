@@ -39,12 +39,14 @@ file_path::select!(file_path_for_file_identifier {
 	extension
 });
 file_path::select!(file_path_for_object_validator {
+	id
 	pub_id
 	materialized_path
 	is_dir
 	name
 	extension
 	integrity_checksum
+	object_id
 });
 file_path::select!(file_path_for_thumbnailer {
 	materialized_path
@@ -52,6 +54,7 @@ file_path::select!(file_path_for_thumbnailer {
 	name
 	extension
 	cas_id
+	size_in_bytes_bytes
 });
 file_path::select!(file_path_to_isolate {
 	location_id
@@ -78,6 +81,36 @@ file_path::select!(file_path_to_handle_custom_uri {
 		path
 	}
 });
+file_path::select!(file_path_to_stream {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+	date_modified
+	location: select {
+		id
+		path
+		node_id
+		node: select { node_peer_id }
+	}
+});
+file_path::select!(file_path_for_quarantine {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	object_id
+	integrity_checksum
+	location: select {
+		id
+		path
+	}
+});
 file_path::select!(file_path_to_full_path {
 	id
 	materialized_path
@@ -170,11 +203,13 @@ pub async fn create_file_path(
 	cas_id: Option<String>,
 	metadata: FilePathMetadata,
 ) -> Result<file_path::Data, FilePathError> {
-	use crate::{sync, util::db::uuid_to_bytes};
+	use crate::{
+		sync,
+		util::db::{new_pub_id, uuid_to_bytes},
+	};
 
 	use sd_prisma::prisma;
 	use serde_json::json;
-	use uuid::Uuid;
 
 	let location = db
 		.location()
@@ -210,7 +245,7 @@ pub async fn create_file_path(
 		]
 	};
 
-	let pub_id = uuid_to_bytes(Uuid::new_v4());
+	let pub_id = uuid_to_bytes(new_pub_id());
 
 	let created_path = sync
 		.write_op(
@@ -0,0 +1,190 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::find_location,
+	prisma::{file_path, hot_folder, location},
+	util::db::maybe_missing,
+};
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use super::{ingest_file, HotFolder, HotFolderError, IngestStep};
+
+/// Runs files of a hot folder through its pipeline, or every file directly in it when none are
+/// given
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct HotFolderIngestJobInit {
+	pub hot_folder_id: hot_folder::id::Type,
+	pub file_path_ids: Vec<file_path::id::Type>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HotFolderIngestJobData {
+	location_id: location::id::Type,
+	location_path: PathBuf,
+	pipeline: Vec<IngestStep>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct HotFolderIngestJobRunMetadata {
+	total_files: usize,
+	ingested_files: usize,
+}
+
+impl JobRunMetadata for HotFolderIngestJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_files += new_data.total_files;
+		self.ingested_files += new_data.ingested_files;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for HotFolderIngestJobInit {
+	type Data = HotFolderIngestJobData;
+	type Step = file_path::id::Type;
+	type RunMetadata = HotFolderIngestJobRunMetadata;
+
+	const NAME: &'static str = "hot_folder_ingest";
+	const PRIORITY: JobPriority = JobPriority::Background;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("hot-folder-{}", self.hot_folder_id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		let hot_folder = HotFolder::try_from(
+			db.hot_folder()
+				.find_unique(hot_folder::id::equals(init.hot_folder_id))
+				.exec()
+				.await?
+				.ok_or(HotFolderError::IdNotFound(init.hot_folder_id))?,
+		)?;
+
+		if !hot_folder.enabled {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "the hot folder is disabled".to_string(),
+			});
+		}
+
+		let location_id = maybe_missing(hot_folder.location_id, "hot_folder.location_id")?;
+		let location = find_location(&ctx.library, location_id)
+			.exec()
+			.await?
+			.ok_or(HotFolderError::DirectoryNotFound(hot_folder.path.clone()))?;
+
+		if location.node_id != Some(ctx.library.node_local_id) {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "the location of the hot folder is on another node".to_string(),
+			});
+		}
+
+		let steps = if init.file_path_ids.is_empty() {
+			db.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::equals(Some(hot_folder.path.clone())),
+					file_path::is_dir::equals(Some(false)),
+					file_path::date_deleted::equals(None),
+				])
+				.select(file_path::select!({ id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|file_path| file_path.id)
+				.collect()
+		} else {
+			init.file_path_ids.clone()
+		};
+
+		*data = Some(HotFolderIngestJobData {
+			location_id,
+			location_path: maybe_missing(location.path, "location.path")?.into(),
+			pipeline: hot_folder.pipeline,
+		});
+
+		ctx.progress_msg(format!("Ingesting {} files", steps.len()));
+
+		Ok((
+			HotFolderIngestJobRunMetadata {
+				total_files: steps.len(),
+				..Default::default()
+			},
+			steps,
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep {
+			step: file_path_id, ..
+		}: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let mut new_metadata = HotFolderIngestJobRunMetadata::default();
+		let mut errors = vec![];
+
+		match ingest_file(
+			&ctx.library,
+			data.location_id,
+			&data.location_path,
+			&data.pipeline,
+			*file_path_id,
+		)
+		.await
+		{
+			Ok(path) => {
+				debug!("Ingested file {file_path_id} to '{}'", path.display());
+				new_metadata.ingested_files += 1;
+			}
+			// A file failing one of the steps is left where it is, the others are still ingested
+			Err(e) => errors.push(format!("Failed to ingest file {file_path_id}: {e}")),
+		}
+
+		ctx.progress_msg(format!(
+			"Ingested {} of {} files",
+			run_metadata.ingested_files + new_metadata.ingested_files,
+			run_metadata.total_files
+		));
+
+		Ok((vec![], new_metadata, errors.into()).into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Ingested {} of {} files of hot folder {}",
+			run_metadata.ingested_files, run_metadata.total_files, self.hot_folder_id
+		);
+
+		if run_metadata.ingested_files > 0 {
+			invalidate_query!(ctx.library, "search.paths");
+			invalidate_query!(ctx.library, "search.objects");
+		}
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
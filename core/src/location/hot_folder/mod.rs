@@ -0,0 +1,568 @@
+//! Directories whose new files go through an ingest pipeline, for camera imports and scanners:
+//! files copied straight into a hot folder are renamed, converted and sorted into dated
+//! directories, in the order of the steps of its pipeline. Files already sorted into directories
+//! under the hot folder aren't ingested again, only the ones appearing directly in it.
+
+use crate::{
+	job::{Job, JobManagerError},
+	library::Library,
+	location::file_path_helper::IsolatedFilePathData,
+	object::embedded_metadata::{read_capture_info, CaptureInfo},
+	prisma::{file_path, hot_folder, location, object},
+	sync,
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::{
+	collections::HashSet,
+	fmt::Write,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use chrono::{
+	format::{Item, StrftimeItems},
+	DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc,
+};
+use image::ImageFormat;
+use once_cell::sync::Lazy;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, error};
+
+use super::{FileEvent, FileEventKind, LocationError};
+
+pub mod ingest_job;
+
+use ingest_job::HotFolderIngestJobInit;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Files written by the pipelines, which the watchers see as new files but aren't to be ingested
+static PRODUCED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+/// A step of the pipeline of a hot folder
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum IngestStep {
+	/// Renames files from a template of `{name}` (the name without its extension), `{make}` and
+	/// `{model}` of the camera, and `{date}` or `{date:%Y%m%d}` with a date format. The capture
+	/// date of photos is used, or the creation date of the file. The extension is kept.
+	Rename { template: String },
+	/// Keeps the capture date of photos as the date of their object
+	ExtractMetadata,
+	/// Converts images, like the TIFFs of scanners, into a format thumbnails and previews can be
+	/// generated from everywhere
+	ConvertImage {
+		format: ConvertFormat,
+		keep_original: bool,
+	},
+	/// Moves files into dated directories, like `Photos/2023/07-14`, from a date format where
+	/// slashes separate directories. The directory is relative to the root of the location.
+	MoveToDatedFolder { directory: PathBuf, format: String },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConvertFormat {
+	Jpeg,
+	Png,
+}
+
+impl ConvertFormat {
+	fn extension(self) -> &'static str {
+		match self {
+			Self::Jpeg => "jpg",
+			Self::Png => "png",
+		}
+	}
+
+	fn image_format(self) -> ImageFormat {
+		match self {
+			Self::Jpeg => ImageFormat::Jpeg,
+			Self::Png => ImageFormat::Png,
+		}
+	}
+
+	fn is_extension(self, extension: &str) -> bool {
+		let extension = extension.to_lowercase();
+		match self {
+			Self::Jpeg => extension == "jpg" || extension == "jpeg",
+			Self::Png => extension == "png",
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum HotFolderError {
+	#[error("hot folder not found <id='{0}'>")]
+	IdNotFound(hot_folder::id::Type),
+	#[error("directory not found in the location <path='{0}'>")]
+	DirectoryNotFound(String),
+	#[error("invalid name template '{0}'")]
+	InvalidTemplate(String),
+	#[error("invalid date format '{0}'")]
+	InvalidDateFormat(String),
+	#[error("renamed file would have an invalid name '{0}'")]
+	InvalidFileName(String),
+	#[error("file path not found <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("failed to convert image: {0}")]
+	Image(#[from] image::ImageError),
+	#[error("image conversion task failed: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to (de)serialize the pipeline: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	JobManager(#[from] JobManagerError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<HotFolderError> for rspc::Error {
+	fn from(err: HotFolderError) -> Self {
+		match err {
+			HotFolderError::IdNotFound(_) | HotFolderError::DirectoryNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			HotFolderError::InvalidTemplate(_) | HotFolderError::InvalidDateFormat(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			HotFolderError::Location(err) => err.into(),
+			HotFolderError::JobManager(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A hot folder as shown to the frontend, its pipeline deserialized
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct HotFolder {
+	pub id: hot_folder::id::Type,
+	pub name: String,
+	pub location_id: Option<location::id::Type>,
+	/// Path of the directory in its location, like `/Imports/`
+	pub path: String,
+	pub pipeline: Vec<IngestStep>,
+	pub enabled: bool,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub date_modified: Option<DateTime<FixedOffset>>,
+}
+
+impl TryFrom<hot_folder::Data> for HotFolder {
+	type Error = HotFolderError;
+
+	fn try_from(hot_folder: hot_folder::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: hot_folder.id,
+			name: hot_folder.name.unwrap_or_default(),
+			location_id: hot_folder.location_id,
+			path: hot_folder.path.unwrap_or_default(),
+			pipeline: hot_folder
+				.pipeline
+				.as_deref()
+				.map(serde_json::from_slice)
+				.transpose()?
+				.unwrap_or_default(),
+			enabled: hot_folder.enabled.unwrap_or(false),
+			date_created: hot_folder.date_created,
+			date_modified: hot_folder.date_modified,
+		})
+	}
+}
+
+/// What the placeholders of the name template are replaced with
+struct NameVars<'a> {
+	name: &'a str,
+	date: NaiveDateTime,
+	capture: &'a CaptureInfo,
+}
+
+fn validate_date_format(format: &str) -> Result<(), HotFolderError> {
+	if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+		return Err(HotFolderError::InvalidDateFormat(format.to_string()));
+	}
+
+	Ok(())
+}
+
+fn render_name(template: &str, vars: &NameVars<'_>) -> Result<String, HotFolderError> {
+	let invalid = || HotFolderError::InvalidTemplate(template.to_string());
+
+	let mut name = String::new();
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		name.push_str(&rest[..start]);
+		let end = start + rest[start..].find('}').ok_or_else(invalid)?;
+
+		let placeholder = &rest[start + 1..end];
+		let (key, arg) = placeholder
+			.split_once(':')
+			.map_or((placeholder, None), |(key, arg)| (key, Some(arg)));
+
+		match (key, arg) {
+			("name", None) => name.push_str(vars.name),
+			("make", None) => name.push_str(vars.capture.make.as_deref().unwrap_or("Unknown")),
+			("model", None) => name.push_str(vars.capture.model.as_deref().unwrap_or("Unknown")),
+			("date", format) => {
+				let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+				validate_date_format(format)?;
+				write!(name, "{}", vars.date.format(format)).map_err(|_| invalid())?;
+			}
+			_ => return Err(invalid()),
+		}
+
+		rest = &rest[end + 1..];
+	}
+	name.push_str(rest);
+
+	// Cameras and date formats aren't to add directories to the name
+	Ok(name.replace(['/', '\\'], "-"))
+}
+
+/// Makes sure the templates and date formats of a pipeline can be used before it's saved
+pub fn validate_pipeline(pipeline: &[IngestStep]) -> Result<(), HotFolderError> {
+	for step in pipeline {
+		match step {
+			IngestStep::Rename { template } => {
+				render_name(
+					template,
+					&NameVars {
+						name: "name",
+						date: NaiveDateTime::default(),
+						capture: &CaptureInfo::default(),
+					},
+				)?;
+			}
+			IngestStep::MoveToDatedFolder { format, .. } => validate_date_format(format)?,
+			IngestStep::ExtractMetadata | IngestStep::ConvertImage { .. } => {}
+		}
+	}
+
+	Ok(())
+}
+
+/// Path of the directory of a hot folder in its location, like `/Imports/`
+pub fn normalize_path(path: &str) -> String {
+	let path = path.trim_matches('/');
+	if path.is_empty() {
+		"/".to_string()
+	} else {
+		format!("/{path}/")
+	}
+}
+
+/// A path next to `path` which isn't taken, numbered like `name (1).jpg` if `path` is
+async fn available_path(path: PathBuf) -> PathBuf {
+	if !fs::try_exists(&path).await.unwrap_or(true) {
+		return path;
+	}
+
+	let stem = path
+		.file_stem()
+		.map(|stem| stem.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	let extension = path
+		.extension()
+		.map(|extension| format!(".{}", extension.to_string_lossy()))
+		.unwrap_or_default();
+
+	let mut copy = 1;
+	loop {
+		let candidate = path.with_file_name(format!("{stem} ({copy}){extension}"));
+		if !fs::try_exists(&candidate).await.unwrap_or(true) {
+			return candidate;
+		}
+		copy += 1;
+	}
+}
+
+async fn move_file(from: &Path, to: PathBuf) -> Result<PathBuf, HotFolderError> {
+	let to = available_path(to).await;
+	fs::rename(from, &to)
+		.await
+		.map_err(|e| FileIOError::from((from, e)))?;
+
+	Ok(to)
+}
+
+file_path::select!(file_path_to_ingest {
+	materialized_path
+	name
+	extension
+	date_created
+	object: select { id pub_id }
+});
+
+/// Runs a file of a hot folder through its pipeline, returning where the file ended up
+pub(crate) async fn ingest_file(
+	library: &Library,
+	location_id: location::id::Type,
+	location_path: &Path,
+	pipeline: &[IngestStep],
+	file_path_id: file_path::id::Type,
+) -> Result<PathBuf, HotFolderError> {
+	let Library { db, sync, .. } = library;
+
+	let file_path = db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path_to_ingest::select())
+		.exec()
+		.await?
+		.ok_or(HotFolderError::FilePathNotFound(file_path_id))?;
+
+	let iso_file_path = IsolatedFilePathData::from_db_data(
+		location_id,
+		false,
+		maybe_missing(
+			file_path.materialized_path.as_deref(),
+			"file_path.materialized_path",
+		)?
+		.into(),
+		maybe_missing(file_path.name.as_deref(), "file_path.name")?.into(),
+		file_path.extension.as_deref().unwrap_or_default().into(),
+	);
+	let mut path = location_path.join(&iso_file_path);
+
+	// Read before any conversion, which would lose it
+	let capture = read_capture_info(&path)
+		.await
+		.ok()
+		.flatten()
+		.unwrap_or_default();
+	let date = capture
+		.date_taken
+		.or(file_path.date_created.map(|date| date.naive_local()))
+		.unwrap_or_else(|| Utc::now().naive_local());
+
+	for step in pipeline {
+		match step {
+			IngestStep::Rename { template } => {
+				let stem = path
+					.file_stem()
+					.map(|stem| stem.to_string_lossy().into_owned())
+					.unwrap_or_default();
+				let mut new_name = render_name(
+					template,
+					&NameVars {
+						name: &stem,
+						date,
+						capture: &capture,
+					},
+				)?;
+				if let Some(extension) = path.extension() {
+					new_name.push('.');
+					new_name.push_str(&extension.to_string_lossy());
+				}
+
+				if !IsolatedFilePathData::accept_file_name(&new_name) {
+					return Err(HotFolderError::InvalidFileName(new_name));
+				}
+
+				path = move_file(&path, path.with_file_name(new_name)).await?;
+			}
+			IngestStep::ExtractMetadata => {
+				let (Some(date_taken), Some(object)) = (capture.date_taken, &file_path.object)
+				else {
+					continue;
+				};
+				let Some(date_taken) = Local.from_local_datetime(&date_taken).single() else {
+					continue;
+				};
+				let date_taken: DateTime<FixedOffset> = date_taken.into();
+
+				sync.write_op(
+					db,
+					sync.shared_update(
+						sync::object::SyncId {
+							pub_id: object.pub_id.clone(),
+						},
+						object::date_created::NAME,
+						json!(&date_taken.to_rfc3339()),
+					),
+					db.object().update(
+						object::id::equals(object.id),
+						vec![object::date_created::set(Some(date_taken))],
+					),
+				)
+				.await?;
+			}
+			IngestStep::ConvertImage {
+				format,
+				keep_original,
+			} => {
+				let extension = path
+					.extension()
+					.map(|extension| extension.to_string_lossy().into_owned())
+					.unwrap_or_default();
+				if format.is_extension(&extension) {
+					continue;
+				}
+
+				let converted_path = available_path(path.with_extension(format.extension())).await;
+				PRODUCED
+					.lock()
+					.expect("poisoned lock")
+					.insert(converted_path.clone());
+
+				let (source, target, format) = (path.clone(), converted_path.clone(), *format);
+				let converted = spawn_blocking(move || {
+					image::open(&source)?.save_with_format(&target, format.image_format())
+				})
+				.await?;
+
+				if let Err(e) = converted {
+					PRODUCED
+						.lock()
+						.expect("poisoned lock")
+						.remove(&converted_path);
+					return Err(e.into());
+				}
+
+				if !*keep_original {
+					fs::remove_file(&path)
+						.await
+						.map_err(|e| FileIOError::from((&path, e)))?;
+				}
+				path = converted_path;
+			}
+			IngestStep::MoveToDatedFolder { directory, format } => {
+				let directory = location_path
+					.join(directory.strip_prefix("/").unwrap_or(directory))
+					.join(date.format(format).to_string());
+				fs::create_dir_all(&directory)
+					.await
+					.map_err(|e| FileIOError::from((&directory, e)))?;
+
+				let file_name = path.file_name().unwrap_or_default().to_owned();
+				path = move_file(&path, directory.join(file_name)).await?;
+			}
+		}
+	}
+
+	Ok(path)
+}
+
+/// Ingests a file that appeared directly in a hot folder, without blocking the watcher
+pub(crate) fn ingest_in_background(library: &Library, event: &FileEvent) {
+	if event.is_dir || !matches!(event.kind, FileEventKind::Created) {
+		return;
+	}
+	let Some(parent) = event.path.rfind('/').map(|idx| &event.path[..=idx]) else {
+		return;
+	};
+
+	let library = library.clone();
+	let (location_id, file_path_id, path, parent) = (
+		event.location_id,
+		event.file_path_id,
+		event.path.clone(),
+		parent.to_string(),
+	);
+
+	tokio::spawn(async move {
+		let res = async {
+			let hot_folders = library
+				.db
+				.hot_folder()
+				.find_many(vec![
+					hot_folder::location_id::equals(Some(location_id)),
+					hot_folder::path::equals(Some(parent)),
+					hot_folder::enabled::equals(Some(true)),
+				])
+				.select(hot_folder::select!({ id }))
+				.exec()
+				.await?;
+
+			let Some(hot_folder) = hot_folders.first() else {
+				return Ok(());
+			};
+
+			if let Some(location_path) = library
+				.db
+				.location()
+				.find_unique(location::id::equals(location_id))
+				.select(location::select!({ path }))
+				.exec()
+				.await?
+				.and_then(|location| location.path)
+			{
+				let full_path = Path::new(&location_path).join(path.trim_start_matches('/'));
+				if PRODUCED.lock().expect("poisoned lock").remove(&full_path) {
+					debug!(
+						"Not ingesting '{}', written by a pipeline",
+						full_path.display()
+					);
+					return Ok(());
+				}
+			}
+
+			Job::new(HotFolderIngestJobInit {
+				hot_folder_id: hot_folder.id,
+				file_path_ids: vec![file_path_id],
+			})
+			.spawn(&library)
+			.await
+			.map_err(HotFolderError::from)
+		}
+		.await;
+
+		if let Err(e) = res {
+			error!("Failed to ingest '{path}' of location {location_id}: {e:#?}");
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_name_templates() {
+		let capture = CaptureInfo {
+			date_taken: NaiveDateTime::parse_from_str("2023-07-14 09:30:00", "%Y-%m-%d %H:%M:%S")
+				.ok(),
+			make: Some("Canon".to_string()),
+			model: Some("EOS R6/II".to_string()),
+		};
+		let vars = NameVars {
+			name: "IMG_0001",
+			date: capture.date_taken.unwrap(),
+			capture: &capture,
+		};
+
+		assert_eq!(
+			render_name("{date}_{name}", &vars).unwrap(),
+			"2023-07-14_IMG_0001"
+		);
+		assert_eq!(
+			render_name("{date:%Y%m%d-%H%M} {make} {model}", &vars).unwrap(),
+			"20230714-0930 Canon EOS R6-II"
+		);
+		assert!(render_name("{unknown}", &vars).is_err());
+		assert!(render_name("{name", &vars).is_err());
+		assert!(render_name("{date:%Q}", &vars).is_err());
+	}
+
+	#[test]
+	fn normalizes_paths() {
+		assert_eq!(normalize_path("Imports"), "/Imports/");
+		assert_eq!(normalize_path("/Scans/Inbox/"), "/Scans/Inbox/");
+		assert_eq!(normalize_path(""), "/");
+	}
+}
@@ -0,0 +1,151 @@
+use crate::{
+	library::Library,
+	prisma::{file_path, location, SortOrder},
+	sync,
+};
+
+use std::collections::HashMap;
+
+use serde_json::json;
+use tracing::trace;
+
+use super::IndexerError;
+
+/// How many file paths are read from the database at a time
+const FETCH_BATCH_SIZE: i64 = 10_000;
+/// How many directories are updated in the same transaction
+const UPDATE_BATCH_SIZE: usize = 1000;
+
+file_path::select!(file_path_for_dir_sizes {
+	id
+	pub_id
+	is_dir
+	materialized_path
+	name
+	size_in_bytes_bytes
+});
+
+fn size_from_bytes(bytes: Option<&[u8]>) -> u64 {
+	bytes
+		.and_then(|bytes| bytes.try_into().ok())
+		.map_or(0, u64::from_be_bytes)
+}
+
+/// Materialized paths of the directories a file with this materialized path is in, the location
+/// root excluded, as it doesn't have a file path
+fn ancestors(materialized_path: &str) -> impl Iterator<Item = &str> {
+	materialized_path
+		.match_indices('/')
+		.filter(|(idx, _)| *idx > 0)
+		.map(|(idx, _)| &materialized_path[..=idx])
+}
+
+/// Stores in each directory of the location the total size of the files under it, instead of the
+/// size the filesystem reports for the directory itself, so the heaviest directories can be found
+/// without going through all of their contents. Only the directories whose size changed are
+/// updated.
+pub async fn update_directories_sizes(
+	location_id: location::id::Type,
+	Library { db, sync, .. }: &Library,
+) -> Result<(), IndexerError> {
+	let mut sizes = HashMap::<String, u64>::new();
+	let mut directories = vec![];
+
+	let mut cursor = 0;
+	loop {
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::id::gt(cursor),
+				file_path::date_deleted::equals(None),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(FETCH_BATCH_SIZE)
+			.select(file_path_for_dir_sizes::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		for file_path in file_paths {
+			let (Some(materialized_path), Some(name)) =
+				(file_path.materialized_path, file_path.name)
+			else {
+				continue;
+			};
+
+			let size = size_from_bytes(file_path.size_in_bytes_bytes.as_deref());
+
+			if file_path.is_dir.unwrap_or(false) {
+				directories.push((
+					file_path.pub_id,
+					format!("{materialized_path}{name}/"),
+					size,
+				));
+			} else {
+				for ancestor in ancestors(&materialized_path) {
+					*sizes.entry(ancestor.to_string()).or_default() += size;
+				}
+			}
+		}
+	}
+
+	let changed = directories
+		.into_iter()
+		.filter_map(|(pub_id, path, size)| {
+			let total_size = sizes.get(&path).copied().unwrap_or_default();
+			(total_size != size).then_some((pub_id, total_size))
+		})
+		.collect::<Vec<_>>();
+
+	if changed.is_empty() {
+		return Ok(());
+	}
+
+	trace!(
+		"Updating the sizes of {} directories of location <id='{location_id}'>",
+		changed.len()
+	);
+
+	for chunk in changed.chunks(UPDATE_BATCH_SIZE) {
+		let (sync_ops, queries): (Vec<_>, Vec<_>) = chunk
+			.iter()
+			.map(|(pub_id, size)| {
+				let size = size.to_be_bytes().to_vec();
+
+				(
+					sync.shared_update(
+						sync::file_path::SyncId {
+							pub_id: pub_id.clone(),
+						},
+						file_path::size_in_bytes_bytes::NAME,
+						json!(size),
+					),
+					db.file_path().update(
+						file_path::pub_id::equals(pub_id.clone()),
+						vec![file_path::size_in_bytes_bytes::set(Some(size))],
+					),
+				)
+			})
+			.unzip();
+
+		sync.write_ops(db, (sync_ops, queries)).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ancestors_of_materialized_path() {
+		assert_eq!(ancestors("/").count(), 0);
+		assert_eq!(ancestors("/a/b/").collect::<Vec<_>>(), vec!["/a/", "/a/b/"]);
+	}
+}
@@ -2,7 +2,7 @@ use crate::{
 	file_paths_db_fetcher_fn, invalidate_query,
 	job::{
 		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunMetadata,
-		JobStepOutput, StatefulJob, WorkerContext,
+		JobStepOutput, RetryPolicy, StatefulJob, WorkerContext,
 	},
 	location::{
 		file_path_helper::{
@@ -31,6 +31,7 @@ use tracing::info;
 use super::{
 	execute_indexer_save_step, iso_file_path_factory, remove_non_existing_file_paths,
 	rules::IndexerRule,
+	update_directories_sizes,
 	walk::{keep_walking, walk, ToWalkEntry, WalkResult},
 	IndexerError, IndexerJobSaveStep,
 };
@@ -125,6 +126,11 @@ impl StatefulJob for IndexerJobInit {
 	type RunMetadata = IndexerJobRunMetadata;
 
 	const NAME: &'static str = "indexer";
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
 
 	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
 	async fn init(
@@ -372,6 +378,8 @@ impl StatefulJob for IndexerJobInit {
 		);
 
 		if run_metadata.indexed_count > 0 || run_metadata.removed_count > 0 {
+			update_directories_sizes(init.location.id, &ctx.library).await?;
+
 			invalidate_query!(ctx.library, "search.paths");
 		}
 
@@ -1,14 +1,19 @@
 use crate::{
-	library::Library,
+	library::{DatabaseQueryError, Library},
 	prisma::{file_path, location, PrismaClient},
 	sync,
-	util::{db::uuid_to_bytes, error::FileIOError},
+	util::{
+		db::uuid_to_bytes,
+		error::{coded_error, CoreErrorCode, FileIOError},
+	},
 };
 
-use std::path::Path;
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+};
 
 use chrono::Utc;
-use rspc::ErrorCode;
 use sd_prisma::prisma_sync;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -18,8 +23,10 @@ use tracing::trace;
 use super::{
 	file_path_helper::{file_path_just_pub_id, FilePathError, IsolatedFilePathData},
 	location_with_indexer_rules,
+	soft_delete::soft_delete_file_paths,
 };
 
+mod dir_sizes;
 pub mod indexer_job;
 pub mod rules;
 mod shallow;
@@ -28,6 +35,7 @@ mod walk;
 use rules::IndexerRuleError;
 use walk::WalkedEntry;
 
+pub use dir_sizes::update_directories_sizes;
 pub use indexer_job::IndexerJobInit;
 pub use shallow::*;
 
@@ -50,6 +58,8 @@ pub enum IndexerError {
 	#[error("Database Error: {}", .0.to_string())]
 	Database(#[from] prisma_client_rust::QueryError),
 	#[error(transparent)]
+	DatabaseQuery(#[from] DatabaseQueryError),
+	#[error(transparent)]
 	FileIO(#[from] FileIOError),
 	#[error(transparent)]
 	FilePath(#[from] FilePathError),
@@ -61,15 +71,17 @@ pub enum IndexerError {
 
 impl From<IndexerError> for rspc::Error {
 	fn from(err: IndexerError) -> Self {
-		match err {
+		let code = match err {
 			IndexerError::IndexerRuleNotFound(_) | IndexerError::SubPathNotFound(_) => {
-				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+				CoreErrorCode::NotFound
 			}
+			IndexerError::IndexerRules(rule_err) => return rule_err.into(),
+			IndexerError::Database(_) | IndexerError::DatabaseQuery(_) => CoreErrorCode::Database,
+			IndexerError::FileIO(_) => CoreErrorCode::FileSystem,
+			_ => CoreErrorCode::Internal,
+		};
 
-			IndexerError::IndexerRules(rule_err) => rule_err.into(),
-
-			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
-		}
+		coded_error(code, err)
 	}
 }
 
@@ -78,102 +90,229 @@ async fn execute_indexer_save_step(
 	save_step: &IndexerJobSaveStep,
 	library: &Library,
 ) -> Result<i64, IndexerError> {
-	let Library { sync, db, .. } = &library;
-
-	let (sync_stuff, paths): (Vec<_>, Vec<_>) = save_step
-		.walked
-		.iter()
-		.map(|entry| {
-			let IsolatedFilePathData {
-				materialized_path,
-				is_dir,
-				name,
-				extension,
-				..
-			} = &entry.iso_file_path;
-
-			use file_path::*;
-
-			let pub_id = uuid_to_bytes(entry.pub_id);
-
-			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
-				(
+	let Library {
+		sync, db, config, ..
+	} = &library;
+
+	let moved = restore_moved_file_paths(location.id, &save_step.walked, library).await?;
+
+	let file_paths = || {
+		save_step
+			.walked
+			.iter()
+			.filter(|entry| !moved.contains(&(entry.metadata.inode, entry.metadata.device)))
+			.map(|entry| {
+				let IsolatedFilePathData {
+					materialized_path,
+					is_dir,
+					name,
+					extension,
+					..
+				} = &entry.iso_file_path;
+
+				use file_path::*;
+
+				let pub_id = uuid_to_bytes(entry.pub_id);
+
+				let (sync_params, db_params): (Vec<_>, Vec<_>) = [
 					(
-						location::NAME,
-						json!(prisma_sync::location::SyncId {
-							pub_id: pub_id.clone()
-						}),
+						(
+							location::NAME,
+							json!(prisma_sync::location::SyncId {
+								pub_id: pub_id.clone()
+							}),
+						),
+						location_id::set(Some(location.id)),
 					),
-					location_id::set(Some(location.id)),
-				),
-				(
-					(materialized_path::NAME, json!(materialized_path)),
-					materialized_path::set(Some(materialized_path.to_string())),
-				),
-				((name::NAME, json!(name)), name::set(Some(name.to_string()))),
-				((is_dir::NAME, json!(*is_dir)), is_dir::set(Some(*is_dir))),
-				(
-					(extension::NAME, json!(extension)),
-					extension::set(Some(extension.to_string())),
-				),
-				(
 					(
-						size_in_bytes_bytes::NAME,
-						json!(entry.metadata.size_in_bytes.to_be_bytes().to_vec()),
+						(materialized_path::NAME, json!(materialized_path)),
+						materialized_path::set(Some(materialized_path.to_string())),
 					),
-					size_in_bytes_bytes::set(Some(
-						entry.metadata.size_in_bytes.to_be_bytes().to_vec(),
-					)),
-				),
-				(
-					(inode::NAME, json!(entry.metadata.inode.to_le_bytes())),
-					inode::set(Some(entry.metadata.inode.to_le_bytes().into())),
-				),
-				(
-					(device::NAME, json!(entry.metadata.device.to_le_bytes())),
-					device::set(Some(entry.metadata.device.to_le_bytes().into())),
-				),
-				(
-					(date_created::NAME, json!(entry.metadata.created_at)),
-					date_created::set(Some(entry.metadata.created_at.into())),
-				),
+					((name::NAME, json!(name)), name::set(Some(name.to_string()))),
+					((is_dir::NAME, json!(*is_dir)), is_dir::set(Some(*is_dir))),
+					(
+						(extension::NAME, json!(extension)),
+						extension::set(Some(extension.to_string())),
+					),
+					(
+						(
+							size_in_bytes_bytes::NAME,
+							json!(entry.metadata.size_in_bytes.to_be_bytes().to_vec()),
+						),
+						size_in_bytes_bytes::set(Some(
+							entry.metadata.size_in_bytes.to_be_bytes().to_vec(),
+						)),
+					),
+					(
+						(inode::NAME, json!(entry.metadata.inode.to_le_bytes())),
+						inode::set(Some(entry.metadata.inode.to_le_bytes().into())),
+					),
+					(
+						(device::NAME, json!(entry.metadata.device.to_le_bytes())),
+						device::set(Some(entry.metadata.device.to_le_bytes().into())),
+					),
+					(
+						(date_created::NAME, json!(entry.metadata.created_at)),
+						date_created::set(Some(entry.metadata.created_at.into())),
+					),
+					(
+						(date_modified::NAME, json!(entry.metadata.modified_at)),
+						date_modified::set(Some(entry.metadata.modified_at.into())),
+					),
+					(
+						(date_indexed::NAME, json!(Utc::now())),
+						date_indexed::set(Some(Utc::now().into())),
+					),
+				]
+				.into_iter()
+				.unzip();
+
 				(
-					(date_modified::NAME, json!(entry.metadata.modified_at)),
-					date_modified::set(Some(entry.metadata.modified_at.into())),
-				),
+					sync.unique_shared_create(
+						sync::file_path::SyncId {
+							pub_id: uuid_to_bytes(entry.pub_id),
+						},
+						sync_params,
+					),
+					file_path::create_unchecked(pub_id, db_params),
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>()
+	};
+
+	let count = config
+		.database
+		.run(|| {
+			let (sync_stuff, paths) = file_paths();
+			sync.write_ops(
+				db,
 				(
-					(date_indexed::NAME, json!(Utc::now())),
-					date_indexed::set(Some(Utc::now().into())),
+					sync_stuff,
+					db.file_path().create_many(paths).skip_duplicates(),
 				),
-			]
-			.into_iter()
-			.unzip();
+			)
+		})
+		.await?;
+
+	trace!("Inserted {count} records");
+
+	Ok(count + moved.len() as i64)
+}
+
+/// Files moved within a location while it wasn't watched are found by the indexer at a new path,
+/// while their old file path, with the same inode, was marked as deleted. Those file paths are
+/// moved to the new path and restored instead of creating new ones, so they keep their objects.
+/// Returns the inodes and devices of the restored entries.
+async fn restore_moved_file_paths(
+	location_id: location::id::Type,
+	walked: &[WalkedEntry],
+	Library { sync, db, .. }: &Library,
+) -> Result<HashSet<(u64, u64)>, IndexerError> {
+	let deleted = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::date_deleted::not(None),
+			file_path::inode::in_vec(
+				walked
+					.iter()
+					.map(|entry| entry.metadata.inode.to_le_bytes().to_vec())
+					.collect(),
+			),
+		])
+		.select(file_path::select!({ pub_id inode device }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			let inode = u64::from_le_bytes(file_path.inode?.try_into().ok()?);
+			let device = u64::from_le_bytes(file_path.device?.try_into().ok()?);
+			Some(((inode, device), file_path.pub_id))
+		})
+		.collect::<HashMap<_, _>>();
+
+	let mut moved = HashSet::with_capacity(deleted.len());
 
+	for entry in walked {
+		let key = (entry.metadata.inode, entry.metadata.device);
+		let Some(pub_id) = deleted.get(&key) else {
+			continue;
+		};
+
+		let IsolatedFilePathData {
+			materialized_path,
+			is_dir,
+			name,
+			extension,
+			..
+		} = &entry.iso_file_path;
+
+		use file_path::*;
+
+		let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+			(
+				(materialized_path::NAME, json!(materialized_path)),
+				materialized_path::set(Some(materialized_path.to_string())),
+			),
+			((name::NAME, json!(name)), name::set(Some(name.to_string()))),
+			((is_dir::NAME, json!(*is_dir)), is_dir::set(Some(*is_dir))),
 			(
-				sync.unique_shared_create(
-					sync::file_path::SyncId {
-						pub_id: uuid_to_bytes(entry.pub_id),
-					},
-					sync_params,
+				(extension::NAME, json!(extension)),
+				extension::set(Some(extension.to_string())),
+			),
+			(
+				(
+					size_in_bytes_bytes::NAME,
+					json!(entry.metadata.size_in_bytes.to_be_bytes().to_vec()),
 				),
-				file_path::create_unchecked(pub_id, db_params),
-			)
-		})
+				size_in_bytes_bytes::set(Some(entry.metadata.size_in_bytes.to_be_bytes().to_vec())),
+			),
+			(
+				(date_modified::NAME, json!(entry.metadata.modified_at)),
+				date_modified::set(Some(entry.metadata.modified_at.into())),
+			),
+			(
+				(date_indexed::NAME, json!(Utc::now())),
+				date_indexed::set(Some(Utc::now().into())),
+			),
+		]
+		.into_iter()
 		.unzip();
 
-	let count = sync
-		.write_ops(
+		sync.write_ops(
 			db,
 			(
-				sync_stuff,
-				db.file_path().create_many(paths).skip_duplicates(),
+				sync_params
+					.into_iter()
+					.map(|(field, value)| {
+						sync.shared_update(
+							sync::file_path::SyncId {
+								pub_id: pub_id.clone(),
+							},
+							field,
+							value,
+						)
+					})
+					.collect(),
+				db.file_path().update(
+					file_path::pub_id::equals(pub_id.clone()),
+					db_params
+						.into_iter()
+						.chain([date_deleted::set(None)])
+						.collect(),
+				),
 			),
 		)
 		.await?;
 
-	trace!("Inserted {count} records");
+		moved.insert(key);
+	}
+
+	if !moved.is_empty() {
+		trace!("Restored {} moved file paths", moved.len());
+	}
 
-	Ok(count)
+	Ok(moved)
 }
 
 fn iso_file_path_factory(
@@ -185,18 +324,22 @@ fn iso_file_path_factory(
 	}
 }
 
+/// Marks the file paths of files that weren't found anymore as deleted, see [`soft_delete`]
+///
+/// [`soft_delete`]: crate::location::soft_delete
 async fn remove_non_existing_file_paths(
 	to_remove: impl IntoIterator<Item = file_path_just_pub_id::Data>,
 	db: &PrismaClient,
 ) -> Result<u64, IndexerError> {
-	db.file_path()
-		.delete_many(vec![file_path::pub_id::in_vec(
+	soft_delete_file_paths(
+		db,
+		vec![file_path::pub_id::in_vec(
 			to_remove.into_iter().map(|data| data.pub_id).collect(),
-		)])
-		.exec()
-		.await
-		.map(|count| count as u64)
-		.map_err(Into::into)
+		)],
+	)
+	.await
+	.map(|count| count as u64)
+	.map_err(Into::into)
 }
 
 // TODO: Change this macro to a fn when we're able to return
@@ -240,22 +383,51 @@ macro_rules! to_remove_db_fetcher_fn {
 			let iso_file_path: $crate::location::file_path_helper::IsolatedFilePathData<'static> =
 				iso_file_path;
 
-			// FIXME: Can't pass this chunks variable direct to _batch because of lifetime issues
-			let chunks = unique_location_id_materialized_path_name_extension_params
+			let materialized_path = iso_file_path
+				.materialized_path_for_children()
+				.expect("the received isolated file path must be from a directory");
+
+			let unique_params_chunks = unique_location_id_materialized_path_name_extension_params
 				.into_iter()
 				.chunks(200)
+				.into_iter()
+				.map(|unique_params| unique_params.collect::<::std::vec::Vec<_>>())
+				.collect::<::std::vec::Vec<_>>();
+
+			// Files that were marked as deleted and are back
+			$db._batch(
+				unique_params_chunks
+					.iter()
+					.map(|unique_params| {
+						$db.file_path().update_many(
+							vec![
+								$crate::prisma::file_path::location_id::equals(Some($location_id)),
+								$crate::prisma::file_path::materialized_path::equals(Some(
+									materialized_path.clone(),
+								)),
+								$crate::prisma::file_path::date_deleted::not(None),
+								::prisma_client_rust::operator::or(unique_params.clone()),
+							],
+							vec![$crate::prisma::file_path::date_deleted::set(None)],
+						)
+					})
+					.collect::<::std::vec::Vec<_>>(),
+			)
+			.await?;
+
+			// FIXME: Can't pass this chunks variable direct to _batch because of lifetime issues
+			let chunks = unique_params_chunks
 				.into_iter()
 				.map(|unique_params| {
 					$db.file_path()
 						.find_many(vec![
 							$crate::prisma::file_path::location_id::equals(Some($location_id)),
 							$crate::prisma::file_path::materialized_path::equals(Some(
-								iso_file_path.materialized_path_for_children().expect(
-									"the received isolated file path must be from a directory",
-								),
+								materialized_path.clone(),
 							)),
+							$crate::prisma::file_path::date_deleted::equals(None),
 							::prisma_client_rust::operator::not(vec![
-								::prisma_client_rust::operator::or(unique_params.collect()),
+								::prisma_client_rust::operator::or(unique_params),
 							]),
 						])
 						.select($crate::location::file_path_helper::file_path_just_pub_id::select())
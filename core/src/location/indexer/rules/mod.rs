@@ -5,7 +5,7 @@ use crate::{
 	prisma::indexer_rule,
 	util::{
 		db::{maybe_missing, uuid_to_bytes, MissingFieldError},
-		error::{FileIOError, NonUtf8PathError},
+		error::{coded_error, CoreErrorCode, FileIOError, NonUtf8PathError},
 	},
 };
 
@@ -19,7 +19,6 @@ use chrono::{DateTime, Utc};
 use futures::future::try_join_all;
 use globset::{Glob, GlobSet, GlobSetBuilder};
 use rmp_serde::{self, decode, encode};
-use rspc::ErrorCode;
 use serde::{de, ser, Deserialize, Serialize};
 use specta::Type;
 use thiserror::Error;
@@ -54,15 +53,17 @@ pub enum IndexerRuleError {
 
 impl From<IndexerRuleError> for rspc::Error {
 	fn from(err: IndexerRuleError) -> Self {
-		match err {
+		let code = match err {
 			IndexerRuleError::InvalidRuleKindInt(_)
 			| IndexerRuleError::Glob(_)
-			| IndexerRuleError::NonUtf8Path(_) => {
-				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
-			}
-
-			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
-		}
+			| IndexerRuleError::NonUtf8Path(_) => CoreErrorCode::IndexerRuleInvalid,
+			IndexerRuleError::Database(_) => CoreErrorCode::Database,
+			IndexerRuleError::AcceptByItsChildrenFileIO(_)
+			| IndexerRuleError::RejectByItsChildrenFileIO(_) => CoreErrorCode::FileSystem,
+			_ => CoreErrorCode::Internal,
+		};
+
+		coded_error(code, err)
 	}
 }
 
@@ -19,8 +19,8 @@ use itertools::Itertools;
 
 use super::{
 	execute_indexer_save_step, iso_file_path_factory, location_with_indexer_rules,
-	remove_non_existing_file_paths, rules::IndexerRule, walk::walk_single_dir, IndexerError,
-	IndexerJobSaveStep,
+	remove_non_existing_file_paths, rules::IndexerRule, update_directories_sizes,
+	walk::walk_single_dir, IndexerError, IndexerJobSaveStep,
 };
 
 /// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
@@ -82,7 +82,7 @@ pub async fn shallow(
 	errors.into_iter().for_each(|e| error!("{e}"));
 
 	// TODO pass these uuids to sync system
-	remove_non_existing_file_paths(to_remove, &db).await?;
+	let removed_count = remove_non_existing_file_paths(to_remove, &db).await?;
 
 	let total_paths = &mut 0;
 
@@ -106,6 +106,10 @@ pub async fn shallow(
 		execute_indexer_save_step(location, &step, library).await?;
 	}
 
+	if *total_paths > 0 || removed_count > 0 {
+		update_directories_sizes(location_id, library).await?;
+	}
+
 	invalidate_query!(library, "search.paths");
 
 	library.orphan_remover.invoke().await;
@@ -4,7 +4,7 @@ use crate::{
 		MetadataExt,
 	},
 	prisma::file_path,
-	util::error::FileIOError,
+	util::{db::new_pub_id, error::FileIOError},
 };
 
 #[cfg(target_family = "unix")]
@@ -309,7 +309,7 @@ where
 
 		indexed_paths.into_iter().filter_map(move |entry| {
 			(!isolated_paths_already_in_db.contains(&entry.iso_file_path)).then(|| WalkedEntry {
-				pub_id: Uuid::new_v4(),
+				pub_id: new_pub_id(),
 				iso_file_path: entry.iso_file_path,
 				metadata: entry
 					.maybe_metadata
@@ -1,4 +1,8 @@
-use crate::{library::Library, prisma::location, util::db::maybe_missing};
+use crate::{
+	library::{Library, NotificationKind, WebhookEvent},
+	prisma::location,
+	util::db::maybe_missing,
+};
 
 use std::{
 	collections::{HashMap, HashSet},
@@ -32,6 +36,18 @@ pub(super) async fn check_online(
 				Ok(true)
 			}
 			Err(e) if e.kind() == ErrorKind::NotFound => {
+				if library.location_manager().is_online(&pub_id).await {
+					library.webhooks.dispatch(WebhookEvent::LocationOffline {
+						location_id: location.id,
+						location_pub_id: pub_id,
+					});
+					library
+						.notify(NotificationKind::LocationOffline {
+							location_id: location.id,
+						})
+						.await;
+				}
+
 				library.location_manager().remove_online(&pub_id).await;
 				Ok(false)
 			}
@@ -64,8 +80,8 @@ pub(super) fn watch_location(
 	let location_id = location.id;
 	let location_path = location.path.as_ref();
 	let Some(location_path) = location_path.map(Path::new) else {
-        return
-    };
+		return;
+	};
 
 	if let Some(mut watcher) = locations_unwatched.remove(&(location_id, library_id)) {
 		if watcher.check_path(location_path) {
@@ -85,8 +101,8 @@ pub(super) fn unwatch_location(
 	let location_id = location.id;
 	let location_path = location.path.as_ref();
 	let Some(location_path) = location_path.map(Path::new) else {
-        return
-    };
+		return;
+	};
 
 	if let Some(mut watcher) = locations_watched.remove(&(location_id, library_id)) {
 		if watcher.check_path(location_path) {
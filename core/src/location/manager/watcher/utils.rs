@@ -2,7 +2,6 @@ use crate::{
 	invalidate_query,
 	library::Library,
 	location::{
-		delete_directory,
 		file_path_helper::{
 			check_file_path_exists, create_file_path, file_path_with_object,
 			filter_existing_file_path_params,
@@ -13,15 +12,26 @@ use crate::{
 		find_location, location_with_indexer_rules,
 		manager::LocationManagerError,
 		scan_location_sub_path,
+		soft_delete::soft_delete_file_paths,
+		FileEvent, FileEventKind,
 	},
 	object::{
+		antivirus::scan_new_file_in_background,
 		file_identifier::FileMetadata,
-		preview::{can_generate_thumbnail_for_image, generate_image_thumbnail, get_thumbnail_path},
+		preview::{
+			can_generate_preview_for_text, can_generate_thumbnail_for_image,
+			can_generate_waveform_for_audio, generate_audio_waveform, generate_image_thumbnail,
+			generate_text_preview, get_text_preview_path, get_thumbnail_path, get_waveform_path,
+			remove_text_preview, remove_thumbnail_tiers, remove_waveform,
+		},
 		validation::hash::file_checksum,
 	},
 	prisma::{file_path, location, object},
 	sync,
-	util::{db::maybe_missing, error::FileIOError},
+	util::{
+		db::{maybe_missing, new_pub_id, uuid_to_bytes},
+		error::FileIOError,
+	},
 };
 
 #[cfg(target_family = "unix")]
@@ -37,15 +47,17 @@ use std::{
 	str::FromStr,
 };
 
-use sd_file_ext::extensions::ImageExtension;
+use sd_file_ext::extensions::{AudioExtension, CodeExtension, ImageExtension, TextExtension};
 
 use chrono::{DateTime, Local};
 use notify::{Event, EventKind};
-use prisma_client_rust::{raw, PrismaValue};
+use prisma_client_rust::{
+	operator::{and, or},
+	raw, PrismaValue,
+};
 use serde_json::json;
 use tokio::{fs, io::ErrorKind};
 use tracing::{debug, error, trace, warn};
-use uuid::Uuid;
 
 use super::INodeAndDevice;
 
@@ -113,21 +125,52 @@ pub(super) async fn create_dir(
 		.materialized_path_for_children()
 		.expect("We're in the create dir function lol");
 
-	debug!("Creating path: {}", iso_file_path);
+	// The directory may be coming back, after being marked as deleted
+	let deleted_dir = library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::date_deleted::not(None),
+			or(vec![
+				and(filter_existing_file_path_params(&iso_file_path)),
+				and(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::inode::equals(Some(inode.to_le_bytes().to_vec())),
+					file_path::device::equals(Some(device.to_le_bytes().to_vec())),
+				]),
+			]),
+		])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await?;
 
-	create_file_path(
-		library,
-		iso_file_path,
-		None,
-		FilePathMetadata {
-			inode,
-			device,
-			size_in_bytes: metadata.len(),
-			created_at: metadata.created_or_now().into(),
-			modified_at: metadata.modified_or_now().into(),
-		},
-	)
-	.await?;
+	let dir_id = if let Some(deleted_dir) = deleted_dir {
+		debug!("Restoring path: {}", iso_file_path);
+
+		restore_deleted_file_path(&deleted_dir.pub_id, &iso_file_path, inode, device, library)
+			.await?;
+
+		deleted_dir.id
+	} else {
+		debug!("Creating path: {}", iso_file_path);
+
+		create_file_path(
+			library,
+			iso_file_path.clone(),
+			None,
+			FilePathMetadata {
+				inode,
+				device,
+				size_in_bytes: metadata.len(),
+				created_at: metadata.created_or_now().into(),
+				modified_at: metadata.modified_or_now().into(),
+			},
+		)
+		.await?
+		.id
+	};
+
+	FileEvent::emit(library, dir_id, &iso_file_path, FileEventKind::Created);
 
 	// scan the new directory
 	scan_location_sub_path(library, location, &children_materialized_path).await?;
@@ -137,6 +180,39 @@ pub(super) async fn create_dir(
 	Ok(())
 }
 
+/// Restores a file path marked as deleted whose file is back at `iso_file_path`, which can differ
+/// from its previous path if it was moved while it wasn't watched
+async fn restore_deleted_file_path(
+	pub_id: &[u8],
+	iso_file_path: &IsolatedFilePathData<'_>,
+	inode: u64,
+	device: u64,
+	library: &Library,
+) -> Result<(), LocationManagerError> {
+	library
+		.db
+		.file_path()
+		.update(
+			file_path::pub_id::equals(pub_id.to_vec()),
+			vec![
+				file_path::materialized_path::set(Some(
+					iso_file_path.materialized_path.to_string(),
+				)),
+				file_path::name::set(Some(iso_file_path.name.to_string())),
+				file_path::extension::set(Some(iso_file_path.extension.to_string())),
+				file_path::inode::set(Some(inode.to_le_bytes().to_vec())),
+				file_path::device::set(Some(device.to_le_bytes().to_vec())),
+				file_path::date_deleted::set(None),
+			],
+		)
+		.exec()
+		.await?;
+
+	invalidate_query!(library, "files.deleted");
+
+	Ok(())
+}
+
 pub(super) async fn create_file(
 	location_id: location::id::Type,
 	path: impl AsRef<Path>,
@@ -205,6 +281,10 @@ async fn inner_create_file(
 			"File already exists with that inode and device: {}",
 			iso_file_path
 		);
+		if file_path.date_deleted.is_some() {
+			restore_deleted_file_path(&file_path.pub_id, &iso_file_path, inode, device, library)
+				.await?;
+		}
 		return inner_update_file(location_path, &file_path, path, library, None).await;
 
 	// If we can't find an existing file with the same inode and device, we check if there is a file with the same path
@@ -224,6 +304,10 @@ async fn inner_create_file(
 			"File already exists with that iso_file_path: {}",
 			iso_file_path
 		);
+		if file_path.date_deleted.is_some() {
+			restore_deleted_file_path(&file_path.pub_id, &iso_file_path, inode, device, library)
+				.await?;
+		}
 		return inner_update_file(
 			location_path,
 			&file_path,
@@ -253,7 +337,7 @@ async fn inner_create_file(
 
 	let created_file = create_file_path(
 		library,
-		iso_file_path,
+		iso_file_path.clone(),
 		Some(cas_id.clone()),
 		FilePathMetadata {
 			inode,
@@ -282,7 +366,7 @@ async fn inner_create_file(
 	} else {
 		db.object()
 			.create(
-				Uuid::new_v4().as_bytes().to_vec(),
+				uuid_to_bytes(new_pub_id()),
 				vec![
 					object::date_created::set(Some(
 						DateTime::<Local>::from(fs_metadata.created_or_now()).into(),
@@ -303,6 +387,27 @@ async fn inner_create_file(
 		.exec()
 		.await?;
 
+	FileEvent::emit(
+		library,
+		created_file.id,
+		&iso_file_path,
+		FileEventKind::Created,
+	);
+
+	// Scanned in the background, if it's enabled for the location
+	scan_new_file_in_background(
+		library.clone(),
+		location_id,
+		created_file.id,
+		Some(object.id),
+		format!(
+			"{}{}",
+			iso_file_path.materialized_path,
+			iso_file_path.full_name()
+		),
+		path.to_path_buf(),
+	);
+
 	if !extension.is_empty() {
 		// Running in a detached task as thumbnail generation can take a while and we don't want to block the watcher
 		let path = path.to_path_buf();
@@ -529,16 +634,22 @@ async fn inner_update_file(
 			.await?;
 
 			if let Some(ref object) = file_path.object {
-				// if this file had a thumbnail previously, we update it to match the new content
-				if library.thumbnail_exists(old_cas_id).await? {
+				let had_waveform = fs::metadata(get_waveform_path(library, old_cas_id))
+					.await
+					.is_ok();
+				let had_text_preview = fs::metadata(get_text_preview_path(library, old_cas_id))
+					.await
+					.is_ok();
+
+				// if this file had a thumbnail or a preview previously, we update it to match the new content
+				if library.thumbnail_exists(old_cas_id).await? || had_waveform || had_text_preview {
 					if let Some(ext) = &file_path.extension {
 						generate_thumbnail(ext, &cas_id, full_path, library).await;
 
 						// remove the old thumbnail as we're generating a new one
-						let thumb_path = get_thumbnail_path(library, old_cas_id);
-						fs::remove_file(&thumb_path)
-							.await
-							.map_err(|e| FileIOError::from((thumb_path, e)))?;
+						remove_thumbnail_tiers(&get_thumbnail_path(library, old_cas_id)).await?;
+						remove_waveform(library, old_cas_id).await?;
+						remove_text_preview(library, old_cas_id).await?;
 					}
 				}
 
@@ -563,6 +674,13 @@ async fn inner_update_file(
 				}
 			}
 
+			FileEvent::emit(
+				library,
+				file_path.id,
+				&iso_file_path,
+				FileEventKind::Modified,
+			);
+
 			invalidate_query!(library, "search.paths");
 		}
 	}
@@ -612,10 +730,10 @@ pub(super) async fn rename(
 		let is_dir = maybe_missing(file_path.is_dir, "file_path.is_dir")?;
 
 		let new = IsolatedFilePathData::new(location_id, &location_path, new_path, is_dir)?;
+		let old = IsolatedFilePathData::new(location_id, &location_path, old_path, is_dir)?;
 
 		// If the renamed path is a directory, we have to update every successor
 		if is_dir {
-			let old = IsolatedFilePathData::new(location_id, &location_path, old_path, is_dir)?;
 			// TODO: Fetch all file_paths that will be updated and dispatch sync events
 
 			let updated = library
@@ -647,6 +765,8 @@ pub(super) async fn rename(
 			.exec()
 			.await?;
 
+		FileEvent::emit(library, file_path.id, &new, FileEventKind::renamed(&old));
+
 		invalidate_query!(library, "search.paths");
 	}
 
@@ -690,34 +810,33 @@ pub(super) async fn remove_by_file_path(
 			let db = &library.db;
 
 			let is_dir = maybe_missing(file_path.is_dir, "file_path.is_dir")?;
-
-			// if is doesn't, we can remove it safely from our db
-			if is_dir {
-				delete_directory(
-					library,
-					location_id,
-					Some(&IsolatedFilePathData::try_from(file_path)?),
-				)
-				.await?;
+			let iso_file_path = IsolatedFilePathData::try_from(file_path)?;
+
+			// if is doesn't, we mark it as deleted, so its object survives the file coming back
+			let file_path_param = file_path::pub_id::equals(file_path.pub_id.clone());
+			let params = if is_dir {
+				vec![or(vec![
+					file_path_param,
+					and(vec![
+						file_path::location_id::equals(Some(location_id)),
+						file_path::materialized_path::starts_with(
+							iso_file_path
+								.materialized_path_for_children()
+								.expect("the file path is a directory"),
+						),
+					]),
+				])]
 			} else {
-				db.file_path()
-					.delete(file_path::pub_id::equals(file_path.pub_id.clone()))
-					.exec()
-					.await?;
-
-				if let Some(object_id) = file_path.object_id {
-					db.object()
-						.delete_many(vec![
-							object::id::equals(object_id),
-							// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
-							object::file_paths::none(vec![]),
-						])
-						.exec()
-						.await?;
-				}
-			}
+				vec![file_path_param]
+			};
+			soft_delete_file_paths(db, params).await?;
 
-			library.orphan_remover.invoke().await;
+			FileEvent::emit(
+				library,
+				file_path.id,
+				&iso_file_path,
+				FileEventKind::Deleted,
+			);
 		}
 		Err(e) => return Err(FileIOError::from((path, e)).into()),
 	}
@@ -734,6 +853,36 @@ async fn generate_thumbnail(
 	library: &Library,
 ) {
 	let path = path.as_ref();
+	let preferences = library.config().get().await.thumbnailer;
+
+	// Audio files get a waveform instead of a thumbnail
+	if let Ok(extension) = AudioExtension::from_str(extension) {
+		if preferences.audio && can_generate_waveform_for_audio(&extension) {
+			if let Err(e) = generate_audio_waveform(path, &get_waveform_path(library, cas_id)).await
+			{
+				error!("Failed to generate waveform on location manager: {e:#?}");
+			}
+		}
+
+		return;
+	}
+
+	// Text and source code files get a preview of their beginning instead of a thumbnail
+	let is_text = TextExtension::from_str(extension)
+		.map_or(false, |extension| can_generate_preview_for_text(&extension))
+		|| CodeExtension::from_str(extension).is_ok();
+	if is_text {
+		if preferences.text {
+			if let Err(e) =
+				generate_text_preview(path, &get_text_preview_path(library, cas_id)).await
+			{
+				error!("Failed to generate text preview on location manager: {e:#?}");
+			}
+		}
+
+		return;
+	}
+
 	let output_path = get_thumbnail_path(library, cas_id);
 
 	if let Err(e) = fs::metadata(&output_path).await {
@@ -752,8 +901,10 @@ async fn generate_thumbnail(
 	}
 
 	if let Ok(extension) = ImageExtension::from_str(extension) {
-		if can_generate_thumbnail_for_image(&extension) {
-			if let Err(e) = generate_image_thumbnail(path, &output_path).await {
+		if preferences.image && can_generate_thumbnail_for_image(&extension) {
+			if let Err(e) =
+				generate_image_thumbnail(path, &output_path, &library.config.thumbnails).await
+			{
 				error!("Failed to image thumbnail on location manager: {e:#?}");
 			}
 		}
@@ -765,13 +916,55 @@ async fn generate_thumbnail(
 		use sd_file_ext::extensions::VideoExtension;
 
 		if let Ok(extension) = VideoExtension::from_str(extension) {
-			if can_generate_thumbnail_for_video(&extension) {
-				if let Err(e) = generate_video_thumbnail(path, &output_path).await {
+			if preferences.video && can_generate_thumbnail_for_video(&extension) {
+				if let Err(e) = generate_video_thumbnail(
+					path,
+					&output_path,
+					&preferences,
+					&library.config.thumbnails,
+				)
+				.await
+				{
 					error!("Failed to video thumbnail on location manager: {e:#?}");
 				}
 			}
 		}
 	}
+
+	#[cfg(feature = "mesh")]
+	{
+		use crate::object::preview::can_generate_thumbnail_for_mesh;
+		use sd_file_ext::extensions::MeshExtension;
+
+		if let Ok(extension) = MeshExtension::from_str(extension) {
+			if preferences.mesh && can_generate_thumbnail_for_mesh(&extension) {
+				if let Err(e) =
+					generate_image_thumbnail(path, &output_path, &library.config.thumbnails).await
+				{
+					error!("Failed to 3D model thumbnail on location manager: {e:#?}");
+				}
+			}
+		}
+	}
+
+	#[cfg(feature = "pdf")]
+	{
+		use crate::object::preview::{
+			can_generate_thumbnail_for_document, generate_document_thumbnail,
+		};
+		use sd_file_ext::extensions::DocumentExtension;
+
+		if let Ok(extension) = DocumentExtension::from_str(extension) {
+			if can_generate_thumbnail_for_document(&extension, &preferences) {
+				if let Err(e) =
+					generate_document_thumbnail(path, &output_path, &library.config.thumbnails)
+						.await
+				{
+					error!("Failed to document thumbnail on location manager: {e:#?}");
+				}
+			}
+		}
+	}
 }
 
 pub(super) async fn extract_inode_and_device_from_path(
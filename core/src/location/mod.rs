@@ -4,13 +4,16 @@ use crate::{
 	library::Library,
 	location::file_path_helper::filter_existing_file_path_params,
 	object::{
+		album::rematerialize_smart_albums,
+		antivirus::scanner_job::MalwareScannerJobInit,
 		file_identifier::{self, file_identifier_job::FileIdentifierJobInit},
 		preview::{shallow_thumbnailer, thumbnailer_job::ThumbnailerJobInit},
+		sensitive::classifier_job::SensitiveContentClassifierJobInit,
 	},
 	prisma::{file_path, indexer_rules_in_location, location, node, PrismaClient},
 	sync,
 	util::{
-		db::{chain_optional_iter, uuid_to_bytes},
+		db::{chain_optional_iter, maybe_missing, new_pub_id, uuid_to_bytes},
 		error::FileIOError,
 	},
 };
@@ -31,16 +34,25 @@ use tokio::{fs, io};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+pub mod automation;
 mod error;
+mod file_event;
 pub mod file_path_helper;
+pub mod hot_folder;
 pub mod indexer;
 mod manager;
 mod metadata;
+pub mod provider;
+pub mod soft_delete;
+pub mod space;
+mod upload;
 
 pub use error::LocationError;
+pub use file_event::{FileEvent, FileEventKind};
 use indexer::IndexerJobInit;
 pub use manager::{LocationManager, LocationManagerError};
 use metadata::SpacedriveLocationMetadataFile;
+pub use upload::{upload_file, UploadError, UploadedFile};
 
 use file_path_helper::IsolatedFilePathData;
 
@@ -105,7 +117,7 @@ impl LocationCreateArgs {
 			self.path.display()
 		);
 
-		let uuid = Uuid::new_v4();
+		let uuid = new_pub_id();
 
 		let location = create_location(
 			library,
@@ -175,7 +187,7 @@ impl LocationCreateArgs {
 			self.path.display()
 		);
 
-		let uuid = Uuid::new_v4();
+		let uuid = new_pub_id();
 
 		let location = create_location(
 			library,
@@ -221,6 +233,8 @@ pub struct LocationUpdateArgs {
 	pub generate_preview_media: Option<bool>,
 	pub sync_preview_media: Option<bool>,
 	pub hidden: Option<bool>,
+	pub write_embedded_metadata: Option<bool>,
+	pub scan_for_malware: Option<bool>,
 	pub indexer_rules_ids: Vec<i32>,
 }
 
@@ -262,6 +276,18 @@ impl LocationUpdateArgs {
 					location::hidden::set(Some(v)),
 				)
 			}),
+			self.write_embedded_metadata.map(|v| {
+				(
+					(location::write_embedded_metadata::NAME, json!(v)),
+					location::write_embedded_metadata::set(Some(v)),
+				)
+			}),
+			self.scan_for_malware.map(|v| {
+				(
+					(location::scan_for_malware::NAME, json!(v)),
+					location::scan_for_malware::set(Some(v)),
+				)
+			}),
 		]
 		.into_iter()
 		.flatten()
@@ -341,6 +367,25 @@ impl LocationUpdateArgs {
 	}
 }
 
+/// Name of a location as a directory, like in the WebDAV share or the mount of a library, which
+/// must be a valid file name unique among the `taken` ones
+pub(crate) fn location_dir_name(
+	taken: &mut HashSet<String>,
+	id: location::id::Type,
+	name: Option<&str>,
+) -> String {
+	let mut dir_name = name
+		.filter(|name| !name.is_empty() && !name.contains(['/', '\\']))
+		.map_or_else(|| id.to_string(), ToString::to_string);
+
+	if !taken.insert(dir_name.clone()) {
+		dir_name = format!("{dir_name} ({id})");
+		taken.insert(dir_name.clone());
+	}
+
+	dir_name
+}
+
 pub fn find_location(
 	library: &Library,
 	location_id: location::id::Type,
@@ -351,6 +396,24 @@ pub fn find_location(
 		.find_unique(location::id::equals(location_id))
 }
 
+/// Fails if the directory of a location of this node can't be reached, like when its drive was
+/// unplugged. Locations of other nodes aren't checked.
+pub async fn ensure_online(
+	library: &Library,
+	location: &location::Data,
+) -> Result<(), LocationError> {
+	if location.node_id != Some(library.node_local_id) {
+		return Ok(());
+	}
+
+	let path = maybe_missing(&location.path, "location.path")?;
+	match fs::metadata(path).await {
+		Ok(_) => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Err(LocationError::Offline(location.id)),
+		Err(e) => Err(LocationError::FileIO(FileIOError::from((path, e)))),
+	}
+}
+
 async fn link_location_and_indexer_rules(
 	library: &Library,
 	location_id: location::id::Type,
@@ -381,24 +444,64 @@ pub async fn scan_location(
 
 	let location_base_data = location::Data::from(&location);
 
-	JobBuilder::new(IndexerJobInit {
+	let indexer = JobBuilder::new(IndexerJobInit {
 		location,
 		sub_path: None,
 	})
 	.with_action("scan_location")
-	.with_metadata(json!({"location": location_base_data.clone()}))
-	.build()
-	.queue_next(FileIdentifierJobInit {
+	.with_metadata(json!({"location": location_base_data.clone()}));
+
+	let identifier = JobBuilder::new(FileIdentifierJobInit {
 		location: location_base_data.clone(),
 		sub_path: None,
 	})
-	.queue_next(ThumbnailerJobInit {
-		location: location_base_data,
+	.with_action("scan_location-1")
+	.with_parent_id(indexer.id())
+	.with_dependency(indexer.id());
+
+	let malware_scanner = (location_base_data.scan_for_malware == Some(true)).then(|| {
+		JobBuilder::new(MalwareScannerJobInit {
+			location: location_base_data.clone(),
+			sub_path: None,
+		})
+		.with_action("scan_location-3")
+		.with_parent_id(indexer.id())
+		.with_dependency(identifier.id())
+	});
+
+	let thumbnailer = JobBuilder::new(ThumbnailerJobInit {
+		location: location_base_data.clone(),
 		sub_path: None,
 	})
-	.spawn(library)
+	.with_action("scan_location-2")
+	.with_parent_id(indexer.id())
+	.with_dependency(identifier.id());
+
+	let classifier = library
+		.config()
+		.get()
+		.await
+		.sensitive_content
+		.enabled
+		.then(|| {
+			JobBuilder::new(SensitiveContentClassifierJobInit {
+				location: location_base_data,
+				sub_path: None,
+			})
+			.with_action("scan_location-4")
+			.with_parent_id(indexer.id())
+			.with_dependency(thumbnailer.id())
+		});
+
+	spawn_scan_jobs(
+		library,
+		indexer,
+		identifier,
+		thumbnailer,
+		malware_scanner,
+		classifier,
+	)
 	.await
-	.map_err(Into::into)
 }
 
 #[cfg(feature = "location-watcher")]
@@ -414,7 +517,7 @@ pub async fn scan_location_sub_path(
 
 	let location_base_data = location::Data::from(&location);
 
-	JobBuilder::new(IndexerJobInit {
+	let indexer = JobBuilder::new(IndexerJobInit {
 		location,
 		sub_path: Some(sub_path.clone()),
 	})
@@ -422,19 +525,86 @@ pub async fn scan_location_sub_path(
 	.with_metadata(json!({
 		"location": location_base_data.clone(),
 		"sub_path": sub_path.clone(),
-	}))
-	.build()
-	.queue_next(FileIdentifierJobInit {
+	}));
+
+	let identifier = JobBuilder::new(FileIdentifierJobInit {
 		location: location_base_data.clone(),
 		sub_path: Some(sub_path.clone()),
 	})
-	.queue_next(ThumbnailerJobInit {
-		location: location_base_data,
-		sub_path: Some(sub_path),
+	.with_action("scan_location_sub_path-1")
+	.with_parent_id(indexer.id())
+	.with_dependency(indexer.id());
+
+	let malware_scanner = (location_base_data.scan_for_malware == Some(true)).then(|| {
+		JobBuilder::new(MalwareScannerJobInit {
+			location: location_base_data.clone(),
+			sub_path: Some(sub_path.clone()),
+		})
+		.with_action("scan_location_sub_path-3")
+		.with_parent_id(indexer.id())
+		.with_dependency(identifier.id())
+	});
+
+	let thumbnailer = JobBuilder::new(ThumbnailerJobInit {
+		location: location_base_data.clone(),
+		sub_path: Some(sub_path.clone()),
 	})
-	.spawn(library)
+	.with_action("scan_location_sub_path-2")
+	.with_parent_id(indexer.id())
+	.with_dependency(identifier.id());
+
+	let classifier = library
+		.config()
+		.get()
+		.await
+		.sensitive_content
+		.enabled
+		.then(|| {
+			JobBuilder::new(SensitiveContentClassifierJobInit {
+				location: location_base_data,
+				sub_path: Some(sub_path),
+			})
+			.with_action("scan_location_sub_path-4")
+			.with_parent_id(indexer.id())
+			.with_dependency(thumbnailer.id())
+		});
+
+	spawn_scan_jobs(
+		library,
+		indexer,
+		identifier,
+		thumbnailer,
+		malware_scanner,
+		classifier,
+	)
 	.await
-	.map_err(Into::into)
+}
+
+/// Spawns the jobs of a scan as a dependency graph: indexing, then identifying, then generating
+/// thumbnails and rematerializing smart albums, as new objects may match their search queries.
+/// New files are scanned for malware alongside the thumbnails, once they have objects to be tagged.
+/// New images are classified after the thumbnails, which are smaller to decode than the images.
+async fn spawn_scan_jobs(
+	library: &Library,
+	indexer: JobBuilder<IndexerJobInit>,
+	identifier: JobBuilder<FileIdentifierJobInit>,
+	thumbnailer: JobBuilder<ThumbnailerJobInit>,
+	malware_scanner: Option<JobBuilder<MalwareScannerJobInit>>,
+	classifier: Option<JobBuilder<SensitiveContentClassifierJobInit>>,
+) -> Result<(), JobManagerError> {
+	let identifier_id = identifier.id();
+
+	indexer.build().spawn(library).await?;
+	identifier.build().spawn(library).await?;
+	thumbnailer.build().spawn(library).await?;
+	if let Some(malware_scanner) = malware_scanner {
+		malware_scanner.build().spawn(library).await?;
+	}
+	if let Some(classifier) = classifier {
+		classifier.build().spawn(library).await?;
+	}
+
+	rematerialize_smart_albums(library, Some(identifier_id)).await
 }
 
 pub async fn light_scan_location(
@@ -721,10 +891,13 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			write_embedded_metadata: data.write_embedded_metadata,
+			scan_for_malware: data.scan_for_malware,
 			date_created: data.date_created,
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
+			statistics_snapshots: None,
 		}
 	}
 }
@@ -743,10 +916,13 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			write_embedded_metadata: data.write_embedded_metadata,
+			scan_for_malware: data.scan_for_malware,
 			date_created: data.date_created,
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
+			statistics_snapshots: None,
 		}
 	}
 }
@@ -0,0 +1,151 @@
+use crate::util::error::FileIOError;
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use tokio::{fs, io};
+
+use super::{check_relative, LocationProvider, ProviderEntry, ProviderError, ProviderReader};
+
+/// Files on the disks of this node, its roots being absolute paths to directories
+pub struct LocalProvider;
+
+impl LocalProvider {
+	fn full_path(root: &str, path: &Path) -> Result<PathBuf, ProviderError> {
+		check_relative(path)?;
+		Ok(Path::new(root).join(path))
+	}
+}
+
+fn not_found_or_io(path: &Path, e: io::Error) -> ProviderError {
+	if e.kind() == io::ErrorKind::NotFound {
+		ProviderError::NotFound(path.into())
+	} else {
+		FileIOError::from((path, e)).into()
+	}
+}
+
+fn entry(relative_path: PathBuf, metadata: &std::fs::Metadata) -> ProviderEntry {
+	ProviderEntry {
+		name: relative_path
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_default(),
+		path: relative_path,
+		is_dir: metadata.is_dir(),
+		size_in_bytes: metadata.len(),
+		date_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+	}
+}
+
+#[async_trait::async_trait]
+impl LocationProvider for LocalProvider {
+	fn scheme(&self) -> &'static str {
+		"local"
+	}
+
+	fn name(&self) -> &str {
+		"This device"
+	}
+
+	async fn stat(&self, root: &str, path: &Path) -> Result<ProviderEntry, ProviderError> {
+		let full_path = Self::full_path(root, path)?;
+		let metadata = fs::metadata(&full_path)
+			.await
+			.map_err(|e| not_found_or_io(&full_path, e))?;
+
+		Ok(entry(path.to_path_buf(), &metadata))
+	}
+
+	async fn list(&self, root: &str, path: &Path) -> Result<Vec<ProviderEntry>, ProviderError> {
+		let full_path = Self::full_path(root, path)?;
+		let mut read_dir = fs::read_dir(&full_path)
+			.await
+			.map_err(|e| not_found_or_io(&full_path, e))?;
+
+		let mut entries = vec![];
+		while let Some(dir_entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&full_path, e)))?
+		{
+			let metadata = dir_entry
+				.metadata()
+				.await
+				.map_err(|e| FileIOError::from((dir_entry.path(), e)))?;
+
+			entries.push(entry(path.join(dir_entry.file_name()), &metadata));
+		}
+
+		Ok(entries)
+	}
+
+	async fn read(&self, root: &str, path: &Path) -> Result<ProviderReader, ProviderError> {
+		let full_path = Self::full_path(root, path)?;
+		let file = fs::File::open(&full_path)
+			.await
+			.map_err(|e| not_found_or_io(&full_path, e))?;
+
+		Ok(Box::pin(file))
+	}
+
+	async fn write(
+		&self,
+		root: &str,
+		path: &Path,
+		mut contents: ProviderReader,
+	) -> Result<u64, ProviderError> {
+		let full_path = Self::full_path(root, path)?;
+		let mut file = fs::File::create(&full_path)
+			.await
+			.map_err(|e| FileIOError::from((&full_path, e)))?;
+
+		io::copy(&mut contents, &mut file)
+			.await
+			.map_err(|e| FileIOError::from((&full_path, e)).into())
+	}
+
+	#[cfg(feature = "location-watcher")]
+	async fn watch(&self, root: &str) -> Result<super::ProviderEvents, ProviderError> {
+		use super::ProviderEvent;
+
+		use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+		use tokio::sync::mpsc;
+
+		let root = PathBuf::from(root);
+		let (events_tx, mut events_rx) = mpsc::unbounded_channel::<Event>();
+
+		let mut watcher = RecommendedWatcher::new(
+			move |result: notify::Result<Event>| {
+				if let Ok(event) = result {
+					events_tx.send(event).ok();
+				}
+			},
+			Config::default(),
+		)
+		.map_err(|e| ProviderError::Provider(Box::new(e)))?;
+		watcher
+			.watch(&root, RecursiveMode::Recursive)
+			.map_err(|e| ProviderError::Provider(Box::new(e)))?;
+
+		Ok(Box::pin(async_stream::stream! {
+			// Dropping the watcher along with the stream stops it
+			let _watcher = watcher;
+
+			while let Some(event) = events_rx.recv().await {
+				for path in event.paths {
+					let Ok(path) = path.strip_prefix(&root).map(Path::to_path_buf) else {
+						continue;
+					};
+
+					match event.kind {
+						EventKind::Create(_) => yield ProviderEvent::Created { path },
+						EventKind::Modify(_) => yield ProviderEvent::Modified { path },
+						EventKind::Remove(_) => yield ProviderEvent::Removed { path },
+						_ => {}
+					}
+				}
+			}
+		}))
+	}
+}
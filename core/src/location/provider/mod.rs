@@ -0,0 +1,210 @@
+//! Where the files of locations are stored, for crates embedding the core to add storage it
+//! doesn't know about, like IPFS, Storj or a corporate DMS, without forking it. Providers are
+//! registered when the node starts, through [`NodeExtensions`](crate::NodeExtensions), under a
+//! scheme of their own, and the core only goes through the [`LocationProvider`] trait to reach
+//! them. Files on the disks of the node are provided by the built-in `local` provider.
+//!
+//! Roots are opaque to the core: each provider decides what it means, a directory, a bucket or
+//! a content id. Paths are always relative to a root, and can't leave it.
+
+use crate::util::error::{coded_error, CoreErrorCode, FileIOError};
+
+use std::{
+	collections::HashMap,
+	error::Error as StdError,
+	path::{Component, Path, PathBuf},
+	pin::Pin,
+	sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::io::AsyncRead;
+
+mod local;
+
+pub use local::LocalProvider;
+
+/// Contents of a file being read from or written to a provider
+pub type ProviderReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Changes to the files under a root, for as long as the stream is kept
+pub type ProviderEvents = BoxStream<'static, ProviderEvent>;
+
+/// A file or directory of a provider
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderEntry {
+	pub name: String,
+	/// Relative to the root it was listed from
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub size_in_bytes: u64,
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ProviderEvent {
+	Created { path: PathBuf },
+	Modified { path: PathBuf },
+	Removed { path: PathBuf },
+}
+
+/// A provider registered on this node, as shown to the frontend
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct ProviderInfo {
+	pub scheme: String,
+	pub name: String,
+}
+
+#[derive(Error, Debug)]
+pub enum ProviderError {
+	#[error("no location provider registered for scheme '{0}'")]
+	UnknownScheme(String),
+	#[error("a location provider is already registered for scheme '{0}'")]
+	DuplicateScheme(String),
+	#[error("path not found <path='{}'>", .0.display())]
+	NotFound(Box<Path>),
+	#[error("path isn't relative to the root <path='{}'>", .0.display())]
+	OutsideRoot(Box<Path>),
+	#[error("the '{0}' location provider doesn't support {1}")]
+	Unsupported(&'static str, &'static str),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	/// Errors of the providers themselves, which the core doesn't know about
+	#[error("location provider error: {0}")]
+	Provider(Box<dyn StdError + Send + Sync>),
+}
+
+impl From<ProviderError> for rspc::Error {
+	fn from(err: ProviderError) -> Self {
+		let code = match err {
+			ProviderError::UnknownScheme(_) => CoreErrorCode::ProviderUnknown,
+			ProviderError::NotFound(_) => CoreErrorCode::NotFound,
+			ProviderError::OutsideRoot(_) => CoreErrorCode::InvalidInput,
+			ProviderError::Unsupported(..) => CoreErrorCode::ProviderUnsupported,
+			ProviderError::FileIO(_) => CoreErrorCode::FileSystem,
+			_ => CoreErrorCode::Internal,
+		};
+
+		coded_error(code, err)
+	}
+}
+
+/// Storage locations can be kept in. Implementations are called concurrently, from any thread.
+#[async_trait::async_trait]
+pub trait LocationProvider: Send + Sync + 'static {
+	/// Unique among the providers of a node, like `ipfs`
+	fn scheme(&self) -> &'static str;
+
+	/// Shown to users
+	fn name(&self) -> &str;
+
+	async fn stat(&self, root: &str, path: &Path) -> Result<ProviderEntry, ProviderError>;
+
+	/// The direct children of a directory
+	async fn list(&self, root: &str, path: &Path) -> Result<Vec<ProviderEntry>, ProviderError>;
+
+	async fn read(&self, root: &str, path: &Path) -> Result<ProviderReader, ProviderError>;
+
+	/// Creates or replaces a file, returning how many bytes were written
+	async fn write(
+		&self,
+		root: &str,
+		path: &Path,
+		contents: ProviderReader,
+	) -> Result<u64, ProviderError>;
+
+	/// Changes to the files under a root. Providers that can't be watched don't have to
+	/// implement it, their locations are only updated when they're rescanned.
+	async fn watch(&self, _root: &str) -> Result<ProviderEvents, ProviderError> {
+		Err(ProviderError::Unsupported(self.scheme(), "watching"))
+	}
+}
+
+/// Makes sure a path stays under its root, as providers are given paths coming from clients
+pub fn check_relative(path: &Path) -> Result<(), ProviderError> {
+	if path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+	{
+		Ok(())
+	} else {
+		Err(ProviderError::OutsideRoot(path.into()))
+	}
+}
+
+/// The providers registered on this node
+pub struct LocationProviders {
+	providers: HashMap<&'static str, Arc<dyn LocationProvider>>,
+}
+
+impl LocationProviders {
+	/// The built-in providers, along with the ones registered by the crate embedding the core
+	pub(crate) fn new(
+		registered: Vec<Arc<dyn LocationProvider>>,
+	) -> Result<Arc<Self>, ProviderError> {
+		let mut providers = HashMap::new();
+
+		for provider in [Arc::new(LocalProvider) as Arc<dyn LocationProvider>]
+			.into_iter()
+			.chain(registered)
+		{
+			let scheme = provider.scheme();
+			if providers.insert(scheme, provider).is_some() {
+				return Err(ProviderError::DuplicateScheme(scheme.to_string()));
+			}
+		}
+
+		Ok(Arc::new(Self { providers }))
+	}
+
+	pub fn get(&self, scheme: &str) -> Result<Arc<dyn LocationProvider>, ProviderError> {
+		self.providers
+			.get(scheme)
+			.cloned()
+			.ok_or_else(|| ProviderError::UnknownScheme(scheme.to_string()))
+	}
+
+	pub fn list(&self) -> Vec<ProviderInfo> {
+		let mut infos = self
+			.providers
+			.values()
+			.map(|provider| ProviderInfo {
+				scheme: provider.scheme().to_string(),
+				name: provider.name().to_string(),
+			})
+			.collect::<Vec<_>>();
+		infos.sort_by(|a, b| a.scheme.cmp(&b.scheme));
+
+		infos
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_paths_leaving_the_root() {
+		assert!(check_relative(Path::new("photos/cat.jpg")).is_ok());
+		assert!(check_relative(Path::new("./photos")).is_ok());
+		assert!(check_relative(Path::new("")).is_ok());
+		assert!(check_relative(Path::new("../etc/passwd")).is_err());
+		assert!(check_relative(Path::new("photos/../../etc")).is_err());
+		assert!(check_relative(Path::new("/etc/passwd")).is_err());
+	}
+
+	#[test]
+	fn rejects_duplicate_schemes() {
+		assert!(LocationProviders::new(vec![]).is_ok());
+		assert!(matches!(
+			LocationProviders::new(vec![Arc::new(LocalProvider)]),
+			Err(ProviderError::DuplicateScheme(_))
+		));
+	}
+}
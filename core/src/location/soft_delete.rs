@@ -0,0 +1,184 @@
+//! File paths of files that disappeared are marked as deleted instead of being removed from the
+//! database, so the objects they link to, along with their tags, notes and ratings, survive a
+//! drive being unplugged or a share going offline for a while. They're restored when the indexer
+//! or the watcher find their files again, and removed for good once they've been deleted for
+//! longer than the grace period of the node.
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	prisma::{file_path, location, PrismaClient},
+};
+
+use std::time::Duration;
+
+use chrono::Utc;
+use prisma_client_rust::{
+	operator::{and, or},
+	QueryError,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error};
+
+use super::file_path_helper::IsolatedFilePathData;
+
+/// How often file paths past their grace period are removed
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftDeletePolicy {
+	/// Days file paths stay marked as deleted before being removed, along with the objects only
+	/// they linked to
+	pub grace_period_days: u32,
+}
+
+impl Default for SoftDeletePolicy {
+	fn default() -> Self {
+		Self {
+			grace_period_days: 30,
+		}
+	}
+}
+
+/// Marks the file paths matching `params` as deleted, returning how many were
+pub async fn soft_delete_file_paths(
+	db: &PrismaClient,
+	mut params: Vec<file_path::WhereParam>,
+) -> Result<i64, QueryError> {
+	// Keeping the date of the first deletion, so the grace period doesn't restart
+	params.push(file_path::date_deleted::equals(None));
+
+	db.file_path()
+		.update_many(
+			params,
+			vec![file_path::date_deleted::set(Some(Utc::now().into()))],
+		)
+		.exec()
+		.await
+}
+
+/// Restores deleted file paths, along with the contents of the directories among them and their
+/// ancestors, so they can be browsed to again
+pub async fn restore_file_paths(
+	library: &Library,
+	ids: Vec<file_path::id::Type>,
+) -> Result<i64, QueryError> {
+	let Library { db, .. } = library;
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::id::in_vec(ids.clone()),
+			file_path::date_deleted::not(None),
+		])
+		.exec()
+		.await?;
+
+	let mut params = vec![file_path::id::in_vec(ids)];
+
+	for file_path in &file_paths {
+		let Ok(iso_file_path) = IsolatedFilePathData::try_from(file_path) else {
+			continue;
+		};
+
+		if let Some(materialized_path) = iso_file_path.materialized_path_for_children() {
+			params.push(and(vec![
+				file_path::location_id::equals(file_path.location_id),
+				file_path::materialized_path::starts_with(materialized_path),
+			]));
+		}
+
+		// Every ancestor directory, like `/a/` named `b` for a materialized path of `/a/b/`
+		let materialized_path = iso_file_path.materialized_path.as_ref();
+		let mut name_start = 1;
+		while let Some(name_len) = materialized_path
+			.get(name_start..)
+			.and_then(|rest| rest.find('/'))
+		{
+			params.push(and(vec![
+				file_path::location_id::equals(file_path.location_id),
+				file_path::materialized_path::equals(Some(
+					materialized_path[..name_start].to_string(),
+				)),
+				file_path::name::equals(Some(
+					materialized_path[name_start..name_start + name_len].to_string(),
+				)),
+				file_path::is_dir::equals(Some(true)),
+			]));
+			name_start += name_len + 1;
+		}
+	}
+
+	let mut restored = 0;
+	// SQLite has a limit on the size of expression trees
+	for params in params.chunks(200) {
+		restored += db
+			.file_path()
+			.update_many(
+				vec![file_path::date_deleted::not(None), or(params.to_vec())],
+				vec![file_path::date_deleted::set(None)],
+			)
+			.exec()
+			.await?;
+	}
+
+	if restored > 0 {
+		invalidate_query!(library, "search.paths");
+		invalidate_query!(library, "files.deleted");
+	}
+
+	Ok(restored)
+}
+
+/// Removes the file paths deleted for longer than `grace_period_days`, or only those of a location,
+/// then the objects left without any file path. Returns how many file paths were removed.
+pub async fn purge_deleted_file_paths(
+	library: &Library,
+	location_id: Option<location::id::Type>,
+	grace_period_days: u32,
+) -> Result<i64, QueryError> {
+	let cutoff = Utc::now() - chrono::Duration::days(grace_period_days as i64);
+
+	let mut params = vec![file_path::date_deleted::lte(cutoff.into())];
+	if let Some(location_id) = location_id {
+		params.push(file_path::location_id::equals(Some(location_id)));
+	}
+
+	let purged = library.db.file_path().delete_many(params).exec().await?;
+
+	if purged > 0 {
+		library.orphan_remover.invoke().await;
+		invalidate_query!(library, "files.deleted");
+	}
+
+	Ok(purged)
+}
+
+/// Spawns a loop that periodically removes the file paths of the library past the grace period
+/// of the node
+pub fn spawn_deleted_file_paths_purge(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(PURGE_INTERVAL);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			tick.tick().await;
+
+			let policy = library.config().get().await.soft_delete;
+
+			match purge_deleted_file_paths(&library, None, policy.grace_period_days).await {
+				Ok(purged) => debug!(
+					"Removed {purged} deleted file paths from library <id='{}'>",
+					library.id
+				),
+				Err(e) => error!(
+					"Failed to remove deleted file paths of library <id='{}'>: {e:#?}",
+					library.id
+				),
+			}
+		}
+	});
+}
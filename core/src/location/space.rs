@@ -0,0 +1,371 @@
+//! Where the space of a location goes, from the sizes the indexer keeps in each directory, see
+//! [`update_directories_sizes`].
+//!
+//! [`update_directories_sizes`]: crate::location::indexer::update_directories_sizes
+
+use crate::{
+	library::Library,
+	prisma::{file_path, location},
+};
+
+use std::path::PathBuf;
+
+use futures::future::{BoxFuture, FutureExt};
+use serde::Serialize;
+use specta::Type;
+
+use super::{
+	file_path_helper::{check_file_path_exists, IsolatedFilePathData},
+	find_location, LocationError,
+};
+
+file_path::select!(file_path_for_space {
+	id
+	is_dir
+	materialized_path
+	name
+	extension
+	size_in_bytes_bytes
+});
+
+/// A file or directory, with its heaviest contents if it's a directory
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceItem {
+	pub id: file_path::id::Type,
+	pub name: String,
+	/// Relative to the root of the location, without a leading or trailing slash
+	pub path: String,
+	pub is_dir: bool,
+	#[specta(type = String)]
+	pub size: u64,
+	pub children: Vec<SpaceItem>,
+}
+
+impl SpaceItem {
+	/// Materialized path of the contents of this directory
+	fn materialized_path_for_children(&self) -> String {
+		format!("/{}/", self.path)
+	}
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceAnalysis {
+	/// Size of everything in the analyzed directory
+	#[specta(type = String)]
+	pub total_size: u64,
+	pub items: Vec<SpaceItem>,
+}
+
+/// Materialized path of the contents of a directory of the location, checking it's indexed
+async fn materialized_path_for_sub_path(
+	library: &Library,
+	location_id: location::id::Type,
+	sub_path: Option<&str>,
+) -> Result<String, LocationError> {
+	find_location(library, location_id)
+		.select(location::select!({ id }))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let Some(sub_path) = sub_path
+		.map(|sub_path| sub_path.trim_matches('/'))
+		.filter(|sub_path| !sub_path.is_empty())
+	else {
+		return Ok("/".to_string());
+	};
+
+	let relative_path = format!("{sub_path}/");
+	let iso_file_path = IsolatedFilePathData::from_relative_str(location_id, &relative_path);
+
+	if !check_file_path_exists::<LocationError>(&iso_file_path, &library.db).await? {
+		return Err(LocationError::DirectoryNotFound(PathBuf::from(sub_path)));
+	}
+
+	Ok(format!("/{relative_path}"))
+}
+
+/// Contents of a directory, heaviest first
+async fn children_by_size(
+	library: &Library,
+	location_id: location::id::Type,
+	materialized_path: &str,
+) -> Result<Vec<SpaceItem>, LocationError> {
+	let mut items = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(Some(materialized_path.to_string())),
+			file_path::date_deleted::equals(None),
+		])
+		.select(file_path_for_space::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			let iso_file_path = IsolatedFilePathData::from_db_data(
+				location_id,
+				file_path.is_dir.unwrap_or(false),
+				file_path.materialized_path?.into(),
+				file_path.name?.into(),
+				file_path.extension.unwrap_or_default().into(),
+			);
+
+			Some(SpaceItem {
+				id: file_path.id,
+				name: iso_file_path.full_name(),
+				path: iso_file_path.to_string(),
+				is_dir: iso_file_path.is_dir(),
+				size: file_path
+					.size_in_bytes_bytes
+					.as_deref()
+					.and_then(|bytes| bytes.try_into().ok())
+					.map_or(0, u64::from_be_bytes),
+				children: vec![],
+			})
+		})
+		.collect::<Vec<_>>();
+
+	items.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+	Ok(items)
+}
+
+/// Fills the directories among `items` with their `take` heaviest contents, `depth` levels deep
+fn add_largest_children<'a>(
+	library: &'a Library,
+	location_id: location::id::Type,
+	items: &'a mut [SpaceItem],
+	depth: u32,
+	take: usize,
+) -> BoxFuture<'a, Result<(), LocationError>> {
+	async move {
+		if depth == 0 {
+			return Ok(());
+		}
+
+		for item in items.iter_mut().filter(|item| item.is_dir) {
+			let mut children =
+				children_by_size(library, location_id, &item.materialized_path_for_children())
+					.await?;
+			children.truncate(take);
+
+			add_largest_children(library, location_id, &mut children, depth - 1, take).await?;
+
+			item.children = children;
+		}
+
+		Ok(())
+	}
+	.boxed()
+}
+
+/// The `take` heaviest files and directories of a directory of the location, or of its root,
+/// along with the heaviest contents of those directories, `depth` levels deep
+pub async fn largest_items(
+	library: &Library,
+	location_id: location::id::Type,
+	sub_path: Option<&str>,
+	depth: u32,
+	take: usize,
+) -> Result<SpaceAnalysis, LocationError> {
+	let materialized_path = materialized_path_for_sub_path(library, location_id, sub_path).await?;
+
+	let mut items = children_by_size(library, location_id, &materialized_path).await?;
+	let total_size = items.iter().map(|item| item.size).sum();
+	items.truncate(take);
+
+	add_largest_children(
+		library,
+		location_id,
+		&mut items,
+		depth.saturating_sub(1),
+		take,
+	)
+	.await?;
+
+	Ok(SpaceAnalysis { total_size, items })
+}
+
+/// Items of a directory too small to be told apart in a treemap, summed up
+#[derive(Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TreemapRest {
+	pub count: u32,
+	#[specta(type = String)]
+	pub size: u64,
+}
+
+/// A node of the treemap of a location, the directories among them only having children up to the
+/// requested depth
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TreemapNode {
+	pub name: String,
+	/// Relative to the root of the location, without a leading or trailing slash
+	pub path: String,
+	pub is_dir: bool,
+	#[specta(type = String)]
+	pub size: u64,
+	/// Heaviest first
+	pub children: Vec<TreemapNode>,
+	/// Children smaller than the minimum size, not in `children`
+	pub rest: TreemapRest,
+}
+
+impl From<SpaceItem> for TreemapNode {
+	fn from(item: SpaceItem) -> Self {
+		Self {
+			name: item.name,
+			path: item.path,
+			is_dir: item.is_dir,
+			size: item.size,
+			children: vec![],
+			rest: TreemapRest::default(),
+		}
+	}
+}
+
+/// Splits the contents of a directory into the ones big enough to be shown and the rest
+fn split_treemap_children(
+	items: Vec<SpaceItem>,
+	min_size: u64,
+	max_children: usize,
+) -> (Vec<TreemapNode>, TreemapRest) {
+	let mut children = vec![];
+	let mut rest = TreemapRest::default();
+
+	// Items are sorted heaviest first
+	for item in items {
+		if item.size >= min_size && children.len() < max_children {
+			children.push(item.into());
+		} else {
+			rest.count += 1;
+			rest.size += item.size;
+		}
+	}
+
+	(children, rest)
+}
+
+fn add_treemap_children<'a>(
+	library: &'a Library,
+	location_id: location::id::Type,
+	nodes: &'a mut [TreemapNode],
+	depth: u32,
+	min_size: u64,
+	max_children: usize,
+) -> BoxFuture<'a, Result<(), LocationError>> {
+	async move {
+		if depth == 0 {
+			return Ok(());
+		}
+
+		for node in nodes.iter_mut().filter(|node| node.is_dir && node.size > 0) {
+			let (mut children, rest) = split_treemap_children(
+				children_by_size(library, location_id, &format!("/{}/", node.path)).await?,
+				min_size,
+				max_children,
+			);
+
+			add_treemap_children(
+				library,
+				location_id,
+				&mut children,
+				depth - 1,
+				min_size,
+				max_children,
+			)
+			.await?;
+
+			node.children = children;
+			node.rest = rest;
+		}
+
+		Ok(())
+	}
+	.boxed()
+}
+
+/// Sizes of the contents of a directory of the location, or of its root, aggregated `depth` levels
+/// deep. Items smaller than `min_size_ratio` of the whole directory, or past the `max_children`
+/// heaviest of their directory, are only summed up, so only what can be drawn is sent.
+pub async fn treemap(
+	library: &Library,
+	location_id: location::id::Type,
+	sub_path: Option<&str>,
+	depth: u32,
+	min_size_ratio: f64,
+	max_children: usize,
+) -> Result<TreemapNode, LocationError> {
+	let materialized_path = materialized_path_for_sub_path(library, location_id, sub_path).await?;
+
+	let items = children_by_size(library, location_id, &materialized_path).await?;
+	let size = items.iter().map(|item| item.size).sum::<u64>();
+	let min_size = (size as f64 * min_size_ratio.clamp(0.0, 1.0)) as u64;
+
+	let (mut children, rest) = split_treemap_children(items, min_size, max_children);
+
+	add_treemap_children(
+		library,
+		location_id,
+		&mut children,
+		depth.saturating_sub(1),
+		min_size,
+		max_children,
+	)
+	.await?;
+
+	let path = materialized_path.trim_matches('/').to_string();
+
+	Ok(TreemapNode {
+		name: path.rsplit('/').next().unwrap_or_default().to_string(),
+		path,
+		is_dir: true,
+		size,
+		children,
+		rest,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn item(name: &str, size: u64) -> SpaceItem {
+		SpaceItem {
+			id: 0,
+			name: name.to_string(),
+			path: name.to_string(),
+			is_dir: false,
+			size,
+			children: vec![],
+		}
+	}
+
+	#[test]
+	fn small_treemap_children_are_summed_up() {
+		let (children, rest) = split_treemap_children(
+			vec![item("a", 500), item("b", 300), item("c", 20), item("d", 10)],
+			100,
+			10,
+		);
+
+		assert_eq!(
+			children
+				.iter()
+				.map(|node| node.name.as_str())
+				.collect::<Vec<_>>(),
+			vec!["a", "b"]
+		);
+		assert_eq!((rest.count, rest.size), (2, 30));
+
+		let (children, rest) = split_treemap_children(vec![item("a", 500), item("b", 300)], 0, 1);
+
+		assert_eq!(children.len(), 1);
+		assert_eq!((rest.count, rest.size), (1, 300));
+	}
+}
@@ -0,0 +1,247 @@
+use crate::{
+	job::JobError,
+	library::Library,
+	location::{
+		file_path_helper::{
+			ensure_sub_path_is_directory, ensure_sub_path_is_in_location, FilePathError,
+			IsolatedFilePathData,
+		},
+		find_location, light_scan_location, location_with_indexer_rules,
+	},
+	prisma::{file_path, location, object},
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::path::{Component, Path, PathBuf};
+
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs::{self, OpenOptions},
+	io::{self, AsyncRead, AsyncWriteExt},
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+/// A file written into a location by [`upload_file`]
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct UploadedFile {
+	/// Name the file was written with, which differs from the one asked for if it was taken
+	pub name: String,
+	/// `None` when the indexer rules of the location exclude the file
+	pub file_path_id: Option<file_path::id::Type>,
+	pub object_id: Option<object::id::Type>,
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+	#[error("library not found <id='{0}'>")]
+	LibraryNotFound(Uuid),
+	#[error("location not found <id='{0}'>")]
+	LocationNotFound(location::id::Type),
+	#[error("location <id='{0}'> is owned by another node")]
+	RemoteLocation(location::id::Type),
+	#[error("invalid file name '{0}'")]
+	InvalidName(String),
+	#[error("directory isn't in the location <path='{}'>", .0.display())]
+	OutsideLocation(Box<Path>),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to index the uploaded file: {0}")]
+	Index(#[from] JobError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+/// Writes a file into a directory of a location of this node, then indexes and identifies it
+/// right away, so it can be used as soon as the upload is over. The name gets a ` (n)` suffix if
+/// a file already has it, as uploads never overwrite files.
+pub async fn upload_file(
+	library: &Library,
+	location_id: location::id::Type,
+	sub_path: impl AsRef<Path>,
+	name: &str,
+	mut contents: impl AsyncRead + Unpin,
+) -> Result<UploadedFile, UploadError> {
+	if name.is_empty()
+		|| name == "."
+		|| name == ".."
+		|| name.contains(['/', '\\'])
+		|| Path::new(name).file_name().is_none()
+	{
+		return Err(UploadError::InvalidName(name.to_string()));
+	}
+
+	let location = find_location(library, location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?
+		.ok_or(UploadError::LocationNotFound(location_id))?;
+
+	if location.node_id != Some(library.node_local_id) {
+		return Err(UploadError::RemoteLocation(location_id));
+	}
+
+	let location_path = PathBuf::from(maybe_missing(&location.path, "location.path")?);
+	let dir = ensure_sub_path_is_in_location(&location_path, sub_path).await?;
+	ensure_inside_location(&location_path, &dir).await?;
+	ensure_sub_path_is_directory(&location_path, &dir).await?;
+
+	let (path, mut file) = create_new_file(&dir, name).await?;
+
+	debug!("Receiving upload into '{}'", path.display());
+
+	let written = async {
+		io::copy(&mut contents, &mut file).await?;
+		file.flush().await
+	}
+	.await;
+
+	if let Err(e) = written {
+		drop(file);
+		if let Err(e) = fs::remove_file(&path).await {
+			error!(
+				"Failed to remove incomplete upload '{}': {e:#?}",
+				path.display()
+			);
+		}
+
+		return Err(FileIOError::from((&path, e)).into());
+	}
+	drop(file);
+
+	// Same as a quick rescan of the directory, which identifies and thumbnails the file inline
+	light_scan_location(
+		library.clone(),
+		location,
+		if dir == location_path {
+			PathBuf::new()
+		} else {
+			dir.clone()
+		},
+	)
+	.await?;
+
+	let iso_file_path = IsolatedFilePathData::new(location_id, &location_path, &path, false)?;
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_materialized_path_name_extension(
+			location_id,
+			iso_file_path.materialized_path.to_string(),
+			iso_file_path.name.to_string(),
+			iso_file_path.extension.to_string(),
+		))
+		.select(file_path::select!({ id object_id }))
+		.exec()
+		.await?;
+
+	Ok(UploadedFile {
+		name: path
+			.file_name()
+			.map(|name| name.to_string_lossy().to_string())
+			.unwrap_or_default(),
+		file_path_id: file_path.as_ref().map(|file_path| file_path.id),
+		object_id: file_path.and_then(|file_path| file_path.object_id),
+	})
+}
+
+/// Makes sure uploads can't be written outside of the location, as sub paths come from clients
+/// and can hold `..` components or go through symlinks
+async fn ensure_inside_location(location_path: &Path, dir: &Path) -> Result<(), UploadError> {
+	let escapes = dir.strip_prefix(location_path).map_or(true, |relative| {
+		relative
+			.components()
+			.any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+	});
+	if escapes {
+		return Err(UploadError::OutsideLocation(dir.into()));
+	}
+
+	let canonical_location_path = fs::canonicalize(location_path)
+		.await
+		.map_err(|e| FileIOError::from((location_path, e)))?;
+	let canonical_dir = fs::canonicalize(dir)
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?;
+	if !canonical_dir.starts_with(canonical_location_path) {
+		return Err(UploadError::OutsideLocation(dir.into()));
+	}
+
+	Ok(())
+}
+
+/// Creates the file, atomically, with the first name of `name`, `name (1)`, `name (2)`... which
+/// isn't taken
+async fn create_new_file(dir: &Path, name: &str) -> Result<(PathBuf, fs::File), FileIOError> {
+	let name = Path::new(name);
+	let stem = name
+		.file_stem()
+		.map(|stem| stem.to_string_lossy().to_string())
+		.unwrap_or_default();
+	let extension = name
+		.extension()
+		.map(|extension| format!(".{}", extension.to_string_lossy()))
+		.unwrap_or_default();
+
+	let mut attempt = 0;
+	loop {
+		let path = if attempt == 0 {
+			dir.join(name)
+		} else {
+			dir.join(format!("{stem} ({attempt}){extension}"))
+		};
+
+		match OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&path)
+			.await
+		{
+			Ok(file) => return Ok((path, file)),
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+			Err(e) => return Err(FileIOError::from((&path, e))),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn rejects_directories_outside_of_the_location() {
+		let root = tempfile::tempdir().unwrap();
+		let location_path = root.path().join("location");
+		std::fs::create_dir_all(location_path.join("photos")).unwrap();
+
+		let dir = ensure_sub_path_is_in_location(&location_path, "photos")
+			.await
+			.unwrap();
+		assert!(ensure_inside_location(&location_path, &dir).await.is_ok());
+
+		let dir = ensure_sub_path_is_in_location(&location_path, "../..")
+			.await
+			.unwrap();
+		assert!(matches!(
+			ensure_inside_location(&location_path, &dir).await,
+			Err(UploadError::OutsideLocation(_))
+		));
+
+		let dir = location_path.join("photos/../..");
+		assert!(matches!(
+			ensure_inside_location(&location_path, &dir).await,
+			Err(UploadError::OutsideLocation(_))
+		));
+	}
+}
@@ -0,0 +1,548 @@
+//! Read-only FUSE filesystem over the virtual hierarchy of a library, so other apps can open the
+//! files as Spacedrive organizes them:
+//! - `Locations/{location}/...` with the indexed files of every location, including the ones of
+//!   other nodes, whose contents are fetched from them as they're read
+//! - `Tags/{tag}/...` with the files of the objects having each tag
+//!
+//! Only FUSE is supported for now, so mounting isn't available on Windows.
+
+use crate::{
+	library::Library,
+	location::{
+		file_path_helper::{file_path_to_stream, IsolatedFilePathData},
+		location_dir_name,
+	},
+	p2p::{RemoteFileRequest, MAX_REMOTE_FILE_CHUNK_LEN},
+	prisma::{file_path, location, object, tag, tag_on_object},
+	Node,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::OsStr,
+	fs::File,
+	os::unix::fs::FileExt,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use fuser::{
+	BackgroundSession, FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData,
+	ReplyDirectory, ReplyEntry, ReplyOpen, Request,
+};
+use prisma_client_rust::QueryError;
+use sd_p2p::PeerId;
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+/// How long the kernel can cache what it's told, as the library changes under the mount
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum MountError {
+	#[error("library not found <id='{0}'>")]
+	LibraryNotFound(Uuid),
+	#[error("failed to mount the library at '{}': {1}", .0.display())]
+	Mount(PathBuf, std::io::Error),
+}
+
+/// Mounts the library at `mountpoint` until the returned session is dropped. Must be called
+/// from within the tokio runtime the node runs on.
+pub async fn mount_library(
+	node: Arc<Node>,
+	library_id: Uuid,
+	mountpoint: impl AsRef<Path>,
+) -> Result<BackgroundSession, MountError> {
+	let mountpoint = mountpoint.as_ref();
+	let library = node
+		.library_manager
+		.get_library(library_id)
+		.await
+		.ok_or(MountError::LibraryNotFound(library_id))?;
+
+	let fs = LibraryMount {
+		node,
+		library,
+		runtime: Handle::current(),
+		entries: vec![Entry::Root],
+		inodes: HashMap::from([(Entry::Root, ROOT_INO)]),
+		open_files: HashMap::new(),
+		next_fh: 1,
+	};
+
+	fuser::spawn_mount2(
+		fs,
+		mountpoint,
+		&[
+			MountOption::RO,
+			MountOption::FSName("spacedrive".to_string()),
+			MountOption::DefaultPermissions,
+		],
+	)
+	.map_err(|e| MountError::Mount(mountpoint.to_path_buf(), e))
+}
+
+/// What an inode of the mount is, inodes being handed out as entries are first looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Entry {
+	Root,
+	Locations,
+	Tags,
+	Location(location::id::Type),
+	Tag(tag::id::Type),
+	FilePath(file_path::id::Type),
+}
+
+struct Child {
+	name: String,
+	entry: Entry,
+	is_dir: bool,
+	size: u64,
+	modified: SystemTime,
+}
+
+impl Child {
+	fn dir(name: String, entry: Entry) -> Self {
+		Self {
+			name,
+			entry,
+			is_dir: true,
+			size: 0,
+			modified: SystemTime::UNIX_EPOCH,
+		}
+	}
+
+	fn file_path(name: String, file_path: &file_path_to_stream::Data) -> Self {
+		Self {
+			name,
+			entry: Entry::FilePath(file_path.id),
+			is_dir: file_path.is_dir.unwrap_or_default(),
+			size: file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.and_then(|bytes| bytes.try_into().ok())
+				.map_or(0, u64::from_be_bytes),
+			modified: file_path
+				.date_modified
+				.map_or(SystemTime::UNIX_EPOCH, SystemTime::from),
+		}
+	}
+}
+
+/// Where the contents of an open file are read from
+enum Source {
+	Local(File),
+	Remote {
+		peer_id: PeerId,
+		file_path_pub_id: Uuid,
+	},
+}
+
+struct LibraryMount {
+	node: Arc<Node>,
+	library: Library,
+	runtime: Handle,
+	/// Entry of each inode, the inode being its index plus one
+	entries: Vec<Entry>,
+	inodes: HashMap<Entry, u64>,
+	open_files: HashMap<u64, Source>,
+	next_fh: u64,
+}
+
+impl LibraryMount {
+	fn ino(&mut self, entry: Entry) -> u64 {
+		if let Some(ino) = self.inodes.get(&entry) {
+			return *ino;
+		}
+
+		self.entries.push(entry);
+		let ino = self.entries.len() as u64;
+		self.inodes.insert(entry, ino);
+
+		ino
+	}
+
+	fn entry(&self, ino: u64) -> Option<Entry> {
+		self.entries.get(ino.checked_sub(1)? as usize).copied()
+	}
+
+	fn attr(&mut self, child: &Child) -> FileAttr {
+		FileAttr {
+			ino: self.ino(child.entry),
+			size: child.size,
+			blocks: (child.size + 511) / 512,
+			atime: child.modified,
+			mtime: child.modified,
+			ctime: child.modified,
+			crtime: child.modified,
+			kind: if child.is_dir {
+				FileType::Directory
+			} else {
+				FileType::RegularFile
+			},
+			perm: if child.is_dir { 0o555 } else { 0o444 },
+			nlink: if child.is_dir { 2 } else { 1 },
+			// SAFETY: these calls can't fail
+			uid: unsafe { libc::getuid() },
+			gid: unsafe { libc::getgid() },
+			rdev: 0,
+			blksize: 512,
+			flags: 0,
+		}
+	}
+
+	async fn file_path(
+		&self,
+		id: file_path::id::Type,
+	) -> Result<Option<file_path_to_stream::Data>, QueryError> {
+		self.library
+			.db
+			.file_path()
+			.find_first(vec![
+				file_path::id::equals(id),
+				file_path::date_deleted::equals(None),
+				file_path::date_quarantined::equals(None),
+			])
+			.select(file_path_to_stream::select())
+			.exec()
+			.await
+	}
+
+	/// The entry itself, as its parent would list it
+	async fn describe(&self, entry: Entry) -> Result<Option<Child>, QueryError> {
+		Ok(match entry {
+			Entry::FilePath(id) => self.file_path(id).await?.map(|file_path| {
+				let name = file_name(&file_path);
+				Child::file_path(name, &file_path)
+			}),
+			_ => Some(Child::dir(String::new(), entry)),
+		})
+	}
+
+	async fn children(&self, entry: Entry) -> Result<Vec<Child>, QueryError> {
+		let db = &self.library.db;
+
+		Ok(match entry {
+			Entry::Root => vec![
+				Child::dir("Locations".to_string(), Entry::Locations),
+				Child::dir("Tags".to_string(), Entry::Tags),
+			],
+			Entry::Locations => {
+				let mut names = HashSet::new();
+				db.location()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|location| {
+						Child::dir(
+							location_dir_name(&mut names, location.id, location.name.as_deref()),
+							Entry::Location(location.id),
+						)
+					})
+					.collect()
+			}
+			Entry::Tags => {
+				let mut names = HashSet::new();
+				db.tag()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|tag| {
+						let name = tag
+							.name
+							.filter(|name| !name.is_empty() && !name.contains('/'))
+							.unwrap_or_else(|| tag.id.to_string());
+
+						Child::dir(unique_name(&mut names, tag.id, &name), Entry::Tag(tag.id))
+					})
+					.collect()
+			}
+			Entry::Location(location_id) => {
+				self.file_path_children(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::equals(Some("/".to_string())),
+				])
+				.await?
+			}
+			Entry::Tag(tag_id) => {
+				self.file_path_children(vec![
+					file_path::is_dir::equals(Some(false)),
+					file_path::object::is(vec![object::tags::some(vec![
+						tag_on_object::tag_id::equals(tag_id),
+					])]),
+				])
+				.await?
+			}
+			Entry::FilePath(id) => match self.file_path(id).await? {
+				Some(file_path) if file_path.is_dir == Some(true) => {
+					let materialized_path = format!(
+						"{}{}/",
+						file_path.materialized_path.unwrap_or_default(),
+						file_path.name.unwrap_or_default()
+					);
+
+					self.file_path_children(vec![
+						file_path::location_id::equals(
+							file_path.location.as_ref().map(|location| location.id),
+						),
+						file_path::materialized_path::equals(Some(materialized_path)),
+					])
+					.await?
+				}
+				_ => vec![],
+			},
+		})
+	}
+
+	async fn file_path_children(
+		&self,
+		filters: Vec<file_path::WhereParam>,
+	) -> Result<Vec<Child>, QueryError> {
+		let mut names = HashSet::new();
+
+		Ok(self
+			.library
+			.db
+			.file_path()
+			.find_many(
+				filters
+					.into_iter()
+					.chain([
+						file_path::date_deleted::equals(None),
+						file_path::date_quarantined::equals(None),
+					])
+					.collect(),
+			)
+			.select(file_path_to_stream::select())
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| {
+				let name = unique_name(&mut names, file_path.id, &file_name(&file_path));
+				Child::file_path(name, &file_path)
+			})
+			.collect())
+	}
+
+	/// Reads the file from its location if it's reachable from this node, or else from the node
+	/// owning the location
+	async fn open_source(&self, id: file_path::id::Type) -> Option<Source> {
+		let file_path = self.file_path(id).await.ok()??;
+		let location = file_path.location.as_ref()?;
+
+		if let Some(location_path) = &location.path {
+			if let Ok(iso_file_path) = IsolatedFilePathData::try_from((location.id, &file_path)) {
+				if let Ok(file) = File::open(Path::new(location_path).join(iso_file_path)) {
+					return Some(Source::Local(file));
+				}
+			}
+		}
+
+		Some(Source::Remote {
+			peer_id: location
+				.node
+				.as_ref()
+				.and_then(|node| node.node_peer_id.as_deref())
+				.and_then(|peer_id| PeerId::from_str(peer_id).ok())?,
+			file_path_pub_id: Uuid::from_slice(&file_path.pub_id).ok()?,
+		})
+	}
+}
+
+impl Filesystem for LibraryMount {
+	fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+		let Some(parent) = self.entry(parent) else {
+			return reply.error(libc::ENOENT);
+		};
+
+		match self.runtime.clone().block_on(self.children(parent)) {
+			Ok(children) => match children.iter().find(|child| name == child.name.as_str()) {
+				Some(child) => reply.entry(&TTL, &self.attr(child), 0),
+				None => reply.error(libc::ENOENT),
+			},
+			Err(e) => {
+				error!(
+					"Failed to look up '{}' in the mount: {e:#?}",
+					name.to_string_lossy()
+				);
+				reply.error(libc::EIO)
+			}
+		}
+	}
+
+	fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+		let Some(entry) = self.entry(ino) else {
+			return reply.error(libc::ENOENT);
+		};
+
+		match self.runtime.clone().block_on(self.describe(entry)) {
+			Ok(Some(child)) => reply.attr(&TTL, &self.attr(&child)),
+			Ok(None) => reply.error(libc::ENOENT),
+			Err(e) => {
+				error!("Failed to get the attributes of inode {ino} in the mount: {e:#?}");
+				reply.error(libc::EIO)
+			}
+		}
+	}
+
+	fn readdir(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		offset: i64,
+		mut reply: ReplyDirectory,
+	) {
+		let Some(entry) = self.entry(ino) else {
+			return reply.error(libc::ENOENT);
+		};
+
+		let children = match self.runtime.clone().block_on(self.children(entry)) {
+			Ok(children) => children,
+			Err(e) => {
+				error!("Failed to list inode {ino} in the mount: {e:#?}");
+				return reply.error(libc::EIO);
+			}
+		};
+
+		let entries = [
+			(ino, FileType::Directory, ".".to_string()),
+			(ino, FileType::Directory, "..".to_string()),
+		]
+		.into_iter()
+		.chain(children.into_iter().map(|child| {
+			(
+				self.ino(child.entry),
+				if child.is_dir {
+					FileType::Directory
+				} else {
+					FileType::RegularFile
+				},
+				child.name,
+			)
+		}))
+		.collect::<Vec<_>>();
+
+		for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+			// The offset is the one of the next entry, to resume from once the buffer is full
+			if reply.add(ino, (i + 1) as i64, kind, name) {
+				break;
+			}
+		}
+
+		reply.ok()
+	}
+
+	fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+		let Some(Entry::FilePath(id)) = self.entry(ino) else {
+			return reply.error(libc::EISDIR);
+		};
+
+		match self.runtime.clone().block_on(self.open_source(id)) {
+			Some(source) => {
+				let fh = self.next_fh;
+				self.next_fh += 1;
+				self.open_files.insert(fh, source);
+
+				reply.opened(fh, 0)
+			}
+			None => reply.error(libc::ENOENT),
+		}
+	}
+
+	fn read(
+		&mut self,
+		_req: &Request<'_>,
+		_ino: u64,
+		fh: u64,
+		offset: i64,
+		size: u32,
+		_flags: i32,
+		_lock_owner: Option<u64>,
+		reply: ReplyData,
+	) {
+		let Some(source) = self.open_files.get(&fh) else {
+			return reply.error(libc::EBADF);
+		};
+
+		match source {
+			Source::Local(file) => {
+				let mut buf = vec![0; size as usize];
+				match file.read_at(&mut buf, offset as u64) {
+					Ok(read) => reply.data(&buf[..read]),
+					Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+				}
+			}
+			Source::Remote {
+				peer_id,
+				file_path_pub_id,
+			} => {
+				debug!("Reading {size} bytes of '{file_path_pub_id}' from peer '{peer_id}'");
+
+				let request = RemoteFileRequest {
+					file_path_pub_id: *file_path_pub_id,
+					start: offset as u64,
+					length: (size as u64).min(MAX_REMOTE_FILE_CHUNK_LEN),
+				};
+
+				match self.runtime.block_on(self.node.p2p.request_file_chunk(
+					&self.library,
+					*peer_id,
+					request,
+				)) {
+					Ok((_, _, data)) => reply.data(&data),
+					Err(e) => {
+						error!("Failed to read '{file_path_pub_id}' from peer '{peer_id}': {e}");
+						reply.error(libc::EIO)
+					}
+				}
+			}
+		}
+	}
+
+	fn release(
+		&mut self,
+		_req: &Request<'_>,
+		_ino: u64,
+		fh: u64,
+		_flags: i32,
+		_lock_owner: Option<u64>,
+		_flush: bool,
+		reply: fuser::ReplyEmpty,
+	) {
+		self.open_files.remove(&fh);
+		reply.ok()
+	}
+}
+
+fn file_name(file_path: &file_path_to_stream::Data) -> String {
+	let name = file_path.name.as_deref().unwrap_or_default();
+
+	match file_path.extension.as_deref() {
+		Some(extension) if !extension.is_empty() => format!("{name}.{extension}"),
+		_ => name.to_string(),
+	}
+}
+
+/// Files of different directories can end up in the same one, like in a tag, so names taken
+/// already get the id of the entry appended
+fn unique_name(taken: &mut HashSet<String>, id: i32, name: &str) -> String {
+	if taken.insert(name.to_string()) {
+		return name.to_string();
+	}
+
+	let name = match name.rsplit_once('.') {
+		Some((stem, extension)) if !stem.is_empty() => format!("{stem} ({id}).{extension}"),
+		_ => format!("{name} ({id})"),
+	};
+	taken.insert(name.clone());
+
+	name
+}
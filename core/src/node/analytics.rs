@@ -0,0 +1,188 @@
+//! Counts how much the features of the app are used and how long jobs take, on this node only.
+//! Nothing is collected unless it's enabled in the config of the node, and nothing is ever sent
+//! anywhere, the counters are only read through the API, to be shown or exported by the user.
+
+use crate::{job::JobStatus, util::error::FileIOError};
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, Weak,
+	},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, time::interval};
+use tracing::{error, warn};
+
+/// Name of the file the counters are kept in, in the data directory of the node
+const ANALYTICS_FILE_NAME: &str = "analytics.json";
+
+/// How often the counters are written to disk, when they changed
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsSettings {
+	/// Whether usage is counted at all
+	pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsData {
+	/// When the counters were started, or last reset
+	pub since: DateTime<Utc>,
+	/// Number of uses of each feature, by name, like `explorer.quickPreview`
+	pub features: BTreeMap<String, u64>,
+	/// Runs of each job, by name
+	pub jobs: BTreeMap<String, JobStats>,
+}
+
+impl Default for AnalyticsData {
+	fn default() -> Self {
+		Self {
+			since: Utc::now(),
+			features: BTreeMap::new(),
+			jobs: BTreeMap::new(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStats {
+	pub runs: u64,
+	pub failed: u64,
+	/// Time spent running the jobs, including the time they were paused
+	pub total_duration_ms: u64,
+	pub max_duration_ms: u64,
+}
+
+pub struct Analytics {
+	path: PathBuf,
+	enabled: AtomicBool,
+	data: Mutex<AnalyticsData>,
+	/// Whether the counters changed since they were last saved
+	dirty: AtomicBool,
+}
+
+impl Analytics {
+	/// Loads the counters saved in the data directory of the node, and saves them periodically
+	pub(crate) async fn new(data_dir: &Path, settings: AnalyticsSettings) -> Arc<Self> {
+		let path = data_dir.join(ANALYTICS_FILE_NAME);
+
+		let data = match fs::read(&path).await {
+			Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+				warn!(
+					"Failed to read the analytics in '{}', starting over: {e}",
+					path.display()
+				);
+				AnalyticsData::default()
+			}),
+			Err(_) => AnalyticsData::default(),
+		};
+
+		let this = Arc::new(Self {
+			path,
+			enabled: AtomicBool::new(settings.enabled),
+			data: Mutex::new(data),
+			dirty: AtomicBool::new(false),
+		});
+
+		tokio::spawn(Self::save_periodically(Arc::downgrade(&this)));
+
+		this
+	}
+
+	async fn save_periodically(this: Weak<Self>) {
+		let mut interval = interval(SAVE_INTERVAL);
+		loop {
+			interval.tick().await;
+
+			let Some(this) = this.upgrade() else {
+				break;
+			};
+
+			if let Err(e) = this.save().await {
+				error!("Failed to save analytics: {e:#?}");
+			}
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled.load(Ordering::Relaxed)
+	}
+
+	pub(crate) fn set_enabled(&self, enabled: bool) {
+		self.enabled.store(enabled, Ordering::Relaxed);
+	}
+
+	fn update(&self, f: impl FnOnce(&mut AnalyticsData)) {
+		if !self.is_enabled() {
+			return;
+		}
+
+		f(&mut self.data.lock().unwrap_or_else(|e| e.into_inner()));
+		self.dirty.store(true, Ordering::Relaxed);
+	}
+
+	pub fn record_feature(&self, feature: &str) {
+		self.update(|data| *data.features.entry(feature.to_string()).or_default() += 1);
+	}
+
+	pub(crate) fn record_job(&self, name: &str, status: JobStatus, duration: Duration) {
+		let duration_ms = duration.as_millis().try_into().unwrap_or(u64::MAX);
+
+		self.update(|data| {
+			let stats = data.jobs.entry(name.to_string()).or_default();
+			stats.runs += 1;
+			if status == JobStatus::Failed {
+				stats.failed += 1;
+			}
+			stats.total_duration_ms = stats.total_duration_ms.saturating_add(duration_ms);
+			stats.max_duration_ms = stats.max_duration_ms.max(duration_ms);
+		});
+	}
+
+	pub fn data(&self) -> AnalyticsData {
+		self.data.lock().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+
+	/// Clears the counters, and the file they're saved in
+	pub(crate) async fn reset(&self) -> Result<(), FileIOError> {
+		*self.data.lock().unwrap_or_else(|e| e.into_inner()) = AnalyticsData::default();
+		self.dirty.store(false, Ordering::Relaxed);
+
+		match fs::remove_file(&self.path).await {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+				Err(FileIOError::from((&self.path, e)))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// Writes the counters to disk if they changed since they were last saved
+	pub(crate) async fn save(&self) -> Result<(), FileIOError> {
+		if !self.dirty.swap(false, Ordering::Relaxed) {
+			return Ok(());
+		}
+
+		self.write_to(&self.path).await
+	}
+
+	/// Writes the counters to a JSON file, to share them or look into them
+	pub(crate) async fn write_to(&self, path: &Path) -> Result<(), FileIOError> {
+		let bytes = serde_json::to_vec_pretty(&self.data())
+			.expect("analytics are always serializable to JSON");
+
+		fs::write(path, bytes)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))
+	}
+}
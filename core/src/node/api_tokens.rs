@@ -0,0 +1,242 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Requests per minute allowed to tokens issued without a rate limit of their own
+pub const DEFAULT_API_RATE_LIMIT: u32 = 600;
+
+/// Mutations allowed with the `files:write` scope. Entries ending with a dot cover every
+/// procedure of their router.
+const FILES_WRITE_PROCEDURES: &[&str] = &["files.", "batch.", "tags.assign"];
+/// Mutations allowed with the `jobs:run` scope
+const JOBS_RUN_PROCEDURES: &[&str] = &[
+	"jobs.",
+	"scheduledJobs.runNow",
+	"locations.fullRescan",
+	"locations.quickRescan",
+];
+
+/// What a client authenticated with an API token is allowed to do
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiTokenScope {
+	/// Run queries and subscriptions
+	Read,
+	/// Run any mutation, which includes the ones of the narrower scopes below
+	Write,
+	/// Change, move and delete files, and their notes, favorites, ratings and tags
+	#[serde(rename = "files:write")]
+	FilesWrite,
+	/// Run, pause and cancel jobs, including rescans of locations
+	#[serde(rename = "jobs:run")]
+	JobsRun,
+}
+
+impl ApiTokenScope {
+	pub const ALL: [Self; 4] = [Self::Read, Self::Write, Self::FilesWrite, Self::JobsRun];
+
+	/// Scope needed to run a procedure of the core API, like `files.deleteFiles`
+	pub fn required_for(procedure: &str, is_mutation: bool) -> Self {
+		let matches = |procedures: &[&str]| {
+			procedures.iter().any(|p| match p.strip_suffix('.') {
+				Some(router) => procedure
+					.strip_prefix(router)
+					.map_or(false, |rest| rest.starts_with('.')),
+				None => procedure == *p,
+			})
+		};
+
+		if !is_mutation {
+			Self::Read
+		} else if matches(FILES_WRITE_PROCEDURES) {
+			Self::FilesWrite
+		} else if matches(JOBS_RUN_PROCEDURES) {
+			Self::JobsRun
+		} else {
+			Self::Write
+		}
+	}
+
+	pub fn grants(self, required: Self) -> bool {
+		self == required
+			|| (self == Self::Write && matches!(required, Self::FilesWrite | Self::JobsRun))
+	}
+}
+
+/// Token issued to a frontend or script connecting to this node when it runs as a daemon.
+/// Only the hash of its secret is kept, the secret itself is shown once when issued.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+	pub id: Uuid,
+	pub name: String,
+	pub scopes: Vec<ApiTokenScope>,
+	/// Requests per minute, [`DEFAULT_API_RATE_LIMIT`] if not set
+	#[serde(default)]
+	pub rate_limit: Option<u32>,
+	pub date_created: DateTime<Utc>,
+	secret_hash: String,
+}
+
+// A version of [ApiToken] without the hash of its secret, to list tokens in the frontend
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct SanitisedApiToken {
+	pub id: Uuid,
+	pub name: String,
+	pub scopes: Vec<ApiTokenScope>,
+	pub rate_limit: Option<u32>,
+	pub date_created: DateTime<Utc>,
+}
+
+impl From<ApiToken> for SanitisedApiToken {
+	fn from(value: ApiToken) -> Self {
+		Self {
+			id: value.id,
+			name: value.name,
+			scopes: value.scopes,
+			rate_limit: value.rate_limit,
+			date_created: value.date_created,
+		}
+	}
+}
+
+impl ApiToken {
+	/// Creates a token along with the secret clients authenticate with
+	pub fn issue(
+		name: String,
+		scopes: Vec<ApiTokenScope>,
+		rate_limit: Option<u32>,
+	) -> (Self, String) {
+		let secret = format!("sd_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+		(
+			Self {
+				id: Uuid::new_v4(),
+				name,
+				scopes,
+				rate_limit,
+				date_created: Utc::now(),
+				secret_hash: hash_secret(&secret),
+			},
+			secret,
+		)
+	}
+
+	// Hashes are compared instead of secrets, so the time taken doesn't tell how much of the
+	// secret matched
+	pub fn matches(&self, secret: &str) -> bool {
+		self.secret_hash == hash_secret(secret)
+	}
+
+	pub fn has_scope(&self, scope: ApiTokenScope) -> bool {
+		self.scopes.iter().any(|granted| granted.grants(scope))
+	}
+
+	/// Whether the token allows running a procedure of the core API
+	pub fn allows(&self, procedure: &str, is_mutation: bool) -> bool {
+		self.has_scope(ApiTokenScope::required_for(procedure, is_mutation))
+	}
+}
+
+fn hash_secret(secret: &str) -> String {
+	hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[derive(Error, Debug)]
+pub enum ApiAuthError {
+	#[error("missing or invalid API token")]
+	InvalidToken,
+	#[error("too many requests with this API token, retry in {}s", .retry_after.as_secs().max(1))]
+	RateLimited { retry_after: Duration },
+}
+
+/// Counts the requests of each token, allowing bursts of up to its rate limit which then
+/// refill evenly over a minute
+#[derive(Default)]
+pub(crate) struct ApiRateLimiter {
+	buckets: Mutex<HashMap<Uuid, (f64, Instant)>>,
+}
+
+impl ApiRateLimiter {
+	pub(crate) fn check(&self, token: &ApiToken) -> Result<(), ApiAuthError> {
+		let capacity = token.rate_limit.unwrap_or(DEFAULT_API_RATE_LIMIT).max(1) as f64;
+		let refill_per_sec = capacity / 60.0;
+		let now = Instant::now();
+
+		let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+		let (available, last_refill) = buckets.entry(token.id).or_insert((capacity, now));
+
+		*available = (*available + now.duration_since(*last_refill).as_secs_f64() * refill_per_sec)
+			.min(capacity);
+		*last_refill = now;
+
+		if *available < 1.0 {
+			return Err(ApiAuthError::RateLimited {
+				retry_after: Duration::from_secs_f64((1.0 - *available) / refill_per_sec),
+			});
+		}
+
+		*available -= 1.0;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_issued_token_matches_its_secret_only() {
+		let (token, secret) = ApiToken::issue("cli".to_string(), vec![ApiTokenScope::Read], None);
+
+		assert!(token.matches(&secret));
+		assert!(!token.matches(&secret[1..]));
+		assert!(token.has_scope(ApiTokenScope::Read));
+		assert!(!token.has_scope(ApiTokenScope::Write));
+	}
+
+	#[test]
+	fn test_scopes_of_procedures() {
+		let (dashboard, _) = ApiToken::issue(
+			"dashboard".to_string(),
+			vec![ApiTokenScope::Read, ApiTokenScope::JobsRun],
+			None,
+		);
+
+		assert!(dashboard.allows("search.paths", false));
+		assert!(dashboard.allows("jobs.pause", true));
+		assert!(dashboard.allows("locations.fullRescan", true));
+		assert!(!dashboard.allows("files.deleteFiles", true));
+		assert!(!dashboard.allows("batch.apply", true));
+		assert!(!dashboard.allows("locations.delete", true));
+		// Only whole router names are matched
+		assert!(!dashboard.allows("jobsExtra.run", true));
+
+		let (admin, _) = ApiToken::issue("admin".to_string(), vec![ApiTokenScope::Write], None);
+		assert!(admin.allows("files.deleteFiles", true));
+		assert!(admin.allows("jobs.cancel", true));
+		assert!(!admin.allows("search.paths", false));
+	}
+
+	#[test]
+	fn test_rate_limit() {
+		let (token, _) = ApiToken::issue("cli".to_string(), vec![ApiTokenScope::Read], Some(2));
+		let limiter = ApiRateLimiter::default();
+
+		assert!(limiter.check(&token).is_ok());
+		assert!(limiter.check(&token).is_ok());
+		assert!(matches!(
+			limiter.check(&token),
+			Err(ApiAuthError::RateLimited { .. })
+		));
+	}
+}
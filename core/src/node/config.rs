@@ -1,6 +1,5 @@
 use sd_p2p::Keypair;
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
 use specta::Type;
 use std::{
 	path::{Path, PathBuf},
@@ -9,7 +8,18 @@ use std::{
 use tokio::sync::{RwLock, RwLockWriteGuard};
 use uuid::Uuid;
 
-use crate::util::migrator::{Migrate, MigratorError};
+use crate::{
+	job::{retention::JobRetentionPolicy, throttle::JobThrottlePolicy, JobConcurrencyLimits},
+	location::soft_delete::SoftDeletePolicy,
+	node::{AnalyticsSettings, ApiToken, NotificationProviderConfig},
+	object::{
+		antivirus::AntivirusSettings, preview::ThumbnailerPreferences,
+		sensitive::SensitiveContentSettings,
+	},
+	p2p::{CapabilitiesConfig, RelayConfig, SpacedropSettings},
+	share::ShareRelayConfig,
+	util::migrator::{Migrate, Migration, MigratorError},
+};
 
 /// NODE_STATE_CONFIG_NAME is the name of the file which stores the NodeState
 pub const NODE_STATE_CONFIG_NAME: &str = "node_state.sdconfig";
@@ -29,6 +39,48 @@ pub struct NodeConfig {
 	// TODO: These will probs be replaced by your Spacedrive account in the near future.
 	pub p2p_email: Option<String>,
 	pub p2p_img_url: Option<String>,
+	/// How many jobs can run at the same time, by priority class and by job type
+	#[serde(default)]
+	pub job_concurrency_limits: JobConcurrencyLimits,
+	/// How long finished job reports are kept in each library's history
+	#[serde(default)]
+	pub job_retention: JobRetentionPolicy,
+	/// How jobs are paused or slowed down on battery, low power mode or thermal pressure
+	#[serde(default)]
+	pub job_throttle_policy: JobThrottlePolicy,
+	/// How long the file paths of files that disappeared are kept in each library
+	#[serde(default)]
+	pub soft_delete: SoftDeletePolicy,
+	/// Which kinds of thumbnails are generated, and how
+	#[serde(default)]
+	pub thumbnailer: ThumbnailerPreferences,
+	/// Tokens issued to the clients of this node when it runs as a daemon
+	#[serde(default)]
+	pub api_tokens: Vec<ApiToken>,
+	/// Where the notifications raised in the libraries are delivered, besides the app
+	#[serde(default)]
+	pub notification_providers: Vec<NotificationProviderConfig>,
+	/// Relay the share links of the node go through, they can't be opened without one
+	#[serde(default)]
+	pub share_relay: Option<ShareRelayConfig>,
+	/// Which peers can Spacedrop files to this node, and which are accepted without asking
+	#[serde(default)]
+	pub spacedrop: SpacedropSettings,
+	/// What the node advertises it can do for its peers, besides what's detected
+	#[serde(default)]
+	pub capabilities: CapabilitiesConfig,
+	/// Relays the node registers with to reach peers beyond the local network
+	#[serde(default)]
+	pub p2p_relays: Vec<RelayConfig>,
+	/// Whether the usage of the app is counted on this node, it's never sent anywhere
+	#[serde(default)]
+	pub analytics: AnalyticsSettings,
+	/// Scanner the files added to the locations where it's enabled are sent to
+	#[serde(default)]
+	pub antivirus: AntivirusSettings,
+	/// Whether images likely showing nudity are flagged, which is only done on this node
+	#[serde(default)]
+	pub sensitive_content: SensitiveContentSettings,
 }
 
 // A version of [NodeConfig] that is safe to share with the frontend
@@ -59,10 +111,10 @@ impl From<NodeConfig> for SanitisedNodeConfig {
 
 #[async_trait::async_trait]
 impl Migrate for NodeConfig {
-	const CURRENT_VERSION: u32 = 0;
-
 	type Ctx = ();
 
+	const MIGRATIONS: &'static [Migration<Self::Ctx>] = &[];
+
 	fn default(_path: PathBuf) -> Result<Self, MigratorError> {
 		Ok(Self {
 			id: Uuid::new_v4(),
@@ -78,19 +130,22 @@ impl Migrate for NodeConfig {
 			keypair: Keypair::generate(),
 			p2p_email: None,
 			p2p_img_url: None,
+			job_concurrency_limits: JobConcurrencyLimits::default(),
+			job_retention: JobRetentionPolicy::default(),
+			job_throttle_policy: JobThrottlePolicy::default(),
+			soft_delete: SoftDeletePolicy::default(),
+			thumbnailer: ThumbnailerPreferences::default(),
+			api_tokens: Vec::new(),
+			notification_providers: Vec::new(),
+			share_relay: None,
+			spacedrop: SpacedropSettings::default(),
+			capabilities: CapabilitiesConfig::default(),
+			p2p_relays: Vec::new(),
+			analytics: AnalyticsSettings::default(),
+			antivirus: AntivirusSettings::default(),
+			sensitive_content: SensitiveContentSettings::default(),
 		})
 	}
-
-	async fn migrate(
-		from_version: u32,
-		_config: &mut Map<String, Value>,
-		_ctx: &Self::Ctx,
-	) -> Result<(), MigratorError> {
-		match from_version {
-			0 => Ok(()),
-			v => unreachable!("Missing migration for library version {}", v),
-		}
-	}
 }
 
 impl Default for NodeConfig {
@@ -109,6 +164,20 @@ impl Default for NodeConfig {
 			keypair: Keypair::generate(),
 			p2p_email: None,
 			p2p_img_url: None,
+			job_concurrency_limits: JobConcurrencyLimits::default(),
+			job_retention: JobRetentionPolicy::default(),
+			job_throttle_policy: JobThrottlePolicy::default(),
+			soft_delete: SoftDeletePolicy::default(),
+			thumbnailer: ThumbnailerPreferences::default(),
+			api_tokens: Vec::new(),
+			notification_providers: Vec::new(),
+			share_relay: None,
+			spacedrop: SpacedropSettings::default(),
+			capabilities: CapabilitiesConfig::default(),
+			p2p_relays: Vec::new(),
+			analytics: AnalyticsSettings::default(),
+			antivirus: AntivirusSettings::default(),
+			sensitive_content: SensitiveContentSettings::default(),
 		}
 	}
 }
@@ -0,0 +1,76 @@
+use crate::{
+	job::{Job, JobResumer, StatefulJob},
+	location::provider::LocationProvider,
+};
+
+use std::{collections::HashMap, mem, sync::Arc};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExtensionError {
+	#[error("names of the jobs of extensions are to be namespaced, like 'ipfs.pin': '{0}'")]
+	JobNameNotNamespaced(&'static str),
+	#[error("a job named '{0}' is already registered")]
+	DuplicateJobName(&'static str),
+}
+
+/// What crates embedding the core add to it, registered when the node starts
+#[derive(Default)]
+pub struct NodeExtensions {
+	pub(crate) location_providers: Vec<Arc<dyn LocationProvider>>,
+	jobs: Vec<(&'static str, JobResumer)>,
+}
+
+impl NodeExtensions {
+	/// Adds a provider locations can be kept in, under its own scheme
+	pub fn location_provider(mut self, provider: impl LocationProvider) -> Self {
+		self.location_providers.push(Arc::new(provider));
+		self
+	}
+
+	/// Adds a job type, so jobs of this type that were paused or interrupted by the node
+	/// stopping are resumed, like the built-in ones. Its name is to be namespaced, like
+	/// `ipfs.pin`, so it can't be mistaken for a built-in job.
+	pub fn job<SJob: StatefulJob>(mut self) -> Self {
+		self.jobs.push((SJob::NAME, Job::<SJob>::new_from_report));
+		self
+	}
+
+	pub(crate) fn take_jobs(
+		&mut self,
+	) -> Result<HashMap<&'static str, JobResumer>, ExtensionError> {
+		let mut jobs = HashMap::new();
+
+		for (name, resume) in mem::take(&mut self.jobs) {
+			// Built-in jobs are named in snake case, without dots
+			if !name.contains('.') {
+				return Err(ExtensionError::JobNameNotNamespaced(name));
+			}
+
+			if jobs.insert(name, resume).is_some() {
+				return Err(ExtensionError::DuplicateJobName(name));
+			}
+		}
+
+		Ok(jobs)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::object::fs::delete::FileDeleterJobInit;
+
+	#[test]
+	fn rejects_jobs_named_like_built_in_ones() {
+		assert!(matches!(
+			NodeExtensions::default()
+				.job::<FileDeleterJobInit>()
+				.take_jobs(),
+			Err(ExtensionError::JobNameNotNamespaced("file_deleter"))
+		));
+		assert!(NodeExtensions::default().take_jobs().unwrap().is_empty());
+	}
+}
@@ -2,9 +2,17 @@ use crate::NodeError;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+mod analytics;
+mod api_tokens;
 mod config;
+mod extensions;
+mod notification_providers;
 
+pub use analytics::*;
+pub use api_tokens::*;
 pub use config::*;
+pub use extensions::*;
+pub use notification_providers::*;
 
 #[allow(clippy::upper_case_acronyms)]
 #[repr(u8)]
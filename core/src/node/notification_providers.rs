@@ -0,0 +1,540 @@
+//! Providers delivering the notifications raised in the libraries of the node outside of the app,
+//! so the admins of a headless node learn about failed jobs or integrity errors.
+
+use crate::library::{NotificationItem, NotificationSeverity};
+
+use std::time::Duration;
+
+use futures::future::join_all;
+use lettre::{
+	message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+	AsyncTransport, Message, Tokio1Executor,
+};
+use reqwest::{Client, Url};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_NTFY_SERVER: &str = "https://ntfy.sh";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SmtpSecurity {
+	/// TLS from the start of the connection, usually on port 465
+	Tls,
+	/// Plain connection upgraded to TLS, usually on port 587
+	#[default]
+	StartTls,
+	/// No encryption at all, only fit for relays on the same machine or network
+	None,
+}
+
+/// Where notifications are delivered. Secrets are optional so they can be left out when updating a
+/// provider, keeping the ones it already has.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum NotificationProvider {
+	/// Notifications of the operating system of the node
+	System,
+	Smtp {
+		host: String,
+		/// The default one of the chosen security if not set
+		#[serde(default)]
+		port: Option<u16>,
+		#[serde(default)]
+		security: SmtpSecurity,
+		#[serde(default)]
+		username: Option<String>,
+		#[serde(default)]
+		password: Option<String>,
+		from: String,
+		to: Vec<String>,
+	},
+	/// JSON payloads posted to any url
+	Webhook { url: String },
+	Ntfy {
+		/// The public ntfy.sh server if not set
+		#[serde(default)]
+		server: Option<String>,
+		topic: String,
+		#[serde(default)]
+		token: Option<String>,
+	},
+	Gotify {
+		server: String,
+		/// Token of the Gotify application the messages are sent as
+		#[serde(default)]
+		token: Option<String>,
+	},
+}
+
+impl NotificationProvider {
+	fn validate(&self) -> Result<(), NotificationProviderError> {
+		let check_url = |url: &str| match Url::parse(url) {
+			Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+			_ => Err(NotificationProviderError::InvalidUrl(url.to_string())),
+		};
+
+		match self {
+			Self::System => Ok(()),
+			Self::Smtp { from, to, .. } => {
+				from.parse::<lettre::Address>()?;
+				if to.is_empty() {
+					return Err(NotificationProviderError::NoRecipients);
+				}
+				to.iter()
+					.try_for_each(|to| to.parse::<lettre::Address>().map(|_| ()))?;
+				Ok(())
+			}
+			Self::Webhook { url } => check_url(url),
+			Self::Ntfy { server, .. } => {
+				check_url(server.as_deref().unwrap_or(DEFAULT_NTFY_SERVER))
+			}
+			Self::Gotify { server, .. } => check_url(server),
+		}
+	}
+
+	/// Keeps the secrets of `previous` that this provider left out, if it's the same kind
+	fn keep_secrets_of(&mut self, previous: &Self) {
+		match (self, previous) {
+			(
+				Self::Smtp { password, .. },
+				Self::Smtp {
+					password: previous, ..
+				},
+			)
+			| (
+				Self::Ntfy {
+					token: password, ..
+				},
+				Self::Ntfy {
+					token: previous, ..
+				},
+			)
+			| (
+				Self::Gotify {
+					token: password, ..
+				},
+				Self::Gotify {
+					token: previous, ..
+				},
+			) => {
+				if password.is_none() {
+					*password = previous.clone();
+				}
+			}
+			_ => {}
+		}
+	}
+
+	fn redacted(mut self) -> Self {
+		match &mut self {
+			Self::Smtp { password, .. } => *password = None,
+			Self::Ntfy { token, .. } | Self::Gotify { token, .. } => *token = None,
+			Self::System | Self::Webhook { .. } => {}
+		}
+		self
+	}
+
+	fn has_secret(&self) -> bool {
+		match self {
+			Self::Smtp { password, .. } => password.is_some(),
+			Self::Ntfy { token, .. } | Self::Gotify { token, .. } => token.is_some(),
+			Self::System | Self::Webhook { .. } => false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationProviderConfig {
+	pub id: Uuid,
+	pub name: String,
+	pub enabled: bool,
+	/// Notifications less severe than this aren't delivered by the provider
+	pub min_severity: NotificationSeverity,
+	pub provider: NotificationProvider,
+}
+
+impl NotificationProviderConfig {
+	pub fn new(
+		name: String,
+		min_severity: NotificationSeverity,
+		provider: NotificationProvider,
+	) -> Result<Self, NotificationProviderError> {
+		provider.validate()?;
+
+		Ok(Self {
+			id: Uuid::new_v4(),
+			name,
+			enabled: true,
+			min_severity,
+			provider,
+		})
+	}
+
+	/// Replaces the settings of the provider, keeping the secrets the new ones leave out
+	pub fn update(
+		&mut self,
+		name: String,
+		enabled: bool,
+		min_severity: NotificationSeverity,
+		mut provider: NotificationProvider,
+	) -> Result<(), NotificationProviderError> {
+		provider.keep_secrets_of(&self.provider);
+		provider.validate()?;
+
+		self.name = name;
+		self.enabled = enabled;
+		self.min_severity = min_severity;
+		self.provider = provider;
+
+		Ok(())
+	}
+}
+
+/// A version of [`NotificationProviderConfig`] that is safe to share with the frontend
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitisedNotificationProvider {
+	pub id: Uuid,
+	pub name: String,
+	pub enabled: bool,
+	pub min_severity: NotificationSeverity,
+	pub provider: NotificationProvider,
+	/// Whether a password or token is set, as they aren't sent back
+	pub has_secret: bool,
+}
+
+impl From<NotificationProviderConfig> for SanitisedNotificationProvider {
+	fn from(config: NotificationProviderConfig) -> Self {
+		Self {
+			id: config.id,
+			name: config.name,
+			enabled: config.enabled,
+			min_severity: config.min_severity,
+			has_secret: config.provider.has_secret(),
+			provider: config.provider.redacted(),
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum NotificationProviderError {
+	#[error("notification provider not found <id='{0}'>")]
+	NotFound(Uuid),
+	#[error("invalid url '{0}', it must be an http or https one")]
+	InvalidUrl(String),
+	#[error("invalid email address: {0}")]
+	InvalidAddress(#[from] lettre::address::AddressError),
+	#[error("emails need at least one recipient")]
+	NoRecipients,
+	#[error("the {0} of the provider isn't set")]
+	MissingSecret(&'static str),
+	#[error("system notifications aren't available on this platform")]
+	SystemUnsupported,
+	#[error("failed to show system notification: {0}")]
+	System(String),
+	#[error("failed to build email: {0}")]
+	Email(#[from] lettre::error::Error),
+	#[error("failed to send email: {0}")]
+	Smtp(#[from] lettre::transport::smtp::Error),
+	#[error("request failed: {0}")]
+	Http(#[from] reqwest::Error),
+}
+
+impl From<NotificationProviderError> for rspc::Error {
+	fn from(err: NotificationProviderError) -> Self {
+		match err {
+			NotificationProviderError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			NotificationProviderError::InvalidUrl(_)
+			| NotificationProviderError::InvalidAddress(_)
+			| NotificationProviderError::NoRecipients
+			| NotificationProviderError::MissingSecret(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A notification as delivered by the providers
+#[derive(Debug, Clone)]
+pub struct NotificationMessage {
+	pub library_id: Uuid,
+	pub library_name: String,
+	pub title: String,
+	pub body: String,
+	pub notification: NotificationItem,
+}
+
+fn ntfy_priority(severity: NotificationSeverity) -> &'static str {
+	match severity {
+		NotificationSeverity::Info => "default",
+		NotificationSeverity::Warning => "high",
+		NotificationSeverity::Error => "urgent",
+	}
+}
+
+fn gotify_priority(severity: NotificationSeverity) -> u8 {
+	match severity {
+		NotificationSeverity::Info => 2,
+		NotificationSeverity::Warning => 5,
+		NotificationSeverity::Error => 8,
+	}
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+async fn show_system_notification(
+	message: &NotificationMessage,
+) -> Result<(), NotificationProviderError> {
+	let (title, body) = (message.title.clone(), message.body.clone());
+
+	tokio::task::spawn_blocking(move || {
+		notify_rust::Notification::new()
+			.appname("Spacedrive")
+			.summary(&title)
+			.body(&body)
+			.show()
+			.map(|_| ())
+			.map_err(|e| NotificationProviderError::System(e.to_string()))
+	})
+	.await
+	.map_err(|e| NotificationProviderError::System(e.to_string()))?
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+async fn show_system_notification(
+	_message: &NotificationMessage,
+) -> Result<(), NotificationProviderError> {
+	Err(NotificationProviderError::SystemUnsupported)
+}
+
+async fn send_email(
+	provider: &NotificationProvider,
+	message: &NotificationMessage,
+) -> Result<(), NotificationProviderError> {
+	let NotificationProvider::Smtp {
+		host,
+		port,
+		security,
+		username,
+		password,
+		from,
+		to,
+	} = provider
+	else {
+		unreachable!("only called for SMTP providers");
+	};
+
+	let mut transport = match security {
+		SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+		SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+		SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+	}
+	.timeout(Some(DELIVERY_TIMEOUT));
+
+	if let Some(port) = port {
+		transport = transport.port(*port);
+	}
+
+	if let Some(username) = username {
+		let password = password
+			.clone()
+			.ok_or(NotificationProviderError::MissingSecret("password"))?;
+		transport = transport.credentials(Credentials::new(username.clone(), password));
+	}
+
+	let mut email = Message::builder()
+		.from(from.parse()?)
+		.subject(format!("[{}] {}", message.library_name, message.title))
+		.header(ContentType::TEXT_PLAIN);
+	for to in to {
+		email = email.to(to.parse()?);
+	}
+
+	transport
+		.build()
+		.send(email.body(message.body.clone())?)
+		.await?;
+
+	Ok(())
+}
+
+async fn deliver(
+	client: &Client,
+	provider: &NotificationProvider,
+	message: &NotificationMessage,
+) -> Result<(), NotificationProviderError> {
+	let severity = message.notification.severity;
+
+	match provider {
+		NotificationProvider::System => show_system_notification(message).await,
+		NotificationProvider::Smtp { .. } => send_email(provider, message).await,
+		NotificationProvider::Webhook { url } => {
+			client
+				.post(url)
+				.json(&json!({
+					"libraryId": message.library_id,
+					"libraryName": message.library_name,
+					"title": message.title,
+					"body": message.body,
+					"notification": message.notification,
+				}))
+				.send()
+				.await?
+				.error_for_status()?;
+
+			Ok(())
+		}
+		NotificationProvider::Ntfy {
+			server,
+			topic,
+			token,
+		} => {
+			let server = server.as_deref().unwrap_or(DEFAULT_NTFY_SERVER);
+
+			let mut req = client
+				.post(format!("{}/{topic}", server.trim_end_matches('/')))
+				.header("Title", &message.title)
+				.header("Priority", ntfy_priority(severity))
+				.header("Tags", message.notification.category.as_str())
+				.body(message.body.clone());
+			if let Some(token) = token {
+				req = req.bearer_auth(token);
+			}
+
+			req.send().await?.error_for_status()?;
+
+			Ok(())
+		}
+		NotificationProvider::Gotify { server, token } => {
+			let token = token
+				.as_deref()
+				.ok_or(NotificationProviderError::MissingSecret("token"))?;
+
+			client
+				.post(format!("{}/message", server.trim_end_matches('/')))
+				.header("X-Gotify-Key", token)
+				.json(&json!({
+					"title": message.title,
+					"message": message.body,
+					"priority": gotify_priority(severity),
+				}))
+				.send()
+				.await?
+				.error_for_status()?;
+
+			Ok(())
+		}
+	}
+}
+
+/// Delivers a notification through a single provider, whatever its settings, to try it out
+pub async fn deliver_with_provider(
+	provider: &NotificationProvider,
+	message: &NotificationMessage,
+) -> Result<(), NotificationProviderError> {
+	let client = Client::builder().timeout(DELIVERY_TIMEOUT).build()?;
+
+	deliver(&client, provider, message).await
+}
+
+/// Delivers a notification through the enabled providers that want notifications this severe.
+/// Failures are only logged, the notification being kept in the library anyway.
+pub async fn deliver_notification(
+	providers: Vec<NotificationProviderConfig>,
+	message: NotificationMessage,
+) {
+	let providers = providers
+		.into_iter()
+		.filter(|config| config.enabled && message.notification.severity >= config.min_severity)
+		.collect::<Vec<_>>();
+
+	if providers.is_empty() {
+		return;
+	}
+
+	let client = match Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+		Ok(client) => client,
+		Err(e) => {
+			error!("Failed to build the notifications HTTP client: {e:#?}");
+			return;
+		}
+	};
+
+	join_all(providers.iter().map(|config| {
+		let client = &client;
+		let message = &message;
+		async move {
+			match deliver(client, &config.provider, message).await {
+				Ok(()) => debug!(
+					"Delivered notification <id='{}'> through provider '{}'",
+					message.notification.id, config.name
+				),
+				Err(e) => error!(
+					"Failed to deliver notification <id='{}'> through provider '{}': {e}",
+					message.notification.id, config.name
+				),
+			}
+		}
+	}))
+	.await;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn secrets_are_kept_when_left_out() {
+		let previous = NotificationProvider::Gotify {
+			server: "https://gotify.example.com".to_string(),
+			token: Some("secret".to_string()),
+		};
+
+		let mut updated = previous.clone().redacted();
+		assert!(!updated.has_secret());
+
+		updated.keep_secrets_of(&previous);
+		assert!(matches!(
+			updated,
+			NotificationProvider::Gotify { token: Some(token), .. } if token == "secret"
+		));
+	}
+
+	#[test]
+	fn providers_are_validated() {
+		assert!(NotificationProvider::Webhook {
+			url: "file:///etc/passwd".to_string()
+		}
+		.validate()
+		.is_err());
+
+		assert!(NotificationProvider::Smtp {
+			host: "smtp.example.com".to_string(),
+			port: None,
+			security: SmtpSecurity::StartTls,
+			username: None,
+			password: None,
+			from: "spacedrive@example.com".to_string(),
+			to: vec![],
+		}
+		.validate()
+		.is_err());
+
+		assert!(NotificationProvider::Ntfy {
+			server: None,
+			topic: "spacedrive".to_string(),
+			token: None,
+		}
+		.validate()
+		.is_ok());
+	}
+}
@@ -0,0 +1,191 @@
+use crate::{
+	api::search::ObjectFilterArgs,
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	prisma::{album, object, object_in_album},
+	util::db::maybe_missing,
+};
+
+use std::{collections::HashSet, hash::Hash};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+const CHUNK_SIZE: usize = 1000;
+
+/// `SmartAlbumMaterializerJobInit` evaluates the stored search query of a smart album and
+/// brings its `object_in_album` rows in line with the current set of matching objects, so album
+/// contents can be queried through a simple relation instead of re-running the search.
+/// Objects still matching keep their rows, so the album is never emptied while it runs.
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct SmartAlbumMaterializerJobInit {
+	pub album_id: album::id::Type,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct SmartAlbumMaterializerJobRunMetadata {
+	total_objects: usize,
+}
+
+impl JobRunMetadata for SmartAlbumMaterializerJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_objects += new_data.total_objects;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for SmartAlbumMaterializerJobInit {
+	type Data = ();
+	type Step = Vec<object::id::Type>;
+	type RunMetadata = SmartAlbumMaterializerJobRunMetadata;
+
+	const NAME: &'static str = "smart_album_materializer";
+	const IS_BACKGROUND: bool = true;
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		let album = db
+			.album()
+			.find_unique(album::id::equals(self.album_id))
+			.select(album::select!({ search_query }))
+			.exec()
+			.await?
+			.ok_or_else(|| JobError::MissingFromDb("album", self.album_id.to_string()))?;
+
+		let filter = serde_json::from_slice::<ObjectFilterArgs>(&maybe_missing(
+			album.search_query,
+			"album.search_query",
+		)?)?;
+
+		let object_ids = db
+			.object()
+			.find_many(filter.into_params())
+			.select(object::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|object| object.id)
+			.collect::<HashSet<_>>();
+
+		let current_ids = db
+			.object_in_album()
+			.find_many(vec![object_in_album::album_id::equals(self.album_id)])
+			.select(object_in_album::select!({ object_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|membership| membership.object_id)
+			.collect::<HashSet<_>>();
+
+		// Objects that stopped matching drop out, the new ones are added by the steps
+		let stale_ids = current_ids
+			.difference(&object_ids)
+			.copied()
+			.collect::<Vec<_>>();
+		for chunk in stale_ids.chunks(CHUNK_SIZE) {
+			db.object_in_album()
+				.delete_many(vec![
+					object_in_album::album_id::equals(self.album_id),
+					object_in_album::object_id::in_vec(chunk.to_vec()),
+				])
+				.exec()
+				.await?;
+		}
+
+		let new_ids = object_ids
+			.difference(&current_ids)
+			.copied()
+			.collect::<Vec<_>>();
+
+		*data = Some(());
+
+		Ok((
+			SmartAlbumMaterializerJobRunMetadata {
+				total_objects: object_ids.len(),
+			},
+			new_ids
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep {
+			step: object_ids, ..
+		}: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		let date_created = Utc::now().into();
+
+		// Objects deleted since the job started are skipped, as their rows would fail to insert
+		let object_ids = db
+			.object()
+			.find_many(vec![object::id::in_vec(object_ids.clone())])
+			.select(object::select!({ id }))
+			.exec()
+			.await?;
+
+		db.object_in_album()
+			.create_many(
+				object_ids
+					.into_iter()
+					.map(|object| object_in_album::CreateUnchecked {
+						album_id: self.album_id,
+						object_id: object.id,
+						_params: vec![object_in_album::date_created::set(Some(date_created))],
+					})
+					.collect(),
+			)
+			.exec()
+			.await?;
+
+		Ok(().into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let library = &ctx.library;
+
+		library
+			.db
+			.album()
+			.update(
+				album::id::equals(self.album_id),
+				vec![album::date_materialized::set(Some(Utc::now().into()))],
+			)
+			.exec()
+			.await?;
+
+		info!(
+			"Materialized smart album <id='{}'> with {} objects",
+			self.album_id, run_metadata.total_objects
+		);
+
+		invalidate_query!(library, "albums.list");
+		invalidate_query!(library, "search.objects");
+
+		Ok(Some(json!({ "init": self, "run_metadata": run_metadata })))
+	}
+}
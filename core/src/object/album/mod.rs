@@ -0,0 +1,87 @@
+pub mod materializer_job;
+
+use crate::{
+	api::search::ObjectFilterArgs,
+	job::{JobBuilder, JobManagerError},
+	library::Library,
+	prisma::album,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+use specta::Type;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use materializer_job::SmartAlbumMaterializerJobInit;
+
+#[derive(Type, Deserialize, Clone)]
+pub struct AlbumCreateArgs {
+	pub name: String,
+	/// If present, the album is a smart album and its contents are every object matching this filter
+	pub search_query: Option<ObjectFilterArgs>,
+}
+
+impl AlbumCreateArgs {
+	pub async fn exec(
+		self,
+		Library { db, .. }: &Library,
+	) -> Result<album::Data, prisma_client_rust::QueryError> {
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+		db.album()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![
+					album::name::set(Some(self.name)),
+					album::search_query::set(
+						self.search_query
+							.as_ref()
+							.map(serde_json::to_vec)
+							.transpose()
+							.expect("search filters are always serializable"),
+					),
+					album::date_created::set(Some(date_created)),
+				],
+			)
+			.exec()
+			.await
+	}
+}
+
+/// Spawns a materialization job for every smart album in the library, so their membership
+/// reflects objects that were created or changed since the last run.
+/// If `after` is given, the materializations only run once that job completes.
+pub async fn rematerialize_smart_albums(
+	library: &Library,
+	after: Option<Uuid>,
+) -> Result<(), JobManagerError> {
+	let albums = library
+		.db
+		.album()
+		.find_many(vec![album::search_query::not(None)])
+		.select(album::select!({ id }))
+		.exec()
+		.await?;
+
+	for album in albums {
+		let mut builder = JobBuilder::new(SmartAlbumMaterializerJobInit { album_id: album.id });
+		if let Some(job_id) = after {
+			builder = builder.with_dependency(job_id);
+		}
+
+		match builder.build().spawn(library).await {
+			Ok(()) => {}
+			// A materialization for this album is already queued, it will pick up the new objects
+			Err(JobManagerError::AlreadyRunningJob { .. }) => {
+				debug!(
+					"Smart album <id='{}'> is already being materialized",
+					album.id
+				);
+			}
+			Err(e) => error!("Failed to spawn smart album materializer: {e:#?}"),
+		}
+	}
+
+	Ok(())
+}
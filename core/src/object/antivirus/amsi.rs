@@ -0,0 +1,82 @@
+//! Scanning through the Antimalware Scan Interface of Windows, which hands the contents of files
+//! to the antivirus registered with it, Microsoft Defender by default
+
+use crate::util::error::FileIOError;
+
+use std::{os::windows::ffi::OsStrExt, path::Path};
+
+use tokio::{fs, task::spawn_blocking};
+use windows::{
+	core::{HSTRING, PCWSTR},
+	Win32::System::Antimalware::{
+		AmsiCloseSession, AmsiInitialize, AmsiOpenSession, AmsiScanBuffer, AmsiUninitialize,
+		AMSI_RESULT_DETECTED,
+	},
+};
+
+use super::{ScanError, ScanVerdict, Scanner};
+
+/// AMSI scans buffers, so files are read whole, and the ones bigger than this aren't scanned
+const MAX_FILE_LEN: u64 = 256 * 1024 * 1024;
+
+pub struct AmsiScanner;
+
+#[async_trait::async_trait]
+impl Scanner for AmsiScanner {
+	async fn scan(&self, path: &Path) -> Result<ScanVerdict, ScanError> {
+		let metadata = fs::metadata(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		if metadata.len() > MAX_FILE_LEN {
+			return Err(ScanError::Scanner(format!(
+				"'{}' is too big to be scanned through AMSI",
+				path.display()
+			)));
+		}
+
+		let contents = fs::read(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		let content_name = path
+			.as_os_str()
+			.encode_wide()
+			.chain(Some(0))
+			.collect::<Vec<_>>();
+
+		spawn_blocking(move || scan_buffer(&contents, &content_name))
+			.await
+			.map_err(|e| ScanError::Scanner(e.to_string()))?
+	}
+}
+
+fn scan_buffer(contents: &[u8], content_name: &[u16]) -> Result<ScanVerdict, ScanError> {
+	let to_scan_error = |e: windows::core::Error| ScanError::Scanner(e.to_string());
+
+	// SAFETY: the context and session are closed before returning, and the buffer and the name
+	// outlive the call scanning them
+	unsafe {
+		let context = AmsiInitialize(&HSTRING::from("Spacedrive")).map_err(to_scan_error)?;
+		let res = AmsiOpenSession(context).and_then(|session| {
+			let res = AmsiScanBuffer(
+				context,
+				contents.as_ptr().cast(),
+				contents.len() as u32,
+				PCWSTR(content_name.as_ptr()),
+				session,
+			);
+			AmsiCloseSession(context, session);
+			res
+		});
+		AmsiUninitialize(context);
+
+		let result = res.map_err(to_scan_error)?;
+
+		// Any result from `AMSI_RESULT_DETECTED` up means the content is malware, AMSI doesn't
+		// name what was found
+		Ok(if result.0 >= AMSI_RESULT_DETECTED.0 {
+			ScanVerdict::Detected("AMSI detection".to_string())
+		} else {
+			ScanVerdict::Clean
+		})
+	}
+}
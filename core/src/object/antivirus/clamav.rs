@@ -0,0 +1,125 @@
+//! Client of the `clamd` daemon of ClamAV. Files are streamed to it with `INSTREAM` rather than
+//! handed over by path, so it doesn't need to be able to read them itself.
+
+use crate::util::error::FileIOError;
+
+use std::path::Path;
+
+use tokio::{
+	fs::File,
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	net::TcpStream,
+};
+
+use super::{ScanError, ScanVerdict, Scanner};
+
+/// Size of the chunks files are streamed in, well under the default `StreamMaxLength` of `clamd`
+const CHUNK_LEN: usize = 64 * 1024;
+
+pub struct ClamAvScanner {
+	address: String,
+}
+
+impl ClamAvScanner {
+	pub fn new(address: impl Into<String>) -> Self {
+		Self {
+			address: address.into(),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Scanner for ClamAvScanner {
+	async fn scan(&self, path: &Path) -> Result<ScanVerdict, ScanError> {
+		let unreachable = |e| ScanError::Unreachable(self.address.clone(), e);
+
+		#[cfg(unix)]
+		if self.address.starts_with('/') {
+			let stream = tokio::net::UnixStream::connect(&self.address)
+				.await
+				.map_err(unreachable)?;
+			return scan_stream(stream, path).await;
+		}
+
+		let stream = TcpStream::connect(&self.address)
+			.await
+			.map_err(unreachable)?;
+		scan_stream(stream, path).await
+	}
+}
+
+async fn scan_stream(
+	mut stream: impl AsyncRead + AsyncWrite + Unpin,
+	path: &Path,
+) -> Result<ScanVerdict, ScanError> {
+	let mut file = File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	stream.write_all(b"zINSTREAM\0").await?;
+
+	let mut buffer = vec![0; CHUNK_LEN];
+	loop {
+		let read = file
+			.read(&mut buffer)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		if read == 0 {
+			break;
+		}
+
+		stream.write_all(&(read as u32).to_be_bytes()).await?;
+		stream.write_all(&buffer[..read]).await?;
+	}
+	// A chunk of length zero ends the stream
+	stream.write_all(&0u32.to_be_bytes()).await?;
+	stream.flush().await?;
+
+	// The daemon closes the connection once it replied, as no session was started
+	let mut reply = vec![];
+	stream.read_to_end(&mut reply).await?;
+
+	parse_reply(&reply)
+}
+
+/// Replies look like `stream: OK`, `stream: Eicar-Signature FOUND` or `<reason> ERROR`, `\0`
+/// terminated
+fn parse_reply(reply: &[u8]) -> Result<ScanVerdict, ScanError> {
+	let reply = String::from_utf8_lossy(reply);
+	let reply = reply.trim_end_matches('\0').trim();
+
+	if reply.ends_with("ERROR") {
+		return Err(ScanError::Scanner(reply.to_string()));
+	}
+
+	match reply.strip_prefix("stream:").map(str::trim) {
+		Some("OK") => Ok(ScanVerdict::Clean),
+		Some(result) => result
+			.strip_suffix(" FOUND")
+			.map(|signature| ScanVerdict::Detected(signature.to_string()))
+			.ok_or_else(|| ScanError::UnexpectedReply(reply.to_string())),
+		None => Err(ScanError::UnexpectedReply(reply.to_string())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_replies() {
+		assert_eq!(parse_reply(b"stream: OK\0").unwrap(), ScanVerdict::Clean);
+		assert_eq!(
+			parse_reply(b"stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+			ScanVerdict::Detected("Win.Test.EICAR_HDB-1".to_string())
+		);
+		assert!(matches!(
+			parse_reply(b"INSTREAM size limit exceeded. ERROR\0"),
+			Err(ScanError::Scanner(_))
+		));
+		assert!(matches!(
+			parse_reply(b"PONG\0"),
+			Err(ScanError::UnexpectedReply(_))
+		));
+	}
+}
@@ -0,0 +1,249 @@
+//! Hooks sending the files added to locations to an antivirus scanner, for the locations where
+//! it's enabled. New files are scanned by a job chained to the indexer, and by the location
+//! watcher as they show up, which also covers the ones copied or moved into a location. What the
+//! scanner flags is tagged or quarantined, as set in the config of the node.
+
+use crate::{
+	library::{Library, NotificationKind},
+	location::file_path_helper::FilePathError,
+	object::{tag::find_or_create_tag, validation::quarantine::quarantine_file_path},
+	prisma::{file_path, location, object, tag, tag_on_object},
+	util::error::FileIOError,
+};
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tracing::{error, warn};
+
+#[cfg(windows)]
+mod amsi;
+mod clamav;
+pub mod scanner_job;
+
+/// Tag given to the objects of the files the scanner flags
+const DETECTION_TAG_NAME: &str = "Malware";
+const DETECTION_TAG_COLOR: &str = "#E5484D";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AntivirusSettings {
+	/// Scanner the new files are sent to, nothing is scanned without one
+	pub scanner: Option<ScannerConfig>,
+	pub on_detection: DetectionAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScannerConfig {
+	/// A `clamd` daemon, reached through a Unix socket if the address is a path, or TCP otherwise
+	ClamAv { address: String },
+	/// The Antimalware Scan Interface of Windows, handing files to whichever antivirus is installed
+	Amsi,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DetectionAction {
+	/// Tags the object of the file, leaving it where it is
+	#[default]
+	Tag,
+	/// Hides the file until it's released, like the ones failing integrity checks
+	Quarantine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+	Clean,
+	/// Flagged by the scanner, with the name of what it found
+	Detected(String),
+}
+
+#[derive(Debug, Error)]
+pub enum ScanError {
+	#[error("AMSI is only available on Windows")]
+	AmsiUnsupported,
+	#[error("failed to reach the antivirus scanner at '{0}': {1}")]
+	Unreachable(String, #[source] io::Error),
+	#[error("lost the connection to the antivirus scanner: {0}")]
+	Connection(#[from] io::Error),
+	#[error("unexpected reply from the antivirus scanner: '{0}'")]
+	UnexpectedReply(String),
+	#[error("the antivirus scanner failed: {0}")]
+	Scanner(String),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+/// What the files are scanned with, [`ScannerConfig`] being the way to pick one
+#[async_trait::async_trait]
+pub trait Scanner: Send + Sync {
+	async fn scan(&self, path: &Path) -> Result<ScanVerdict, ScanError>;
+}
+
+pub fn scanner(config: &ScannerConfig) -> Result<Box<dyn Scanner>, ScanError> {
+	match config {
+		ScannerConfig::ClamAv { address } => Ok(Box::new(clamav::ClamAvScanner::new(address))),
+		#[cfg(windows)]
+		ScannerConfig::Amsi => Ok(Box::new(amsi::AmsiScanner)),
+		#[cfg(not(windows))]
+		ScannerConfig::Amsi => Err(ScanError::AmsiUnsupported),
+	}
+}
+
+/// The scanner for the new files of a location, with what's done with detections, if scanning is
+/// enabled for it and a scanner is set up on this node
+pub(crate) async fn location_scanner(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<Option<(Box<dyn Scanner>, DetectionAction)>, ScanError> {
+	let Some(location) = library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ node_id scan_for_malware }))
+		.exec()
+		.await?
+	else {
+		return Ok(None);
+	};
+
+	if location.node_id != Some(library.node_local_id) || location.scan_for_malware != Some(true) {
+		return Ok(None);
+	}
+
+	let settings = library.config().get().await.antivirus;
+	settings
+		.scanner
+		.as_ref()
+		.map(|config| scanner(config).map(|scanner| (scanner, settings.on_detection)))
+		.transpose()
+}
+
+/// Tags or quarantines a file the scanner flagged, and lets the user know
+pub(crate) async fn handle_detection(
+	library: &Library,
+	action: DetectionAction,
+	location_id: location::id::Type,
+	file_path_id: file_path::id::Type,
+	object_id: Option<object::id::Type>,
+	path: String,
+	signature: String,
+) -> Result<(), prisma_client_rust::QueryError> {
+	warn!("Antivirus scanner flagged '{path}' in location {location_id} as '{signature}'");
+
+	match (action, object_id) {
+		(DetectionAction::Tag, Some(object_id)) => {
+			let tag_id =
+				find_or_create_tag(library, DETECTION_TAG_NAME, DETECTION_TAG_COLOR).await?;
+
+			library
+				.db
+				.tag_on_object()
+				.upsert(
+					tag_on_object::tag_id_object_id(tag_id, object_id),
+					tag_on_object::create(
+						tag::id::equals(tag_id),
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![],
+				)
+				.exec()
+				.await?;
+		}
+		// Without an object there's nothing to tag, so it's better hidden than left unmarked
+		(DetectionAction::Tag, None) | (DetectionAction::Quarantine, _) => {
+			quarantine_file_path(library, file_path_id).await?;
+		}
+	}
+
+	library
+		.notify(NotificationKind::MalwareDetected {
+			location_id,
+			path,
+			signature,
+		})
+		.await;
+
+	Ok(())
+}
+
+pub(crate) async fn mark_scanned(
+	library: &Library,
+	file_path_ids: Vec<file_path::id::Type>,
+) -> Result<(), prisma_client_rust::QueryError> {
+	library
+		.db
+		.file_path()
+		.update_many(
+			vec![file_path::id::in_vec(file_path_ids)],
+			vec![file_path::date_scanned::set(Some(Utc::now().into()))],
+		)
+		.exec()
+		.await
+		.map(|_| ())
+}
+
+async fn scan_new_file(
+	library: &Library,
+	location_id: location::id::Type,
+	file_path_id: file_path::id::Type,
+	object_id: Option<object::id::Type>,
+	materialized_path: String,
+	full_path: &Path,
+) -> Result<(), ScanError> {
+	let Some((scanner, action)) = location_scanner(library, location_id).await? else {
+		return Ok(());
+	};
+
+	if let ScanVerdict::Detected(signature) = scanner.scan(full_path).await? {
+		handle_detection(
+			library,
+			action,
+			location_id,
+			file_path_id,
+			object_id,
+			materialized_path,
+			signature,
+		)
+		.await?;
+	}
+
+	Ok(mark_scanned(library, vec![file_path_id]).await?)
+}
+
+/// Scans a file the watcher found in a location, in the background as scanners can be slow
+pub(crate) fn scan_new_file_in_background(
+	library: Library,
+	location_id: location::id::Type,
+	file_path_id: file_path::id::Type,
+	object_id: Option<object::id::Type>,
+	materialized_path: String,
+	full_path: PathBuf,
+) {
+	tokio::spawn(async move {
+		if let Err(e) = scan_new_file(
+			&library,
+			location_id,
+			file_path_id,
+			object_id,
+			materialized_path,
+			&full_path,
+		)
+		.await
+		{
+			error!("Failed to scan '{}' for malware: {e}", full_path.display());
+		}
+	});
+}
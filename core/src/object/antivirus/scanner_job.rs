@@ -0,0 +1,246 @@
+use crate::{
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobRunMetadata,
+		JobStepOutput, RetryPolicy, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::{ensure_sub_path_is_in_location, IsolatedFilePathData},
+	prisma::{file_path, location, object},
+	util::db::{chain_optional_iter, maybe_missing},
+};
+
+use std::{
+	borrow::Cow,
+	hash::{Hash, Hasher},
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use super::{handle_detection, location_scanner, mark_scanned, ScanError, ScanVerdict};
+
+/// Scanners take a while per file, so they're only given a few at a time
+const CHUNK_SIZE: usize = 10;
+
+/// Sends the files of a location that weren't scanned yet to the antivirus scanner, for locations
+/// where it's enabled
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MalwareScannerJobInit {
+	pub location: location::Data,
+	pub sub_path: Option<PathBuf>,
+}
+
+impl Hash for MalwareScannerJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MalwareScannerJobStep {
+	file_path_id: file_path::id::Type,
+	object_id: Option<object::id::Type>,
+	/// Path of the file in its location, as shown to the user
+	materialized_path: String,
+	path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct MalwareScannerJobRunMetadata {
+	total_files: usize,
+	scanned_files: usize,
+	detections: usize,
+}
+
+impl JobRunMetadata for MalwareScannerJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_files += new_data.total_files;
+		self.scanned_files += new_data.scanned_files;
+		self.detections += new_data.detections;
+	}
+}
+
+file_path::select!(file_path_for_malware_scanner {
+	id
+	materialized_path
+	name
+	extension
+	object_id
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for MalwareScannerJobInit {
+	type Data = ();
+	type Step = Vec<MalwareScannerJobStep>;
+	type RunMetadata = MalwareScannerJobRunMetadata;
+
+	const NAME: &'static str = "malware_scanner";
+	const PRIORITY: JobPriority = JobPriority::Background;
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		_: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		if location_scanner(&ctx.library, init.location.id)
+			.await?
+			.is_none()
+		{
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "malware scanning is disabled for this location, or no scanner is set up"
+					.to_string(),
+			});
+		}
+
+		let location_path = PathBuf::from(maybe_missing(&init.location.path, "location.path")?);
+
+		let sub_path_materialized_path = match &init.sub_path {
+			Some(sub_path) if sub_path != Path::new("") => {
+				let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+					.await
+					.map_err(ScanError::from)?;
+
+				IsolatedFilePathData::new(init.location.id, &location_path, &full_path, true)
+					.map_err(ScanError::from)?
+					.materialized_path_for_children()
+			}
+			_ => None,
+		};
+
+		let steps = db
+			.file_path()
+			.find_many(chain_optional_iter(
+				[
+					file_path::location_id::equals(Some(init.location.id)),
+					file_path::is_dir::equals(Some(false)),
+					file_path::date_scanned::equals(None),
+					file_path::date_deleted::equals(None),
+					file_path::date_quarantined::equals(None),
+				],
+				[sub_path_materialized_path.map(file_path::materialized_path::starts_with)],
+			))
+			.select(file_path_for_malware_scanner::select())
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| {
+				let materialized_path =
+					maybe_missing(file_path.materialized_path, "file_path.materialized_path")?;
+				let iso_file_path = IsolatedFilePathData::from_db_data(
+					init.location.id,
+					false,
+					Cow::Borrowed(&materialized_path),
+					Cow::Owned(maybe_missing(file_path.name, "file_path.name")?),
+					Cow::Owned(maybe_missing(file_path.extension, "file_path.extension")?),
+				);
+
+				Ok(MalwareScannerJobStep {
+					file_path_id: file_path.id,
+					object_id: file_path.object_id,
+					path: location_path.join(&iso_file_path),
+					materialized_path: format!("{materialized_path}{}", iso_file_path.full_name()),
+				})
+			})
+			.collect::<Result<Vec<_>, JobError>>()?;
+
+		ctx.progress_msg(format!("Scanning {} files for malware", steps.len()));
+
+		Ok((
+			MalwareScannerJobRunMetadata {
+				total_files: steps.len(),
+				..Default::default()
+			},
+			steps
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: files, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let location_id = self.location.id;
+
+		// Turned off while the job was running
+		let Some((scanner, action)) = location_scanner(&ctx.library, location_id).await? else {
+			return Ok(().into());
+		};
+
+		let mut new_metadata = MalwareScannerJobRunMetadata::default();
+		let mut scanned = Vec::with_capacity(files.len());
+		let mut errors = vec![];
+
+		for file in files {
+			match scanner.scan(&file.path).await {
+				Ok(verdict) => {
+					if let ScanVerdict::Detected(signature) = verdict {
+						handle_detection(
+							&ctx.library,
+							action,
+							location_id,
+							file.file_path_id,
+							file.object_id,
+							file.materialized_path.clone(),
+							signature,
+						)
+						.await?;
+						new_metadata.detections += 1;
+					}
+
+					scanned.push(file.file_path_id);
+				}
+				// Nothing else can be scanned without the scanner
+				Err(e @ ScanError::Unreachable(..)) => return Err(e.into()),
+				Err(e) => errors.push(format!(
+					"Failed to scan '{}' for malware: {e}",
+					file.path.display()
+				)),
+			}
+		}
+
+		new_metadata.scanned_files = scanned.len();
+		mark_scanned(&ctx.library, scanned).await?;
+
+		ctx.progress_msg(format!(
+			"Scanned {} of {} files for malware",
+			run_metadata.scanned_files + new_metadata.scanned_files,
+			run_metadata.total_files
+		));
+
+		Ok((vec![], new_metadata, errors.into()).into())
+	}
+
+	async fn finalize(
+		&self,
+		_: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Scanned {} files of location {} for malware, {} were flagged",
+			run_metadata.scanned_files, self.location.id, run_metadata.detections
+		);
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
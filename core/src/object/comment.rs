@@ -0,0 +1,102 @@
+use crate::{
+	library::Library,
+	prisma::{comment, node, object},
+	sync,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use specta::Type;
+use uuid::Uuid;
+
+comment::include!(comment_with_author { author: select { pub_id name } });
+
+#[derive(Type, Deserialize, Clone)]
+pub struct CommentCreateArgs {
+	pub object_id: object::id::Type,
+	/// The comment being replied to, if any
+	pub parent_id: Option<comment::id::Type>,
+	pub content: String,
+}
+
+impl CommentCreateArgs {
+	/// Creates the comment authored by the current node, returns `None` if the object or the parent comment don't exist,
+	/// or if the parent comment is on another object
+	pub async fn exec(
+		self,
+		library: &Library,
+	) -> prisma_client_rust::Result<Option<comment::Data>> {
+		let Library { db, sync, .. } = library;
+
+		let Some(object) = db
+			.object()
+			.find_unique(object::id::equals(self.object_id))
+			.select(object::select!({ pub_id }))
+			.exec()
+			.await?
+		else {
+			return Ok(None);
+		};
+
+		let parent = if let Some(parent_id) = self.parent_id {
+			let Some(parent) = db
+				.comment()
+				.find_unique(comment::id::equals(parent_id))
+				.select(comment::select!({ id pub_id object_id }))
+				.exec()
+				.await?
+			else {
+				return Ok(None);
+			};
+
+			// Replies stay on the object of the comment they answer
+			if parent.object_id != Some(self.object_id) {
+				return Ok(None);
+			}
+
+			Some(parent)
+		} else {
+			None
+		};
+
+		let pub_id = Uuid::new_v4().as_bytes().to_vec();
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+		let author_pub_id = library.config.node_id.as_bytes().to_vec();
+
+		let mut sync_params = vec![
+			(comment::content::NAME, json!(&self.content)),
+			(
+				comment::date_created::NAME,
+				json!(&date_created.to_rfc3339()),
+			),
+			(comment::object::NAME, json!({ "pub_id": object.pub_id })),
+			(comment::author::NAME, json!({ "pub_id": author_pub_id })),
+		];
+
+		let mut db_params = vec![
+			comment::content::set(Some(self.content)),
+			comment::date_created::set(Some(date_created)),
+			comment::object::connect(object::id::equals(self.object_id)),
+			comment::author::connect(node::id::equals(library.node_local_id)),
+		];
+
+		if let Some(parent) = parent {
+			sync_params.push((comment::parent::NAME, json!({ "pub_id": parent.pub_id })));
+			db_params.push(comment::parent::connect(comment::id::equals(parent.id)));
+		}
+
+		sync.write_op(
+			db,
+			sync.unique_shared_create(
+				sync::comment::SyncId {
+					pub_id: pub_id.clone(),
+				},
+				sync_params,
+			),
+			db.comment().create(pub_id, db_params),
+		)
+		.await
+		.map(Some)
+	}
+}
@@ -0,0 +1,514 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::file_path_helper::{file_path_to_stream, IsolatedFilePathData},
+	p2p::{DelegationError, P2PManager, RemoteFileRequest, MAX_REMOTE_FILE_CHUNK_LEN},
+	prisma::{file_path, offline_pin},
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::{
+	collections::HashSet,
+	path::PathBuf,
+	str::FromStr,
+	sync::{Arc, Mutex},
+	time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+use sd_p2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Contents of files on locations of other nodes, fetched when they're opened or previewed
+pub const CONTENT_CACHE_DIR_NAME: &str = "content_cache";
+
+/// Once over the limit, files are evicted until the cache is this fraction of the limit, so
+/// eviction doesn't run again as soon as another file is opened
+const EVICTION_TARGET_RATIO: f64 = 0.9;
+
+/// Files being fetched, so opening a file again while it's fetched doesn't fetch it twice
+static MATERIALIZING: Lazy<Mutex<HashSet<Uuid>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentCacheSettings {
+	/// Least recently opened files are evicted past this size, files pinned offline excepted
+	pub max_size_mb: Option<u32>,
+}
+
+impl Default for ContentCacheSettings {
+	fn default() -> Self {
+		Self {
+			max_size_mb: Some(5 * 1024),
+		}
+	}
+}
+
+impl ContentCacheSettings {
+	pub fn max_size_in_bytes(&self) -> Option<u64> {
+		self.max_size_mb.map(|size_mb| size_mb as u64 * 1024 * 1024)
+	}
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentCacheStats {
+	pub files_count: u32,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+	/// Part of the size taken by files pinned offline, which are never evicted
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub pinned_size_in_bytes: u64,
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub max_size_in_bytes: Option<u64>,
+}
+
+#[derive(Error, Debug)]
+pub enum ContentCacheError {
+	#[error("file path not found: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("file is on a location of this node")]
+	NotRemote,
+	#[error("the node owning the file isn't paired")]
+	UnknownPeer,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Remote(#[from] DelegationError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+impl From<ContentCacheError> for rspc::Error {
+	fn from(err: ContentCacheError) -> Self {
+		match err {
+			ContentCacheError::FilePathNotFound(_) => {
+				rspc::Error::with_cause(rspc::ErrorCode::NotFound, err.to_string(), err)
+			}
+			ContentCacheError::NotRemote | ContentCacheError::UnknownPeer => {
+				rspc::Error::with_cause(rspc::ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => {
+				rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, err.to_string(), err)
+			}
+		}
+	}
+}
+
+fn content_cache_dir(library: &Library) -> PathBuf {
+	library
+		.data_directory()
+		.join(CONTENT_CACHE_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+/// Where the contents of a file fetched from another node are kept
+pub fn cached_content_path(library: &Library, file_path_pub_id: Uuid) -> PathBuf {
+	content_cache_dir(library).join(file_path_pub_id.to_string())
+}
+
+/// Path of the fetched contents of a file, if they're in the cache, counting as a use of them
+pub async fn cached_content(library: &Library, file_path_pub_id: Uuid) -> Option<PathBuf> {
+	let path = cached_content_path(library, file_path_pub_id);
+	fs::metadata(&path).await.ok()?;
+
+	// The modification time is used as the last time the file was opened, to evict the least
+	// recently opened ones when the cache is over its limit
+	if let Err(e) = filetime::set_file_mtime(&path, filetime::FileTime::now()) {
+		error!(
+			"Failed to update the last use of cached file {}: {e:#?}",
+			path.display()
+		);
+	}
+
+	Some(path)
+}
+
+/// Fetches the whole contents of a file on a location of another node from it, unless they're
+/// already in the cache, returning where they're kept
+pub async fn materialize(
+	library: &Library,
+	p2p: &P2PManager,
+	file_path_id: file_path::id::Type,
+) -> Result<PathBuf, ContentCacheError> {
+	let file_path = library
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path_to_stream::select())
+		.exec()
+		.await?
+		.ok_or(ContentCacheError::FilePathNotFound(file_path_id))?;
+
+	let file_path_pub_id = Uuid::from_slice(&file_path.pub_id)
+		.map_err(|_| ContentCacheError::FilePathNotFound(file_path_id))?;
+
+	if let Some(path) = cached_content(library, file_path_pub_id).await {
+		return Ok(path);
+	}
+
+	let location = maybe_missing(&file_path.location, "file_path.location")?;
+	if location.node_id == Some(library.node_local_id) {
+		return Err(ContentCacheError::NotRemote);
+	}
+
+	let peer_id = location
+		.node
+		.as_ref()
+		.and_then(|node| node.node_peer_id.as_deref())
+		.and_then(|peer_id| PeerId::from_str(peer_id).ok())
+		.ok_or(ContentCacheError::UnknownPeer)?;
+
+	let path = cached_content_path(library, file_path_pub_id);
+	let part_path = path.with_extension("part");
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e)))?;
+	}
+
+	debug!("Fetching file '{file_path_pub_id}' from peer '{peer_id}'");
+
+	// Written aside first, so an interrupted fetch is never taken for the whole file
+	let mut file = fs::File::create(&part_path)
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?;
+	let mut written = 0;
+	loop {
+		let (file_size, _, chunk) = p2p
+			.request_file_chunk(
+				library,
+				peer_id,
+				RemoteFileRequest {
+					file_path_pub_id,
+					start: written,
+					length: MAX_REMOTE_FILE_CHUNK_LEN,
+				},
+			)
+			.await?;
+
+		file.write_all(&chunk)
+			.await
+			.map_err(|e| FileIOError::from((&part_path, e)))?;
+		written += chunk.len() as u64;
+
+		if chunk.is_empty() || written >= file_size {
+			break;
+		}
+	}
+	file.flush()
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?;
+
+	fs::rename(&part_path, &path)
+		.await
+		.map_err(|e| FileIOError::from((&part_path, e)))?;
+
+	enforce_content_cache_limit(library).await?;
+
+	invalidate_query!(library, "cache.contentStats");
+
+	Ok(path)
+}
+
+/// Fetches the contents of a file of another node in the background, if they aren't already
+/// being fetched
+pub fn materialize_in_background(
+	library: Library,
+	p2p: Arc<P2PManager>,
+	file_path_id: file_path::id::Type,
+	file_path_pub_id: Uuid,
+) {
+	if !MATERIALIZING
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.insert(file_path_pub_id)
+	{
+		return;
+	}
+
+	tokio::spawn(async move {
+		if let Err(e) = materialize(&library, &p2p, file_path_id).await {
+			error!("Failed to fetch file '{file_path_pub_id}' from its node: {e}");
+			let path = cached_content_path(&library, file_path_pub_id).with_extension("part");
+			fs::remove_file(path).await.ok();
+		}
+
+		MATERIALIZING
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.remove(&file_path_pub_id);
+	});
+}
+
+/// Ids of the pinned file paths, directories included
+pub async fn list_offline_pins(
+	library: &Library,
+) -> Result<Vec<file_path::id::Type>, ContentCacheError> {
+	Ok(library
+		.db
+		.offline_pin()
+		.find_many(vec![])
+		.select(offline_pin::select!({ file_path_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|pin| pin.file_path_id)
+		.collect())
+}
+
+/// Files under the pins, which are kept in the cache until they're unpinned
+async fn pinned_files(library: &Library) -> Result<Vec<file_path::Data>, ContentCacheError> {
+	let pinned = library
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(
+			list_offline_pins(library).await?,
+		)])
+		.exec()
+		.await?;
+
+	let mut files = Vec::new();
+	for file_path in pinned {
+		if file_path.is_dir != Some(true) {
+			files.push(file_path);
+			continue;
+		}
+
+		let location_id = maybe_missing(file_path.location_id, "file_path.location_id")?;
+		let Some(materialized_path) =
+			IsolatedFilePathData::try_from(&file_path)?.materialized_path_for_children()
+		else {
+			continue;
+		};
+
+		files.extend(
+			library
+				.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(materialized_path),
+					file_path::is_dir::equals(Some(false)),
+				])
+				.exec()
+				.await?,
+		);
+	}
+
+	Ok(files)
+}
+
+/// Keeps a file, or everything under a directory, of another node available offline, fetching
+/// what isn't in the cache yet in the background
+pub async fn pin_offline(
+	library: &Library,
+	p2p: Arc<P2PManager>,
+	file_path_id: file_path::id::Type,
+) -> Result<(), ContentCacheError> {
+	library
+		.db
+		.offline_pin()
+		.upsert(
+			offline_pin::file_path_id::equals(file_path_id),
+			offline_pin::create(file_path::id::equals(file_path_id), vec![]),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	let pinned = pinned_files(library)
+		.await?
+		.into_iter()
+		.filter_map(|file_path| Some((file_path.id, Uuid::from_slice(&file_path.pub_id).ok()?)))
+		.collect::<Vec<_>>();
+
+	invalidate_query!(library, "files.offlinePins");
+
+	let library = library.clone();
+	tokio::spawn(async move {
+		for (file_path_id, file_path_pub_id) in pinned {
+			if fs::metadata(cached_content_path(&library, file_path_pub_id))
+				.await
+				.is_ok()
+			{
+				continue;
+			}
+
+			match materialize(&library, &p2p, file_path_id).await {
+				// Files on locations of this node are already offline
+				Ok(_) | Err(ContentCacheError::NotRemote) => {}
+				Err(e) => error!("Failed to fetch pinned file '{file_path_pub_id}': {e}"),
+			}
+		}
+
+		invalidate_query!(library, "cache.contentStats");
+	});
+
+	Ok(())
+}
+
+/// Lets the files of a pin be evicted again like any other
+pub async fn unpin_offline(
+	library: &Library,
+	file_path_id: file_path::id::Type,
+) -> Result<(), ContentCacheError> {
+	library
+		.db
+		.offline_pin()
+		.delete_many(vec![offline_pin::file_path_id::equals(file_path_id)])
+		.exec()
+		.await?;
+
+	enforce_content_cache_limit(library).await?;
+
+	invalidate_query!(library, "files.offlinePins");
+	invalidate_query!(library, "cache.contentStats");
+
+	Ok(())
+}
+
+struct CacheEntry {
+	path: PathBuf,
+	size_in_bytes: u64,
+	last_used: SystemTime,
+	pinned: bool,
+}
+
+async fn cache_entries(library: &Library) -> Result<Vec<CacheEntry>, ContentCacheError> {
+	let cache_dir = content_cache_dir(library);
+	let mut read_dir = match fs::read_dir(&cache_dir).await {
+		Ok(read_dir) => read_dir,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(FileIOError::from((&cache_dir, e)).into()),
+	};
+
+	let pinned = pinned_files(library)
+		.await?
+		.into_iter()
+		.filter_map(|file_path| Uuid::from_slice(&file_path.pub_id).ok())
+		.collect::<HashSet<_>>();
+
+	let mut entries = Vec::new();
+	while let Some(entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((&cache_dir, e)))?
+	{
+		let path = entry.path();
+		// Files being fetched aren't part of the cache yet
+		let Some(file_path_pub_id) = path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.and_then(|name| Uuid::from_str(name).ok())
+		else {
+			continue;
+		};
+
+		let metadata = entry
+			.metadata()
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+
+		entries.push(CacheEntry {
+			size_in_bytes: metadata.len(),
+			last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+			pinned: pinned.contains(&file_path_pub_id),
+			path,
+		});
+	}
+
+	Ok(entries)
+}
+
+pub async fn content_cache_stats(
+	library: &Library,
+) -> Result<ContentCacheStats, ContentCacheError> {
+	let entries = cache_entries(library).await?;
+
+	Ok(ContentCacheStats {
+		files_count: entries.len() as u32,
+		size_in_bytes: entries.iter().map(|entry| entry.size_in_bytes).sum(),
+		pinned_size_in_bytes: entries
+			.iter()
+			.filter(|entry| entry.pinned)
+			.map(|entry| entry.size_in_bytes)
+			.sum(),
+		max_size_in_bytes: library.config.content_cache.max_size_in_bytes(),
+	})
+}
+
+/// Removes the fetched files that aren't pinned offline, returning how many bytes were freed
+pub async fn clear_content_cache(library: &Library) -> Result<u64, ContentCacheError> {
+	let mut freed = 0;
+	for entry in cache_entries(library).await? {
+		if entry.pinned {
+			continue;
+		}
+
+		fs::remove_file(&entry.path)
+			.await
+			.map_err(|e| FileIOError::from((&entry.path, e)))?;
+		freed += entry.size_in_bytes;
+	}
+
+	info!(
+		"Cleared {freed} bytes of fetched files of library {}",
+		library.id
+	);
+
+	Ok(freed)
+}
+
+/// Evicts the least recently opened files that aren't pinned offline if the cache is over the
+/// limit set in the library's settings, returning how many bytes were freed
+pub async fn enforce_content_cache_limit(library: &Library) -> Result<u64, ContentCacheError> {
+	let Some(max_size) = library.config.content_cache.max_size_in_bytes() else {
+		return Ok(0);
+	};
+
+	let mut entries = cache_entries(library).await?;
+
+	let mut size = entries.iter().map(|entry| entry.size_in_bytes).sum::<u64>();
+	if size <= max_size {
+		return Ok(0);
+	}
+
+	let target_size = (max_size as f64 * EVICTION_TARGET_RATIO) as u64;
+	entries.sort_unstable_by_key(|entry| entry.last_used);
+
+	let mut freed = 0;
+	for entry in entries.into_iter().filter(|entry| !entry.pinned) {
+		if size <= target_size {
+			break;
+		}
+
+		fs::remove_file(&entry.path)
+			.await
+			.map_err(|e| FileIOError::from((&entry.path, e)))?;
+		size -= entry.size_in_bytes;
+		freed += entry.size_in_bytes;
+	}
+
+	debug!(
+		"Evicted {freed} bytes of fetched files of library {}, over its {max_size} bytes limit",
+		library.id
+	);
+
+	Ok(freed)
+}
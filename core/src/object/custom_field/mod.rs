@@ -0,0 +1,294 @@
+use crate::{
+	library::Library,
+	prisma::{custom_field, custom_field_value, object},
+	sync,
+	util::db::maybe_missing,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use int_enum::IntEnum;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The kind of values a custom field accepts. Values are always stored as text.
+#[repr(i32)]
+#[derive(IntEnum, Serialize, Deserialize, Type, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CustomFieldKind {
+	Text = 0,
+	Number = 1,
+	/// RFC 3339 date
+	Date = 2,
+	/// One of the field's options
+	Enum = 3,
+}
+
+#[derive(Error, Debug)]
+pub enum CustomFieldError {
+	#[error("custom field not found <id='{0}'>")]
+	IdNotFound(custom_field::id::Type),
+	#[error("object not found <id='{0}'>")]
+	ObjectNotFound(object::id::Type),
+	#[error("invalid custom field kind integer: {0}")]
+	InvalidKindInt(i32),
+	#[error("value '{value}' is not valid for a field of kind {kind:?}")]
+	InvalidValue {
+		kind: CustomFieldKind,
+		value: String,
+	},
+	#[error("enum fields need at least one option")]
+	MissingOptions,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to (de)serialize field options: {0}")]
+	Options(#[from] serde_json::Error),
+	#[error("missing-field: {0}")]
+	MissingField(#[from] crate::util::db::MissingFieldError),
+}
+
+impl From<CustomFieldError> for rspc::Error {
+	fn from(err: CustomFieldError) -> Self {
+		match err {
+			CustomFieldError::IdNotFound(_) | CustomFieldError::ObjectNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			CustomFieldError::InvalidValue { .. } | CustomFieldError::MissingOptions => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+impl CustomFieldKind {
+	/// Checks that a value can be interpreted according to this kind
+	pub fn validate(self, value: &str, options: &[String]) -> Result<(), CustomFieldError> {
+		let is_valid = match self {
+			Self::Text => true,
+			Self::Number => value.parse::<f64>().is_ok(),
+			Self::Date => DateTime::parse_from_rfc3339(value).is_ok(),
+			Self::Enum => options.iter().any(|option| option == value),
+		};
+
+		if is_valid {
+			Ok(())
+		} else {
+			Err(CustomFieldError::InvalidValue {
+				kind: self,
+				value: value.to_string(),
+			})
+		}
+	}
+}
+
+/// Returns the kind and enum options of a field as stored in the database
+pub fn parse_field(
+	field: &custom_field::Data,
+) -> Result<(CustomFieldKind, Vec<String>), CustomFieldError> {
+	let kind = maybe_missing(field.kind, "custom_field.kind")?;
+	let kind =
+		CustomFieldKind::from_int(kind).map_err(|_| CustomFieldError::InvalidKindInt(kind))?;
+
+	let options = field
+		.options
+		.as_ref()
+		.map(|options| serde_json::from_slice(options))
+		.transpose()?
+		.unwrap_or_default();
+
+	Ok((kind, options))
+}
+
+#[derive(Type, Deserialize, Clone)]
+pub struct CustomFieldCreateArgs {
+	pub name: String,
+	pub kind: CustomFieldKind,
+	#[serde(default)]
+	pub options: Vec<String>,
+}
+
+impl CustomFieldCreateArgs {
+	pub async fn exec(
+		self,
+		Library { db, sync, .. }: &Library,
+	) -> Result<custom_field::Data, CustomFieldError> {
+		if self.kind == CustomFieldKind::Enum && self.options.is_empty() {
+			return Err(CustomFieldError::MissingOptions);
+		}
+
+		let pub_id = Uuid::new_v4().as_bytes().to_vec();
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+		let options = serde_json::to_vec(&self.options)?;
+
+		Ok(sync
+			.write_op(
+				db,
+				sync.unique_shared_create(
+					sync::custom_field::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					[
+						(custom_field::name::NAME, json!(&self.name)),
+						(custom_field::kind::NAME, json!(self.kind as i32)),
+						(custom_field::options::NAME, json!(&options)),
+						(
+							custom_field::date_created::NAME,
+							json!(&date_created.to_rfc3339()),
+						),
+					],
+				),
+				db.custom_field().create(
+					pub_id,
+					vec![
+						custom_field::name::set(Some(self.name)),
+						custom_field::kind::set(Some(self.kind as i32)),
+						custom_field::options::set(Some(options)),
+						custom_field::date_created::set(Some(date_created)),
+					],
+				),
+			)
+			.await?)
+	}
+}
+
+#[derive(Type, Deserialize, Clone)]
+pub struct CustomFieldSetValueArgs {
+	pub field_id: custom_field::id::Type,
+	pub object_id: object::id::Type,
+	pub value: Option<String>,
+}
+
+impl CustomFieldSetValueArgs {
+	pub async fn exec(self, Library { db, sync, .. }: &Library) -> Result<(), CustomFieldError> {
+		let field = db
+			.custom_field()
+			.find_unique(custom_field::id::equals(self.field_id))
+			.exec()
+			.await?
+			.ok_or(CustomFieldError::IdNotFound(self.field_id))?;
+
+		if let Some(value) = &self.value {
+			let (kind, options) = parse_field(&field)?;
+			kind.validate(value, &options)?;
+		}
+
+		let object = db
+			.object()
+			.find_unique(object::id::equals(self.object_id))
+			.select(object::select!({ pub_id }))
+			.exec()
+			.await?
+			.ok_or(CustomFieldError::ObjectNotFound(self.object_id))?;
+
+		let date_modified: DateTime<FixedOffset> = Utc::now().into();
+
+		let existing = db
+			.custom_field_value()
+			.find_unique(custom_field_value::field_id_object_id(
+				self.field_id,
+				self.object_id,
+			))
+			.select(custom_field_value::select!({ pub_id }))
+			.exec()
+			.await?;
+
+		if let Some(existing) = existing {
+			sync.write_op(
+				db,
+				sync.shared_update(
+					sync::custom_field_value::SyncId {
+						pub_id: existing.pub_id.clone(),
+					},
+					custom_field_value::value::NAME,
+					json!(&self.value),
+				),
+				db.custom_field_value().update(
+					custom_field_value::pub_id::equals(existing.pub_id),
+					vec![
+						custom_field_value::value::set(self.value),
+						custom_field_value::date_modified::set(Some(date_modified)),
+					],
+				),
+			)
+			.await?;
+		} else {
+			let pub_id = Uuid::new_v4().as_bytes().to_vec();
+
+			sync.write_op(
+				db,
+				sync.unique_shared_create(
+					sync::custom_field_value::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					[
+						(custom_field_value::value::NAME, json!(&self.value)),
+						(
+							custom_field_value::field::NAME,
+							json!({ "pub_id": field.pub_id }),
+						),
+						(
+							custom_field_value::object::NAME,
+							json!({ "pub_id": object.pub_id }),
+						),
+						(
+							custom_field_value::date_modified::NAME,
+							json!(&date_modified.to_rfc3339()),
+						),
+					],
+				),
+				db.custom_field_value().create(
+					pub_id,
+					vec![
+						custom_field_value::value::set(self.value),
+						custom_field_value::field::connect(custom_field::id::equals(field.id)),
+						custom_field_value::object::connect(object::id::equals(self.object_id)),
+						custom_field_value::date_modified::set(Some(date_modified)),
+					],
+				),
+			)
+			.await?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Search filter matching objects whose value for a custom field equals `value`
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomFieldFilter {
+	pub field_id: custom_field::id::Type,
+	pub value: String,
+}
+
+impl CustomFieldFilter {
+	pub fn to_where_param(self) -> object::WhereParam {
+		object::custom_field_values::some(vec![
+			custom_field_value::field_id::equals(Some(self.field_id)),
+			custom_field_value::value::equals(Some(self.value)),
+		])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn validate_values_by_kind() {
+		let options = vec!["Draft".to_string(), "Sent".to_string()];
+
+		assert!(CustomFieldKind::Text.validate("anything", &[]).is_ok());
+		assert!(CustomFieldKind::Number.validate("42.5", &[]).is_ok());
+		assert!(CustomFieldKind::Number.validate("INV-42", &[]).is_err());
+		assert!(CustomFieldKind::Date
+			.validate("2023-06-24T12:00:00+00:00", &[])
+			.is_ok());
+		assert!(CustomFieldKind::Date.validate("yesterday", &[]).is_err());
+		assert!(CustomFieldKind::Enum.validate("Sent", &options).is_ok());
+		assert!(CustomFieldKind::Enum.validate("Paid", &options).is_err());
+	}
+}
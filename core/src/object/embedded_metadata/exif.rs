@@ -0,0 +1,411 @@
+//! Edits the first IFD of the TIFF structure of an EXIF block.
+//!
+//! Offsets of a TIFF structure are relative to its header, so the block is never rewritten:
+//! a new IFD0 with the changed tags is appended instead, followed by their values, and the header
+//! is pointed to it. The other entries keep pointing to their values, wherever they are. When the
+//! end of the block only holds an IFD0 appended like this, it's replaced instead of appended to
+//! again, so writing a file many times doesn't grow it.
+//!
+//! The camera and capture date are read from IFD0 and the EXIF IFD it points to.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDateTime;
+
+use super::{CaptureInfo, EmbeddedMetadata, EmbeddedMetadataError};
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_RATING: u16 = 0x4746;
+const TAG_RATING_PERCENT: u16 = 0x4749;
+const TAG_XP_TITLE: u16 = 0x9c9b;
+const TAG_XP_KEYWORDS: u16 = 0x9c9e;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+
+/// Format of the dates of EXIF, in the local time of the camera
+const DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+	LittleEndian,
+	BigEndian,
+}
+
+impl ByteOrder {
+	fn u16(self, bytes: &[u8]) -> u16 {
+		let bytes = [bytes[0], bytes[1]];
+		match self {
+			Self::LittleEndian => u16::from_le_bytes(bytes),
+			Self::BigEndian => u16::from_be_bytes(bytes),
+		}
+	}
+
+	fn u32(self, bytes: &[u8]) -> u32 {
+		let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		match self {
+			Self::LittleEndian => u32::from_le_bytes(bytes),
+			Self::BigEndian => u32::from_be_bytes(bytes),
+		}
+	}
+
+	fn put_u16(self, out: &mut Vec<u8>, value: u16) {
+		out.extend_from_slice(&match self {
+			Self::LittleEndian => value.to_le_bytes(),
+			Self::BigEndian => value.to_be_bytes(),
+		});
+	}
+
+	fn put_u32(self, out: &mut Vec<u8>, value: u32) {
+		out.extend_from_slice(&match self {
+			Self::LittleEndian => value.to_le_bytes(),
+			Self::BigEndian => value.to_be_bytes(),
+		});
+	}
+}
+
+/// An entry of an IFD, with its value field as it is in the file
+struct Entry {
+	field_type: u16,
+	count: u32,
+	value: [u8; 4],
+}
+
+impl Entry {
+	fn size(&self) -> Option<usize> {
+		let type_size = match self.field_type {
+			1 | 2 | 6 | 7 => 1,
+			3 | 8 => 2,
+			4 | 9 | 11 | 13 => 4,
+			5 | 10 | 12 => 8,
+			_ => return None,
+		};
+
+		(self.count as usize).checked_mul(type_size)
+	}
+}
+
+/// Byte order of a TIFF structure, from its header
+fn byte_order(tiff: &[u8]) -> Option<ByteOrder> {
+	let order = match tiff.get(..2)? {
+		b"II" => ByteOrder::LittleEndian,
+		b"MM" => ByteOrder::BigEndian,
+		_ => return None,
+	};
+
+	(tiff.len() >= 8 && order.u16(&tiff[2..]) == 42).then_some(order)
+}
+
+/// Entries of the IFD at `offset`, by tag
+fn read_ifd(tiff: &[u8], order: ByteOrder, offset: usize) -> Option<BTreeMap<u16, Entry>> {
+	let count = order.u16(tiff.get(offset..offset + 2)?) as usize;
+	let raw_entries = tiff.get(offset + 2..offset + 2 + count * 12)?;
+
+	Some(
+		raw_entries
+			.chunks_exact(12)
+			.map(|raw| {
+				(
+					order.u16(raw),
+					Entry {
+						field_type: order.u16(&raw[2..]),
+						count: order.u32(&raw[4..]),
+						value: [raw[8], raw[9], raw[10], raw[11]],
+					},
+				)
+			})
+			.collect(),
+	)
+}
+
+/// Value of an ASCII entry, without its terminating null and padding
+fn ascii(tiff: &[u8], order: ByteOrder, entry: &Entry) -> Option<String> {
+	if entry.field_type != TYPE_ASCII {
+		return None;
+	}
+
+	let len = entry.count as usize;
+	let bytes = if len <= 4 {
+		entry.value.get(..len)?
+	} else {
+		let offset = order.u32(&entry.value) as usize;
+		tiff.get(offset..offset + len)?
+	};
+
+	let value = String::from_utf8_lossy(bytes)
+		.trim_end_matches('\0')
+		.trim()
+		.to_string();
+
+	(!value.is_empty()).then_some(value)
+}
+
+/// Reads the camera and the capture date of a photo from its TIFF structure. The original date
+/// is preferred to the one of IFD0, which editors update when saving.
+pub fn read(tiff: &[u8]) -> Option<CaptureInfo> {
+	let order = byte_order(tiff)?;
+	let ifd0 = read_ifd(tiff, order, order.u32(&tiff[4..]) as usize)?;
+
+	let exif_ifd = ifd0
+		.get(&TAG_EXIF_IFD)
+		.filter(|entry| entry.field_type == TYPE_LONG)
+		.and_then(|entry| read_ifd(tiff, order, order.u32(&entry.value) as usize))
+		.unwrap_or_default();
+
+	let date = |entry: Option<&Entry>| {
+		entry
+			.and_then(|entry| ascii(tiff, order, entry))
+			.and_then(|date| NaiveDateTime::parse_from_str(&date, DATE_FORMAT).ok())
+	};
+
+	Some(CaptureInfo {
+		date_taken: date(exif_ifd.get(&TAG_DATE_TIME_ORIGINAL)).or(date(ifd0.get(&TAG_DATE_TIME))),
+		make: ifd0
+			.get(&TAG_MAKE)
+			.and_then(|entry| ascii(tiff, order, entry)),
+		model: ifd0
+			.get(&TAG_MODEL)
+			.and_then(|entry| ascii(tiff, order, entry)),
+	})
+}
+
+/// A TIFF structure with just a header and an empty IFD0, for files without EXIF
+pub fn empty_tiff() -> Vec<u8> {
+	vec![b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0]
+}
+
+/// Writes the rating, title and keywords of `metadata` in the IFD0 of `tiff`, as the tags
+/// Windows reads them from
+pub fn write(tiff: &[u8], metadata: &EmbeddedMetadata) -> Result<Vec<u8>, EmbeddedMetadataError> {
+	let invalid = || EmbeddedMetadataError::InvalidExif;
+
+	let order = match tiff.get(..2) {
+		Some(b"II") => ByteOrder::LittleEndian,
+		Some(b"MM") => ByteOrder::BigEndian,
+		_ => return Err(invalid()),
+	};
+
+	if tiff.len() < 8 || order.u16(&tiff[2..]) != 42 {
+		return Err(invalid());
+	}
+
+	let ifd_offset = order.u32(&tiff[4..]) as usize;
+	let count = tiff
+		.get(ifd_offset..ifd_offset + 2)
+		.map(|bytes| order.u16(bytes) as usize)
+		.ok_or_else(invalid)?;
+	let ifd_end = ifd_offset + 2 + count * 12 + 4;
+	let ifd = tiff.get(ifd_offset..ifd_end).ok_or_else(invalid)?;
+
+	let mut entries = BTreeMap::new();
+	for raw in ifd[2..2 + count * 12].chunks_exact(12) {
+		entries.insert(
+			order.u16(raw),
+			Entry {
+				field_type: order.u16(&raw[2..]),
+				count: order.u32(&raw[4..]),
+				value: [raw[8], raw[9], raw[10], raw[11]],
+			},
+		);
+	}
+	let next_ifd = order.u32(&ifd[2 + count * 12..]);
+
+	let mut values = BTreeMap::new();
+	if let Some(rating) = metadata.rating {
+		values.insert(TAG_RATING, (TYPE_SHORT, short(order, rating as u16)));
+		values.insert(
+			TAG_RATING_PERCENT,
+			(TYPE_SHORT, short(order, rating_percent(rating))),
+		);
+	}
+	if let Some(title) = &metadata.title {
+		values.insert(TAG_XP_TITLE, (TYPE_BYTE, utf16(title)));
+	}
+	if let Some(keywords) = &metadata.keywords {
+		values.insert(TAG_XP_KEYWORDS, (TYPE_BYTE, utf16(&keywords.join(";"))));
+	}
+
+	let mut out =
+		tiff[..reusable_end(tiff, order, ifd_offset, ifd_end, &entries, &values)].to_vec();
+
+	// Empty values remove their tag, like clearing a title
+	for (tag, (_, data)) in &values {
+		if data.is_empty() {
+			entries.remove(tag);
+		}
+	}
+	values.retain(|_, (_, data)| !data.is_empty());
+
+	let tags = entries
+		.keys()
+		.chain(values.keys())
+		.copied()
+		.collect::<BTreeSet<_>>();
+
+	// IFDs start on a word boundary
+	if out.len() % 2 == 1 {
+		out.push(0);
+	}
+	let new_ifd_offset = out.len();
+	let values_offset = new_ifd_offset + 2 + tags.len() * 12 + 4;
+
+	let mut ifd = vec![];
+	let mut out_of_line = vec![];
+
+	order.put_u16(&mut ifd, tags.len() as u16);
+	for tag in tags {
+		order.put_u16(&mut ifd, tag);
+
+		match values.get(&tag) {
+			Some((field_type, data)) => {
+				order.put_u16(&mut ifd, *field_type);
+				let type_size = if *field_type == TYPE_SHORT { 2 } else { 1 };
+				order.put_u32(&mut ifd, (data.len() / type_size) as u32);
+
+				if data.len() <= 4 {
+					let mut value = data.clone();
+					value.resize(4, 0);
+					ifd.extend_from_slice(&value);
+				} else {
+					if out_of_line.len() % 2 == 1 {
+						out_of_line.push(0);
+					}
+					order.put_u32(&mut ifd, (values_offset + out_of_line.len()) as u32);
+					out_of_line.extend_from_slice(data);
+				}
+			}
+			None => {
+				let entry = &entries[&tag];
+				order.put_u16(&mut ifd, entry.field_type);
+				order.put_u32(&mut ifd, entry.count);
+				ifd.extend_from_slice(&entry.value);
+			}
+		}
+	}
+	order.put_u32(&mut ifd, next_ifd);
+
+	out.extend_from_slice(&ifd);
+	out.extend_from_slice(&out_of_line);
+
+	let mut header = vec![];
+	order.put_u32(&mut header, new_ifd_offset as u32);
+	out[4..8].copy_from_slice(&header);
+
+	Ok(out)
+}
+
+/// End of the data of `tiff` which has to be kept: all of it, unless it ends with an IFD0 and
+/// values of tags being replaced that nothing else points to, which were appended by a
+/// previous write
+fn reusable_end(
+	tiff: &[u8],
+	order: ByteOrder,
+	ifd_offset: usize,
+	ifd_end: usize,
+	entries: &BTreeMap<u16, Entry>,
+	values: &BTreeMap<u16, (u16, Vec<u8>)>,
+) -> usize {
+	let mut tail = vec![];
+	for (tag, entry) in entries {
+		let Some(size) = entry.size() else {
+			return tiff.len();
+		};
+		if size <= 4 {
+			continue;
+		}
+
+		let offset = order.u32(&entry.value) as usize;
+		if offset >= ifd_offset {
+			// Values of the kept tags must stay where they are
+			if !values.contains_key(tag) {
+				return tiff.len();
+			}
+			tail.push((offset, size));
+		}
+	}
+	tail.sort_unstable();
+
+	let mut end = ifd_end;
+	for (offset, size) in tail {
+		if offset != end + end % 2 && offset != end {
+			return tiff.len();
+		}
+		end = offset + size;
+	}
+
+	if end == tiff.len() {
+		ifd_offset
+	} else {
+		tiff.len()
+	}
+}
+
+fn short(order: ByteOrder, value: u16) -> Vec<u8> {
+	let mut bytes = vec![];
+	order.put_u16(&mut bytes, value);
+	bytes
+}
+
+/// What Windows writes along the rating, from 0 to 99
+fn rating_percent(rating: i32) -> u16 {
+	match rating {
+		1 => 1,
+		2 => 25,
+		3 => 50,
+		4 => 75,
+		5 => 99,
+		_ => 0,
+	}
+}
+
+/// The `XP*` tags are UTF-16LE whatever the byte order of the file, and null terminated. Empty
+/// strings give no bytes, to remove the tag.
+fn utf16(value: &str) -> Vec<u8> {
+	if value.is_empty() {
+		return vec![];
+	}
+
+	value
+		.encode_utf16()
+		.chain([0])
+		.flat_map(u16::to_le_bytes)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reads_camera_and_capture_date() {
+		let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+		// IFD0, at 8: Make, out of line at 38, and the EXIF IFD, at 44
+		tiff.extend_from_slice(&[2, 0]);
+		tiff.extend_from_slice(&[0x0f, 0x01, 2, 0, 6, 0, 0, 0, 38, 0, 0, 0]);
+		tiff.extend_from_slice(&[0x69, 0x87, 4, 0, 1, 0, 0, 0, 44, 0, 0, 0]);
+		tiff.extend_from_slice(&[0, 0, 0, 0]);
+		tiff.extend_from_slice(b"Canon\0");
+		// EXIF IFD, at 44: DateTimeOriginal, out of line at 62
+		tiff.extend_from_slice(&[1, 0]);
+		tiff.extend_from_slice(&[0x03, 0x90, 2, 0, 20, 0, 0, 0, 62, 0, 0, 0]);
+		tiff.extend_from_slice(&[0, 0, 0, 0]);
+		tiff.extend_from_slice(b"2023:07:14 09:30:00\0");
+
+		let info = read(&tiff).unwrap();
+
+		assert_eq!(info.make.as_deref(), Some("Canon"));
+		assert_eq!(info.model, None);
+		assert_eq!(
+			info.date_taken,
+			NaiveDateTime::parse_from_str("2023:07:14 09:30:00", DATE_FORMAT).ok()
+		);
+		assert!(read(&empty_tiff()).unwrap().date_taken.is_none());
+		assert!(read(b"not a tiff").is_none());
+	}
+}
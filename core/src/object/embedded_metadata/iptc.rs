@@ -0,0 +1,244 @@
+//! Edits the IPTC-IIM records of the Photoshop image resources of a JPEG, in its APP13 segment.
+
+use super::{EmbeddedMetadata, EmbeddedMetadataError};
+
+pub const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+
+const RESOURCE_SIGNATURE: &[u8] = b"8BIM";
+const RESOURCE_IPTC: u16 = 0x0404;
+/// MD5 of the IPTC records, which apps compare to find out the records were edited by others
+const RESOURCE_IPTC_DIGEST: u16 = 0x0425;
+
+const TAG_MARKER: u8 = 0x1c;
+const RECORD_ENVELOPE: u8 = 1;
+const RECORD_APPLICATION: u8 = 2;
+const DATASET_CODED_CHARACTER_SET: u8 = 90;
+const DATASET_RECORD_VERSION: u8 = 0;
+const DATASET_OBJECT_NAME: u8 = 5;
+const DATASET_KEYWORDS: u8 = 25;
+
+/// ISO 2022 escape sequence declaring the datasets are UTF-8
+const UTF8_CHARACTER_SET: &[u8] = b"\x1b%G";
+
+/// Longest keywords and titles the standard allows, in bytes
+const MAX_KEYWORD_LEN: usize = 64;
+const MAX_OBJECT_NAME_LEN: usize = 64;
+
+struct Resource<'a> {
+	id: u16,
+	/// Pascal string, padded to an even size
+	name: &'a [u8],
+	data: Vec<u8>,
+}
+
+struct Dataset {
+	record: u8,
+	number: u8,
+	data: Vec<u8>,
+}
+
+/// Writes the title and keywords of `metadata` in the IPTC records of the APP13 segment
+/// `payload`, which is a new segment if `None`
+pub fn write(
+	payload: Option<&[u8]>,
+	metadata: &EmbeddedMetadata,
+) -> Result<Vec<u8>, EmbeddedMetadataError> {
+	let mut resources = match payload {
+		Some(payload) => parse_resources(
+			payload
+				.strip_prefix(PHOTOSHOP_SIGNATURE)
+				.ok_or(EmbeddedMetadataError::InvalidIptc)?,
+		)?,
+		None => vec![],
+	};
+
+	let datasets = resources
+		.iter()
+		.find(|resource| resource.id == RESOURCE_IPTC)
+		.map(|resource| parse_datasets(&resource.data))
+		.transpose()?
+		.unwrap_or_default();
+
+	let iptc = write_datasets(datasets, metadata);
+
+	resources.retain(|resource| resource.id != RESOURCE_IPTC_DIGEST);
+	match resources
+		.iter_mut()
+		.find(|resource| resource.id == RESOURCE_IPTC)
+	{
+		Some(resource) => resource.data = iptc,
+		None => resources.push(Resource {
+			id: RESOURCE_IPTC,
+			name: &[0, 0],
+			data: iptc,
+		}),
+	}
+
+	let mut out = PHOTOSHOP_SIGNATURE.to_vec();
+	for resource in resources {
+		out.extend_from_slice(RESOURCE_SIGNATURE);
+		out.extend_from_slice(&resource.id.to_be_bytes());
+		out.extend_from_slice(resource.name);
+		out.extend_from_slice(&(resource.data.len() as u32).to_be_bytes());
+		out.extend_from_slice(&resource.data);
+		if resource.data.len() % 2 == 1 {
+			out.push(0);
+		}
+	}
+
+	Ok(out)
+}
+
+fn parse_resources(mut bytes: &[u8]) -> Result<Vec<Resource<'_>>, EmbeddedMetadataError> {
+	let invalid = || EmbeddedMetadataError::InvalidIptc;
+
+	let mut resources = vec![];
+	while !bytes.is_empty() {
+		let rest = bytes.strip_prefix(RESOURCE_SIGNATURE).ok_or_else(invalid)?;
+		let id = u16::from_be_bytes(
+			rest.get(..2)
+				.ok_or_else(invalid)?
+				.try_into()
+				.expect("2 bytes"),
+		);
+
+		let name_len = *rest.get(2).ok_or_else(invalid)? as usize;
+		let name_size = (name_len + 1) + (name_len + 1) % 2;
+		let name = rest.get(2..2 + name_size).ok_or_else(invalid)?;
+
+		let size_start = 2 + name_size;
+		let size = u32::from_be_bytes(
+			rest.get(size_start..size_start + 4)
+				.ok_or_else(invalid)?
+				.try_into()
+				.expect("4 bytes"),
+		) as usize;
+
+		let data_start = size_start + 4;
+		let data = rest
+			.get(data_start..data_start + size)
+			.ok_or_else(invalid)?;
+
+		resources.push(Resource {
+			id,
+			name,
+			data: data.to_vec(),
+		});
+
+		let next = (data_start + size + size % 2).min(rest.len());
+		bytes = &rest[next..];
+	}
+
+	Ok(resources)
+}
+
+fn parse_datasets(mut bytes: &[u8]) -> Result<Vec<Dataset>, EmbeddedMetadataError> {
+	let invalid = || EmbeddedMetadataError::InvalidIptc;
+
+	let mut datasets = vec![];
+	// Resources are padded, so the records can be followed by a null byte
+	while bytes.first() == Some(&TAG_MARKER) {
+		let header = bytes.get(..5).ok_or_else(invalid)?;
+		let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+
+		let (data_start, len) = if len & 0x8000 == 0 {
+			(5, len)
+		} else {
+			// Extended datasets give the number of bytes of their length instead
+			let len_size = len & 0x7fff;
+			let len_bytes = bytes.get(5..5 + len_size).ok_or_else(invalid)?;
+			let len = len_bytes
+				.iter()
+				.try_fold(0usize, |len, byte| {
+					len.checked_mul(256).map(|len| len + *byte as usize)
+				})
+				.ok_or_else(invalid)?;
+			(5 + len_size, len)
+		};
+
+		let data = bytes
+			.get(data_start..data_start + len)
+			.ok_or_else(invalid)?;
+
+		datasets.push(Dataset {
+			record: header[1],
+			number: header[2],
+			data: data.to_vec(),
+		});
+
+		bytes = &bytes[data_start + len..];
+	}
+
+	Ok(datasets)
+}
+
+fn write_datasets(mut datasets: Vec<Dataset>, metadata: &EmbeddedMetadata) -> Vec<u8> {
+	let is_application = |dataset: &Dataset, number| {
+		dataset.record == RECORD_APPLICATION && dataset.number == number
+	};
+
+	if metadata.title.is_some() {
+		datasets.retain(|dataset| !is_application(dataset, DATASET_OBJECT_NAME));
+	}
+	if metadata.keywords.is_some() {
+		datasets.retain(|dataset| !is_application(dataset, DATASET_KEYWORDS));
+	}
+
+	// Older records may be in another character set, but they're mostly ASCII
+	datasets.retain(|dataset| {
+		!(dataset.record == RECORD_ENVELOPE && dataset.number == DATASET_CODED_CHARACTER_SET)
+	});
+	datasets.push(Dataset {
+		record: RECORD_ENVELOPE,
+		number: DATASET_CODED_CHARACTER_SET,
+		data: UTF8_CHARACTER_SET.to_vec(),
+	});
+
+	if !datasets
+		.iter()
+		.any(|dataset| is_application(dataset, DATASET_RECORD_VERSION))
+	{
+		datasets.push(Dataset {
+			record: RECORD_APPLICATION,
+			number: DATASET_RECORD_VERSION,
+			data: vec![0, 4],
+		});
+	}
+
+	if let Some(title) = metadata.title.as_deref().filter(|title| !title.is_empty()) {
+		datasets.push(Dataset {
+			record: RECORD_APPLICATION,
+			number: DATASET_OBJECT_NAME,
+			data: truncate(title, MAX_OBJECT_NAME_LEN).as_bytes().to_vec(),
+		});
+	}
+
+	for keyword in metadata.keywords.iter().flatten() {
+		datasets.push(Dataset {
+			record: RECORD_APPLICATION,
+			number: DATASET_KEYWORDS,
+			data: truncate(keyword, MAX_KEYWORD_LEN).as_bytes().to_vec(),
+		});
+	}
+
+	// Records are in ascending order, and the version is the first dataset of its record
+	datasets.sort_by_key(|dataset| (dataset.record, dataset.number != DATASET_RECORD_VERSION));
+
+	let mut out = vec![];
+	for dataset in datasets {
+		out.extend_from_slice(&[TAG_MARKER, dataset.record, dataset.number]);
+		out.extend_from_slice(&(dataset.data.len().min(0x7fff) as u16).to_be_bytes());
+		out.extend_from_slice(&dataset.data[..dataset.data.len().min(0x7fff)]);
+	}
+
+	out
+}
+
+fn truncate(value: &str, max_len: usize) -> &str {
+	let mut end = value.len().min(max_len);
+	while !value.is_char_boundary(end) {
+		end -= 1;
+	}
+
+	&value[..end]
+}
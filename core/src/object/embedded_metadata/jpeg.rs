@@ -0,0 +1,237 @@
+//! Splits JPEG files into their segments, to replace the EXIF and IPTC ones and keep the others,
+//! and the image data, as they are.
+
+use std::borrow::Cow;
+
+use super::{exif, iptc, EmbeddedMetadata, EmbeddedMetadataError};
+
+const MARKER_PREFIX: u8 = 0xff;
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+/// Start of scan, followed by the image data until the end of the file
+const SOS: u8 = 0xda;
+const APP0: u8 = 0xe0;
+const APP1: u8 = 0xe1;
+const APP13: u8 = 0xed;
+
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+
+/// Payloads can't be longer than the 16-bit length of their segment, which counts itself
+const MAX_PAYLOAD_LEN: usize = u16::MAX as usize - 2;
+
+struct Segment<'a> {
+	marker: u8,
+	/// `None` for the markers standing alone, without a length
+	payload: Option<Cow<'a, [u8]>>,
+}
+
+/// TIFF structure of the EXIF segment of a JPEG file, if it has one. `jpeg` can be just the
+/// beginning of the file, as long as it holds the segments before the image data.
+pub fn exif_block(jpeg: &[u8]) -> Option<&[u8]> {
+	if !jpeg.starts_with(&[MARKER_PREFIX, SOI]) {
+		return None;
+	}
+
+	let mut pos = 2;
+	loop {
+		while jpeg.get(pos) == Some(&MARKER_PREFIX) {
+			pos += 1;
+		}
+
+		let marker = *jpeg.get(pos)?;
+		pos += 1;
+
+		if marker == SOS || marker == EOI {
+			return None;
+		}
+		if (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+			continue;
+		}
+
+		let len = jpeg
+			.get(pos..pos + 2)
+			.map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+			.filter(|len| *len >= 2)?;
+		let payload = jpeg.get(pos + 2..pos + len)?;
+
+		if marker == APP1 && payload.starts_with(EXIF_SIGNATURE) {
+			return Some(&payload[EXIF_SIGNATURE.len()..]);
+		}
+		pos += len;
+	}
+}
+
+pub fn write(jpeg: &[u8], metadata: &EmbeddedMetadata) -> Result<Vec<u8>, EmbeddedMetadataError> {
+	let not_jpeg = || EmbeddedMetadataError::NotJpeg;
+
+	if !jpeg.starts_with(&[MARKER_PREFIX, SOI]) {
+		return Err(not_jpeg());
+	}
+
+	let mut segments = vec![];
+	let mut pos = 2;
+	let image_data = loop {
+		if jpeg.get(pos) != Some(&MARKER_PREFIX) {
+			return Err(not_jpeg());
+		}
+		// Markers can be preceded by any number of fill bytes
+		while jpeg.get(pos) == Some(&MARKER_PREFIX) {
+			pos += 1;
+		}
+
+		let marker = *jpeg.get(pos).ok_or_else(not_jpeg)?;
+		pos += 1;
+
+		if marker == SOS || marker == EOI {
+			break &jpeg[pos - 2..];
+		}
+
+		if (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+			segments.push(Segment {
+				marker,
+				payload: None,
+			});
+			continue;
+		}
+
+		let len = jpeg
+			.get(pos..pos + 2)
+			.map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+			.filter(|len| *len >= 2)
+			.ok_or_else(not_jpeg)?;
+		let payload = jpeg.get(pos + 2..pos + len).ok_or_else(not_jpeg)?;
+
+		segments.push(Segment {
+			marker,
+			payload: Some(payload.into()),
+		});
+		pos += len;
+	};
+
+	let exif_index = segments.iter().position(|segment| {
+		segment.marker == APP1
+			&& matches!(&segment.payload, Some(payload) if payload.starts_with(EXIF_SIGNATURE))
+	});
+
+	let tiff = exif::write(
+		&exif_index
+			.and_then(|i| segments[i].payload.as_deref())
+			.map_or_else(exif::empty_tiff, |payload| {
+				payload[EXIF_SIGNATURE.len()..].to_vec()
+			}),
+		metadata,
+	)?;
+	let exif_segment = Segment {
+		marker: APP1,
+		payload: Some([EXIF_SIGNATURE, tiff.as_slice()].concat().into()),
+	};
+
+	let exif_index = match exif_index {
+		Some(i) => {
+			segments[i] = exif_segment;
+			i
+		}
+		None => {
+			// The EXIF segment has to follow the JFIF one, if any
+			let i = segments
+				.iter()
+				.take_while(|segment| segment.marker == APP0)
+				.count();
+			segments.insert(i, exif_segment);
+			i
+		}
+	};
+
+	if metadata.title.is_some() || metadata.keywords.is_some() {
+		let iptc_index = segments.iter().position(|segment| {
+			segment.marker == APP13
+				&& matches!(
+					&segment.payload,
+					Some(payload) if payload.starts_with(iptc::PHOTOSHOP_SIGNATURE)
+				)
+		});
+
+		let payload = iptc::write(
+			iptc_index.and_then(|i| segments[i].payload.as_deref()),
+			metadata,
+		)?;
+		let iptc_segment = Segment {
+			marker: APP13,
+			payload: Some(payload.into()),
+		};
+
+		match iptc_index {
+			Some(i) => segments[i] = iptc_segment,
+			None => segments.insert(exif_index + 1, iptc_segment),
+		}
+	}
+
+	let mut out = Vec::with_capacity(jpeg.len() + u16::MAX as usize);
+	out.extend_from_slice(&[MARKER_PREFIX, SOI]);
+	for segment in segments {
+		out.extend_from_slice(&[MARKER_PREFIX, segment.marker]);
+		if let Some(payload) = segment.payload {
+			if payload.len() > MAX_PAYLOAD_LEN {
+				return Err(EmbeddedMetadataError::SegmentTooLarge);
+			}
+			out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+			out.extend_from_slice(&payload);
+		}
+	}
+	out.extend_from_slice(image_data);
+
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// SOI, a JFIF APP0 segment, then a fake scan
+	const JPEG: &[u8] = &[
+		0xff, 0xd8, 0xff, 0xe0, 0x00, 0x07, b'J', b'F', b'I', b'F', 0x00, 0xff, 0xda, 0x00, 0x02,
+		0x12, 0x34, 0xff, 0xd9,
+	];
+
+	fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+		haystack
+			.windows(needle.len())
+			.any(|window| window == needle)
+	}
+
+	#[test]
+	fn write_keeps_segments_and_image_data() {
+		let metadata = EmbeddedMetadata {
+			rating: Some(4),
+			title: Some("Sunset".to_string()),
+			keywords: Some(vec!["Beach".to_string(), "Summer".to_string()]),
+		};
+
+		let written = write(JPEG, &metadata).unwrap();
+
+		assert!(written.starts_with(&JPEG[..11]));
+		assert!(written.ends_with(&JPEG[11..]));
+		assert!(contains(&written, EXIF_SIGNATURE));
+		assert!(contains(&written, iptc::PHOTOSHOP_SIGNATURE));
+		assert!(contains(&written, b"\x1c\x02\x19\x00\x05Beach"));
+		assert!(contains(&written, b"\x1c\x02\x05\x00\x06Sunset"));
+		assert_eq!(
+			write(b"not a jpeg", &metadata).unwrap_err().to_string(),
+			"not a JPEG file"
+		);
+	}
+
+	#[test]
+	fn write_again_does_not_grow() {
+		let metadata = EmbeddedMetadata {
+			rating: Some(2),
+			title: None,
+			keywords: Some(vec!["Cats".to_string(), "Dogs".to_string()]),
+		};
+
+		let once = write(JPEG, &metadata).unwrap();
+		let twice = write(&once, &metadata).unwrap();
+
+		assert_eq!(once, twice);
+	}
+}
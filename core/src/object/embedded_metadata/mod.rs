@@ -0,0 +1,122 @@
+//! Writes the organization of files in Spacedrive into the EXIF and IPTC metadata embedded in
+//! them, for the apps which don't read XMP sidecars, and reads what cameras wrote there. Only
+//! JPEG files are supported.
+
+use crate::util::error::FileIOError;
+
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncWriteExt},
+};
+
+mod exif;
+mod iptc;
+mod jpeg;
+pub mod writer_job;
+
+/// Extensions of the files metadata can be written to
+pub const SUPPORTED_EXTENSIONS: [&str; 2] = ["jpg", "jpeg"];
+
+/// The segments before the image data of JPEG files, EXIF included, are well within this
+const HEADER_READ_LEN: u64 = 256 * 1024;
+
+/// Metadata to write to a file, where `None` leaves what the file has. Empty titles and keywords
+/// remove the ones of the file.
+#[derive(Debug, Default, Clone)]
+pub struct EmbeddedMetadata {
+	/// From 0 to 5 stars
+	pub rating: Option<i32>,
+	pub title: Option<String>,
+	pub keywords: Option<Vec<String>>,
+}
+
+/// How and when a photo was taken, as written by the camera
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CaptureInfo {
+	/// In the local time of the camera, as EXIF has no time zone
+	pub date_taken: Option<NaiveDateTime>,
+	pub make: Option<String>,
+	pub model: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum EmbeddedMetadataError {
+	#[error("not a JPEG file")]
+	NotJpeg,
+	#[error("invalid EXIF block")]
+	InvalidExif,
+	#[error("invalid IPTC records")]
+	InvalidIptc,
+	#[error("metadata too large to fit in a JPEG segment")]
+	SegmentTooLarge,
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+/// Writes `metadata` into the file at `path`. The file is written next to it first, then
+/// renamed over it, so it's never left half written if Spacedrive or the system stops midway.
+pub async fn write_embedded_metadata(
+	path: impl AsRef<Path>,
+	metadata: &EmbeddedMetadata,
+) -> Result<(), EmbeddedMetadataError> {
+	let path = path.as_ref();
+
+	let bytes = fs::read(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	let permissions = fs::metadata(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.permissions();
+
+	let new_bytes = jpeg::write(&bytes, metadata)?;
+
+	let mut temp_name = std::ffi::OsString::from(".");
+	temp_name.push(path.file_name().unwrap_or_default());
+	temp_name.push(".sdtmp");
+	let temp_path = path.with_file_name(temp_name);
+
+	let result = async {
+		let mut file = fs::File::create(&temp_path).await?;
+		file.write_all(&new_bytes).await?;
+		file.sync_all().await?;
+		drop(file);
+
+		fs::set_permissions(&temp_path, permissions).await?;
+		fs::rename(&temp_path, path).await
+	}
+	.await;
+
+	if let Err(e) = result {
+		fs::remove_file(&temp_path).await.ok();
+		return Err(FileIOError::from((path, e)).into());
+	}
+
+	Ok(())
+}
+
+/// Reads the camera and capture date of the JPEG file at `path`, `None` if it has no EXIF
+pub async fn read_capture_info(
+	path: impl AsRef<Path>,
+) -> Result<Option<CaptureInfo>, EmbeddedMetadataError> {
+	let path = path.as_ref();
+
+	let mut header = vec![];
+	fs::File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.take(HEADER_READ_LEN)
+		.read_to_end(&mut header)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	if !header.starts_with(&[0xff, 0xd8]) {
+		return Err(EmbeddedMetadataError::NotJpeg);
+	}
+
+	Ok(jpeg::exif_block(&header).and_then(exif::read))
+}
@@ -0,0 +1,264 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::{file_path_helper::IsolatedFilePathData, LocationError},
+	prisma::{custom_field, file_path, location, object},
+	util::db::maybe_missing,
+};
+
+use std::{borrow::Cow, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::info;
+
+use super::{write_embedded_metadata, EmbeddedMetadata, SUPPORTED_EXTENSIONS};
+
+const CHUNK_SIZE: usize = 100;
+
+/// Which metadata is written into the files
+#[derive(Serialize, Deserialize, Type, Hash, Debug, Clone)]
+pub struct EmbeddedMetadataFields {
+	pub rating: bool,
+	/// The tags of the objects, as keywords
+	pub keywords: bool,
+	/// Custom field holding the titles of the objects, as they don't have one of their own
+	pub title_field_id: Option<custom_field::id::Type>,
+}
+
+/// Writes the rating, tags and title of the objects of the files of a location into them, for
+/// locations which allow it
+#[derive(Serialize, Deserialize, Type, Hash, Debug)]
+pub struct EmbeddedMetadataWriterJobInit {
+	pub location_id: location::id::Type,
+	/// Restricts the job to these files of the location
+	pub file_path_ids: Option<Vec<file_path::id::Type>>,
+	pub fields: EmbeddedMetadataFields,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmbeddedMetadataWriterJobStep {
+	path: PathBuf,
+	object_id: object::id::Type,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct EmbeddedMetadataWriterJobRunMetadata {
+	total_files: usize,
+	written_files: usize,
+	failed_files: usize,
+}
+
+impl JobRunMetadata for EmbeddedMetadataWriterJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_files += new_data.total_files;
+		self.written_files += new_data.written_files;
+		self.failed_files += new_data.failed_files;
+	}
+}
+
+file_path::select!(file_path_for_embedded_metadata {
+	materialized_path
+	name
+	extension
+	object_id
+});
+
+object::select!(object_for_embedded_metadata {
+	id
+	rating
+	tags: select { tag: select { name } }
+	custom_field_values: select { field_id value }
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for EmbeddedMetadataWriterJobInit {
+	type Data = ();
+	type Step = Vec<EmbeddedMetadataWriterJobStep>;
+	type RunMetadata = EmbeddedMetadataWriterJobRunMetadata;
+
+	const NAME: &'static str = "embedded_metadata_writer";
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location_id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		_: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(init.location_id))
+			.select(location::select!({ path node_id write_embedded_metadata }))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(init.location_id))?;
+
+		let early_finish = |reason: &str| JobError::EarlyFinish {
+			name: Self::NAME.to_string(),
+			reason: reason.to_string(),
+		};
+
+		if location.node_id != Some(ctx.library.node_local_id) {
+			return Err(early_finish("the location is on another node"));
+		}
+
+		if location.write_embedded_metadata != Some(true) {
+			return Err(early_finish(
+				"writing metadata into files is disabled for this location",
+			));
+		}
+
+		let location_path = PathBuf::from(maybe_missing(location.path, "location.path")?);
+
+		let mut params = vec![
+			file_path::location_id::equals(Some(init.location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::object_id::not(None),
+			file_path::extension::in_vec(
+				SUPPORTED_EXTENSIONS
+					.iter()
+					.map(|extension| Some(extension.to_string()))
+					.collect(),
+			),
+		];
+		if let Some(file_path_ids) = &init.file_path_ids {
+			params.push(file_path::id::in_vec(file_path_ids.clone()));
+		}
+
+		let steps = db
+			.file_path()
+			.find_many(params)
+			.select(file_path_for_embedded_metadata::select())
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| {
+				let iso_file_path = IsolatedFilePathData::from_db_data(
+					init.location_id,
+					false,
+					Cow::Owned(maybe_missing(
+						file_path.materialized_path,
+						"file_path.materialized_path",
+					)?),
+					Cow::Owned(maybe_missing(file_path.name, "file_path.name")?),
+					Cow::Owned(maybe_missing(file_path.extension, "file_path.extension")?),
+				);
+
+				Ok(EmbeddedMetadataWriterJobStep {
+					path: location_path.join(&iso_file_path),
+					object_id: maybe_missing(file_path.object_id, "file_path.object_id")?,
+				})
+			})
+			.collect::<Result<Vec<_>, JobError>>()?;
+
+		ctx.progress_msg(format!("Writing metadata into {} files", steps.len()));
+
+		Ok((
+			EmbeddedMetadataWriterJobRunMetadata {
+				total_files: steps.len(),
+				..Default::default()
+			},
+			steps
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: files, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let fields = &self.fields;
+
+		let objects = ctx
+			.library
+			.db
+			.object()
+			.find_many(vec![object::id::in_vec(
+				files.iter().map(|file| file.object_id).collect(),
+			)])
+			.select(object_for_embedded_metadata::select())
+			.exec()
+			.await?;
+
+		let mut new_metadata = EmbeddedMetadataWriterJobRunMetadata::default();
+		let mut errors = vec![];
+
+		for file in files {
+			let Some(object) = objects.iter().find(|object| object.id == file.object_id) else {
+				continue;
+			};
+
+			let metadata = EmbeddedMetadata {
+				rating: fields.rating.then(|| object.rating.unwrap_or(0)),
+				title: fields.title_field_id.map(|field_id| {
+					object
+						.custom_field_values
+						.iter()
+						.find(|value| value.field_id == Some(field_id))
+						.and_then(|value| value.value.clone())
+						.unwrap_or_default()
+				}),
+				keywords: fields.keywords.then(|| {
+					object
+						.tags
+						.iter()
+						.filter_map(|tag_on_object| tag_on_object.tag.name.clone())
+						.collect()
+				}),
+			};
+
+			match write_embedded_metadata(&file.path, &metadata).await {
+				Ok(()) => new_metadata.written_files += 1,
+				Err(e) => {
+					new_metadata.failed_files += 1;
+					errors.push(format!(
+						"Failed to write metadata into '{}': {e}",
+						file.path.display()
+					));
+				}
+			}
+		}
+
+		ctx.progress_msg(format!(
+			"Wrote metadata into {} of {} files",
+			run_metadata.written_files + run_metadata.failed_files + files.len(),
+			run_metadata.total_files
+		));
+
+		Ok((vec![], new_metadata, errors.into()).into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Wrote metadata into {} files of location {}, {} failed",
+			run_metadata.written_files, self.location_id, run_metadata.failed_files
+		);
+
+		// The files changed, so their size and checksums too
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
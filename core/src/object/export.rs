@@ -0,0 +1,269 @@
+use crate::{
+	library::Library,
+	prisma::{file_path, location, SortOrder},
+	util::error::FileIOError,
+};
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncWriteExt, BufWriter},
+};
+
+/// How many file paths are fetched from the database at a time while exporting
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ListingExportFormat {
+	Csv,
+	Json,
+}
+
+#[derive(Error, Debug)]
+pub enum ListingExportError {
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	Csv(#[from] csv::Error),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+}
+
+impl From<ListingExportError> for rspc::Error {
+	fn from(err: ListingExportError) -> Self {
+		rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err)
+	}
+}
+
+file_path::select!(file_path_to_export {
+	location_id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+	date_created
+	date_modified
+	cas_id
+	integrity_checksum
+	location: select { path }
+	object: select { tags: select { tag: select { name } } }
+});
+
+/// A file of an exported listing, with what inventories and audits usually need
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedFile {
+	pub location_id: Option<location::id::Type>,
+	/// Full path of the file on the node owning its location
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub size_in_bytes: u64,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub date_modified: Option<DateTime<FixedOffset>>,
+	pub cas_id: Option<String>,
+	pub integrity_checksum: Option<String>,
+	pub tags: Vec<String>,
+}
+
+impl From<file_path_to_export::Data> for ExportedFile {
+	fn from(file_path: file_path_to_export::Data) -> Self {
+		let mut path = file_path
+			.location
+			.and_then(|location| location.path)
+			.map(PathBuf::from)
+			.unwrap_or_default();
+
+		if let Some(materialized_path) = &file_path.materialized_path {
+			path.push(materialized_path.trim_start_matches('/'));
+		}
+
+		let name = file_path.name.unwrap_or_default();
+		match file_path.extension.as_deref() {
+			Some(extension) if !extension.is_empty() => path.push(format!("{name}.{extension}")),
+			_ => path.push(name),
+		}
+
+		Self {
+			location_id: file_path.location_id,
+			path,
+			is_dir: file_path.is_dir.unwrap_or_default(),
+			size_in_bytes: file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.and_then(|bytes| bytes.try_into().ok())
+				.map_or(0, u64::from_be_bytes),
+			date_created: file_path.date_created,
+			date_modified: file_path.date_modified,
+			cas_id: file_path.cas_id,
+			integrity_checksum: file_path.integrity_checksum,
+			tags: file_path
+				.object
+				.map(|object| {
+					object
+						.tags
+						.into_iter()
+						.filter_map(|tag_on_object| tag_on_object.tag.name)
+						.collect()
+				})
+				.unwrap_or_default(),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct ListingExportSummary {
+	pub output_path: PathBuf,
+	pub exported_files: u32,
+}
+
+/// Writes every file path matching `params` to `output_path`, in batches so listings of huge
+/// locations never need to be held in memory. The CSV has one column per field of
+/// [`ExportedFile`], tags being separated by semicolons, while the JSON is an array of them.
+pub async fn export_file_paths(
+	library: &Library,
+	params: Vec<file_path::WhereParam>,
+	order: Option<file_path::OrderByWithRelationParam>,
+	format: ListingExportFormat,
+	output_path: impl AsRef<Path>,
+) -> Result<ListingExportSummary, ListingExportError> {
+	let output_path = output_path.as_ref();
+
+	let mut output = BufWriter::new(
+		fs::File::create(output_path)
+			.await
+			.map_err(|e| FileIOError::from((output_path, e)))?,
+	);
+
+	let mut exported_files = 0;
+
+	match format {
+		ListingExportFormat::Csv => {
+			let mut header = csv::Writer::from_writer(vec![]);
+			header.write_record([
+				"location_id",
+				"path",
+				"is_dir",
+				"size_in_bytes",
+				"date_created",
+				"date_modified",
+				"cas_id",
+				"integrity_checksum",
+				"tags",
+			])?;
+			write(&mut output, output_path, &into_inner(header)?).await?;
+		}
+		ListingExportFormat::Json => write(&mut output, output_path, b"[").await?,
+	}
+
+	loop {
+		let mut query = library
+			.db
+			.file_path()
+			.find_many(params.clone())
+			.skip(exported_files as i64)
+			.take(EXPORT_BATCH_SIZE);
+
+		// Ordering by id last, so the batches are stable when the other ordering has ties
+		if let Some(order) = &order {
+			query = query.order_by(order.clone());
+		}
+
+		let file_paths = query
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.select(file_path_to_export::select())
+			.exec()
+			.await?;
+
+		if file_paths.is_empty() {
+			break;
+		}
+
+		let batch_len = file_paths.len();
+
+		let bytes = match format {
+			ListingExportFormat::Csv => {
+				let mut writer = csv::Writer::from_writer(vec![]);
+				for file in file_paths.into_iter().map(ExportedFile::from) {
+					writer.write_record([
+						file.location_id
+							.map(|id| id.to_string())
+							.unwrap_or_default(),
+						file.path.to_string_lossy().to_string(),
+						file.is_dir.to_string(),
+						file.size_in_bytes.to_string(),
+						file.date_created
+							.map(|date| date.to_rfc3339())
+							.unwrap_or_default(),
+						file.date_modified
+							.map(|date| date.to_rfc3339())
+							.unwrap_or_default(),
+						file.cas_id.unwrap_or_default(),
+						file.integrity_checksum.unwrap_or_default(),
+						file.tags.join(";"),
+					])?;
+				}
+				into_inner(writer)?
+			}
+			ListingExportFormat::Json => {
+				let mut bytes = vec![];
+				for (i, file) in file_paths.into_iter().map(ExportedFile::from).enumerate() {
+					if exported_files > 0 || i > 0 {
+						bytes.push(b',');
+					}
+					bytes.push(b'\n');
+					serde_json::to_writer(&mut bytes, &file)?;
+				}
+				bytes
+			}
+		};
+
+		write(&mut output, output_path, &bytes).await?;
+
+		exported_files += batch_len as u32;
+
+		if batch_len < EXPORT_BATCH_SIZE as usize {
+			break;
+		}
+	}
+
+	if let ListingExportFormat::Json = format {
+		write(&mut output, output_path, b"\n]\n").await?;
+	}
+
+	output
+		.flush()
+		.await
+		.map_err(|e| FileIOError::from((output_path, e)))?;
+
+	Ok(ListingExportSummary {
+		output_path: output_path.to_path_buf(),
+		exported_files,
+	})
+}
+
+async fn write(
+	output: &mut BufWriter<fs::File>,
+	output_path: &Path,
+	bytes: &[u8],
+) -> Result<(), FileIOError> {
+	output
+		.write_all(bytes)
+		.await
+		.map_err(|e| FileIOError::from((output_path, e)))
+}
+
+fn into_inner(writer: csv::Writer<Vec<u8>>) -> Result<Vec<u8>, csv::Error> {
+	writer
+		.into_inner()
+		.map_err(|e| csv::Error::from(e.into_error()))
+}
@@ -1,7 +1,7 @@
 use crate::{
 	job::{
 		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
-		StatefulJob, WorkerContext,
+		RetryPolicy, StatefulJob, WorkerContext,
 	},
 	library::Library,
 	location::file_path_helper::{
@@ -75,6 +75,11 @@ impl StatefulJob for FileIdentifierJobInit {
 	type RunMetadata = FileIdentifierJobRunMetadata;
 
 	const NAME: &'static str = "file_identifier";
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
 
 	async fn init(
 		&self,
@@ -241,6 +246,7 @@ fn orphan_path_filters(
 	chain_optional_iter(
 		[
 			file_path::object_id::equals(None),
+			file_path::date_deleted::equals(None),
 			file_path::is_dir::equals(Some(false)),
 			file_path::location_id::equals(Some(location_id)),
 		],
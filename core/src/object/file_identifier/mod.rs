@@ -4,12 +4,13 @@ use crate::{
 	location::file_path_helper::{
 		file_path_for_file_identifier, FilePathError, IsolatedFilePathData,
 	},
-	object::{cas::generate_cas_id, object_for_file_identifier},
+	object::{cas::generate_cas_id, object_for_file_identifier, xmp},
+	plugin,
 	prisma::{file_path, location, object, PrismaClient},
 	sync,
 	sync::SyncManager,
 	util::{
-		db::{maybe_missing, uuid_to_bytes},
+		db::{maybe_missing, new_pub_id, uuid_to_bytes},
 		error::FileIOError,
 	},
 };
@@ -22,7 +23,7 @@ use std::{
 	path::Path,
 };
 
-use futures::future::join_all;
+use futures::{stream, StreamExt};
 use serde_json::json;
 use thiserror::Error;
 use tokio::fs;
@@ -94,13 +95,15 @@ impl FileMetadata {
 }
 
 async fn identifier_job_step(
-	Library { db, sync, .. }: &Library,
+	Library {
+		db, sync, config, ..
+	}: &Library,
 	location: &location::Data,
 	file_paths: &[file_path_for_file_identifier::Data],
 ) -> Result<(usize, usize), JobError> {
 	let location_path = maybe_missing(&location.path, "location.path").map(Path::new)?;
 
-	let file_path_metas = join_all(file_paths.iter().map(|file_path| async move {
+	let file_path_metas = stream::iter(file_paths.iter().map(|file_path| async move {
 		// NOTE: `file_path`'s `materialized_path` begins with a `/` character so we remove it to join it with `location.path`
 		let meta = FileMetadata::new(
 			&location_path,
@@ -114,6 +117,8 @@ async fn identifier_job_step(
 			(meta, file_path),
 		)) as Result<_, JobError>
 	}))
+	.buffer_unordered(config.jobs.io_parallelism())
+	.collect::<Vec<_>>()
 	.await
 	.into_iter()
 	.flat_map(|data| {
@@ -133,28 +138,32 @@ async fn identifier_job_step(
 		.collect();
 
 	// Assign cas_id to each file path
-	sync.write_ops(
-		db,
-		file_path_metas
-			.iter()
-			.map(|(pub_id, (meta, _))| {
-				(
-					sync.shared_update(
-						sync::file_path::SyncId {
-							pub_id: uuid_to_bytes(*pub_id),
-						},
-						file_path::cas_id::NAME,
-						json!(&meta.cas_id),
-					),
-					db.file_path().update(
-						file_path::pub_id::equals(uuid_to_bytes(*pub_id)),
-						vec![file_path::cas_id::set(Some(meta.cas_id.clone()))],
-					),
-				)
-			})
-			.unzip::<_, _, _, Vec<_>>(),
-	)
-	.await?;
+	config
+		.database
+		.run(|| {
+			sync.write_ops(
+				db,
+				file_path_metas
+					.iter()
+					.map(|(pub_id, (meta, _))| {
+						(
+							sync.shared_update(
+								sync::file_path::SyncId {
+									pub_id: uuid_to_bytes(*pub_id),
+								},
+								file_path::cas_id::NAME,
+								json!(&meta.cas_id),
+							),
+							db.file_path().update(
+								file_path::pub_id::equals(uuid_to_bytes(*pub_id)),
+								vec![file_path::cas_id::set(Some(meta.cas_id.clone()))],
+							),
+						)
+					})
+					.unzip::<_, _, _, Vec<_>>(),
+			)
+		})
+		.await?;
 
 	// Retrieves objects that are already connected to file paths with the same id
 	let existing_objects = db
@@ -230,7 +239,7 @@ async fn identifier_job_step(
 			file_paths_requiring_new_object
 				.iter()
 				.map(|(file_path_pub_id, (meta, fp))| {
-					let object_pub_id = Uuid::new_v4();
+					let object_pub_id = new_pub_id();
 
 					let sync_id = || sync::object::SyncId {
 						pub_id: uuid_to_bytes(object_pub_id),
@@ -351,6 +360,16 @@ async fn process_identifier_file_paths(
 	let (total_objects_created, total_objects_linked) =
 		identifier_job_step(library, location, file_paths).await?;
 
+	if library.config.xmp_sidecars.read {
+		if let Err(e) = xmp::import_sidecars(library, location, file_paths).await {
+			error!("Failed to import XMP sidecars: {e:#?}");
+		}
+	}
+
+	if let Err(e) = plugin::run_extractors(library, location, file_paths).await {
+		error!("Failed to run the extractors of plugins: {e:#?}");
+	}
+
 	Ok((
 		total_objects_created,
 		total_objects_linked,
@@ -123,6 +123,7 @@ fn orphan_path_filters(
 	chain_optional_iter(
 		[
 			file_path::object_id::equals(None),
+			file_path::date_deleted::equals(None),
 			file_path::is_dir::equals(Some(false)),
 			file_path::location_id::equals(Some(location_id)),
 			file_path::materialized_path::equals(Some(
@@ -1,8 +1,8 @@
 use crate::{
 	invalidate_query,
 	job::{
-		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobStepOutput, StatefulJob,
-		WorkerContext,
+		CurrentStep, FileProgress, JobError, JobInitOutput, JobPriority, JobReportUpdate,
+		JobResult, JobRunErrors, JobStepOutput, StatefulJob, WorkerContext,
 	},
 	library::Library,
 	location::file_path_helper::{join_location_relative_path, IsolatedFilePathData},
@@ -13,19 +13,40 @@ use crate::{
 	},
 };
 
-use std::{hash::Hash, path::PathBuf};
+use std::{
+	hash::Hash,
+	path::{Path, PathBuf},
+	time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use specta::Type;
-use tokio::{fs, io};
+use tokio::{
+	fs, io,
+	io::{AsyncReadExt, AsyncWriteExt},
+	time::Instant,
+};
 use tracing::{trace, warn};
 
 use super::{
-	construct_target_filename, error::FileSystemJobsError, fetch_source_and_target_location_paths,
-	get_file_data_from_isolated_file_path, get_many_files_datas, FileData,
+	construct_target_filename,
+	error::FileSystemJobsError,
+	fetch_source_and_target_location_paths, get_file_data_from_isolated_file_path,
+	get_many_files_datas,
+	journal::{FileOperationKind, JournaledOperation},
+	FileData, FileOperationResult, FileOperationRunMetadata,
 };
 
+/// Size of the chunks files are copied in, between each progress report
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Minimum time between two progress reports of the same file
+const FILE_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Extension of files being copied, which are renamed to their target name once complete
+const PARTIAL_COPY_EXTENSION: &str = "sdpart";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileCopierJobData {
 	sources_location_path: PathBuf,
@@ -50,9 +71,10 @@ pub struct FileCopierJobStep {
 impl StatefulJob for FileCopierJobInit {
 	type Data = FileCopierJobData;
 	type Step = FileCopierJobStep;
-	type RunMetadata = ();
+	type RunMetadata = FileOperationRunMetadata;
 
 	const NAME: &'static str = "file_copier";
+	const PRIORITY: JobPriority = JobPriority::Interactive;
 
 	async fn init(
 		&self,
@@ -162,7 +184,12 @@ impl StatefulJob for FileCopierJobInit {
 			Ok(more_steps.into())
 		} else if &source_file_data.full_path == target_full_path {
 			// File is already here, do nothing
-			Ok(().into())
+			Ok(FileOperationRunMetadata::single(
+				&source_file_data.full_path,
+				target_full_path,
+				FileOperationResult::AlreadyAtTarget,
+			)
+			.into())
 		} else {
 			match fs::metadata(target_full_path).await {
 				Ok(_) => {
@@ -172,11 +199,19 @@ impl StatefulJob for FileCopierJobInit {
 						target_full_path.display()
 					);
 
-					Ok(JobRunErrors(vec![FileSystemJobsError::WouldOverwrite(
-						target_full_path.clone().into_boxed_path(),
+					Ok((
+						vec![],
+						FileOperationRunMetadata::single(
+							&source_file_data.full_path,
+							target_full_path,
+							FileOperationResult::SkippedToAvoidOverwrite,
+						),
+						JobRunErrors(vec![FileSystemJobsError::WouldOverwrite(
+							target_full_path.clone().into_boxed_path(),
+						)
+						.to_string()]),
 					)
-					.to_string()])
-					.into())
+						.into())
 				}
 				Err(e) if e.kind() == io::ErrorKind::NotFound => {
 					trace!(
@@ -185,13 +220,34 @@ impl StatefulJob for FileCopierJobInit {
 						target_full_path.display()
 					);
 
-					fs::copy(&source_file_data.full_path, &target_full_path)
+					let operation = JournaledOperation::begin(
+						&ctx.library,
+						FileOperationKind::Copy,
+						&source_file_data.full_path,
+						Some(target_full_path.clone()),
+					)
+					.await?;
+
+					// Using the ? here because we don't want to increase the completed task
+					// count in case of file system errors
+					match copy_with_progress(ctx, &source_file_data.full_path, target_full_path)
 						.await
-						// Using the ? here because we don't want to increase the completed task
-						// count in case of file system errors
-						.map_err(|e| FileIOError::from((target_full_path, e)))?;
+					{
+						Ok(()) => operation.done().await,
+						// The partial copy is removed on cleanup
+						Err(JobError::StepCanceled) => {
+							operation.done().await;
+							return Err(JobError::StepCanceled);
+						}
+						Err(e) => return Err(e),
+					}
 
-					Ok(().into())
+					Ok(FileOperationRunMetadata::single(
+						&source_file_data.full_path,
+						target_full_path,
+						FileOperationResult::Done,
+					)
+					.into())
 				}
 				Err(e) => return Err(FileIOError::from((target_full_path, e)).into()),
 			}
@@ -202,12 +258,124 @@ impl StatefulJob for FileCopierJobInit {
 		&self,
 		ctx: &WorkerContext,
 		_data: &Option<Self::Data>,
-		_run_metadata: &Self::RunMetadata,
+		run_metadata: &Self::RunMetadata,
 	) -> JobResult {
 		let init = self;
 
 		invalidate_query!(ctx.library, "search.paths");
 
-		Ok(Some(json!({ "init": init })))
+		Ok(Some(json!({ "init": init, "files": run_metadata.files })))
 	}
+
+	async fn cleanup(
+		&self,
+		_: &WorkerContext,
+		_: Option<&Self::Data>,
+		interrupted_step: Option<&Self::Step>,
+	) -> Result<(), JobError> {
+		let Some(FileCopierJobStep {
+			target_full_path, ..
+		}) = interrupted_step
+		else {
+			return Ok(());
+		};
+
+		// Only the partial file is removed, as the target itself may have existed before the copy
+		let partial_path = partial_copy_path(target_full_path);
+		match fs::remove_file(&partial_path).await {
+			Ok(()) => {
+				trace!("Removed partial copy {}", partial_path.display());
+				Ok(())
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(FileIOError::from((partial_path, e)).into()),
+		}
+	}
+}
+
+pub(crate) fn partial_copy_path(target: &Path) -> PathBuf {
+	let mut partial_path = target.as_os_str().to_owned();
+	partial_path.push(".");
+	partial_path.push(PARTIAL_COPY_EXTENSION);
+	partial_path.into()
+}
+
+/// Copies a file in chunks, reporting how many bytes were copied so far to the job's progress.
+/// The data is written to a partial file first, only getting its target name once complete,
+/// so a canceled copy never leaves a truncated file behind.
+async fn copy_with_progress(
+	ctx: &WorkerContext,
+	source: &Path,
+	target: &Path,
+) -> Result<(), JobError> {
+	let partial_target = partial_copy_path(target);
+
+	let mut source_file = fs::File::open(source)
+		.await
+		.map_err(|e| FileIOError::from((source, e)))?;
+
+	let metadata = source_file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((source, e)))?;
+
+	let mut target_file = fs::File::create(&partial_target)
+		.await
+		.map_err(|e| FileIOError::from((&partial_target, e)))?;
+
+	let mut progress = FileProgress {
+		path: source.to_path_buf(),
+		bytes_done: 0,
+		bytes_total: metadata.len(),
+	};
+	ctx.progress(vec![JobReportUpdate::FileProgress(Some(progress.clone()))]);
+
+	let mut buf = vec![0; COPY_CHUNK_SIZE];
+	let mut last_report = Instant::now();
+
+	loop {
+		if ctx.is_canceled() {
+			return Err(JobError::StepCanceled);
+		}
+
+		let read = source_file
+			.read(&mut buf)
+			.await
+			.map_err(|e| FileIOError::from((source, e)))?;
+
+		if read == 0 {
+			break;
+		}
+
+		target_file
+			.write_all(&buf[..read])
+			.await
+			.map_err(|e| FileIOError::from((&partial_target, e)))?;
+
+		progress.bytes_done += read as u64;
+
+		if last_report.elapsed() >= FILE_PROGRESS_INTERVAL {
+			ctx.progress(vec![JobReportUpdate::FileProgress(Some(progress.clone()))]);
+			last_report = Instant::now();
+		}
+	}
+
+	target_file
+		.flush()
+		.await
+		.map_err(|e| FileIOError::from((&partial_target, e)))?;
+	drop(target_file);
+
+	// Keeping the same permissions as the source, like `fs::copy` does
+	fs::set_permissions(&partial_target, metadata.permissions())
+		.await
+		.map_err(|e| FileIOError::from((&partial_target, e)))?;
+
+	fs::rename(&partial_target, target)
+		.await
+		.map_err(|e| FileIOError::from((target, e)))?;
+
+	ctx.progress(vec![JobReportUpdate::FileProgress(None)]);
+
+	Ok(())
 }
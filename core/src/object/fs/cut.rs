@@ -1,8 +1,8 @@
 use crate::{
 	invalidate_query,
 	job::{
-		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobStepOutput, StatefulJob,
-		WorkerContext,
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobRunErrors, JobStepOutput,
+		StatefulJob, WorkerContext,
 	},
 	library::Library,
 	location::file_path_helper::push_location_relative_path,
@@ -19,7 +19,11 @@ use specta::Type;
 use tokio::{fs, io};
 use tracing::{trace, warn};
 
-use super::{fetch_source_and_target_location_paths, get_many_files_datas, FileData};
+use super::{
+	fetch_source_and_target_location_paths, get_many_files_datas,
+	journal::{FileOperationKind, JournaledOperation},
+	FileData, FileOperationResult, FileOperationRunMetadata,
+};
 
 #[derive(Serialize, Deserialize, Hash, Type, Debug)]
 pub struct FileCutterJobInit {
@@ -38,9 +42,10 @@ pub struct FileCutterJobData {
 impl StatefulJob for FileCutterJobInit {
 	type Data = FileCutterJobData;
 	type Step = FileData;
-	type RunMetadata = ();
+	type RunMetadata = FileOperationRunMetadata;
 
 	const NAME: &'static str = "file_cutter";
+	const PRIORITY: JobPriority = JobPriority::Interactive;
 
 	async fn init(
 		&self,
@@ -75,7 +80,7 @@ impl StatefulJob for FileCutterJobInit {
 
 	async fn execute_step(
 		&self,
-		_: &WorkerContext,
+		ctx: &WorkerContext,
 		CurrentStep {
 			step: file_data, ..
 		}: CurrentStep<'_, Self::Step>,
@@ -88,7 +93,12 @@ impl StatefulJob for FileCutterJobInit {
 
 		if file_data.full_path == full_output {
 			// File is already here, do nothing
-			Ok(().into())
+			Ok(FileOperationRunMetadata::single(
+				&file_data.full_path,
+				full_output,
+				FileOperationResult::AlreadyAtTarget,
+			)
+			.into())
 		} else {
 			match fs::metadata(&full_output).await {
 				Ok(_) => {
@@ -97,11 +107,19 @@ impl StatefulJob for FileCutterJobInit {
 						full_output.display()
 					);
 
-					Ok(JobRunErrors(vec![FileSystemJobsError::WouldOverwrite(
-						full_output.into_boxed_path(),
+					Ok((
+						vec![],
+						FileOperationRunMetadata::single(
+							&file_data.full_path,
+							&full_output,
+							FileOperationResult::SkippedToAvoidOverwrite,
+						),
+						JobRunErrors(vec![FileSystemJobsError::WouldOverwrite(
+							full_output.into_boxed_path(),
+						)
+						.to_string()]),
 					)
-					.to_string()])
-					.into())
+						.into())
 				}
 				Err(e) if e.kind() == io::ErrorKind::NotFound => {
 					trace!(
@@ -110,11 +128,25 @@ impl StatefulJob for FileCutterJobInit {
 						full_output.display()
 					);
 
-					fs::rename(&file_data.full_path, &full_output)
-						.await
-						.map_err(|e| FileIOError::from((&file_data.full_path, e)))?;
+					let operation = JournaledOperation::begin(
+						&ctx.library,
+						FileOperationKind::Move,
+						&file_data.full_path,
+						Some(full_output.clone()),
+					)
+					.await?;
+
+					// Renames are atomic, nothing is left half done if it fails
+					let res = fs::rename(&file_data.full_path, &full_output).await;
+					operation.done().await;
+					res.map_err(|e| FileIOError::from((&file_data.full_path, e)))?;
 
-					Ok(().into())
+					Ok(FileOperationRunMetadata::single(
+						&file_data.full_path,
+						full_output,
+						FileOperationResult::Done,
+					)
+					.into())
 				}
 
 				Err(e) => return Err(FileIOError::from((&full_output, e)).into()),
@@ -126,11 +158,11 @@ impl StatefulJob for FileCutterJobInit {
 		&self,
 		ctx: &WorkerContext,
 		_data: &Option<Self::Data>,
-		_run_metadata: &Self::RunMetadata,
+		run_metadata: &Self::RunMetadata,
 	) -> JobResult {
 		let init = self;
 		invalidate_query!(ctx.library, "search.paths");
 
-		Ok(Some(json!({ "init": init })))
+		Ok(Some(json!({ "init": init, "files": run_metadata.files })))
 	}
 }
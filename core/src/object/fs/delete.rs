@@ -1,7 +1,8 @@
 use crate::{
 	invalidate_query,
 	job::{
-		CurrentStep, JobError, JobInitOutput, JobResult, JobStepOutput, StatefulJob, WorkerContext,
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobStepOutput, StatefulJob,
+		WorkerContext,
 	},
 	library::Library,
 	prisma::{file_path, location},
@@ -16,7 +17,11 @@ use specta::Type;
 use tokio::{fs, io};
 use tracing::warn;
 
-use super::{get_location_path_from_location_id, get_many_files_datas, FileData};
+use super::{
+	get_location_path_from_location_id, get_many_files_datas,
+	journal::{FileOperationKind, JournaledOperation},
+	FileData,
+};
 
 #[derive(Serialize, Deserialize, Hash, Type, Debug)]
 pub struct FileDeleterJobInit {
@@ -31,6 +36,7 @@ impl StatefulJob for FileDeleterJobInit {
 	type RunMetadata = ();
 
 	const NAME: &'static str = "file_deleter";
+	const PRIORITY: JobPriority = JobPriority::Interactive;
 
 	async fn init(
 		&self,
@@ -63,13 +69,24 @@ impl StatefulJob for FileDeleterJobInit {
 		// need to handle stuff such as querying prisma for all paths of a file, and deleting all of those if requested (with a checkbox in the ui)
 		// maybe a files.countOccurances/and or files.getPath(location_id, path_id) to show how many of these files would be deleted (and where?)
 
-		match if maybe_missing(step.file_path.is_dir, "file_path.is_dir")? {
+		let is_dir = maybe_missing(step.file_path.is_dir, "file_path.is_dir")?;
+
+		let operation = JournaledOperation::begin(
+			&ctx.library,
+			FileOperationKind::Delete,
+			&step.full_path,
+			None,
+		)
+		.await?;
+
+		match if is_dir {
 			fs::remove_dir_all(&step.full_path).await
 		} else {
 			fs::remove_file(&step.full_path).await
 		} {
-			Ok(()) => { /*	Everything is awesome! */ }
+			Ok(()) => operation.done().await,
 			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				operation.done().await;
 				warn!(
 					"File not found in the file system, will remove from database: {}",
 					step.full_path.display()
@@ -81,6 +98,7 @@ impl StatefulJob for FileDeleterJobInit {
 					.exec()
 					.await?;
 			}
+			// Left in the journal, as a directory may have been partly deleted
 			Err(e) => {
 				return Err(JobError::from(FileIOError::from((&step.full_path, e))));
 			}
@@ -0,0 +1,372 @@
+//! Journal of the file operations in progress in a library. Each copy, move or delete is written
+//! to it before touching the file system, and removed from it once done, so the operations that
+//! were cut short by a crash are still in it the next time the library is loaded. They're set
+//! aside then, apart from the operations of this run, and left for the user to finish or roll
+//! back, as only they know which one they want.
+
+use crate::{library::Library, util::error::FileIOError};
+
+use std::{
+	io::ErrorKind,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+use super::copy::partial_copy_path;
+
+/// Directory of the journals, in the data directory of a library, with one per library
+const JOURNAL_DIR_NAME: &str = "file_operations";
+/// Directory of a journal the operations interrupted in a previous run are set aside in
+const INTERRUPTED_DIR_NAME: &str = "interrupted";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileOperationKind {
+	Copy,
+	Move,
+	Delete,
+}
+
+/// A file operation written to the journal before it started
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+	pub id: Uuid,
+	pub kind: FileOperationKind,
+	pub source: PathBuf,
+	/// Not set for deletes
+	pub target: Option<PathBuf>,
+	pub started_at: DateTime<Utc>,
+}
+
+/// How far an interrupted operation got, found by looking at its files
+#[derive(Debug, Clone, Copy, Serialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum InterruptedOperationState {
+	/// Nothing was changed yet, there's nothing to roll back
+	NotStarted,
+	/// Some of it was done, like a copy that didn't finish writing, or a directory that was only
+	/// partly deleted
+	Partial,
+	/// The operation went through, only its entry wasn't removed
+	Done,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct InterruptedOperation {
+	#[serde(flatten)]
+	pub entry: JournalEntry,
+	pub state: InterruptedOperationState,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum InterruptedOperationAction {
+	/// Runs the rest of the operation
+	Finish,
+	/// Undoes what was done, deletes can't be rolled back
+	RollBack,
+	/// Forgets about the operation, leaving its files as they are
+	Dismiss,
+}
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+	#[error("file operation <id='{0}'> not found in the journal")]
+	NotFound(Uuid),
+	#[error("a delete can't be rolled back")]
+	CantRollBackDelete,
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("invalid journal entry: {0}")]
+	Json(#[from] serde_json::Error),
+}
+
+impl From<JournalError> for rspc::Error {
+	fn from(err: JournalError) -> Self {
+		match err {
+			JournalError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			JournalError::CantRollBackDelete => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+fn journal_dir(library: &Library) -> PathBuf {
+	library
+		.data_directory()
+		.join(JOURNAL_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+fn interrupted_dir(library: &Library) -> PathBuf {
+	journal_dir(library).join(INTERRUPTED_DIR_NAME)
+}
+
+fn entry_path(journal_dir: &Path, id: Uuid) -> PathBuf {
+	journal_dir.join(format!("{id}.json"))
+}
+
+/// An operation written to the journal, removed from it by [`JournaledOperation::done`]. If it's
+/// dropped instead, like when the operation failed, it stays in the journal, as its files may
+/// have been left half changed.
+#[must_use]
+pub struct JournaledOperation {
+	path: PathBuf,
+}
+
+impl JournaledOperation {
+	/// Writes the operation to the journal, and makes sure it reached the disk before returning
+	pub async fn begin(
+		library: &Library,
+		kind: FileOperationKind,
+		source: impl Into<PathBuf>,
+		target: Option<PathBuf>,
+	) -> Result<Self, FileIOError> {
+		let journal_dir = journal_dir(library);
+		fs::create_dir_all(&journal_dir)
+			.await
+			.map_err(|e| FileIOError::from((&journal_dir, e)))?;
+
+		let entry = JournalEntry {
+			id: Uuid::new_v4(),
+			kind,
+			source: source.into(),
+			target,
+			started_at: Utc::now(),
+		};
+		let path = entry_path(&journal_dir, entry.id);
+
+		let mut file = fs::File::create(&path)
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+		file.write_all(&serde_json::to_vec(&entry).expect("journal entries are serializable"))
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+		file.sync_all()
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+
+		trace!("Journaled file operation {entry:?}");
+
+		Ok(Self { path })
+	}
+
+	pub async fn done(self) {
+		if let Err(e) = fs::remove_file(&self.path).await {
+			warn!(
+				"Failed to remove file operation '{}' from the journal: {e}",
+				self.path.display()
+			);
+		}
+	}
+}
+
+/// Sets aside the operations left in the journal by a previous run, as none of them can be
+/// running while the library is being loaded. Returns how many there are.
+pub(crate) async fn set_aside_interrupted_operations(
+	library: &Library,
+) -> Result<usize, JournalError> {
+	let journal_dir = journal_dir(library);
+	let interrupted_dir = interrupted_dir(library);
+
+	let entry_paths = entry_paths(&journal_dir).await?;
+	if !entry_paths.is_empty() {
+		fs::create_dir_all(&interrupted_dir)
+			.await
+			.map_err(|e| FileIOError::from((&interrupted_dir, e)))?;
+	}
+
+	for path in entry_paths {
+		if let Some(file_name) = path.file_name() {
+			fs::rename(&path, interrupted_dir.join(file_name))
+				.await
+				.map_err(|e| FileIOError::from((&path, e)))?;
+		}
+	}
+
+	Ok(entry_paths(&interrupted_dir).await?.len())
+}
+
+async fn entry_paths(dir: &Path) -> Result<Vec<PathBuf>, FileIOError> {
+	let mut read_dir = match fs::read_dir(dir).await {
+		Ok(read_dir) => read_dir,
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(FileIOError::from((dir, e))),
+	};
+
+	let mut paths = vec![];
+	while let Some(dir_entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?
+	{
+		let path = dir_entry.path();
+		if path.extension().map_or(false, |ext| ext == "json") {
+			paths.push(path);
+		}
+	}
+
+	Ok(paths)
+}
+
+/// Operations interrupted in a previous run, with how far they got
+pub async fn interrupted_operations(
+	library: &Library,
+) -> Result<Vec<InterruptedOperation>, JournalError> {
+	let mut operations = vec![];
+	for path in entry_paths(&interrupted_dir(library)).await? {
+		let bytes = fs::read(&path)
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+
+		match serde_json::from_slice::<JournalEntry>(&bytes) {
+			Ok(entry) => operations.push(InterruptedOperation {
+				state: state_of(&entry).await,
+				entry,
+			}),
+			// Cut short while being written, so its operation never started
+			Err(e) => {
+				warn!(
+					"Removing unreadable journal entry '{}': {e}",
+					path.display()
+				);
+				fs::remove_file(&path).await.ok();
+			}
+		}
+	}
+
+	operations.sort_by_key(|operation| operation.entry.started_at);
+
+	Ok(operations)
+}
+
+async fn exists(path: &Path) -> bool {
+	fs::metadata(path).await.is_ok()
+}
+
+async fn state_of(entry: &JournalEntry) -> InterruptedOperationState {
+	use InterruptedOperationState::*;
+
+	let source_exists = exists(&entry.source).await;
+
+	match (entry.kind, &entry.target) {
+		(FileOperationKind::Copy, Some(target)) => {
+			if exists(&partial_copy_path(target)).await {
+				Partial
+			} else if exists(target).await {
+				Done
+			} else {
+				NotStarted
+			}
+		}
+		// Renames are atomic, so a move is either done or not
+		(FileOperationKind::Move, Some(target)) => {
+			if !source_exists && exists(target).await {
+				Done
+			} else {
+				NotStarted
+			}
+		}
+		(FileOperationKind::Delete, _) => match fs::metadata(&entry.source).await {
+			Err(_) => Done,
+			// A directory deleted part of the way
+			Ok(metadata) if metadata.is_dir() => Partial,
+			Ok(_) => NotStarted,
+		},
+		(_, None) => NotStarted,
+	}
+}
+
+/// Finishes, rolls back or dismisses an interrupted operation, removing it from the journal
+pub async fn resolve_interrupted_operation(
+	library: &Library,
+	id: Uuid,
+	action: InterruptedOperationAction,
+) -> Result<(), JournalError> {
+	let path = entry_path(&interrupted_dir(library), id);
+	let entry = match fs::read(&path).await {
+		Ok(bytes) => serde_json::from_slice::<JournalEntry>(&bytes)?,
+		Err(e) if e.kind() == ErrorKind::NotFound => return Err(JournalError::NotFound(id)),
+		Err(e) => return Err(FileIOError::from((&path, e)).into()),
+	};
+
+	match (action, entry.kind, &entry.target) {
+		(InterruptedOperationAction::Dismiss, _, _) => {}
+
+		(InterruptedOperationAction::Finish, FileOperationKind::Copy, Some(target)) => {
+			if !exists(target).await {
+				let partial_path = partial_copy_path(target);
+				fs::copy(&entry.source, &partial_path)
+					.await
+					.map_err(|e| FileIOError::from((&partial_path, e)))?;
+				fs::rename(&partial_path, target)
+					.await
+					.map_err(|e| FileIOError::from((target, e)))?;
+			}
+		}
+		(InterruptedOperationAction::RollBack, FileOperationKind::Copy, Some(target)) => {
+			// The target didn't exist before, as copies never overwrite
+			for path in [partial_copy_path(target), target.clone()] {
+				remove_if_exists(&path).await?;
+			}
+		}
+
+		(InterruptedOperationAction::Finish, FileOperationKind::Move, Some(target)) => {
+			if exists(&entry.source).await && !exists(target).await {
+				fs::rename(&entry.source, target)
+					.await
+					.map_err(|e| FileIOError::from((&entry.source, e)))?;
+			}
+		}
+		(InterruptedOperationAction::RollBack, FileOperationKind::Move, Some(target)) => {
+			if !exists(&entry.source).await && exists(target).await {
+				fs::rename(target, &entry.source)
+					.await
+					.map_err(|e| FileIOError::from((target, e)))?;
+			}
+		}
+
+		(InterruptedOperationAction::Finish, FileOperationKind::Delete, _) => {
+			remove_if_exists(&entry.source).await?;
+		}
+		(InterruptedOperationAction::RollBack, FileOperationKind::Delete, _) => {
+			if state_of(&entry).await != InterruptedOperationState::NotStarted {
+				return Err(JournalError::CantRollBackDelete);
+			}
+		}
+
+		// Copies and moves always have a target
+		(_, _, None) => {}
+	}
+
+	fs::remove_file(&path)
+		.await
+		.map_err(|e| FileIOError::from((&path, e)))?;
+
+	Ok(())
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), FileIOError> {
+	let res = match fs::metadata(path).await {
+		Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path).await,
+		Ok(_) => fs::remove_file(path).await,
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+		Err(e) => Err(e),
+	};
+
+	res.map_err(|e| FileIOError::from((path, e)))
+}
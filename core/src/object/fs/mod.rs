@@ -1,4 +1,5 @@
 use crate::{
+	job::JobRunMetadata,
 	location::{
 		file_path_helper::{file_path_with_object, IsolatedFilePathData},
 		LocationError,
@@ -10,6 +11,7 @@ use crate::{
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
+use specta::Type;
 
 pub mod create;
 pub mod delete;
@@ -18,6 +20,8 @@ pub mod erase;
 pub mod copy;
 pub mod cut;
 
+pub mod journal;
+
 // pub mod decrypt;
 // pub mod encrypt;
 
@@ -33,6 +37,50 @@ pub enum ObjectType {
 	Directory,
 }
 
+/// What happened to a single file in a file operation job, kept in the job report so users
+/// can later check what was moved where
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct FileOperationOutcome {
+	pub source: PathBuf,
+	pub target: PathBuf,
+	pub result: FileOperationResult,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileOperationResult {
+	Done,
+	AlreadyAtTarget,
+	SkippedToAvoidOverwrite,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct FileOperationRunMetadata {
+	pub files: Vec<FileOperationOutcome>,
+}
+
+impl JobRunMetadata for FileOperationRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.files.extend(new_data.files);
+	}
+}
+
+impl FileOperationRunMetadata {
+	pub fn single(
+		source: impl Into<PathBuf>,
+		target: impl Into<PathBuf>,
+		result: FileOperationResult,
+	) -> Self {
+		Self {
+			files: vec![FileOperationOutcome {
+				source: source.into(),
+				target: target.into(),
+				result,
+			}],
+		}
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileData {
 	pub file_path: file_path_with_object::Data,
@@ -0,0 +1,353 @@
+//! Reads the originals, capture dates, favorites, keywords and albums of an Apple Photos library,
+//! from the `database/Photos.sqlite` database of its `.photoslibrary` package. Only libraries of
+//! Photos 5 and later, from macOS Catalina, are supported.
+//!
+//! The originals are copied into a location by [`ingest_job`](super::ingest_job), each into a
+//! directory of its capture year, then the location is indexed and the metadata imported by
+//! [`importer_job`](super::importer_job) onto the copies, found by their path.
+
+use crate::{
+	job::{JobBuilder, JobManagerError},
+	library::Library,
+	location::{indexer::indexer_job::IndexerJobInit, location_with_indexer_rules},
+	object::{
+		album::rematerialize_smart_albums,
+		file_identifier::file_identifier_job::FileIdentifierJobInit,
+		preview::thumbnailer_job::ThumbnailerJobInit,
+	},
+	prisma::location,
+	util::db::maybe_missing,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde_json::json;
+
+use super::{
+	importer_job::MetadataImporterJobInit, ingest_job::ApplePhotosIngestJobInit, ImportedMedia,
+	MetadataImportError, MetadataImportSource,
+};
+
+/// Core Data stores dates as seconds since the first of January of 2001
+const CORE_DATA_EPOCH: i64 = 978_307_200;
+
+/// Kind of the albums users create, instead of folders or smart albums
+const USER_ALBUM_KIND: i64 = 2;
+
+/// An original of the library, with where it's copied to
+#[derive(Debug, Clone)]
+pub struct ApplePhotosAsset {
+	pub original_path: PathBuf,
+	pub media: ImportedMedia,
+}
+
+/// The assets of the library at `library_path`, with the path they're copied to in `destination`.
+/// Names are made unique in their directory in the order of the database, so reading the same
+/// library again gives the same paths.
+pub async fn read(
+	library_path: &Path,
+	destination: &Path,
+) -> Result<Vec<ApplePhotosAsset>, MetadataImportError> {
+	let library_path = library_path.to_path_buf();
+	let destination = destination.to_path_buf();
+
+	tokio::task::spawn_blocking(move || read_blocking(&library_path, &destination))
+		.await
+		.expect("reading the Apple Photos database panicked")
+}
+
+fn read_blocking(
+	library_path: &Path,
+	destination: &Path,
+) -> Result<Vec<ApplePhotosAsset>, MetadataImportError> {
+	let conn = Connection::open_with_flags(
+		library_path.join("database").join("Photos.sqlite"),
+		OpenFlags::SQLITE_OPEN_READ_ONLY,
+	)?;
+
+	// Photos 5 named the assets table `ZGENERICASSET`, later versions `ZASSET`
+	let assets_table = if table_exists(&conn, "ZASSET")? {
+		"ZASSET"
+	} else if table_exists(&conn, "ZGENERICASSET")? {
+		"ZGENERICASSET"
+	} else {
+		return Err(MetadataImportError::UnsupportedLibrary(library_path.into()));
+	};
+
+	let albums = read_links(&conn, "ALBUMS", "ASSETS", |table, albums, assets| {
+		format!(
+			"SELECT {table}.{assets}, ZGENERICALBUM.ZTITLE FROM {table}
+			JOIN ZGENERICALBUM ON ZGENERICALBUM.Z_PK = {table}.{albums}
+			WHERE ZGENERICALBUM.ZKIND = {USER_ALBUM_KIND} AND ZGENERICALBUM.ZTRASHEDSTATE = 0
+				AND ZGENERICALBUM.ZTITLE IS NOT NULL"
+		)
+	})?;
+
+	let keywords = read_links(
+		&conn,
+		"KEYWORDS",
+		"ASSETATTRIBUTES",
+		|table, keywords, attributes| {
+			format!(
+				"SELECT ZADDITIONALASSETATTRIBUTES.ZASSET, ZKEYWORD.ZTITLE FROM {table}
+				JOIN ZKEYWORD ON ZKEYWORD.Z_PK = {table}.{keywords}
+				JOIN ZADDITIONALASSETATTRIBUTES
+					ON ZADDITIONALASSETATTRIBUTES.Z_PK = {table}.{attributes}
+				WHERE ZKEYWORD.ZTITLE IS NOT NULL"
+			)
+		},
+	)?;
+
+	let mut stmt = conn.prepare(&format!(
+		"SELECT {assets_table}.Z_PK, {assets_table}.ZDIRECTORY, {assets_table}.ZFILENAME,
+			{assets_table}.ZDATECREATED, {assets_table}.ZFAVORITE,
+			ZADDITIONALASSETATTRIBUTES.ZORIGINALFILENAME
+		FROM {assets_table}
+		LEFT JOIN ZADDITIONALASSETATTRIBUTES
+			ON ZADDITIONALASSETATTRIBUTES.ZASSET = {assets_table}.Z_PK
+		WHERE {assets_table}.ZTRASHEDSTATE = 0
+		ORDER BY {assets_table}.Z_PK"
+	))?;
+
+	let originals_path = library_path.join("originals");
+	let mut taken_paths = HashSet::new();
+	let mut assets = vec![];
+
+	let mut rows = stmt.query([])?;
+	while let Some(row) = rows.next()? {
+		let id = row.get::<_, i64>(0)?;
+		let (Some(directory), Some(file_name)) = (
+			row.get::<_, Option<String>>(1)?,
+			row.get::<_, Option<String>>(2)?,
+		) else {
+			continue;
+		};
+
+		let date_created = row.get::<_, Option<f64>>(3)?.and_then(core_data_date);
+
+		// The originals are named after their uuid, their name when imported is kept aside
+		let name = row
+			.get::<_, Option<String>>(5)?
+			.filter(|name| !name.is_empty())
+			.unwrap_or_else(|| file_name.clone());
+
+		let mut dir = destination.to_path_buf();
+		if let Some(date_created) = &date_created {
+			dir.push(date_created.year().to_string());
+		}
+
+		assets.push(ApplePhotosAsset {
+			original_path: originals_path.join(directory).join(&file_name),
+			media: ImportedMedia {
+				path: unique_path(&mut taken_paths, &dir, &name),
+				tags: keywords.get(&id).cloned().unwrap_or_default(),
+				favorite: row.get::<_, Option<bool>>(4)?.filter(|favorite| *favorite),
+				albums: albums.get(&id).cloned().unwrap_or_default(),
+				date_created,
+				..Default::default()
+			},
+		});
+	}
+
+	Ok(assets)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool, rusqlite::Error> {
+	conn.query_row(
+		"SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+		[name],
+		|_| Ok(()),
+	)
+	.optional()
+	.map(|row| row.is_some())
+}
+
+/// Reads a many-to-many relation of the database, by asset id. Core Data names their join
+/// tables and columns after the number of their entities, which changes between versions of
+/// Photos, like `Z_26ASSETS` with the `Z_26ALBUMS` and `Z_3ASSETS` columns, so they're found by
+/// the suffixes of their columns.
+fn read_links(
+	conn: &Connection,
+	left_suffix: &str,
+	right_suffix: &str,
+	query: impl Fn(&str, &str, &str) -> String,
+) -> Result<HashMap<i64, Vec<String>>, rusqlite::Error> {
+	let tables = conn
+		.prepare(
+			"SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'Z\\_%' ESCAPE '\\'",
+		)?
+		.query_map([], |row| row.get::<_, String>(0))?
+		.collect::<Result<Vec<_>, _>>()?;
+
+	for table in tables {
+		let columns = conn
+			.prepare(&format!("PRAGMA table_info({table})"))?
+			.query_map([], |row| row.get::<_, String>(1))?
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let find = |suffix: &str| {
+			columns.iter().find(|column| {
+				column.len() > 2 + suffix.len()
+					&& column.starts_with("Z_")
+					&& column.ends_with(suffix)
+					&& column[2..column.len() - suffix.len()]
+						.chars()
+						.all(|c| c.is_ascii_digit())
+			})
+		};
+
+		let (Some(left), Some(right)) = (find(left_suffix), find(right_suffix)) else {
+			continue;
+		};
+
+		let mut links = HashMap::<i64, Vec<String>>::new();
+		let mut stmt = conn.prepare(&query(&table, left, right))?;
+		let mut rows = stmt.query([])?;
+		while let Some(row) = rows.next()? {
+			links.entry(row.get(0)?).or_default().push(row.get(1)?);
+		}
+
+		return Ok(links);
+	}
+
+	// Libraries without any album or keyword may not have the table
+	Ok(HashMap::new())
+}
+
+fn core_data_date(seconds: f64) -> Option<DateTime<Utc>> {
+	let whole_seconds = seconds.floor();
+
+	Utc.timestamp_opt(
+		CORE_DATA_EPOCH + whole_seconds as i64,
+		((seconds - whole_seconds) * 1e9) as u32,
+	)
+	.single()
+}
+
+fn unique_path(taken_paths: &mut HashSet<PathBuf>, dir: &Path, name: &str) -> PathBuf {
+	let name = Path::new(name);
+	let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+	let extension = name
+		.extension()
+		.map(|extension| extension.to_string_lossy());
+
+	let mut path = dir.join(name);
+	let mut n = 1;
+	while !taken_paths.insert(path.clone()) {
+		n += 1;
+		path = dir.join(match &extension {
+			Some(extension) => format!("{stem} ({n}).{extension}"),
+			None => format!("{stem} ({n})"),
+		});
+	}
+
+	path
+}
+
+/// Copies the originals of an Apple Photos library into `target_dir` of a location, then
+/// indexes them and imports their metadata, as jobs depending on each other
+pub async fn import_apple_photos(
+	library: &Library,
+	location: location_with_indexer_rules::Data,
+	library_path: PathBuf,
+	target_dir: impl AsRef<Path>,
+) -> Result<(), JobManagerError> {
+	let location_base_data = location::Data::from(&location);
+	let destination = Path::new(maybe_missing(&location.path, "location.path")?).join(target_dir);
+
+	let ingest = JobBuilder::new(ApplePhotosIngestJobInit {
+		location_id: location.id,
+		library_path: library_path.clone(),
+		destination: destination.clone(),
+	})
+	.with_action("import_apple_photos")
+	.with_metadata(json!({
+		"location": location_base_data.clone(),
+		"library_path": library_path.clone(),
+	}));
+
+	let indexer = JobBuilder::new(IndexerJobInit {
+		location,
+		sub_path: Some(destination.clone()),
+	})
+	.with_action("import_apple_photos-1")
+	.with_parent_id(ingest.id())
+	.with_dependency(ingest.id());
+
+	let identifier = JobBuilder::new(FileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: Some(destination.clone()),
+	})
+	.with_action("import_apple_photos-2")
+	.with_parent_id(ingest.id())
+	.with_dependency(indexer.id());
+
+	let importer = JobBuilder::new(MetadataImporterJobInit {
+		source: MetadataImportSource::ApplePhotos {
+			library_path,
+			destination: destination.clone(),
+		},
+	})
+	.with_action("import_apple_photos-3")
+	.with_parent_id(ingest.id())
+	.with_dependency(identifier.id());
+
+	let thumbnailer = JobBuilder::new(ThumbnailerJobInit {
+		location: location_base_data,
+		sub_path: Some(destination),
+	})
+	.with_action("import_apple_photos-4")
+	.with_parent_id(ingest.id())
+	.with_dependency(identifier.id());
+
+	let identifier_id = identifier.id();
+
+	ingest.build().spawn(library).await?;
+	indexer.build().spawn(library).await?;
+	identifier.build().spawn(library).await?;
+	importer.build().spawn(library).await?;
+	thumbnailer.build().spawn(library).await?;
+
+	rematerialize_smart_albums(library, Some(identifier_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn convert_core_data_dates() {
+		assert_eq!(
+			core_data_date(0.0).unwrap().to_rfc3339(),
+			"2001-01-01T00:00:00+00:00"
+		);
+		assert_eq!(
+			core_data_date(-0.5).unwrap().to_rfc3339(),
+			"2000-12-31T23:59:59.500+00:00"
+		);
+	}
+
+	#[test]
+	fn make_paths_unique() {
+		let mut taken_paths = HashSet::new();
+		let dir = Path::new("/photos/2021");
+
+		assert_eq!(
+			unique_path(&mut taken_paths, dir, "IMG_0001.HEIC"),
+			Path::new("/photos/2021/IMG_0001.HEIC")
+		);
+		assert_eq!(
+			unique_path(&mut taken_paths, dir, "IMG_0001.HEIC"),
+			Path::new("/photos/2021/IMG_0001 (2).HEIC")
+		);
+		assert_eq!(
+			unique_path(&mut taken_paths, dir, "IMG_0001.HEIC"),
+			Path::new("/photos/2021/IMG_0001 (3).HEIC")
+		);
+	}
+}
@@ -0,0 +1,142 @@
+//! Reads the tags and ratings of a digiKam collection from its `digikam4.db` SQLite database.
+//! Albums of digiKam are the directories of the files, which Spacedrive already has, so they
+//! aren't imported as albums.
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use super::{ImportedChecksum, ImportedMedia, MetadataImportError};
+
+/// Parent of the tags digiKam uses internally, like the color and pick labels
+const INTERNAL_TAGS_ROOT: &str = "_Digikam_Internal_Tags_";
+
+/// Status of the images that are in the collection, instead of removed or obsolete ones
+const IMAGE_STATUS_VISIBLE: i64 = 1;
+
+pub async fn read(database_path: &Path) -> Result<Vec<ImportedMedia>, MetadataImportError> {
+	let database_path = database_path.to_path_buf();
+
+	tokio::task::spawn_blocking(move || read_blocking(&database_path))
+		.await
+		.expect("reading the digiKam database panicked")
+}
+
+fn read_blocking(database_path: &Path) -> Result<Vec<ImportedMedia>, MetadataImportError> {
+	let conn = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+	let tags = read_tags(&conn)?;
+
+	let mut tags_by_image = HashMap::<i64, Vec<String>>::new();
+	{
+		let mut stmt = conn.prepare("SELECT imageid, tagid FROM ImageTags")?;
+		let mut rows = stmt.query([])?;
+		while let Some(row) = rows.next()? {
+			if let Some(tag) = tags.get(&row.get::<_, i64>(1)?) {
+				tags_by_image
+					.entry(row.get(0)?)
+					.or_default()
+					.push(tag.clone());
+			}
+		}
+	}
+
+	let mut stmt = conn.prepare(
+		"SELECT Images.id, AlbumRoots.identifier, AlbumRoots.specificPath, Albums.relativePath,
+			Images.name, Images.fileSize, Images.uniqueHash, ImageInformation.rating
+		FROM Images
+		JOIN Albums ON Images.album = Albums.id
+		JOIN AlbumRoots ON Albums.albumRoot = AlbumRoots.id
+		LEFT JOIN ImageInformation ON ImageInformation.imageid = Images.id
+		WHERE Images.status = ?",
+	)?;
+
+	let mut media = vec![];
+	let mut rows = stmt.query([IMAGE_STATUS_VISIBLE])?;
+	while let Some(row) = rows.next()? {
+		let image_id = row.get::<_, i64>(0)?;
+		let identifier = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+		let specific_path = row.get::<_, Option<String>>(2)?.unwrap_or_default();
+		let relative_path = row.get::<_, Option<String>>(3)?.unwrap_or_default();
+		let name = row.get::<_, String>(4)?;
+
+		let mut path = album_root_path(&identifier, &specific_path);
+		path.push(relative_path.trim_start_matches('/'));
+		path.push(name);
+
+		media.push(ImportedMedia {
+			path,
+			size_in_bytes: row
+				.get::<_, Option<i64>>(5)?
+				.and_then(|size| size.try_into().ok()),
+			checksum: row
+				.get::<_, Option<String>>(6)?
+				.filter(|hash| !hash.is_empty())
+				.map(ImportedChecksum::DigiKamUniqueHashV2),
+			tags: tags_by_image.remove(&image_id).unwrap_or_default(),
+			// Images without stars have a rating of 0, and of -1 when they were never rated
+			rating: row
+				.get::<_, Option<i32>>(7)?
+				.filter(|rating| (1..=5).contains(rating)),
+			favorite: None,
+			albums: vec![],
+			date_created: None,
+		});
+	}
+
+	Ok(media)
+}
+
+/// The tags by id, with the names of their parents, like `Places/Paris`
+fn read_tags(conn: &Connection) -> Result<HashMap<i64, String>, rusqlite::Error> {
+	let mut stmt = conn.prepare("SELECT id, pid, name FROM Tags")?;
+	let raw_tags = stmt
+		.query_map([], |row| {
+			Ok((
+				row.get::<_, i64>(0)?,
+				(row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?),
+			))
+		})?
+		.collect::<Result<HashMap<_, _>, _>>()?;
+
+	Ok(raw_tags
+		.keys()
+		.filter_map(|&id| {
+			let mut names = vec![];
+			let mut current = Some(id);
+
+			// The depth is bounded in case the hierarchy has a cycle
+			while let Some((parent_id, name)) = current.and_then(|id| raw_tags.get(&id)) {
+				if name == INTERNAL_TAGS_ROOT || names.len() > raw_tags.len() {
+					return None;
+				}
+
+				names.push(name.as_str());
+				current = parent_id.filter(|&parent_id| parent_id != 0);
+			}
+
+			names.reverse();
+
+			Some((id, names.join("/")))
+		})
+		.collect())
+}
+
+/// digiKam identifies the volume of a collection by its path or by its uuid, with the root of
+/// the collection relative to where the volume is mounted. Volumes identified by uuid are
+/// expected to be mounted at `/`, files on other volumes can still be found by checksum.
+fn album_root_path(identifier: &str, specific_path: &str) -> PathBuf {
+	let mount_point = identifier
+		.split_once('?')
+		.into_iter()
+		.flat_map(|(_, query)| query.split('&'))
+		.find_map(|param| param.strip_prefix("path="))
+		.unwrap_or("/");
+
+	let mut path = PathBuf::from(mount_point);
+	path.push(specific_path.trim_start_matches('/'));
+	path
+}
@@ -0,0 +1,444 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::IsolatedFilePathData,
+	object::{album::AlbumCreateArgs, tag::find_or_create_tag},
+	prisma::{album, file_path, location, object, object_in_album, tag, tag_on_object},
+	sync,
+	util::db::maybe_missing,
+};
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::{debug, info};
+
+use super::{apple_photos, digikam, photoprism, ImportedMedia, MetadataImportSource};
+
+const CHUNK_SIZE: usize = 100;
+
+/// Files with the same name and size are hashed to find moved ones, up to this many of them
+const MAX_CHECKSUM_CANDIDATES: i64 = 10;
+
+/// Color of the tags created for the imported ones
+const IMPORTED_TAG_COLOR: &str = "#646278";
+
+/// Applies the organization users had in another photo manager onto the objects of the files it
+/// knew, found by their path or, if they were moved since, by their checksum
+#[derive(Serialize, Deserialize, Type, Hash, Debug)]
+pub struct MetadataImporterJobInit {
+	pub source: MetadataImportSource,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MetadataImporterJobData {
+	/// Locations of this node, the only ones files can be matched in
+	locations: Vec<(location::id::Type, PathBuf)>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct MetadataImporterJobRunMetadata {
+	total_files: usize,
+	matched_by_path: usize,
+	matched_by_checksum: usize,
+	/// Files found in a location, but not identified yet, so without an object to apply to
+	not_identified: usize,
+	not_found: usize,
+}
+
+impl JobRunMetadata for MetadataImporterJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_files += new_data.total_files;
+		self.matched_by_path += new_data.matched_by_path;
+		self.matched_by_checksum += new_data.matched_by_checksum;
+		self.not_identified += new_data.not_identified;
+		self.not_found += new_data.not_found;
+	}
+}
+
+file_path::select!(file_path_to_import {
+	location_id
+	materialized_path
+	name
+	extension
+	object: select { id pub_id }
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for MetadataImporterJobInit {
+	type Data = MetadataImporterJobData;
+	type Step = Vec<ImportedMedia>;
+	type RunMetadata = MetadataImporterJobRunMetadata;
+
+	const NAME: &'static str = "metadata_importer";
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		let media = match &self.source {
+			MetadataImportSource::DigiKam { database_path } => digikam::read(database_path).await,
+			MetadataImportSource::PhotoPrism {
+				export_path,
+				originals_path,
+			} => photoprism::read(export_path, originals_path).await,
+			MetadataImportSource::ApplePhotos {
+				library_path,
+				destination,
+			} => apple_photos::read(library_path, destination)
+				.await
+				.map(|assets| assets.into_iter().map(|asset| asset.media).collect()),
+		}
+		.map_err(|e| JobError::EarlyFinish {
+			name: Self::NAME.to_string(),
+			reason: e.to_string(),
+		})?;
+
+		let locations = db
+			.location()
+			.find_many(vec![location::node_id::equals(Some(
+				ctx.library.node_local_id,
+			))])
+			.select(location::select!({ id path }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|location| Some((location.id, PathBuf::from(location.path?))))
+			.collect();
+
+		*data = Some(MetadataImporterJobData { locations });
+
+		Ok((
+			MetadataImporterJobRunMetadata {
+				total_files: media.len(),
+				..Default::default()
+			},
+			media
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: media, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let library = &ctx.library;
+
+		let mut new_metadata = MetadataImporterJobRunMetadata::default();
+		let mut tag_ids = HashMap::new();
+		let mut album_ids = HashMap::new();
+
+		for item in media {
+			let file_path = match find_by_path(library, &data.locations, &item.path).await? {
+				Some(file_path) => {
+					new_metadata.matched_by_path += 1;
+					file_path
+				}
+				None => match find_by_checksum(library, &data.locations, item).await? {
+					Some(file_path) => {
+						new_metadata.matched_by_checksum += 1;
+						file_path
+					}
+					None => {
+						debug!("No file matches '{}'", item.path.display());
+						new_metadata.not_found += 1;
+						continue;
+					}
+				},
+			};
+
+			let Some(object) = file_path.object else {
+				new_metadata.not_identified += 1;
+				continue;
+			};
+
+			apply(library, item, object, &mut tag_ids, &mut album_ids).await?;
+		}
+
+		let processed = run_metadata.matched_by_path
+			+ run_metadata.matched_by_checksum
+			+ run_metadata.not_identified
+			+ run_metadata.not_found
+			+ media.len();
+
+		ctx.progress_msg(format!(
+			"Imported metadata of {processed} of {} files",
+			run_metadata.total_files
+		));
+
+		Ok(new_metadata.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Imported metadata: {} files matched by path, {} by checksum, {} not identified yet and {} not found",
+			run_metadata.matched_by_path,
+			run_metadata.matched_by_checksum,
+			run_metadata.not_identified,
+			run_metadata.not_found
+		);
+
+		invalidate_query!(ctx.library, "tags.list");
+		invalidate_query!(ctx.library, "tags.getForObject");
+		invalidate_query!(ctx.library, "albums.list");
+		invalidate_query!(ctx.library, "search.objects");
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
+
+async fn find_by_path(
+	library: &Library,
+	locations: &[(location::id::Type, PathBuf)],
+	path: &Path,
+) -> Result<Option<file_path_to_import::Data>, JobError> {
+	let Some((location_id, location_path)) = locations
+		.iter()
+		.find(|(_, location_path)| path.starts_with(location_path))
+	else {
+		return Ok(None);
+	};
+
+	let Ok(iso_file_path) = IsolatedFilePathData::new(*location_id, location_path, path, false)
+	else {
+		return Ok(None);
+	};
+
+	library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_materialized_path_name_extension(
+			*location_id,
+			iso_file_path.materialized_path.to_string(),
+			iso_file_path.name.to_string(),
+			iso_file_path.extension.to_string(),
+		))
+		.select(file_path_to_import::select())
+		.exec()
+		.await
+		.map_err(Into::into)
+}
+
+/// Looks for the file among the ones with the same name and size, as moved files usually keep
+/// them, then hashes them like the other app did to make sure it's the same file
+async fn find_by_checksum(
+	library: &Library,
+	locations: &[(location::id::Type, PathBuf)],
+	media: &ImportedMedia,
+) -> Result<Option<file_path_to_import::Data>, JobError> {
+	let (Some(checksum), Some(size_in_bytes)) = (&media.checksum, media.size_in_bytes) else {
+		return Ok(None);
+	};
+
+	let name = media
+		.path
+		.file_stem()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_default();
+	let extension = media
+		.path
+		.extension()
+		.map(|extension| extension.to_string_lossy().to_string())
+		.unwrap_or_default();
+
+	let candidates = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::in_vec(locations.iter().map(|(id, _)| *id).collect()),
+			file_path::name::equals(Some(name)),
+			file_path::extension::equals(Some(extension)),
+			file_path::size_in_bytes_bytes::equals(Some(size_in_bytes.to_be_bytes().to_vec())),
+		])
+		.take(MAX_CHECKSUM_CANDIDATES)
+		.select(file_path_to_import::select())
+		.exec()
+		.await?;
+
+	for candidate in candidates {
+		let Some((_, location_path)) = locations
+			.iter()
+			.find(|(id, _)| Some(*id) == candidate.location_id)
+		else {
+			continue;
+		};
+
+		let mut full_path = location_path.join(
+			maybe_missing(&candidate.materialized_path, "file_path.materialized_path")?
+				.trim_start_matches('/'),
+		);
+		match candidate.extension.as_deref() {
+			Some(extension) if !extension.is_empty() => full_path.push(format!(
+				"{}.{extension}",
+				maybe_missing(&candidate.name, "file_path.name")?
+			)),
+			_ => full_path.push(maybe_missing(&candidate.name, "file_path.name")?),
+		}
+
+		match checksum.matches(&full_path).await {
+			Ok(true) => return Ok(Some(candidate)),
+			Ok(false) => {}
+			// The file may have been removed since it was indexed
+			Err(e) => debug!(
+				"Failed to hash a candidate of '{}': {e:#?}",
+				media.path.display()
+			),
+		}
+	}
+
+	Ok(None)
+}
+
+async fn apply(
+	library: &Library,
+	media: &ImportedMedia,
+	object: file_path_to_import::object::Data,
+	tag_ids: &mut HashMap<String, tag::id::Type>,
+	album_ids: &mut HashMap<String, album::id::Type>,
+) -> Result<(), JobError> {
+	let Library { db, sync, .. } = library;
+
+	for name in &media.tags {
+		let tag_id = match tag_ids.get(name) {
+			Some(tag_id) => *tag_id,
+			None => {
+				let tag_id = find_or_create_tag(library, name, IMPORTED_TAG_COLOR).await?;
+
+				tag_ids.insert(name.clone(), tag_id);
+				tag_id
+			}
+		};
+
+		// Upserting keeps the tags the object already had, like `batch.apply`
+		db.tag_on_object()
+			.upsert(
+				tag_on_object::tag_id_object_id(tag_id, object.id),
+				tag_on_object::create(
+					tag::id::equals(tag_id),
+					object::id::equals(object.id),
+					vec![],
+				),
+				vec![],
+			)
+			.exec()
+			.await?;
+	}
+
+	for name in &media.albums {
+		let album_id = match album_ids.get(name) {
+			Some(album_id) => *album_id,
+			None => {
+				let album_id = match db
+					.album()
+					.find_first(vec![
+						album::name::equals(Some(name.clone())),
+						album::search_query::equals(None),
+					])
+					.select(album::select!({ id }))
+					.exec()
+					.await?
+				{
+					Some(album) => album.id,
+					None => {
+						AlbumCreateArgs {
+							name: name.clone(),
+							search_query: None,
+						}
+						.exec(library)
+						.await?
+						.id
+					}
+				};
+
+				album_ids.insert(name.clone(), album_id);
+				album_id
+			}
+		};
+
+		db.object_in_album()
+			.upsert(
+				object_in_album::album_id_object_id(album_id, object.id),
+				object_in_album::create(
+					album::id::equals(album_id),
+					object::id::equals(object.id),
+					vec![object_in_album::date_created::set(Some(Utc::now().into()))],
+				),
+				vec![],
+			)
+			.exec()
+			.await?;
+	}
+
+	if let Some(rating) = media.rating {
+		sync.write_op(
+			db,
+			sync.shared_update(
+				sync::object::SyncId {
+					pub_id: object.pub_id.clone(),
+				},
+				object::rating::NAME,
+				json!(rating),
+			),
+			db.object().update(
+				object::id::equals(object.id),
+				vec![object::rating::set(Some(rating))],
+			),
+		)
+		.await?;
+	}
+
+	if let Some(date_created) = media.date_created {
+		sync.write_op(
+			db,
+			sync.shared_update(
+				sync::object::SyncId {
+					pub_id: object.pub_id.clone(),
+				},
+				object::date_created::NAME,
+				json!(date_created),
+			),
+			db.object().update(
+				object::id::equals(object.id),
+				vec![object::date_created::set(Some(date_created.into()))],
+			),
+		)
+		.await?;
+	}
+
+	if let Some(favorite) = media.favorite {
+		db.object()
+			.update(
+				object::id::equals(object.id),
+				vec![object::favorite::set(Some(favorite))],
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
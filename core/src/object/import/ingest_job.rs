@@ -0,0 +1,190 @@
+use crate::{
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	prisma::location,
+	util::error::FileIOError,
+};
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use filetime::FileTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::fs;
+use tracing::info;
+
+use super::apple_photos;
+
+const CHUNK_SIZE: usize = 50;
+
+/// Copies the originals of an Apple Photos library into a directory of a location, see
+/// [`apple_photos::import_apple_photos`]
+#[derive(Serialize, Deserialize, Type, Hash, Debug)]
+pub struct ApplePhotosIngestJobInit {
+	pub location_id: location::id::Type,
+	pub library_path: PathBuf,
+	pub destination: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApplePhotosIngestJobStep {
+	source: PathBuf,
+	target: PathBuf,
+	date_created: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ApplePhotosIngestJobRunMetadata {
+	total_files: usize,
+	copied_files: usize,
+	/// Files copied by a previous import of the same library
+	skipped_files: usize,
+	failed_files: usize,
+}
+
+impl JobRunMetadata for ApplePhotosIngestJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_files += new_data.total_files;
+		self.copied_files += new_data.copied_files;
+		self.skipped_files += new_data.skipped_files;
+		self.failed_files += new_data.failed_files;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ApplePhotosIngestJobInit {
+	type Data = ();
+	type Step = Vec<ApplePhotosIngestJobStep>;
+	type RunMetadata = ApplePhotosIngestJobRunMetadata;
+
+	const NAME: &'static str = "apple_photos_ingest";
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location_id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		_: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let steps = apple_photos::read(&self.library_path, &self.destination)
+			.await
+			.map_err(|e| JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: e.to_string(),
+			})?
+			.into_iter()
+			.map(|asset| ApplePhotosIngestJobStep {
+				source: asset.original_path,
+				target: asset.media.path,
+				date_created: asset.media.date_created,
+			})
+			.collect::<Vec<_>>();
+
+		ctx.progress_msg(format!("Copying {} photos and videos", steps.len()));
+
+		Ok((
+			ApplePhotosIngestJobRunMetadata {
+				total_files: steps.len(),
+				..Default::default()
+			},
+			steps
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: files, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let mut new_metadata = ApplePhotosIngestJobRunMetadata::default();
+		let mut errors = vec![];
+
+		for file in files {
+			match copy(file).await {
+				Ok(true) => new_metadata.copied_files += 1,
+				Ok(false) => new_metadata.skipped_files += 1,
+				Err(e) => {
+					new_metadata.failed_files += 1;
+					errors.push(e.to_string());
+				}
+			}
+		}
+
+		ctx.progress_msg(format!(
+			"Copied {} of {} photos and videos",
+			run_metadata.copied_files
+				+ run_metadata.skipped_files
+				+ run_metadata.failed_files
+				+ files.len(),
+			run_metadata.total_files
+		));
+
+		Ok((vec![], new_metadata, errors.into()).into())
+	}
+
+	async fn finalize(
+		&self,
+		_: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Copied {} files of the Apple Photos library '{}', {} were already copied and {} failed",
+			run_metadata.copied_files,
+			self.library_path.display(),
+			run_metadata.skipped_files,
+			run_metadata.failed_files
+		);
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
+
+/// Copies a file, unless a previous import already did, giving it the capture date as
+/// modification date. Returns whether the file was copied.
+async fn copy(file: &ApplePhotosIngestJobStep) -> Result<bool, FileIOError> {
+	let source_metadata = fs::metadata(&file.source)
+		.await
+		.map_err(|e| FileIOError::from((&file.source, e)))?;
+
+	if let Ok(target_metadata) = fs::metadata(&file.target).await {
+		if target_metadata.len() == source_metadata.len() {
+			return Ok(false);
+		}
+	}
+
+	if let Some(parent) = file.target.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e)))?;
+	}
+
+	fs::copy(&file.source, &file.target)
+		.await
+		.map_err(|e| FileIOError::from((&file.target, e)))?;
+
+	if let Some(date_created) = file.date_created {
+		filetime::set_file_mtime(
+			&file.target,
+			FileTime::from_unix_time(
+				date_created.timestamp(),
+				date_created.timestamp_subsec_nanos(),
+			),
+		)
+		.map_err(|e| FileIOError::from((&file.target, e)))?;
+	}
+
+	Ok(true)
+}
@@ -0,0 +1,177 @@
+//! Importers of the organization users built in other photo managers, so they keep their tags,
+//! ratings, favorites and albums when migrating to Spacedrive.
+
+use crate::util::error::FileIOError;
+
+use std::{
+	io::SeekFrom,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncSeekExt},
+};
+
+pub mod apple_photos;
+pub mod digikam;
+pub mod importer_job;
+pub mod ingest_job;
+pub mod photoprism;
+
+/// Where the metadata is imported from
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Hash)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MetadataImportSource {
+	/// The `digikam4.db` database of a digiKam collection
+	#[serde(rename_all = "camelCase")]
+	DigiKam { database_path: PathBuf },
+	/// A directory exported from the PhotoPrism API, see [`photoprism`]
+	#[serde(rename_all = "camelCase")]
+	PhotoPrism {
+		export_path: PathBuf,
+		/// Where the originals directory of PhotoPrism is on this node
+		originals_path: PathBuf,
+	},
+	/// A `.photoslibrary` package, whose originals were copied to `destination` by
+	/// [`apple_photos::import_apple_photos`]
+	#[serde(rename_all = "camelCase")]
+	ApplePhotos {
+		library_path: PathBuf,
+		destination: PathBuf,
+	},
+}
+
+#[derive(Error, Debug)]
+pub enum MetadataImportError {
+	/// Reading the SQLite database of digiKam or Apple Photos failed
+	#[error("failed to read the database of the library: {0}")]
+	Sqlite(#[from] rusqlite::Error),
+	#[error("invalid PhotoPrism export '{}': {1}", .0.display())]
+	PhotoPrism(Box<Path>, serde_json::Error),
+	#[error("unsupported Apple Photos library '{}', only the ones of Photos 5 and later are", .0.display())]
+	UnsupportedLibrary(Box<Path>),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+/// Organization of a single file in the app the metadata is imported from
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ImportedMedia {
+	/// Where the file was when it was organized in the other app
+	pub path: PathBuf,
+	pub size_in_bytes: Option<u64>,
+	pub checksum: Option<ImportedChecksum>,
+	pub tags: Vec<String>,
+	/// Between 1 and 5 stars
+	pub rating: Option<i32>,
+	pub favorite: Option<bool>,
+	pub albums: Vec<String>,
+	/// When the photo was taken, which Spacedrive has no other way to know for files without
+	/// EXIF data
+	pub date_created: Option<DateTime<Utc>>,
+}
+
+/// Checksums the other apps identify files with, to find the ones that were moved since
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ImportedChecksum {
+	/// MD5 of the first and last 100 KiB and the size of the file, by digiKam
+	DigiKamUniqueHashV2(String),
+	/// SHA-1 of the whole file, by PhotoPrism
+	Sha1(String),
+}
+
+/// Size of the parts of the files digiKam hashes
+const DIGIKAM_HASH_PART_SIZE: u64 = 100 * 1024;
+
+impl ImportedChecksum {
+	/// Whether the file at `path` has this checksum
+	pub async fn matches(&self, path: impl AsRef<Path>) -> Result<bool, FileIOError> {
+		let path = path.as_ref();
+
+		let checksum = match self {
+			Self::DigiKamUniqueHashV2(_) => digikam_unique_hash_v2(path).await?,
+			Self::Sha1(_) => sha1(path).await?,
+		};
+
+		Ok(match self {
+			Self::DigiKamUniqueHashV2(expected) | Self::Sha1(expected) => {
+				checksum.eq_ignore_ascii_case(expected)
+			}
+		})
+	}
+}
+
+async fn digikam_unique_hash_v2(path: &Path) -> Result<String, FileIOError> {
+	let mut file = fs::File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	let size = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.len();
+
+	let mut hasher = Md5::new();
+	let mut buf = vec![0; DIGIKAM_HASH_PART_SIZE as usize];
+
+	let read = read_up_to(&mut file, &mut buf)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	hasher.update(&buf[..read]);
+
+	if size > DIGIKAM_HASH_PART_SIZE {
+		file.seek(SeekFrom::Start(size - DIGIKAM_HASH_PART_SIZE))
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		let read = read_up_to(&mut file, &mut buf)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		hasher.update(&buf[..read]);
+	}
+
+	hasher.update(size.to_string().as_bytes());
+
+	Ok(hex::encode(hasher.finalize()))
+}
+
+async fn sha1(path: &Path) -> Result<String, FileIOError> {
+	let mut file = fs::File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	let mut hasher = Sha1::new();
+	let mut buf = vec![0; 1024 * 1024];
+	loop {
+		let read = file
+			.read(&mut buf)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		if read == 0 {
+			break;
+		}
+		hasher.update(&buf[..read]);
+	}
+
+	Ok(hex::encode(hasher.finalize()))
+}
+
+/// Fills `buf` unless the file ends before
+async fn read_up_to(file: &mut fs::File, buf: &mut [u8]) -> std::io::Result<usize> {
+	let mut read = 0;
+	while read < buf.len() {
+		match file.read(&mut buf[read..]).await? {
+			0 => break,
+			n => read += n,
+		}
+	}
+
+	Ok(read)
+}
@@ -0,0 +1,126 @@
+//! Reads the favorites, labels and albums of a PhotoPrism library from an export of its API,
+//! which is a directory with:
+//! - `photos.json`, the photos of the library as returned by `GET /api/v1/photos`
+//! - `albums/{album name}.json`, the photos of each album, from `GET /api/v1/photos?album={uid}`
+//! - `labels/{label name}.json`, the photos of each label, from `GET /api/v1/photos?label={slug}`
+//!
+//! Labels become tags, as PhotoPrism has no star ratings to import.
+
+use crate::util::error::FileIOError;
+
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+use tokio::fs;
+
+use super::{ImportedChecksum, ImportedMedia, MetadataImportError};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Photo {
+	#[serde(rename = "UID")]
+	uid: String,
+	/// Path of the primary file, relative to the originals directory
+	file_name: Option<String>,
+	/// SHA-1 of the primary file
+	hash: Option<String>,
+	#[serde(default)]
+	favorite: bool,
+	#[serde(default)]
+	files: Vec<PhotoFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PhotoFile {
+	#[serde(default)]
+	primary: bool,
+	size: Option<u64>,
+}
+
+pub async fn read(
+	export_path: &Path,
+	originals_path: &Path,
+) -> Result<Vec<ImportedMedia>, MetadataImportError> {
+	let mut media = HashMap::new();
+
+	for photo in read_photos(&export_path.join("photos.json")).await? {
+		let Some(file_name) = &photo.file_name else {
+			continue;
+		};
+
+		media.insert(
+			photo.uid,
+			ImportedMedia {
+				path: originals_path.join(file_name),
+				size_in_bytes: photo
+					.files
+					.iter()
+					.find(|file| file.primary)
+					.and_then(|file| file.size),
+				checksum: photo.hash.map(ImportedChecksum::Sha1),
+				favorite: photo.favorite.then_some(true),
+				..Default::default()
+			},
+		);
+	}
+
+	for (name, photos) in read_collections(&export_path.join("albums")).await? {
+		for photo in photos {
+			if let Some(media) = media.get_mut(&photo.uid) {
+				media.albums.push(name.clone());
+			}
+		}
+	}
+
+	for (name, photos) in read_collections(&export_path.join("labels")).await? {
+		for photo in photos {
+			if let Some(media) = media.get_mut(&photo.uid) {
+				media.tags.push(name.clone());
+			}
+		}
+	}
+
+	Ok(media.into_values().collect())
+}
+
+async fn read_photos(path: &Path) -> Result<Vec<Photo>, MetadataImportError> {
+	let bytes = fs::read(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	serde_json::from_slice(&bytes).map_err(|e| MetadataImportError::PhotoPrism(path.into(), e))
+}
+
+/// The photos of each JSON file of a directory, by the name of the file. The directory is
+/// optional in the export, as libraries may have no albums or labels.
+async fn read_collections(dir: &Path) -> Result<Vec<(String, Vec<Photo>)>, MetadataImportError> {
+	let mut read_dir = match fs::read_dir(dir).await {
+		Ok(read_dir) => read_dir,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(FileIOError::from((dir, e)).into()),
+	};
+
+	let mut collections = vec![];
+	while let Some(entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?
+	{
+		let path = entry.path();
+		if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+			continue;
+		}
+
+		let Some(name) = path
+			.file_stem()
+			.map(|name| name.to_string_lossy().to_string())
+		else {
+			continue;
+		};
+
+		collections.push((name, read_photos(&path).await?));
+	}
+
+	Ok(collections)
+}
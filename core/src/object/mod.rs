@@ -3,13 +3,24 @@ use crate::prisma::{file_path, object};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+pub mod album;
+pub mod antivirus;
 pub mod cas;
+pub mod comment;
+pub mod content_cache;
+pub mod custom_field;
+pub mod embedded_metadata;
+pub mod export;
 pub mod file_identifier;
 pub mod fs;
+pub mod import;
 pub mod orphan_remover;
 pub mod preview;
+pub mod sensitive;
 pub mod tag;
+pub mod undo;
 pub mod validation;
+pub mod xmp;
 
 // Objects are primarily created by the identifier from Paths
 // Some Objects are purely virtual, unless they have one or more associated Paths, which refer to a file found in a Location
@@ -0,0 +1,256 @@
+use crate::{
+	library::Library,
+	prisma::{file_path, SortOrder},
+	util::error::FileIOError,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+	time::SystemTime,
+};
+
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::fs;
+use tracing::{debug, info};
+
+use super::{ThumbnailFormat, ThumbnailerError, THUMBNAIL_CACHE_DIR_NAME};
+
+/// How many cas_ids are fetched from the database at once
+const CAS_IDS_BATCH_SIZE: i64 = 10_000;
+
+/// Once over the limit, thumbnails are evicted until the cache is this fraction of the limit,
+/// so eviction doesn't run again as soon as a few new thumbnails are generated
+const EVICTION_TARGET_RATIO: f64 = 0.9;
+
+#[serde_as]
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailCacheStats {
+	/// Files of this library with a thumbnail, a waveform or a text preview
+	pub thumbnails_count: u32,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub max_size_in_bytes: Option<u64>,
+}
+
+/// Every tier of the thumbnail of a cas_id
+struct CacheEntry {
+	paths: Vec<PathBuf>,
+	size_in_bytes: u64,
+	/// Latest modification time of its tiers, which is bumped every time one is served
+	last_used: SystemTime,
+}
+
+/// Thumbnails of the library's files, found by matching the cache content with its cas_ids.
+///
+/// The thumbnail cache is shared by every library of the node, so a thumbnail can belong to
+/// many libraries at once.
+async fn library_cache_entries(
+	library: &Library,
+) -> Result<HashMap<String, CacheEntry>, ThumbnailerError> {
+	let mut cas_ids = HashSet::new();
+	let mut last_id = None;
+
+	loop {
+		let mut params = vec![file_path::cas_id::not(None)];
+		if let Some(last_id) = last_id {
+			params.push(file_path::id::gt(last_id));
+		}
+
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(params)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(CAS_IDS_BATCH_SIZE)
+			.select(file_path::select!({ id cas_id }))
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		cas_ids.extend(
+			file_paths
+				.into_iter()
+				.filter_map(|file_path| file_path.cas_id),
+		);
+	}
+
+	let thumbnail_dir = library.data_directory().join(THUMBNAIL_CACHE_DIR_NAME);
+
+	let mut entries = HashMap::<String, CacheEntry>::new();
+
+	let mut shards = fs::read_dir(&thumbnail_dir)
+		.await
+		.map_err(|e| FileIOError::from((&thumbnail_dir, e)))?;
+
+	while let Some(shard) = shards
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((&thumbnail_dir, e)))?
+	{
+		let shard_path = shard.path();
+		if !shard_path.is_dir() {
+			continue;
+		}
+
+		let mut thumbnails = fs::read_dir(&shard_path)
+			.await
+			.map_err(|e| FileIOError::from((&shard_path, e)))?;
+
+		while let Some(thumbnail) = thumbnails
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&shard_path, e)))?
+		{
+			let path = thumbnail.path();
+			let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+				continue;
+			};
+
+			// Tiers other than the grid one have a suffix after the cas_id
+			let cas_id = stem.split_once('-').map_or(stem, |(cas_id, _)| cas_id);
+
+			if !cas_ids.contains(cas_id) {
+				continue;
+			}
+
+			let metadata = thumbnail
+				.metadata()
+				.await
+				.map_err(|e| FileIOError::from((&path, e)))?;
+
+			let entry = entries
+				.entry(cas_id.to_string())
+				.or_insert_with(|| CacheEntry {
+					paths: vec![],
+					size_in_bytes: 0,
+					last_used: SystemTime::UNIX_EPOCH,
+				});
+
+			if let Ok(modified) = metadata.modified() {
+				entry.last_used = entry.last_used.max(modified);
+			}
+			entry.size_in_bytes += metadata.len();
+			entry.paths.push(path);
+		}
+	}
+
+	Ok(entries)
+}
+
+pub async fn thumbnail_cache_stats(
+	library: &Library,
+) -> Result<ThumbnailCacheStats, ThumbnailerError> {
+	let entries = library_cache_entries(library).await?;
+
+	Ok(ThumbnailCacheStats {
+		thumbnails_count: entries.len() as u32,
+		size_in_bytes: entries.values().map(|entry| entry.size_in_bytes).sum(),
+		max_size_in_bytes: library.config.thumbnails.max_cache_size_in_bytes(),
+	})
+}
+
+/// Removes every thumbnail of the library's files, returning how many bytes were freed
+pub async fn clear_thumbnail_cache(library: &Library) -> Result<u64, ThumbnailerError> {
+	let entries = library_cache_entries(library).await?;
+
+	let mut freed = 0;
+	for entry in entries.into_values() {
+		remove_entry(&entry).await?;
+		freed += entry.size_in_bytes;
+	}
+
+	info!(
+		"Cleared {freed} bytes of thumbnails of library {}",
+		library.id
+	);
+
+	Ok(freed)
+}
+
+/// Evicts the least recently used thumbnails of the library if its cache is over the limit
+/// set in its settings, returning how many bytes were freed
+pub async fn enforce_thumbnail_cache_limit(library: &Library) -> Result<u64, ThumbnailerError> {
+	let Some(max_size) = library.config.thumbnails.max_cache_size_in_bytes() else {
+		return Ok(0);
+	};
+
+	let entries = library_cache_entries(library).await?;
+
+	let mut size = entries
+		.values()
+		.map(|entry| entry.size_in_bytes)
+		.sum::<u64>();
+	if size <= max_size {
+		return Ok(0);
+	}
+
+	let target_size = (max_size as f64 * EVICTION_TARGET_RATIO) as u64;
+
+	let mut entries = entries.into_values().collect::<Vec<_>>();
+	entries.sort_unstable_by_key(|entry| entry.last_used);
+
+	let mut freed = 0;
+	for entry in entries {
+		if size <= target_size {
+			break;
+		}
+
+		remove_entry(&entry).await?;
+		size -= entry.size_in_bytes;
+		freed += entry.size_in_bytes;
+	}
+
+	debug!(
+		"Evicted {freed} bytes of thumbnails of library {}, over its {max_size} bytes limit",
+		library.id
+	);
+
+	Ok(freed)
+}
+
+/// Thumbnails of the library's files encoded in another format than its current one, with the
+/// paths of their stale tiers, by cas_id
+pub(super) async fn stale_format_thumbnails(
+	library: &Library,
+) -> Result<HashMap<String, Vec<PathBuf>>, ThumbnailerError> {
+	let format = library.config.thumbnails.format;
+
+	Ok(library_cache_entries(library)
+		.await?
+		.into_iter()
+		.filter_map(|(cas_id, entry)| {
+			let stale_paths = entry
+				.paths
+				.into_iter()
+				// Waveforms and text previews are stored with the thumbnails, but aren't images
+				.filter(|path| {
+					ThumbnailFormat::from_path(path)
+						.map_or(false, |path_format| path_format != format)
+				})
+				.collect::<Vec<_>>();
+
+			(!stale_paths.is_empty()).then_some((cas_id, stale_paths))
+		})
+		.collect())
+}
+
+async fn remove_entry(entry: &CacheEntry) -> Result<(), FileIOError> {
+	for path in &entry.paths {
+		fs::remove_file(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+	}
+
+	Ok(())
+}
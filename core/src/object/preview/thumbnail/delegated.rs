@@ -0,0 +1,186 @@
+use super::{
+	inner_process_step, preferences::skip_oversized_files, ThumbnailFormat, ThumbnailerError,
+	ThumbnailerJobStep, ThumbnailerJobStepKind, ThumbnailerPreferences,
+};
+use crate::{
+	job::JobError,
+	library::Library,
+	location::file_path_helper::{
+		ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+		file_path_for_thumbnailer, IsolatedFilePathData,
+	},
+	object::preview::thumbnail::{get_shard_hex, init_thumbnail_dir},
+	prisma::{file_path, location, PrismaClient},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use sd_file_ext::extensions::Extension;
+use std::path::{Path, PathBuf};
+use tokio::{fs, io};
+use tracing::{debug, trace};
+
+/// Thumbnails generated on behalf of a paired node, which doesn't have the resources (or
+/// battery) to do it by itself. The thumbnails are written to our own cache, like the
+/// thumbnailer job would do, and read back to be streamed to the requesting node.
+pub struct DelegatedThumbnailer {
+	location: location::Data,
+	location_path: PathBuf,
+	thumbnail_dir: PathBuf,
+	steps: Vec<ThumbnailerJobStep>,
+	preferences: ThumbnailerPreferences,
+}
+
+impl DelegatedThumbnailer {
+	pub async fn new(
+		location: location::Data,
+		sub_path: Option<&Path>,
+		library: &Library,
+	) -> Result<Self, JobError> {
+		let thumbnail_dir = init_thumbnail_dir(library.data_directory()).await?;
+
+		let location_path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+
+		// The location may be shared with the requesting node, but not mounted on this one
+		fs::metadata(&location_path)
+			.await
+			.map_err(|e| FileIOError::from((&location_path, e)))?;
+
+		let iso_file_path = match sub_path {
+			Some(sub_path) if sub_path != Path::new("") => {
+				let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+					.await
+					.map_err(ThumbnailerError::from)?;
+				ensure_sub_path_is_directory(&location_path, sub_path)
+					.await
+					.map_err(ThumbnailerError::from)?;
+
+				let sub_iso_file_path =
+					IsolatedFilePathData::new(location.id, &location_path, &full_path, true)
+						.map_err(ThumbnailerError::from)?;
+
+				ensure_file_path_exists(
+					sub_path,
+					&sub_iso_file_path,
+					&library.db,
+					ThumbnailerError::SubPathNotFound,
+				)
+				.await?;
+
+				sub_iso_file_path
+			}
+			_ => IsolatedFilePathData::new(location.id, &location_path, &location_path, true)
+				.map_err(ThumbnailerError::from)?,
+		};
+
+		debug!(
+			"Searching for files to thumbnail on behalf of a peer in location {} at directory {iso_file_path}",
+			location.id
+		);
+
+		let preferences = library.config().get().await.thumbnailer;
+
+		// Waveforms and text previews aren't sent back to the peer, they're cheap enough to be
+		// generated by it
+		let mut steps = vec![];
+		for (kind, extensions) in preferences.enabled_kinds().into_iter().filter(|(kind, _)| {
+			!matches!(
+				kind,
+				ThumbnailerJobStepKind::Audio | ThumbnailerJobStepKind::Text
+			)
+		}) {
+			steps.extend(
+				get_files_by_extensions(&library.db, &iso_file_path, &extensions, kind).await?,
+			);
+		}
+
+		let steps = skip_oversized_files(steps, preferences.max_file_size(&location, library));
+
+		trace!(
+			"Found {} files to thumbnail on behalf of a peer",
+			steps.len()
+		);
+
+		Ok(Self {
+			location,
+			location_path,
+			thumbnail_dir,
+			steps,
+			preferences,
+		})
+	}
+
+	pub fn len(&self) -> usize {
+		self.steps.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.steps.is_empty()
+	}
+
+	/// Generates the thumbnail of the next file, if needed, returning its cas_id and the encoded
+	/// thumbnail, which is missing if the generation failed. `Ok(None)` means there are no more
+	/// files to process.
+	pub async fn next(
+		&mut self,
+		library: &Library,
+	) -> Result<Option<(String, Option<(ThumbnailFormat, Vec<u8>)>)>, JobError> {
+		// Files without a cas_id weren't identified yet, so they can't have a thumbnail
+		let (step, cas_id) = loop {
+			let Some(step) = self.steps.pop() else {
+				return Ok(None);
+			};
+
+			if let Some(cas_id) = step.file_path.cas_id.clone() {
+				break (step, cas_id);
+			}
+		};
+
+		inner_process_step(
+			&step,
+			&self.location_path,
+			&self.thumbnail_dir,
+			&self.location,
+			library,
+			&self.preferences,
+		)
+		.await?;
+
+		let format = library.config.thumbnails.format;
+		let thumbnail_path = self
+			.thumbnail_dir
+			.join(get_shard_hex(&cas_id))
+			.join(format!("{cas_id}.{}", format.extension()));
+
+		match fs::read(&thumbnail_path).await {
+			Ok(thumbnail) => Ok(Some((cas_id, Some((format, thumbnail))))),
+			// The thumbnailer failed to generate this one, it was already logged
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some((cas_id, None))),
+			Err(e) => Err(ThumbnailerError::from(FileIOError::from((thumbnail_path, e))).into()),
+		}
+	}
+}
+
+async fn get_files_by_extensions(
+	db: &PrismaClient,
+	iso_file_path: &IsolatedFilePathData<'_>,
+	extensions: &[Extension],
+	kind: ThumbnailerJobStepKind,
+) -> Result<Vec<ThumbnailerJobStep>, JobError> {
+	Ok(db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(iso_file_path.location_id())),
+			file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
+			file_path::materialized_path::starts_with(
+				iso_file_path
+					.materialized_path_for_children()
+					.expect("sub path iso_file_path must be a directory"),
+			),
+		])
+		.select(file_path_for_thumbnailer::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| ThumbnailerJobStep { file_path, kind })
+		.collect())
+}
@@ -0,0 +1,208 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::file_path_for_thumbnailer,
+	object::preview::thumbnail::directory::init_thumbnail_dir,
+	prisma::{file_path, location},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use std::{hash::Hash, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, io};
+use tracing::{debug, info};
+
+use super::{
+	get_shard_hex, inner_process_step, stale_format_thumbnails, ThumbnailerJobStep,
+	ThumbnailerPreferences,
+};
+
+/// `ThumbnailFormatMigratorJobInit` regenerates, in the library's current format, the thumbnails
+/// of its files which were encoded in another one, so changing the format doesn't leave the
+/// cache with a mix of both.
+///
+/// Thumbnails are generated again from the original files, as decoding the old thumbnail would
+/// lower their quality. Those whose original file isn't reachable from this node are kept as is.
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ThumbnailFormatMigratorJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailFormatMigratorJobData {
+	thumbnail_dir: PathBuf,
+	preferences: ThumbnailerPreferences,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailFormatMigratorJobStep {
+	cas_id: String,
+	stale_paths: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ThumbnailFormatMigratorJobRunMetadata {
+	thumbnails_migrated: u32,
+	thumbnails_kept: u32,
+}
+
+impl JobRunMetadata for ThumbnailFormatMigratorJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.thumbnails_migrated += new_data.thumbnails_migrated;
+		self.thumbnails_kept += new_data.thumbnails_kept;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailFormatMigratorJobInit {
+	type Data = ThumbnailFormatMigratorJobData;
+	type Step = ThumbnailFormatMigratorJobStep;
+	type RunMetadata = ThumbnailFormatMigratorJobRunMetadata;
+
+	const NAME: &'static str = "thumbnail_format_migrator";
+	const IS_BACKGROUND: bool = true;
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let thumbnail_dir = init_thumbnail_dir(ctx.library.data_directory()).await?;
+
+		let steps = stale_format_thumbnails(&ctx.library)
+			.await?
+			.into_iter()
+			.map(|(cas_id, stale_paths)| ThumbnailFormatMigratorJobStep {
+				cas_id,
+				stale_paths,
+			})
+			.collect::<Vec<_>>();
+
+		ctx.progress_msg(format!("Preparing to re-encode {} thumbnails", steps.len()));
+
+		*data = Some(ThumbnailFormatMigratorJobData {
+			thumbnail_dir,
+			preferences: ctx.library.config().get().await.thumbnailer,
+		});
+
+		Ok((ThumbnailFormatMigratorJobRunMetadata::default(), steps).into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let ThumbnailFormatMigratorJobStep {
+			cas_id,
+			stale_paths,
+		} = step;
+
+		let mut new_metadata = Self::RunMetadata::default();
+
+		if regenerate_thumbnail(&ctx.library, data, cas_id).await? {
+			for path in stale_paths {
+				match fs::remove_file(path).await {
+					Ok(()) => {}
+					Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+					Err(e) => return Err(FileIOError::from((path, e)).into()),
+				}
+			}
+
+			new_metadata.thumbnails_migrated += 1;
+		} else {
+			debug!("Keeping the stale thumbnail of {cas_id}, as it couldn't be regenerated");
+			new_metadata.thumbnails_kept += 1;
+		}
+
+		Ok(new_metadata.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Re-encoded {} thumbnails of library {}, kept {} stale ones",
+			run_metadata.thumbnails_migrated, ctx.library.id, run_metadata.thumbnails_kept
+		);
+
+		if run_metadata.thumbnails_migrated > 0 {
+			invalidate_query!(ctx.library, "search.paths");
+			invalidate_query!(ctx.library, "cache.stats");
+		}
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
+
+/// Generates the thumbnail of `cas_id` in the library's format, from any of its files found in
+/// a location of this node, returning if it now exists
+async fn regenerate_thumbnail(
+	library: &Library,
+	data: &ThumbnailFormatMigratorJobData,
+	cas_id: &str,
+) -> Result<bool, JobError> {
+	let Library { db, .. } = library;
+
+	let Some(location) = db
+		.location()
+		.find_first(vec![
+			location::node_id::equals(Some(library.node_local_id)),
+			location::file_paths::some(vec![file_path::cas_id::equals(Some(cas_id.to_string()))]),
+		])
+		.exec()
+		.await?
+	else {
+		return Ok(false);
+	};
+
+	let Some(file_path) = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(Some(location.id)),
+			file_path::cas_id::equals(Some(cas_id.to_string())),
+		])
+		.select(file_path_for_thumbnailer::select())
+		.exec()
+		.await?
+	else {
+		return Ok(false);
+	};
+
+	// Only files whose kind of thumbnail is still enabled are regenerated
+	let Some(kind) = file_path
+		.extension
+		.as_deref()
+		.and_then(|extension| data.preferences.kind_for_extension(extension))
+	else {
+		return Ok(false);
+	};
+
+	let location_path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+
+	inner_process_step(
+		&ThumbnailerJobStep { file_path, kind },
+		&location_path,
+		&data.thumbnail_dir,
+		&location,
+		library,
+		&data.preferences,
+	)
+	.await?;
+
+	let grid_path = data.thumbnail_dir.join(get_shard_hex(cas_id)).join(format!(
+		"{cas_id}.{}",
+		library.config.thumbnails.format.extension()
+	));
+
+	Ok(fs::metadata(&grid_path).await.is_ok())
+}
@@ -13,12 +13,23 @@ use std::{
 	path::{Path, PathBuf},
 };
 
-use sd_file_ext::extensions::{Extension, ImageExtension};
+use sd_file_ext::extensions::{
+	AudioExtension, CodeExtension, Extension, ImageExtension, TextExtension,
+};
 
 #[cfg(feature = "ffmpeg")]
 use sd_file_ext::extensions::VideoExtension;
 
-use image::{self, imageops, DynamicImage, GenericImageView};
+#[cfg(feature = "pdf")]
+use sd_file_ext::extensions::DocumentExtension;
+
+#[cfg(feature = "mesh")]
+use sd_file_ext::extensions::MeshExtension;
+
+use image::{
+	self, codecs::avif::AvifEncoder, imageops, ColorType, DynamicImage, GenericImageView,
+	ImageEncoder,
+};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -26,28 +37,43 @@ use tokio::{fs, io, task::block_in_place};
 use tracing::{error, trace, warn};
 use webp::Encoder;
 
+mod cache;
+mod delegated;
 mod directory;
+pub mod format_migrator_job;
+mod preferences;
+mod prioritizer;
+pub mod regenerator_job;
 mod shallow;
 mod shard;
+mod text_preview;
 pub mod thumbnailer_job;
+mod tiers;
+mod waveform;
 
+pub use cache::*;
+pub use delegated::*;
 pub use directory::*;
+pub use preferences::*;
+pub use prioritizer::*;
 pub use shallow::*;
 pub use shard::*;
+pub use text_preview::*;
+pub use tiers::*;
+pub use waveform::*;
 
-const THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
-const THUMBNAIL_QUALITY: f32 = 30.0;
 pub const THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
+/// From 1 (slowest, smallest) to 10 (fastest, biggest)
+const AVIF_ENCODING_SPEED: u8 = 8;
 
 /// This does not check if a thumbnail exists, it just returns the path that it would exist at
 pub fn get_thumbnail_path(library: &Library, cas_id: &str) -> PathBuf {
 	library
-		.config()
 		.data_directory()
 		.join(THUMBNAIL_CACHE_DIR_NAME)
 		.join(get_shard_hex(cas_id))
 		.join(cas_id)
-		.with_extension("webp")
+		.with_extension(library.config.thumbnails.format.extension())
 }
 
 // this is used to pass the relevant data to the frontend so it can request the thumbnail
@@ -75,6 +101,56 @@ static FILTERED_IMAGE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 		.collect()
 });
 
+#[cfg(feature = "mesh")]
+static FILTERED_MESH_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_MESH_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_thumbnail_for_mesh)
+		.map(Extension::Mesh)
+		.collect()
+});
+
+static FILTERED_AUDIO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_AUDIO_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_waveform_for_audio)
+		.map(Extension::Audio)
+		.collect()
+});
+
+/// Text and source code files get a preview of their beginning
+static FILTERED_TEXT_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_TEXT_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_preview_for_text)
+		.map(Extension::Text)
+		.chain(
+			sd_file_ext::extensions::ALL_CODE_EXTENSIONS
+				.iter()
+				.map(Clone::clone)
+				.map(Extension::Code),
+		)
+		.collect()
+});
+
+#[cfg(feature = "pdf")]
+static FILTERED_DOCUMENT_EXTENSIONS: Lazy<Vec<Extension>> =
+	Lazy::new(|| vec![Extension::Document(DocumentExtension::Pdf)]);
+
+/// Office documents are converted to PDF by LibreOffice before being rendered
+#[cfg(feature = "pdf")]
+static FILTERED_OFFICE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	use DocumentExtension::*;
+
+	[Doc, Docx, Xls, Xlsx, Ppt, Pptx, Odt, Ods, Odp]
+		.into_iter()
+		.map(Extension::Document)
+		.collect()
+});
+
 #[derive(Error, Debug)]
 pub enum ThumbnailerError {
 	#[error("sub path not found: <path='{}'>", .0.display())]
@@ -90,11 +166,23 @@ pub enum ThumbnailerError {
 	#[error(transparent)]
 	VersionManager(#[from] VersionManagerError),
 }
+
+impl From<ThumbnailerError> for rspc::Error {
+	fn from(e: ThumbnailerError) -> Self {
+		rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+	}
+}
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 enum ThumbnailerJobStepKind {
 	Image,
 	#[cfg(feature = "ffmpeg")]
 	Video,
+	#[cfg(feature = "pdf")]
+	Document,
+	/// Waveform peaks, instead of an image
+	Audio,
+	/// Beginning of the file as UTF-8, instead of an image
+	Text,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -104,70 +192,258 @@ pub struct ThumbnailerJobStep {
 }
 
 // TOOD(brxken128): validate avci and avcs
-#[cfg(all(feature = "heif", not(target_os = "linux")))]
-const HEIF_EXTENSIONS: [&str; 7] = ["heif", "heifs", "heic", "heics", "avif", "avci", "avcs"];
+#[cfg(feature = "heif")]
+pub const HEIF_EXTENSIONS: [&str; 7] = ["heif", "heifs", "heic", "heics", "avif", "avci", "avcs"];
+
+#[cfg(feature = "raw")]
+pub const RAW_EXTENSIONS: [&str; 8] = ["dng", "cr2", "cr3", "dcr", "nwr", "nef", "arw", "rw2"];
+
+#[cfg(feature = "mesh")]
+pub const MESH_EXTENSIONS: [&str; 4] = ["gltf", "glb", "obj", "stl"];
+
+/// Opens an image with the decoder matching its extension, falling back to the `image` crate.
+/// Vector images and 3D models are rendered so their biggest side is `render_dimension` pixels
+/// long.
+fn open_image(path: &Path, render_dimension: u32) -> Result<DynamicImage, Box<dyn Error>> {
+	let ext = path.extension().unwrap_or_default().to_ascii_lowercase();
+
+	#[cfg(feature = "heif")]
+	if HEIF_EXTENSIONS
+		.iter()
+		.any(|e| ext == std::ffi::OsStr::new(e))
+	{
+		return Ok(sd_heif::heif_to_dynamic_image(path)?);
+	}
+
+	#[cfg(feature = "raw")]
+	if RAW_EXTENSIONS
+		.iter()
+		.any(|e| ext == std::ffi::OsStr::new(e))
+	{
+		return Ok(sd_raw::raw_to_dynamic_image(path)?);
+	}
+
+	#[cfg(feature = "mesh")]
+	if MESH_EXTENSIONS
+		.iter()
+		.any(|e| ext == std::ffi::OsStr::new(e))
+	{
+		return Ok(sd_mesh::mesh_to_dynamic_image(path, render_dimension)?);
+	}
+
+	if ext == "svg" {
+		return Ok(sd_svg::svg_to_dynamic_image(path, render_dimension)?);
+	}
+
+	Ok(image::open(path)?)
+}
+
+/// Scales the image down to every enabled tier, returning the thumbnails encoded in the
+/// library's format
+fn encode_tiers(
+	img: &DynamicImage,
+	settings: &ThumbnailSettings,
+) -> Result<Vec<(ThumbnailTier, Vec<u8>)>, Box<dyn Error>> {
+	settings
+		.enabled_tiers()
+		.map(|(tier, tier_settings)| {
+			let (w, h) = img.dimensions();
+			let max_dimension = tier_settings.max_dimension;
+
+			let img = if w > max_dimension || h > max_dimension {
+				img.resize(max_dimension, max_dimension, imageops::FilterType::Triangle)
+			} else {
+				img.clone()
+			};
+
+			Ok((
+				tier,
+				encode_thumbnail(&img, settings.format, tier_settings.quality)?,
+			))
+		})
+		.collect()
+}
+
+fn encode_thumbnail(
+	img: &DynamicImage,
+	format: ThumbnailFormat,
+	quality: f32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+	// Both encoders only support RGB(A) images
+	let img = DynamicImage::ImageRgba8(img.to_rgba8());
+
+	match format {
+		ThumbnailFormat::Webp => {
+			// Create the WebP encoder for the above image
+			let encoder = Encoder::from_image(&img)?;
+
+			// Type WebPMemory is !Send, which makes the Future in this function !Send,
+			// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+			// which implies on a unwanted clone...
+			Ok(encoder.encode(quality).deref().to_owned())
+		}
+		ThumbnailFormat::Avif => {
+			let mut avif = vec![];
+			AvifEncoder::new_with_speed_quality(&mut avif, AVIF_ENCODING_SPEED, quality as u8)
+				.write_image(img.as_bytes(), img.width(), img.height(), ColorType::Rgba8)?;
+
+			Ok(avif)
+		}
+	}
+}
+
+/// Writes the thumbnails of every tier next to `grid_output_path`
+async fn write_tiers(
+	grid_output_path: &Path,
+	tiers: Vec<(ThumbnailTier, Vec<u8>)>,
+) -> Result<(), Box<dyn Error>> {
+	for (tier, thumbnail) in tiers {
+		fs::write(tier.path_from_grid(grid_output_path), &thumbnail).await?;
+	}
+
+	Ok(())
+}
+
+/// Removes the thumbnails of every tier and format, given the path of the grid one
+pub async fn remove_thumbnail_tiers(grid_path: &Path) -> Result<(), FileIOError> {
+	for format in ThumbnailFormat::ALL {
+		let grid_path = grid_path.with_extension(format.extension());
+
+		for tier in ThumbnailTier::ALL {
+			let path = tier.path_from_grid(&grid_path);
+			match fs::remove_file(&path).await {
+				// Only the grid tier in the library's format is always generated
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(FileIOError::from((path, e))),
+			}
+		}
+	}
+
+	Ok(())
+}
 
 pub async fn generate_image_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
+	settings: &ThumbnailSettings,
 ) -> Result<(), Box<dyn Error>> {
 	// Webp creation has blocking code
-	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
-		#[cfg(all(feature = "heif", not(target_os = "linux")))]
-		let img = {
-			let ext = file_path
-				.as_ref()
-				.extension()
-				.unwrap_or_default()
-				.to_ascii_lowercase();
-			if HEIF_EXTENSIONS
-				.iter()
-				.any(|e| ext == std::ffi::OsStr::new(e))
-			{
-				sd_heif::heif_to_dynamic_image(file_path.as_ref())?
-			} else {
-				image::open(file_path)?
-			}
-		};
+	let tiers = block_in_place(|| -> Result<_, Box<dyn Error>> {
+		let img = open_image(file_path.as_ref(), settings.max_dimension())?;
 
-		#[cfg(not(all(feature = "heif", not(target_os = "linux"))))]
-		let img = image::open(file_path)?;
-
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
-			&img,
-			// FIXME : Think of a better heuristic to get the thumbnail size
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
-
-		// Encode the image at a specified quality 0-100
-
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+		encode_tiers(&img, settings)
 	})?;
 
-	fs::write(output_path, &webp).await.map_err(Into::into)
+	write_tiers(output_path.as_ref(), tiers).await
 }
 
 #[cfg(feature = "ffmpeg")]
 pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
+	preferences: &ThumbnailerPreferences,
+	settings: &ThumbnailSettings,
 ) -> Result<(), Box<dyn Error>> {
-	use sd_ffmpeg::to_thumbnail;
+	use sd_ffmpeg::ThumbnailerBuilder;
 
-	to_thumbnail(file_path, output_path, 256, THUMBNAIL_QUALITY).await?;
+	for (tier, tier_settings) in settings.enabled_tiers() {
+		let mut builder = ThumbnailerBuilder::new()
+			.with_film_strip(false)
+			.size(tier_settings.max_dimension)
+			.quality(tier_settings.quality)?;
+
+		if let Some(timestamp_secs) = preferences.video_frame_timestamp_secs {
+			builder = builder.seek_timestamp(std::time::Duration::from_secs(timestamp_secs as u64));
+		}
+
+		let output_path = tier.path_from_grid(output_path.as_ref());
+		let thumbnailer = builder.build();
+
+		match settings.format {
+			ThumbnailFormat::Webp => thumbnailer.process(&file_path, output_path).await?,
+			// FFmpeg only outputs WebP, so the frame is re-encoded
+			ThumbnailFormat::Avif => {
+				let webp = thumbnailer.process_to_webp_bytes(&file_path).await?;
+				let avif = block_in_place(|| -> Result<_, Box<dyn Error>> {
+					let img = image::load_from_memory_with_format(&webp, image::ImageFormat::WebP)?;
+
+					encode_thumbnail(&img, ThumbnailFormat::Avif, tier_settings.quality)
+				})?;
+
+				fs::write(output_path, avif).await?;
+			}
+		}
+	}
 
 	Ok(())
 }
 
+/// Renders the first page of a PDF, or of an office document converted to PDF
+#[cfg(feature = "pdf")]
+pub async fn generate_document_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	settings: &ThumbnailSettings,
+) -> Result<(), Box<dyn Error>> {
+	let file_path = file_path.as_ref();
+
+	// Rendering and converting documents has blocking code
+	let tiers = block_in_place(|| -> Result<_, Box<dyn Error>> {
+		let render_width = settings.max_dimension().min(u16::MAX as u32) as u16;
+
+		let is_pdf = file_path
+			.extension()
+			.map_or(false, |ext| ext.eq_ignore_ascii_case("pdf"));
+
+		let img = if is_pdf {
+			sd_pdf::pdf_to_dynamic_image(file_path, render_width)?
+		} else {
+			let conversion_dir =
+				std::env::temp_dir().join(format!("sd-document-{}", uuid::Uuid::new_v4()));
+			std::fs::create_dir_all(&conversion_dir)?;
+
+			let res = sd_pdf::office_to_pdf(file_path, &conversion_dir).and_then(|pdf_path| {
+				sd_pdf::pdf_to_dynamic_image(&pdf_path, render_width)
+			});
+
+			if let Err(e) = std::fs::remove_dir_all(&conversion_dir) {
+				warn!("Failed to remove document conversion directory: {e:#?}");
+			}
+
+			res?
+		};
+
+		encode_tiers(&img, settings)
+	})?;
+
+	write_tiers(output_path.as_ref(), tiers).await
+}
+
+/// Decodes the whole file to find the peaks of its waveform
+pub async fn generate_audio_waveform<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+) -> Result<(), Box<dyn Error>> {
+	let peaks =
+		block_in_place(|| sd_audio::audio_to_peaks(file_path.as_ref(), WAVEFORM_PEAKS_COUNT))?;
+
+	fs::write(output_path, peaks).await?;
+
+	Ok(())
+}
+
+#[cfg(feature = "pdf")]
+pub fn can_generate_thumbnail_for_document(
+	document_extension: &DocumentExtension,
+	preferences: &ThumbnailerPreferences,
+) -> bool {
+	let extension = Extension::Document(*document_extension);
+
+	(preferences.document && FILTERED_DOCUMENT_EXTENSIONS.contains(&extension))
+		|| (preferences.office_document && FILTERED_OFFICE_EXTENSIONS.contains(&extension))
+}
+
 #[cfg(feature = "ffmpeg")]
 pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension) -> bool {
 	use VideoExtension::*;
@@ -175,17 +451,46 @@ pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension)
 	!matches!(video_extension, Mpg | Swf | M2v | Hevc | M2ts | Mts | Ts)
 }
 
+#[cfg(feature = "mesh")]
+pub const fn can_generate_thumbnail_for_mesh(mesh_extension: &MeshExtension) -> bool {
+	use MeshExtension::*;
+	// File extensions that the renderer can load
+	matches!(mesh_extension, Obj | Gltf | Glb | Stl)
+}
+
+pub const fn can_generate_waveform_for_audio(audio_extension: &AudioExtension) -> bool {
+	use AudioExtension::*;
+	// File extensions that the decoder supports
+	matches!(
+		audio_extension,
+		Mp3 | M4a | Wav | Flac | Ogg | Oga | Aac | Adts
+	)
+}
+
+pub const fn can_generate_preview_for_text(text_extension: &TextExtension) -> bool {
+	use TextExtension::*;
+	// Rich text is mostly markup, so its raw beginning isn't worth showing
+	!matches!(text_extension, Rtf)
+}
+
 pub const fn can_generate_thumbnail_for_image(image_extension: &ImageExtension) -> bool {
 	use ImageExtension::*;
 
-	#[cfg(all(feature = "heif", not(target_os = "linux")))]
+	#[cfg(feature = "heif")]
 	let res = matches!(
 		image_extension,
-		Jpg | Jpeg | Png | Webp | Gif | Heic | Heics | Heif | Heifs | Avif
+		Jpg | Jpeg | Png | Webp | Gif | Svg | Heic | Heics | Heif | Heifs | Avif
 	);
 
-	#[cfg(not(all(feature = "heif", not(target_os = "linux"))))]
-	let res = matches!(image_extension, Jpg | Jpeg | Png | Webp | Gif);
+	#[cfg(not(feature = "heif"))]
+	let res = matches!(image_extension, Jpg | Jpeg | Png | Webp | Gif | Svg);
+
+	#[cfg(feature = "raw")]
+	let res = res
+		|| matches!(
+			image_extension,
+			Dng | Cr2 | Cr3 | Dcr | Nwr | Nef | Arw | Rw2
+		);
 
 	res
 }
@@ -196,10 +501,12 @@ pub async fn inner_process_step(
 	thumbnail_dir: impl AsRef<Path>,
 	location: &location::Data,
 	library: &Library,
+	#[allow(unused_variables)] preferences: &ThumbnailerPreferences,
 ) -> Result<bool, JobError> {
 	let ThumbnailerJobStep { file_path, kind } = step;
 	let location_path = location_path.as_ref();
 	let thumbnail_dir = thumbnail_dir.as_ref();
+	let settings = &library.config.thumbnails;
 
 	// assemble the file path
 	let path = location_path.join(IsolatedFilePathData::try_from((location.id, file_path))?);
@@ -221,8 +528,14 @@ pub async fn inner_process_step(
 		error!("Error creating thumbnail directory {:#?}", e);
 	}
 
-	// Define and write the WebP-encoded file to a given path
-	let output_path = thumb_dir.join(format!("{cas_id}.webp"));
+	// Define and write the encoded file to a given path
+	let output_path = match kind {
+		ThumbnailerJobStepKind::Audio => thumb_dir.join(format!("{cas_id}.{WAVEFORM_EXTENSION}")),
+		ThumbnailerJobStepKind::Text => {
+			thumb_dir.join(format!("{cas_id}.{TEXT_PREVIEW_EXTENSION}"))
+		}
+		_ => thumb_dir.join(format!("{cas_id}.{}", settings.format.extension())),
+	};
 
 	match fs::metadata(&output_path).await {
 		Ok(_) => {
@@ -237,16 +550,42 @@ pub async fn inner_process_step(
 
 			match kind {
 				ThumbnailerJobStepKind::Image => {
-					if let Err(e) = generate_image_thumbnail(&path, &output_path).await {
+					if let Err(e) = generate_image_thumbnail(&path, &output_path, settings).await {
 						error!("Error generating thumb for image {:#?}", e);
 					}
 				}
 				#[cfg(feature = "ffmpeg")]
 				ThumbnailerJobStepKind::Video => {
-					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
+					if let Err(e) =
+						generate_video_thumbnail(&path, &output_path, preferences, settings).await
+					{
 						error!("Error generating thumb for video: {:?} {:#?}", &path, e);
 					}
 				}
+				#[cfg(feature = "pdf")]
+				ThumbnailerJobStepKind::Document => {
+					if let Err(e) = generate_document_thumbnail(&path, &output_path, settings).await
+					{
+						error!("Error generating thumb for document: {:?} {:#?}", &path, e);
+					}
+				}
+				ThumbnailerJobStepKind::Audio => {
+					if let Err(e) = generate_audio_waveform(&path, &output_path).await {
+						error!("Error generating waveform for audio: {:?} {:#?}", &path, e);
+					}
+
+					// Waveforms are fetched when the file is previewed, there's no thumbnail
+					// to refresh
+					return Ok(true);
+				}
+				ThumbnailerJobStepKind::Text => {
+					if let Err(e) = generate_text_preview(&path, &output_path).await {
+						error!("Error generating preview for text: {:?} {:#?}", &path, e);
+					}
+
+					// Same as waveforms, text previews are fetched by the inspector
+					return Ok(true);
+				}
 			}
 
 			trace!("Emitting new thumbnail event");
@@ -0,0 +1,169 @@
+use crate::{library::Library, prisma::location};
+
+use super::{
+	ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_AUDIO_EXTENSIONS,
+	FILTERED_IMAGE_EXTENSIONS, FILTERED_TEXT_EXTENSIONS,
+};
+
+#[cfg(feature = "ffmpeg")]
+use super::FILTERED_VIDEO_EXTENSIONS;
+
+#[cfg(feature = "pdf")]
+use super::{FILTERED_DOCUMENT_EXTENSIONS, FILTERED_OFFICE_EXTENSIONS};
+
+#[cfg(feature = "mesh")]
+use super::FILTERED_MESH_EXTENSIONS;
+
+use sd_file_ext::extensions::Extension;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Which thumbnails are generated by the thumbnailer, and how
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailerPreferences {
+	pub image: bool,
+	pub video: bool,
+	/// First page of PDFs
+	pub document: bool,
+	/// First page of office documents, like DOCX or PPTX, which requires LibreOffice to be
+	/// installed to convert them to PDF
+	pub office_document: bool,
+	/// Waveform peaks of audio files, for the scrubber of their previews
+	#[serde(default = "enabled_by_default")]
+	pub audio: bool,
+	/// Renders of 3D models, like glTF, OBJ or STL files
+	#[serde(default = "enabled_by_default")]
+	pub mesh: bool,
+	/// Beginning of text and source code files, for the inspector to show without reading them
+	#[serde(default = "enabled_by_default")]
+	pub text: bool,
+	/// Second of the video the thumbnail frame is taken at, instead of 10% through the video
+	pub video_frame_timestamp_secs: Option<u32>,
+	/// Files bigger than this aren't thumbnailed on locations added by other nodes, as
+	/// they would have to be read over the network
+	pub remote_max_file_size_mb: Option<u32>,
+}
+
+impl Default for ThumbnailerPreferences {
+	fn default() -> Self {
+		Self {
+			image: true,
+			video: true,
+			document: true,
+			office_document: false,
+			audio: true,
+			mesh: true,
+			text: true,
+			video_frame_timestamp_secs: None,
+			remote_max_file_size_mb: Some(200),
+		}
+	}
+}
+
+fn enabled_by_default() -> bool {
+	true
+}
+
+impl ThumbnailerPreferences {
+	/// Maximum size of the files to thumbnail on the given location, if any
+	pub fn max_file_size(&self, location: &location::Data, library: &Library) -> Option<u64> {
+		let is_remote = location.node_id != Some(library.node_local_id);
+
+		is_remote
+			.then_some(self.remote_max_file_size_mb)
+			.flatten()
+			.map(|size_mb| size_mb as u64 * 1024 * 1024)
+	}
+
+	/// Kinds of thumbnails to generate, with the extensions of the files to look for
+	pub(super) fn enabled_kinds(&self) -> Vec<(ThumbnailerJobStepKind, Vec<Extension>)> {
+		let mut kinds = vec![];
+
+		if self.image {
+			kinds.push((
+				ThumbnailerJobStepKind::Image,
+				FILTERED_IMAGE_EXTENSIONS.clone(),
+			));
+		}
+
+		#[cfg(feature = "ffmpeg")]
+		if self.video {
+			kinds.push((
+				ThumbnailerJobStepKind::Video,
+				FILTERED_VIDEO_EXTENSIONS.clone(),
+			));
+		}
+
+		#[cfg(feature = "pdf")]
+		if self.document || self.office_document {
+			let mut extensions = vec![];
+			if self.document {
+				extensions.extend(FILTERED_DOCUMENT_EXTENSIONS.iter().cloned());
+			}
+			if self.office_document {
+				extensions.extend(FILTERED_OFFICE_EXTENSIONS.iter().cloned());
+			}
+
+			kinds.push((ThumbnailerJobStepKind::Document, extensions));
+		}
+
+		// Models are rendered to an image, like vector images are
+		#[cfg(feature = "mesh")]
+		if self.mesh {
+			kinds.push((
+				ThumbnailerJobStepKind::Image,
+				FILTERED_MESH_EXTENSIONS.clone(),
+			));
+		}
+
+		if self.audio {
+			kinds.push((
+				ThumbnailerJobStepKind::Audio,
+				FILTERED_AUDIO_EXTENSIONS.clone(),
+			));
+		}
+
+		if self.text {
+			kinds.push((
+				ThumbnailerJobStepKind::Text,
+				FILTERED_TEXT_EXTENSIONS.clone(),
+			));
+		}
+
+		kinds
+	}
+
+	/// Kind of thumbnail to generate for files with this extension, if it's enabled
+	pub(super) fn kind_for_extension(&self, extension: &str) -> Option<ThumbnailerJobStepKind> {
+		self.enabled_kinds()
+			.into_iter()
+			.find(|(_, extensions)| {
+				extensions
+					.iter()
+					.any(|candidate| candidate.to_string() == extension)
+			})
+			.map(|(kind, _)| kind)
+	}
+}
+
+/// Drops the files bigger than `max_file_size`, files with an unknown size are kept
+pub(super) fn skip_oversized_files(
+	steps: Vec<ThumbnailerJobStep>,
+	max_file_size: Option<u64>,
+) -> Vec<ThumbnailerJobStep> {
+	let Some(max_file_size) = max_file_size else {
+		return steps;
+	};
+
+	steps
+		.into_iter()
+		.filter(|step| {
+			step.file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.and_then(|bytes| bytes.try_into().ok())
+				.map_or(true, |bytes| u64::from_be_bytes(bytes) <= max_file_size)
+		})
+		.collect()
+}
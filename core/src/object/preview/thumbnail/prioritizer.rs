@@ -0,0 +1,192 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::file_path_for_thumbnailer,
+	prisma::{file_path, location},
+	util::db::maybe_missing,
+};
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use tokio::{
+	fs,
+	sync::mpsc::{self, error::TryRecvError},
+};
+use tracing::{debug, error};
+
+use super::{
+	init_thumbnail_dir, inner_process_step, preferences::skip_oversized_files, ThumbnailerError,
+	ThumbnailerJobStep,
+};
+
+type Request = (Library, Vec<file_path::id::Type>);
+
+// Actor that generates the thumbnails of the files the user is looking at, ahead of the
+// thumbnailer jobs, which then skip them as they already exist.
+//
+// Only the latest request matters: files scrolled out of view before their thumbnail was
+// generated are left to the jobs.
+#[derive(Clone)]
+pub struct ThumbnailPrioritizerActor {
+	tx: mpsc::Sender<Request>,
+}
+
+impl ThumbnailPrioritizerActor {
+	pub fn spawn() -> Self {
+		let (tx, mut rx) = mpsc::channel::<Request>(16);
+
+		tokio::spawn(async move {
+			let mut next_request = None;
+
+			loop {
+				let Some((library, file_path_ids)) = (match next_request.take() {
+					Some(request) => Some(request),
+					None => rx.recv().await,
+				}) else {
+					break;
+				};
+
+				debug!(
+					"Prioritizing the thumbnails of {} files",
+					file_path_ids.len()
+				);
+
+				let (locations, thumbnail_dir) = match (
+					load_locations(&library, &file_path_ids).await,
+					init_thumbnail_dir(library.data_directory()).await,
+				) {
+					(Ok(locations), Ok(thumbnail_dir)) => (locations, thumbnail_dir),
+					(Err(e), _) | (_, Err(e)) => {
+						error!("Failed to prepare the prioritized thumbnails: {e:#?}");
+						continue;
+					}
+				};
+
+				for file_path_id in file_path_ids {
+					// The user scrolled somewhere else, so we switch to the newly visible files
+					match rx.try_recv() {
+						Ok(request) => {
+							next_request = Some(request);
+							break;
+						}
+						Err(TryRecvError::Empty) => {}
+						Err(TryRecvError::Disconnected) => return,
+					}
+
+					if let Err(e) =
+						process_file(&library, file_path_id, &locations, &thumbnail_dir).await
+					{
+						error!("Failed to generate a prioritized thumbnail: {e:#?}");
+					}
+				}
+
+				// Requests sent while the last file was processed are also outdated, except
+				// the latest one
+				while let Ok(request) = rx.try_recv() {
+					next_request = Some(request);
+				}
+			}
+		});
+
+		Self { tx }
+	}
+
+	/// Replaces the files to generate thumbnails for first, in the order they're displayed
+	pub async fn prioritize(&self, library: Library, file_path_ids: Vec<file_path::id::Type>) {
+		self.tx.send((library, file_path_ids)).await.ok();
+	}
+}
+
+/// Locations of the files, with their path, by file_path id
+async fn load_locations(
+	library: &Library,
+	file_path_ids: &[file_path::id::Type],
+) -> Result<HashMap<file_path::id::Type, (location::Data, PathBuf)>, ThumbnailerError> {
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(file_path_ids.to_vec())])
+		.select(file_path::select!({ id location }))
+		.exec()
+		.await?;
+
+	Ok(file_paths
+		.into_iter()
+		.filter_map(|file_path| {
+			let location = file_path.location?;
+			let location_path = maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.ok()?;
+
+			Some((file_path.id, (location, location_path)))
+		})
+		.collect())
+}
+
+async fn process_file(
+	library: &Library,
+	file_path_id: file_path::id::Type,
+	locations: &HashMap<file_path::id::Type, (location::Data, PathBuf)>,
+	thumbnail_dir: &Path,
+) -> Result<(), ThumbnailerError> {
+	let Some((location, location_path)) = locations.get(&file_path_id) else {
+		return Ok(());
+	};
+
+	// Locations of other nodes which aren't reachable from this one get their thumbnails from
+	// these nodes instead
+	if fs::metadata(location_path).await.is_err() {
+		return Ok(());
+	}
+
+	let Some(file_path) = library
+		.db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path_for_thumbnailer::select())
+		.exec()
+		.await?
+	else {
+		return Ok(());
+	};
+
+	// Files without a cas_id weren't identified yet, so they can't have a thumbnail
+	if file_path.cas_id.is_none() {
+		return Ok(());
+	}
+
+	let preferences = library.config().get().await.thumbnailer;
+
+	let Some(kind) = file_path
+		.extension
+		.as_deref()
+		.and_then(|extension| preferences.kind_for_extension(extension))
+	else {
+		return Ok(());
+	};
+
+	let Some(step) = skip_oversized_files(
+		vec![ThumbnailerJobStep { file_path, kind }],
+		preferences.max_file_size(location, library),
+	)
+	.pop() else {
+		return Ok(());
+	};
+
+	if let Err(e) = inner_process_step(
+		&step,
+		location_path,
+		thumbnail_dir,
+		location,
+		library,
+		&preferences,
+	)
+	.await
+	{
+		error!("Failed to generate the thumbnail of file_path {file_path_id}: {e:#?}");
+	}
+
+	Ok(())
+}
@@ -0,0 +1,327 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::{
+		ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+		file_path_for_thumbnailer, IsolatedFilePathData,
+	},
+	object::preview::thumbnail::directory::init_thumbnail_dir,
+	prisma::{file_path, location},
+	util::db::maybe_missing,
+};
+
+use std::{
+	hash::Hash,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, task::block_in_place};
+use tracing::{debug, error, info};
+
+use super::{
+	enforce_thumbnail_cache_limit, get_shard_hex, inner_process_step, remove_text_preview,
+	remove_thumbnail_tiers, remove_waveform, thumbnailer_job::get_files_by_extensions,
+	ThumbnailFormat, ThumbnailSettings, ThumbnailerError, ThumbnailerJobStep,
+	ThumbnailerJobStepKind, ThumbnailerPreferences, TEXT_PREVIEW_EXTENSION, WAVEFORM_EXTENSION,
+	WAVEFORM_PEAKS_COUNT,
+};
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ThumbnailRegenerationMode {
+	/// Every thumbnail is generated again, to apply new settings
+	All,
+	/// Only thumbnails with missing tiers, or which can't be decoded
+	MissingOrBroken,
+}
+
+/// `ThumbnailRegeneratorJobInit` removes and generates again the thumbnails of a location, of a
+/// sub path of it, or of a selection of its files.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailRegeneratorJobInit {
+	pub location: location::Data,
+	pub sub_path: Option<PathBuf>,
+	/// Restricts the job to these files of the location
+	pub file_path_ids: Option<Vec<file_path::id::Type>>,
+	pub mode: ThumbnailRegenerationMode,
+}
+
+impl Hash for ThumbnailRegeneratorJobInit {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+		if let Some(ref file_path_ids) = self.file_path_ids {
+			file_path_ids.hash(state);
+		}
+		self.mode.hash(state);
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailRegeneratorJobData {
+	thumbnail_dir: PathBuf,
+	location_path: PathBuf,
+	preferences: ThumbnailerPreferences,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ThumbnailRegeneratorJobRunMetadata {
+	thumbnails_regenerated: u32,
+	thumbnails_skipped: u32,
+}
+
+impl JobRunMetadata for ThumbnailRegeneratorJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.thumbnails_regenerated += new_data.thumbnails_regenerated;
+		self.thumbnails_skipped += new_data.thumbnails_skipped;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailRegeneratorJobInit {
+	type Data = ThumbnailRegeneratorJobData;
+	type Step = ThumbnailerJobStep;
+	type RunMetadata = ThumbnailRegeneratorJobRunMetadata;
+
+	const NAME: &'static str = "thumbnail_regenerator";
+	const IS_BACKGROUND: bool = true;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		let thumbnail_dir = init_thumbnail_dir(ctx.library.data_directory()).await?;
+
+		let location_id = init.location.id;
+		let location_path =
+			maybe_missing(&init.location.path, "location.path").map(PathBuf::from)?;
+
+		let preferences = ctx.library.config().get().await.thumbnailer;
+
+		let steps = match &init.file_path_ids {
+			Some(file_path_ids) => db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::id::in_vec(file_path_ids.clone()),
+				])
+				.select(file_path_for_thumbnailer::select())
+				.exec()
+				.await?
+				.into_iter()
+				.filter_map(|file_path| {
+					let kind = preferences.kind_for_extension(file_path.extension.as_deref()?)?;
+
+					Some(ThumbnailerJobStep { file_path, kind })
+				})
+				.collect(),
+			None => {
+				let iso_file_path = match &init.sub_path {
+					Some(sub_path) if sub_path != Path::new("") => {
+						let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+							.await
+							.map_err(ThumbnailerError::from)?;
+						ensure_sub_path_is_directory(&location_path, sub_path)
+							.await
+							.map_err(ThumbnailerError::from)?;
+
+						let sub_iso_file_path = IsolatedFilePathData::new(
+							location_id,
+							&location_path,
+							&full_path,
+							true,
+						)
+						.map_err(ThumbnailerError::from)?;
+
+						ensure_file_path_exists(
+							sub_path,
+							&sub_iso_file_path,
+							db,
+							ThumbnailerError::SubPathNotFound,
+						)
+						.await?;
+
+						sub_iso_file_path
+					}
+					_ => {
+						IsolatedFilePathData::new(location_id, &location_path, &location_path, true)
+							.map_err(ThumbnailerError::from)?
+					}
+				};
+
+				let mut steps = vec![];
+				for (kind, extensions) in preferences.enabled_kinds() {
+					steps.extend(
+						get_files_by_extensions(db, &iso_file_path, &extensions, kind).await?,
+					);
+				}
+
+				steps
+			}
+		};
+
+		ctx.progress_msg(format!("Preparing to check {} thumbnails", steps.len()));
+
+		*data = Some(ThumbnailRegeneratorJobData {
+			thumbnail_dir,
+			location_path,
+			preferences,
+		});
+
+		Ok((ThumbnailRegeneratorJobRunMetadata::default(), steps).into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let init = self;
+		let mut new_metadata = Self::RunMetadata::default();
+
+		let Some(cas_id) = &step.file_path.cas_id else {
+			new_metadata.thumbnails_skipped += 1;
+			return Ok(new_metadata.into());
+		};
+
+		ctx.progress_msg(format!(
+			"Checking {}",
+			maybe_missing(
+				&step.file_path.materialized_path,
+				"file_path.materialized_path"
+			)?
+		));
+
+		let settings = &ctx.library.config.thumbnails;
+		let shard_dir = data.thumbnail_dir.join(get_shard_hex(cas_id));
+
+		let regenerate = match init.mode {
+			ThumbnailRegenerationMode::All => true,
+			ThumbnailRegenerationMode::MissingOrBroken => match step.kind {
+				ThumbnailerJobStepKind::Audio => {
+					is_waveform_broken(&shard_dir.join(format!("{cas_id}.{WAVEFORM_EXTENSION}")))
+						.await
+				}
+				// Binary files never get a text preview, so it can't tell a broken one apart
+				ThumbnailerJobStepKind::Text => {
+					fs::metadata(shard_dir.join(format!("{cas_id}.{TEXT_PREVIEW_EXTENSION}")))
+						.await
+						.is_err()
+				}
+				_ => {
+					is_thumbnail_broken(
+						&shard_dir.join(format!("{cas_id}.{}", settings.format.extension())),
+						settings,
+					)
+					.await
+				}
+			},
+		};
+
+		if !regenerate {
+			new_metadata.thumbnails_skipped += 1;
+			return Ok(new_metadata.into());
+		}
+
+		debug!("Regenerating the thumbnail of {cas_id}");
+
+		match step.kind {
+			ThumbnailerJobStepKind::Audio => remove_waveform(&ctx.library, cas_id).await?,
+			ThumbnailerJobStepKind::Text => remove_text_preview(&ctx.library, cas_id).await?,
+			_ => {
+				remove_thumbnail_tiers(
+					&shard_dir.join(format!("{cas_id}.{}", settings.format.extension())),
+				)
+				.await?
+			}
+		}
+
+		inner_process_step(
+			step,
+			&data.location_path,
+			&data.thumbnail_dir,
+			&init.location,
+			&ctx.library,
+			&data.preferences,
+		)
+		.await?;
+
+		new_metadata.thumbnails_regenerated += 1;
+
+		Ok(new_metadata.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!(
+			"Regenerated {} thumbnails of location {}, {} were fine",
+			run_metadata.thumbnails_regenerated, init.location.id, run_metadata.thumbnails_skipped
+		);
+
+		if run_metadata.thumbnails_regenerated > 0 {
+			invalidate_query!(ctx.library, "search.paths");
+
+			let library = ctx.library.clone();
+			tokio::spawn(async move {
+				if let Err(e) = enforce_thumbnail_cache_limit(&library).await {
+					error!("Failed to enforce the thumbnail cache limit: {e:#?}");
+				}
+			});
+		}
+
+		Ok(Some(json!({"init: ": init, "run_metadata": run_metadata})))
+	}
+}
+
+/// If any tier to generate is missing, or can't be decoded
+async fn is_thumbnail_broken(grid_path: &Path, settings: &ThumbnailSettings) -> bool {
+	for (tier, _) in settings.enabled_tiers() {
+		let Ok(thumbnail) = fs::read(tier.path_from_grid(grid_path)).await else {
+			return true;
+		};
+
+		let is_valid = match settings.format {
+			ThumbnailFormat::Webp => block_in_place(|| {
+				image::load_from_memory_with_format(&thumbnail, image::ImageFormat::WebP).is_ok()
+			}),
+			// AVIF can't be decoded, as only its encoder is built, so only its header is checked
+			ThumbnailFormat::Avif => thumbnail.get(4..12) == Some(b"ftypavif"),
+		};
+
+		if !is_valid {
+			return true;
+		}
+	}
+
+	false
+}
+
+async fn is_waveform_broken(path: &Path) -> bool {
+	fs::read(path)
+		.await
+		.map_or(true, |peaks| peaks.len() != WAVEFORM_PEAKS_COUNT)
+}
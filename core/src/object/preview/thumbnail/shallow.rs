@@ -1,5 +1,5 @@
 use super::{
-	ThumbnailerError, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
+	preferences::skip_oversized_files, ThumbnailerError, ThumbnailerJobStep, ThumbnailerJobStepKind,
 };
 use crate::{
 	invalidate_query,
@@ -19,9 +19,6 @@ use thumbnail::init_thumbnail_dir;
 use tokio::fs;
 use tracing::{debug, trace};
 
-#[cfg(feature = "ffmpeg")]
-use super::FILTERED_VIDEO_EXTENSIONS;
-
 pub async fn shallow_thumbnailer(
 	location: &location::Data,
 	sub_path: &PathBuf,
@@ -29,7 +26,7 @@ pub async fn shallow_thumbnailer(
 ) -> Result<(), JobError> {
 	let Library { db, .. } = &library;
 
-	let thumbnail_dir = init_thumbnail_dir(library.config().data_directory()).await?;
+	let thumbnail_dir = init_thumbnail_dir(library.data_directory()).await?;
 
 	let location_id = location.id;
 	let location_path = match &location.path {
@@ -76,46 +73,32 @@ pub async fn shallow_thumbnailer(
 		.await
 		.map_err(|e| FileIOError::from((&thumbnail_dir, e)))?;
 
-	// query database for all image files in this location that need thumbnails
-	let image_files = get_files_by_extensions(
-		&library.db,
-		location_id,
-		&iso_file_path,
-		&FILTERED_IMAGE_EXTENSIONS,
-		ThumbnailerJobStepKind::Image,
-	)
-	.await?;
-
-	trace!("Found {:?} image files", image_files.len());
-
-	#[cfg(feature = "ffmpeg")]
-	let video_files = {
-		// query database for all video files in this location that need thumbnails
-		let video_files = get_files_by_extensions(
-			&library.db,
-			location_id,
-			&iso_file_path,
-			&FILTERED_VIDEO_EXTENSIONS,
-			ThumbnailerJobStepKind::Video,
-		)
-		.await?;
+	let preferences = library.config().get().await.thumbnailer;
 
-		trace!("Found {:?} video files", video_files.len());
+	// query database for all files in this location that need thumbnails
+	let mut all_files = vec![];
+	for (kind, extensions) in preferences.enabled_kinds() {
+		let files =
+			get_files_by_extensions(&library.db, location_id, &iso_file_path, &extensions, kind)
+				.await?;
 
-		video_files
-	};
+		trace!("Found {} {kind:?} files", files.len());
 
-	let all_files = [
-		image_files,
-		#[cfg(feature = "ffmpeg")]
-		video_files,
-	]
-	.into_iter()
-	.flatten();
+		all_files.extend(files);
+	}
+
+	let all_files = skip_oversized_files(all_files, preferences.max_file_size(location, library));
 
 	for file in all_files {
-		thumbnail::inner_process_step(&file, &location_path, &thumbnail_dir, location, library)
-			.await?;
+		thumbnail::inner_process_step(
+			&file,
+			&location_path,
+			&thumbnail_dir,
+			location,
+			library,
+			&preferences,
+		)
+		.await?;
 	}
 
 	invalidate_query!(library, "search.paths");
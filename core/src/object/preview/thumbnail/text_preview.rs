@@ -0,0 +1,141 @@
+use crate::{library::Library, util::error::FileIOError};
+
+use std::path::{Path, PathBuf};
+
+use chardetng::EncodingDetector;
+use tokio::{
+	fs::{self, File},
+	io::{self, AsyncReadExt},
+};
+
+use super::{get_shard_hex, ThumbnailerError, THUMBNAIL_CACHE_DIR_NAME};
+
+/// Text previews are stored next to the thumbnails, as `{cas_id}.txt`, always in UTF-8
+pub const TEXT_PREVIEW_EXTENSION: &str = "txt";
+
+/// How much of the beginning of the file is kept, which fills the inspector many times over
+pub const TEXT_PREVIEW_MAX_BYTES: u64 = 16 * 1024;
+
+/// Files with more NUL bytes than this in their beginning are considered binary
+const MAX_NUL_BYTES_RATIO: f32 = 0.01;
+
+/// This does not check if a text preview exists, it just returns the path that it would exist at
+pub fn get_text_preview_path(library: &Library, cas_id: &str) -> PathBuf {
+	library
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(get_shard_hex(cas_id))
+		.join(format!("{cas_id}.{TEXT_PREVIEW_EXTENSION}"))
+}
+
+/// Beginning of a text file, converted to UTF-8, if it was generated
+pub async fn get_text_preview(
+	library: &Library,
+	cas_id: &str,
+) -> Result<Option<String>, ThumbnailerError> {
+	let path = get_text_preview_path(library, cas_id);
+
+	match fs::read_to_string(&path).await {
+		Ok(text) => Ok(Some(text)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(FileIOError::from((path, e)).into()),
+	}
+}
+
+pub async fn remove_text_preview(library: &Library, cas_id: &str) -> Result<(), FileIOError> {
+	let path = get_text_preview_path(library, cas_id);
+
+	match fs::remove_file(&path).await {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		Err(e) => Err(FileIOError::from((path, e))),
+	}
+}
+
+/// Reads the beginning of a text file, guessing its encoding, and writes it as UTF-8.
+/// Files which turn out to be binary are skipped.
+pub async fn generate_text_preview(
+	file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+) -> Result<(), FileIOError> {
+	let file_path = file_path.as_ref();
+	let output_path = output_path.as_ref();
+
+	let mut bytes = Vec::with_capacity(TEXT_PREVIEW_MAX_BYTES as usize);
+	File::open(file_path)
+		.await
+		.map_err(|e| FileIOError::from((file_path, e)))?
+		.take(TEXT_PREVIEW_MAX_BYTES)
+		.read_to_end(&mut bytes)
+		.await
+		.map_err(|e| FileIOError::from((file_path, e)))?;
+
+	let Some(text) = decode_text(&bytes, (bytes.len() as u64) < TEXT_PREVIEW_MAX_BYTES) else {
+		return Ok(());
+	};
+
+	fs::write(output_path, text)
+		.await
+		.map_err(|e| FileIOError::from((output_path, e)))
+}
+
+/// Decodes text of any encoding, a BOM taking precedence over the guessed one.
+///
+/// `is_complete` tells if `bytes` is the whole file, otherwise it may end in the middle of a
+/// character.
+fn decode_text(bytes: &[u8], is_complete: bool) -> Option<String> {
+	let nul_bytes = bytes.iter().filter(|byte| **byte == 0).count();
+	// UTF-16 text is full of NUL bytes, but starts with a BOM
+	let has_bom = bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]);
+	if !has_bom && nul_bytes as f32 > bytes.len() as f32 * MAX_NUL_BYTES_RATIO {
+		return None;
+	}
+
+	let mut detector = EncodingDetector::new();
+	detector.feed(bytes, is_complete);
+	let encoding = detector.guess(None, true);
+
+	let (text, _, _) = encoding.decode(bytes);
+
+	// A character cut at the end of the preview is decoded as a replacement character
+	let text = if is_complete {
+		&text
+	} else {
+		text.trim_end_matches(char::REPLACEMENT_CHARACTER)
+	};
+
+	Some(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_decode_text() {
+		assert_eq!(
+			decode_text("héllo wörld".as_bytes(), true).as_deref(),
+			Some("héllo wörld")
+		);
+
+		// Latin-1, which isn't valid UTF-8
+		assert_eq!(
+			decode_text(b"caf\xE9 cr\xE8me br\xFBl\xE9e, d\xE9j\xE0 vu", true).as_deref(),
+			Some("café crème brûlée, déjà vu")
+		);
+
+		// UTF-16 with a BOM
+		let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0];
+		assert_eq!(decode_text(&utf16, true).as_deref(), Some("hi"));
+
+		// A multi-byte character cut in half
+		let cut = &"abc€".as_bytes()[..5];
+		assert_eq!(decode_text(cut, false).as_deref(), Some("abc"));
+
+		// Binary data
+		assert_eq!(
+			decode_text(&[0x7F, b'E', b'L', b'F', 0, 0, 0, 1], false),
+			None
+		);
+	}
+}
@@ -1,8 +1,8 @@
 use crate::{
 	invalidate_query,
 	job::{
-		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
-		StatefulJob, WorkerContext,
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobRunMetadata,
+		JobStepOutput, RetryPolicy, StatefulJob, WorkerContext,
 	},
 	library::Library,
 	location::file_path_helper::{
@@ -24,16 +24,13 @@ use sd_file_ext::extensions::Extension;
 use serde::{Deserialize, Serialize};
 
 use serde_json::json;
-use tracing::{debug, info, trace};
+use tracing::{debug, error, info, trace};
 
 use super::{
-	inner_process_step, ThumbnailerError, ThumbnailerJobStep, ThumbnailerJobStepKind,
-	FILTERED_IMAGE_EXTENSIONS,
+	enforce_thumbnail_cache_limit, inner_process_step, preferences::skip_oversized_files,
+	ThumbnailerError, ThumbnailerJobStep, ThumbnailerJobStepKind, ThumbnailerPreferences,
 };
 
-#[cfg(feature = "ffmpeg")]
-use super::FILTERED_VIDEO_EXTENSIONS;
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ThumbnailerJobInit {
 	pub location: location::Data,
@@ -54,6 +51,8 @@ pub struct ThumbnailerJobData {
 	thumbnail_dir: PathBuf,
 	location_path: PathBuf,
 	path: PathBuf,
+	#[serde(default)]
+	preferences: ThumbnailerPreferences,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
@@ -76,6 +75,12 @@ impl StatefulJob for ThumbnailerJobInit {
 	type RunMetadata = ThumbnailerJobRunMetadata;
 
 	const NAME: &'static str = "thumbnailer";
+	const PRIORITY: JobPriority = JobPriority::Background;
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
 
 	async fn init(
 		&self,
@@ -85,7 +90,7 @@ impl StatefulJob for ThumbnailerJobInit {
 		let init = self;
 		let Library { db, .. } = &ctx.library;
 
-		let thumbnail_dir = init_thumbnail_dir(ctx.library.config().data_directory()).await?;
+		let thumbnail_dir = init_thumbnail_dir(ctx.library.data_directory()).await?;
 
 		let location_id = init.location.id;
 		let location_path =
@@ -121,37 +126,23 @@ impl StatefulJob for ThumbnailerJobInit {
 			),
 		};
 
+		let preferences = ctx.library.config().get().await.thumbnailer;
+
 		debug!("Searching for images in location {location_id} at directory {iso_file_path}");
 
-		// query database for all image files in this location that need thumbnails
-		let image_files = get_files_by_extensions(
-			db,
-			&iso_file_path,
-			&FILTERED_IMAGE_EXTENSIONS,
-			ThumbnailerJobStepKind::Image,
-		)
-		.await?;
-		trace!("Found {:?} image files", image_files.len());
-
-		#[cfg(feature = "ffmpeg")]
-		let all_files = {
-			// query database for all video files in this location that need thumbnails
-			let video_files = get_files_by_extensions(
-				db,
-				&iso_file_path,
-				&FILTERED_VIDEO_EXTENSIONS,
-				ThumbnailerJobStepKind::Video,
-			)
-			.await?;
-			trace!("Found {:?} video files", video_files.len());
-
-			image_files
-				.into_iter()
-				.chain(video_files.into_iter())
-				.collect::<Vec<_>>()
-		};
-		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<Vec<_>>() };
+		// query database for all files in this location that need thumbnails
+		let mut all_files = vec![];
+		for (kind, extensions) in preferences.enabled_kinds() {
+			let files = get_files_by_extensions(db, &iso_file_path, &extensions, kind).await?;
+			trace!("Found {} {kind:?} files", files.len());
+
+			all_files.extend(files);
+		}
+
+		let all_files = skip_oversized_files(
+			all_files,
+			preferences.max_file_size(&init.location, &ctx.library),
+		);
 
 		ctx.progress_msg(format!("Preparing to process {} files", all_files.len()));
 
@@ -159,6 +150,7 @@ impl StatefulJob for ThumbnailerJobInit {
 			thumbnail_dir,
 			location_path,
 			path,
+			preferences,
 		});
 
 		Ok((
@@ -195,6 +187,7 @@ impl StatefulJob for ThumbnailerJobInit {
 			&data.thumbnail_dir,
 			&init.location,
 			&ctx.library,
+			&data.preferences,
 		)
 		.await;
 
@@ -227,13 +220,20 @@ impl StatefulJob for ThumbnailerJobInit {
 
 		if run_metadata.thumbnails_created > 0 {
 			invalidate_query!(ctx.library, "search.paths");
+
+			let library = ctx.library.clone();
+			tokio::spawn(async move {
+				if let Err(e) = enforce_thumbnail_cache_limit(&library).await {
+					error!("Failed to enforce the thumbnail cache limit: {e:#?}");
+				}
+			});
 		}
 
 		Ok(Some(json!({"init: ": init, "run_metadata": run_metadata})))
 	}
 }
 
-async fn get_files_by_extensions(
+pub(super) async fn get_files_by_extensions(
 	db: &PrismaClient,
 	iso_file_path: &IsolatedFilePathData<'_>,
 	extensions: &[Extension],
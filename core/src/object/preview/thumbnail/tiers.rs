@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Resolutions a thumbnail is generated at, each one meant for a different display size
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ThumbnailTier {
+	/// Explorer grid and list items
+	Grid,
+	/// Inspector sidebar
+	Inspector,
+	/// Full window previews, when the original file can't be displayed
+	Preview,
+}
+
+impl ThumbnailTier {
+	/// From the smallest to the biggest tier
+	pub const ALL: [Self; 3] = [Self::Grid, Self::Inspector, Self::Preview];
+
+	/// Path of this tier's thumbnail, from the path of the grid one, which keeps the
+	/// `{cas_id}.webp` name thumbnails had before tiers were introduced
+	pub fn path_from_grid(self, grid_path: &Path) -> PathBuf {
+		match self {
+			Self::Grid => grid_path.to_path_buf(),
+			Self::Inspector | Self::Preview => {
+				let cas_id = grid_path.file_stem().unwrap_or_default().to_string_lossy();
+				let extension = grid_path.extension().unwrap_or_default().to_string_lossy();
+				grid_path.with_file_name(format!("{cas_id}-{}.{extension}", self.suffix()))
+			}
+		}
+	}
+
+	const fn suffix(self) -> &'static str {
+		match self {
+			Self::Grid => "grid",
+			Self::Inspector => "inspector",
+			Self::Preview => "preview",
+		}
+	}
+}
+
+/// Image format thumbnails are encoded in
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ThumbnailFormat {
+	#[default]
+	Webp,
+	/// Smaller than WebP at the same quality, but way slower to encode
+	Avif,
+}
+
+impl ThumbnailFormat {
+	pub const ALL: [Self; 2] = [Self::Webp, Self::Avif];
+
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Self::Webp => "webp",
+			Self::Avif => "avif",
+		}
+	}
+
+	pub const fn mime_type(self) -> &'static str {
+		match self {
+			Self::Webp => "image/webp",
+			Self::Avif => "image/avif",
+		}
+	}
+
+	pub fn from_path(path: &Path) -> Option<Self> {
+		let extension = path.extension()?;
+
+		Self::ALL
+			.into_iter()
+			.find(|format| extension == format.extension())
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailTierSettings {
+	/// Ignored for the grid tier, which is always generated
+	pub enabled: bool,
+	/// Size, in pixels, of the biggest side of the thumbnail. Images are never upscaled.
+	pub max_dimension: u32,
+	/// Encoding quality, from 0 to 100
+	pub quality: f32,
+}
+
+/// Per library settings of the generated thumbnails
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailSettings {
+	pub grid: ThumbnailTierSettings,
+	pub inspector: ThumbnailTierSettings,
+	pub preview: ThumbnailTierSettings,
+	/// Changing it re-encodes the existing thumbnails in the background
+	#[serde(default)]
+	pub format: ThumbnailFormat,
+	/// Least recently used thumbnails of the library are evicted past this size
+	#[serde(default = "default_max_cache_size_mb")]
+	pub max_cache_size_mb: Option<u32>,
+}
+
+fn default_max_cache_size_mb() -> Option<u32> {
+	Some(10 * 1024)
+}
+
+impl Default for ThumbnailSettings {
+	fn default() -> Self {
+		Self {
+			grid: ThumbnailTierSettings {
+				enabled: true,
+				max_dimension: 256,
+				quality: 30.0,
+			},
+			inspector: ThumbnailTierSettings {
+				enabled: true,
+				max_dimension: 768,
+				quality: 50.0,
+			},
+			preview: ThumbnailTierSettings {
+				enabled: false,
+				max_dimension: 1920,
+				quality: 70.0,
+			},
+			format: ThumbnailFormat::default(),
+			max_cache_size_mb: default_max_cache_size_mb(),
+		}
+	}
+}
+
+impl ThumbnailSettings {
+	pub fn tier(&self, tier: ThumbnailTier) -> &ThumbnailTierSettings {
+		match tier {
+			ThumbnailTier::Grid => &self.grid,
+			ThumbnailTier::Inspector => &self.inspector,
+			ThumbnailTier::Preview => &self.preview,
+		}
+	}
+
+	/// Tiers to generate, the grid one being always generated as it's the one checked to know
+	/// if a file already has thumbnails
+	pub fn enabled_tiers(
+		&self,
+	) -> impl Iterator<Item = (ThumbnailTier, ThumbnailTierSettings)> + '_ {
+		ThumbnailTier::ALL
+			.into_iter()
+			.map(|tier| (tier, *self.tier(tier)))
+			.filter(|(tier, settings)| *tier == ThumbnailTier::Grid || settings.enabled)
+	}
+
+	pub fn max_cache_size_in_bytes(&self) -> Option<u64> {
+		self.max_cache_size_mb
+			.map(|size_mb| size_mb as u64 * 1024 * 1024)
+	}
+
+	/// Size of the biggest thumbnail to generate, which sources rendered on demand, like
+	/// documents or SVGs, are rendered at
+	pub fn max_dimension(&self) -> u32 {
+		self.enabled_tiers()
+			.map(|(_, settings)| settings.max_dimension)
+			.max()
+			.unwrap_or(self.grid.max_dimension)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_tier_paths() {
+		let grid_path = Path::new("thumbnails/ab/abcdef.webp");
+
+		assert_eq!(ThumbnailTier::Grid.path_from_grid(grid_path), grid_path);
+		assert_eq!(
+			ThumbnailTier::Inspector.path_from_grid(grid_path),
+			Path::new("thumbnails/ab/abcdef-inspector.webp")
+		);
+		assert_eq!(
+			ThumbnailTier::Preview.path_from_grid(grid_path),
+			Path::new("thumbnails/ab/abcdef-preview.webp")
+		);
+
+		assert_eq!(
+			ThumbnailTier::Inspector.path_from_grid(Path::new("thumbnails/ab/abcdef.avif")),
+			Path::new("thumbnails/ab/abcdef-inspector.avif")
+		);
+		assert_eq!(
+			ThumbnailFormat::from_path(Path::new("thumbnails/ab/abcdef-preview.avif")),
+			Some(ThumbnailFormat::Avif)
+		);
+	}
+}
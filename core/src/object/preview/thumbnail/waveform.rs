@@ -0,0 +1,47 @@
+use crate::{library::Library, util::error::FileIOError};
+
+use std::path::PathBuf;
+
+use tokio::{fs, io};
+
+use super::{get_shard_hex, ThumbnailerError, THUMBNAIL_CACHE_DIR_NAME};
+
+/// Waveforms are stored next to the thumbnails, as `{cas_id}.peaks`, with one byte per peak
+pub const WAVEFORM_EXTENSION: &str = "peaks";
+
+/// Enough peaks for a scrubber as wide as a full screen preview, while keeping each waveform
+/// under a kilobyte
+pub const WAVEFORM_PEAKS_COUNT: usize = 512;
+
+/// This does not check if a waveform exists, it just returns the path that it would exist at
+pub fn get_waveform_path(library: &Library, cas_id: &str) -> PathBuf {
+	library
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(get_shard_hex(cas_id))
+		.join(format!("{cas_id}.{WAVEFORM_EXTENSION}"))
+}
+
+/// Peaks of the waveform of an audio file, from 0 to 255, if it was generated
+pub async fn get_waveform(
+	library: &Library,
+	cas_id: &str,
+) -> Result<Option<Vec<u8>>, ThumbnailerError> {
+	let path = get_waveform_path(library, cas_id);
+
+	match fs::read(&path).await {
+		Ok(peaks) => Ok(Some(peaks)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(FileIOError::from((path, e)).into()),
+	}
+}
+
+pub async fn remove_waveform(library: &Library, cas_id: &str) -> Result<(), FileIOError> {
+	let path = get_waveform_path(library, cas_id);
+
+	match fs::remove_file(&path).await {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		Err(e) => Err(FileIOError::from((path, e))),
+	}
+}
@@ -0,0 +1,240 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::IsolatedFilePathData,
+	prisma::{file_path, location, object},
+	util::db::{chain_optional_iter, maybe_missing},
+};
+
+use std::{
+	borrow::Cow,
+	collections::HashSet,
+	hash::{Hash, Hasher},
+	path::PathBuf,
+};
+
+use sd_file_ext::kind::ObjectKind;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use super::{flag_object, score_image};
+
+const CHUNK_SIZE: usize = 20;
+
+/// Flags the images of a location that weren't classified yet which likely are sensitive, when
+/// it's enabled on this node
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SensitiveContentClassifierJobInit {
+	pub location: location::Data,
+	pub sub_path: Option<PathBuf>,
+}
+
+impl Hash for SensitiveContentClassifierJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensitiveContentClassifierJobStep {
+	object_id: object::id::Type,
+	cas_id: Option<String>,
+	path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct SensitiveContentClassifierJobRunMetadata {
+	total_images: usize,
+	classified_images: usize,
+	flagged_images: usize,
+}
+
+impl JobRunMetadata for SensitiveContentClassifierJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_images += new_data.total_images;
+		self.classified_images += new_data.classified_images;
+		self.flagged_images += new_data.flagged_images;
+	}
+}
+
+file_path::select!(file_path_for_sensitive_classifier {
+	cas_id
+	materialized_path
+	name
+	extension
+	object_id
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for SensitiveContentClassifierJobInit {
+	type Data = ();
+	type Step = Vec<SensitiveContentClassifierJobStep>;
+	type RunMetadata = SensitiveContentClassifierJobRunMetadata;
+
+	const NAME: &'static str = "sensitive_content_classifier";
+	const PRIORITY: JobPriority = JobPriority::Background;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		_: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		if !ctx.library.config().get().await.sensitive_content.enabled {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "sensitive content detection is disabled on this node".to_string(),
+			});
+		}
+
+		if init.location.node_id != Some(ctx.library.node_local_id) {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: "the location is on another node".to_string(),
+			});
+		}
+
+		let location_path = PathBuf::from(maybe_missing(&init.location.path, "location.path")?);
+
+		let sub_path_materialized_path = init
+			.sub_path
+			.as_ref()
+			.filter(|sub_path| !sub_path.as_os_str().is_empty())
+			.and_then(|sub_path| {
+				IsolatedFilePathData::new(
+					init.location.id,
+					&location_path,
+					location_path.join(sub_path),
+					true,
+				)
+				.ok()?
+				.materialized_path_for_children()
+			});
+
+		let mut seen_objects = HashSet::new();
+
+		let steps = db
+			.file_path()
+			.find_many(chain_optional_iter(
+				[
+					file_path::location_id::equals(Some(init.location.id)),
+					file_path::date_deleted::equals(None),
+					file_path::object::is(vec![
+						object::kind::equals(Some(ObjectKind::Image as i32)),
+						object::sensitive_score::equals(None),
+					]),
+				],
+				[sub_path_materialized_path.map(file_path::materialized_path::starts_with)],
+			))
+			.select(file_path_for_sensitive_classifier::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| {
+				// Objects are classified once, whichever of their files is used
+				let object_id = file_path.object_id?;
+				seen_objects
+					.insert(object_id)
+					.then_some((object_id, file_path))
+			})
+			.map(|(object_id, file_path)| {
+				let iso_file_path = IsolatedFilePathData::from_db_data(
+					init.location.id,
+					false,
+					Cow::Owned(maybe_missing(
+						file_path.materialized_path,
+						"file_path.materialized_path",
+					)?),
+					Cow::Owned(maybe_missing(file_path.name, "file_path.name")?),
+					Cow::Owned(maybe_missing(file_path.extension, "file_path.extension")?),
+				);
+
+				Ok(SensitiveContentClassifierJobStep {
+					object_id,
+					cas_id: file_path.cas_id,
+					path: location_path.join(&iso_file_path),
+				})
+			})
+			.collect::<Result<Vec<_>, JobError>>()?;
+
+		ctx.progress_msg(format!("Classifying {} images", steps.len()));
+
+		Ok((
+			SensitiveContentClassifierJobRunMetadata {
+				total_images: steps.len(),
+				..Default::default()
+			},
+			steps
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: images, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let threshold = ctx.library.config().get().await.sensitive_content.threshold;
+
+		let mut new_metadata = SensitiveContentClassifierJobRunMetadata::default();
+
+		for image in images {
+			// Images that can't be decoded are left for when they can, like once thumbnailed
+			let Some(score) = score_image(&ctx.library, image.cas_id.as_deref(), &image.path).await
+			else {
+				continue;
+			};
+
+			if flag_object(&ctx.library, image.object_id, score, threshold).await? {
+				new_metadata.flagged_images += 1;
+			}
+			new_metadata.classified_images += 1;
+		}
+
+		ctx.progress_msg(format!(
+			"Classified {} of {} images",
+			run_metadata.classified_images + new_metadata.classified_images,
+			run_metadata.total_images
+		));
+
+		Ok(new_metadata.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Classified {} images of location {}, {} were flagged as sensitive",
+			run_metadata.classified_images, self.location.id, run_metadata.flagged_images
+		);
+
+		if run_metadata.flagged_images > 0 {
+			invalidate_query!(ctx.library, "tags.list");
+			invalidate_query!(ctx.library, "search.objects");
+		}
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
@@ -0,0 +1,271 @@
+//! Flags images that likely show nudity with a system tag, so they can be left out of shared
+//! views, searches and auto accepted Spacedrops. It's opt-in, and runs on this node only: images
+//! are scored by how much of them is skin toned, which needs no model to be downloaded, at the
+//! cost of flagging close-up portraits and skin colored things too. That's why the threshold
+//! is configurable, and why the tag can just be removed from objects flagged by mistake.
+
+use crate::{
+	library::Library,
+	object::preview::get_thumbnail_path,
+	prisma::{object, tag, tag_on_object},
+	sync,
+	util::{db::uuid_to_bytes, error::FileIOError},
+};
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+pub mod classifier_job;
+
+/// Pub id of the system tag, the same in every library so the tags created by each node of a
+/// library are one and the same once synced
+pub const SENSITIVE_TAG_PUB_ID: Uuid = Uuid::from_u128(0x5d5e4517_194c_4e0e_9a6b_8f3b2c71d0e4);
+const SENSITIVE_TAG_NAME: &str = "Sensitive";
+const SENSITIVE_TAG_COLOR: &str = "#F76808";
+
+/// Images are scaled down to this many pixels across before being scored
+const SAMPLE_SIZE: u32 = 128;
+
+/// Directory of the Spacedrop receive directory flagged files are moved to
+const HELD_DIR_NAME: &str = "Sensitive";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SensitiveContentSettings {
+	/// Whether the images of the locations of this node are classified at all
+	pub enabled: bool,
+	/// Share of an image that has to be skin toned for it to be flagged, between 0 and 1
+	pub threshold: f32,
+	/// Moves the auto accepted Spacedrops that are flagged aside, into a directory of their own
+	pub hold_spacedrops: bool,
+}
+
+impl Default for SensitiveContentSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			threshold: 0.5,
+			hold_spacedrops: false,
+		}
+	}
+}
+
+/// Share of the pixels of an image that are skin toned, between 0 and 1
+pub fn sensitive_score(image: &DynamicImage) -> f32 {
+	let sample = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+	let total = sample.width() * sample.height();
+	if total == 0 {
+		return 0.0;
+	}
+
+	let skin = sample
+		.pixels()
+		.filter(|pixel| is_skin_tone(pixel.0))
+		.count();
+
+	skin as f32 / total as f32
+}
+
+/// The explicit RGB skin color rule of Kovač, Peer and Solina, for daylight
+fn is_skin_tone([r, g, b]: [u8; 3]) -> bool {
+	let (r, g, b) = (i16::from(r), i16::from(g), i16::from(b));
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+
+	r > 95 && g > 40 && b > 20 && max - min > 15 && (r - g).abs() > 15 && r > g && r > b
+}
+
+async fn decode(path: PathBuf) -> Option<DynamicImage> {
+	spawn_blocking(move || match image::open(&path) {
+		Ok(image) => Some(image),
+		Err(e) => {
+			debug!("Failed to decode '{}' to classify it: {e}", path.display());
+			None
+		}
+	})
+	.await
+	.ok()
+	.flatten()
+}
+
+/// Scores an image from its thumbnail if it has one this node can decode, or from the file itself
+pub(crate) async fn score_image(
+	library: &Library,
+	cas_id: Option<&str>,
+	path: &Path,
+) -> Option<f32> {
+	let mut image = None;
+	if let Some(cas_id) = cas_id {
+		let thumbnail_path = get_thumbnail_path(library, cas_id);
+		if fs::try_exists(&thumbnail_path).await.unwrap_or(false) {
+			image = decode(thumbnail_path).await;
+		}
+	}
+	if image.is_none() {
+		image = decode(path.to_path_buf()).await;
+	}
+
+	let image = image?;
+	spawn_blocking(move || sensitive_score(&image)).await.ok()
+}
+
+/// Id of the system tag in the library, creating it the first time it's needed
+pub(crate) async fn sensitive_tag_id(
+	library: &Library,
+) -> prisma_client_rust::Result<tag::id::Type> {
+	let Library { db, sync, .. } = library;
+	let pub_id = uuid_to_bytes(SENSITIVE_TAG_PUB_ID);
+
+	if let Some(tag) = db
+		.tag()
+		.find_unique(tag::pub_id::equals(pub_id.clone()))
+		.select(tag::select!({ id }))
+		.exec()
+		.await?
+	{
+		return Ok(tag.id);
+	}
+
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+	sync.write_op(
+		db,
+		sync.unique_shared_create(
+			sync::tag::SyncId {
+				pub_id: pub_id.clone(),
+			},
+			[
+				(tag::name::NAME, json!(SENSITIVE_TAG_NAME)),
+				(tag::color::NAME, json!(SENSITIVE_TAG_COLOR)),
+				(tag::date_created::NAME, json!(&date_created.to_rfc3339())),
+			],
+		),
+		db.tag().create(
+			pub_id,
+			vec![
+				tag::name::set(Some(SENSITIVE_TAG_NAME.to_string())),
+				tag::color::set(Some(SENSITIVE_TAG_COLOR.to_string())),
+				tag::date_created::set(Some(date_created)),
+			],
+		),
+	)
+	.await
+	.map(|tag| tag.id)
+}
+
+/// Keeps the score of an object, tagging it if it's over the threshold
+pub(crate) async fn flag_object(
+	library: &Library,
+	object_id: object::id::Type,
+	score: f32,
+	threshold: f32,
+) -> prisma_client_rust::Result<bool> {
+	let db = &library.db;
+
+	db.object()
+		.update(
+			object::id::equals(object_id),
+			vec![object::sensitive_score::set(Some(score.into()))],
+		)
+		.exec()
+		.await?;
+
+	if score < threshold {
+		return Ok(false);
+	}
+
+	let tag_id = sensitive_tag_id(library).await?;
+	db.tag_on_object()
+		.upsert(
+			tag_on_object::tag_id_object_id(tag_id, object_id),
+			tag_on_object::create(
+				tag::id::equals(tag_id),
+				object::id::equals(object_id),
+				vec![],
+			),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(true)
+}
+
+/// Objects that aren't tagged as sensitive, to leave the others out of a query
+pub fn not_sensitive() -> object::WhereParam {
+	object::tags::none(vec![tag_on_object::tag::is(vec![tag::pub_id::equals(
+		uuid_to_bytes(SENSITIVE_TAG_PUB_ID),
+	)])])
+}
+
+/// Moves an auto accepted Spacedrop aside if it's flagged, returning where it was moved to
+pub(crate) async fn hold_if_sensitive(
+	settings: &SensitiveContentSettings,
+	path: &Path,
+) -> Result<Option<PathBuf>, FileIOError> {
+	if !settings.enabled || !settings.hold_spacedrops {
+		return Ok(None);
+	}
+
+	let Some(image) = decode(path.to_path_buf()).await else {
+		return Ok(None);
+	};
+	let score = spawn_blocking(move || sensitive_score(&image))
+		.await
+		.unwrap_or_default();
+	if score < settings.threshold {
+		return Ok(None);
+	}
+
+	let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+		return Ok(None);
+	};
+	let held_dir = parent.join(HELD_DIR_NAME);
+	fs::create_dir_all(&held_dir)
+		.await
+		.map_err(|e| FileIOError::from((&held_dir, e)))?;
+
+	// Not overwriting what's already held under the same name
+	let mut held_path = held_dir.join(file_name);
+	let mut copy = 1;
+	while fs::try_exists(&held_path).await.unwrap_or(false) {
+		held_path = held_dir.join(format!("{copy}-{}", file_name.to_string_lossy()));
+		copy += 1;
+	}
+
+	fs::rename(path, &held_path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	info!(
+		"Moved Spacedrop '{}' to '{}', as it was flagged as sensitive",
+		path.display(),
+		held_path.display()
+	);
+
+	Ok(Some(held_path))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn scores_skin_toned_images() {
+		let skin = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([224, 172, 140])));
+		let sky = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([90, 150, 230])));
+
+		assert_eq!(sensitive_score(&skin), 1.0);
+		assert_eq!(sensitive_score(&sky), 0.0);
+	}
+}
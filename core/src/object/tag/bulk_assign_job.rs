@@ -0,0 +1,183 @@
+use crate::{
+	api::search::ObjectFilterArgs,
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	object::xmp::spawn_sidecar_write_back,
+	prisma::{object, tag, tag_on_object},
+};
+
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::info;
+
+const CHUNK_SIZE: usize = 500;
+
+/// The set of objects a bulk tag operation applies to
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub enum BulkTagSelection {
+	Objects(Vec<object::id::Type>),
+	Search(ObjectFilterArgs),
+}
+
+/// `BulkTagAssignJobInit` applies or removes a set of tags on a (possibly huge) selection
+/// of objects, writing the `tag_on_object` rows in chunked transactions.
+#[derive(Serialize, Deserialize, Type, Debug)]
+pub struct BulkTagAssignJobInit {
+	pub tag_ids: Vec<tag::id::Type>,
+	pub selection: BulkTagSelection,
+	pub unassign: bool,
+}
+
+impl Hash for BulkTagAssignJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.tag_ids.hash(state);
+		self.unassign.hash(state);
+		match &self.selection {
+			BulkTagSelection::Objects(object_ids) => object_ids.hash(state),
+			BulkTagSelection::Search(filter) => serde_json::to_string(filter)
+				.unwrap_or_default()
+				.hash(state),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct BulkTagAssignJobRunMetadata {
+	total_objects: usize,
+	processed_objects: usize,
+}
+
+impl JobRunMetadata for BulkTagAssignJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_objects += new_data.total_objects;
+		self.processed_objects += new_data.processed_objects;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for BulkTagAssignJobInit {
+	type Data = ();
+	type Step = Vec<object::id::Type>;
+	type RunMetadata = BulkTagAssignJobRunMetadata;
+
+	const NAME: &'static str = "bulk_tag_assign";
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		let object_ids = match &self.selection {
+			BulkTagSelection::Objects(object_ids) => object_ids.clone(),
+			BulkTagSelection::Search(filter) => db
+				.object()
+				.find_many(filter.clone().into_params())
+				.select(object::select!({ id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|object| object.id)
+				.collect(),
+		};
+
+		*data = Some(());
+
+		Ok((
+			BulkTagAssignJobRunMetadata {
+				total_objects: object_ids.len(),
+				processed_objects: 0,
+			},
+			object_ids
+				.chunks(CHUNK_SIZE)
+				.map(<[_]>::to_vec)
+				.collect::<Vec<_>>(),
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep {
+			step: object_ids, ..
+		}: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		// Removing first makes assigning idempotent, as `create_many` fails on already tagged objects
+		let delete = db.tag_on_object().delete_many(vec![
+			tag_on_object::tag_id::in_vec(self.tag_ids.clone()),
+			tag_on_object::object_id::in_vec(object_ids.clone()),
+		]);
+
+		if self.unassign {
+			delete.exec().await?;
+		} else {
+			db._batch((
+				delete,
+				db.tag_on_object().create_many(
+					self.tag_ids
+						.iter()
+						.flat_map(|&tag_id| {
+							object_ids.iter().map(move |&object_id| {
+								tag_on_object::CreateUnchecked {
+									tag_id,
+									object_id,
+									_params: vec![],
+								}
+							})
+						})
+						.collect(),
+				),
+			))
+			.await?;
+		}
+
+		spawn_sidecar_write_back(&ctx.library, object_ids.clone());
+
+		let processed_objects = run_metadata.processed_objects + object_ids.len();
+
+		ctx.progress_msg(format!(
+			"{} tags on {} of {} objects",
+			if self.unassign { "Removed" } else { "Applied" },
+			processed_objects,
+			run_metadata.total_objects
+		));
+
+		Ok(BulkTagAssignJobRunMetadata {
+			total_objects: 0,
+			processed_objects: object_ids.len(),
+		}
+		.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		info!(
+			"Finalizing bulk tag job: {} tags on {} objects",
+			self.tag_ids.len(),
+			run_metadata.processed_objects
+		);
+
+		invalidate_query!(ctx.library, "tags.getForObject");
+		invalidate_query!(ctx.library, "search.objects");
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(json!({ "run_metadata": run_metadata })))
+	}
+}
@@ -1,3 +1,4 @@
+pub mod bulk_assign_job;
 pub mod seed;
 
 use chrono::{DateTime, FixedOffset, Utc};
@@ -47,3 +48,29 @@ impl TagCreateArgs {
 		.await
 	}
 }
+
+/// Id of the tag with this name, created with `color` if there is none, used to bring in the
+/// tags of other apps
+pub async fn find_or_create_tag(
+	library: &Library,
+	name: &str,
+	color: &str,
+) -> prisma_client_rust::Result<tag::id::Type> {
+	match library
+		.db
+		.tag()
+		.find_first(vec![tag::name::equals(Some(name.to_string()))])
+		.select(tag::select!({ id }))
+		.exec()
+		.await?
+	{
+		Some(tag) => Ok(tag.id),
+		None => Ok(TagCreateArgs {
+			name: name.to_string(),
+			color: color.to_string(),
+		}
+		.exec(library)
+		.await?
+		.id),
+	}
+}
@@ -0,0 +1,405 @@
+//! Undo and redo of the moves, renames and tag assignments done from the app. Each of them records
+//! an [`UndoableOperation`] in the history of its library, which is inverted and replayed on undo.
+//! Files are referenced by their path, as moving them between locations changes their ids.
+
+use crate::{
+	invalidate_query,
+	job::{Job, JobManagerError},
+	library::Library,
+	location::{
+		file_path_helper::{filter_existing_file_path_params, IsolatedFilePathData},
+		find_location, LocationError,
+	},
+	object::{fs::cut::FileCutterJobInit, xmp::spawn_sidecar_write_back},
+	prisma::{file_path, location, object, tag, tag_on_object},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use std::{
+	collections::{BTreeMap, VecDeque},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use prisma_client_rust::operator::{and, or};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs, sync::Mutex};
+use tracing::debug;
+
+/// Operations kept in the history of each library, older ones can't be undone anymore
+const MAX_UNDO_HISTORY: usize = 50;
+
+/// A file or directory of a location
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRef {
+	pub location_id: location::id::Type,
+	/// Relative to the root of the location, without a leading or trailing slash
+	pub relative_path: String,
+	pub is_dir: bool,
+}
+
+impl FileRef {
+	pub fn from_iso_file_path(iso_file_path: &IsolatedFilePathData<'_>) -> Self {
+		Self {
+			location_id: iso_file_path.location_id(),
+			relative_path: iso_file_path.to_string(),
+			is_dir: iso_file_path.is_dir(),
+		}
+	}
+
+	/// The same file with another name, in the same directory
+	pub fn renamed(&self, full_name: &str) -> Self {
+		Self {
+			relative_path: match self.relative_path.rsplit_once('/') {
+				Some((parent, _)) => format!("{parent}/{full_name}"),
+				None => full_name.to_string(),
+			},
+			..self.clone()
+		}
+	}
+
+	/// Directory of the file, relative to the root of the location
+	fn parent(&self) -> PathBuf {
+		self.relative_path
+			.rsplit_once('/')
+			.map(|(parent, _)| PathBuf::from(parent))
+			.unwrap_or_default()
+	}
+
+	fn params(&self) -> file_path::WhereParam {
+		// Directories are told apart by their trailing slash
+		let relative_path = if self.is_dir {
+			format!("{}/", self.relative_path)
+		} else {
+			self.relative_path.clone()
+		};
+
+		and(filter_existing_file_path_params(
+			&IsolatedFilePathData::from_relative_str(self.location_id, &relative_path),
+		))
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct FileChange {
+	pub from: FileRef,
+	pub to: FileRef,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UndoableOperation {
+	/// Files moved to another directory, replayed with the file cutter job
+	Move { files: Vec<FileChange> },
+	/// Files renamed in place
+	Rename { files: Vec<FileChange> },
+	/// Objects that were assigned a tag, or unassigned from it. Only the objects whose tags
+	/// actually changed are kept, so undoing doesn't untag the objects already tagged.
+	Tag {
+		tag_id: tag::id::Type,
+		object_ids: Vec<object::id::Type>,
+		assigned: bool,
+	},
+}
+
+#[derive(Error, Debug)]
+pub enum UndoError {
+	#[error("nothing to undo")]
+	NothingToUndo,
+	#[error("nothing to redo")]
+	NothingToRedo,
+	#[error("file not found anymore: <location_id={}, path='{}'>", .0.location_id, .0.relative_path)]
+	FileNotFound(FileRef),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	JobManager(#[from] JobManagerError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<UndoError> for rspc::Error {
+	fn from(err: UndoError) -> Self {
+		match err {
+			UndoError::NothingToUndo | UndoError::NothingToRedo => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			UndoError::FileNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::Conflict, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+impl UndoableOperation {
+	/// The operation that reverts this one
+	pub fn inverse(&self) -> Self {
+		let swap = |files: &[FileChange]| {
+			files
+				.iter()
+				.map(|FileChange { from, to }| FileChange {
+					from: to.clone(),
+					to: from.clone(),
+				})
+				.collect()
+		};
+
+		match self {
+			Self::Move { files } => Self::Move { files: swap(files) },
+			Self::Rename { files } => Self::Rename { files: swap(files) },
+			Self::Tag {
+				tag_id,
+				object_ids,
+				assigned,
+			} => Self::Tag {
+				tag_id: *tag_id,
+				object_ids: object_ids.clone(),
+				assigned: !assigned,
+			},
+		}
+	}
+
+	async fn apply(&self, library: &Library) -> Result<(), UndoError> {
+		match self {
+			Self::Move { files } => {
+				let db = &library.db;
+
+				// A cutter job moves files from a single location to a single directory
+				let mut groups = BTreeMap::<_, Vec<_>>::new();
+				for FileChange { from, to } in files {
+					groups
+						.entry((from.location_id, to.location_id, to.parent()))
+						.or_default()
+						.push(from);
+				}
+
+				for ((source_location_id, target_location_id, target_dir), sources) in groups {
+					let file_paths = db
+						.file_path()
+						.find_many(vec![or(sources.iter().map(|from| from.params()).collect())])
+						.select(file_path::select!({ id }))
+						.exec()
+						.await?;
+
+					if file_paths.len() != sources.len() {
+						return Err(UndoError::FileNotFound(sources[0].clone()));
+					}
+
+					Job::new(FileCutterJobInit {
+						source_location_id,
+						target_location_id,
+						sources_file_path_ids: file_paths
+							.into_iter()
+							.map(|file_path| file_path.id)
+							.collect(),
+						target_location_relative_directory_path: target_dir,
+					})
+					.spawn(library)
+					.await?;
+				}
+			}
+			Self::Rename { files } => {
+				for FileChange { from, to } in files {
+					let location_path = maybe_missing(
+						find_location(library, from.location_id)
+							.select(location::select!({ path }))
+							.exec()
+							.await?
+							.ok_or(LocationError::IdNotFound(from.location_id))?
+							.path,
+						"location.path",
+					)
+					.map_err(LocationError::MissingField)?;
+					let location_path = Path::new(&location_path);
+
+					let source = location_path.join(&from.relative_path);
+					if fs::metadata(&source).await.is_err() {
+						return Err(UndoError::FileNotFound(from.clone()));
+					}
+
+					fs::rename(&source, location_path.join(&to.relative_path))
+						.await
+						.map_err(|e| FileIOError::from((source, e)))?;
+				}
+
+				invalidate_query!(library, "search.paths");
+			}
+			Self::Tag {
+				tag_id,
+				object_ids,
+				assigned,
+			} => {
+				let db = &library.db;
+
+				if *assigned {
+					db.tag_on_object()
+						.create_many(
+							object_ids
+								.iter()
+								.map(|&object_id| tag_on_object::CreateUnchecked {
+									tag_id: *tag_id,
+									object_id,
+									_params: vec![],
+								})
+								.collect(),
+						)
+						.skip_duplicates()
+						.exec()
+						.await?;
+				} else {
+					db.tag_on_object()
+						.delete_many(vec![
+							tag_on_object::tag_id::equals(*tag_id),
+							tag_on_object::object_id::in_vec(object_ids.clone()),
+						])
+						.exec()
+						.await?;
+				}
+
+				invalidate_query!(library, "tags.getForObject");
+
+				spawn_sidecar_write_back(library, object_ids.clone());
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Default)]
+struct UndoStacks {
+	undo: VecDeque<UndoableOperation>,
+	redo: Vec<UndoableOperation>,
+}
+
+/// What can currently be undone and redone
+#[derive(Debug, Serialize, Type)]
+pub struct UndoState {
+	pub undo: Option<UndoableOperation>,
+	pub redo: Option<UndoableOperation>,
+}
+
+/// Undo and redo stacks of a library, kept in memory
+#[derive(Clone, Default)]
+pub struct UndoHistory {
+	stacks: Arc<Mutex<UndoStacks>>,
+}
+
+impl UndoHistory {
+	/// Records an operation that was just done, which can't be redone anymore once it's undone
+	/// if another operation was recorded in between
+	pub async fn record(&self, operation: UndoableOperation) {
+		let mut stacks = self.stacks.lock().await;
+
+		stacks.undo.push_back(operation);
+		if stacks.undo.len() > MAX_UNDO_HISTORY {
+			stacks.undo.pop_front();
+		}
+		stacks.redo.clear();
+	}
+
+	/// Reverts the last operation, which stays in the history if it fails
+	pub async fn undo(&self, library: &Library) -> Result<UndoableOperation, UndoError> {
+		let mut stacks = self.stacks.lock().await;
+
+		let operation = stacks.undo.pop_back().ok_or(UndoError::NothingToUndo)?;
+
+		debug!("Undoing {operation:?}");
+
+		if let Err(e) = operation.inverse().apply(library).await {
+			stacks.undo.push_back(operation);
+			return Err(e);
+		}
+
+		stacks.redo.push(operation.clone());
+
+		Ok(operation)
+	}
+
+	/// Replays the last undone operation
+	pub async fn redo(&self, library: &Library) -> Result<UndoableOperation, UndoError> {
+		let mut stacks = self.stacks.lock().await;
+
+		let operation = stacks.redo.pop().ok_or(UndoError::NothingToRedo)?;
+
+		debug!("Redoing {operation:?}");
+
+		if let Err(e) = operation.apply(library).await {
+			stacks.redo.push(operation);
+			return Err(e);
+		}
+
+		stacks.undo.push_back(operation.clone());
+
+		Ok(operation)
+	}
+
+	pub async fn state(&self) -> UndoState {
+		let stacks = self.stacks.lock().await;
+
+		UndoState {
+			undo: stacks.undo.back().cloned(),
+			redo: stacks.redo.last().cloned(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(relative_path: &str) -> FileRef {
+		FileRef {
+			location_id: 1,
+			relative_path: relative_path.to_string(),
+			is_dir: false,
+		}
+	}
+
+	#[test]
+	fn inverse_of_inverse_is_the_operation() {
+		let operations = [
+			UndoableOperation::Move {
+				files: vec![FileChange {
+					from: file("a/photo.jpg"),
+					to: file("b/photo.jpg"),
+				}],
+			},
+			UndoableOperation::Rename {
+				files: vec![FileChange {
+					from: file("a/photo.jpg"),
+					to: file("a/renamed.jpg"),
+				}],
+			},
+			UndoableOperation::Tag {
+				tag_id: 1,
+				object_ids: vec![1, 2],
+				assigned: true,
+			},
+		];
+
+		for operation in operations {
+			assert_ne!(operation.inverse(), operation);
+			assert_eq!(operation.inverse().inverse(), operation);
+		}
+	}
+
+	#[test]
+	fn renamed_keeps_the_directory() {
+		assert_eq!(
+			file("a/b/photo.jpg").renamed("other.png").relative_path,
+			"a/b/other.png"
+		);
+		assert_eq!(
+			file("photo.jpg").renamed("other.png").relative_path,
+			"other.png"
+		);
+	}
+}
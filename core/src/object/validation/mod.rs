@@ -5,6 +5,7 @@ use std::path::Path;
 use thiserror::Error;
 
 pub mod hash;
+pub mod quarantine;
 pub mod validator_job;
 
 #[derive(Error, Debug)]
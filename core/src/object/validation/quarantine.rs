@@ -0,0 +1,315 @@
+//! Files failing an integrity check are quarantined: hidden from browsing and listed by
+//! `files.quarantined` instead, until they're repaired or released. Like being marked as deleted,
+//! it's about the copy on this node only, so it isn't synced.
+
+use crate::{
+	custom_uri, invalidate_query,
+	library::Library,
+	location::file_path_helper::{file_path_for_quarantine, FilePathError, IsolatedFilePathData},
+	object::{
+		content_cache::{materialize, ContentCacheError},
+		fs::copy::partial_copy_path,
+	},
+	p2p::P2PManager,
+	prisma::{file_path, location, object, SortOrder},
+	sync,
+	util::{
+		db::{chain_optional_iter, maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::{
+	collections::HashSet,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use rspc::ErrorCode;
+use serde::Serialize;
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tokio::fs;
+use tracing::{error, info};
+
+use super::hash::file_checksum;
+
+/// Files being repaired, so asking for a repair again while it runs doesn't fetch them twice
+static REPAIRING: Lazy<Mutex<HashSet<file_path::id::Type>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Error)]
+pub enum QuarantineError {
+	#[error("quarantined file path not found: <id='{0}'>")]
+	NotQuarantined(file_path::id::Type),
+	#[error("no other device has a good copy of the file")]
+	NoGoodCopy,
+	#[error("the copy fetched from another device doesn't match the checksum either")]
+	BadCopy,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	ContentCache(#[from] ContentCacheError),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+impl From<QuarantineError> for rspc::Error {
+	fn from(err: QuarantineError) -> Self {
+		match err {
+			QuarantineError::NotQuarantined(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			QuarantineError::NoGoodCopy => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A quarantined file, as listed in the report
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedFile {
+	pub file_path: file_path::Data,
+	/// File path of a good copy on another node, the file can be repaired from, if there's one
+	pub repairable_from: Option<file_path::id::Type>,
+}
+
+pub(crate) async fn quarantine_file_path(
+	library: &Library,
+	id: file_path::id::Type,
+) -> Result<(), prisma_client_rust::QueryError> {
+	library
+		.db
+		.file_path()
+		.update(
+			file_path::id::equals(id),
+			vec![file_path::date_quarantined::set(Some(Utc::now().into()))],
+		)
+		.exec()
+		.await?;
+
+	custom_uri::evict_file_path(library.id, id);
+
+	invalidate_query!(library, "search.paths");
+	invalidate_query!(library, "files.quarantined");
+
+	Ok(())
+}
+
+/// A copy of an object on a location of another node, that still has the checksum a quarantined
+/// file of it should have
+pub(crate) async fn find_good_copy(
+	library: &Library,
+	object_id: object::id::Type,
+	integrity_checksum: &str,
+) -> Result<Option<file_path::id::Type>, prisma_client_rust::QueryError> {
+	Ok(library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::object_id::equals(Some(object_id)),
+			file_path::integrity_checksum::equals(Some(integrity_checksum.to_string())),
+			file_path::date_deleted::equals(None),
+			file_path::date_quarantined::equals(None),
+			file_path::location::is(vec![location::node_id::not(Some(library.node_local_id))]),
+		])
+		.select(file_path::select!({ id }))
+		.exec()
+		.await?
+		.map(|file_path| file_path.id))
+}
+
+/// Quarantined files, with the good copies they can be repaired from
+pub async fn quarantined_files(
+	library: &Library,
+	location_id: Option<location::id::Type>,
+) -> Result<Vec<QuarantinedFile>, QuarantineError> {
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(chain_optional_iter(
+			[file_path::date_quarantined::not(None)],
+			[location_id.map(|id| file_path::location_id::equals(Some(id)))],
+		))
+		.order_by(file_path::date_quarantined::order(SortOrder::Desc))
+		.exec()
+		.await?;
+
+	let mut files = Vec::with_capacity(file_paths.len());
+	for file_path in file_paths {
+		let repairable_from = match (file_path.object_id, &file_path.integrity_checksum) {
+			(Some(object_id), Some(checksum)) => {
+				find_good_copy(library, object_id, checksum).await?
+			}
+			_ => None,
+		};
+
+		files.push(QuarantinedFile {
+			file_path,
+			repairable_from,
+		});
+	}
+
+	Ok(files)
+}
+
+async fn quarantined_file_path(
+	library: &Library,
+	id: file_path::id::Type,
+) -> Result<file_path_for_quarantine::Data, QuarantineError> {
+	library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::id::equals(id),
+			file_path::date_quarantined::not(None),
+		])
+		.select(file_path_for_quarantine::select())
+		.exec()
+		.await?
+		.ok_or(QuarantineError::NotQuarantined(id))
+}
+
+fn full_path(file_path: &file_path_for_quarantine::Data) -> Result<PathBuf, QuarantineError> {
+	let location = maybe_missing(&file_path.location, "file_path.location")?;
+	let location_path = maybe_missing(&location.path, "location.path")?;
+
+	Ok(Path::new(location_path).join(IsolatedFilePathData::try_from((location.id, file_path))?))
+}
+
+/// Takes files out of quarantine as they are, accepting their current content. Their checksums
+/// are cleared, to be computed again from that content by the next validation.
+pub async fn release_quarantined(
+	library: &Library,
+	ids: Vec<file_path::id::Type>,
+) -> Result<(), QuarantineError> {
+	let Library { db, sync, .. } = library;
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::id::in_vec(ids),
+			file_path::date_quarantined::not(None),
+		])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await?;
+
+	for file_path in file_paths {
+		sync.write_op(
+			db,
+			sync.shared_update(
+				sync::file_path::SyncId {
+					pub_id: file_path.pub_id.clone(),
+				},
+				file_path::integrity_checksum::NAME,
+				json!(null),
+			),
+			db.file_path().update(
+				file_path::id::equals(file_path.id),
+				vec![
+					file_path::integrity_checksum::set(None),
+					file_path::date_quarantined::set(None),
+				],
+			),
+		)
+		.await?;
+	}
+
+	invalidate_query!(library, "search.paths");
+	invalidate_query!(library, "files.quarantined");
+
+	Ok(())
+}
+
+/// Replaces a quarantined file with a good copy fetched from another node, taking it out of
+/// quarantine once its checksum is checked
+pub async fn repair_quarantined(
+	library: &Library,
+	p2p: &P2PManager,
+	id: file_path::id::Type,
+) -> Result<(), QuarantineError> {
+	let file_path = quarantined_file_path(library, id).await?;
+
+	let (Some(object_id), Some(checksum)) = (file_path.object_id, &file_path.integrity_checksum)
+	else {
+		return Err(QuarantineError::NoGoodCopy);
+	};
+	let good_copy_id = find_good_copy(library, object_id, checksum)
+		.await?
+		.ok_or(QuarantineError::NoGoodCopy)?;
+
+	let fetched_path = materialize(library, p2p, good_copy_id).await?;
+	let fetched_checksum = file_checksum(&fetched_path)
+		.await
+		.map_err(|e| FileIOError::from((&fetched_path, e)))?;
+	if &fetched_checksum != checksum {
+		return Err(QuarantineError::BadCopy);
+	}
+
+	// Written aside first, so the quarantined file is only replaced by a whole good copy
+	let path = full_path(&file_path)?;
+	let partial_path = partial_copy_path(&path);
+	fs::copy(&fetched_path, &partial_path)
+		.await
+		.map_err(|e| FileIOError::from((&partial_path, e)))?;
+	fs::rename(&partial_path, &path)
+		.await
+		.map_err(|e| FileIOError::from((&partial_path, e)))?;
+
+	library
+		.db
+		.file_path()
+		.update(
+			file_path::id::equals(id),
+			vec![file_path::date_quarantined::set(None)],
+		)
+		.exec()
+		.await?;
+
+	info!(
+		"Repaired quarantined file at {} from a copy on another device",
+		path.display()
+	);
+
+	invalidate_query!(library, "search.paths");
+	invalidate_query!(library, "files.quarantined");
+
+	Ok(())
+}
+
+/// Repairs a quarantined file in the background, as fetching it from another node can take a
+/// while, if it isn't already being repaired
+pub fn repair_quarantined_in_background(
+	library: Library,
+	p2p: Arc<P2PManager>,
+	id: file_path::id::Type,
+) {
+	if !REPAIRING
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.insert(id)
+	{
+		return;
+	}
+
+	tokio::spawn(async move {
+		if let Err(e) = repair_quarantined(&library, &p2p, id).await {
+			error!("Failed to repair quarantined file <id='{id}'>: {e}");
+		}
+
+		REPAIRING
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.remove(&id);
+	});
+}
@@ -1,8 +1,9 @@
 use crate::{
 	job::{
-		CurrentStep, JobError, JobInitOutput, JobResult, JobStepOutput, StatefulJob, WorkerContext,
+		CurrentStep, JobError, JobInitOutput, JobPriority, JobResult, JobStepOutput, RetryPolicy,
+		StatefulJob, WorkerContext,
 	},
-	library::Library,
+	library::{Library, NotificationKind, WebhookEvent},
 	location::file_path_helper::{
 		ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
 		file_path_for_object_validator, IsolatedFilePathData,
@@ -22,9 +23,13 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::info;
+use tracing::{info, warn};
 
-use super::{hash::file_checksum, ValidatorError};
+use super::{
+	hash::file_checksum,
+	quarantine::{find_good_copy, quarantine_file_path},
+	ValidatorError,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ObjectValidatorJobData {
@@ -37,6 +42,10 @@ pub struct ObjectValidatorJobData {
 pub struct ObjectValidatorJobInit {
 	pub location: location::Data,
 	pub sub_path: Option<PathBuf>,
+	/// Also recomputes the checksums of files that already have one, reporting the ones that
+	/// don't match anymore as integrity failures
+	#[serde(default)]
+	pub verify: bool,
 }
 
 impl Hash for ObjectValidatorJobInit {
@@ -45,6 +54,7 @@ impl Hash for ObjectValidatorJobInit {
 		if let Some(ref sub_path) = self.sub_path {
 			sub_path.hash(state);
 		}
+		self.verify.hash(state);
 	}
 }
 
@@ -59,6 +69,12 @@ impl StatefulJob for ObjectValidatorJobInit {
 	type RunMetadata = ();
 
 	const NAME: &'static str = "object_validator";
+	const PRIORITY: JobPriority = JobPriority::Background;
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("location-{}", self.location.id))
+	}
 
 	async fn init(
 		&self,
@@ -105,13 +121,15 @@ impl StatefulJob for ObjectValidatorJobInit {
 				[
 					file_path::location_id::equals(Some(init.location.id)),
 					file_path::is_dir::equals(Some(false)),
-					file_path::integrity_checksum::equals(None),
 				],
-				[maybe_sub_iso_file_path.and_then(|iso_sub_path| {
-					iso_sub_path
-						.materialized_path_for_children()
-						.map(file_path::materialized_path::starts_with)
-				})],
+				[
+					(!init.verify).then(|| file_path::integrity_checksum::equals(None)),
+					maybe_sub_iso_file_path.and_then(|iso_sub_path| {
+						iso_sub_path
+							.materialized_path_for_children()
+							.map(file_path::materialized_path::starts_with)
+					}),
+				],
 			))
 			.select(file_path_for_object_validator::select())
 			.exec()
@@ -137,19 +155,56 @@ impl StatefulJob for ObjectValidatorJobInit {
 		let init = self;
 		let Library { db, sync, .. } = &ctx.library;
 
-		// this is to skip files that already have checksums
-		// i'm unsure what the desired behaviour is in this case
-		// we can also compare old and new checksums here
-		// This if is just to make sure, we already queried objects where integrity_checksum is null
-		if file_path.integrity_checksum.is_none() {
-			let full_path = data.location_path.join(IsolatedFilePathData::try_from((
-				init.location.id,
-				file_path,
-			))?);
-			let checksum = file_checksum(&full_path)
-				.await
-				.map_err(|e| ValidatorError::FileIO(FileIOError::from((full_path, e))))?;
+		if file_path.integrity_checksum.is_some() && !init.verify {
+			return Ok(().into());
+		}
 
+		let iso_file_path = IsolatedFilePathData::try_from((init.location.id, file_path))?;
+		let full_path = data.location_path.join(&iso_file_path);
+		let checksum = file_checksum(&full_path)
+			.await
+			.map_err(|e| ValidatorError::FileIO(FileIOError::from((&full_path, e))))?;
+
+		// When verifying, files that already have checksums get their old and new ones compared
+		if let Some(integrity_checksum) = &file_path.integrity_checksum {
+			if &checksum != integrity_checksum {
+				warn!(
+					"Integrity check failed for file at {}, its content doesn't match its checksum",
+					full_path.display()
+				);
+
+				// Hidden until it's repaired or released, as it can't be trusted anymore
+				quarantine_file_path(&ctx.library, file_path.id).await?;
+
+				let repairable = match file_path.object_id {
+					Some(object_id) => find_good_copy(&ctx.library, object_id, integrity_checksum)
+						.await?
+						.is_some(),
+					None => false,
+				};
+
+				let path = format!(
+					"{}{}",
+					file_path.materialized_path.as_deref().unwrap_or("/"),
+					iso_file_path.full_name()
+				);
+
+				ctx.library
+					.webhooks
+					.dispatch(WebhookEvent::IntegrityFailure {
+						location_id: init.location.id,
+						path: path.clone(),
+					});
+
+				ctx.library
+					.notify(NotificationKind::IntegrityFailure {
+						location_id: init.location.id,
+						path,
+						repairable,
+					})
+					.await;
+			}
+		} else {
 			sync.write_op(
 				db,
 				sync.shared_update(
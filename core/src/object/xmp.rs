@@ -0,0 +1,637 @@
+//! XMP sidecars are the files photo managers like Lightroom and Darktable keep the ratings,
+//! labels and keywords of photos in, next to them. Reading them while identifying files brings
+//! that organization into Spacedrive, and writing tags and ratings back to them lets those apps
+//! see the changes made in Spacedrive.
+//!
+//! Sidecars are expected to use the usual `rdf`, `xmp` and `dc` prefixes, like every app writing
+//! them does.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_file_identifier, IsolatedFilePathData},
+	object::tag::find_or_create_tag,
+	prisma::{file_path, location, object, tag, tag_on_object},
+	sync,
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::path::{Path, PathBuf};
+
+use quick_xml::{
+	events::{attributes::AttrError, BytesEnd, BytesStart, BytesText, Event},
+	Reader, Writer,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use thiserror::Error;
+use tokio::fs;
+use tracing::{debug, error};
+
+/// Color of the tags created for the labels and keywords of sidecars
+const SIDECAR_TAG_COLOR: &str = "#646278";
+
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// Sidecar written for files which don't have one yet
+const SIDECAR_TEMPLATE: &str = r#"<?xpacket begin="﻿" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#;
+
+/// Whether XMP sidecars are read and written for a library
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct XmpSidecarSettings {
+	/// Imports the rating, label and keywords of sidecars when identifying their files
+	pub read: bool,
+	/// Writes the tags and rating of objects to the sidecars of their files when they change
+	pub write: bool,
+	/// How sidecars are named when one has to be created
+	pub naming: XmpSidecarNaming,
+}
+
+impl Default for XmpSidecarSettings {
+	fn default() -> Self {
+		Self {
+			read: true,
+			write: false,
+			naming: XmpSidecarNaming::Darktable,
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum XmpSidecarNaming {
+	/// `photo.jpg.xmp`, which keeps the sidecars of `photo.jpg` and `photo.cr2` apart
+	Darktable,
+	/// `photo.xmp`
+	Lightroom,
+}
+
+#[derive(Error, Debug)]
+pub enum XmpError {
+	#[error("invalid XMP: {0}")]
+	Xml(#[from] quick_xml::Error),
+	#[error("invalid XMP attribute: {0}")]
+	Attribute(#[from] AttrError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+/// What Spacedrive understands of a sidecar
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct XmpMetadata {
+	/// Between 1 and 5 stars, as 0 means unrated and -1 rejected
+	pub rating: Option<i32>,
+	/// Color label of Lightroom, like `Red`
+	pub label: Option<String>,
+	pub keywords: Vec<String>,
+}
+
+impl XmpMetadata {
+	pub fn parse(xml: &[u8]) -> Result<Self, XmpError> {
+		let mut reader = Reader::from_reader(xml);
+		reader.trim_text(true);
+
+		let mut metadata = Self::default();
+		let mut path = Vec::<Vec<u8>>::new();
+
+		loop {
+			match reader.read_event()? {
+				Event::Start(e) => {
+					if e.name().as_ref() == b"rdf:Description" {
+						metadata.read_attributes(&e)?;
+					}
+					path.push(e.name().as_ref().to_vec());
+				}
+				Event::Empty(e) if e.name().as_ref() == b"rdf:Description" => {
+					metadata.read_attributes(&e)?;
+				}
+				Event::End(_) => {
+					path.pop();
+				}
+				Event::Text(text) => {
+					let text = text.unescape()?;
+					let text = text.trim();
+
+					match path.last().map(Vec::as_slice) {
+						Some(b"xmp:Rating") => metadata.set_rating(text),
+						Some(b"xmp:Label") => metadata.set_label(text),
+						Some(b"rdf:li") if path.iter().any(|name| name == b"dc:subject") => {
+							if !text.is_empty() {
+								metadata.keywords.push(text.to_string());
+							}
+						}
+						_ => {}
+					}
+				}
+				Event::Eof => break,
+				_ => {}
+			}
+		}
+
+		Ok(metadata)
+	}
+
+	/// Reads the properties written as attributes of a `rdf:Description`, as Darktable does
+	fn read_attributes(&mut self, description: &BytesStart) -> Result<(), XmpError> {
+		for attr in description.attributes() {
+			let attr = attr?;
+			match attr.key.as_ref() {
+				b"xmp:Rating" => self.set_rating(attr.unescape_value()?.trim()),
+				b"xmp:Label" => self.set_label(attr.unescape_value()?.trim()),
+				_ => {}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn set_rating(&mut self, value: &str) {
+		self.rating = value
+			.parse::<f64>()
+			.ok()
+			.map(|rating| rating.round() as i32)
+			.filter(|rating| (1..=5).contains(rating));
+	}
+
+	fn set_label(&mut self, value: &str) {
+		self.label = (!value.is_empty()).then(|| value.to_string());
+	}
+
+	/// Labels and keywords, which both become tags
+	pub fn tags(&self) -> impl Iterator<Item = &str> {
+		self.label
+			.iter()
+			.chain(self.keywords.iter())
+			.map(String::as_str)
+	}
+}
+
+/// Replaces the rating and keywords of a sidecar, keeping everything else other apps wrote in it
+pub fn rewrite(
+	xml: Option<&[u8]>,
+	rating: Option<i32>,
+	keywords: &[String],
+) -> Result<Vec<u8>, XmpError> {
+	let mut reader = Reader::from_reader(xml.unwrap_or(SIDECAR_TEMPLATE.as_bytes()));
+	let mut writer = Writer::new(Vec::new());
+
+	let mut descriptions = 0;
+	// Depth inside the current `rdf:Description`, which is 0 outside of them
+	let mut depth = 0;
+
+	loop {
+		match reader.read_event()? {
+			Event::Start(e) if depth == 0 && e.name().as_ref() == b"rdf:Description" => {
+				descriptions += 1;
+				depth = 1;
+				writer.write_event(Event::Start(description_start(&e, descriptions == 1)?))?;
+			}
+			Event::Empty(e) if depth == 0 && e.name().as_ref() == b"rdf:Description" => {
+				descriptions += 1;
+				let start = description_start(&e, descriptions == 1)?;
+				if descriptions == 1 {
+					let end = start.to_end().into_owned();
+					writer.write_event(Event::Start(start))?;
+					write_properties(&mut writer, rating, keywords)?;
+					writer.write_event(Event::End(end))?;
+				} else {
+					writer.write_event(Event::Empty(start))?;
+				}
+			}
+			// The properties written by Spacedrive are dropped from every description, then
+			// written again in the first one
+			Event::Start(e) if depth == 1 && is_replaced_property(e.name().as_ref()) => {
+				reader.read_to_end(e.name())?;
+			}
+			Event::Empty(e) if depth == 1 && is_replaced_property(e.name().as_ref()) => {}
+			Event::Start(e) => {
+				if depth > 0 {
+					depth += 1;
+				}
+				writer.write_event(Event::Start(e))?;
+			}
+			Event::End(e) => {
+				if depth == 1 && descriptions == 1 {
+					write_properties(&mut writer, rating, keywords)?;
+				}
+				depth = depth.saturating_sub(1);
+				writer.write_event(Event::End(e))?;
+			}
+			Event::Eof => break,
+			event => writer.write_event(event)?,
+		}
+	}
+
+	Ok(writer.into_inner())
+}
+
+fn is_replaced_property(name: &[u8]) -> bool {
+	name == b"xmp:Rating" || name == b"dc:subject"
+}
+
+/// Copy of a `rdf:Description` without its rating, declaring the namespaces of the properties
+/// Spacedrive writes in the first one
+fn description_start(
+	description: &BytesStart,
+	is_first: bool,
+) -> Result<BytesStart<'static>, XmpError> {
+	let mut start =
+		BytesStart::new(String::from_utf8_lossy(description.name().as_ref()).into_owned());
+	let mut declares_xmp = false;
+	let mut declares_dc = false;
+
+	for attr in description.attributes() {
+		let attr = attr?;
+		match attr.key.as_ref() {
+			b"xmp:Rating" => continue,
+			b"xmlns:xmp" => declares_xmp = true,
+			b"xmlns:dc" => declares_dc = true,
+			_ => {}
+		}
+		start.push_attribute(attr);
+	}
+
+	if is_first {
+		if !declares_xmp {
+			start.push_attribute(("xmlns:xmp", XMP_NAMESPACE));
+		}
+		if !declares_dc {
+			start.push_attribute(("xmlns:dc", DC_NAMESPACE));
+		}
+	}
+
+	Ok(start)
+}
+
+fn write_properties(
+	writer: &mut Writer<Vec<u8>>,
+	rating: Option<i32>,
+	keywords: &[String],
+) -> Result<(), XmpError> {
+	if let Some(rating) = rating {
+		writer
+			.create_element("xmp:Rating")
+			.write_text_content(BytesText::new(&rating.to_string()))?;
+	}
+
+	if !keywords.is_empty() {
+		writer.write_event(Event::Start(BytesStart::new("dc:subject")))?;
+		writer.write_event(Event::Start(BytesStart::new("rdf:Bag")))?;
+		for keyword in keywords {
+			writer
+				.create_element("rdf:li")
+				.write_text_content(BytesText::new(keyword))?;
+		}
+		writer.write_event(Event::End(BytesEnd::new("rdf:Bag")))?;
+		writer.write_event(Event::End(BytesEnd::new("dc:subject")))?;
+	}
+
+	Ok(())
+}
+
+/// The existing sidecar of a file, with either naming
+pub async fn find_sidecar(path: impl AsRef<Path>) -> Option<PathBuf> {
+	for naming in [XmpSidecarNaming::Darktable, XmpSidecarNaming::Lightroom] {
+		let sidecar_path = sidecar_path(path.as_ref(), naming);
+		if fs::metadata(&sidecar_path).await.is_ok() {
+			return Some(sidecar_path);
+		}
+	}
+
+	None
+}
+
+pub fn sidecar_path(path: &Path, naming: XmpSidecarNaming) -> PathBuf {
+	match naming {
+		XmpSidecarNaming::Darktable => {
+			let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+			file_name.push(".xmp");
+			path.with_file_name(file_name)
+		}
+		XmpSidecarNaming::Lightroom => path.with_extension("xmp"),
+	}
+}
+
+/// Applies the sidecars of just identified files to their objects. Tags are only added, so
+/// identifying a file again never removes the ones given in Spacedrive.
+pub async fn import_sidecars(
+	library: &Library,
+	location: &location::Data,
+	file_paths: &[file_path_for_file_identifier::Data],
+) -> Result<(), XmpError> {
+	let Library { db, sync, .. } = library;
+
+	let location_path = maybe_missing(&location.path, "location.path").map(Path::new)?;
+
+	let objects = db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(
+			file_paths.iter().map(|file_path| file_path.id).collect(),
+		)])
+		.select(file_path::select!({ id object: select { id pub_id } }))
+		.exec()
+		.await?;
+
+	for file_path in file_paths {
+		if file_path.is_dir.unwrap_or_default() {
+			continue;
+		}
+
+		let Some(object) = objects
+			.iter()
+			.find(|data| data.id == file_path.id)
+			.and_then(|data| data.object.as_ref())
+		else {
+			continue;
+		};
+
+		let Ok(iso_file_path) = IsolatedFilePathData::try_from((location.id, file_path)) else {
+			continue;
+		};
+
+		let Some(sidecar_path) = find_sidecar(location_path.join(&iso_file_path)).await else {
+			continue;
+		};
+
+		let metadata = match fs::read(&sidecar_path).await {
+			Ok(xml) => XmpMetadata::parse(&xml),
+			Err(e) => Err(FileIOError::from((&sidecar_path, e)).into()),
+		};
+
+		let metadata = match metadata {
+			Ok(metadata) => metadata,
+			Err(e) => {
+				debug!("Skipping sidecar '{}': {e}", sidecar_path.display());
+				continue;
+			}
+		};
+
+		for name in metadata.tags() {
+			let tag_id = find_or_create_tag(library, name, SIDECAR_TAG_COLOR).await?;
+
+			db.tag_on_object()
+				.upsert(
+					tag_on_object::tag_id_object_id(tag_id, object.id),
+					tag_on_object::create(
+						tag::id::equals(tag_id),
+						object::id::equals(object.id),
+						vec![],
+					),
+					vec![],
+				)
+				.exec()
+				.await?;
+		}
+
+		if let Some(rating) = metadata.rating {
+			sync.write_op(
+				db,
+				sync.shared_update(
+					sync::object::SyncId {
+						pub_id: object.pub_id.clone(),
+					},
+					object::rating::NAME,
+					json!(rating),
+				),
+				db.object().update(
+					object::id::equals(object.id),
+					vec![object::rating::set(Some(rating))],
+				),
+			)
+			.await?;
+		}
+	}
+
+	Ok(())
+}
+
+object::select!(object_for_sidecar_write_back {
+	rating
+	tags: select { tag: select { name } }
+	file_paths: select {
+		materialized_path
+		is_dir
+		name
+		extension
+		location: select { path node_id }
+	}
+});
+
+/// Writes the tags and rating of objects to the sidecars of their files in the background, if the
+/// library is set to, so changes made in Spacedrive show up in other photo managers
+pub fn spawn_sidecar_write_back(library: &Library, object_ids: Vec<object::id::Type>) {
+	if !library.config.xmp_sidecars.write || object_ids.is_empty() {
+		return;
+	}
+
+	let library = library.clone();
+	tokio::spawn(async move {
+		if let Err(e) = write_back_sidecars(&library, object_ids).await {
+			error!("Failed to write XMP sidecars: {e:#?}");
+		}
+	});
+}
+
+async fn write_back_sidecars(
+	library: &Library,
+	object_ids: Vec<object::id::Type>,
+) -> Result<(), XmpError> {
+	let naming = library.config.xmp_sidecars.naming;
+
+	let objects = library
+		.db
+		.object()
+		.find_many(vec![object::id::in_vec(object_ids)])
+		.select(object_for_sidecar_write_back::select())
+		.exec()
+		.await?;
+
+	for object in objects {
+		let tags = object
+			.tags
+			.into_iter()
+			.filter_map(|tag_on_object| tag_on_object.tag.name)
+			.collect::<Vec<_>>();
+
+		for file_path in object.file_paths {
+			// Only files of this node can be written to
+			let (Some(location_path), Some(node_id)) = (
+				file_path.location.as_ref().and_then(|l| l.path.as_ref()),
+				file_path.location.as_ref().and_then(|l| l.node_id),
+			) else {
+				continue;
+			};
+
+			if node_id != library.node_local_id || file_path.is_dir.unwrap_or_default() {
+				continue;
+			}
+
+			let (Some(materialized_path), Some(name)) =
+				(&file_path.materialized_path, &file_path.name)
+			else {
+				continue;
+			};
+
+			let mut path = Path::new(location_path).join(materialized_path.trim_start_matches('/'));
+			match file_path.extension.as_deref() {
+				Some(extension) if !extension.is_empty() => {
+					path.push(format!("{name}.{extension}"))
+				}
+				_ => path.push(name),
+			}
+
+			// Sidecars are never written for sidecars
+			if path
+				.extension()
+				.map_or(false, |extension| extension.eq_ignore_ascii_case("xmp"))
+			{
+				continue;
+			}
+
+			if let Err(e) = write_sidecar(&path, naming, object.rating, &tags).await {
+				error!("Failed to write the sidecar of '{}': {e}", path.display());
+			}
+		}
+	}
+
+	Ok(())
+}
+
+async fn write_sidecar(
+	path: &Path,
+	naming: XmpSidecarNaming,
+	rating: Option<i32>,
+	tags: &[String],
+) -> Result<(), XmpError> {
+	let (sidecar_path, existing) = match find_sidecar(path).await {
+		Some(sidecar_path) => {
+			let xml = fs::read(&sidecar_path)
+				.await
+				.map_err(|e| FileIOError::from((&sidecar_path, e)))?;
+			(sidecar_path, Some(xml))
+		}
+		None => (sidecar_path(path, naming), None),
+	};
+
+	// The label of the sidecar was imported as a tag, it isn't a keyword
+	let label = existing
+		.as_deref()
+		.map(XmpMetadata::parse)
+		.transpose()?
+		.and_then(|metadata| metadata.label);
+	let mut keywords = tags
+		.iter()
+		.filter(|tag| Some(*tag) != label.as_ref())
+		.cloned()
+		.collect::<Vec<_>>();
+	keywords.sort();
+	keywords.dedup();
+
+	let xml = rewrite(
+		existing.as_deref(),
+		rating.filter(|rating| *rating > 0),
+		&keywords,
+	)?;
+
+	fs::write(&sidecar_path, xml)
+		.await
+		.map_err(|e| FileIOError::from((&sidecar_path, e)).into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DARKTABLE_SIDECAR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:darktable="http://darktable.sf.net/"
+    xmp:Rating="3"
+    xmp:Label="Red"
+    darktable:xmp_version="5">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>Holidays</rdf:li>
+     <rdf:li>Paris &amp; Lyon</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+	#[test]
+	fn parse_sidecar() {
+		let metadata = XmpMetadata::parse(DARKTABLE_SIDECAR.as_bytes()).unwrap();
+
+		assert_eq!(metadata.rating, Some(3));
+		assert_eq!(metadata.label.as_deref(), Some("Red"));
+		assert_eq!(metadata.keywords, vec!["Holidays", "Paris & Lyon"]);
+
+		let rejected = XmpMetadata::parse(
+			br#"<rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="-1"/>"#,
+		)
+		.unwrap();
+		assert_eq!(rejected.rating, None);
+	}
+
+	#[test]
+	fn rewrite_keeps_other_properties() {
+		let xml = rewrite(
+			Some(DARKTABLE_SIDECAR.as_bytes()),
+			Some(5),
+			&["Museums".to_string()],
+		)
+		.unwrap();
+
+		let metadata = XmpMetadata::parse(&xml).unwrap();
+		assert_eq!(metadata.rating, Some(5));
+		assert_eq!(metadata.label.as_deref(), Some("Red"));
+		assert_eq!(metadata.keywords, vec!["Museums"]);
+		assert!(String::from_utf8(xml)
+			.unwrap()
+			.contains(r#"darktable:xmp_version="5""#));
+	}
+
+	#[test]
+	fn rewrite_without_sidecar() {
+		let xml = rewrite(None, Some(2), &["Cats".to_string()]).unwrap();
+
+		let metadata = XmpMetadata::parse(&xml).unwrap();
+		assert_eq!(metadata.rating, Some(2));
+		assert_eq!(metadata.keywords, vec!["Cats"]);
+	}
+
+	#[test]
+	fn sidecar_naming() {
+		let path = Path::new("/photos/IMG_0001.CR2");
+
+		assert_eq!(
+			sidecar_path(path, XmpSidecarNaming::Darktable),
+			Path::new("/photos/IMG_0001.CR2.xmp")
+		);
+		assert_eq!(
+			sidecar_path(path, XmpSidecarNaming::Lightroom),
+			Path::new("/photos/IMG_0001.xmp")
+		);
+	}
+}
@@ -0,0 +1,271 @@
+use std::{
+	collections::HashMap,
+	path::{Component, Path, PathBuf},
+	str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use sd_p2p::{spacetime::UnicastStream, spacetunnel::Tunnel, PeerId};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+	library::Library,
+	location::file_path_helper::IsolatedFilePathData,
+	prisma::{file_path, location},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use super::{is_paired, read_delegation_message, write_delegation_message, DelegationError};
+
+/// Upper bound of the entries sent for a directory, the rest being left out
+pub const MAX_REMOTE_DIRECTORY_ENTRIES: usize = 10_000;
+
+/// Sent by the node browsing a location owned by the peer, right after the
+/// [`Header::Browse`](super::Header). The peer answers with a single [`RemoteDirectoryResponse`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteDirectoryRequest {
+	pub location_pub_id: Uuid,
+	/// Relative to the root of the location, which is listed if empty
+	pub path: PathBuf,
+}
+
+/// A file or directory as it is on the disk of the node owning the location, whether or not it
+/// was indexed or synced
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDirectoryEntry {
+	pub name: String,
+	pub is_dir: bool,
+	pub size_in_bytes: u64,
+	pub date_created: Option<DateTime<Utc>>,
+	pub date_modified: Option<DateTime<Utc>>,
+	/// Set if the entry is indexed, to stream it through [`Header::File`](super::Header)
+	pub file_path_pub_id: Option<Uuid>,
+	/// Set if the entry is identified, to get its thumbnail through
+	/// [`Header::Thumbnails`](super::Header)
+	pub cas_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RemoteDirectoryResponse {
+	Entries(Vec<RemoteDirectoryEntry>),
+	Error(String),
+}
+
+/// Peer of the node owning the location, if it isn't this one
+pub(super) async fn location_owner(
+	library: &Library,
+	location_pub_id: Uuid,
+) -> Result<PeerId, DelegationError> {
+	let location = library
+		.db
+		.location()
+		.find_unique(location::pub_id::equals(
+			location_pub_id.as_bytes().to_vec(),
+		))
+		.select(location::select!({
+			node_id
+			node: select { node_peer_id }
+		}))
+		.exec()
+		.await?
+		.ok_or_else(|| DelegationError::Remote("location not found".to_string()))?;
+
+	if location.node_id == Some(library.node_local_id) {
+		return Err(DelegationError::Remote(
+			"location is on this node".to_string(),
+		));
+	}
+
+	location
+		.node
+		.and_then(|node| node.node_peer_id)
+		.and_then(|peer_id| PeerId::from_str(&peer_id).ok())
+		.ok_or_else(|| DelegationError::Remote("location's node isn't paired".to_string()))
+}
+
+/// Lists a directory of a location we own for a paired node
+pub(super) async fn serve_remote_directory(
+	library: &Library,
+	peer_id: PeerId,
+	mut stream: UnicastStream,
+) -> Result<(), DelegationError> {
+	stream.write_all(b"T").await?;
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	let request = read_delegation_message::<RemoteDirectoryRequest>(&mut tunnel).await?;
+
+	if !is_paired(library, peer_id).await {
+		warn!("Rejecting browse request from unpaired peer '{peer_id}'");
+		return write_delegation_message(
+			&mut tunnel,
+			&RemoteDirectoryResponse::Error("node is not paired within this library".to_string()),
+		)
+		.await;
+	}
+
+	let response = match list_directory(library, &request).await {
+		Ok(entries) => RemoteDirectoryResponse::Entries(entries),
+		Err(e) => {
+			warn!(
+				"Failed to list '{}' of location '{}' for peer '{peer_id}': {e}",
+				request.path.display(),
+				request.location_pub_id
+			);
+			RemoteDirectoryResponse::Error(e.to_string())
+		}
+	};
+
+	write_delegation_message(&mut tunnel, &response).await
+}
+
+async fn list_directory(
+	library: &Library,
+	RemoteDirectoryRequest {
+		location_pub_id,
+		path,
+	}: &RemoteDirectoryRequest,
+) -> Result<Vec<RemoteDirectoryEntry>, DelegationError> {
+	let not_found = || DelegationError::Remote("directory not found on this node".to_string());
+
+	// Only plain names, so the peer can't get out of the location
+	if !path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_)))
+	{
+		return Err(not_found());
+	}
+
+	let location = library
+		.db
+		.location()
+		.find_unique(location::pub_id::equals(
+			location_pub_id.as_bytes().to_vec(),
+		))
+		.exec()
+		.await?
+		.ok_or_else(not_found)?;
+
+	if location.node_id != Some(library.node_local_id) {
+		return Err(not_found());
+	}
+
+	let location_path = Path::new(maybe_missing(&location.path, "location.path")?);
+	let dir_path = location_path.join(path);
+
+	// What was indexed in the directory, matched with the entries by name
+	let indexed = match IsolatedFilePathData::new(location.id, location_path, &dir_path, true)
+		.ok()
+		.and_then(|iso_file_path| iso_file_path.materialized_path_for_children())
+	{
+		Some(materialized_path) => library
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location.id)),
+				file_path::materialized_path::equals(Some(materialized_path)),
+			])
+			.select(file_path::select!({ pub_id name extension cas_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| {
+				let name = match file_path.extension.as_deref() {
+					Some(extension) if !extension.is_empty() => {
+						format!("{}.{extension}", file_path.name?)
+					}
+					_ => file_path.name?,
+				};
+
+				Some((
+					name,
+					(Uuid::from_slice(&file_path.pub_id).ok(), file_path.cas_id),
+				))
+			})
+			.collect(),
+		None => HashMap::new(),
+	};
+
+	let mut read_dir = fs::read_dir(&dir_path)
+		.await
+		.map_err(|e| FileIOError::from((&dir_path, e)))?;
+
+	let mut entries = Vec::new();
+	while let Some(entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((&dir_path, e)))?
+	{
+		if entries.len() >= MAX_REMOTE_DIRECTORY_ENTRIES {
+			debug!(
+				"Directory '{}' has more than {MAX_REMOTE_DIRECTORY_ENTRIES} entries, leaving the rest out",
+				dir_path.display()
+			);
+			break;
+		}
+
+		// Entries removed while listing, or we can't read, are left out
+		let Ok(metadata) = entry.metadata().await else {
+			continue;
+		};
+		let Ok(name) = entry.file_name().into_string() else {
+			continue;
+		};
+
+		let is_dir = metadata.is_dir();
+		// Extensions are indexed in lowercase
+		let indexed_name = match IsolatedFilePathData::separate_name_and_extension_from_str(&name) {
+			Ok((stem, extension)) if !is_dir && !extension.is_empty() => {
+				format!("{stem}.{}", extension.to_lowercase())
+			}
+			_ => name.clone(),
+		};
+		let (file_path_pub_id, cas_id) = indexed.get(&indexed_name).cloned().unwrap_or_default();
+
+		entries.push(RemoteDirectoryEntry {
+			name,
+			is_dir,
+			size_in_bytes: if is_dir { 0 } else { metadata.len() },
+			date_created: metadata.created().ok().map(Into::into),
+			date_modified: metadata.modified().ok().map(Into::into),
+			file_path_pub_id,
+			cas_id,
+		});
+	}
+
+	Ok(entries)
+}
+
+/// Asks a paired node for the entries of a directory of a location it owns
+pub(super) async fn request_remote_directory(
+	library: &Library,
+	mut stream: UnicastStream,
+	request: &RemoteDirectoryRequest,
+) -> Result<Vec<RemoteDirectoryEntry>, DelegationError> {
+	let mut header = super::Header::Browse(library.id).to_bytes();
+	header.push(b'T');
+	stream.write_all(&header).await?;
+
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	debug!(
+		"Browsing '{}' of location '{}'",
+		request.path.display(),
+		request.location_pub_id
+	);
+
+	write_delegation_message(&mut tunnel, request).await?;
+
+	match read_delegation_message(&mut tunnel).await? {
+		RemoteDirectoryResponse::Entries(entries) => Ok(entries),
+		RemoteDirectoryResponse::Error(e) => Err(DelegationError::Remote(e)),
+	}
+}
@@ -0,0 +1,149 @@
+//! What a node can do for its peers, advertised along with its metadata during discovery so jobs
+//! and requests are sent to the peers able to handle them.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::node::{NodeConfig, Platform};
+
+/// What a node is used for storage wise
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageRole {
+	/// Holds the files the user works on
+	#[default]
+	Primary,
+	/// Holds copies of the files of other nodes
+	Backup,
+	/// Only holds files for a while, like a phone or a laptop with little space
+	Ephemeral,
+}
+
+impl StorageRole {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Primary => "primary",
+			Self::Backup => "backup",
+			Self::Ephemeral => "ephemeral",
+		}
+	}
+
+	fn parse(s: &str) -> Option<Self> {
+		match s {
+			"primary" => Some(Self::Primary),
+			"backup" => Some(Self::Backup),
+			"ephemeral" => Some(Self::Ephemeral),
+			_ => None,
+		}
+	}
+}
+
+/// Capabilities of the node the user declares, as they can't be detected reliably
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesConfig {
+	pub has_gpu: bool,
+	pub storage_role: StorageRole,
+	/// Whether the node relays the traffic of peers that can't reach each other directly
+	pub relay: bool,
+}
+
+/// Capabilities a peer advertises
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerCapabilities {
+	/// Runs delegated thumbnailer jobs and has thumbnails of its own files to share
+	pub can_thumbnail: bool,
+	pub has_gpu: bool,
+	pub storage_role: StorageRole,
+	pub supports_relay: bool,
+}
+
+const CAN_THUMBNAIL: &str = "thumbnail";
+const HAS_GPU: &str = "gpu";
+const SUPPORTS_RELAY: &str = "relay";
+
+impl PeerCapabilities {
+	pub fn of_node(config: &NodeConfig) -> Self {
+		let thumbnailer = &config.thumbnailer;
+
+		Self {
+			// Mobile nodes are the ones delegating thumbnailing in the first place
+			can_thumbnail: !matches!(Platform::current(), Platform::IOS | Platform::Android)
+				&& (thumbnailer.image || thumbnailer.video || thumbnailer.document),
+			has_gpu: config.capabilities.has_gpu,
+			storage_role: config.capabilities.storage_role,
+			supports_relay: config.capabilities.relay,
+		}
+	}
+
+	/// Flags and storage role, as the values of the `caps` and `storage` metadata entries
+	pub(super) fn to_metadata(&self) -> (String, String) {
+		let flags = [
+			(self.can_thumbnail, CAN_THUMBNAIL),
+			(self.has_gpu, HAS_GPU),
+			(self.supports_relay, SUPPORTS_RELAY),
+		]
+		.into_iter()
+		.filter_map(|(set, flag)| set.then_some(flag))
+		.collect::<Vec<_>>()
+		.join(",");
+
+		(flags, self.storage_role.as_str().to_string())
+	}
+
+	pub(super) fn from_metadata(flags: &str, storage_role: Option<&str>) -> Self {
+		// Flags unknown to this version are from newer peers, and ignored
+		let flags = flags.split(',').collect::<BTreeSet<_>>();
+
+		Self {
+			can_thumbnail: flags.contains(CAN_THUMBNAIL),
+			has_gpu: flags.contains(HAS_GPU),
+			storage_role: storage_role
+				.and_then(StorageRole::parse)
+				.unwrap_or_default(),
+			supports_relay: flags.contains(SUPPORTS_RELAY),
+		}
+	}
+
+	/// How well suited the peer is to run a delegated thumbnailer job, if at all
+	pub fn thumbnailer_rank(&self) -> Option<u8> {
+		self.can_thumbnail.then(|| {
+			u8::from(self.has_gpu) * 2 + u8::from(self.storage_role != StorageRole::Ephemeral)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn metadata_round_trip() {
+		let capabilities = PeerCapabilities {
+			can_thumbnail: true,
+			has_gpu: false,
+			storage_role: StorageRole::Backup,
+			supports_relay: true,
+		};
+
+		let (flags, storage_role) = capabilities.to_metadata();
+		assert_eq!(flags, "thumbnail,relay");
+		assert_eq!(
+			PeerCapabilities::from_metadata(&flags, Some(&storage_role)),
+			capabilities
+		);
+
+		assert_eq!(
+			PeerCapabilities::from_metadata("gpu,teleport", None),
+			PeerCapabilities {
+				can_thumbnail: false,
+				has_gpu: true,
+				storage_role: StorageRole::Primary,
+				supports_relay: false,
+			}
+		);
+	}
+}
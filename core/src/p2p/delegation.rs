@@ -0,0 +1,356 @@
+use std::path::PathBuf;
+
+use sd_p2p::{spacetime::UnicastStream, spacetunnel::Tunnel, PeerId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	library::Library,
+	object::preview::{get_thumb_key, get_thumbnail_path, DelegatedThumbnailer, ThumbnailFormat},
+	prisma::{location, node},
+	util::{db::MissingFieldError, error::FileIOError},
+};
+
+use super::PeerCapabilities;
+
+/// Upper bound of a single delegated job message, a thumbnail being the biggest one
+const MAX_MESSAGE_LEN: u32 = 32 * 1024 * 1024;
+
+/// Jobs a node can ask a paired node to run on its behalf
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DelegatedJobKind {
+	Thumbnailer,
+}
+
+impl DelegatedJobKind {
+	/// How well suited a peer is to run the job, if at all
+	pub(super) fn rank(&self, capabilities: &PeerCapabilities) -> Option<u8> {
+		match self {
+			Self::Thumbnailer => capabilities.thumbnailer_rank(),
+		}
+	}
+}
+
+/// Sent by the node delegating a job, right after the [`Header::DelegateJob`](super::Header)
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DelegatedJobRequest {
+	pub kind: DelegatedJobKind,
+	pub location_pub_id: Uuid,
+	pub sub_path: Option<PathBuf>,
+}
+
+/// Results streamed back by the node running a delegated job, until `Done` or `Error`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DelegatedJobMessage {
+	Accepted {
+		task_count: u32,
+	},
+	Thumbnail {
+		cas_id: String,
+		format: ThumbnailFormat,
+		thumbnail: Vec<u8>,
+	},
+	Done,
+	Error(String),
+}
+
+#[derive(Debug, Error)]
+pub enum DelegationError {
+	#[error("io error on delegated job stream: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("error encoding delegated job message: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("error decoding delegated job message: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("delegated job message is too big: {0} bytes")]
+	MessageTooBig(u32),
+	#[error("failed to open a stream to the peer")]
+	Connection,
+	#[error("peer '{0}' doesn't advertise it can run this job")]
+	Unsupported(PeerId),
+	#[error("no discovered peer of the library can run this job")]
+	NoCapablePeer,
+	#[error("failed to establish a tunnel with the peer: {0}")]
+	Tunnel(&'static str),
+	#[error("the peer failed to run the job: {0}")]
+	Remote(String),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+/// Writes a length prefixed MessagePack message
+pub async fn write_delegation_message(
+	stream: &mut (impl AsyncWrite + Unpin),
+	message: &impl Serialize,
+) -> Result<(), DelegationError> {
+	let buf = rmp_serde::to_vec_named(message)?;
+	if buf.len() > MAX_MESSAGE_LEN as usize {
+		return Err(DelegationError::MessageTooBig(buf.len() as u32));
+	}
+
+	stream.write_all(&(buf.len() as u32).to_le_bytes()).await?;
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	Ok(())
+}
+
+/// Reads a message written by [`write_delegation_message`]
+pub async fn read_delegation_message<T: DeserializeOwned>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, DelegationError> {
+	let len = stream.read_u32_le().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(DelegationError::MessageTooBig(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+
+	Ok(rmp_serde::from_slice(&buf)?)
+}
+
+/// If the peer is a node paired within the library
+pub(super) async fn is_paired(library: &Library, peer_id: PeerId) -> bool {
+	library
+		.db
+		.node()
+		.count(vec![node::node_peer_id::equals(Some(peer_id.to_string()))])
+		.exec()
+		.await
+		.map_or(false, |count| count > 0)
+}
+
+/// Runs a job requested by a paired node, streaming its results back over the tunnel
+pub(super) async fn run_delegated_job(
+	library: &Library,
+	peer_id: PeerId,
+	mut stream: UnicastStream,
+) -> Result<(), DelegationError> {
+	stream.write_all(b"T").await?;
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	let request = read_delegation_message::<DelegatedJobRequest>(&mut tunnel).await?;
+
+	// Only nodes paired within this library are allowed to use our resources
+	if !is_paired(library, peer_id).await {
+		warn!("Rejecting delegated job from unpaired peer '{peer_id}'");
+		return write_delegation_message(
+			&mut tunnel,
+			&DelegatedJobMessage::Error("node is not paired within this library".to_string()),
+		)
+		.await;
+	}
+
+	let location = match library
+		.db
+		.location()
+		.find_unique(location::pub_id::equals(
+			request.location_pub_id.as_bytes().to_vec(),
+		))
+		.exec()
+		.await
+	{
+		Ok(Some(location)) => location,
+		Ok(None) => {
+			return write_delegation_message(
+				&mut tunnel,
+				&DelegatedJobMessage::Error(format!(
+					"location not found: {}",
+					request.location_pub_id
+				)),
+			)
+			.await
+		}
+		Err(e) => {
+			return write_delegation_message(
+				&mut tunnel,
+				&DelegatedJobMessage::Error(e.to_string()),
+			)
+			.await
+		}
+	};
+
+	info!(
+		"Running {:?} for peer '{peer_id}' on location {}",
+		request.kind, location.id
+	);
+
+	match request.kind {
+		DelegatedJobKind::Thumbnailer => {
+			let mut thumbnailer =
+				match DelegatedThumbnailer::new(location, request.sub_path.as_deref(), library)
+					.await
+				{
+					Ok(thumbnailer) => thumbnailer,
+					Err(e) => {
+						return write_delegation_message(
+							&mut tunnel,
+							&DelegatedJobMessage::Error(e.to_string()),
+						)
+						.await
+					}
+				};
+
+			write_delegation_message(
+				&mut tunnel,
+				&DelegatedJobMessage::Accepted {
+					task_count: thumbnailer.len() as u32,
+				},
+			)
+			.await?;
+
+			loop {
+				match thumbnailer.next(library).await {
+					Ok(Some((cas_id, Some((format, thumbnail))))) => {
+						write_delegation_message(
+							&mut tunnel,
+							&DelegatedJobMessage::Thumbnail {
+								cas_id,
+								format,
+								thumbnail,
+							},
+						)
+						.await?;
+					}
+					Ok(Some((cas_id, None))) => {
+						debug!("No thumbnail was generated for '{cas_id}', skipping it");
+					}
+					Ok(None) => break,
+					Err(e) => {
+						return write_delegation_message(
+							&mut tunnel,
+							&DelegatedJobMessage::Error(e.to_string()),
+						)
+						.await
+					}
+				}
+			}
+		}
+	}
+
+	write_delegation_message(&mut tunnel, &DelegatedJobMessage::Done).await
+}
+
+/// Sends a job request to a paired node and stores the results it streams back, returning
+/// how many thumbnails were received
+pub(super) async fn request_delegated_job(
+	library: &Library,
+	mut stream: UnicastStream,
+	request: &DelegatedJobRequest,
+) -> Result<u32, DelegationError> {
+	let mut header = super::Header::DelegateJob(library.id).to_bytes();
+	header.push(b'T');
+	stream.write_all(&header).await?;
+
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	write_delegation_message(&mut tunnel, request).await?;
+
+	let mut thumbnails_received = 0;
+	loop {
+		match read_delegation_message::<DelegatedJobMessage>(&mut tunnel).await? {
+			DelegatedJobMessage::Accepted { task_count } => {
+				debug!(
+					"Delegated {:?} accepted with {task_count} tasks",
+					request.kind
+				);
+			}
+			DelegatedJobMessage::Thumbnail {
+				cas_id,
+				format,
+				thumbnail,
+			} => {
+				store_peer_thumbnail(library, &cas_id, format, thumbnail).await?;
+				thumbnails_received += 1;
+			}
+			DelegatedJobMessage::Done => break,
+			DelegatedJobMessage::Error(e) => return Err(DelegationError::Remote(e)),
+		}
+	}
+
+	if thumbnails_received > 0 {
+		invalidate_query!(library, "search.paths");
+	}
+
+	Ok(thumbnails_received)
+}
+
+/// Stores a thumbnail sent by a peer, kept in the peer's format, as the format migrator job
+/// re-encodes it if it differs from ours
+pub(super) async fn store_peer_thumbnail(
+	library: &Library,
+	cas_id: &str,
+	format: ThumbnailFormat,
+	thumbnail: Vec<u8>,
+) -> Result<(), DelegationError> {
+	let thumbnail_path = get_thumbnail_path(library, cas_id).with_extension(format.extension());
+	if let Some(parent) = thumbnail_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+	fs::write(&thumbnail_path, thumbnail).await?;
+
+	library.emit(CoreEvent::NewThumbnail {
+		thumb_key: get_thumb_key(cas_id),
+	});
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_delegation_messages() {
+		let request = DelegatedJobRequest {
+			kind: DelegatedJobKind::Thumbnailer,
+			location_pub_id: Uuid::new_v4(),
+			sub_path: Some("photos/2023".into()),
+		};
+		let thumbnail = DelegatedJobMessage::Thumbnail {
+			cas_id: "abc".into(),
+			format: ThumbnailFormat::Avif,
+			thumbnail: vec![1, 2, 3],
+		};
+
+		let mut buf = Vec::new();
+		write_delegation_message(&mut buf, &request).await.unwrap();
+		write_delegation_message(&mut buf, &thumbnail)
+			.await
+			.unwrap();
+
+		let mut cursor = std::io::Cursor::new(buf);
+		assert_eq!(
+			read_delegation_message::<DelegatedJobRequest>(&mut cursor)
+				.await
+				.unwrap(),
+			request
+		);
+		assert_eq!(
+			read_delegation_message::<DelegatedJobMessage>(&mut cursor)
+				.await
+				.unwrap(),
+			thumbnail
+		);
+	}
+}
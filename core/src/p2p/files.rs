@@ -0,0 +1,173 @@
+use std::{io::SeekFrom, path::Path};
+
+use sd_p2p::{spacetime::UnicastStream, spacetunnel::Tunnel, PeerId};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	fs::File,
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_to_stream, IsolatedFilePathData},
+	prisma::file_path,
+	util::{
+		db::{maybe_missing, uuid_to_bytes},
+		error::FileIOError,
+	},
+};
+
+use super::{is_paired, read_delegation_message, write_delegation_message, DelegationError};
+
+/// Upper bound of a chunk sent at once, clients seeking through media ask for the next one as
+/// they play it
+pub const MAX_REMOTE_FILE_CHUNK_LEN: u64 = 4 * 1024 * 1024;
+
+/// Sent by the node streaming a file of a location owned by the peer, right after the
+/// [`Header::File`](super::Header). The peer answers with a single [`RemoteFileResponse`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteFileRequest {
+	pub file_path_pub_id: Uuid,
+	pub start: u64,
+	/// Clamped to [`MAX_REMOTE_FILE_CHUNK_LEN`]
+	pub length: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RemoteFileResponse {
+	Chunk {
+		/// Size of the whole file, which may differ from the one in the library if it changed
+		/// since it was last indexed
+		file_size: u64,
+		start: u64,
+		data: Vec<u8>,
+	},
+	Error(String),
+}
+
+/// Sends the chunk of a file a paired node asked for, if the file is on a location we own
+pub(super) async fn serve_remote_file(
+	library: &Library,
+	peer_id: PeerId,
+	mut stream: UnicastStream,
+) -> Result<(), DelegationError> {
+	stream.write_all(b"T").await?;
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	let request = read_delegation_message::<RemoteFileRequest>(&mut tunnel).await?;
+
+	if !is_paired(library, peer_id).await {
+		warn!("Rejecting file request from unpaired peer '{peer_id}'");
+		return write_delegation_message(
+			&mut tunnel,
+			&RemoteFileResponse::Error("node is not paired within this library".to_string()),
+		)
+		.await;
+	}
+
+	let response = match read_chunk(library, &request).await {
+		Ok(response) => response,
+		Err(e) => {
+			warn!(
+				"Failed to read file '{}' for peer '{peer_id}': {e}",
+				request.file_path_pub_id
+			);
+			RemoteFileResponse::Error(e.to_string())
+		}
+	};
+
+	write_delegation_message(&mut tunnel, &response).await
+}
+
+async fn read_chunk(
+	library: &Library,
+	RemoteFileRequest {
+		file_path_pub_id,
+		start,
+		length,
+	}: &RemoteFileRequest,
+) -> Result<RemoteFileResponse, DelegationError> {
+	let not_found = || DelegationError::Remote("file not found on this node".to_string());
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::pub_id::equals(uuid_to_bytes(*file_path_pub_id)),
+			file_path::date_quarantined::equals(None),
+		])
+		.select(file_path_to_stream::select())
+		.exec()
+		.await?
+		.ok_or_else(not_found)?;
+
+	let location = file_path.location.as_ref().ok_or_else(not_found)?;
+	if location.node_id != Some(library.node_local_id) || file_path.is_dir == Some(true) {
+		return Err(not_found());
+	}
+
+	let full_path = Path::new(maybe_missing(&location.path, "location.path")?)
+		.join(IsolatedFilePathData::try_from((location.id, &file_path))?);
+
+	let mut file = File::open(&full_path)
+		.await
+		.map_err(|e| FileIOError::from((&full_path, e)))?;
+	let file_size = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((&full_path, e)))?
+		.len();
+
+	let start = (*start).min(file_size);
+	let length = (*length).min(MAX_REMOTE_FILE_CHUNK_LEN);
+
+	let mut data = Vec::with_capacity(length.min(file_size - start) as usize);
+	file.seek(SeekFrom::Start(start))
+		.await
+		.map_err(|e| FileIOError::from((&full_path, e)))?;
+	file.take(length)
+		.read_to_end(&mut data)
+		.await
+		.map_err(|e| FileIOError::from((&full_path, e)))?;
+
+	Ok(RemoteFileResponse::Chunk {
+		file_size,
+		start,
+		data,
+	})
+}
+
+/// Asks a paired node for a chunk of a file it owns
+pub(super) async fn request_remote_file(
+	library: &Library,
+	mut stream: UnicastStream,
+	request: RemoteFileRequest,
+) -> Result<(u64, u64, Vec<u8>), DelegationError> {
+	let mut header = super::Header::File(library.id).to_bytes();
+	header.push(b'T');
+	stream.write_all(&header).await?;
+
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	debug!(
+		"Requesting {} bytes of file '{}' from offset {}",
+		request.length, request.file_path_pub_id, request.start
+	);
+
+	write_delegation_message(&mut tunnel, &request).await?;
+
+	match read_delegation_message(&mut tunnel).await? {
+		RemoteFileResponse::Chunk {
+			file_size,
+			start,
+			data,
+		} => Ok((file_size, start, data)),
+		RemoteFileResponse::Error(e) => Err(DelegationError::Remote(e)),
+	}
+}
@@ -0,0 +1,601 @@
+//! Invites letting a new node join a library in one step. A node of the library issues a single
+//! use code bound to a role, which the new node redeems with it over P2P to get the settings of
+//! the library and its operations, its private identity staying on the issuing node.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use rspc::ErrorCode;
+use sd_p2p::{
+	spacetime::UnicastStream,
+	spacetunnel::{Identity, Tunnel},
+	Manager, PeerId, StreamError,
+};
+use sd_prisma::prisma::{library_invite, node};
+use sd_sync::CRDTOperation;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	library::{
+		AuditAction, IdentityKey, Library, LibraryConfig, LibraryManager, LibraryManagerError,
+		LibraryName, NodeRole, WebhookEvent,
+	},
+	node::{NodeConfig, Platform},
+	sync::IngestError,
+};
+
+use super::{Header, NodeInformation, NodeInformationError, PeerMetadata};
+
+const INVITE_CODE_PREFIX: &str = "spacedrive-invite";
+/// How long invites can be redeemed for if not set
+const DEFAULT_INVITE_VALIDITY_HOURS: i64 = 24;
+/// Operations sent at a time during the initial sync of a new node
+const INITIAL_SYNC_BATCH_SIZE: usize = 1000;
+/// Upper bound of a single invite message, a batch of operations being the biggest one
+const MAX_MESSAGE_LEN: u32 = 64 * 1024 * 1024;
+
+/// What a new node needs to redeem an invite, shared as `spacedrive-invite:{peer}:{library}:{token}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InviteCode {
+	/// Node that issued the invite, which the new node redeems it with
+	pub peer_id: PeerId,
+	pub library_id: Uuid,
+	pub token: String,
+}
+
+impl fmt::Display for InviteCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{INVITE_CODE_PREFIX}:{}:{}:{}",
+			self.peer_id, self.library_id, self.token
+		)
+	}
+}
+
+impl FromStr for InviteCode {
+	type Err = InviteError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.trim().split(':');
+
+		match (
+			parts.next(),
+			parts
+				.next()
+				.and_then(|peer_id| PeerId::from_str(peer_id).ok()),
+			parts
+				.next()
+				.and_then(|library_id| Uuid::parse_str(library_id).ok()),
+			parts.next(),
+			parts.next(),
+		) {
+			(Some(INVITE_CODE_PREFIX), Some(peer_id), Some(library_id), Some(token), None)
+				if !token.is_empty() =>
+			{
+				Ok(Self {
+					peer_id,
+					library_id,
+					token: token.to_string(),
+				})
+			}
+			_ => Err(InviteError::InvalidCode),
+		}
+	}
+}
+
+/// An invite issued by this node, without its token which is only shown once
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryInvite {
+	pub id: Uuid,
+	pub role: NodeRole,
+	pub expires_at: DateTime<Utc>,
+	pub date_created: DateTime<Utc>,
+	/// Node the invite was redeemed by, if it was
+	pub redeemed_by: Option<Uuid>,
+	pub date_redeemed: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<library_invite::Data> for LibraryInvite {
+	type Error = InviteError;
+
+	fn try_from(invite: library_invite::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: Uuid::from_slice(&invite.pub_id).unwrap_or_default(),
+			role: NodeRole::parse(&invite.role).ok_or(InviteError::InvalidRole)?,
+			expires_at: invite.expires_at.into(),
+			date_created: invite.date_created.into(),
+			redeemed_by: invite
+				.redeemed_by
+				.and_then(|pub_id| Uuid::from_slice(&pub_id).ok()),
+			date_redeemed: invite.date_redeemed.map(Into::into),
+		})
+	}
+}
+
+/// Invite just created, along with the code to share with the new node
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedInvite {
+	pub invite: LibraryInvite,
+	pub code: String,
+}
+
+/// Library a node joined by redeeming an invite
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinedLibrary {
+	pub library_id: Uuid,
+	pub role: NodeRole,
+	/// Operations of the library received from the issuing node
+	pub operations: u32,
+}
+
+/// Sent by the issuing node once the new node sent the token of the invite
+#[derive(Debug, Serialize, Deserialize)]
+enum InviteMessage {
+	/// The invite is valid, the [`NodeInformation`] of the issuing node follows
+	Accepted {
+		name: LibraryName,
+		description: Option<String>,
+		role: NodeRole,
+	},
+	Rejected(String),
+	Operations(Vec<CRDTOperation>),
+	Done,
+}
+
+#[derive(Debug, Error)]
+pub enum InviteError {
+	#[error("invalid invite code")]
+	InvalidCode,
+	#[error("invite not found <id='{0}'>")]
+	NotFound(Uuid),
+	#[error("invalid role in invite")]
+	InvalidRole,
+	#[error("invites must be valid for at least an hour")]
+	InvalidValidity,
+	#[error("this node is already part of the library")]
+	AlreadyMember,
+	#[error("the node that issued the invite isn't discovered on the network")]
+	PeerNotFound,
+	#[error("the invite was rejected: {0}")]
+	Rejected(String),
+	#[error("failed to connect to the peer: {0}")]
+	Connection(#[from] StreamError),
+	#[error("failed to establish a tunnel with the peer: {0}")]
+	Tunnel(&'static str),
+	#[error("io error on invite stream: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("error encoding invite message: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("error decoding invite message: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("invite message is too big: {0} bytes")]
+	MessageTooBig(u32),
+	#[error("the peer sent an unexpected message")]
+	UnexpectedMessage,
+	#[error("invalid node information: {0}")]
+	NodeInformation(#[from] NodeInformationError),
+	#[error(transparent)]
+	LibraryManager(#[from] LibraryManagerError),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to ingest the operations of the library: {0}")]
+	Ingest(#[from] IngestError),
+}
+
+impl From<InviteError> for rspc::Error {
+	fn from(err: InviteError) -> Self {
+		match err {
+			InviteError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			InviteError::InvalidCode
+			| InviteError::InvalidValidity
+			| InviteError::AlreadyMember
+			| InviteError::PeerNotFound
+			| InviteError::Rejected(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+async fn write_message(
+	stream: &mut (impl AsyncWrite + Unpin),
+	message: &impl Serialize,
+) -> Result<(), InviteError> {
+	let buf = rmp_serde::to_vec_named(message)?;
+	if buf.len() > MAX_MESSAGE_LEN as usize {
+		return Err(InviteError::MessageTooBig(buf.len() as u32));
+	}
+
+	stream.write_all(&(buf.len() as u32).to_le_bytes()).await?;
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	Ok(())
+}
+
+async fn read_message<T: DeserializeOwned>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, InviteError> {
+	let len = stream.read_u32_le().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(InviteError::MessageTooBig(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+
+	Ok(rmp_serde::from_slice(&buf)?)
+}
+
+/// Generates the secret part of a new invite code
+fn generate_invite_token() -> String {
+	format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub async fn create_invite(
+	library: &Library,
+	node_config: &NodeConfig,
+	role: NodeRole,
+	valid_for_hours: Option<i64>,
+) -> Result<CreatedInvite, InviteError> {
+	let valid_for_hours = valid_for_hours.unwrap_or(DEFAULT_INVITE_VALIDITY_HOURS);
+	if valid_for_hours < 1 {
+		return Err(InviteError::InvalidValidity);
+	}
+
+	let token = generate_invite_token();
+	let invite = library
+		.db
+		.library_invite()
+		.create(
+			Uuid::new_v4().as_bytes().to_vec(),
+			token.clone(),
+			role.as_str().to_string(),
+			(Utc::now() + Duration::hours(valid_for_hours)).into(),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(CreatedInvite {
+		invite: invite.try_into()?,
+		code: InviteCode {
+			peer_id: node_config.keypair.peer_id(),
+			library_id: library.id,
+			token,
+		}
+		.to_string(),
+	})
+}
+
+pub async fn list_invites(library: &Library) -> Result<Vec<LibraryInvite>, InviteError> {
+	library
+		.db
+		.library_invite()
+		.find_many(vec![])
+		.exec()
+		.await?
+		.into_iter()
+		.map(TryInto::try_into)
+		.collect()
+}
+
+pub async fn revoke_invite(library: &Library, id: Uuid) -> Result<(), InviteError> {
+	let deleted = library
+		.db
+		.library_invite()
+		.delete_many(vec![library_invite::pub_id::equals(id.as_bytes().to_vec())])
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(InviteError::NotFound(id));
+	}
+
+	Ok(())
+}
+
+/// Marks the invite with the token as redeemed by the node, returning its role if it could still
+/// be redeemed
+async fn claim_invite(
+	library: &Library,
+	token: &str,
+	node_pub_id: Uuid,
+) -> Result<Option<NodeRole>, InviteError> {
+	let Some(invite) = library
+		.db
+		.library_invite()
+		.find_unique(library_invite::token::equals(token.to_string()))
+		.exec()
+		.await?
+	else {
+		return Ok(None);
+	};
+
+	if invite.redeemed_by.is_some() || DateTime::<Utc>::from(invite.expires_at) < Utc::now() {
+		return Ok(None);
+	}
+
+	// Only the first of two nodes redeeming the invite at once gets it
+	let claimed = library
+		.db
+		.library_invite()
+		.update_many(
+			vec![
+				library_invite::id::equals(invite.id),
+				library_invite::redeemed_by::equals(None),
+			],
+			vec![
+				library_invite::redeemed_by::set(Some(node_pub_id.as_bytes().to_vec())),
+				library_invite::date_redeemed::set(Some(Utc::now().into())),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok((claimed == 1)
+		.then(|| NodeRole::parse(&invite.role))
+		.flatten())
+}
+
+async fn create_node(
+	library: &Library,
+	info: &NodeInformation,
+	peer_id: PeerId,
+	role: NodeRole,
+) -> Result<(), InviteError> {
+	node::Create {
+		pub_id: info.pub_id.as_bytes().to_vec(),
+		name: info.name.clone(),
+		platform: info.platform as i32,
+		date_created: Utc::now().into(),
+		_params: vec![
+			node::identity::set(Some(info.public_key.to_bytes().to_vec())),
+			node::node_peer_id::set(Some(peer_id.to_string())),
+			node::role::set(Some(role.as_str().to_string())),
+		],
+	}
+	.to_query(&library.db)
+	.exec()
+	.await?;
+
+	Ok(())
+}
+
+/// Lets a new node redeeming an invite join the library, sending it the operations of the library
+pub(super) async fn serve_invite(
+	library: &Library,
+	peer_id: PeerId,
+	mut stream: UnicastStream,
+) -> Result<(), InviteError> {
+	stream.write_all(b"T").await?;
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(InviteError::Tunnel)?;
+
+	let token = read_message::<String>(&mut tunnel).await?;
+	let remote_info = NodeInformation::from_stream(&mut tunnel).await?;
+
+	let Some(role) = claim_invite(library, &token, remote_info.pub_id).await? else {
+		warn!("Rejecting invalid invite from peer '{peer_id}'");
+		return write_message(
+			&mut tunnel,
+			&InviteMessage::Rejected("invite is invalid, expired or already redeemed".to_string()),
+		)
+		.await;
+	};
+
+	create_node(library, &remote_info, peer_id, role).await?;
+
+	write_message(
+		&mut tunnel,
+		&InviteMessage::Accepted {
+			name: library.config.name.clone(),
+			description: library.config.description.clone(),
+			role,
+		},
+	)
+	.await?;
+	tunnel
+		.write_all(
+			&NodeInformation {
+				pub_id: library.config.node_id,
+				name: library.config.name.to_string(),
+				public_key: library.identity.to_remote_identity(),
+				platform: Platform::current(),
+			}
+			.to_bytes(),
+		)
+		.await?;
+
+	info!(
+		"Node '{}' joined library '{}' with an invite as {role:?}",
+		remote_info.pub_id, library.id
+	);
+
+	library
+		.audit(AuditAction::DevicePaired {
+			node_pub_id: remote_info.pub_id,
+			name: remote_info.name.clone(),
+		})
+		.await;
+	library.webhooks.dispatch(WebhookEvent::DevicePaired {
+		node_pub_id: remote_info.pub_id,
+		name: remote_info.name,
+	});
+	invalidate_query!(library, "invites.list");
+
+	// The new node has created the library once it asks for its operations
+	if tunnel.read_u8().await? != 1 {
+		return Ok(());
+	}
+
+	let operations = library.sync.get_ops().await?;
+	debug!(
+		"Sending {} operations of library '{}' to peer '{peer_id}'",
+		operations.len(),
+		library.id
+	);
+	for batch in operations.chunks(INITIAL_SYNC_BATCH_SIZE) {
+		write_message(&mut tunnel, &InviteMessage::Operations(batch.to_vec())).await?;
+	}
+
+	write_message(&mut tunnel, &InviteMessage::Done).await
+}
+
+/// Redeems the invite with the node that issued it, creating the library on this node and
+/// ingesting its operations
+pub(super) async fn redeem_invite(
+	manager: &Manager<PeerMetadata>,
+	library_manager: &LibraryManager,
+	node_config: NodeConfig,
+	code: &InviteCode,
+) -> Result<JoinedLibrary, InviteError> {
+	if library_manager.get_library(code.library_id).await.is_some() {
+		return Err(InviteError::AlreadyMember);
+	}
+
+	if !manager
+		.get_discovered_peers()
+		.await
+		.iter()
+		.any(|peer| peer.peer_id == code.peer_id)
+	{
+		return Err(InviteError::PeerNotFound);
+	}
+
+	let mut stream = manager.stream(code.peer_id).await?;
+	let mut header = Header::Invite(code.library_id).to_bytes();
+	header.push(b'T');
+	stream.write_all(&header).await?;
+
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(InviteError::Tunnel)?;
+
+	// The identity of the library on this node, which is never sent
+	let identity = Identity::new();
+	write_message(&mut tunnel, &code.token).await?;
+	tunnel
+		.write_all(
+			&NodeInformation {
+				pub_id: node_config.id,
+				name: node_config.name.clone(),
+				public_key: identity.to_remote_identity(),
+				platform: Platform::current(),
+			}
+			.to_bytes(),
+		)
+		.await?;
+
+	let (name, description, role) = match read_message(&mut tunnel).await? {
+		InviteMessage::Accepted {
+			name,
+			description,
+			role,
+		} => (name, description, role),
+		InviteMessage::Rejected(reason) => return Err(InviteError::Rejected(reason)),
+		_ => return Err(InviteError::UnexpectedMessage),
+	};
+	let remote_info = NodeInformation::from_stream(&mut tunnel).await?;
+
+	let identity_key = IdentityKey::new(&node_config.keypair);
+	let config = LibraryConfig {
+		description,
+		identity: identity_key
+			.encrypt(&identity)
+			.await
+			.map_err(LibraryManagerError::from)?,
+		..LibraryConfig::new(name, node_config.id, &identity_key)
+			.await
+			.map_err(LibraryManagerError::from)?
+	};
+	library_manager
+		.create_with_uuid(code.library_id, config, node_config)
+		.await?;
+	let library = library_manager
+		.get_library(code.library_id)
+		.await
+		.ok_or(InviteError::UnexpectedMessage)?;
+
+	// Only owners can issue invites
+	create_node(&library, &remote_info, code.peer_id, NodeRole::Owner).await?;
+	library
+		.db
+		.node()
+		.update(
+			node::id::equals(library.node_local_id),
+			vec![node::role::set(Some(role.as_str().to_string()))],
+		)
+		.exec()
+		.await?;
+
+	tunnel.write_u8(1).await?;
+	tunnel.flush().await?;
+
+	let mut operations = 0;
+	loop {
+		match read_message(&mut tunnel).await? {
+			InviteMessage::Operations(batch) => {
+				operations += batch.len() as u32;
+				for op in batch {
+					library.sync.ingest_op(op).await?;
+				}
+			}
+			InviteMessage::Done => break,
+			_ => return Err(InviteError::UnexpectedMessage),
+		}
+	}
+
+	info!(
+		"Joined library '{}' as {role:?} with {operations} operations from peer '{}'",
+		library.id, code.peer_id
+	);
+
+	Ok(JoinedLibrary {
+		library_id: library.id,
+		role,
+		operations,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn invite_code_round_trip() {
+		let code = InviteCode {
+			peer_id: sd_p2p::Keypair::generate().peer_id(),
+			library_id: Uuid::new_v4(),
+			token: generate_invite_token(),
+		};
+
+		assert_eq!(code.to_string().parse::<InviteCode>().unwrap(), code);
+
+		for invalid in [
+			"",
+			"spacedrive-invite:a:b:c",
+			&format!("{}:extra", code),
+			&format!("other:{}:{}:token", code.peer_id, code.library_id),
+		] {
+			assert!(matches!(
+				invalid.parse::<InviteCode>(),
+				Err(InviteError::InvalidCode)
+			));
+		}
+	}
+}
@@ -1,11 +1,43 @@
 #![allow(clippy::unwrap_used, clippy::panic)] // TODO: Remove once this is fully stablised
 
+use sd_p2p::spacetime::ProtocolInfo;
+
+mod browse;
+mod capabilities;
+mod delegation;
+mod files;
+mod invites;
 mod p2p_manager;
 mod peer_metadata;
 mod protocol;
+mod relay;
+mod spacedrop;
+mod thumbnails;
 
+pub use browse::*;
+pub use capabilities::*;
+pub use delegation::*;
+pub use files::*;
+pub use invites::*;
 pub use p2p_manager::*;
 pub use peer_metadata::*;
 pub use protocol::*;
+pub use relay::*;
+pub use spacedrop::*;
+pub use thumbnails::*;
 
 pub(super) const SPACEDRIVE_APP_ID: &str = "spacedrive";
+
+/// Version of the protocol nodes speak over their streams, to bump when a [`Header`] or what
+/// follows one changes in a way older nodes can't understand
+const PROTOCOL_VERSION: u16 = 1;
+/// Oldest version of the protocol this node still speaks
+const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Spacedrops can be spread over several streams with [`Header::SpacedropStream`]
+pub(super) const CAPABILITY_SPACEDROP_STREAMS: &str = "spacedrop-streams";
+
+pub(super) fn protocol_info() -> ProtocolInfo {
+	ProtocolInfo::new(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION)
+		.with_capability(CAPABILITY_SPACEDROP_STREAMS)
+}
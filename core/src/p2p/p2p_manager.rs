@@ -1,7 +1,6 @@
 use std::{
-	borrow::Cow,
-	collections::HashMap,
-	path::PathBuf,
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
 	str::FromStr,
 	sync::{
 		atomic::{AtomicU16, Ordering},
@@ -10,31 +9,45 @@ use std::{
 	time::{Duration, Instant},
 };
 
-use chrono::Utc;
-use futures::Stream;
+use chrono::{DateTime, Utc};
+use futures::{future::join_all, Stream};
 use sd_p2p::{
-	spaceblock::{BlockSize, SpaceblockRequest, Transfer},
+	spaceblock::ParallelReceiver,
 	spacetime::SpaceTimeStream,
 	spacetunnel::{Identity, Tunnel},
 	Event, Manager, ManagerError, MetadataManager, PeerId,
 };
-use sd_prisma::prisma::node;
+use sd_prisma::prisma::{file_path, node};
 use sd_sync::CRDTOperation;
 use serde::Serialize;
 use specta::Type;
 use tokio::{
 	fs::File,
-	io::{AsyncReadExt, AsyncWriteExt, BufReader},
-	sync::{broadcast, oneshot, Mutex},
-	time::sleep,
+	io::{AsyncReadExt, AsyncWriteExt},
+	sync::{broadcast, oneshot, Mutex, Notify, RwLock},
+	time::{sleep, timeout},
 };
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
-	library::{Library, LibraryManager, SubscriberEvent},
+	job::FileProgress,
+	library::{
+		AuditAction, Library, LibraryManager, NotificationKind, SubscriberEvent, WebhookEvent,
+	},
 	node::{NodeConfig, NodeConfigManager, Platform},
-	p2p::{NodeInformation, OperatingSystem, SyncRequestError, SPACEDRIVE_APP_ID},
+	object::sensitive::hold_if_sensitive,
+	p2p::{
+		location_owner, missing_remote_thumbnails, protocol_info, redeem_invite,
+		request_delegated_job, request_remote_directory, request_remote_file,
+		request_remote_thumbnails, run_delegated_job, send_spacedrop, serve_invite,
+		serve_remote_directory, serve_remote_file, serve_remote_thumbnails, spawn_relay_client,
+		DelegatedJobRequest, DelegationError, InviteCode, InviteError, JoinedLibrary,
+		NodeInformation, OperatingSystem, PeerCapabilities, RelayStatus, RelayStatuses,
+		RemoteDirectoryEntry, RemoteDirectoryRequest, RemoteFileRequest, SpacedropPolicy,
+		SpacedropRecipient, SyncRequestError, CAPABILITY_SPACEDROP_STREAMS,
+		MAX_REMOTE_THUMBNAILS_PER_REQUEST, SPACEDRIVE_APP_ID,
+	},
 	sync::SyncMessage,
 };
 
@@ -42,6 +55,11 @@ use super::{Header, PeerMetadata};
 
 /// The amount of time to wait for a Spacedrop request to be accepted or rejected before it's automatically rejected
 const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
+/// Peers are discovered again and again, so each device is notified about once in this time
+const DEVICE_ONLINE_NOTIFICATION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Spacedrops being received, by id, with the peer sending them
+type SpacedropTransfers = Arc<Mutex<HashMap<Uuid, (PeerId, Arc<ParallelReceiver>)>>>;
 
 /// TODO: P2P event for the frontend
 #[derive(Debug, Clone, Type, Serialize)]
@@ -56,17 +74,109 @@ pub enum P2PEvent {
 		peer_id: PeerId,
 		name: String,
 	},
+	/// A Spacedrop from a trusted peer is being saved to the receive directory without asking
+	SpacedropAutoAccepted {
+		id: Uuid,
+		peer_id: PeerId,
+		name: String,
+		path: PathBuf,
+	},
+	/// A Spacedrop sent with [`P2PManager::spacedrop`] is over, `accepted` being false if the peer
+	/// rejected it
+	SpacedropFinished {
+		id: Uuid,
+		peer_id: PeerId,
+		accepted: bool,
+		error: Option<String>,
+	},
+	/// A job delegated to a peer with [`P2PManager::delegate_job`] is over
+	DelegatedJobFinished {
+		id: Uuid,
+		peer_id: PeerId,
+		thumbnails_received: u32,
+		error: Option<String>,
+	},
 	// TODO: Expire peer + connection/disconnect
 }
 
+/// Progress of a Spacedrop, sent after each transferred block
+#[derive(Debug, Clone, Type, Serialize)]
+pub struct SpacedropProgress {
+	pub file: FileProgress,
+	pub estimated_completion: DateTime<Utc>,
+}
+
+impl SpacedropProgress {
+	pub(super) fn new(
+		path: PathBuf,
+		bytes_done: u64,
+		bytes_total: u64,
+		started_at: Instant,
+	) -> Self {
+		// Assuming the transfer rate so far holds for the rest of the file
+		let remaining = if bytes_done == 0 {
+			Duration::ZERO
+		} else {
+			started_at
+				.elapsed()
+				.mul_f64(bytes_total.saturating_sub(bytes_done) as f64 / bytes_done as f64)
+		};
+
+		Self {
+			file: FileProgress {
+				path,
+				bytes_done,
+				bytes_total,
+			},
+			estimated_completion: chrono::Duration::from_std(remaining)
+				.ok()
+				.and_then(|remaining| Utc::now().checked_add_signed(remaining))
+				.unwrap_or_else(Utc::now),
+		}
+	}
+}
+
+/// Notifies the libraries the discovered peer is a node of that it came online
+async fn notify_device_online(library_manager: &LibraryManager, peer_id: PeerId) {
+	for library in library_manager.get_all_libraries().await {
+		let node = match library
+			.db
+			.node()
+			.find_first(vec![node::node_peer_id::equals(Some(peer_id.to_string()))])
+			.exec()
+			.await
+		{
+			Ok(Some(node)) => node,
+			Ok(None) => continue,
+			Err(e) => {
+				error!("Failed to find the node of peer '{peer_id}': {e:#?}");
+				continue;
+			}
+		};
+
+		let Ok(node_pub_id) = Uuid::from_slice(&node.pub_id) else {
+			continue;
+		};
+
+		library
+			.notify(NotificationKind::DeviceOnline {
+				node_pub_id,
+				name: node.name,
+			})
+			.await;
+	}
+}
+
 pub struct P2PManager {
 	pub events: (broadcast::Sender<P2PEvent>, broadcast::Receiver<P2PEvent>),
 	pub manager: Arc<Manager<PeerMetadata>>,
 	spacedrop_pairing_reqs: Arc<Mutex<HashMap<Uuid, oneshot::Sender<Option<String>>>>>,
 	pub metadata_manager: Arc<MetadataManager<PeerMetadata>>,
-	pub spacedrop_progress: Arc<Mutex<HashMap<Uuid, broadcast::Sender<u8>>>>,
+	pub spacedrop_progress: Arc<Mutex<HashMap<Uuid, broadcast::Sender<SpacedropProgress>>>>,
 	pairing_id: AtomicU16,
 	library_manager: Arc<LibraryManager>,
+	relay_statuses: RelayStatuses,
+	relay_refresh: Arc<Notify>,
 }
 
 impl P2PManager {
@@ -81,8 +191,13 @@ impl P2PManager {
 
 		let metadata_manager = MetadataManager::new(config);
 
-		let (manager, mut stream) =
-			Manager::new(SPACEDRIVE_APP_ID, &keypair, metadata_manager.clone()).await?;
+		let (manager, mut stream) = Manager::new(
+			SPACEDRIVE_APP_ID,
+			&keypair,
+			metadata_manager.clone(),
+			protocol_info(),
+		)
+		.await?;
 
 		info!(
 			"Node '{}' is now online listening at addresses: {:?}",
@@ -101,9 +216,12 @@ impl P2PManager {
 			let spacedrop_pairing_reqs = spacedrop_pairing_reqs.clone();
 			let spacedrop_progress = spacedrop_progress.clone();
 			let library_manager = library_manager.clone();
+			let node_config = node_config.clone();
+			let spacedrop_transfers = SpacedropTransfers::default();
 
 			async move {
 				let mut shutdown = false;
+				let mut devices_notified = HashMap::<String, Instant>::new();
 				while let Some(event) = stream.next().await {
 					match event {
 						Event::PeerDiscovered(event) => {
@@ -120,6 +238,21 @@ impl P2PManager {
 								.map_err(|_| error!("Failed to send event to p2p event stream!"))
 								.ok();
 
+							let notified_recently = devices_notified
+								.get(&event.peer_id.to_string())
+								.map_or(false, |at| {
+									at.elapsed() < DEVICE_ONLINE_NOTIFICATION_INTERVAL
+								});
+
+							if !notified_recently {
+								devices_notified.insert(event.peer_id.to_string(), Instant::now());
+								tokio::spawn({
+									let library_manager = library_manager.clone();
+									let peer_id = event.peer_id;
+									async move { notify_device_online(&library_manager, peer_id).await }
+								});
+							}
+
 							// TODO: Don't just connect to everyone when we find them. We should only do it if we know them.
 							// TODO(Spacedrop): Disable Spacedrop for now
 							// event.dial().await;
@@ -128,7 +261,9 @@ impl P2PManager {
 							let events = events.clone();
 							let spacedrop_pairing_reqs = spacedrop_pairing_reqs.clone();
 							let spacedrop_progress = spacedrop_progress.clone();
+							let spacedrop_transfers = spacedrop_transfers.clone();
 							let library_manager = library_manager.clone();
+							let node_config = node_config.clone();
 
 							tokio::spawn(async move {
 								let header = Header::from_stream(&mut event.stream).await.unwrap();
@@ -151,7 +286,15 @@ impl P2PManager {
 
 										info!("spacedrop({id}): received from peer '{}' for file '{}' with file length '{}'", event.peer_id, req.name, req.size);
 
-										spacedrop_pairing_reqs.lock().await.insert(id, tx);
+										let settings = node_config.get().await.spacedrop;
+										let policy = settings.policy(&event.peer_id);
+										if policy == SpacedropPolicy::Block {
+											info!(
+												"spacedrop({id}): peer '{}' is blocked, rejecting!",
+												event.peer_id
+											);
+											return;
+										}
 
 										let (process_tx, _) = broadcast::channel(100);
 										spacedrop_progress
@@ -159,17 +302,43 @@ impl P2PManager {
 											.await
 											.insert(id, process_tx.clone());
 
-										if events
-											.send(P2PEvent::SpacedropRequest {
-												id,
-												peer_id: event.peer_id,
-												name: req.name.clone(),
-											})
-											.is_err()
-										{
-											// No frontend's are active
+										let auto_accept_path = match policy {
+											SpacedropPolicy::AutoAccept => {
+												settings.receive_path(&req.name).await
+											}
+											_ => None,
+										};
 
-											todo!("Outright reject Spacedrop");
+										match &auto_accept_path {
+											Some(path) => {
+												events
+													.send(P2PEvent::SpacedropAutoAccepted {
+														id,
+														peer_id: event.peer_id,
+														name: req.name.clone(),
+														path: path.clone(),
+													})
+													.ok();
+
+												tx.send(Some(path.to_string_lossy().to_string()))
+													.ok();
+											}
+											None => {
+												spacedrop_pairing_reqs.lock().await.insert(id, tx);
+
+												if events
+													.send(P2PEvent::SpacedropRequest {
+														id,
+														peer_id: event.peer_id,
+														name: req.name.clone(),
+													})
+													.is_err()
+												{
+													// No frontend's are active
+
+													todo!("Outright reject Spacedrop");
+												}
+											}
 										}
 
 										tokio::select! {
@@ -181,15 +350,44 @@ impl P2PManager {
 													Ok(Some(file_path)) => {
 														info!("spacedrop({id}): accepted saving to '{:?}'", file_path);
 
-														stream.write_all(&[1]).await.unwrap();
-
-														let f = File::create(file_path).await.unwrap();
+														let f = File::create(&file_path).await.unwrap();
 
-														Transfer::new(&req, |percent| {
-															process_tx.send(percent).ok();
-														}).receive(&mut stream, f).await;
+														let started_at = Instant::now();
+														let receiver = Arc::new(ParallelReceiver::new(req.clone(), f, {
+															let file_path = PathBuf::from(&file_path);
+															move |bytes_done| {
+																process_tx.send(SpacedropProgress::new(file_path.clone(), bytes_done, req.size, started_at)).ok();
+															}
+														}));
+														spacedrop_transfers.lock().await.insert(id, (event.peer_id, receiver.clone()));
 
-														info!("spacedrop({id}): complete");
+														stream.write_all(&[1]).await.unwrap();
+														if stream.negotiated().supports(CAPABILITY_SPACEDROP_STREAMS) {
+															// The peer opens the other streams of the transfer with the id
+															stream.write_all(id.as_bytes()).await.unwrap();
+														}
+
+														if let Err(e) = receiver.receive_stream(&mut stream).await {
+															warn!("spacedrop({id}): transfer stream failed: {e}");
+														}
+
+														// The other streams may still be sending their last blocks
+														let finished = timeout(SPACEDROP_TIMEOUT, receiver.finished()).await.is_ok();
+														spacedrop_transfers.lock().await.remove(&id);
+
+														if finished {
+															info!("spacedrop({id}): complete");
+
+															// Only what's saved without asking is held, the user chose where the rest goes
+															if auto_accept_path.is_some() {
+																let sensitive_content = node_config.get().await.sensitive_content;
+																if let Err(e) = hold_if_sensitive(&sensitive_content, Path::new(&file_path)).await {
+																	error!("spacedrop({id}): failed to hold the file flagged as sensitive: {e:#?}");
+																}
+															}
+														} else {
+															error!("spacedrop({id}): the peer stopped sending before the end of the file!");
+														}
 													}
 													Ok(None) => {
 														info!("spacedrop({id}): rejected");
@@ -233,7 +431,7 @@ impl P2PManager {
 										debug!("Creating node in database");
 										node::Create {
 											pub_id: remote_info.pub_id.as_bytes().to_vec(),
-											name: remote_info.name,
+											name: remote_info.name.clone(),
 											platform: remote_info.platform as i32,
 											date_created: Utc::now().into(),
 											_params: vec![
@@ -266,6 +464,18 @@ impl P2PManager {
 											"Paired with '{}' for library '{library_id}'",
 											remote_info.pub_id
 										); // TODO: Use hash of identity cert here cause pub_id can be forged
+
+										library
+											.audit(AuditAction::DevicePaired {
+												node_pub_id: remote_info.pub_id,
+												name: remote_info.name.clone(),
+											})
+											.await;
+
+										library.webhooks.dispatch(WebhookEvent::DevicePaired {
+											node_pub_id: remote_info.pub_id,
+											name: remote_info.name,
+										});
 									}
 									Header::Sync(library_id) => {
 										let stream = match event.stream {
@@ -312,6 +522,158 @@ impl P2PManager {
 											);
 										}
 									}
+									Header::DelegateJob(library_id) => {
+										let stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												// TODO: Return an error to the remote client
+												error!("Received delegated job request from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let Some(library) =
+											library_manager.get_library(library_id).await
+										else {
+											warn!("Rejecting delegated job from peer '{}'. no library by id '{library_id}' found!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) =
+											run_delegated_job(&library, event.peer_id, stream).await
+										{
+											error!(
+												"Error running delegated job for peer '{}': {e}",
+												event.peer_id
+											);
+										}
+									}
+									Header::Thumbnails(library_id) => {
+										let stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received thumbnails request from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let Some(library) =
+											library_manager.get_library(library_id).await
+										else {
+											warn!("Rejecting thumbnails request from peer '{}'. no library by id '{library_id}' found!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) =
+											serve_remote_thumbnails(&library, event.peer_id, stream)
+												.await
+										{
+											error!(
+												"Error sending thumbnails to peer '{}': {e}",
+												event.peer_id
+											);
+										}
+									}
+									Header::Invite(library_id) => {
+										let stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received invite from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let Some(library) =
+											library_manager.get_library(library_id).await
+										else {
+											warn!("Rejecting invite from peer '{}'. no library by id '{library_id}' found!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) =
+											serve_invite(&library, event.peer_id, stream).await
+										{
+											error!(
+												"Error serving invite to peer '{}': {e}",
+												event.peer_id
+											);
+										}
+									}
+									Header::SpacedropStream(id) => {
+										let mut stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received Spacedrop stream from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let receiver = spacedrop_transfers
+											.lock()
+											.await
+											.get(&id)
+											.filter(|(peer_id, _)| *peer_id == event.peer_id)
+											.map(|(_, receiver)| receiver.clone());
+										let Some(receiver) = receiver else {
+											warn!("spacedrop({id}): peer '{}' opened a stream for an unknown transfer!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) = receiver.receive_stream(&mut stream).await {
+											warn!("spacedrop({id}): transfer stream failed: {e}");
+										}
+									}
+									Header::Browse(library_id) => {
+										let stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received browse request from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let Some(library) =
+											library_manager.get_library(library_id).await
+										else {
+											warn!("Rejecting browse request from peer '{}'. no library by id '{library_id}' found!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) =
+											serve_remote_directory(&library, event.peer_id, stream)
+												.await
+										{
+											error!(
+												"Error listing directory for peer '{}': {e}",
+												event.peer_id
+											);
+										}
+									}
+									Header::File(library_id) => {
+										let stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received file request from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										let Some(library) =
+											library_manager.get_library(library_id).await
+										else {
+											warn!("Rejecting file request from peer '{}'. no library by id '{library_id}' found!", event.peer_id);
+											return;
+										};
+
+										if let Err(e) =
+											serve_remote_file(&library, event.peer_id, stream).await
+										{
+											error!(
+												"Error sending file to peer '{}': {e}",
+												event.peer_id
+											);
+										}
+									}
 								}
 							});
 						}
@@ -343,8 +705,17 @@ impl P2PManager {
 			spacedrop_progress,
 			pairing_id: AtomicU16::new(0),
 			library_manager: library_manager.clone(),
+			relay_statuses: Arc::new(RwLock::new(HashMap::new())),
+			relay_refresh: Arc::new(Notify::new()),
 		});
 
+		spawn_relay_client(
+			this.manager.clone(),
+			node_config,
+			this.relay_statuses.clone(),
+			this.relay_refresh.clone(),
+		);
+
 		library_manager
 			.subscribe({
 				let this = this.clone();
@@ -385,15 +756,30 @@ impl P2PManager {
 			version: Some(env!("CARGO_PKG_VERSION").to_string()),
 			email: config.p2p_email.clone(),
 			img_url: config.p2p_img_url.clone(),
+			capabilities: Some(PeerCapabilities::of_node(config)),
 		}
 	}
 
-	#[allow(unused)] // TODO: Should probs be using this
 	pub async fn update_metadata(&self, node_config_manager: &NodeConfigManager) {
 		self.metadata_manager
 			.update(Self::config_to_metadata(&node_config_manager.get().await));
 	}
 
+	/// Outcome of the last registration with the relay, if the node tried registering with it
+	pub async fn relay_status(&self, id: Uuid) -> RelayStatus {
+		self.relay_statuses
+			.read()
+			.await
+			.get(&id)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// Registers with the relays right away, to call once they are changed
+	pub fn refresh_relays(&self) {
+		self.relay_refresh.notify_one();
+	}
+
 	pub async fn accept_spacedrop(&self, id: Uuid, path: String) {
 		if let Some(chan) = self.spacedrop_pairing_reqs.lock().await.remove(&id) {
 			chan.send(Some(path)).unwrap();
@@ -444,7 +830,7 @@ impl P2PManager {
 
 			node::Create {
 				pub_id: remote_info.pub_id.as_bytes().to_vec(),
-				name: remote_info.name,
+				name: remote_info.name.clone(),
 				platform: remote_info.platform as i32,
 				date_created: Utc::now().into(),
 				_params: vec![
@@ -462,11 +848,31 @@ impl P2PManager {
 				"Paired with '{}' for library '{}'",
 				remote_info.pub_id, lib.id
 			); // TODO: Use hash of identity cert here cause pub_id can be forged
+
+			lib.audit(AuditAction::DevicePaired {
+				node_pub_id: remote_info.pub_id,
+				name: remote_info.name.clone(),
+			})
+			.await;
+
+			lib.webhooks.dispatch(WebhookEvent::DevicePaired {
+				node_pub_id: remote_info.pub_id,
+				name: remote_info.name,
+			});
 		});
 
 		pairing_id
 	}
 
+	/// Joins a library with an invite issued by one of its nodes
+	pub async fn redeem_invite(
+		&self,
+		node_config: NodeConfig,
+		code: &InviteCode,
+	) -> Result<JoinedLibrary, InviteError> {
+		redeem_invite(&self.manager, &self.library_manager, node_config, code).await
+	}
+
 	pub async fn broadcast_sync_events(
 		&self,
 		library_id: Uuid,
@@ -509,79 +915,291 @@ impl P2PManager {
 			library_id, target_nodes
 		);
 
-		// TODO: Do in parallel
-		for peer_id in target_nodes {
-			let stream = self.manager.stream(peer_id).await.map_err(|_| ()).unwrap(); // TODO: handle providing incorrect peer id
+		// Sent to every peer at once, so a slow or unreachable one doesn't hold back the others
+		join_all(target_nodes.into_iter().map(|peer_id| {
+			let head_buf = &head_buf;
+			async move {
+				// TODO: handle providing incorrect peer id
+				let Ok(stream) = self.manager.stream(peer_id).await else {
+					warn!("Failed to open a stream to peer '{peer_id}' to send sync messages");
+					return;
+				};
+
+				let mut tunnel = match Tunnel::from_stream(stream).await {
+					Ok(tunnel) => tunnel,
+					Err(e) => {
+						warn!("Failed to open a tunnel to peer '{peer_id}': {e}");
+						return;
+					}
+				};
 
-			let mut tunnel = Tunnel::from_stream(stream).await.unwrap();
+				if let Err(e) = tunnel.write_all(head_buf).await {
+					warn!("Failed to send sync messages to peer '{peer_id}': {e}");
+				}
+			}
+		}))
+		.await;
+	}
 
-			tunnel.write_all(&head_buf).await.unwrap();
-		}
+	/// Asks a paired node to run a job on a location of the library, for nodes that shouldn't
+	/// run heavy jobs themselves like phones. The results are stored as they are streamed back.
+	/// Without a peer, the job goes to the discovered node of the library best suited to run it.
+	pub async fn delegate_job(
+		&self,
+		peer_id: Option<PeerId>,
+		library: Library,
+		request: DelegatedJobRequest,
+	) -> Result<Uuid, DelegationError> {
+		let peer_id = match peer_id {
+			Some(peer_id) => {
+				// Peers advertising no capabilities predate them, so they're given a chance
+				if self
+					.peer_capabilities(peer_id)
+					.await
+					.map_or(false, |capabilities| {
+						request.kind.rank(&capabilities).is_none()
+					}) {
+					return Err(DelegationError::Unsupported(peer_id));
+				}
+
+				peer_id
+			}
+			None => self
+				.best_peer_for_delegation(&library, &request)
+				.await?
+				.ok_or(DelegationError::NoCapablePeer)?,
+		};
+
+		let id = Uuid::new_v4();
+		let stream = self
+			.manager
+			.stream(peer_id)
+			.await
+			.map_err(|_| DelegationError::Connection)?;
+
+		let events = self.events.0.clone();
+		tokio::spawn(async move {
+			info!(
+				"delegated_job({id}): requesting {:?} from peer '{peer_id}'",
+				request.kind
+			);
+
+			let res = request_delegated_job(&library, stream, &request).await;
+			if let Err(e) = &res {
+				error!("delegated_job({id}): failed: {e}");
+			}
+
+			events
+				.send(P2PEvent::DelegatedJobFinished {
+					id,
+					peer_id,
+					thumbnails_received: res.as_ref().map_or(0, |received| *received),
+					error: res.err().map(|e| e.to_string()),
+				})
+				.ok();
+		});
+
+		Ok(id)
 	}
 
-	pub async fn ping(&self) {
-		self.manager.broadcast(Header::Ping.to_bytes()).await;
+	/// Capabilities a discovered peer advertises
+	pub async fn peer_capabilities(&self, peer_id: PeerId) -> Option<PeerCapabilities> {
+		self.manager
+			.get_discovered_peers()
+			.await
+			.into_iter()
+			.find(|peer| peer.peer_id == peer_id)
+			.and_then(|peer| peer.metadata.capabilities)
 	}
 
-	// TODO: Proper error handling
-	pub async fn big_bad_spacedrop(
+	async fn best_peer_for_delegation(
 		&self,
+		library: &Library,
+		request: &DelegatedJobRequest,
+	) -> Result<Option<PeerId>, DelegationError> {
+		let library_peers = library
+			.db
+			.node()
+			.find_many(vec![node::id::not(library.node_local_id)])
+			.select(node::select!({ node_peer_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|node| node.node_peer_id)
+			.collect::<HashSet<_>>();
+
+		Ok(self
+			.manager
+			.get_discovered_peers()
+			.await
+			.into_iter()
+			.filter(|peer| library_peers.contains(&peer.peer_id.to_string()))
+			.filter_map(|peer| {
+				let rank = request.kind.rank(peer.metadata.capabilities.as_ref()?)?;
+				Some((rank, peer.peer_id))
+			})
+			.max_by_key(|(rank, _)| *rank)
+			.map(|(_, peer_id)| peer_id))
+	}
+
+	/// Fetches the missing thumbnails of files on locations owned by other nodes, from these
+	/// nodes, so they can be shown before the files are physically present on this one
+	pub async fn fetch_remote_thumbnails(
+		&self,
+		library: Library,
+		file_path_ids: Vec<file_path::id::Type>,
+	) -> Result<(), DelegationError> {
+		let cas_ids_by_peer = missing_remote_thumbnails(&library, file_path_ids).await?;
+
+		for (peer_id, mut cas_ids) in cas_ids_by_peer {
+			cas_ids.truncate(MAX_REMOTE_THUMBNAILS_PER_REQUEST);
+
+			if self
+				.peer_capabilities(peer_id)
+				.await
+				.map_or(false, |capabilities| !capabilities.can_thumbnail)
+			{
+				debug!("Peer '{peer_id}' doesn't generate thumbnails, skipping it");
+				continue;
+			}
+
+			// The peer may be offline, in which case the thumbnails are generated once the
+			// files are synced
+			let Ok(stream) = self.manager.stream(peer_id).await else {
+				debug!("Peer '{peer_id}' is unreachable, skipping its thumbnails");
+				continue;
+			};
+
+			let library = library.clone();
+			tokio::spawn(async move {
+				match request_remote_thumbnails(&library, stream, cas_ids).await {
+					Ok(received) => {
+						debug!("Received {received} thumbnails from peer '{peer_id}'")
+					}
+					Err(e) => error!("Failed to fetch thumbnails from peer '{peer_id}': {e}"),
+				}
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Reads a chunk of a file on a location owned by a peer, returning the size of the whole
+	/// file, where the chunk starts and its bytes
+	pub async fn request_file_chunk(
+		&self,
+		library: &Library,
 		peer_id: PeerId,
-		path: PathBuf,
-	) -> Result<Option<Uuid>, ()> {
-		let id = Uuid::new_v4();
-		let (tx, _) = broadcast::channel(25);
-		let mut stream = self.manager.stream(peer_id).await.map_err(|_| ())?; // TODO: handle providing incorrect peer id
-
-		let file = File::open(&path).await.map_err(|_| ())?;
-		let metadata = file.metadata().await.map_err(|_| ())?;
-
-		let header = Header::Spacedrop(SpaceblockRequest {
-			name: path
-				.file_name()
-				.map(|v| v.to_string_lossy())
-				.unwrap_or(Cow::Borrowed(""))
-				.to_string(),
-			size: metadata.len(),
-			block_size: BlockSize::from_size(metadata.len()), // TODO: This should be dynamic
-		});
-		stream.write_all(&header.to_bytes()).await.map_err(|_| ())?;
-
-		debug!("Waiting for Spacedrop to be accepted from peer '{peer_id}'");
-		let mut buf = [0; 1];
-		// TODO: Add timeout so the connection is dropped if they never response
-		stream.read_exact(&mut buf).await.map_err(|_| ())?;
-		if buf[0] != 1 {
-			debug!("Spacedrop was rejected from peer '{peer_id}'");
-			return Ok(None);
+		request: RemoteFileRequest,
+	) -> Result<(u64, u64, Vec<u8>), DelegationError> {
+		let stream = self
+			.manager
+			.stream(peer_id)
+			.await
+			.map_err(|_| DelegationError::Connection)?;
+
+		request_remote_file(library, stream, request).await
+	}
+
+	/// Lists a directory of a location owned by a peer as it is on its disk right now, so it can
+	/// be browsed without the location being synced. The thumbnails the peer has for the entries
+	/// are fetched in the background.
+	pub async fn browse_remote_directory(
+		&self,
+		library: Library,
+		request: RemoteDirectoryRequest,
+	) -> Result<Vec<RemoteDirectoryEntry>, DelegationError> {
+		let peer_id = location_owner(&library, request.location_pub_id).await?;
+
+		let stream = self
+			.manager
+			.stream(peer_id)
+			.await
+			.map_err(|_| DelegationError::Connection)?;
+
+		let entries = request_remote_directory(&library, stream, &request).await?;
+
+		let mut cas_ids = Vec::new();
+		for cas_id in entries.iter().filter_map(|entry| entry.cas_id.as_ref()) {
+			if !library.thumbnail_exists(cas_id).await? {
+				cas_ids.push(cas_id.clone());
+			}
 		}
+		cas_ids.truncate(MAX_REMOTE_THUMBNAILS_PER_REQUEST);
 
-		debug!("Starting Spacedrop to peer '{peer_id}'");
-		let i = Instant::now();
+		let can_thumbnail = self
+			.peer_capabilities(peer_id)
+			.await
+			.map_or(true, |capabilities| capabilities.can_thumbnail);
+		if !cas_ids.is_empty() && can_thumbnail {
+			if let Ok(stream) = self.manager.stream(peer_id).await {
+				tokio::spawn(async move {
+					if let Err(e) = request_remote_thumbnails(&library, stream, cas_ids).await {
+						error!("Failed to fetch thumbnails from peer '{peer_id}': {e}");
+					}
+				});
+			}
+		}
 
-		let file = BufReader::new(file);
-		self.spacedrop_progress.lock().await.insert(id, tx.clone());
-		Transfer::new(
-			&match header {
-				Header::Spacedrop(req) => req,
-				_ => unreachable!(),
-			},
-			|percent| {
-				tx.send(percent).ok();
-			},
-		)
-		.send(&mut stream, file)
-		.await;
+		Ok(entries)
+	}
 
-		debug!(
-			"Finished Spacedrop to peer '{peer_id}' after '{:?}",
-			i.elapsed()
-		);
+	pub async fn ping(&self) {
+		self.manager.broadcast(Header::Ping.to_bytes()).await;
+	}
 
-		Ok(Some(id))
+	/// Spacedrops a file to several peers at once. Each transfer is independent, so a peer being
+	/// unreachable or rejecting the file doesn't affect the others, and ends with a
+	/// [`P2PEvent::SpacedropFinished`].
+	pub async fn spacedrop(&self, peer_ids: Vec<PeerId>, path: PathBuf) -> Vec<SpacedropRecipient> {
+		let path = Arc::new(path);
+		let mut recipients = Vec::with_capacity(peer_ids.len());
+
+		for peer_id in peer_ids {
+			if recipients
+				.iter()
+				.any(|recipient: &SpacedropRecipient| recipient.peer_id == peer_id)
+			{
+				continue;
+			}
+
+			let id = Uuid::new_v4();
+			// Registered before the peer accepts, so the progress can be subscribed to right away
+			let (progress_tx, _) = broadcast::channel(25);
+			self.spacedrop_progress
+				.lock()
+				.await
+				.insert(id, progress_tx.clone());
+
+			let manager = self.manager.clone();
+			let events = self.events.0.clone();
+			let path = path.clone();
+			tokio::spawn(async move {
+				let res = send_spacedrop(&manager, peer_id, &path, &progress_tx).await;
+				if let Err(e) = &res {
+					error!("spacedrop({id}): failed to send to peer '{peer_id}': {e}");
+				}
+
+				events
+					.send(P2PEvent::SpacedropFinished {
+						id,
+						peer_id,
+						accepted: res.as_ref().map_or(false, |accepted| *accepted),
+						error: res.err().map(|e| e.to_string()),
+					})
+					.ok();
+			});
+
+			recipients.push(SpacedropRecipient { peer_id, id });
+		}
+
+		recipients
 	}
 
-	pub async fn spacedrop_progress(&self, id: Uuid) -> Option<impl Stream<Item = u8>> {
+	pub async fn spacedrop_progress(
+		&self,
+		id: Uuid,
+	) -> Option<impl Stream<Item = SpacedropProgress>> {
 		self.spacedrop_progress.lock().await.get(&id).map(|v| {
 			let mut v = v.subscribe();
 			async_stream::stream! {
@@ -4,6 +4,8 @@ use sd_p2p::Metadata;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+use super::PeerCapabilities;
+
 #[derive(Debug, Clone, Type, Serialize, Deserialize)]
 pub struct PeerMetadata {
 	pub(super) name: String,
@@ -11,6 +13,8 @@ pub struct PeerMetadata {
 	pub(super) version: Option<String>,
 	pub(super) email: Option<String>,
 	pub(super) img_url: Option<String>,
+	/// Not advertised by older peers
+	pub(super) capabilities: Option<PeerCapabilities>,
 }
 
 impl Metadata for PeerMetadata {
@@ -29,6 +33,11 @@ impl Metadata for PeerMetadata {
 		if let Some(img_url) = self.img_url {
 			map.insert("img_url".to_owned(), img_url);
 		}
+		if let Some(capabilities) = self.capabilities {
+			let (flags, storage_role) = capabilities.to_metadata();
+			map.insert("caps".to_owned(), flags);
+			map.insert("storage".to_owned(), storage_role);
+		}
 		map
 	}
 
@@ -51,6 +60,9 @@ impl Metadata for PeerMetadata {
 			version: data.get("version").map(|v| v.to_owned()),
 			email: data.get("email").map(|v| v.to_owned()),
 			img_url: data.get("img_url").map(|v| v.to_owned()),
+			capabilities: data.get("caps").map(|flags| {
+				PeerCapabilities::from_metadata(flags, data.get("storage").map(String::as_str))
+			}),
 		})
 	}
 }
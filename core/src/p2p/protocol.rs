@@ -19,6 +19,18 @@ pub enum Header {
 	Spacedrop(SpaceblockRequest),
 	Pair(Uuid),
 	Sync(Uuid),
+	/// Asks the peer to run a job for the library with the given id, see [`DelegatedJobRequest`](super::DelegatedJobRequest)
+	DelegateJob(Uuid),
+	/// Asks the peer for the thumbnails it has for files of the library with the given id, see [`RemoteThumbnailsRequest`](super::RemoteThumbnailsRequest)
+	Thumbnails(Uuid),
+	/// Asks the peer for a chunk of a file of the library with the given id, see [`RemoteFileRequest`](super::RemoteFileRequest)
+	File(Uuid),
+	/// Another stream of the accepted Spacedrop with the given id, the transfer being spread over several
+	SpacedropStream(Uuid),
+	/// Redeems an invite to the library with the given id, see [`InviteCode`](super::InviteCode)
+	Invite(Uuid),
+	/// Lists a directory of a location of the library with the given id, see [`RemoteDirectoryRequest`](super::RemoteDirectoryRequest)
+	Browse(Uuid),
 }
 
 #[derive(Debug, Error)]
@@ -82,6 +94,72 @@ impl Header {
 					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
 				))
 			}
+			4 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::DelegateJob(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
+			5 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::Thumbnails(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
+			6 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::File(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
+			7 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::SpacedropStream(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
+			8 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::Invite(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
+			9 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::Browse(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
 			d => Err(HeaderError::InvalidDiscriminator(d)),
 		}
 	}
@@ -104,6 +182,36 @@ impl Header {
 				bytes.extend_from_slice(uuid.as_bytes());
 				bytes
 			}
+			Self::DelegateJob(library_id) => {
+				let mut bytes = vec![4];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
+			Self::Thumbnails(library_id) => {
+				let mut bytes = vec![5];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
+			Self::File(library_id) => {
+				let mut bytes = vec![6];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
+			Self::SpacedropStream(id) => {
+				let mut bytes = vec![7];
+				bytes.extend_from_slice(id.as_bytes());
+				bytes
+			}
+			Self::Invite(library_id) => {
+				let mut bytes = vec![8];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
+			Self::Browse(library_id) => {
+				let mut bytes = vec![9];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
 		}
 	}
 }
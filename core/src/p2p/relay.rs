@@ -0,0 +1,518 @@
+//! Client of the relays an organization runs for its nodes to find each other beyond the local
+//! network, set up in the node config. The node registers with each relay every minute,
+//! proving it holds the keypair of its peer id, and dials the peers the relay knows of, hole
+//! punching through the NATs of the ones it can't reach directly.
+//!
+//! - `POST {relay}/api/v1/p2p/challenge` with the `peerId` of the node answers with a `nonce`
+//! - `POST {relay}/api/v1/p2p/register` with the `peerId`, the `nonce`, the hex encoded
+//!   `signature` of `spacedrive-relay:{nonce}` and the `addresses` the node listens on answers
+//!   with the `peers` registered with the relay and their `addresses`
+//! - `POST {relay}/api/v1/p2p/rendezvous` with the `peerId` of the node, the `nonce` of its last
+//!   registration and the `target` peer it can't reach directly answers with when both peers are
+//!   to start hole punching, `punchAt`, which the target learns of in the `rendezvous` of its next
+//!   registration
+//!
+//! The relay also answers registrations with the `observedIp` the node made them from, its public
+//! address when it's behind a NAT, which the node adds to the addresses it registers next time.
+//!
+//! All of them are made with the token of the relay as a bearer token, if it asks for one.
+
+use std::{
+	collections::{BTreeSet, HashMap, HashSet},
+	net::{IpAddr, SocketAddr},
+	str::FromStr,
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder, Url};
+use rspc::ErrorCode;
+use sd_p2p::{Keypair, Manager, PeerId};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	sync::{Notify, RwLock},
+	time::{sleep, timeout},
+};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::node::NodeConfigManager;
+
+use super::PeerMetadata;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often the node registers with its relays, which forget the nodes that stop doing so
+const REGISTRATION_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a dial to a peer registered with a relay may take before hole punching to it instead
+const DIRECT_DIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A relay the node registers with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayConfig {
+	pub id: Uuid,
+	/// eg: `https://relay.example.com`
+	pub url: String,
+	/// Token the relay authenticates the node with, if it asks for one
+	#[serde(default)]
+	pub token: Option<String>,
+	pub enabled: bool,
+}
+
+impl RelayConfig {
+	pub fn new(url: String, token: Option<String>) -> Result<Self, RelayError> {
+		validate_url(&url)?;
+
+		Ok(Self {
+			id: Uuid::new_v4(),
+			url,
+			token,
+			enabled: true,
+		})
+	}
+
+	/// Replaces the settings of the relay, keeping its token if the new ones leave it out
+	pub fn update(
+		&mut self,
+		url: String,
+		token: Option<String>,
+		enabled: bool,
+	) -> Result<(), RelayError> {
+		validate_url(&url)?;
+
+		self.url = url;
+		if token.is_some() {
+			self.token = token;
+		}
+		self.enabled = enabled;
+
+		Ok(())
+	}
+
+	fn endpoint(&self, path: &str) -> String {
+		format!("{}/api/v1/p2p/{path}", self.url.trim_end_matches('/'))
+	}
+
+	fn authorized(&self, req: RequestBuilder) -> RequestBuilder {
+		match &self.token {
+			Some(token) => req.bearer_auth(token),
+			None => req,
+		}
+	}
+}
+
+fn validate_url(url: &str) -> Result<(), RelayError> {
+	match Url::parse(url) {
+		Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+		_ => Err(RelayError::InvalidUrl(url.to_string())),
+	}
+}
+
+/// A version of [`RelayConfig`] that is safe to share with the frontend
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitisedRelayConfig {
+	pub id: Uuid,
+	pub url: String,
+	pub enabled: bool,
+	/// Whether a token is set, as it isn't sent back
+	pub has_token: bool,
+	pub status: RelayStatus,
+}
+
+impl SanitisedRelayConfig {
+	pub fn new(config: RelayConfig, status: RelayStatus) -> Self {
+		Self {
+			id: config.id,
+			url: config.url,
+			enabled: config.enabled,
+			has_token: config.token.is_some(),
+			status,
+		}
+	}
+}
+
+/// Outcome of the last registration with a relay
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayStatus {
+	/// When the node last registered successfully
+	pub registered_at: Option<DateTime<Utc>>,
+	/// How many peers the relay knew of then
+	pub peers: u32,
+	/// Why the last registration failed, if it did
+	pub error: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+	#[error("relay not found <id='{0}'>")]
+	NotFound(Uuid),
+	#[error("invalid url '{0}', it must be an http or https one")]
+	InvalidUrl(String),
+	#[error("request to the relay failed: {0}")]
+	Http(#[from] reqwest::Error),
+	#[error("the relay didn't answer in time")]
+	Timeout,
+}
+
+impl From<RelayError> for rspc::Error {
+	fn from(err: RelayError) -> Self {
+		match err {
+			RelayError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			RelayError::InvalidUrl(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			RelayError::Http(_) | RelayError::Timeout => {
+				rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err)
+			}
+		}
+	}
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChallengeRequest {
+	peer_id: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+	nonce: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterRequest {
+	peer_id: String,
+	nonce: String,
+	signature: String,
+	addresses: Vec<SocketAddr>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Registration {
+	peers: Vec<RelayedPeer>,
+	/// Address the requests of the node come from, which is its public one when behind a NAT
+	#[serde(default)]
+	observed_ip: Option<IpAddr>,
+	/// Peers that asked for a rendezvous with the node
+	#[serde(default)]
+	rendezvous: Vec<Rendezvous>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RelayedPeer {
+	peer_id: String,
+	addresses: Vec<SocketAddr>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RendezvousRequest {
+	peer_id: String,
+	nonce: String,
+	target: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RendezvousResponse {
+	punch_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rendezvous {
+	peer_id: String,
+	addresses: Vec<SocketAddr>,
+	/// When both peers start dialing each other
+	punch_at: DateTime<Utc>,
+}
+
+/// Registers the node with the relay, returning the nonce that authenticates the node until its
+/// next registration along with what the relay answered
+async fn register(
+	client: &Client,
+	relay: &RelayConfig,
+	keypair: &Keypair,
+	addresses: Vec<SocketAddr>,
+) -> Result<(String, Registration), RelayError> {
+	let peer_id = keypair.peer_id().to_string();
+
+	let Challenge { nonce } = relay
+		.authorized(client.post(relay.endpoint("challenge")))
+		.json(&ChallengeRequest {
+			peer_id: peer_id.clone(),
+		})
+		.send()
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+
+	let signature = hex::encode(keypair.sign(format!("spacedrive-relay:{nonce}").as_bytes()));
+
+	let registration = relay
+		.authorized(client.post(relay.endpoint("register")))
+		.json(&RegisterRequest {
+			peer_id,
+			nonce: nonce.clone(),
+			signature,
+			addresses,
+		})
+		.send()
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+
+	Ok((nonce, registration))
+}
+
+/// Addresses the node can likely be reached at from beyond its NAT, most home routers keeping the
+/// port of the socket the node listens on for the mapping they open
+fn public_addresses(observed_ip: IpAddr, listen_addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+	listen_addrs
+		.iter()
+		.filter(|addr| addr.is_ipv4() == observed_ip.is_ipv4())
+		.map(|addr| SocketAddr::new(observed_ip, addr.port()))
+		.filter(|addr| !listen_addrs.contains(addr))
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.collect()
+}
+
+/// Connects to the peers registered with the relay, dialing them directly first and hole punching
+/// through the NATs of those that can't be reached that way
+async fn connect_to_peers(
+	client: Client,
+	relay: RelayConfig,
+	manager: Arc<Manager<PeerMetadata>>,
+	nonce: String,
+	registration: Registration,
+) {
+	let parse_peer_id = |peer_id: &str| match PeerId::from_str(peer_id) {
+		Ok(peer_id) if peer_id != manager.peer_id() => Some(peer_id),
+		Ok(_) => None,
+		Err(e) => {
+			warn!(
+				"Relay '{}' sent invalid peer id '{peer_id}': {e}",
+				relay.url
+			);
+			None
+		}
+	};
+
+	// Peers that asked for a rendezvous already know they can't reach us directly
+	let mut punching = HashSet::new();
+	for rendezvous in registration.rendezvous {
+		let Some(peer_id) = parse_peer_id(&rendezvous.peer_id) else {
+			continue;
+		};
+
+		punching.insert(peer_id);
+		tokio::spawn(hole_punch(
+			manager.clone(),
+			peer_id,
+			rendezvous.addresses,
+			rendezvous.punch_at,
+		));
+	}
+
+	let mut peers = Vec::new();
+	for peer in registration.peers {
+		let Some(peer_id) = parse_peer_id(&peer.peer_id) else {
+			continue;
+		};
+
+		if !punching.contains(&peer_id) {
+			manager.dial(peer_id, peer.addresses.clone()).await;
+			peers.push((peer_id, peer.addresses));
+		}
+	}
+
+	if peers.is_empty() {
+		return;
+	}
+
+	sleep(DIRECT_DIAL_TIMEOUT).await;
+	let connected = manager.get_connected_peers().await.unwrap_or_default();
+
+	for (peer_id, addresses) in peers
+		.into_iter()
+		.filter(|(peer_id, _)| !connected.contains(peer_id))
+	{
+		let result = relay
+			.authorized(client.post(relay.endpoint("rendezvous")))
+			.json(&RendezvousRequest {
+				peer_id: manager.peer_id().to_string(),
+				nonce: nonce.clone(),
+				target: peer_id.to_string(),
+			})
+			.send()
+			.await
+			.and_then(|res| res.error_for_status());
+
+		let punch_at = match result {
+			Ok(res) => match res.json::<RendezvousResponse>().await {
+				Ok(RendezvousResponse { punch_at }) => punch_at,
+				Err(e) => {
+					warn!("Invalid rendezvous from relay '{}': {e}", relay.url);
+					continue;
+				}
+			},
+			Err(e) => {
+				warn!(
+					"Failed to ask relay '{}' for a rendezvous with peer '{peer_id}': {e}",
+					relay.url
+				);
+				continue;
+			}
+		};
+
+		tokio::spawn(hole_punch(manager.clone(), peer_id, addresses, punch_at));
+	}
+}
+
+async fn hole_punch(
+	manager: Arc<Manager<PeerMetadata>>,
+	peer_id: PeerId,
+	addresses: Vec<SocketAddr>,
+	punch_at: DateTime<Utc>,
+) {
+	if manager
+		.hole_punch(peer_id, addresses, punch_at.into())
+		.await
+	{
+		debug!("Connected directly to peer '{peer_id}' through hole punching");
+	} else {
+		warn!("Failed to hole punch to peer '{peer_id}', it's unreachable until the next registration");
+	}
+}
+
+/// Last registration outcome of each relay, by id
+pub(super) type RelayStatuses = Arc<RwLock<HashMap<Uuid, RelayStatus>>>;
+
+/// Spawns the loop registering the node with the relays in its config, which registers again
+/// right away when `refresh` is notified
+pub(super) fn spawn_relay_client(
+	manager: Arc<Manager<PeerMetadata>>,
+	node_config: Arc<NodeConfigManager>,
+	statuses: RelayStatuses,
+	refresh: Arc<Notify>,
+) {
+	tokio::spawn(async move {
+		let client = match Client::builder().timeout(REQUEST_TIMEOUT).build() {
+			Ok(client) => client,
+			Err(e) => {
+				error!("Failed to build the relay HTTP client: {e:#?}");
+				return;
+			}
+		};
+
+		// Public address of the node as last seen by each relay
+		let mut observed_ips = HashMap::<Uuid, IpAddr>::new();
+
+		loop {
+			let config = node_config.get().await;
+			let listen_addrs = manager.listen_addrs().await.into_iter().collect::<Vec<_>>();
+
+			// Relays removed from the config are forgotten
+			statuses
+				.write()
+				.await
+				.retain(|id, _| config.p2p_relays.iter().any(|relay| relay.id == *id));
+			observed_ips.retain(|id, _| config.p2p_relays.iter().any(|relay| relay.id == *id));
+
+			for relay in config.p2p_relays.iter().filter(|relay| relay.enabled) {
+				let mut addresses = listen_addrs.clone();
+				if let Some(observed_ip) = observed_ips.get(&relay.id) {
+					addresses.extend(public_addresses(*observed_ip, &listen_addrs));
+				}
+
+				let result = timeout(
+					REQUEST_TIMEOUT * 2,
+					register(&client, relay, &config.keypair, addresses),
+				)
+				.await
+				.unwrap_or(Err(RelayError::Timeout));
+
+				let status = match result {
+					Ok((nonce, registration)) => {
+						debug!(
+							"Registered with relay '{}', which knows of {} peers",
+							relay.url,
+							registration.peers.len()
+						);
+
+						if let Some(observed_ip) = registration.observed_ip {
+							observed_ips.insert(relay.id, observed_ip);
+						}
+
+						let count = registration.peers.len() as u32;
+						tokio::spawn(connect_to_peers(
+							client.clone(),
+							relay.clone(),
+							manager.clone(),
+							nonce,
+							registration,
+						));
+
+						RelayStatus {
+							registered_at: Some(Utc::now()),
+							peers: count,
+							error: None,
+						}
+					}
+					Err(e) => {
+						warn!("Failed to register with relay '{}': {e}", relay.url);
+
+						RelayStatus {
+							error: Some(e.to_string()),
+							..statuses
+								.read()
+								.await
+								.get(&relay.id)
+								.cloned()
+								.unwrap_or_default()
+						}
+					}
+				};
+
+				statuses.write().await.insert(relay.id, status);
+			}
+
+			let _ = timeout(REGISTRATION_INTERVAL, refresh.notified()).await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn public_addresses_keep_listening_ports() {
+		let listen_addrs = [
+			"192.168.1.20:53117".parse().unwrap(),
+			"[fe80::1]:53118".parse().unwrap(),
+		];
+
+		assert_eq!(
+			public_addresses("203.0.113.7".parse().unwrap(), &listen_addrs),
+			vec!["203.0.113.7:53117".parse::<SocketAddr>().unwrap()]
+		);
+		assert_eq!(
+			public_addresses("2001:db8::7".parse().unwrap(), &listen_addrs),
+			vec!["[2001:db8::7]:53118".parse::<SocketAddr>().unwrap()]
+		);
+	}
+}
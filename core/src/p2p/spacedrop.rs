@@ -0,0 +1,228 @@
+//! Which peers can Spacedrop files to this node, and whether they have to be accepted by hand,
+//! along with the sending side of a Spacedrop.
+
+use std::{
+	borrow::Cow,
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	time::Instant,
+};
+
+use sd_p2p::{
+	spaceblock::{BlockSize, ParallelTransfer, SpaceblockRequest, TransferError},
+	Manager, PeerId, StreamError,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncWriteExt},
+	sync::broadcast,
+};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::util::error::FileIOError;
+
+use super::{Header, PeerMetadata, SpacedropProgress, CAPABILITY_SPACEDROP_STREAMS};
+
+/// What happens to the Spacedrops sent by a peer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SpacedropPolicy {
+	/// Trusted peer, its files are saved to the receive directory without asking
+	AutoAccept,
+	/// The user is asked to accept each file
+	#[default]
+	Ask,
+	/// Its requests are rejected without the user knowing
+	Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SpacedropSettings {
+	/// Where auto accepted files are saved, peers are asked about instead while it isn't set
+	pub receive_dir: Option<PathBuf>,
+	/// Policy of the peers without one of their own
+	pub default_policy: SpacedropPolicy,
+	/// Policies by peer id
+	pub peer_policies: BTreeMap<String, SpacedropPolicy>,
+}
+
+impl SpacedropSettings {
+	pub fn policy(&self, peer_id: &PeerId) -> SpacedropPolicy {
+		let policy = self
+			.peer_policies
+			.get(&peer_id.to_string())
+			.copied()
+			.unwrap_or(self.default_policy);
+
+		match (policy, &self.receive_dir) {
+			(SpacedropPolicy::AutoAccept, None) => SpacedropPolicy::Ask,
+			(policy, _) => policy,
+		}
+	}
+
+	pub fn set_peer_policy(&mut self, peer_id: &PeerId, policy: Option<SpacedropPolicy>) {
+		match policy {
+			Some(policy) => self.peer_policies.insert(peer_id.to_string(), policy),
+			None => self.peer_policies.remove(&peer_id.to_string()),
+		};
+	}
+
+	/// Path an auto accepted file is saved to, next to the files of the same name already received
+	/// instead of overwriting them
+	pub async fn receive_path(&self, name: &str) -> Option<PathBuf> {
+		let receive_dir = self.receive_dir.as_ref()?;
+
+		// The name comes from the peer, so anything but a plain file name is ignored
+		let name = PathBuf::from(name)
+			.file_name()
+			.map(|name| name.to_string_lossy().to_string())
+			.unwrap_or_else(|| "spacedrop".to_string());
+
+		let (stem, extension) = match name.rsplit_once('.') {
+			Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+			_ => (name.as_str(), None),
+		};
+
+		let mut path = receive_dir.join(&name);
+		let mut copy = 1;
+		while fs::try_exists(&path).await.unwrap_or(false) {
+			path = receive_dir.join(match extension {
+				Some(extension) => format!("{stem} ({copy}).{extension}"),
+				None => format!("{stem} ({copy})"),
+			});
+			copy += 1;
+		}
+
+		Some(path)
+	}
+}
+
+/// Spacedrop sent to one of the peers of a selection
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SpacedropRecipient {
+	pub peer_id: PeerId,
+	/// Id of the progress of the Spacedrop to this peer
+	pub id: Uuid,
+}
+
+#[derive(Debug, Error)]
+pub enum SpacedropError {
+	#[error("failed to connect to the peer: {0}")]
+	Connection(#[from] StreamError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("io error on Spacedrop stream: {0}")]
+	Io(#[from] std::io::Error),
+	#[error(transparent)]
+	Transfer(#[from] TransferError),
+}
+
+/// Sends a file to a peer once it accepts it, returning whether it did
+pub(super) async fn send_spacedrop(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	path: &Path,
+	progress_tx: &broadcast::Sender<SpacedropProgress>,
+) -> Result<bool, SpacedropError> {
+	// TODO: handle providing incorrect peer id
+	let mut stream = manager.stream(peer_id).await?;
+
+	let metadata = fs::metadata(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	let req = SpaceblockRequest {
+		name: path
+			.file_name()
+			.map(|v| v.to_string_lossy())
+			.unwrap_or(Cow::Borrowed(""))
+			.to_string(),
+		size: metadata.len(),
+		block_size: BlockSize::from_size(metadata.len()), // TODO: This should be dynamic
+	};
+	stream
+		.write_all(&Header::Spacedrop(req.clone()).to_bytes())
+		.await?;
+
+	debug!("Waiting for Spacedrop to be accepted from peer '{peer_id}'");
+	let mut buf = [0; 1];
+	// The peer drops the stream if it isn't accepted in time
+	stream.read_exact(&mut buf).await?;
+	if buf[0] != 1 {
+		debug!("Spacedrop was rejected from peer '{peer_id}'");
+		return Ok(false);
+	}
+
+	// Older peers take the whole file over this stream
+	let id = if stream.negotiated().supports(CAPABILITY_SPACEDROP_STREAMS) {
+		let mut id = [0; 16];
+		stream.read_exact(&mut id).await?;
+		Some(Uuid::from_bytes(id))
+	} else {
+		None
+	};
+
+	debug!("Starting Spacedrop to peer '{peer_id}'");
+	let started_at = Instant::now();
+
+	ParallelTransfer::new(&req, |bytes_done| {
+		progress_tx
+			.send(SpacedropProgress::new(
+				path.to_path_buf(),
+				bytes_done,
+				req.size,
+				started_at,
+			))
+			.ok();
+	})
+	.send(path, stream, || async move {
+		let id = id?;
+		let mut stream = manager.stream(peer_id).await.ok()?;
+		stream
+			.write_all(&Header::SpacedropStream(id).to_bytes())
+			.await
+			.ok()?;
+		Some(stream)
+	})
+	.await?;
+
+	debug!(
+		"Finished Spacedrop to peer '{peer_id}' after '{:?}",
+		started_at.elapsed()
+	);
+
+	Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn receive_path_doesnt_escape_or_overwrite() {
+		let dir = tempfile::tempdir().unwrap();
+		let settings = SpacedropSettings {
+			receive_dir: Some(dir.path().to_path_buf()),
+			..Default::default()
+		};
+
+		assert_eq!(
+			settings.receive_path("../../etc/passwd").await,
+			Some(dir.path().join("passwd"))
+		);
+
+		fs::write(dir.path().join("photo.jpg"), b"").await.unwrap();
+		assert_eq!(
+			settings.receive_path("photo.jpg").await,
+			Some(dir.path().join("photo (1).jpg"))
+		);
+
+		assert_eq!(SpacedropSettings::default().receive_path("a").await, None);
+	}
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, str::FromStr};
+
+use sd_p2p::{spacetime::UnicastStream, spacetunnel::Tunnel, PeerId};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::preview::{get_thumbnail_path, ThumbnailFormat},
+	prisma::file_path,
+};
+
+use super::{
+	is_paired, read_delegation_message, store_peer_thumbnail, write_delegation_message,
+	DelegatedJobMessage, DelegationError,
+};
+
+/// Upper bound of the thumbnails asked for at once, about a screen full of files
+pub const MAX_REMOTE_THUMBNAILS_PER_REQUEST: usize = 500;
+
+/// Sent by the node browsing files of a location owned by the peer, right after the
+/// [`Header::Thumbnails`](super::Header). The peer answers with a
+/// [`DelegatedJobMessage::Thumbnail`] for each thumbnail it has, then `Done`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteThumbnailsRequest {
+	pub cas_ids: Vec<String>,
+}
+
+/// Files among `file_path_ids` without a thumbnail here, which are on a location owned by
+/// another node and aren't reachable from this one, grouped by the peer owning them
+pub(super) async fn missing_remote_thumbnails(
+	library: &Library,
+	file_path_ids: Vec<file_path::id::Type>,
+) -> Result<HashMap<PeerId, Vec<String>>, DelegationError> {
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::id::in_vec(file_path_ids),
+			file_path::cas_id::not(None),
+		])
+		.select(file_path::select!({
+			cas_id
+			location: select {
+				node_id
+				path
+				node: select { node_peer_id }
+			}
+		}))
+		.exec()
+		.await?;
+
+	let mut cas_ids_by_peer = HashMap::<_, Vec<_>>::new();
+	for file_path in file_paths {
+		let (Some(cas_id), Some(location)) = (file_path.cas_id, file_path.location) else {
+			continue;
+		};
+
+		if location.node_id == Some(library.node_local_id) {
+			continue;
+		}
+
+		// Locations of other nodes on a network share are thumbnailed like local ones
+		if let Some(path) = &location.path {
+			if fs::metadata(path).await.is_ok() {
+				continue;
+			}
+		}
+
+		let Some(peer_id) = location
+			.node
+			.and_then(|node| node.node_peer_id)
+			.and_then(|peer_id| PeerId::from_str(&peer_id).ok())
+		else {
+			continue;
+		};
+
+		if library.thumbnail_exists(&cas_id).await? {
+			continue;
+		}
+
+		cas_ids_by_peer.entry(peer_id).or_default().push(cas_id);
+	}
+
+	Ok(cas_ids_by_peer)
+}
+
+/// Sends the thumbnails a paired node asked for, skipping the ones we don't have
+pub(super) async fn serve_remote_thumbnails(
+	library: &Library,
+	peer_id: PeerId,
+	mut stream: UnicastStream,
+) -> Result<(), DelegationError> {
+	stream.write_all(b"T").await?;
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	let RemoteThumbnailsRequest { mut cas_ids } = read_delegation_message(&mut tunnel).await?;
+	cas_ids.truncate(MAX_REMOTE_THUMBNAILS_PER_REQUEST);
+
+	if !is_paired(library, peer_id).await {
+		warn!("Rejecting thumbnails request from unpaired peer '{peer_id}'");
+		return write_delegation_message(
+			&mut tunnel,
+			&DelegatedJobMessage::Error("node is not paired within this library".to_string()),
+		)
+		.await;
+	}
+
+	debug!(
+		"Sending up to {} thumbnails to peer '{peer_id}'",
+		cas_ids.len()
+	);
+
+	for cas_id in cas_ids {
+		let thumbnail_path = get_thumbnail_path(library, &cas_id);
+
+		for format in ThumbnailFormat::ALL {
+			// Only the grid tier is sent, other tiers are generated once the file is present
+			let Ok(thumbnail) = fs::read(thumbnail_path.with_extension(format.extension())).await
+			else {
+				continue;
+			};
+
+			write_delegation_message(
+				&mut tunnel,
+				&DelegatedJobMessage::Thumbnail {
+					cas_id,
+					format,
+					thumbnail,
+				},
+			)
+			.await?;
+			break;
+		}
+	}
+
+	write_delegation_message(&mut tunnel, &DelegatedJobMessage::Done).await
+}
+
+/// Asks a paired node for the thumbnails of files it owns and stores the ones it sends back,
+/// returning how many thumbnails were received
+pub(super) async fn request_remote_thumbnails(
+	library: &Library,
+	mut stream: UnicastStream,
+	cas_ids: Vec<String>,
+) -> Result<u32, DelegationError> {
+	let mut header = super::Header::Thumbnails(library.id).to_bytes();
+	header.push(b'T');
+	stream.write_all(&header).await?;
+
+	let mut tunnel = Tunnel::from_stream(stream)
+		.await
+		.map_err(DelegationError::Tunnel)?;
+
+	write_delegation_message(&mut tunnel, &RemoteThumbnailsRequest { cas_ids }).await?;
+
+	let mut thumbnails_received = 0;
+	loop {
+		match read_delegation_message::<DelegatedJobMessage>(&mut tunnel).await? {
+			DelegatedJobMessage::Thumbnail {
+				cas_id,
+				format,
+				thumbnail,
+			} => {
+				store_peer_thumbnail(library, &cas_id, format, thumbnail).await?;
+				thumbnails_received += 1;
+			}
+			DelegatedJobMessage::Done => break,
+			DelegatedJobMessage::Error(e) => return Err(DelegationError::Remote(e)),
+			DelegatedJobMessage::Accepted { .. } => {}
+		}
+	}
+
+	if thumbnails_received > 0 {
+		invalidate_query!(library, "search.paths");
+	}
+
+	Ok(thumbnails_received)
+}
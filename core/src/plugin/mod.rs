@@ -0,0 +1,488 @@
+//! Plugins adding metadata extractors for formats Spacedrive doesn't know about, like CAD files
+//! or scientific data. Each plugin is a directory in the `plugins` directory of the node, with a
+//! `manifest.json` and a `plugin.wasm` module, which is called for the files with one of the
+//! extensions of its manifest once they're identified. Extracted metadata is kept in the library,
+//! for this node only, as other nodes may not have the same plugins.
+//!
+//! Modules run in a sandbox, without WASI: they can't reach the network nor the filesystem, they
+//! only see the file they're called for, and are stopped once over their memory or CPU budget.
+//! Each file gets a fresh instance, so nothing is kept between calls.
+//!
+//! # Host API, version 1
+//!
+//! Modules export:
+//! - `memory`, their linear memory;
+//! - `sd_api_version() -> i32`, returning the version of the host API they were built against;
+//! - `sd_extract() -> i64`, returning the offset of their output in memory in the high 32 bits
+//!   and its length in the low ones, or 0 when there's nothing to extract. The output is a JSON
+//!   object, whose values are kept as strings.
+//!
+//! They may import, from the `spacedrive` module:
+//! - `file_size() -> i64`, the size of the file in bytes;
+//! - `file_read(offset: i64, ptr: i32, len: i32) -> i32`, reading the file from `offset` into
+//!   their memory, returning how many bytes were read, 0 at the end of the file, or -1 on errors;
+//! - `log(level: i32, ptr: i32, len: i32)`, logging an UTF-8 message on the node, from 0 for
+//!   debug to 3 for errors.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_file_identifier, IsolatedFilePathData},
+	prisma::{extracted_metadata, file_path, location, object},
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::fs;
+use tracing::{info, warn};
+
+#[cfg(feature = "plugins")]
+use tokio::task::spawn_blocking;
+
+#[cfg(feature = "plugins")]
+mod runtime;
+
+/// Version of the host API of this node, plugins built against another one aren't loaded
+pub const HOST_API_VERSION: u32 = 1;
+
+const PLUGINS_DIR_NAME: &str = "plugins";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+#[cfg(feature = "plugins")]
+const MODULE_FILE_NAME: &str = "plugin.wasm";
+
+/// Most values an extractor can return for a file, the others are dropped
+const MAX_VALUES: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+	#[error("invalid plugin manifest <path='{}'>: {1}", .0.display())]
+	InvalidManifest(Box<Path>, serde_json::Error),
+	#[error("invalid plugin name '{0}', only letters, digits, '-' and '_' are allowed")]
+	InvalidName(String),
+	#[error("a plugin named '{0}' is already loaded")]
+	DuplicateName(String),
+	#[error(
+		"plugin was built against version {0} of the host API, this node supports version {HOST_API_VERSION}"
+	)]
+	UnsupportedApiVersion(i64),
+	#[error("plugin didn't export '{0}'")]
+	MissingExport(&'static str),
+	#[error("plugin output of {0} bytes is over the limit")]
+	OutputTooLarge(usize),
+	#[error("plugin output is out of the bounds of its memory")]
+	OutputOutOfBounds,
+	#[error("plugin output isn't a JSON object: {0}")]
+	InvalidOutput(serde_json::Error),
+	#[error("this node was built without plugin support")]
+	Unsupported,
+	#[cfg(feature = "plugins")]
+	#[error("plugin runtime error: {0}")]
+	Runtime(#[from] wasmtime::Error),
+	#[error("plugin task failed: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+	/// Unique among the plugins of a node, the metadata it extracts is kept under this name
+	pub name: String,
+	pub version: String,
+	#[serde(default)]
+	pub description: String,
+	/// Version of the host API the plugin was built against
+	pub api_version: u32,
+	/// Extensions of the files its extractor is called for, without the leading dot
+	pub extensions: Vec<String>,
+}
+
+impl PluginManifest {
+	fn handles(&self, extension: &str) -> bool {
+		self.extensions
+			.iter()
+			.any(|handled| handled.eq_ignore_ascii_case(extension))
+	}
+}
+
+pub struct Plugin {
+	pub manifest: PluginManifest,
+	#[cfg(feature = "plugins")]
+	module: wasmtime::Module,
+}
+
+/// A plugin directory as shown to the frontend, with why it wasn't loaded if it wasn't
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginInfo {
+	pub directory: PathBuf,
+	pub manifest: Option<PluginManifest>,
+	pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct LoadedPlugins {
+	plugins: Vec<Arc<Plugin>>,
+	infos: Vec<PluginInfo>,
+}
+
+/// The plugins installed on this node, loaded when it starts
+pub struct PluginManager {
+	dir: PathBuf,
+	loaded: RwLock<LoadedPlugins>,
+	#[cfg(feature = "plugins")]
+	runtime: Option<runtime::Runtime>,
+}
+
+impl PluginManager {
+	pub(crate) async fn new(data_dir: &Path) -> Arc<Self> {
+		#[cfg(feature = "plugins")]
+		let runtime = runtime::Runtime::new()
+			.map_err(|e| {
+				warn!("Failed to start the plugin runtime, no plugins will be loaded: {e:#?}")
+			})
+			.ok();
+
+		let this = Arc::new(Self {
+			dir: data_dir.join(PLUGINS_DIR_NAME),
+			loaded: RwLock::default(),
+			#[cfg(feature = "plugins")]
+			runtime,
+		});
+
+		this.reload().await;
+
+		this
+	}
+
+	/// Loads the plugins of the plugins directory again, for the ones installed since the node
+	/// started
+	pub(crate) async fn reload(&self) -> Vec<PluginInfo> {
+		let mut loaded = LoadedPlugins::default();
+
+		let mut entries = match fs::read_dir(&self.dir).await {
+			Ok(entries) => entries,
+			// No plugins were ever installed
+			Err(_) => {
+				*self.loaded.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+				return vec![];
+			}
+		};
+
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			let directory = entry.path();
+			if !fs::metadata(&directory)
+				.await
+				.map(|metadata| metadata.is_dir())
+				.unwrap_or(false)
+			{
+				continue;
+			}
+
+			let info = match self.load(&directory, &loaded.plugins).await {
+				Ok(plugin) => {
+					info!(
+						"Loaded plugin '{}' {}",
+						plugin.manifest.name, plugin.manifest.version
+					);
+					let manifest = plugin.manifest.clone();
+					loaded.plugins.push(Arc::new(plugin));
+
+					PluginInfo {
+						directory,
+						manifest: Some(manifest),
+						error: None,
+					}
+				}
+				Err((manifest, e)) => {
+					warn!("Failed to load plugin '{}': {e}", directory.display());
+
+					PluginInfo {
+						directory,
+						manifest,
+						error: Some(e.to_string()),
+					}
+				}
+			};
+
+			loaded.infos.push(info);
+		}
+
+		let infos = loaded.infos.clone();
+		*self.loaded.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+
+		infos
+	}
+
+	async fn load(
+		&self,
+		directory: &Path,
+		plugins: &[Arc<Plugin>],
+	) -> Result<Plugin, (Option<PluginManifest>, PluginError)> {
+		let manifest_path = directory.join(MANIFEST_FILE_NAME);
+		let manifest = fs::read(&manifest_path)
+			.await
+			.map_err(|e| (None, FileIOError::from((&manifest_path, e)).into()))
+			.and_then(|bytes| {
+				serde_json::from_slice::<PluginManifest>(&bytes).map_err(|e| {
+					(
+						None,
+						PluginError::InvalidManifest(manifest_path.into_boxed_path(), e),
+					)
+				})
+			})?;
+
+		let fail = |e| Err((Some(manifest.clone()), e));
+
+		if manifest.name.is_empty()
+			|| !manifest
+				.name
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+		{
+			return fail(PluginError::InvalidName(manifest.name.clone()));
+		}
+
+		if plugins
+			.iter()
+			.any(|plugin| plugin.manifest.name == manifest.name)
+		{
+			return fail(PluginError::DuplicateName(manifest.name.clone()));
+		}
+
+		if manifest.api_version != HOST_API_VERSION {
+			return fail(PluginError::UnsupportedApiVersion(
+				manifest.api_version.into(),
+			));
+		}
+
+		#[cfg(feature = "plugins")]
+		{
+			let Some(runtime) = self.runtime.clone() else {
+				return fail(PluginError::Unsupported);
+			};
+
+			let module_path = directory.join(MODULE_FILE_NAME);
+			let wasm = match fs::read(&module_path).await {
+				Ok(wasm) => wasm,
+				Err(e) => return fail(FileIOError::from((&module_path, e)).into()),
+			};
+
+			// Compiling can take a while for big modules
+			match spawn_blocking(move || runtime.compile(&wasm)).await {
+				Ok(Ok(module)) => Ok(Plugin { manifest, module }),
+				Ok(Err(e)) => fail(e),
+				Err(e) => fail(e.into()),
+			}
+		}
+
+		#[cfg(not(feature = "plugins"))]
+		fail(PluginError::Unsupported)
+	}
+
+	pub fn list(&self) -> Vec<PluginInfo> {
+		self.loaded
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.infos
+			.clone()
+	}
+
+	/// The plugins with an extractor for files with this extension
+	fn extractors_for(&self, extension: &str) -> Vec<Arc<Plugin>> {
+		self.loaded
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.plugins
+			.iter()
+			.filter(|plugin| plugin.manifest.handles(extension))
+			.cloned()
+			.collect()
+	}
+
+	/// Runs the extractor of a plugin on a file, returning the values it extracted
+	async fn extract(
+		&self,
+		plugin: Arc<Plugin>,
+		path: PathBuf,
+	) -> Result<BTreeMap<String, String>, PluginError> {
+		parse_output(&self.run(plugin, path).await?)
+	}
+
+	#[cfg(feature = "plugins")]
+	async fn run(&self, plugin: Arc<Plugin>, path: PathBuf) -> Result<Vec<u8>, PluginError> {
+		let runtime = self.runtime.clone().ok_or(PluginError::Unsupported)?;
+
+		spawn_blocking(move || runtime.extract(&plugin.manifest.name, &plugin.module, &path))
+			.await?
+	}
+
+	#[cfg(not(feature = "plugins"))]
+	async fn run(&self, _: Arc<Plugin>, _: PathBuf) -> Result<Vec<u8>, PluginError> {
+		Err(PluginError::Unsupported)
+	}
+}
+
+/// Turns the JSON object returned by an extractor into the values kept in the library
+fn parse_output(output: &[u8]) -> Result<BTreeMap<String, String>, PluginError> {
+	if output.is_empty() {
+		return Ok(BTreeMap::new());
+	}
+
+	let values = serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(output)
+		.map_err(PluginError::InvalidOutput)?;
+
+	Ok(values
+		.into_iter()
+		.filter_map(|(key, value)| match value {
+			serde_json::Value::Null => None,
+			serde_json::Value::String(value) => Some((key, value)),
+			value => Some((key, value.to_string())),
+		})
+		.take(MAX_VALUES)
+		.collect())
+}
+
+/// Runs the extractors of the plugins of this node on files that were just identified, replacing
+/// what they extracted from their objects before
+pub(crate) async fn run_extractors(
+	library: &Library,
+	location: &location::Data,
+	file_paths: &[file_path_for_file_identifier::Data],
+) -> Result<(), PluginError> {
+	let plugins = &library.node_context.plugins;
+	let db = &library.db;
+
+	let to_extract = file_paths
+		.iter()
+		.filter_map(|file_path| {
+			let extractors = plugins.extractors_for(file_path.extension.as_deref()?);
+			(!extractors.is_empty()).then_some((file_path, extractors))
+		})
+		.collect::<Vec<_>>();
+
+	if to_extract.is_empty() {
+		return Ok(());
+	}
+
+	let location_path = maybe_missing(&location.path, "location.path").map(Path::new)?;
+
+	let object_ids = db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(
+			to_extract
+				.iter()
+				.map(|(file_path, _)| file_path.id)
+				.collect(),
+		)])
+		.select(file_path::select!({ id object_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| Some((file_path.id, file_path.object_id?)))
+		.collect::<BTreeMap<_, _>>();
+
+	for (file_path, extractors) in to_extract {
+		let Some(&object_id) = object_ids.get(&file_path.id) else {
+			continue;
+		};
+
+		let Ok(iso_file_path) = IsolatedFilePathData::try_from((location.id, file_path)) else {
+			continue;
+		};
+		let path = location_path.join(&iso_file_path);
+
+		for plugin in extractors {
+			let name = plugin.manifest.name.clone();
+
+			// A faulty plugin isn't to stop files from being identified
+			let values = match plugins.extract(plugin, path.clone()).await {
+				Ok(values) => values,
+				Err(e) => {
+					warn!(
+						"Plugin '{name}' failed to extract metadata from '{}': {e}",
+						path.display()
+					);
+					continue;
+				}
+			};
+
+			save_metadata(library, object_id, &name, values).await?;
+		}
+	}
+
+	Ok(())
+}
+
+async fn save_metadata(
+	Library { db, .. }: &Library,
+	object_id: object::id::Type,
+	plugin: &str,
+	values: BTreeMap<String, String>,
+) -> Result<(), PluginError> {
+	db.extracted_metadata()
+		.delete_many(vec![
+			extracted_metadata::object_id::equals(Some(object_id)),
+			extracted_metadata::plugin::equals(Some(plugin.to_string())),
+		])
+		.exec()
+		.await?;
+
+	if values.is_empty() {
+		return Ok(());
+	}
+
+	let date_created = Utc::now().into();
+
+	db.extracted_metadata()
+		.create_many(
+			values
+				.into_iter()
+				.map(|(key, value)| {
+					extracted_metadata::create_unchecked(vec![
+						extracted_metadata::plugin::set(Some(plugin.to_string())),
+						extracted_metadata::key::set(Some(key)),
+						extracted_metadata::value::set(Some(value)),
+						extracted_metadata::object_id::set(Some(object_id)),
+						extracted_metadata::date_created::set(Some(date_created)),
+					])
+				})
+				.collect(),
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_extractor_output() {
+		let values = parse_output(br#"{"layers":12,"units":"mm","author":null}"#).unwrap();
+
+		assert_eq!(values.len(), 2);
+		assert_eq!(values["layers"], "12");
+		assert_eq!(values["units"], "mm");
+		assert!(parse_output(b"[1, 2]").is_err());
+		assert!(parse_output(b"").unwrap().is_empty());
+	}
+}
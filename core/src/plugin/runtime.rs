@@ -0,0 +1,232 @@
+use crate::util::error::FileIOError;
+
+use std::{
+	fs::File,
+	io::{Read, Seek, SeekFrom},
+	path::Path,
+};
+
+use tracing::{debug, error, info, warn};
+use wasmtime::{
+	Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use super::{PluginError, HOST_API_VERSION};
+
+/// Name of the module the host API is imported from
+const HOST_MODULE: &str = "spacedrive";
+
+/// Instructions an extractor can run for a file, roughly a few seconds of CPU time
+const FUEL_PER_CALL: u64 = 2_000_000_000;
+/// Memory an extractor can grow to
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+/// Most bytes read from the file in one `file_read` call
+const MAX_READ_LEN: usize = 1024 * 1024;
+/// Longest output an extractor can return, in bytes
+const MAX_OUTPUT_LEN: usize = 64 * 1024;
+/// Longest message an extractor can log
+const MAX_LOG_LEN: usize = 4096;
+
+struct HostState {
+	plugin: String,
+	file: File,
+	file_len: u64,
+	limits: StoreLimits,
+}
+
+#[derive(Clone)]
+pub(super) struct Runtime {
+	engine: Engine,
+	linker: Linker<HostState>,
+}
+
+impl Runtime {
+	pub(super) fn new() -> Result<Self, PluginError> {
+		let mut config = Config::new();
+		config.consume_fuel(true);
+
+		let engine = Engine::new(&config)?;
+		let mut linker = Linker::new(&engine);
+
+		linker.func_wrap(HOST_MODULE, "file_size", |caller: Caller<'_, HostState>| {
+			i64::try_from(caller.data().file_len).unwrap_or(i64::MAX)
+		})?;
+		linker.func_wrap(HOST_MODULE, "file_read", file_read)?;
+		linker.func_wrap(HOST_MODULE, "log", log)?;
+
+		Ok(Self { engine, linker })
+	}
+
+	pub(super) fn compile(&self, wasm: &[u8]) -> Result<Module, PluginError> {
+		Module::from_binary(&self.engine, wasm).map_err(Into::into)
+	}
+
+	/// Runs the extractor of a module on a file in a fresh instance, blocking until it returns
+	pub(super) fn extract(
+		&self,
+		plugin: &str,
+		module: &Module,
+		path: &Path,
+	) -> Result<Vec<u8>, PluginError> {
+		let file = File::open(path).map_err(|e| FileIOError::from((path, e)))?;
+		let file_len = file
+			.metadata()
+			.map_err(|e| FileIOError::from((path, e)))?
+			.len();
+
+		let mut store = Store::new(
+			&self.engine,
+			HostState {
+				plugin: plugin.to_string(),
+				file,
+				file_len,
+				limits: StoreLimitsBuilder::new()
+					.memory_size(MAX_MEMORY)
+					.instances(1)
+					.build(),
+			},
+		);
+		store.limiter(|state| &mut state.limits);
+		store.add_fuel(FUEL_PER_CALL)?;
+
+		let instance = self.linker.instantiate(&mut store, module)?;
+
+		let api_version = instance
+			.get_typed_func::<(), i32>(&mut store, "sd_api_version")?
+			.call(&mut store, ())?;
+		if i64::from(api_version) != i64::from(HOST_API_VERSION) {
+			return Err(PluginError::UnsupportedApiVersion(api_version.into()));
+		}
+
+		let packed = instance
+			.get_typed_func::<(), i64>(&mut store, "sd_extract")?
+			.call(&mut store, ())? as u64;
+		if packed == 0 {
+			return Ok(vec![]);
+		}
+
+		let (ptr, len) = ((packed >> 32) as usize, (packed & 0xFFFF_FFFF) as usize);
+		if len > MAX_OUTPUT_LEN {
+			return Err(PluginError::OutputTooLarge(len));
+		}
+
+		let memory = instance
+			.get_memory(&mut store, "memory")
+			.ok_or(PluginError::MissingExport("memory"))?;
+
+		let mut output = vec![0; len];
+		memory
+			.read(&store, ptr, &mut output)
+			.map_err(|_| PluginError::OutputOutOfBounds)?;
+
+		Ok(output)
+	}
+}
+
+fn file_read(mut caller: Caller<'_, HostState>, offset: i64, ptr: i32, len: i32) -> i32 {
+	let (Ok(offset), Ok(ptr), Ok(len)) = (
+		u64::try_from(offset),
+		usize::try_from(ptr),
+		usize::try_from(len),
+	) else {
+		return -1;
+	};
+
+	let mut buf = vec![0; len.min(MAX_READ_LEN)];
+	let file = &mut caller.data_mut().file;
+	let Ok(read) = file
+		.seek(SeekFrom::Start(offset))
+		.and_then(|_| file.read(&mut buf))
+	else {
+		return -1;
+	};
+
+	let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+		return -1;
+	};
+	if memory.write(&mut caller, ptr, &buf[..read]).is_err() {
+		return -1;
+	}
+
+	// Can't overflow, as reads are capped to `MAX_READ_LEN`
+	read as i32
+}
+
+fn log(mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32) {
+	let (Ok(ptr), Ok(len)) = (usize::try_from(ptr), usize::try_from(len)) else {
+		return;
+	};
+	let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+		return;
+	};
+
+	let mut message = vec![0; len.min(MAX_LOG_LEN)];
+	if memory.read(&caller, ptr, &mut message).is_err() {
+		return;
+	}
+
+	let plugin = &caller.data().plugin;
+	let message = String::from_utf8_lossy(&message);
+	match level {
+		0 => debug!("Plugin '{plugin}': {message}"),
+		1 => info!("Plugin '{plugin}': {message}"),
+		2 => warn!("Plugin '{plugin}': {message}"),
+		_ => error!("Plugin '{plugin}': {message}"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::io::Write;
+
+	/// Reads the first 4 bytes of the file into a JSON object
+	const MAGIC_EXTRACTOR: &str = r#"
+		(module
+			(import "spacedrive" "file_read" (func $file_read (param i64 i32 i32) (result i32)))
+			(memory (export "memory") 1)
+			(data (i32.const 0) "{\"magic\":\"")
+			(data (i32.const 14) "\"}")
+			(func (export "sd_api_version") (result i32) i32.const 1)
+			(func (export "sd_extract") (result i64)
+				(drop (call $file_read (i64.const 0) (i32.const 10) (i32.const 4)))
+				i64.const 16))
+	"#;
+
+	/// Never returns, to be stopped once out of fuel
+	const LOOPING_EXTRACTOR: &str = r#"
+		(module
+			(memory (export "memory") 1)
+			(func (export "sd_api_version") (result i32) i32.const 1)
+			(func (export "sd_extract") (result i64)
+				(loop $forever (br $forever))
+				i64.const 0))
+	"#;
+
+	fn file_with(contents: &[u8]) -> tempfile::NamedTempFile {
+		let mut file = tempfile::NamedTempFile::new().unwrap();
+		file.write_all(contents).unwrap();
+		file
+	}
+
+	#[test]
+	fn runs_extractors() {
+		let runtime = Runtime::new().unwrap();
+		let module = Module::new(&runtime.engine, MAGIC_EXTRACTOR).unwrap();
+		let file = file_with(b"FITS data");
+
+		let output = runtime.extract("magic", &module, file.path()).unwrap();
+
+		assert_eq!(output, br#"{"magic":"FITS"}"#);
+	}
+
+	#[test]
+	fn stops_extractors_out_of_fuel() {
+		let runtime = Runtime::new().unwrap();
+		let module = Module::new(&runtime.engine, LOOPING_EXTRACTOR).unwrap();
+		let file = file_with(b"");
+
+		assert!(runtime.extract("looping", &module, file.path()).is_err());
+	}
+}
@@ -0,0 +1,337 @@
+//! Storage providers files can be transferred to, like rclone remotes. Every provider is accessed
+//! through the same [`Remote`], with the same credentials and transfer code, only differing in how
+//! its [`Operator`] is built.
+
+use crate::{
+	prisma::{remote, PrismaClient},
+	util::{
+		db::{maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use opendal::{
+	layers::RetryLayer,
+	services::{Azblob, Ftp, Webdav, S3},
+	Metakey, Operator,
+};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{self, AsyncReadExt},
+};
+
+pub mod upload_job;
+
+/// Size of the chunks files are uploaded in
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RemoteBackend {
+	WebDav,
+	Ftp,
+	AzureBlob,
+	S3,
+}
+
+/// Where the storage of a remote is, which can be shown to users
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct RemoteOptions {
+	pub backend: RemoteBackend,
+	/// Url of the server, like `https://dav.example.com` or `ftp://ftp.example.com:21`
+	pub endpoint: String,
+	/// Directory of the storage files are read from and written to, its root if not set
+	pub root: Option<String>,
+	/// Azure Blob container or S3 bucket
+	pub container: Option<String>,
+	/// Region of S3 buckets, guessed from the endpoint if not set
+	pub region: Option<String>,
+}
+
+/// Secrets to access a remote, shared by every backend: a username and password, the name and
+/// key of an Azure storage account, or an S3 access key id and secret
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct RemoteCredentials {
+	pub username: Option<String>,
+	pub secret: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+	#[error("remote not found <id='{0}'>")]
+	IdNotFound(remote::id::Type),
+	#[error("invalid remote: {0}")]
+	InvalidOptions(&'static str),
+	#[error("remote storage error: {0}")]
+	Storage(#[from] opendal::Error),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Serialization(#[from] serde_json::Error),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+}
+
+impl From<RemoteError> for rspc::Error {
+	fn from(err: RemoteError) -> Self {
+		match err {
+			RemoteError::IdNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			RemoteError::InvalidOptions(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A file or directory of a remote
+#[derive(Debug, Serialize, Type)]
+pub struct RemoteEntry {
+	pub name: String,
+	pub path: String,
+	pub is_dir: bool,
+	#[specta(type = String)]
+	pub size_in_bytes: Option<u64>,
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+/// Connection to the storage of a remote
+#[derive(Debug, Clone)]
+pub struct Remote {
+	operator: Operator,
+}
+
+impl Remote {
+	pub fn new(
+		options: &RemoteOptions,
+		credentials: &RemoteCredentials,
+	) -> Result<Self, RemoteError> {
+		if options.endpoint.is_empty() {
+			return Err(RemoteError::InvalidOptions("the endpoint is required"));
+		}
+
+		let root = options.root.as_deref().unwrap_or("/");
+
+		let operator = match options.backend {
+			RemoteBackend::WebDav => {
+				let mut builder = Webdav::default();
+				builder.endpoint(&options.endpoint).root(root);
+				if let Some(username) = &credentials.username {
+					builder.username(username);
+				}
+				if let Some(password) = &credentials.secret {
+					builder.password(password);
+				}
+
+				Operator::new(builder)?.layer(RetryLayer::new()).finish()
+			}
+			RemoteBackend::Ftp => {
+				let mut builder = Ftp::default();
+				builder.endpoint(&options.endpoint).root(root);
+				if let Some(user) = &credentials.username {
+					builder.user(user);
+				}
+				if let Some(password) = &credentials.secret {
+					builder.password(password);
+				}
+
+				Operator::new(builder)?.layer(RetryLayer::new()).finish()
+			}
+			RemoteBackend::AzureBlob => {
+				let container = options
+					.container
+					.as_deref()
+					.ok_or(RemoteError::InvalidOptions(
+						"Azure Blob remotes need a container",
+					))?;
+
+				let mut builder = Azblob::default();
+				builder
+					.endpoint(&options.endpoint)
+					.container(container)
+					.root(root);
+				if let Some(account_name) = &credentials.username {
+					builder.account_name(account_name);
+				}
+				if let Some(account_key) = &credentials.secret {
+					builder.account_key(account_key);
+				}
+
+				Operator::new(builder)?.layer(RetryLayer::new()).finish()
+			}
+			RemoteBackend::S3 => {
+				let bucket = options
+					.container
+					.as_deref()
+					.ok_or(RemoteError::InvalidOptions("S3 remotes need a bucket"))?;
+
+				let mut builder = S3::default();
+				builder
+					.endpoint(&options.endpoint)
+					.bucket(bucket)
+					.root(root);
+				if let Some(region) = &options.region {
+					builder.region(region);
+				}
+				if let Some(access_key_id) = &credentials.username {
+					builder.access_key_id(access_key_id);
+				}
+				if let Some(secret_access_key) = &credentials.secret {
+					builder.secret_access_key(secret_access_key);
+				}
+
+				Operator::new(builder)?.layer(RetryLayer::new()).finish()
+			}
+		};
+
+		Ok(Self { operator })
+	}
+
+	pub async fn from_db(db: &PrismaClient, id: remote::id::Type) -> Result<Self, RemoteError> {
+		let remote = db
+			.remote()
+			.find_unique(remote::id::equals(id))
+			.exec()
+			.await?
+			.ok_or(RemoteError::IdNotFound(id))?;
+
+		let options = serde_json::from_slice(maybe_missing(&remote.options, "remote.options")?)?;
+		let credentials = remote
+			.credentials
+			.as_deref()
+			.map(serde_json::from_slice)
+			.transpose()?
+			.unwrap_or_default();
+
+		Self::new(&options, &credentials)
+	}
+
+	/// Makes sure the storage can be reached with the credentials of the remote
+	pub async fn check(&self) -> Result<(), RemoteError> {
+		self.operator.check().await.map_err(Into::into)
+	}
+
+	/// Entries of a directory of the remote, `path` being relative to its root
+	pub async fn list(&self, path: &str) -> Result<Vec<RemoteEntry>, RemoteError> {
+		let mut lister = self.operator.list(&dir_path(path)).await?;
+
+		let mut entries = vec![];
+		while let Some(entry) = lister.next().await {
+			let entry = entry?;
+			let metadata = self
+				.operator
+				.metadata(
+					&entry,
+					Metakey::Mode | Metakey::ContentLength | Metakey::LastModified,
+				)
+				.await?;
+
+			entries.push(RemoteEntry {
+				name: entry.name().trim_end_matches('/').to_string(),
+				path: entry.path().to_string(),
+				is_dir: metadata.is_dir(),
+				size_in_bytes: (!metadata.is_dir()).then(|| metadata.content_length()),
+				date_modified: metadata.last_modified(),
+			});
+		}
+
+		Ok(entries)
+	}
+
+	/// Uploads a local file to `target`, relative to the root of the remote, replacing the file
+	/// there if any. `on_progress` is called with the bytes uploaded so far after each chunk.
+	pub async fn upload_file(
+		&self,
+		source: impl AsRef<Path>,
+		target: &str,
+		mut on_progress: impl FnMut(u64),
+	) -> Result<(), RemoteError> {
+		let source = source.as_ref();
+
+		let mut file = fs::File::open(source)
+			.await
+			.map_err(|e| FileIOError::from((source, e)))?;
+
+		let mut writer = self.operator.writer(target).await?;
+
+		let mut uploaded = 0;
+		let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+		loop {
+			let read = file
+				.read(&mut buf)
+				.await
+				.map_err(|e| FileIOError::from((source, e)))?;
+
+			if read == 0 {
+				break;
+			}
+
+			writer.write(buf[..read].to_vec()).await?;
+
+			uploaded += read as u64;
+			on_progress(uploaded);
+		}
+
+		writer.close().await?;
+
+		Ok(())
+	}
+
+	/// Downloads `source`, relative to the root of the remote, into a local file
+	pub async fn download_file(
+		&self,
+		source: &str,
+		target: impl AsRef<Path>,
+	) -> Result<(), RemoteError> {
+		let target = target.as_ref();
+
+		let mut reader = self.operator.reader(source).await?;
+		let mut file = fs::File::create(target)
+			.await
+			.map_err(|e| FileIOError::from((target, e)))?;
+
+		io::copy(&mut reader, &mut file)
+			.await
+			.map_err(|e| FileIOError::from((target, e)))?;
+
+		file.sync_all()
+			.await
+			.map_err(|e| FileIOError::from((target, e)))?;
+
+		Ok(())
+	}
+
+	pub async fn delete(&self, path: &str) -> Result<(), RemoteError> {
+		self.operator.delete(path).await.map_err(Into::into)
+	}
+
+	pub async fn create_dir(&self, path: &str) -> Result<(), RemoteError> {
+		self.operator
+			.create_dir(&dir_path(path))
+			.await
+			.map_err(Into::into)
+	}
+}
+
+/// Paths of directories end with a slash for the operators
+fn dir_path(path: &str) -> String {
+	let path = path.trim_matches('/');
+
+	if path.is_empty() {
+		"/".to_string()
+	} else {
+		format!("{path}/")
+	}
+}
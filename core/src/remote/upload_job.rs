@@ -0,0 +1,199 @@
+use crate::{
+	job::{
+		CurrentStep, FileProgress, JobError, JobInitOutput, JobReportUpdate, JobResult,
+		JobStepOutput, RetryPolicy, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	object::fs::{
+		get_location_path_from_location_id, get_many_files_datas, FileOperationResult,
+		FileOperationRunMetadata,
+	},
+	prisma::{file_path, location, remote},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use std::{
+	hash::Hash,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, time::Instant};
+use tracing::trace;
+
+use super::Remote;
+
+/// Minimum time between two progress reports of the same file
+const FILE_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Uploads files and directories of a location to a remote, keeping their structure, so the
+/// remote can be used as a backup target
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct RemoteUploaderJobInit {
+	pub remote_id: remote::id::Type,
+	pub location_id: location::id::Type,
+	pub file_path_ids: Vec<file_path::id::Type>,
+	/// Directory of the remote the files are uploaded into, relative to its root
+	pub target_dir: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RemoteUploaderJobStep {
+	source: PathBuf,
+	target: String,
+	is_dir: bool,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for RemoteUploaderJobInit {
+	type Data = ();
+	type Step = RemoteUploaderJobStep;
+	type RunMetadata = FileOperationRunMetadata;
+
+	const NAME: &'static str = "remote_uploader";
+	const RETRY_POLICY: RetryPolicy = RetryPolicy::TRANSIENT;
+
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(format!("remote:{}", self.remote_id))
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &ctx.library;
+
+		let target_dir = init.target_dir.trim_matches('/');
+
+		let steps = get_many_files_datas(
+			db,
+			get_location_path_from_location_id(db, init.location_id).await?,
+			&init.file_path_ids,
+		)
+		.await?
+		.into_iter()
+		.map(|file_data| {
+			let name = file_data
+				.full_path
+				.file_name()
+				.map(|name| name.to_string_lossy().to_string())
+				.unwrap_or_default();
+
+			Ok(RemoteUploaderJobStep {
+				is_dir: maybe_missing(file_data.file_path.is_dir, "file_path.is_dir")?,
+				source: file_data.full_path,
+				target: if target_dir.is_empty() {
+					name
+				} else {
+					format!("{target_dir}/{name}")
+				},
+			})
+		})
+		.collect::<Result<Vec<_>, JobError>>()?;
+
+		// Must fill in the data, otherwise the job will not run
+		*data = Some(());
+
+		Ok(steps.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let remote = Remote::from_db(&ctx.library.db, self.remote_id).await?;
+
+		if step.is_dir {
+			remote.create_dir(&step.target).await?;
+
+			let mut more_steps = Vec::new();
+
+			let mut read_dir = fs::read_dir(&step.source)
+				.await
+				.map_err(|e| FileIOError::from((&step.source, e)))?;
+
+			while let Some(entry) = read_dir
+				.next_entry()
+				.await
+				.map_err(|e| FileIOError::from((&step.source, e)))?
+			{
+				let path = entry.path();
+
+				more_steps.push(RemoteUploaderJobStep {
+					is_dir: entry
+						.metadata()
+						.await
+						.map_err(|e| FileIOError::from((&path, e)))?
+						.is_dir(),
+					target: format!("{}/{}", step.target, entry.file_name().to_string_lossy()),
+					source: path,
+				});
+			}
+
+			return Ok(more_steps.into());
+		}
+
+		if ctx.is_canceled() {
+			return Err(JobError::StepCanceled);
+		}
+
+		trace!("Uploading {} to {}", step.source.display(), step.target);
+
+		upload_with_progress(ctx, &remote, &step.source, &step.target).await?;
+
+		Ok(
+			FileOperationRunMetadata::single(&step.source, &step.target, FileOperationResult::Done)
+				.into(),
+		)
+	}
+
+	async fn finalize(
+		&self,
+		_: &WorkerContext,
+		_data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		Ok(Some(json!({
+			"init": self,
+			"uploaded_files": run_metadata.files.len(),
+		})))
+	}
+}
+
+async fn upload_with_progress(
+	ctx: &WorkerContext,
+	remote: &Remote,
+	source: &Path,
+	target: &str,
+) -> Result<(), JobError> {
+	let bytes_total = fs::metadata(source)
+		.await
+		.map_err(|e| FileIOError::from((source, e)))?
+		.len();
+
+	let mut last_report = Instant::now();
+	remote
+		.upload_file(source, target, |bytes_done| {
+			if last_report.elapsed() >= FILE_PROGRESS_INTERVAL {
+				ctx.progress(vec![JobReportUpdate::FileProgress(Some(FileProgress {
+					path: source.to_path_buf(),
+					bytes_done,
+					bytes_total,
+				}))]);
+				last_report = Instant::now();
+			}
+		})
+		.await?;
+
+	ctx.progress(vec![JobReportUpdate::FileProgress(None)]);
+
+	Ok(())
+}
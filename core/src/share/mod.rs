@@ -0,0 +1,375 @@
+//! Links to a file or directory of a library that anyone given them can open in a browser. They
+//! go through a relay the node keeps a connection to, so the node doesn't need to be reachable
+//! from the internet, and can expire or be limited to a number of downloads.
+
+use crate::{
+	library::Library,
+	node::NodeConfig,
+	prisma::{file_path, location, object, share_link},
+	util::{
+		db::{maybe_missing, uuid_to_bytes, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use uuid::Uuid;
+
+mod password;
+mod relay;
+mod serve;
+
+use password::hash_share_password;
+
+pub use relay::*;
+pub use serve::*;
+
+#[derive(Error, Debug)]
+pub enum ShareLinkError {
+	#[error("share link not found <id='{0}'>")]
+	NotFound(Uuid),
+	#[error("file path not found <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("object <id='{0}'> has no file on this node")]
+	NoLocalFile(object::id::Type),
+	#[error("download limit must be at least 1")]
+	InvalidDownloadLimit,
+	#[error("expiry date is in the past")]
+	AlreadyExpired,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("the password can't be empty")]
+	EmptyPassword,
+	#[error("failed to hash password: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("password hashing task failed: {0}")]
+	Join(#[from] tokio::task::JoinError),
+}
+
+impl From<ShareLinkError> for rspc::Error {
+	fn from(err: ShareLinkError) -> Self {
+		match err {
+			ShareLinkError::NotFound(_)
+			| ShareLinkError::FilePathNotFound(_)
+			| ShareLinkError::NoLocalFile(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			ShareLinkError::InvalidDownloadLimit
+			| ShareLinkError::AlreadyExpired
+			| ShareLinkError::EmptyPassword => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+share_link::include!(share_link_with_file_path {
+	file_path: select { name extension is_dir }
+});
+
+/// What a new link shares
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(tag = "type", content = "id", rename_all = "camelCase")]
+pub enum ShareTarget {
+	/// A file or directory
+	FilePath(file_path::id::Type),
+	/// Any of the files of an object reachable from this node
+	Object(object::id::Type),
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLink {
+	pub id: Uuid,
+	/// Not available while no relay is set up on the node
+	pub url: Option<String>,
+	pub file_path_id: file_path::id::Type,
+	pub name: String,
+	pub is_dir: bool,
+	pub expires_at: Option<DateTime<FixedOffset>>,
+	pub max_downloads: Option<i32>,
+	pub downloads: i32,
+	pub has_password: bool,
+	/// Whether what's tagged as sensitive is left out of a shared directory
+	pub hide_sensitive: bool,
+	pub date_created: DateTime<FixedOffset>,
+}
+
+impl ShareLink {
+	fn new(
+		link: share_link_with_file_path::Data,
+		library_id: Uuid,
+		node_config: &NodeConfig,
+	) -> Self {
+		let file_path = link.file_path;
+		let name = match (file_path.name, file_path.extension) {
+			(Some(name), Some(extension)) if !extension.is_empty() => format!("{name}.{extension}"),
+			(name, _) => name.unwrap_or_default(),
+		};
+
+		Self {
+			id: Uuid::from_slice(&link.pub_id).unwrap_or_default(),
+			url: share_link_url(node_config, library_id, &link.token),
+			file_path_id: link.file_path_id,
+			name,
+			is_dir: file_path.is_dir.unwrap_or(false),
+			expires_at: link.expires_at,
+			max_downloads: link.max_downloads,
+			downloads: link.downloads,
+			has_password: link.password_hash.is_some(),
+			hide_sensitive: link.hide_sensitive,
+			date_created: link.date_created,
+		}
+	}
+}
+
+/// Public url of a link, on the relay set up on the node
+fn share_link_url(node_config: &NodeConfig, library_id: Uuid, token: &str) -> Option<String> {
+	node_config.share_relay.as_ref().map(|relay| {
+		format!(
+			"{}/s/{}/{library_id}/{token}",
+			relay.url.trim_end_matches('/'),
+			node_config.id
+		)
+	})
+}
+
+/// Generates the secret part of the url of a new link
+fn generate_share_token() -> String {
+	format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub async fn create_share_link(
+	library: &Library,
+	node_config: &NodeConfig,
+	target: ShareTarget,
+	expires_at: Option<DateTime<Utc>>,
+	max_downloads: Option<i32>,
+	password: Option<String>,
+	hide_sensitive: bool,
+) -> Result<ShareLink, ShareLinkError> {
+	validate_limits(expires_at, max_downloads)?;
+
+	let password = match password {
+		Some(password) => Some(hash_password(password).await?),
+		None => None,
+	};
+
+	let file_path_id = match target {
+		ShareTarget::FilePath(id) => {
+			library
+				.db
+				.file_path()
+				.find_unique(file_path::id::equals(id))
+				.select(file_path::select!({ id }))
+				.exec()
+				.await?
+				.ok_or(ShareLinkError::FilePathNotFound(id))?
+				.id
+		}
+		ShareTarget::Object(id) => {
+			library
+				.db
+				.file_path()
+				.find_first(vec![
+					file_path::object_id::equals(Some(id)),
+					file_path::date_deleted::equals(None),
+					file_path::location::is(vec![location::node_id::equals(Some(
+						library.node_local_id,
+					))]),
+				])
+				.select(file_path::select!({ id }))
+				.exec()
+				.await?
+				.ok_or(ShareLinkError::NoLocalFile(id))?
+				.id
+		}
+	};
+
+	let link = library
+		.db
+		.share_link()
+		.create(
+			uuid_to_bytes(Uuid::new_v4()),
+			generate_share_token(),
+			file_path::id::equals(file_path_id),
+			vec![
+				share_link::expires_at::set(expires_at.map(Into::into)),
+				share_link::max_downloads::set(max_downloads),
+				share_link::hide_sensitive::set(hide_sensitive),
+				share_link::session_secret::set(Some(generate_share_token())),
+			],
+		)
+		.include(share_link_with_file_path::include())
+		.exec()
+		.await?;
+
+	Ok(ShareLink::new(link, library.id, node_config))
+}
+
+fn validate_limits(
+	expires_at: Option<DateTime<Utc>>,
+	max_downloads: Option<i32>,
+) -> Result<(), ShareLinkError> {
+	if max_downloads.map_or(false, |max| max < 1) {
+		return Err(ShareLinkError::InvalidDownloadLimit);
+	}
+
+	if expires_at.map_or(false, |expires_at| expires_at <= Utc::now()) {
+		return Err(ShareLinkError::AlreadyExpired);
+	}
+
+	Ok(())
+}
+
+async fn hash_password(password: String) -> Result<(Vec<u8>, Vec<u8>), ShareLinkError> {
+	if password.is_empty() {
+		return Err(ShareLinkError::EmptyPassword);
+	}
+
+	hash_share_password(password).await
+}
+
+/// Changes when a link expires and how many times it can be downloaded, the downloads already made
+/// counting towards the new limit
+pub async fn update_share_link_limits(
+	library: &Library,
+	node_config: &NodeConfig,
+	id: Uuid,
+	expires_at: Option<DateTime<Utc>>,
+	max_downloads: Option<i32>,
+) -> Result<ShareLink, ShareLinkError> {
+	validate_limits(expires_at, max_downloads)?;
+
+	update_share_link(
+		library,
+		node_config,
+		id,
+		vec![
+			share_link::expires_at::set(expires_at.map(Into::into)),
+			share_link::max_downloads::set(max_downloads),
+		],
+	)
+	.await
+}
+
+/// Sets or removes the password of a link. Browsers that were given the previous one have to be
+/// given the new one.
+pub async fn set_share_link_password(
+	library: &Library,
+	node_config: &NodeConfig,
+	id: Uuid,
+	password: Option<String>,
+) -> Result<ShareLink, ShareLinkError> {
+	let (hash, salt) = match password {
+		Some(password) => {
+			let (hash, salt) = hash_password(password).await?;
+			(Some(hash), Some(salt))
+		}
+		None => (None, None),
+	};
+
+	update_share_link(
+		library,
+		node_config,
+		id,
+		vec![
+			share_link::password_hash::set(hash),
+			share_link::password_salt::set(salt),
+		],
+	)
+	.await
+}
+
+async fn update_share_link(
+	library: &Library,
+	node_config: &NodeConfig,
+	id: Uuid,
+	params: Vec<share_link::SetParam>,
+) -> Result<ShareLink, ShareLinkError> {
+	let pub_id = uuid_to_bytes(id);
+
+	if library
+		.db
+		.share_link()
+		.count(vec![share_link::pub_id::equals(pub_id.clone())])
+		.exec()
+		.await?
+		== 0
+	{
+		return Err(ShareLinkError::NotFound(id));
+	}
+
+	let link = library
+		.db
+		.share_link()
+		.update(share_link::pub_id::equals(pub_id), params)
+		.include(share_link_with_file_path::include())
+		.exec()
+		.await?;
+
+	Ok(ShareLink::new(link, library.id, node_config))
+}
+
+pub async fn list_share_links(
+	library: &Library,
+	node_config: &NodeConfig,
+) -> Result<Vec<ShareLink>, ShareLinkError> {
+	Ok(library
+		.db
+		.share_link()
+		.find_many(vec![])
+		.include(share_link_with_file_path::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|link| ShareLink::new(link, library.id, node_config))
+		.collect())
+}
+
+/// Deletes a link, which can't be opened anymore
+pub async fn revoke_share_link(library: &Library, id: Uuid) -> Result<(), ShareLinkError> {
+	library
+		.db
+		.share_link()
+		.delete_many(vec![share_link::pub_id::equals(uuid_to_bytes(id))])
+		.exec()
+		.await
+		.map_err(Into::into)
+		.and_then(|deleted| {
+			(deleted > 0)
+				.then_some(())
+				.ok_or(ShareLinkError::NotFound(id))
+		})
+}
+
+/// Whether a link can still be opened
+fn is_active(
+	expires_at: Option<DateTime<FixedOffset>>,
+	max_downloads: Option<i32>,
+	downloads: i32,
+) -> bool {
+	!is_expired(expires_at) && max_downloads.map_or(true, |max| downloads < max)
+}
+
+fn is_expired(expires_at: Option<DateTime<FixedOffset>>) -> bool {
+	expires_at.map_or(false, |expires_at| expires_at <= Utc::now())
+}
+
+fn full_name(name: Option<&str>, extension: Option<&str>) -> Result<String, MissingFieldError> {
+	let name = maybe_missing(name, "file_path.name")?;
+
+	Ok(match extension {
+		Some(extension) if !extension.is_empty() => format!("{name}.{extension}"),
+		_ => name.to_string(),
+	})
+}
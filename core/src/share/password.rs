@@ -0,0 +1,161 @@
+use sd_crypto::{
+	types::{HashingAlgorithm, Params, Salt},
+	Protected,
+};
+
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+use tokio::task::spawn_blocking;
+
+use super::ShareLinkError;
+
+const HASHING_ALGORITHM: HashingAlgorithm = HashingAlgorithm::Argon2id(Params::Standard);
+
+/// Hashes the password of a link with a new salt, returning both
+pub(super) async fn hash_share_password(
+	password: String,
+) -> Result<(Vec<u8>, Vec<u8>), ShareLinkError> {
+	let salt = Salt::generate();
+
+	let hash = hash_with_salt(password, salt).await?;
+
+	Ok((hash, salt.0.to_vec()))
+}
+
+async fn hash_with_salt(password: String, salt: Salt) -> Result<Vec<u8>, ShareLinkError> {
+	// Argon2 is meant to be slow, so it's kept away from the async runtime
+	spawn_blocking(move || {
+		HASHING_ALGORITHM
+			.hash(Protected::new(password.into_bytes()), salt, None)
+			.map(|key| key.expose().to_vec())
+	})
+	.await?
+	.map_err(Into::into)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub(super) async fn verify_share_password(
+	password: String,
+	hash: &[u8],
+	salt: &[u8],
+) -> Result<bool, ShareLinkError> {
+	let Ok(salt) = salt.try_into().map(Salt) else {
+		return Ok(false);
+	};
+
+	Ok(constant_time_eq(
+		&hash_with_salt(password, salt).await?,
+		hash,
+	))
+}
+
+/// Value of the cookie proving the password of a link was given, so it isn't hashed again on each
+/// request. Changing the password changes it.
+pub(super) fn access_cookie_value(password_hash: &[u8], token: &str) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(password_hash);
+	hasher.update(token.as_bytes());
+	hex::encode(hasher.finalize())
+}
+
+/// Value of the cookie holding the session of a client, signed with the secret of the link so
+/// sessions made up by clients are told apart from the ones issued
+pub(super) fn session_cookie_value(session_secret: &str, session: &str) -> String {
+	format!("{session}.{}", sign_session(session_secret, session))
+}
+
+/// Session held by a cookie, if it was issued with the secret of the link
+pub(super) fn issued_session<'a>(session_secret: &str, cookie_value: &'a str) -> Option<&'a str> {
+	let (session, signature) = cookie_value.split_once('.')?;
+
+	constant_time_eq(
+		signature.as_bytes(),
+		sign_session(session_secret, session).as_bytes(),
+	)
+	.then_some(session)
+}
+
+fn sign_session(session_secret: &str, session: &str) -> String {
+	let mut mac = Hmac::<Sha256>::new_from_slice(session_secret.as_bytes())
+		.expect("HMAC accepts keys of any size");
+	mac.update(session.as_bytes());
+
+	hex::encode(mac.finalize().into_bytes())
+}
+
+/// Value of a cookie in the `Cookie` header of a request
+pub(super) fn cookie<'a>(cookie_header: Option<&'a str>, name: &str) -> Option<&'a str> {
+	cookie_header?.split(';').find_map(|cookie| {
+		let (cookie_name, cookie_value) = cookie.trim().split_once('=')?;
+		(cookie_name == name).then_some(cookie_value)
+	})
+}
+
+/// Whether the `Cookie` header of a request holds the given cookie
+pub(super) fn has_cookie(cookie_header: Option<&str>, name: &str, value: &str) -> bool {
+	cookie(cookie_header, name).map_or(false, |cookie_value| {
+		constant_time_eq(cookie_value.as_bytes(), value.as_bytes())
+	})
+}
+
+/// Value of a field of an `application/x-www-form-urlencoded` body
+pub(super) fn form_field(body: &str, name: &str) -> Option<String> {
+	body.split('&').find_map(|pair| {
+		let (key, value) = pair.split_once('=')?;
+		(key == name).then(|| {
+			percent_decode_str(&value.replace('+', " "))
+				.decode_utf8_lossy()
+				.into_owned()
+		})
+	})
+}
+
+pub(super) fn password_form(wrong_password: bool) -> String {
+	format!(
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Password required</title></head><body>\
+		<form method=\"post\"><p>This link is protected by a password.</p>{}\
+		<input type=\"password\" name=\"password\" autofocus required> <button type=\"submit\">Open</button>\
+		</form></body></html>",
+		if wrong_password {
+			"<p>Wrong password, try again.</p>"
+		} else {
+			""
+		}
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cookies_and_form_fields() {
+		assert!(has_cookie(Some("a=1; sd_share_x=abc"), "sd_share_x", "abc"));
+		assert!(!has_cookie(Some("sd_share_x=abd"), "sd_share_x", "abc"));
+		assert!(!has_cookie(None, "sd_share_x", "abc"));
+		assert_eq!(cookie(Some("a=1; b=2"), "b"), Some("2"));
+		assert_eq!(cookie(Some("a=1"), "b"), None);
+
+		assert_eq!(
+			form_field("other=1&password=hello+w%C3%B6rld%21", "password").as_deref(),
+			Some("hello wörld!")
+		);
+		assert_eq!(form_field("other=1", "password"), None);
+	}
+
+	#[test]
+	fn only_issued_sessions() {
+		let value = session_cookie_value("secret", "session");
+		assert_eq!(issued_session("secret", &value), Some("session"));
+		assert_eq!(issued_session("other secret", &value), None);
+		assert_eq!(issued_session("secret", "session"), None);
+		assert_eq!(
+			issued_session("secret", &value.replacen("session", "forged", 1)),
+			None
+		);
+	}
+}
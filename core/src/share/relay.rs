@@ -0,0 +1,165 @@
+//! Client of the relay that share links go through. The node asks the relay for the requests made
+//! to its links with long polling, and posts back each response, so it only ever makes outgoing
+//! connections.
+//!
+//! - `GET {relay}/api/v1/nodes/{node_id}/requests` waits for the next request made to the links of
+//!   the node, answering with a [`ShareRequest`] and its id, or with no content after a while. The
+//!   relay forwards the `Cookie` header and the form body of password submissions with it
+//! - `POST {relay}/api/v1/requests/{id}/response` streams back the response, whose status and
+//!   headers are sent as `X-Relay-Status` and `X-Relay-Header-*` headers
+
+use crate::{library::LibraryManager, node::NodeConfigManager};
+
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{Body, Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncReadExt, time::sleep};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::{handle_share_request, ShareBody, ShareRequest};
+
+/// How long the relay may hold a poll before answering that there's no request
+const POLL_TIMEOUT: Duration = Duration::from_secs(65);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before polling again after the relay couldn't be reached
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How often the node config is checked for a relay while none is set up
+const DISABLED_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Relay the share links of the node go through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareRelayConfig {
+	/// eg: `https://relay.example.com`
+	pub url: String,
+	/// Token the relay authenticates the node with, if it asks for one
+	#[serde(default)]
+	pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RelayedRequest {
+	id: Uuid,
+	#[serde(flatten)]
+	request: ShareRequest,
+}
+
+fn relay_url(relay: &ShareRelayConfig, path: &str) -> String {
+	format!("{}/api/v1/{path}", relay.url.trim_end_matches('/'))
+}
+
+fn authorized(req: RequestBuilder, relay: &ShareRelayConfig) -> RequestBuilder {
+	match &relay.token {
+		Some(token) => req.bearer_auth(token),
+		None => req,
+	}
+}
+
+async fn next_request(
+	client: &Client,
+	relay: &ShareRelayConfig,
+	node_id: Uuid,
+) -> Result<Option<RelayedRequest>, reqwest::Error> {
+	let res = authorized(
+		client.get(relay_url(relay, &format!("nodes/{node_id}/requests"))),
+		relay,
+	)
+	.timeout(POLL_TIMEOUT)
+	.send()
+	.await?
+	.error_for_status()?;
+
+	if res.status() == StatusCode::NO_CONTENT {
+		return Ok(None);
+	}
+
+	res.json().await.map(Some)
+}
+
+async fn respond(
+	client: Client,
+	relay: ShareRelayConfig,
+	library_manager: Arc<LibraryManager>,
+	relayed: RelayedRequest,
+) {
+	let response = handle_share_request(&library_manager, &relayed.request).await;
+
+	let mut req = authorized(
+		client.post(relay_url(
+			&relay,
+			&format!("requests/{}/response", relayed.id),
+		)),
+		&relay,
+	)
+	.header("X-Relay-Status", response.status);
+
+	for (name, value) in response.headers {
+		req = req.header(format!("X-Relay-Header-{name}"), value);
+	}
+
+	let body = match response.body {
+		ShareBody::Bytes(bytes) => Body::from(bytes),
+		ShareBody::File { file, length } => Body::wrap_stream(ReaderStream::new(file.take(length))),
+	};
+
+	match req
+		.body(body)
+		.send()
+		.await
+		.and_then(|res| res.error_for_status())
+	{
+		Ok(_) => debug!(
+			"Answered relayed request <id='{}'> to '{}' with status {}",
+			relayed.id, relayed.request.path, response.status
+		),
+		Err(e) => warn!(
+			"Failed to answer relayed request <id='{}'>: {e}",
+			relayed.id
+		),
+	}
+}
+
+/// Spawns the loop that serves the requests made to the share links of the node through the relay
+/// set up in its config, if any
+pub fn spawn_share_relay_client(
+	config: Arc<NodeConfigManager>,
+	library_manager: Arc<LibraryManager>,
+) {
+	tokio::spawn(async move {
+		// No overall timeout, as responses can be whole files
+		let client = match Client::builder().connect_timeout(CONNECT_TIMEOUT).build() {
+			Ok(client) => client,
+			Err(e) => {
+				error!("Failed to build the share relay HTTP client: {e:#?}");
+				return;
+			}
+		};
+
+		loop {
+			let node_config = config.get().await;
+			let Some(relay) = node_config.share_relay else {
+				sleep(DISABLED_CHECK_INTERVAL).await;
+				continue;
+			};
+
+			match next_request(&client, &relay, node_config.id).await {
+				Ok(Some(relayed)) => {
+					tokio::spawn(respond(
+						client.clone(),
+						relay,
+						library_manager.clone(),
+						relayed,
+					));
+				}
+				Ok(None) => {}
+				Err(e) => {
+					warn!("Failed to poll the share relay at '{}': {e}", relay.url);
+					sleep(RETRY_DELAY).await;
+				}
+			}
+		}
+	});
+}
@@ -0,0 +1,661 @@
+use crate::{
+	custom_uri::mime_type,
+	library::{Library, LibraryManager},
+	object::sensitive::not_sensitive,
+	prisma::{file_path, location, share_link, share_link_download},
+	util::{
+		db::{chain_optional_iter, maybe_missing, MissingFieldError},
+		error::FileIOError,
+	},
+};
+
+use std::{io, path::Path, str::FromStr};
+
+use http_range::HttpRange;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use prisma_client_rust::{or, raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	fs::File,
+	io::{AsyncSeekExt, SeekFrom},
+};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{
+	full_name, is_active, is_expired,
+	password::{
+		access_cookie_value, cookie, form_field, has_cookie, issued_session, password_form,
+		session_cookie_value, verify_share_password,
+	},
+	ShareLinkError,
+};
+
+share_link::include!(share_link_to_serve {
+	file_path: select {
+		is_dir
+		materialized_path
+		name
+		extension
+		date_quarantined
+		location: select { id path node_id }
+	}
+});
+
+/// Cookie holding the random id of a client, signed by the node, so its downloads are counted
+/// once per file
+const SESSION_COOKIE: &str = "sd_share_session";
+
+file_path::select!(file_path_to_share {
+	is_dir
+	materialized_path
+	name
+	extension
+});
+
+/// A request made to a share link, as forwarded by the relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareRequest {
+	pub method: String,
+	/// `/{library_id}/{token}`, followed by the path of a file when a directory is shared
+	pub path: String,
+	#[serde(default)]
+	pub range: Option<String>,
+	/// `Cookie` header, holding the proof that the password of the link was given
+	#[serde(default)]
+	pub cookie: Option<String>,
+	/// Form sent to give the password of the link
+	#[serde(default)]
+	pub body: Option<String>,
+}
+
+pub enum ShareBody {
+	Bytes(Vec<u8>),
+	/// A file already seeked to the start of the range to send
+	File {
+		file: File,
+		length: u64,
+	},
+}
+
+pub struct ShareResponse {
+	pub status: u16,
+	pub headers: Vec<(&'static str, String)>,
+	pub body: ShareBody,
+}
+
+impl ShareResponse {
+	fn text(status: u16, message: &str) -> Self {
+		Self {
+			status,
+			headers: vec![("Content-Type", "text/plain; charset=utf-8".to_string())],
+			body: ShareBody::Bytes(message.as_bytes().to_vec()),
+		}
+	}
+}
+
+enum ServeError {
+	BadRequest(&'static str),
+	NotFound,
+	/// The link expired or reached its download limit
+	Gone,
+	RangeNotSatisfiable,
+	Internal(ShareLinkError),
+}
+
+fn password_required(wrong_password: bool) -> ShareResponse {
+	ShareResponse {
+		status: 401,
+		headers: vec![("Content-Type", "text/html; charset=utf-8".to_string())],
+		body: ShareBody::Bytes(password_form(wrong_password).into_bytes()),
+	}
+}
+
+impl From<ShareLinkError> for ServeError {
+	fn from(err: ShareLinkError) -> Self {
+		Self::Internal(err)
+	}
+}
+
+impl From<prisma_client_rust::QueryError> for ServeError {
+	fn from(err: prisma_client_rust::QueryError) -> Self {
+		Self::Internal(err.into())
+	}
+}
+
+impl From<MissingFieldError> for ServeError {
+	fn from(err: MissingFieldError) -> Self {
+		Self::Internal(err.into())
+	}
+}
+
+impl From<FileIOError> for ServeError {
+	fn from(err: FileIOError) -> Self {
+		Self::Internal(err.into())
+	}
+}
+
+impl From<ServeError> for ShareResponse {
+	fn from(err: ServeError) -> Self {
+		match err {
+			ServeError::BadRequest(message) => Self::text(400, message),
+			ServeError::NotFound => Self::text(404, "Not found"),
+			ServeError::Gone => Self::text(410, "This link expired"),
+			ServeError::RangeNotSatisfiable => Self::text(416, "Range not satisfiable"),
+			ServeError::Internal(e) => {
+				error!("Failed to serve share link: {e:#?}");
+				Self::text(500, "Internal server error")
+			}
+		}
+	}
+}
+
+/// Serves a request made to a share link: the shared file, the listing of a shared directory or a
+/// file in it
+pub async fn handle_share_request(
+	library_manager: &LibraryManager,
+	req: &ShareRequest,
+) -> ShareResponse {
+	serve(library_manager, req).await.unwrap_or_else(Into::into)
+}
+
+async fn serve(
+	library_manager: &LibraryManager,
+	req: &ShareRequest,
+) -> Result<ShareResponse, ServeError> {
+	if !matches!(req.method.as_str(), "GET" | "HEAD" | "POST") {
+		return Err(ServeError::BadRequest(
+			"Only GET, HEAD and POST requests are allowed",
+		));
+	}
+
+	let raw_segments = req
+		.path
+		.trim_start_matches('/')
+		.split('/')
+		.collect::<Vec<_>>();
+	let segments = raw_segments
+		.iter()
+		.filter(|segment| !segment.is_empty())
+		.map(|segment| {
+			percent_decode_str(segment)
+				.decode_utf8()
+				.map(|segment| segment.into_owned())
+				.map_err(|_| ServeError::BadRequest("Invalid path"))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let (library_id, token, sub_path) = match segments.as_slice() {
+		[library_id, token, sub_path @ ..] => (
+			Uuid::from_str(library_id).map_err(|_| ServeError::NotFound)?,
+			token,
+			sub_path,
+		),
+		_ => return Err(ServeError::NotFound),
+	};
+
+	if sub_path
+		.iter()
+		.any(|segment| segment == "." || segment == ".." || segment.contains('\\'))
+	{
+		return Err(ServeError::BadRequest("Invalid path"));
+	}
+
+	let library = library_manager
+		.get_library(library_id)
+		.await
+		.ok_or(ServeError::NotFound)?;
+
+	let link = library
+		.db
+		.share_link()
+		.find_unique(share_link::token::equals(token.clone()))
+		.include(share_link_to_serve::include())
+		.exec()
+		.await?
+		.ok_or(ServeError::NotFound)?;
+
+	// The download limit is checked when serving files, so clients that already downloaded one
+	// can keep asking for ranges of it
+	if is_expired(link.expires_at) {
+		return Err(ServeError::Gone);
+	}
+
+	// Quarantined files aren't served, be it for a failed integrity check or the antivirus
+	if link.file_path.date_quarantined.is_some() {
+		return Err(ServeError::NotFound);
+	}
+
+	if let (Some(hash), Some(salt)) = (&link.password_hash, &link.password_salt) {
+		let cookie_name = format!(
+			"sd_share_{}",
+			Uuid::from_slice(&link.pub_id).unwrap_or_default().simple()
+		);
+		let cookie_value = access_cookie_value(hash, &link.token);
+
+		if req.method == "POST" {
+			let password = req
+				.body
+				.as_deref()
+				.and_then(|body| form_field(body, "password"))
+				.unwrap_or_default();
+
+			if !verify_share_password(password, hash, salt).await? {
+				return Ok(password_required(true));
+			}
+
+			// Back to the same url, with the cookie saying the password was given
+			return Ok(ShareResponse {
+				status: 303,
+				headers: vec![
+					(
+						"Location",
+						if req.path.ends_with('/') {
+							".".to_string()
+						} else {
+							raw_segments.last().copied().unwrap_or_default().to_string()
+						},
+					),
+					(
+						"Set-Cookie",
+						format!(
+							"{cookie_name}={cookie_value}; Path=/; HttpOnly; Secure; SameSite=Lax"
+						),
+					),
+				],
+				body: ShareBody::Bytes(vec![]),
+			});
+		}
+
+		if !has_cookie(req.cookie.as_deref(), &cookie_name, &cookie_value) {
+			return Ok(password_required(false));
+		}
+	} else if req.method == "POST" {
+		return Err(ServeError::BadRequest(
+			"This link isn't protected by a password",
+		));
+	}
+
+	let location = maybe_missing(&link.file_path.location, "file_path.location")?;
+	if location.node_id != Some(library.node_local_id) {
+		return Err(ServeError::NotFound);
+	}
+	let location_path = Path::new(maybe_missing(&location.path, "location.path")?);
+
+	let shared = &link.file_path;
+	let shared_materialized_path =
+		maybe_missing(&shared.materialized_path, "file_path.materialized_path")?;
+
+	if !shared.is_dir.unwrap_or(false) {
+		if !sub_path.is_empty() {
+			return Err(ServeError::NotFound);
+		}
+
+		let full_path = location_path
+			.join(shared_materialized_path.trim_start_matches('/'))
+			.join(full_name(
+				shared.name.as_deref(),
+				shared.extension.as_deref(),
+			)?);
+
+		return serve_file(
+			&library,
+			&link,
+			req,
+			&full_path,
+			shared.extension.as_deref(),
+		)
+		.await;
+	}
+
+	// Walking down from the shared directory to the file or directory asked for
+	let mut children_path = format!(
+		"{shared_materialized_path}{}/",
+		maybe_missing(&shared.name, "file_path.name")?
+	);
+	let mut target = None;
+
+	for (idx, segment) in sub_path.iter().enumerate() {
+		let entry = find_child(
+			&library,
+			location.id,
+			&children_path,
+			segment,
+			link.hide_sensitive,
+		)
+		.await?
+		.ok_or(ServeError::NotFound)?;
+
+		let is_dir = entry.is_dir.unwrap_or(false);
+		if is_dir {
+			children_path.push_str(segment);
+			children_path.push('/');
+		} else if idx != sub_path.len() - 1 {
+			return Err(ServeError::NotFound);
+		}
+
+		target = Some(entry);
+	}
+
+	match target {
+		Some(entry) if !entry.is_dir.unwrap_or(false) => {
+			let full_path = location_path
+				.join(
+					maybe_missing(&entry.materialized_path, "file_path.materialized_path")?
+						.trim_start_matches('/'),
+				)
+				.join(full_name(
+					entry.name.as_deref(),
+					entry.extension.as_deref(),
+				)?);
+
+			serve_file(&library, &link, req, &full_path, entry.extension.as_deref()).await
+		}
+		_ => {
+			if !is_active(link.expires_at, link.max_downloads, link.downloads) {
+				return Err(ServeError::Gone);
+			}
+
+			// Links of the listing are relative, so they work whatever the prefix of the relay
+			let base = if req.path.ends_with('/') {
+				String::new()
+			} else {
+				format!("{}/", raw_segments.last().copied().unwrap_or_default())
+			};
+
+			list_directory(
+				&library,
+				location.id,
+				&children_path,
+				&base,
+				&segments[2..],
+				link.hide_sensitive,
+			)
+			.await
+		}
+	}
+}
+
+/// Leaves the files tagged as sensitive out of a shared directory, for the links hiding them
+fn hide_sensitive_param(hide_sensitive: bool) -> Option<file_path::WhereParam> {
+	hide_sensitive.then(|| {
+		or![
+			file_path::object_id::equals(None),
+			file_path::object::is(vec![not_sensitive()])
+		]
+	})
+}
+
+async fn find_child(
+	library: &Library,
+	location_id: location::id::Type,
+	materialized_path: &str,
+	name: &str,
+	hide_sensitive: bool,
+) -> Result<Option<file_path_to_share::Data>, ServeError> {
+	// Names are split from their extensions on the last dot, if there's one
+	let stem = match name.rsplit_once('.') {
+		Some((stem, _)) if !stem.is_empty() => stem,
+		_ => name,
+	};
+
+	let mut candidates = library
+		.db
+		.file_path()
+		.find_many(chain_optional_iter(
+			[
+				file_path::location_id::equals(Some(location_id)),
+				file_path::materialized_path::equals(Some(materialized_path.to_string())),
+				file_path::date_deleted::equals(None),
+				file_path::date_quarantined::equals(None),
+				file_path::name::in_vec(vec![stem.to_string(), name.to_string()]),
+			],
+			[hide_sensitive_param(hide_sensitive)],
+		))
+		.select(file_path_to_share::select())
+		.exec()
+		.await?;
+
+	candidates.retain(|candidate| {
+		full_name(candidate.name.as_deref(), candidate.extension.as_deref())
+			.map_or(false, |full_name| full_name == name)
+	});
+
+	Ok(candidates.pop())
+}
+
+fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+async fn list_directory(
+	library: &Library,
+	location_id: location::id::Type,
+	children_path: &str,
+	base: &str,
+	sub_path: &[String],
+	hide_sensitive: bool,
+) -> Result<ShareResponse, ServeError> {
+	let mut children = library
+		.db
+		.file_path()
+		.find_many(chain_optional_iter(
+			[
+				file_path::location_id::equals(Some(location_id)),
+				file_path::materialized_path::equals(Some(children_path.to_string())),
+				file_path::date_deleted::equals(None),
+				file_path::date_quarantined::equals(None),
+			],
+			[hide_sensitive_param(hide_sensitive)],
+		))
+		.select(file_path_to_share::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|child| {
+			let name = full_name(child.name.as_deref(), child.extension.as_deref()).ok()?;
+			Some((child.is_dir.unwrap_or(false), name))
+		})
+		.collect::<Vec<_>>();
+
+	// Directories first, then by name
+	children.sort_by(|(a_is_dir, a), (b_is_dir, b)| {
+		b_is_dir
+			.cmp(a_is_dir)
+			.then_with(|| a.to_lowercase().cmp(&b.to_lowercase()))
+	});
+
+	let title = escape_html(&format!("/{}", sub_path.join("/")));
+
+	let mut html = format!(
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head><body><h1>{title}</h1><ul>"
+	);
+	if !sub_path.is_empty() {
+		html.push_str(&format!("<li><a href=\"{base}..\">..</a></li>"));
+	}
+	for (is_dir, name) in children {
+		let href = utf8_percent_encode(&name, NON_ALPHANUMERIC).to_string();
+		let slash = if is_dir { "/" } else { "" };
+		html.push_str(&format!(
+			"<li><a href=\"{base}{href}{slash}\">{}{slash}</a></li>",
+			escape_html(&name)
+		));
+	}
+	html.push_str("</ul></body></html>");
+
+	Ok(ShareResponse {
+		status: 200,
+		headers: vec![("Content-Type", "text/html; charset=utf-8".to_string())],
+		body: ShareBody::Bytes(html.into_bytes()),
+	})
+}
+
+async fn serve_file(
+	library: &Library,
+	link: &share_link_to_serve::Data,
+	req: &ShareRequest,
+	full_path: &Path,
+	extension: Option<&str>,
+) -> Result<ShareResponse, ServeError> {
+	let mut file = File::open(full_path).await.map_err(|e| {
+		if e.kind() == io::ErrorKind::NotFound {
+			ServeError::NotFound
+		} else {
+			FileIOError::from((full_path, e)).into()
+		}
+	})?;
+
+	let size = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((full_path, e)))?
+		.len();
+
+	let range = match (req.method.as_str(), &req.range) {
+		("GET", Some(range)) => match HttpRange::parse(range, size).as_deref() {
+			Ok([range]) => Some(*range),
+			_ => return Err(ServeError::RangeNotSatisfiable),
+		},
+		_ => None,
+	};
+
+	let file_name = full_path
+		.file_name()
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_default();
+
+	let mut headers = vec![
+		(
+			"Content-Type",
+			extension
+				.and_then(mime_type)
+				.unwrap_or("application/octet-stream")
+				.to_string(),
+		),
+		("Accept-Ranges", "bytes".to_string()),
+		(
+			"Content-Disposition",
+			format!(
+				"inline; filename*=UTF-8''{}",
+				utf8_percent_encode(&file_name, NON_ALPHANUMERIC)
+			),
+		),
+	];
+
+	if req.method == "HEAD" {
+		headers.push(("Content-Length", size.to_string()));
+		return Ok(ShareResponse {
+			status: 200,
+			headers,
+			body: ShareBody::Bytes(vec![]),
+		});
+	}
+
+	// Sessions the node didn't issue get a new one, so clients can't pick them to skip the count
+	let session_secret = maybe_missing(&link.session_secret, "share_link.session_secret")?;
+	let session = match cookie(req.cookie.as_deref(), SESSION_COOKIE)
+		.and_then(|value| issued_session(session_secret, value))
+	{
+		Some(session) => session.to_string(),
+		None => {
+			let session = Uuid::new_v4().simple().to_string();
+			headers.push((
+				"Set-Cookie",
+				format!(
+					"{SESSION_COOKIE}={}; Path=/; HttpOnly; Secure; SameSite=Lax",
+					session_cookie_value(session_secret, &session)
+				),
+			));
+			session
+		}
+	};
+
+	if !count_download(library, link.id, &session, &full_path.to_string_lossy()).await? {
+		return Err(ServeError::Gone);
+	}
+
+	let (status, start, length) = match range {
+		Some(range) => {
+			headers.push((
+				"Content-Range",
+				format!(
+					"bytes {}-{}/{size}",
+					range.start,
+					range.start + range.length - 1
+				),
+			));
+			(206, range.start, range.length)
+		}
+		None => (200, 0, size),
+	};
+	headers.push(("Content-Length", length.to_string()));
+
+	file.seek(SeekFrom::Start(start))
+		.await
+		.map_err(|e| FileIOError::from((full_path, e)))?;
+
+	Ok(ShareResponse {
+		status,
+		headers,
+		body: ShareBody::File { file, length },
+	})
+}
+
+/// Counts the download of a file by a client, once as players and download managers ask for many
+/// ranges of the same file. Returns `false` if the link reached its download limit.
+async fn count_download(
+	library: &Library,
+	link_id: share_link::id::Type,
+	session: &str,
+	path: &str,
+) -> Result<bool, ServeError> {
+	// The unique index lets a single one of the concurrent requests of a client count
+	let inserted = library
+		.db
+		._execute_raw(raw!(
+			"INSERT OR IGNORE INTO share_link_download (share_link_id, session, path) \
+				VALUES ({}, {}, {})",
+			PrismaValue::Int(link_id as i64),
+			PrismaValue::String(session.to_string()),
+			PrismaValue::String(path.to_string())
+		))
+		.exec()
+		.await?;
+
+	if inserted == 0 {
+		return Ok(true);
+	}
+
+	// Checking the limit and counting at once, so concurrent downloads can't go past it
+	let counted = library
+		.db
+		._execute_raw(raw!(
+			"UPDATE share_link SET downloads = downloads + 1 \
+				WHERE id = {} AND (max_downloads IS NULL OR downloads < max_downloads)",
+			PrismaValue::Int(link_id as i64)
+		))
+		.exec()
+		.await?;
+
+	if counted == 0 {
+		library
+			.db
+			.share_link_download()
+			.delete_many(vec![
+				share_link_download::share_link_id::equals(link_id),
+				share_link_download::session::equals(session.to_string()),
+				share_link_download::path::equals(path.to_string()),
+			])
+			.exec()
+			.await?;
+
+		return Ok(false);
+	}
+
+	Ok(true)
+}
@@ -1,12 +1,13 @@
 #![allow(clippy::unwrap_used, clippy::panic)] // TODO: Brendan remove this once you've got error handling here
 
-use crate::prisma::*;
+use crate::{library::NodeRole, prisma::*};
 
 use std::{collections::HashMap, sync::Arc};
 
 use sd_sync::*;
 
 use serde_json::{json, to_vec, Value};
+use thiserror::Error;
 use tokio::sync::broadcast::{self, Receiver, Sender};
 use uhlc::{HLCBuilder, HLC, NTP64};
 use uuid::Uuid;
@@ -27,6 +28,14 @@ pub struct SyncManager {
 	pub tx: Sender<SyncMessage>,
 }
 
+#[derive(Debug, Error)]
+pub enum IngestError {
+	#[error("node '{node}' has the {} role, which doesn't allow this change", role.as_str())]
+	NotAllowed { node: Uuid, role: NodeRole },
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+}
+
 impl SyncManager {
 	pub fn new(db: &Arc<PrismaClient>, node: Uuid) -> (Self, Receiver<SyncMessage>) {
 		let (tx, rx) = broadcast::channel(64);
@@ -162,17 +171,31 @@ impl SyncManager {
 			.collect())
 	}
 
-	pub async fn ingest_op(&self, op: CRDTOperation) -> prisma_client_rust::Result<()> {
+	pub async fn ingest_op(&self, op: CRDTOperation) -> Result<(), IngestError> {
 		let db = &self.db;
 
-		if db
+		let Some(node) = db
 			.node()
 			.find_unique(node::pub_id::equals(op.node.as_bytes().to_vec()))
 			.exec()
 			.await?
-			.is_none()
-		{
+		else {
 			panic!("Node is not paired!")
+		};
+
+		// Viewers can't change the library, and only owners can change the roles of nodes
+		let role = NodeRole::of_node(node.role.as_deref());
+		let required = match &op.typ {
+			CRDTOperationType::Shared(shared_op) if shared_op.model == node::NAME => {
+				NodeRole::Owner
+			}
+			_ => NodeRole::Editor,
+		};
+		if !role.grants(required) {
+			return Err(IngestError::NotAllowed {
+				node: op.node,
+				role,
+			});
 		}
 
 		let msg = SyncMessage::Ingested(op.clone());
@@ -303,6 +326,149 @@ impl SyncManager {
 						.await?;
 				}
 			},
+			ModelSyncData::CustomField(id, shared_op) => match shared_op {
+				SharedOperationData::Create(data) => {
+					let data: Vec<_> = data
+						.into_iter()
+						.flat_map(|(field, value)| {
+							custom_field::SetParam::deserialize(&field, value)
+						})
+						.collect();
+
+					db.custom_field()
+						.upsert(
+							custom_field::pub_id::equals(id.pub_id.clone()),
+							custom_field::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Update { field, value } => {
+					let data = vec![custom_field::SetParam::deserialize(&field, value).unwrap()];
+
+					db.custom_field()
+						.upsert(
+							custom_field::pub_id::equals(id.pub_id.clone()),
+							custom_field::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Delete => {
+					db.custom_field()
+						.delete(custom_field::pub_id::equals(id.pub_id))
+						.exec()
+						.await?;
+				}
+			},
+			ModelSyncData::CustomFieldValue(id, shared_op) => match shared_op {
+				SharedOperationData::Create(data) => {
+					let data: Vec<_> = data
+						.into_iter()
+						.flat_map(|(field, value)| {
+							custom_field_value::SetParam::deserialize(&field, value)
+						})
+						.collect();
+
+					db.custom_field_value()
+						.upsert(
+							custom_field_value::pub_id::equals(id.pub_id.clone()),
+							custom_field_value::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Update { field, value } => {
+					let data =
+						vec![custom_field_value::SetParam::deserialize(&field, value).unwrap()];
+
+					db.custom_field_value()
+						.upsert(
+							custom_field_value::pub_id::equals(id.pub_id.clone()),
+							custom_field_value::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Delete => {
+					db.custom_field_value()
+						.delete(custom_field_value::pub_id::equals(id.pub_id))
+						.exec()
+						.await?;
+				}
+			},
+			ModelSyncData::Comment(id, shared_op) => match shared_op {
+				SharedOperationData::Create(data) => {
+					let data: Vec<_> = data
+						.into_iter()
+						.flat_map(|(field, value)| comment::SetParam::deserialize(&field, value))
+						.collect();
+
+					db.comment()
+						.upsert(
+							comment::pub_id::equals(id.pub_id.clone()),
+							comment::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Update { field, value } => {
+					let data = vec![comment::SetParam::deserialize(&field, value).unwrap()];
+
+					db.comment()
+						.upsert(
+							comment::pub_id::equals(id.pub_id.clone()),
+							comment::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Delete => {
+					db.comment()
+						.delete(comment::pub_id::equals(id.pub_id))
+						.exec()
+						.await?;
+				}
+			},
+			ModelSyncData::AuditLogEntry(id, shared_op) => match shared_op {
+				SharedOperationData::Create(data) => {
+					let data: Vec<_> = data
+						.into_iter()
+						.flat_map(|(field, value)| {
+							audit_log_entry::SetParam::deserialize(&field, value)
+						})
+						.collect();
+
+					db.audit_log_entry()
+						.upsert(
+							audit_log_entry::pub_id::equals(id.pub_id.clone()),
+							audit_log_entry::create(id.pub_id, data.clone()),
+							data,
+						)
+						.exec()
+						.await?;
+				}
+				// The audit log is append-only, entries are never changed once written
+				SharedOperationData::Update { .. } | SharedOperationData::Delete => {}
+			},
+			ModelSyncData::Node(id, shared_op) => match shared_op {
+				// Nodes join libraries by pairing, only their roles are synced
+				SharedOperationData::Update { field, value } if field == node::role::NAME => {
+					let data = vec![node::SetParam::deserialize(&field, value).unwrap()];
+
+					db.node()
+						.update_many(vec![node::pub_id::equals(id.pub_id)], data)
+						.exec()
+						.await?;
+				}
+				_ => {}
+			},
 		}
 
 		if let CRDTOperationType::Shared(shared_op) = op.typ {
@@ -380,4 +546,18 @@ impl SyncManager {
 			},
 		}))
 	}
+
+	pub fn shared_delete<
+		TSyncId: SyncId<ModelTypes = TModel>,
+		TModel: SyncType<Marker = SharedSyncType>,
+	>(
+		&self,
+		id: TSyncId,
+	) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Shared(SharedOperation {
+			model: TModel::MODEL.to_string(),
+			record_id: json!(id),
+			data: SharedOperationData::Delete,
+		}))
+	}
 }
@@ -72,6 +72,13 @@ pub fn chain_optional_iter<T>(
 		.collect()
 }
 
+/// Public id for a new object, file path or location. Version 7 uuids start with the time they
+/// were made, so new rows are added at the end of the indexes on `pub_id` rather than all over
+/// them, and sort by creation. Ids made before are version 4, and are read all the same.
+pub fn new_pub_id() -> Uuid {
+	Uuid::now_v7()
+}
+
 pub fn uuid_to_bytes(uuid: Uuid) -> Vec<u8> {
 	uuid.as_bytes().to_vec()
 }
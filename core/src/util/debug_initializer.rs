@@ -8,7 +8,7 @@ use std::{
 
 use crate::{
 	job::JobManagerError,
-	library::{LibraryConfig, LibraryManagerError, LibraryName},
+	library::{IdentityKey, LibraryConfig, LibraryManagerError, LibraryName},
 	location::{
 		delete_location, scan_location, LocationCreateArgs, LocationError, LocationManagerError,
 	},
@@ -121,8 +121,20 @@ impl InitConfig {
 							LibraryConfig {
 								name: lib.name,
 								description: lib.description,
-								identity: Identity::new().to_bytes(),
+								identity: IdentityKey::new(&node_cfg.keypair)
+									.encrypt(&Identity::new())
+									.await
+									.map_err(LibraryManagerError::from)?,
 								node_id: node_pub_id,
+								thumbnails: Default::default(),
+								xmp_sidecars: Default::default(),
+								backups: Default::default(),
+								database: Default::default(),
+								notifications: Default::default(),
+								content_cache: Default::default(),
+								data_directory: Default::default(),
+								settings: Default::default(),
+								jobs: Default::default(),
 							},
 							node_cfg.clone(),
 						)
@@ -1,5 +1,8 @@
 use std::{io, path::Path};
 
+use rspc::ErrorCode;
+use serde::Serialize;
+use specta::Type;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -22,3 +25,238 @@ impl<P: AsRef<Path>> From<(P, io::Error)> for FileIOError {
 #[derive(Debug, Error)]
 #[error("received a non UTF-8 path: <lossy_path='{}'>", .0.to_string_lossy())]
 pub struct NonUtf8PathError(pub Box<Path>);
+
+/// Stable codes of the errors returned to clients, for them to localize and react to. Codes are
+/// only ever added, never renamed or reused, unlike the messages of the errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CoreErrorCode {
+	Internal,
+	NotFound,
+	InvalidInput,
+	Conflict,
+	Database,
+	FileSystem,
+	LibraryNotFound,
+	LibraryConfigInvalid,
+	LibraryIdentityInvalid,
+	DbMigrationFailed,
+	LocationNotFound,
+	LocationOffline,
+	LocationNotADirectory,
+	LocationAlreadyExists,
+	LocationNested,
+	LocationNeedsRelink,
+	LocationInOtherLibrary,
+	LocationMetadataInvalid,
+	JobNotFound,
+	JobAlreadyRunning,
+	IndexerRuleInvalid,
+	VolumeUnavailable,
+	ProviderUnknown,
+	ProviderUnsupported,
+	BackupFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorSeverity {
+	/// Nothing went wrong, like a job that's already running
+	Info,
+	/// The request was refused, fixing it is up to the user
+	Warning,
+	Error,
+	/// The library or node may not work anymore until it's fixed
+	Critical,
+}
+
+impl CoreErrorCode {
+	pub const ALL: &'static [Self] = &[
+		Self::Internal,
+		Self::NotFound,
+		Self::InvalidInput,
+		Self::Conflict,
+		Self::Database,
+		Self::FileSystem,
+		Self::LibraryNotFound,
+		Self::LibraryConfigInvalid,
+		Self::LibraryIdentityInvalid,
+		Self::DbMigrationFailed,
+		Self::LocationNotFound,
+		Self::LocationOffline,
+		Self::LocationNotADirectory,
+		Self::LocationAlreadyExists,
+		Self::LocationNested,
+		Self::LocationNeedsRelink,
+		Self::LocationInOtherLibrary,
+		Self::LocationMetadataInvalid,
+		Self::JobNotFound,
+		Self::JobAlreadyRunning,
+		Self::IndexerRuleInvalid,
+		Self::VolumeUnavailable,
+		Self::ProviderUnknown,
+		Self::ProviderUnsupported,
+		Self::BackupFailed,
+	];
+
+	pub fn severity(self) -> ErrorSeverity {
+		match self {
+			Self::JobAlreadyRunning => ErrorSeverity::Info,
+			Self::NotFound
+			| Self::InvalidInput
+			| Self::Conflict
+			| Self::LibraryNotFound
+			| Self::LocationNotFound
+			| Self::LocationOffline
+			| Self::LocationNotADirectory
+			| Self::LocationAlreadyExists
+			| Self::LocationNested
+			| Self::LocationNeedsRelink
+			| Self::LocationInOtherLibrary
+			| Self::JobNotFound
+			| Self::IndexerRuleInvalid
+			| Self::ProviderUnknown
+			| Self::ProviderUnsupported => ErrorSeverity::Warning,
+			Self::Internal
+			| Self::Database
+			| Self::FileSystem
+			| Self::LocationMetadataInvalid
+			| Self::VolumeUnavailable
+			| Self::BackupFailed => ErrorSeverity::Error,
+			Self::LibraryConfigInvalid | Self::LibraryIdentityInvalid | Self::DbMigrationFailed => {
+				ErrorSeverity::Critical
+			}
+		}
+	}
+
+	/// What the user can do about the error, in English, for clients without a translation
+	pub fn hint(self) -> Option<&'static str> {
+		Some(match self {
+			Self::FileSystem => "Check that Spacedrive is allowed to access the file",
+			Self::LibraryConfigInvalid => {
+				"Restore the library from a backup, or repair it from the library settings"
+			}
+			Self::LibraryIdentityInvalid => {
+				"The library may have been copied from another device, add it again through pairing"
+			}
+			Self::DbMigrationFailed => {
+				"Restore the library from a backup made before updating Spacedrive"
+			}
+			Self::LocationOffline => "Reconnect the drive or network share the location is on",
+			Self::LocationNotADirectory => "Choose a folder instead of a file",
+			Self::LocationNested => "Choose a folder that isn't inside or around another location",
+			Self::LocationNeedsRelink => "Relink the location to its new path",
+			Self::LocationInOtherLibrary => "Add the location to this library too",
+			Self::LocationMetadataInvalid => {
+				"Remove the .spacedrive file of the location, then add it again"
+			}
+			Self::JobAlreadyRunning => "Wait for the running job to finish",
+			Self::VolumeUnavailable => "Check that the drive is connected and mounted",
+			Self::ProviderUnknown => "Install the extension providing this storage",
+			Self::BackupFailed => "Check that there's enough free space for the backup",
+			_ => return None,
+		})
+	}
+
+	pub fn status(self) -> ErrorCode {
+		match self {
+			Self::NotFound
+			| Self::LibraryNotFound
+			| Self::LocationNotFound
+			| Self::JobNotFound
+			| Self::ProviderUnknown => ErrorCode::NotFound,
+			Self::InvalidInput
+			| Self::LocationNotADirectory
+			| Self::LocationNested
+			| Self::IndexerRuleInvalid
+			| Self::ProviderUnsupported => ErrorCode::BadRequest,
+			Self::Conflict
+			| Self::LocationAlreadyExists
+			| Self::LocationNeedsRelink
+			| Self::LocationInOtherLibrary
+			| Self::JobAlreadyRunning => ErrorCode::Conflict,
+			Self::LocationOffline | Self::VolumeUnavailable => ErrorCode::PreconditionFailed,
+			_ => ErrorCode::InternalServerError,
+		}
+	}
+}
+
+/// An error as sent to clients. As rspc errors can't carry data of their own, it's serialized to
+/// JSON as their message.
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreError {
+	pub code: CoreErrorCode,
+	pub severity: ErrorSeverity,
+	/// Details about the error, in English, not meant to be matched on
+	pub message: String,
+	pub hint: Option<String>,
+}
+
+impl CoreError {
+	pub fn new(code: CoreErrorCode, message: impl Into<String>) -> Self {
+		Self {
+			code,
+			severity: code.severity(),
+			message: message.into(),
+			hint: code.hint().map(str::to_string),
+		}
+	}
+}
+
+impl From<CoreError> for rspc::Error {
+	fn from(err: CoreError) -> Self {
+		rspc::Error::new(
+			err.code.status(),
+			serde_json::to_string(&err).unwrap_or(err.message),
+		)
+	}
+}
+
+/// Turns an error into an rspc one with the given code, keeping it as its cause
+pub fn coded_error(
+	code: CoreErrorCode,
+	err: impl std::error::Error + Send + Sync + 'static,
+) -> rspc::Error {
+	let core_error = CoreError::new(code, err.to_string());
+
+	rspc::Error::with_cause(
+		code.status(),
+		serde_json::to_string(&core_error).unwrap_or(core_error.message),
+		err,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::collections::HashSet;
+
+	#[test]
+	fn codes_are_unique_and_listed() {
+		let names = CoreErrorCode::ALL
+			.iter()
+			.map(|code| serde_json::to_string(code).unwrap())
+			.collect::<HashSet<_>>();
+
+		assert_eq!(names.len(), CoreErrorCode::ALL.len());
+		assert!(names.contains("\"LOCATION_OFFLINE\""));
+		assert!(names.contains("\"DB_MIGRATION_FAILED\""));
+	}
+
+	#[test]
+	fn errors_are_sent_as_json() {
+		let err = CoreError::new(CoreErrorCode::LocationOffline, "location is offline");
+
+		assert_eq!(
+			serde_json::to_value(&err).unwrap(),
+			serde_json::json!({
+				"code": "LOCATION_OFFLINE",
+				"severity": "warning",
+				"message": "location is offline",
+				"hint": "Reconnect the drive or network share the location is on",
+			})
+		);
+	}
+}
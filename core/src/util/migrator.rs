@@ -5,10 +5,12 @@ use std::{
 	path::{Path, PathBuf},
 };
 
+use futures::future::BoxFuture;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Map, Value};
 use specta::Type;
 use thiserror::Error;
+use tracing::{debug, info};
 
 use super::db::MissingFieldError;
 
@@ -23,20 +25,74 @@ pub struct BaseConfig {
 	other: Map<String, Value>,
 }
 
+/// How long a migration can take, to warn users before a slow one runs
+#[derive(Debug, Clone, Copy, Serialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MigrationCost {
+	/// Only changes the config
+	Instant,
+	/// Runs a few queries on the database
+	Quick,
+	/// Goes over rows of the database that grow with the library, like its file paths
+	Slow,
+}
+
+pub type MigrationFn<Ctx> =
+	for<'a> fn(&'a mut Map<String, Value>, &'a Ctx) -> BoxFuture<'a, Result<(), MigratorError>>;
+
+/// A step of the migrations of a config. The n-th one of [`Migrate::MIGRATIONS`] brings the
+/// config from version n - 1 to version n.
+pub struct Migration<Ctx> {
+	pub name: &'static str,
+	pub description: &'static str,
+	pub cost: MigrationCost,
+	pub run: MigrationFn<Ctx>,
+}
+
+/// A migration as reported to the users, before it runs
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationInfo {
+	/// Version of the config once migrated
+	pub version: u32,
+	pub name: &'static str,
+	pub description: &'static str,
+	pub cost: MigrationCost,
+}
+
 /// System for managing app level migrations on a config file so we can introduce breaking changes to the app without the user needing to reset their whole system.
 #[async_trait::async_trait]
 pub trait Migrate: Sized + DeserializeOwned + Serialize {
-	const CURRENT_VERSION: u32;
+	type Ctx: Sync + 'static;
+
+	/// Every migration of the config, from the first version on
+	const MIGRATIONS: &'static [Migration<Self::Ctx>];
 
-	type Ctx: Sync;
+	const CURRENT_VERSION: u32 = Self::MIGRATIONS.len() as u32;
 
 	fn default(path: PathBuf) -> Result<Self, MigratorError>;
 
-	async fn migrate(
-		from_version: u32,
-		config: &mut Map<String, Value>,
-		ctx: &Self::Ctx,
-	) -> Result<(), MigratorError>;
+	/// Migrations a config at the given version has to go through
+	fn migrations_since(version: u32) -> Vec<MigrationInfo> {
+		Self::MIGRATIONS
+			.iter()
+			.zip(1..)
+			.skip(version as usize)
+			.map(|(migration, version)| MigrationInfo {
+				version,
+				name: migration.name,
+				description: migration.description,
+				cost: migration.cost,
+			})
+			.collect()
+	}
+
+	/// Migrations that will run the next time the config file at `path` is loaded
+	fn pending_migrations(path: &Path) -> Result<Vec<MigrationInfo>, MigratorError> {
+		let config = serde_json::from_slice::<BaseConfig>(&std::fs::read(path)?)?;
+
+		Ok(Self::migrations_since(config.version))
+	}
 
 	async fn load_and_migrate(path: &Path, ctx: &Self::Ctx) -> Result<Self, MigratorError> {
 		match path.try_exists()? {
@@ -76,9 +132,30 @@ pub trait Migrate: Sized + DeserializeOwned + Serialize {
 				}
 
 				let is_latest = cfg.version == Self::CURRENT_VERSION;
-				for v in (cfg.version + 1)..=Self::CURRENT_VERSION {
+				if !is_latest {
+					let pending = Self::migrations_since(cfg.version);
+					info!(
+						"{} migrations pending for config '{}': {}",
+						pending.len(),
+						path.display(),
+						pending
+							.iter()
+							.map(|migration| migration.name)
+							.collect::<Vec<_>>()
+							.join(", ")
+					);
+				}
+
+				for (v, migration) in
+					((cfg.version + 1)..).zip(&Self::MIGRATIONS[cfg.version as usize..])
+				{
 					cfg.version = v;
-					match Self::migrate(v, &mut cfg.other, ctx).await {
+					debug!(
+						"Running migration '{}' of config '{}'",
+						migration.name,
+						path.display()
+					);
+					match (migration.run)(&mut cfg.other, ctx).await {
 						Ok(()) => (),
 						Err(err) => {
 							file.write_all(serde_json::to_string(&cfg)?.as_bytes())?; // Writes updated version
@@ -91,7 +168,7 @@ pub trait Migrate: Sized + DeserializeOwned + Serialize {
 					file.write_all(serde_json::to_string(&cfg)?.as_bytes())?; // Writes updated version
 				}
 
-				Ok(serde_json::from_value(Value::Object(cfg.other))?)
+				deserialize_config(path, cfg.other)
 			}
 			false => Ok(serde_json::from_value(Value::Object(
 				Self::default(path.into())?.save(path)?.other,
@@ -116,6 +193,70 @@ pub trait Migrate: Sized + DeserializeOwned + Serialize {
 	}
 }
 
+/// Deserializes a migrated config, telling which of its fields is invalid if it fails
+fn deserialize_config<T: DeserializeOwned>(
+	path: &Path,
+	config: Map<String, Value>,
+) -> Result<T, MigratorError> {
+	let config = Value::Object(config);
+
+	serde_json::from_value::<T>(config.clone()).map_err(|e| {
+		let (field, value) = locate_invalid_field::<T>(&config, &e);
+
+		MigratorError::InvalidField {
+			path: path.to_path_buf(),
+			field,
+			value,
+			message: e.to_string(),
+		}
+	})
+}
+
+/// Finds the field of a config that fails to deserialize, as a dotted path from the root, by
+/// leaving its fields out one at a time and going down into the one that was at fault. Leaving
+/// out the invalid field either fixes the config, or makes it miss a required field.
+fn locate_invalid_field<T: DeserializeOwned>(
+	config: &Value,
+	error: &serde_json::Error,
+) -> (String, Value) {
+	if let Some(field) = error
+		.to_string()
+		.strip_prefix("missing field `")
+		.and_then(|rest| rest.split_once('`'))
+		.map(|(field, _)| field.to_string())
+	{
+		return (field, Value::Null);
+	}
+
+	let is_at_fault = |pointer: &str, key: &str| {
+		let mut candidate = config.clone();
+		if let Some(object) = candidate
+			.pointer_mut(pointer)
+			.and_then(Value::as_object_mut)
+		{
+			object.remove(key);
+		}
+
+		match serde_json::from_value::<T>(candidate) {
+			Ok(_) => true,
+			Err(e) => e.to_string().starts_with(&format!("missing field `{key}`")),
+		}
+	};
+
+	let mut pointer = String::new();
+	while let Some(Value::Object(object)) = config.pointer(&pointer) {
+		match object.keys().find(|key| is_at_fault(&pointer, key)) {
+			Some(key) => pointer = format!("{pointer}/{key}"),
+			None => break,
+		}
+	}
+
+	(
+		pointer.trim_start_matches('/').replace('/', "."),
+		config.pointer(&pointer).cloned().unwrap_or(Value::Null),
+	)
+}
+
 #[derive(Error, Debug)]
 pub enum MigratorError {
 	#[error("Io - error saving or loading the config from the filesystem: {0}")]
@@ -138,6 +279,117 @@ pub enum MigratorError {
 	MissingField(#[from] MissingFieldError),
 	#[error("custom migration error: {0}")]
 	Custom(String),
+	#[error("invalid field '{field}' in config file '{}': {message}", .path.display())]
+	InvalidField {
+		path: PathBuf,
+		/// Dotted path of the field from the root of the config, empty if it couldn't be found
+		field: String,
+		value: Value,
+		message: String,
+	},
+}
+
+/// Configs and databases saved at historical versions, to check migrations against real data of
+/// the old formats. Every config has a version in its name, like `library_v3.sdlibrary`, and can
+/// come with a snapshot of its database next to it, like `library_v3.db`.
+#[cfg(test)]
+pub(crate) const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/migrations");
+
+/// A fixture copied to a temporary directory, so migrating it leaves the original untouched
+#[cfg(test)]
+pub(crate) struct MigrationFixture {
+	name: String,
+	config_path: PathBuf,
+	database_path: Option<PathBuf>,
+	// Removed on drop
+	dir: tempfile::TempDir,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+impl MigrationFixture {
+	/// Every fixture config with the given extension, like `sdlibrary`
+	pub(crate) fn all(extension: &str) -> Vec<Self> {
+		let mut names = std::fs::read_dir(FIXTURES_DIR)
+			.unwrap()
+			.map(|entry| entry.unwrap().path())
+			.filter(|path| path.extension().map_or(false, |ext| ext == extension))
+			.map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+			.collect::<Vec<_>>();
+		names.sort();
+
+		names.iter().map(|name| Self::load(name)).collect()
+	}
+
+	pub(crate) fn load(name: &str) -> Self {
+		let fixture_path = Path::new(FIXTURES_DIR).join(name);
+		let dir = tempfile::tempdir().unwrap();
+
+		let config_path = dir.path().join(name);
+		std::fs::copy(&fixture_path, &config_path)
+			.unwrap_or_else(|e| panic!("missing fixture '{}': {e}", fixture_path.display()));
+
+		let snapshot_path = fixture_path.with_extension("db");
+		let database_path = snapshot_path.exists().then(|| {
+			let database_path = config_path.with_extension("db");
+			std::fs::copy(&snapshot_path, &database_path).unwrap();
+			database_path
+		});
+
+		Self {
+			name: name.to_string(),
+			config_path,
+			database_path,
+			dir,
+		}
+	}
+
+	pub(crate) fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub(crate) fn config_path(&self) -> &Path {
+		&self.config_path
+	}
+
+	/// The copy of the database snapshot of the fixture, if it has one
+	pub(crate) fn database_path(&self) -> Option<&Path> {
+		self.database_path.as_deref()
+	}
+
+	/// Where a database is created for fixtures without a snapshot
+	pub(crate) fn empty_database_path(&self) -> PathBuf {
+		self.dir.path().join("empty.db")
+	}
+
+	/// Version the config is at, before being migrated or after
+	pub(crate) fn version(&self) -> u32 {
+		serde_json::from_slice::<BaseConfig>(&std::fs::read(&self.config_path).unwrap())
+			.unwrap()
+			.version
+	}
+
+	/// Runs the migration chain on the config, checking it ends up at the current version
+	pub(crate) async fn migrate<T: Migrate>(&self, ctx: &T::Ctx) -> T {
+		let from_version = self.version();
+		let config = T::load_and_migrate(&self.config_path, ctx)
+			.await
+			.unwrap_or_else(|e| {
+				panic!(
+					"failed to migrate fixture '{}' from version {from_version}: {e}",
+					self.name
+				)
+			});
+
+		assert_eq!(
+			self.version(),
+			T::CURRENT_VERSION,
+			"fixture '{}' wasn't migrated to the current version",
+			self.name
+		);
+
+		config
+	}
 }
 
 #[cfg(test)]
@@ -158,46 +410,72 @@ mod test {
 
 	#[async_trait::async_trait]
 	impl Migrate for MyConfigType {
-		const CURRENT_VERSION: u32 = 3;
-
 		type Ctx = ();
 
+		const MIGRATIONS: &'static [Migration<Self::Ctx>] = &[
+			Migration {
+				name: "add_a",
+				description: "Adds `a`",
+				cost: MigrationCost::Instant,
+				run: add_a,
+			},
+			Migration {
+				name: "add_b",
+				description: "Adds `b` to `a`",
+				cost: MigrationCost::Instant,
+				run: add_b,
+			},
+			Migration {
+				name: "add_c",
+				description: "Adds `c` to `b`",
+				cost: MigrationCost::Instant,
+				run: add_c,
+			},
+		];
+
 		fn default(_path: PathBuf) -> Result<Self, MigratorError> {
 			Ok(<Self as Default>::default())
 		}
+	}
 
-		async fn migrate(
-			to_version: u32,
-			config: &mut Map<String, Value>,
-			_ctx: &Self::Ctx,
-		) -> Result<(), MigratorError> {
-			match to_version {
-				0 => Ok(()),
-				1 => {
-					config.insert("a".into(), json!({}));
-					Ok(())
-				}
-				2 => {
-					config
-						.get_mut("a")
-						.and_then(|v| v.as_object_mut())
-						.map(|v| v.insert("b".into(), json!({})));
+	fn add_a<'a>(
+		config: &'a mut Map<String, Value>,
+		_: &'a (),
+	) -> BoxFuture<'a, Result<(), MigratorError>> {
+		Box::pin(async move {
+			config.insert("a".into(), json!({}));
+			Ok(())
+		})
+	}
 
-					Ok(())
-				}
-				3 => {
-					config
-						.get_mut("a")
-						.and_then(|v| v.as_object_mut())
-						.and_then(|v| v.get_mut("b"))
-						.and_then(|v| v.as_object_mut())
-						.map(|v| v.insert("c".into(), json!("it works")));
-
-					Ok(())
-				}
-				v => unreachable!("Missing migration for library version {}", v),
-			}
-		}
+	fn add_b<'a>(
+		config: &'a mut Map<String, Value>,
+		_: &'a (),
+	) -> BoxFuture<'a, Result<(), MigratorError>> {
+		Box::pin(async move {
+			config
+				.get_mut("a")
+				.and_then(|v| v.as_object_mut())
+				.map(|v| v.insert("b".into(), json!({})));
+
+			Ok(())
+		})
+	}
+
+	fn add_c<'a>(
+		config: &'a mut Map<String, Value>,
+		_: &'a (),
+	) -> BoxFuture<'a, Result<(), MigratorError>> {
+		Box::pin(async move {
+			config
+				.get_mut("a")
+				.and_then(|v| v.as_object_mut())
+				.and_then(|v| v.get_mut("b"))
+				.and_then(|v| v.as_object_mut())
+				.map(|v| v.insert("c".into(), json!("it works")));
+
+			Ok(())
+		})
 	}
 
 	fn path(file_name: &'static str) -> PathBuf {
@@ -247,6 +525,50 @@ mod test {
 		fs::remove_file(&p).unwrap();
 	}
 
+	#[test]
+	fn locates_invalid_fields() {
+		#[derive(Debug, Deserialize)]
+		#[allow(dead_code)]
+		struct Config {
+			name: String,
+			#[serde(default)]
+			thumbnails: Thumbnails,
+		}
+
+		#[derive(Debug, Default, Deserialize)]
+		#[allow(dead_code)]
+		struct Thumbnails {
+			quality: u8,
+			#[serde(default)]
+			format: Option<String>,
+		}
+
+		let p = PathBuf::from("library.sdlibrary");
+		let invalid_field = |config: Value| {
+			let Value::Object(config) = config else {
+				panic!("config isn't an object");
+			};
+
+			match deserialize_config::<Config>(&p, config) {
+				Err(MigratorError::InvalidField { field, value, .. }) => (field, value),
+				result => panic!("unexpected result: {result:?}"),
+			}
+		};
+
+		assert_eq!(
+			invalid_field(json!({ "thumbnails": { "quality": 50 } })),
+			("name".to_string(), Value::Null)
+		);
+		assert_eq!(
+			invalid_field(json!({ "name": "a", "thumbnails": { "quality": 500 } })),
+			("thumbnails.quality".to_string(), json!(500))
+		);
+		assert_eq!(
+			invalid_field(json!({ "name": "a", "thumbnails": { "quality": 50, "format": 1 } })),
+			("thumbnails.format".to_string(), json!(1))
+		);
+	}
+
 	#[tokio::test]
 	pub async fn test_time_traveling_backwards() {
 		let p = path("test_time_traveling_backwards.config");
@@ -0,0 +1,447 @@
+use crate::{
+	invalidate_query,
+	job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunMetadata, JobStepOutput,
+		StatefulJob, WorkerContext,
+	},
+	library::Library,
+	prisma::{volume, volume_benchmark, SortOrder},
+	util::error::FileIOError,
+};
+
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::task::spawn_blocking;
+use tracing::info;
+use uuid::Uuid;
+
+use super::{save_volume, VolumeError};
+
+const DEFAULT_FILE_SIZE_MIB: u32 = 256;
+const SEQUENTIAL_BLOCK_SIZE: usize = 1024 * 1024;
+const RANDOM_BLOCK_SIZE: usize = 4 * 1024;
+/// Random accesses stop after this long, so slow drives don't take forever
+const RANDOM_ACCESS_DURATION: Duration = Duration::from_secs(10);
+
+/// Measures the sequential and random throughputs of a volume, by writing a temporary file on it
+/// and reading it back. Reads can be served from the OS cache, so the test file should be bigger
+/// than the memory of the device for the read throughputs to be those of the drive.
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone)]
+pub struct VolumeBenchmarkJobInit {
+	/// Mount point of the volume to benchmark
+	pub mount_point: String,
+	/// Directory of the volume the test file is written to, the mount point if not set
+	pub directory: Option<PathBuf>,
+	/// Size of the test file in MiB, 256 if not set
+	pub file_size_mib: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VolumeBenchmarkJobData {
+	test_file: PathBuf,
+	file_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum VolumeBenchmarkJobStep {
+	SequentialWrite,
+	SequentialRead,
+	RandomWrite,
+	RandomRead,
+}
+
+/// Throughputs in bytes per second
+#[derive(Serialize, Deserialize, Type, Default, Debug, Clone)]
+pub struct VolumeBenchmarkResults {
+	#[specta(type = String)]
+	pub sequential_read: u64,
+	#[specta(type = String)]
+	pub sequential_write: u64,
+	#[specta(type = String)]
+	pub random_read: u64,
+	#[specta(type = String)]
+	pub random_write: u64,
+}
+
+impl JobRunMetadata for VolumeBenchmarkResults {
+	fn update(&mut self, new_data: Self) {
+		self.sequential_read = self.sequential_read.max(new_data.sequential_read);
+		self.sequential_write = self.sequential_write.max(new_data.sequential_write);
+		self.random_read = self.random_read.max(new_data.random_read);
+		self.random_write = self.random_write.max(new_data.random_write);
+	}
+}
+
+impl VolumeBenchmarkResults {
+	/// How many jobs can work on the volume at the same time before they slow each other down,
+	/// from how many random reads it does per second: spinning drives can barely seek for more
+	/// than one, while SSDs shine with several
+	pub fn recommended_parallelism(&self) -> u32 {
+		match self.random_read / RANDOM_BLOCK_SIZE as u64 {
+			0..=499 => 1,
+			500..=4_999 => 2,
+			5_000..=49_999 => 4,
+			_ => 8,
+		}
+	}
+}
+
+#[derive(Serialize, Type, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeBenchmark {
+	pub id: volume_benchmark::id::Type,
+	pub mount_point: String,
+	#[specta(type = String)]
+	pub file_size: u64,
+	pub results: VolumeBenchmarkResults,
+	pub recommended_parallelism: u32,
+	pub date_created: DateTime<Utc>,
+}
+
+volume_benchmark::include!(volume_benchmark_with_volume { volume });
+
+impl From<volume_benchmark_with_volume::Data> for VolumeBenchmark {
+	fn from(benchmark: volume_benchmark_with_volume::Data) -> Self {
+		let results = VolumeBenchmarkResults {
+			sequential_read: benchmark.sequential_read.parse().unwrap_or_default(),
+			sequential_write: benchmark.sequential_write.parse().unwrap_or_default(),
+			random_read: benchmark.random_read.parse().unwrap_or_default(),
+			random_write: benchmark.random_write.parse().unwrap_or_default(),
+		};
+
+		Self {
+			id: benchmark.id,
+			mount_point: benchmark.volume.mount_point,
+			file_size: benchmark.file_size.parse().unwrap_or_default(),
+			recommended_parallelism: results.recommended_parallelism(),
+			results,
+			date_created: benchmark.date_created.into(),
+		}
+	}
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> u64 {
+	(bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64
+}
+
+/// Offsets of the random accesses, aligned to their block size, from a xorshift generator, as
+/// they only need to defeat read-ahead
+fn random_offsets(file_size: u64) -> impl Iterator<Item = u64> {
+	let blocks = (file_size / RANDOM_BLOCK_SIZE as u64).max(1);
+	let mut state = Uuid::new_v4().as_u64_pair().0 | 1;
+
+	std::iter::repeat_with(move || {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		(state % blocks) * RANDOM_BLOCK_SIZE as u64
+	})
+}
+
+fn sequential_write(path: &Path, file_size: u64) -> io::Result<u64> {
+	let block = vec![0xA5; SEQUENTIAL_BLOCK_SIZE];
+	let start = Instant::now();
+
+	let mut file = File::create(path)?;
+	let mut written = 0;
+	while written < file_size {
+		file.write_all(&block)?;
+		written += block.len() as u64;
+	}
+	file.sync_all()?;
+
+	Ok(throughput(written, start.elapsed()))
+}
+
+fn sequential_read(path: &Path) -> io::Result<u64> {
+	let mut block = vec![0; SEQUENTIAL_BLOCK_SIZE];
+	let start = Instant::now();
+
+	let mut file = File::open(path)?;
+	let mut read = 0;
+	loop {
+		match file.read(&mut block)? {
+			0 => break,
+			count => read += count as u64,
+		}
+	}
+
+	Ok(throughput(read, start.elapsed()))
+}
+
+fn random_write(path: &Path, file_size: u64) -> io::Result<u64> {
+	let block = vec![0x5A; RANDOM_BLOCK_SIZE];
+	let start = Instant::now();
+
+	let mut file = OpenOptions::new().write(true).open(path)?;
+	let mut written = 0;
+	for offset in random_offsets(file_size) {
+		file.seek(SeekFrom::Start(offset))?;
+		file.write_all(&block)?;
+		written += block.len() as u64;
+
+		if start.elapsed() > RANDOM_ACCESS_DURATION {
+			break;
+		}
+	}
+	file.sync_all()?;
+
+	Ok(throughput(written, start.elapsed()))
+}
+
+fn random_read(path: &Path, file_size: u64) -> io::Result<u64> {
+	let mut block = vec![0; RANDOM_BLOCK_SIZE];
+	let start = Instant::now();
+
+	let mut file = File::open(path)?;
+	let mut read = 0;
+	for offset in random_offsets(file_size) {
+		file.seek(SeekFrom::Start(offset))?;
+		file.read_exact(&mut block)?;
+		read += block.len() as u64;
+
+		if start.elapsed() > RANDOM_ACCESS_DURATION {
+			break;
+		}
+	}
+
+	Ok(throughput(read, start.elapsed()))
+}
+
+/// The volume of this node mounted at the given mount point, saving the volumes of the node first
+/// if it isn't in the database yet
+async fn find_volume(library: &Library, mount_point: &str) -> Result<volume::Data, VolumeError> {
+	let find = || {
+		library.db.volume().find_first(vec![
+			volume::node_id::equals(library.node_local_id),
+			volume::mount_point::equals(mount_point.to_string()),
+		])
+	};
+
+	if let Some(volume) = find().exec().await? {
+		return Ok(volume);
+	}
+
+	save_volume(library).await?;
+
+	find()
+		.exec()
+		.await?
+		.ok_or_else(|| VolumeError::NotFound(mount_point.to_string()))
+}
+
+/// Benchmarks of the volumes of this node, newest first
+pub async fn list_volume_benchmarks(
+	library: &Library,
+	mount_point: Option<String>,
+) -> Result<Vec<VolumeBenchmark>, VolumeError> {
+	let mut params = vec![volume_benchmark::volume::is(vec![volume::node_id::equals(
+		library.node_local_id,
+	)])];
+	if let Some(mount_point) = mount_point {
+		params.push(volume_benchmark::volume::is(vec![
+			volume::mount_point::equals(mount_point),
+		]));
+	}
+
+	Ok(library
+		.db
+		.volume_benchmark()
+		.find_many(params)
+		.order_by(volume_benchmark::date_created::order(SortOrder::Desc))
+		.include(volume_benchmark_with_volume::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for VolumeBenchmarkJobInit {
+	type Data = VolumeBenchmarkJobData;
+	type Step = VolumeBenchmarkJobStep;
+	type RunMetadata = VolumeBenchmarkResults;
+
+	const NAME: &'static str = "volume_benchmark";
+
+	/// Benchmarks running at the same time on a volume would skew each other's results
+	fn concurrency_scope(&self) -> Option<String> {
+		Some(self.mount_point.clone())
+	}
+
+	async fn init(
+		&self,
+		_: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+
+		let directory = init
+			.directory
+			.clone()
+			.unwrap_or_else(|| PathBuf::from(&init.mount_point));
+
+		if !directory.starts_with(&init.mount_point) {
+			return Err(JobError::EarlyFinish {
+				name: Self::NAME.to_string(),
+				reason: format!(
+					"directory '{}' isn't in volume '{}'",
+					directory.display(),
+					init.mount_point
+				),
+			});
+		}
+
+		*data = Some(VolumeBenchmarkJobData {
+			test_file: directory.join(format!(".sd-benchmark-{}", Uuid::new_v4())),
+			file_size: init.file_size_mib.unwrap_or(DEFAULT_FILE_SIZE_MIB).max(1) as u64
+				* 1024 * 1024,
+		});
+
+		// Reads need the file to be written first
+		Ok((
+			VolumeBenchmarkResults::default(),
+			vec![
+				VolumeBenchmarkJobStep::SequentialWrite,
+				VolumeBenchmarkJobStep::SequentialRead,
+				VolumeBenchmarkJobStep::RandomWrite,
+				VolumeBenchmarkJobStep::RandomRead,
+			],
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let VolumeBenchmarkJobData {
+			test_file,
+			file_size,
+		} = data;
+		let (test_file, file_size) = (test_file.clone(), *file_size);
+
+		ctx.progress_msg(format!("Measuring the {step:?} throughput"));
+
+		let mut results = VolumeBenchmarkResults::default();
+
+		let measured = match step {
+			VolumeBenchmarkJobStep::SequentialWrite => {
+				spawn_blocking(move || sequential_write(&test_file, file_size)).await?
+			}
+			VolumeBenchmarkJobStep::SequentialRead => {
+				spawn_blocking(move || sequential_read(&test_file)).await?
+			}
+			VolumeBenchmarkJobStep::RandomWrite => {
+				spawn_blocking(move || random_write(&test_file, file_size)).await?
+			}
+			VolumeBenchmarkJobStep::RandomRead => {
+				spawn_blocking(move || random_read(&test_file, file_size)).await?
+			}
+		};
+
+		let measured = match measured {
+			Ok(measured) => measured,
+			Err(e) => {
+				// Not leaving a huge file behind
+				fs::remove_file(&data.test_file).ok();
+				return Err(FileIOError::from((&data.test_file, e)).into());
+			}
+		};
+
+		match step {
+			VolumeBenchmarkJobStep::SequentialWrite => results.sequential_write = measured,
+			VolumeBenchmarkJobStep::SequentialRead => results.sequential_read = measured,
+			VolumeBenchmarkJobStep::RandomWrite => results.random_write = measured,
+			VolumeBenchmarkJobStep::RandomRead => results.random_read = measured,
+		}
+
+		Ok(results.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		let data = data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		fs::remove_file(&data.test_file).map_err(|e| FileIOError::from((&data.test_file, e)))?;
+
+		info!(
+			"Benchmark of volume '{}' finished: {run_metadata:?}",
+			init.mount_point
+		);
+
+		let volume = find_volume(&ctx.library, &init.mount_point).await?;
+
+		ctx.library
+			.db
+			.volume_benchmark()
+			.create(
+				volume::id::equals(volume.id),
+				vec![
+					volume_benchmark::file_size::set(data.file_size.to_string()),
+					volume_benchmark::sequential_read::set(
+						run_metadata.sequential_read.to_string(),
+					),
+					volume_benchmark::sequential_write::set(
+						run_metadata.sequential_write.to_string(),
+					),
+					volume_benchmark::random_read::set(run_metadata.random_read.to_string()),
+					volume_benchmark::random_write::set(run_metadata.random_write.to_string()),
+				],
+			)
+			.exec()
+			.await?;
+
+		invalidate_query!(ctx.library, "volumes.benchmarks");
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parallelism_follows_random_reads() {
+		let results = |random_read_iops: u64| VolumeBenchmarkResults {
+			random_read: random_read_iops * RANDOM_BLOCK_SIZE as u64,
+			..Default::default()
+		};
+
+		assert_eq!(results(120).recommended_parallelism(), 1);
+		assert_eq!(results(20_000).recommended_parallelism(), 4);
+		assert_eq!(results(300_000).recommended_parallelism(), 8);
+	}
+
+	#[test]
+	fn random_offsets_are_aligned_and_in_the_file() {
+		let file_size = 1024 * 1024;
+
+		assert!(random_offsets(file_size).take(1000).all(|offset| offset
+			% RANDOM_BLOCK_SIZE as u64
+			== 0 && offset
+			+ RANDOM_BLOCK_SIZE as u64
+			<= file_size));
+	}
+}
@@ -0,0 +1,405 @@
+//! Health of the drives backing volumes, from their SMART attributes as read by `smartctl`, from
+//! smartmontools, which needs to be installed and usually to run with enough privileges to open
+//! the drives.
+
+use crate::{
+	library::{Library, NotificationKind, WebhookEvent},
+	prisma::location,
+};
+
+use std::{collections::HashSet, path::Path, process::Command, time::Duration};
+
+use serde::Serialize;
+use serde_json::Value;
+use specta::Type;
+use tokio::{
+	task::spawn_blocking,
+	time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::{get_volumes, Volume, VolumeError};
+
+/// How often the drives backing the locations of a library are checked
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// SMART attributes counting sectors that went bad, any of them being above 0 meaning the drive
+/// is likely to fail soon: reallocated, pending and uncorrectable sectors
+const BAD_SECTOR_ATTRIBUTES: [u32; 3] = [5, 197, 198];
+
+#[derive(Debug, Clone, Copy, Serialize, Type, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum DriveHealthStatus {
+	Healthy,
+	/// Shows pre-failure indicators, its data should be backed up
+	Warning,
+	/// Failed its self-assessment
+	Failing,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartAttribute {
+	pub id: u32,
+	pub name: String,
+	/// Normalized value, lower is worse
+	pub value: u32,
+	pub worst: u32,
+	/// The attribute is failing once its value gets to this threshold
+	pub threshold: u32,
+	#[specta(type = String)]
+	pub raw: u64,
+	/// A failure of this attribute means the drive is about to fail
+	pub pre_failure: bool,
+	pub failing: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveHealth {
+	pub device: String,
+	pub model: Option<String>,
+	pub status: DriveHealthStatus,
+	/// Why the drive isn't healthy, for users
+	pub warnings: Vec<String>,
+	pub temperature_celsius: Option<i32>,
+	pub power_on_hours: Option<u32>,
+	pub attributes: Vec<SmartAttribute>,
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeHealth {
+	pub volume: Volume,
+	pub health: Option<DriveHealth>,
+	/// Why the health couldn't be read, like the platform not being supported or `smartctl`
+	/// missing or not being permitted to open the drive
+	pub error: Option<String>,
+}
+
+/// Device of the drive a volume is a partition of, SMART attributes being those of whole drives
+#[cfg(target_os = "linux")]
+fn drive_device(volume: &Volume) -> Option<String> {
+	let partition = volume.name.strip_prefix("/dev/")?;
+
+	// eg: nvme0n1p2 and mmcblk0p1, whose drive names end with digits too
+	let drive = if partition.starts_with("nvme") || partition.starts_with("mmcblk") {
+		match partition.rfind('p') {
+			Some(idx)
+				if idx > 0
+					&& partition[idx + 1..].chars().all(|c| c.is_ascii_digit())
+					&& !partition[idx + 1..].is_empty() =>
+			{
+				&partition[..idx]
+			}
+			_ => partition,
+		}
+	} else {
+		partition.trim_end_matches(|c: char| c.is_ascii_digit())
+	};
+
+	Some(format!("/dev/{drive}"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drive_device(_volume: &Volume) -> Option<String> {
+	None
+}
+
+fn attribute_u32(attribute: &Value, field: &str) -> u32 {
+	attribute[field].as_u64().unwrap_or_default() as u32
+}
+
+/// Builds the health of a drive from the output of `smartctl --json -H -A`
+fn parse_smartctl_output(device: String, output: &Value) -> DriveHealth {
+	let mut status = DriveHealthStatus::Healthy;
+	let mut warnings = vec![];
+
+	if output["smart_status"]["passed"].as_bool() == Some(false) {
+		status = DriveHealthStatus::Failing;
+		warnings.push("The drive failed its SMART self-assessment".to_string());
+	}
+
+	let attributes = output["ata_smart_attributes"]["table"]
+		.as_array()
+		.map(|table| {
+			table
+				.iter()
+				.map(|attribute| {
+					let value = attribute_u32(attribute, "value");
+					let threshold = attribute_u32(attribute, "thresh");
+
+					SmartAttribute {
+						id: attribute_u32(attribute, "id"),
+						name: attribute["name"].as_str().unwrap_or_default().to_string(),
+						value,
+						worst: attribute_u32(attribute, "worst"),
+						threshold,
+						raw: attribute["raw"]["value"].as_u64().unwrap_or_default(),
+						pre_failure: attribute["flags"]["prefailure"].as_bool().unwrap_or(false),
+						failing: (threshold > 0 && value <= threshold)
+							|| attribute["when_failed"]
+								.as_str()
+								.map_or(false, |when_failed| !when_failed.is_empty()),
+					}
+				})
+				.collect::<Vec<_>>()
+		})
+		.unwrap_or_default();
+
+	for attribute in &attributes {
+		if attribute.failing && attribute.pre_failure {
+			status = status.max(DriveHealthStatus::Warning);
+			warnings.push(format!("{} is failing", attribute.name));
+		} else if BAD_SECTOR_ATTRIBUTES.contains(&attribute.id) && attribute.raw > 0 {
+			status = status.max(DriveHealthStatus::Warning);
+			warnings.push(format!("{}: {}", attribute.name, attribute.raw));
+		}
+	}
+
+	// NVMe drives report a health log instead of attributes
+	let nvme_log = &output["nvme_smart_health_information_log"];
+	if nvme_log.is_object() {
+		if nvme_log["critical_warning"].as_u64().unwrap_or_default() != 0 {
+			status = status.max(DriveHealthStatus::Warning);
+			warnings.push("The drive raised a critical warning".to_string());
+		}
+		if nvme_log["media_errors"].as_u64().unwrap_or_default() > 0 {
+			status = status.max(DriveHealthStatus::Warning);
+			warnings.push(format!("Media errors: {}", nvme_log["media_errors"]));
+		}
+		if nvme_log["percentage_used"].as_u64().unwrap_or_default() >= 100 {
+			status = status.max(DriveHealthStatus::Warning);
+			warnings.push("The drive reached its rated endurance".to_string());
+		}
+	}
+
+	DriveHealth {
+		device,
+		model: output["model_name"].as_str().map(ToString::to_string),
+		status,
+		warnings,
+		temperature_celsius: output["temperature"]["current"]
+			.as_i64()
+			.map(|temperature| temperature as i32),
+		power_on_hours: output["power_on_time"]["hours"]
+			.as_u64()
+			.map(|hours| hours as u32),
+		attributes,
+	}
+}
+
+/// Reads the SMART health of the drive backing a volume
+pub async fn read_drive_health(volume: &Volume) -> Result<DriveHealth, VolumeError> {
+	let device = drive_device(volume).ok_or_else(|| {
+		VolumeError::HealthUnavailable("the drive of this volume can't be queried".to_string())
+	})?;
+
+	let output = {
+		let device = device.clone();
+		spawn_blocking(move || {
+			Command::new("smartctl")
+				.args(["--json", "-H", "-A", &device])
+				.output()
+		})
+		.await
+		.map_err(|e| VolumeError::HealthUnavailable(e.to_string()))?
+		.map_err(|e| VolumeError::HealthUnavailable(format!("failed to run smartctl: {e}")))?
+	};
+
+	// The exit status is a bit mask, only its 2 lowest bits mean the drive couldn't be read,
+	// like when opening it isn't permitted
+	if output.status.code().map_or(true, |code| code & 0b11 != 0) {
+		return Err(VolumeError::HealthUnavailable(format!(
+			"smartctl couldn't read '{device}': {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		)));
+	}
+
+	let output = serde_json::from_slice::<Value>(&output.stdout)
+		.map_err(|e| VolumeError::HealthUnavailable(e.to_string()))?;
+
+	Ok(parse_smartctl_output(device, &output))
+}
+
+/// Health of the drives backing the volumes of this node
+pub async fn get_volumes_health() -> Result<Vec<VolumeHealth>, VolumeError> {
+	let mut volumes_health = vec![];
+
+	for volume in get_volumes()? {
+		let (health, error) = match read_drive_health(&volume).await {
+			Ok(health) => (Some(health), None),
+			Err(e) => (None, Some(e.to_string())),
+		};
+
+		volumes_health.push(VolumeHealth {
+			volume,
+			health,
+			error,
+		});
+	}
+
+	Ok(volumes_health)
+}
+
+/// Volume a path is in, the one with the longest mount point containing it
+pub fn volume_of_path<'a>(volumes: &'a [Volume], path: &Path) -> Option<&'a Volume> {
+	volumes
+		.iter()
+		.filter(|volume| path.starts_with(&volume.mount_point))
+		.max_by_key(|volume| volume.mount_point.len())
+}
+
+async fn check_locations_drives(
+	library: &Library,
+	warned: &mut HashSet<(location::id::Type, String)>,
+) -> Result<(), VolumeError> {
+	let volumes = get_volumes()?;
+
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(Some(library.node_local_id))])
+		.select(location::select!({ id pub_id path }))
+		.exec()
+		.await?;
+
+	for location in locations {
+		let Some(volume) = location
+			.path
+			.as_deref()
+			.and_then(|path| volume_of_path(&volumes, Path::new(path)))
+		else {
+			continue;
+		};
+
+		let health = match read_drive_health(volume).await {
+			Ok(health) => health,
+			Err(e) => {
+				debug!("Couldn't read the health of volume '{}': {e}", volume.name);
+				continue;
+			}
+		};
+
+		let key = (location.id, health.device.clone());
+
+		if health.status == DriveHealthStatus::Healthy {
+			warned.remove(&key);
+			continue;
+		}
+
+		// Only raised once, until the drive gets healthy again
+		if !warned.insert(key) {
+			continue;
+		}
+
+		warn!(
+			"Drive '{}' backing location <id='{}'> shows pre-failure indicators: {:?}",
+			health.device, location.id, health.warnings
+		);
+
+		library
+			.notify(NotificationKind::DriveHealthWarning {
+				location_id: location.id,
+				device: health.device.clone(),
+				warnings: health.warnings.clone(),
+			})
+			.await;
+
+		library.webhooks.dispatch(WebhookEvent::DriveHealthWarning {
+			location_id: location.id,
+			location_pub_id: Uuid::from_slice(&location.pub_id).unwrap_or_default(),
+			device: health.device,
+			status: health.status,
+			warnings: health.warnings,
+		});
+	}
+
+	Ok(())
+}
+
+/// Spawns a loop that periodically checks the health of the drives backing the locations of the
+/// library, raising a warning when one of them shows pre-failure indicators
+pub fn spawn_drive_health_monitor(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(HEALTH_CHECK_INTERVAL);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		let mut warned = HashSet::new();
+
+		loop {
+			tick.tick().await;
+
+			if let Err(e) = check_locations_drives(&library, &mut warned).await {
+				warn!(
+					"Failed to check the drives of library <id='{}'>: {e:#?}",
+					library.id
+				);
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use serde_json::json;
+
+	#[test]
+	fn bad_sectors_are_pre_failure_indicators() {
+		let health = parse_smartctl_output(
+			"/dev/sda".to_string(),
+			&json!({
+				"model_name": "Some HDD",
+				"smart_status": { "passed": true },
+				"temperature": { "current": 35 },
+				"ata_smart_attributes": { "table": [
+					{
+						"id": 5,
+						"name": "Reallocated_Sector_Ct",
+						"value": 100,
+						"worst": 100,
+						"thresh": 10,
+						"when_failed": "",
+						"flags": { "prefailure": true },
+						"raw": { "value": 8 }
+					},
+					{
+						"id": 9,
+						"name": "Power_On_Hours",
+						"value": 90,
+						"worst": 90,
+						"thresh": 0,
+						"when_failed": "",
+						"flags": { "prefailure": false },
+						"raw": { "value": 12000 }
+					}
+				]}
+			}),
+		);
+
+		assert_eq!(health.status, DriveHealthStatus::Warning);
+		assert_eq!(health.warnings.len(), 1);
+		assert_eq!(health.temperature_celsius, Some(35));
+		assert_eq!(health.attributes.len(), 2);
+	}
+
+	#[test]
+	fn failed_self_assessment() {
+		let health = parse_smartctl_output(
+			"/dev/nvme0n1".to_string(),
+			&json!({
+				"smart_status": { "passed": false },
+				"nvme_smart_health_information_log": {
+					"critical_warning": 0,
+					"media_errors": 0,
+					"percentage_used": 3
+				}
+			}),
+		);
+
+		assert_eq!(health.status, DriveHealthStatus::Failing);
+	}
+}
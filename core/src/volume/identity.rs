@@ -0,0 +1,252 @@
+//! Volumes are told apart by the UUID or serial number of their filesystem, so a drive mounted at
+//! another path, like a USB drive getting another letter on Windows, is still the same volume, and
+//! the locations on it are relinked to their new path.
+
+use crate::{
+	library::{Library, NotificationKind},
+	location::{relink_location, LocationError},
+	prisma::location,
+};
+
+use std::{
+	collections::HashSet,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+use super::{get_volumes, save_volume, Volume};
+
+/// How often the volumes of the node are checked for remounts
+const VOLUMES_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Share of the capacity of a volume under which its available space is reported as low
+const LOW_DISK_SPACE_RATIO: f64 = 0.05;
+
+/// A volume that was found mounted somewhere else than the last time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeRemount {
+	pub identifier: String,
+	pub old_mount_point: String,
+	pub new_mount_point: String,
+}
+
+/// UUID of the filesystem of a partition, from the links of `/dev/disk/by-uuid` to the devices
+#[cfg(target_os = "linux")]
+pub(super) fn volume_identifier(name: &str, _mount_point: &str) -> Option<String> {
+	let device = std::fs::canonicalize(name).ok()?;
+
+	std::fs::read_dir("/dev/disk/by-uuid")
+		.ok()?
+		.filter_map(Result::ok)
+		.find(|entry| std::fs::canonicalize(entry.path()).ok().as_ref() == Some(&device))
+		.and_then(|entry| entry.file_name().to_str().map(ToString::to_string))
+}
+
+#[cfg(target_os = "macos")]
+pub(super) fn volume_identifier(_name: &str, mount_point: &str) -> Option<String> {
+	let output = std::process::Command::new("diskutil")
+		.args(["info", mount_point])
+		.output()
+		.ok()?;
+
+	// eg: "   Volume UUID:               0A81F3B1-51D9-3335-B3E3-169C3640360D"
+	String::from_utf8(output.stdout)
+		.ok()?
+		.lines()
+		.find_map(|line| line.trim().strip_prefix("Volume UUID:"))
+		.map(|uuid| uuid.trim().to_string())
+		.filter(|uuid| !uuid.is_empty())
+}
+
+#[cfg(target_os = "windows")]
+pub(super) fn volume_identifier(_name: &str, mount_point: &str) -> Option<String> {
+	let drive = mount_point.trim_end_matches('\\');
+
+	let output = std::process::Command::new("cmd")
+		.args(["/C", &format!("vol {drive}")])
+		.output()
+		.ok()?;
+
+	// eg: " Volume Serial Number is 1234-ABCD"
+	String::from_utf8(output.stdout)
+		.ok()?
+		.lines()
+		.find_map(|line| line.trim().strip_prefix("Volume Serial Number is"))
+		.map(|serial| serial.trim().to_string())
+		.filter(|serial| !serial.is_empty())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub(super) fn volume_identifier(_name: &str, _mount_point: &str) -> Option<String> {
+	None
+}
+
+/// Path a location had on a volume, now that the volume is mounted somewhere else
+fn remounted_path(location_path: &Path, remount: &VolumeRemount) -> Option<PathBuf> {
+	location_path
+		.strip_prefix(&remount.old_mount_point)
+		.ok()
+		.map(|relative_path| Path::new(&remount.new_mount_point).join(relative_path))
+}
+
+/// Relinks the locations of this node that were on remounted volumes to their new path, if they
+/// are found there
+pub async fn reconnect_remounted_locations(library: &Library, remounts: &[VolumeRemount]) {
+	if remounts.is_empty() {
+		return;
+	}
+
+	let locations = match library
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(Some(library.node_local_id))])
+		.select(location::select!({ id path }))
+		.exec()
+		.await
+	{
+		Ok(locations) => locations,
+		Err(e) => {
+			error!("Failed to fetch the locations to reconnect: {e:#?}");
+			return;
+		}
+	};
+
+	for location in locations {
+		let Some(location_path) = location.path.as_deref().map(Path::new) else {
+			continue;
+		};
+
+		// The most specific mount point wins, for volumes mounted inside other volumes
+		let Some(new_path) = remounts
+			.iter()
+			.filter(|remount| location_path.starts_with(&remount.old_mount_point))
+			.max_by_key(|remount| remount.old_mount_point.len())
+			.and_then(|remount| remounted_path(location_path, remount))
+		else {
+			continue;
+		};
+
+		match relink_location(library, &new_path).await {
+			Ok(()) => {}
+			Err(LocationError::MissingMetadataFile(_)) => {
+				warn!(
+					"Location <id='{}'> wasn't found on its remounted volume at '{}'",
+					location.id,
+					new_path.display()
+				);
+				continue;
+			}
+			Err(e) => {
+				error!(
+					"Failed to relink location <id='{}'> to '{}': {e:#?}",
+					location.id,
+					new_path.display()
+				);
+				continue;
+			}
+		}
+
+		info!(
+			"Reconnected location <id='{}'> at '{}'",
+			location.id,
+			new_path.display()
+		);
+
+		// Watchers are bound to a path, so the location is added back with a new one
+		let location_manager = library.location_manager();
+		let rewatched = match location_manager.remove(location.id, library.clone()).await {
+			Ok(()) => location_manager.add(location.id, library.clone()).await,
+			Err(e) => Err(e),
+		};
+
+		if let Err(e) = rewatched {
+			error!(
+				"Failed to watch reconnected location <id='{}'>: {e:#?}",
+				location.id
+			);
+		}
+	}
+}
+
+fn is_low_on_space(volume: &Volume) -> bool {
+	volume.total_capacity > 0
+		&& (volume.available_capacity as f64) < volume.total_capacity as f64 * LOW_DISK_SPACE_RATIO
+}
+
+/// Notifies about the volumes running low on space, once until they have enough space again
+async fn check_low_disk_space(library: &Library, warned: &mut HashSet<String>) {
+	let volumes = match get_volumes() {
+		Ok(volumes) => volumes,
+		Err(e) => {
+			error!("Failed to get the volumes to check their space: {e:#?}");
+			return;
+		}
+	};
+
+	for volume in volumes {
+		if !is_low_on_space(&volume) {
+			warned.remove(&volume.mount_point);
+			continue;
+		}
+
+		if !warned.insert(volume.mount_point.clone()) {
+			continue;
+		}
+
+		library
+			.notify(NotificationKind::LowDiskSpace {
+				mount_point: volume.mount_point,
+				available_bytes: volume.available_capacity,
+				total_bytes: volume.total_capacity,
+			})
+			.await;
+	}
+}
+
+/// Spawns a loop that periodically saves the volumes of the node, reconnecting the locations of
+/// the library that were on volumes mounted somewhere else, and notifying about the ones running
+/// low on space
+pub fn spawn_volume_watcher(library: Library) {
+	library.clone().spawn(async move {
+		let mut tick = interval(VOLUMES_CHECK_INTERVAL);
+		tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		let mut low_on_space = HashSet::new();
+
+		loop {
+			tick.tick().await;
+
+			match save_volume(&library).await {
+				Ok(remounts) => reconnect_remounted_locations(&library, &remounts).await,
+				Err(e) => error!(
+					"Failed to save the volumes of library <id='{}'>: {e:#?}",
+					library.id
+				),
+			}
+
+			check_low_disk_space(&library, &mut low_on_space).await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn location_path_on_remounted_volume() {
+		let remount = VolumeRemount {
+			identifier: "1234-ABCD".to_string(),
+			old_mount_point: "/media/usb0".to_string(),
+			new_mount_point: "/media/usb1".to_string(),
+		};
+
+		assert_eq!(
+			remounted_path(Path::new("/media/usb0/photos"), &remount),
+			Some(PathBuf::from("/media/usb1/photos"))
+		);
+		assert_eq!(remounted_path(Path::new("/home/photos"), &remount), None);
+	}
+}
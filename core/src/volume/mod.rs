@@ -1,6 +1,7 @@
 use crate::{
 	library::Library,
 	prisma::volume::{self, *},
+	util::error::{coded_error, CoreErrorCode},
 };
 
 use serde::{Deserialize, Serialize};
@@ -10,6 +11,14 @@ use std::{fmt::Display, process::Command};
 use sysinfo::{DiskExt, System, SystemExt};
 use thiserror::Error;
 
+mod benchmark;
+mod health;
+mod identity;
+
+pub use benchmark::*;
+pub use health::*;
+pub use identity::*;
+
 #[derive(Serialize, Deserialize, Debug, Clone, Type)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum DiskType {
@@ -43,6 +52,8 @@ pub struct Volume {
 	pub disk_type: Option<DiskType>,
 	pub file_system: Option<String>,
 	pub is_root_filesystem: bool,
+	/// UUID or serial number of the filesystem, which stays the same when mounted elsewhere
+	pub identifier: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -51,16 +62,30 @@ pub enum VolumeError {
 	DatabaseErr(#[from] prisma_client_rust::QueryError),
 	#[error("FromUtf8Error: {0}")]
 	FromUtf8Error(#[from] std::string::FromUtf8Error),
+	#[error("volume not found <mount_point='{0}'>")]
+	NotFound(String),
+	#[error("drive health unavailable: {0}")]
+	HealthUnavailable(String),
 }
 
 impl From<VolumeError> for rspc::Error {
 	fn from(e: VolumeError) -> Self {
-		rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+		let code = match e {
+			VolumeError::NotFound(_) => CoreErrorCode::NotFound,
+			VolumeError::HealthUnavailable(_) => CoreErrorCode::VolumeUnavailable,
+			VolumeError::DatabaseErr(_) => CoreErrorCode::Database,
+			_ => CoreErrorCode::Internal,
+		};
+
+		coded_error(code, e)
 	}
 }
 
-pub async fn save_volume(library: &Library) -> Result<(), VolumeError> {
+/// Saves the volumes of this node, returning the ones that were mounted somewhere else the last
+/// time they were saved
+pub async fn save_volume(library: &Library) -> Result<Vec<VolumeRemount>, VolumeError> {
 	let volumes = get_volumes()?;
+	let mut remounts = vec![];
 
 	// enter all volumes associate with this client add to db
 	for volume in volumes {
@@ -69,8 +94,59 @@ pub async fn save_volume(library: &Library) -> Result<(), VolumeError> {
 			filesystem::set(volume.file_system.clone()),
 			total_bytes_capacity::set(volume.total_capacity.to_string()),
 			total_bytes_available::set(volume.available_capacity.to_string()),
+			identifier::set(volume.identifier.clone()),
 		];
 
+		if let Some(identifier) = &volume.identifier {
+			let known = library
+				.db
+				.volume()
+				.find_first(vec![
+					node_id::equals(library.node_local_id),
+					identifier::equals(Some(identifier.clone())),
+				])
+				.exec()
+				.await?;
+
+			if let Some(known) = known.filter(|known| known.mount_point != volume.mount_point) {
+				// Whatever was mounted there before is gone
+				library
+					.db
+					.volume()
+					.delete_many(vec![
+						node_id::equals(library.node_local_id),
+						mount_point::equals(volume.mount_point.clone()),
+						name::equals(volume.name.clone()),
+					])
+					.exec()
+					.await?;
+
+				library
+					.db
+					.volume()
+					.update(
+						id::equals(known.id),
+						params
+							.into_iter()
+							.chain([
+								name::set(volume.name),
+								mount_point::set(volume.mount_point.clone()),
+							])
+							.collect(),
+					)
+					.exec()
+					.await?;
+
+				remounts.push(VolumeRemount {
+					identifier: identifier.clone(),
+					old_mount_point: known.mount_point,
+					new_mount_point: volume.mount_point,
+				});
+
+				continue;
+			}
+		}
+
 		library
 			.db
 			.volume()
@@ -93,7 +169,7 @@ pub async fn save_volume(library: &Library) -> Result<(), VolumeError> {
 	}
 	// cleanup: remove all unmodified volumes associate with this client
 
-	Ok(())
+	Ok(remounts)
 }
 
 // TODO: Error handling in this function
@@ -137,6 +213,7 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 			}
 
 			(!mount_point.starts_with("/System")).then_some(Ok(Volume {
+				identifier: volume_identifier(&name, &mount_point),
 				name,
 				is_root_filesystem: mount_point == "/",
 				mount_point,
@@ -0,0 +1,243 @@
+//! Read/write WebDAV filesystem over the locations of a library, so OS file managers and other
+//! apps can mount the library as a network drive. Changes go straight to the locations, where
+//! the location watcher picks them up.
+
+use crate::{location::location_dir_name, prisma::location, Node};
+
+use std::{collections::HashSet, path::Component, time::SystemTime};
+
+use dav_server::{
+	davpath::DavPath,
+	fs::{
+		DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream,
+		OpenOptions, ReadDirMeta,
+	},
+	localfs::LocalFs,
+};
+use futures::{future, stream, FutureExt};
+use prisma_client_rust::QueryError;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum WebDavError {
+	#[error("library not found <id='{0}'>")]
+	LibraryNotFound(Uuid),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+/// Filesystem of a library, with a directory at its root for each location owned by this node
+pub async fn library_filesystem(
+	node: &Node,
+	library_id: Uuid,
+) -> Result<Box<dyn DavFileSystem>, WebDavError> {
+	let library = node
+		.library_manager
+		.get_library(library_id)
+		.await
+		.ok_or(WebDavError::LibraryNotFound(library_id))?;
+
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(Some(library.node_local_id))])
+		.exec()
+		.await?;
+
+	let mut names = HashSet::new();
+	let locations = locations
+		.into_iter()
+		.filter_map(|location| {
+			let path = location.path?;
+
+			Some(LocationDir {
+				name: location_dir_name(&mut names, location.id, location.name.as_deref()),
+				fs: LocalFs::new(path, false, false, cfg!(target_os = "macos")),
+			})
+		})
+		.collect();
+
+	Ok(Box::new(LibraryFs { locations }))
+}
+
+#[derive(Clone)]
+struct LibraryFs {
+	locations: Vec<LocationDir>,
+}
+
+#[derive(Clone)]
+struct LocationDir {
+	name: String,
+	fs: Box<LocalFs>,
+}
+
+impl LibraryFs {
+	/// The location a path is in along with the path inside of it, or `None` for the root
+	fn resolve(&self, path: &DavPath) -> FsResult<Option<(&LocationDir, DavPath)>> {
+		let rel_path = path.as_rel_ospath();
+		let Some(Component::Normal(name)) = rel_path.components().next() else {
+			return Ok(None);
+		};
+
+		let location = self
+			.locations
+			.iter()
+			.find(|location| name == location.name.as_str())
+			.ok_or(FsError::NotFound)?;
+
+		// The rest of the url, still encoded, after the name of the location
+		let url = path.as_url_string();
+		let rest = url
+			.trim_start_matches('/')
+			.split_once('/')
+			.map_or("", |(_, rest)| rest);
+
+		Ok(Some((
+			location,
+			DavPath::new(&format!("/{rest}")).map_err(|_| FsError::GeneralFailure)?,
+		)))
+	}
+
+	/// Like [`Self::resolve`], for changes, which can't be made to the root nor to the
+	/// directories of the locations themselves
+	fn resolve_inside(&self, path: &DavPath) -> FsResult<(&LocationDir, DavPath)> {
+		match self.resolve(path)? {
+			Some((location, path)) if path.as_url_string() != "/" => Ok((location, path)),
+			_ => Err(FsError::Forbidden),
+		}
+	}
+
+	/// Resolves both paths of a rename or copy, which must be in the same location
+	fn resolve_pair(
+		&self,
+		from: &DavPath,
+		to: &DavPath,
+	) -> FsResult<(&LocationDir, DavPath, DavPath)> {
+		let (from_location, from) = self.resolve_inside(from)?;
+		let (to_location, to) = self.resolve_inside(to)?;
+
+		if from_location.name != to_location.name {
+			return Err(FsError::NotImplemented);
+		}
+
+		Ok((from_location, from, to))
+	}
+}
+
+impl DavFileSystem for LibraryFs {
+	fn open<'a>(
+		&'a self,
+		path: &'a DavPath,
+		options: OpenOptions,
+	) -> FsFuture<'a, Box<dyn DavFile>> {
+		async move {
+			let (location, path) = self.resolve_inside(path)?;
+			location.fs.open(&path, options).await
+		}
+		.boxed()
+	}
+
+	fn read_dir<'a>(
+		&'a self,
+		path: &'a DavPath,
+		meta: ReadDirMeta,
+	) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+		async move {
+			match self.resolve(path)? {
+				Some((location, path)) => location.fs.read_dir(&path, meta).await,
+				None => {
+					let entries = self
+						.locations
+						.iter()
+						.map(|location| Box::new(location.clone()) as Box<dyn DavDirEntry>)
+						.collect::<Vec<_>>();
+
+					Ok(Box::pin(stream::iter(entries)) as FsStream<Box<dyn DavDirEntry>>)
+				}
+			}
+		}
+		.boxed()
+	}
+
+	fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+		async move {
+			match self.resolve(path)? {
+				Some((location, path)) => location.fs.metadata(&path).await,
+				None => Ok(Box::new(RootMetaData) as Box<dyn DavMetaData>),
+			}
+		}
+		.boxed()
+	}
+
+	fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+		async move {
+			let (location, path) = self.resolve_inside(path)?;
+			location.fs.create_dir(&path).await
+		}
+		.boxed()
+	}
+
+	fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+		async move {
+			let (location, path) = self.resolve_inside(path)?;
+			location.fs.remove_dir(&path).await
+		}
+		.boxed()
+	}
+
+	fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+		async move {
+			let (location, path) = self.resolve_inside(path)?;
+			location.fs.remove_file(&path).await
+		}
+		.boxed()
+	}
+
+	fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+		async move {
+			let (location, from, to) = self.resolve_pair(from, to)?;
+			location.fs.rename(&from, &to).await
+		}
+		.boxed()
+	}
+
+	fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+		async move {
+			let (location, from, to) = self.resolve_pair(from, to)?;
+			location.fs.copy(&from, &to).await
+		}
+		.boxed()
+	}
+}
+
+impl DavDirEntry for LocationDir {
+	fn name(&self) -> Vec<u8> {
+		self.name.as_bytes().to_vec()
+	}
+
+	fn metadata<'a>(&'a self) -> FsFuture<'a, Box<dyn DavMetaData>> {
+		match DavPath::new("/") {
+			Ok(root) => async move { self.fs.metadata(&root).await }.boxed(),
+			Err(_) => future::ready(Err(FsError::GeneralFailure)).boxed(),
+		}
+	}
+}
+
+/// The root of the share, which only exists in the share itself
+#[derive(Debug, Clone)]
+struct RootMetaData;
+
+impl DavMetaData for RootMetaData {
+	fn len(&self) -> u64 {
+		0
+	}
+
+	fn modified(&self) -> FsResult<SystemTime> {
+		Ok(SystemTime::now())
+	}
+
+	fn is_dir(&self) -> bool {
+		true
+	}
+}
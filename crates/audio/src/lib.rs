@@ -0,0 +1,166 @@
+use std::{fs::File, io, path::Path};
+
+use symphonia::core::{
+	audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+	formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+use thiserror::Error;
+
+type AudioResult<T> = Result<T, AudioError>;
+
+/// The maximum file size that an audio file can be in order to have a waveform generated.
+///
+/// This value is in MiB.
+const AUDIO_MAXIMUM_FILE_SIZE: u64 = 1048576 * 500;
+
+/// Samples are first reduced to this many peaks per second of audio, as the duration of the
+/// file isn't always known before decoding all of it
+const PEAKS_PER_SECOND: u32 = 100;
+
+#[derive(Error, Debug)]
+pub enum AudioError {
+	#[error("error while decoding the audio: {0}")]
+	Symphonia(#[from] SymphoniaError),
+	#[error("io error: {0}")]
+	Io(#[from] io::Error),
+	#[error("the file doesn't have any audio track")]
+	NoAudioTrack,
+	#[error("the file provided is too large (over 500MiB)")]
+	TooLarge,
+}
+
+/// Computes the waveform of an audio file, as `peaks_count` peaks of its loudest sample over
+/// every channel, from 0 to 255, relative to the loudest one of the whole file.
+///
+/// Corrupted packets are skipped instead of failing, so a damaged file still gets a waveform.
+pub fn audio_to_peaks(path: &Path, peaks_count: usize) -> AudioResult<Vec<u8>> {
+	let file = File::open(path)?;
+	if file.metadata()?.len() > AUDIO_MAXIMUM_FILE_SIZE {
+		return Err(AudioError::TooLarge);
+	}
+
+	let mut hint = Hint::new();
+	if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+		hint.with_extension(extension);
+	}
+
+	let mut format = symphonia::default::get_probe()
+		.format(
+			&hint,
+			MediaSourceStream::new(Box::new(file), Default::default()),
+			&FormatOptions::default(),
+			&MetadataOptions::default(),
+		)?
+		.format;
+
+	let track = format.default_track().ok_or(AudioError::NoAudioTrack)?;
+	let track_id = track.id;
+	let samples_per_peak =
+		(track.codec_params.sample_rate.unwrap_or(44100) / PEAKS_PER_SECOND).max(1) as usize;
+
+	let mut decoder =
+		symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+	let mut peaks = vec![];
+	let mut peak = 0f32;
+	let mut peak_samples = 0;
+	let mut sample_buf: Option<SampleBuffer<f32>> = None;
+
+	loop {
+		let packet = match format.next_packet() {
+			Ok(packet) => packet,
+			Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+			Err(e) => return Err(e.into()),
+		};
+
+		if packet.track_id() != track_id {
+			continue;
+		}
+
+		let decoded = match decoder.decode(&packet) {
+			Ok(decoded) => decoded,
+			Err(SymphoniaError::DecodeError(_)) => continue,
+			Err(e) => return Err(e.into()),
+		};
+
+		let channels = decoded.spec().channels.count().max(1);
+
+		// Packets are usually the same size, so the buffer is only reallocated for bigger ones
+		if sample_buf
+			.as_ref()
+			.map_or(true, |buf| buf.capacity() < decoded.capacity() * channels)
+		{
+			sample_buf = Some(SampleBuffer::new(
+				decoded.capacity() as u64,
+				*decoded.spec(),
+			));
+		}
+		let buf = sample_buf
+			.as_mut()
+			.expect("sample buffer was just allocated");
+		buf.copy_interleaved_ref(decoded);
+
+		for frame in buf.samples().chunks(channels) {
+			peak = frame
+				.iter()
+				.fold(peak, |peak, sample| peak.max(sample.abs()));
+			peak_samples += 1;
+
+			if peak_samples == samples_per_peak {
+				peaks.push(peak);
+				peak = 0.0;
+				peak_samples = 0;
+			}
+		}
+	}
+
+	if peak_samples > 0 {
+		peaks.push(peak);
+	}
+
+	Ok(merge_peaks(&peaks, peaks_count))
+}
+
+/// Merges, or stretches if there are fewer of them, `peaks` into `count` peaks, scaled so the
+/// loudest one is 255
+fn merge_peaks(peaks: &[f32], count: usize) -> Vec<u8> {
+	if peaks.is_empty() {
+		return vec![0; count];
+	}
+
+	let loudest = peaks.iter().copied().fold(0f32, f32::max);
+	if loudest == 0.0 {
+		return vec![0; count];
+	}
+
+	(0..count)
+		.map(|i| {
+			let start = i * peaks.len() / count;
+			let end = ((i + 1) * peaks.len() / count).max(start + 1);
+
+			let peak = peaks[start..end].iter().copied().fold(0f32, f32::max);
+
+			(peak / loudest * 255.0).round() as u8
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_merge_peaks() {
+		assert_eq!(
+			merge_peaks(&[0.1, 0.5, 0.25, 0.2, 0.0, 0.1], 3),
+			vec![255, 128, 51]
+		);
+
+		// Fewer peaks than requested are stretched
+		assert_eq!(merge_peaks(&[0.5, 1.0], 4), vec![128, 128, 255, 255]);
+
+		// Silence
+		assert_eq!(merge_peaks(&[0.0, 0.0], 2), vec![0, 0]);
+		assert_eq!(merge_peaks(&[], 2), vec![0, 0]);
+	}
+}
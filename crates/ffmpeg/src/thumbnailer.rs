@@ -1,6 +1,6 @@
 use crate::{film_strip_filter, MovieDecoder, ThumbnailSize, ThumbnailerError, VideoFrame};
 
-use std::{ops::Deref, path::Path};
+use std::{ops::Deref, path::Path, time::Duration};
 use tokio::{fs, task::spawn_blocking};
 use tracing::error;
 use webp::Encoder;
@@ -35,6 +35,7 @@ impl Thumbnailer {
 		let video_file_path = video_file_path.as_ref().to_path_buf();
 		let prefer_embedded_metadata = self.builder.prefer_embedded_metadata;
 		let seek_percentage = self.builder.seek_percentage;
+		let seek_timestamp = self.builder.seek_timestamp;
 		let size = self.builder.size;
 		let maintain_aspect_ratio = self.builder.maintain_aspect_ratio;
 		let with_film_strip = self.builder.with_film_strip;
@@ -46,10 +47,11 @@ impl Thumbnailer {
 			decoder.decode_video_frame()?;
 
 			if !decoder.embedded_metadata_is_available() {
-				let result = decoder.seek(
-					(decoder.get_video_duration().as_secs() as f32 * seek_percentage).round()
-						as i64,
-				);
+				let duration = decoder.get_video_duration();
+				let result = decoder.seek(match seek_timestamp {
+					Some(timestamp) if timestamp < duration => timestamp.as_secs() as i64,
+					_ => (duration.as_secs() as f32 * seek_percentage).round() as i64,
+				});
 
 				if let Err(err) = result {
 					error!("Failed to seek: {err:#?}");
@@ -88,6 +90,7 @@ pub struct ThumbnailerBuilder {
 	maintain_aspect_ratio: bool,
 	size: ThumbnailSize,
 	seek_percentage: f32,
+	seek_timestamp: Option<Duration>,
 	quality: f32,
 	prefer_embedded_metadata: bool,
 	with_film_strip: bool,
@@ -99,6 +102,7 @@ impl Default for ThumbnailerBuilder {
 			maintain_aspect_ratio: true,
 			size: ThumbnailSize::Size(128),
 			seek_percentage: 0.1,
+			seek_timestamp: None,
 			quality: 80.0,
 			prefer_embedded_metadata: true,
 			with_film_strip: true,
@@ -145,6 +149,13 @@ impl ThumbnailerBuilder {
 		Ok(self)
 	}
 
+	/// To get the thumbnail frame at a fixed timestamp instead of a percentage of the video.
+	/// Videos shorter than the timestamp fall back to `seek_percentage`.
+	pub fn seek_timestamp(mut self, seek_timestamp: Duration) -> Self {
+		self.seek_timestamp = Some(seek_timestamp);
+		self
+	}
+
 	/// Quality must be a value between 0.0 and 100.0
 	pub fn quality(mut self, quality: f32) -> Result<Self, ThumbnailerError> {
 		if !(0.0..=100.0).contains(&quality) {
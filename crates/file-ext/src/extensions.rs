@@ -86,6 +86,7 @@ extension_category_enum! {
 		Akw = [0x41, 0x4B, 0x57, 0x42],
 		Dng = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x44, 0x4E, 0x47, 0x00],
 		Cr2 = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x43, 0x52, 0x02, 0x00],
+		Cr3 = [0x66, 0x74, 0x79, 0x70, 0x63, 0x72, 0x78, 0x20] + 4,
 		Dcr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x44, 0x43, 0x52, 0x00],
 		Nwr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x4E, 0x57, 0x52, 0x00],
 		Nef = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x4E, 0x45, 0x46, 0x00],
@@ -96,7 +97,7 @@ extension_category_enum! {
 
 // audio extensions
 extension_category_enum! {
-	AudioExtension _ALL_AUDIO_EXTENSIONS {
+	AudioExtension ALL_AUDIO_EXTENSIONS {
 		Mp3 = [0x49, 0x44, 0x33],
 		Mp2 = [0xFF, 0xFB] | [0xFF, 0xFD],
 		M4a = [0x66, 0x74, 0x79, 0x70, 0x4D, 0x34, 0x41, 0x20] + 4,
@@ -175,7 +176,7 @@ extension_category_enum! {
 
 // text file extensions
 extension_category_enum! {
-	TextExtension _ALL_TEXT_EXTENSIONS {
+	TextExtension ALL_TEXT_EXTENSIONS {
 		Txt,
 		Rtf,
 		Md,
@@ -225,15 +226,19 @@ extension_category_enum! {
 
 // font extensions
 extension_category_enum! {
-	MeshExtension _ALL_MESH_EXTENSIONS {
+	MeshExtension ALL_MESH_EXTENSIONS {
 		Fbx = [0x46, 0x42, 0x58, 0x20],
 		Obj = [0x6F, 0x62, 0x6A],
+		Gltf = [0x7B],
+		Glb = [0x67, 0x6C, 0x54, 0x46],
+		// Only ASCII STLs, binary ones start with an arbitrary header
+		Stl = [0x73, 0x6F, 0x6C, 0x69, 0x64],
 	}
 }
 
 // code extensions
 extension_category_enum! {
-	CodeExtension _ALL_CODE_EXTENSIONS {
+	CodeExtension ALL_CODE_EXTENSIONS {
 		Rs,
 		Ts,
 		Tsx,
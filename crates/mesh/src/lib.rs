@@ -0,0 +1,447 @@
+use std::{
+	ffi::OsStr,
+	fs::{self, File},
+	io::BufReader,
+	path::Path,
+};
+
+use image::{imageops, DynamicImage, RgbaImage};
+use thiserror::Error;
+
+type MeshResult<T> = Result<T, MeshError>;
+
+type Vec3 = [f32; 3];
+/// Column-major, like glTF matrices
+type Mat4 = [[f32; 4]; 4];
+
+/// The maximum file size that a 3D model can be in order to have a thumbnail generated.
+///
+/// This value is in MiB.
+const MESH_MAXIMUM_FILE_SIZE: u64 = 1048576 * 200;
+
+/// Rendering is done on the CPU, so huge scans are skipped instead of taking minutes
+const MAXIMUM_TRIANGLES: usize = 5_000_000;
+
+/// Each side is rendered this many times bigger, then scaled down, to smooth the edges
+const SUPERSAMPLING: u32 = 2;
+
+/// Models are seen from the front right and slightly from above, like most asset browsers do
+const CAMERA_YAW: f32 = -std::f32::consts::FRAC_PI_4;
+const CAMERA_PITCH: f32 = std::f32::consts::FRAC_PI_6;
+
+/// Fraction of the image taken by the bounding sphere of the model
+const MODEL_SCALE: f32 = 0.95;
+
+const LIGHT_DIRECTION: Vec3 = [-0.41, 0.58, 0.7];
+const AMBIENT_LIGHT: f32 = 0.25;
+
+/// Used when the file doesn't have materials, or they aren't read
+const DEFAULT_COLOR: Vec3 = [0.72, 0.74, 0.78];
+
+#[derive(Error, Debug)]
+pub enum MeshError {
+	#[error("error while loading the glTF model: {0}")]
+	Gltf(#[from] gltf::Error),
+	#[error("error while loading the OBJ model: {0}")]
+	Obj(#[from] tobj::LoadError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("the model format isn't supported")]
+	UnsupportedFormat,
+	#[error("the model doesn't have any triangle")]
+	Empty,
+	#[error("the model has too many triangles (over 5 million)")]
+	TooManyTriangles,
+	#[error("the model provided is too large (over 200MiB)")]
+	TooLarge,
+}
+
+struct Triangle {
+	vertices: [Vec3; 3],
+	color: Vec3,
+}
+
+/// Renders a 3D model (glTF, GLB, OBJ or STL) offscreen, on the CPU, to a `size` pixels wide
+/// square image with a transparent background.
+///
+/// Textures aren't applied, triangles are flat shaded with the base color of their material.
+pub fn mesh_to_dynamic_image(path: &Path, size: u32) -> MeshResult<DynamicImage> {
+	if fs::metadata(path)?.len() > MESH_MAXIMUM_FILE_SIZE {
+		return Err(MeshError::TooLarge);
+	}
+
+	let extension = path
+		.extension()
+		.and_then(OsStr::to_str)
+		.map(str::to_ascii_lowercase)
+		.unwrap_or_default();
+
+	let triangles = match extension.as_str() {
+		"gltf" | "glb" => load_gltf(path)?,
+		"obj" => load_obj(path)?,
+		"stl" => load_stl(path)?,
+		_ => return Err(MeshError::UnsupportedFormat),
+	};
+
+	if triangles.is_empty() {
+		return Err(MeshError::Empty);
+	}
+
+	Ok(render(&triangles, size))
+}
+
+fn load_gltf(path: &Path) -> MeshResult<Vec<Triangle>> {
+	let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+	// Textures are never decoded, only the buffers holding the geometry are loaded
+	let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+
+	let mut triangles = vec![];
+
+	if let Some(scene) = document
+		.default_scene()
+		.or_else(|| document.scenes().next())
+	{
+		for node in scene.nodes() {
+			load_gltf_node(&node, IDENTITY, &buffers, &mut triangles)?;
+		}
+	}
+
+	Ok(triangles)
+}
+
+fn load_gltf_node(
+	node: &gltf::Node,
+	parent_transform: Mat4,
+	buffers: &[gltf::buffer::Data],
+	triangles: &mut Vec<Triangle>,
+) -> MeshResult<()> {
+	let transform = mat4_mul(&parent_transform, &node.transform().matrix());
+
+	if let Some(mesh) = node.mesh() {
+		for primitive in mesh.primitives() {
+			// Points and lines aren't drawn
+			if primitive.mode() != gltf::mesh::Mode::Triangles {
+				continue;
+			}
+
+			let reader =
+				primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+			let Some(positions) = reader.read_positions() else {
+				continue;
+			};
+			let positions = positions
+				.map(|position| transform_point(&transform, position))
+				.collect::<Vec<_>>();
+
+			let indices = match reader.read_indices() {
+				Some(indices) => indices.into_u32().collect(),
+				None => (0..positions.len() as u32).collect::<Vec<_>>(),
+			};
+
+			let [r, g, b, _] = primitive
+				.material()
+				.pbr_metallic_roughness()
+				.base_color_factor();
+
+			push_indexed_triangles(triangles, &positions, &indices, [r, g, b])?;
+		}
+	}
+
+	for child in node.children() {
+		load_gltf_node(&child, transform, buffers, triangles)?;
+	}
+
+	Ok(())
+}
+
+fn load_obj(path: &Path) -> MeshResult<Vec<Triangle>> {
+	let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+	let mut triangles = vec![];
+
+	for model in models {
+		let positions = model
+			.mesh
+			.positions
+			.chunks_exact(3)
+			.map(|position| [position[0], position[1], position[2]])
+			.collect::<Vec<_>>();
+
+		push_indexed_triangles(
+			&mut triangles,
+			&positions,
+			&model.mesh.indices,
+			DEFAULT_COLOR,
+		)?;
+	}
+
+	Ok(triangles)
+}
+
+fn load_stl(path: &Path) -> MeshResult<Vec<Triangle>> {
+	let mesh = stl_io::read_stl(&mut BufReader::new(File::open(path)?))?;
+
+	let positions = mesh
+		.vertices
+		.iter()
+		.map(|vertex| [vertex[0], vertex[1], vertex[2]])
+		.collect::<Vec<_>>();
+
+	let indices = mesh
+		.faces
+		.iter()
+		.flat_map(|face| face.vertices.map(|index| index as u32))
+		.collect::<Vec<_>>();
+
+	let mut triangles = vec![];
+	push_indexed_triangles(&mut triangles, &positions, &indices, DEFAULT_COLOR)?;
+
+	Ok(triangles)
+}
+
+/// Triangles referencing missing vertices are skipped
+fn push_indexed_triangles(
+	triangles: &mut Vec<Triangle>,
+	positions: &[Vec3],
+	indices: &[u32],
+	color: Vec3,
+) -> MeshResult<()> {
+	for triangle in indices.chunks_exact(3) {
+		let (Some(a), Some(b), Some(c)) = (
+			positions.get(triangle[0] as usize),
+			positions.get(triangle[1] as usize),
+			positions.get(triangle[2] as usize),
+		) else {
+			continue;
+		};
+
+		if triangles.len() == MAXIMUM_TRIANGLES {
+			return Err(MeshError::TooManyTriangles);
+		}
+
+		triangles.push(Triangle {
+			vertices: [*a, *b, *c],
+			color,
+		});
+	}
+
+	Ok(())
+}
+
+/// Orthographic rendering with a depth buffer, the model being scaled to fit the image whatever
+/// its orientation
+fn render(triangles: &[Triangle], size: u32) -> DynamicImage {
+	let (center, radius) = bounding_sphere(triangles);
+
+	let (yaw_sin, yaw_cos) = CAMERA_YAW.sin_cos();
+	let (pitch_sin, pitch_cos) = CAMERA_PITCH.sin_cos();
+
+	// To view space, where x goes right, y goes up and z goes towards the camera
+	let to_view = |[x, y, z]: Vec3| -> Vec3 {
+		let [x, y, z] = [
+			(x - center[0]) / radius,
+			(y - center[1]) / radius,
+			(z - center[2]) / radius,
+		];
+		let (x, z) = (x * yaw_cos + z * yaw_sin, z * yaw_cos - x * yaw_sin);
+		let (y, z) = (y * pitch_cos - z * pitch_sin, y * pitch_sin + z * pitch_cos);
+
+		[x, y, z]
+	};
+
+	let render_size = (size * SUPERSAMPLING).max(1) as usize;
+	let half_size = render_size as f32 / 2.0;
+	let scale = half_size * MODEL_SCALE;
+
+	let mut pixels = vec![0u8; render_size * render_size * 4];
+	let mut depths = vec![f32::NEG_INFINITY; render_size * render_size];
+
+	let light = normalize(LIGHT_DIRECTION);
+
+	for triangle in triangles {
+		let [a, b, c] = triangle.vertices.map(to_view);
+
+		// Meshes aren't always closed nor consistently wound, so both faces are lit the same
+		let normal = normalize(cross(sub(b, a), sub(c, a)));
+		let intensity = AMBIENT_LIGHT + (1.0 - AMBIENT_LIGHT) * dot(normal, light).abs();
+		let color = triangle
+			.color
+			.map(|channel| (channel * intensity * 255.0).clamp(0.0, 255.0) as u8);
+
+		// To screen space, where y goes down
+		let [a, b, c] =
+			[a, b, c].map(|[x, y, z]| [half_size + x * scale, half_size - y * scale, z]);
+
+		let area = edge(a, b, c);
+		if area.abs() < f32::EPSILON {
+			continue;
+		}
+
+		let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+		let max_x = (a[0].max(b[0]).max(c[0]).ceil() as usize).min(render_size - 1);
+		let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as usize;
+		let max_y = (a[1].max(b[1]).max(c[1]).ceil() as usize).min(render_size - 1);
+
+		for y in min_y..=max_y {
+			for x in min_x..=max_x {
+				let point = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+
+				let wa = edge(b, c, point) / area;
+				let wb = edge(c, a, point) / area;
+				let wc = edge(a, b, point) / area;
+				if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+					continue;
+				}
+
+				let depth = wa * a[2] + wb * b[2] + wc * c[2];
+				let index = y * render_size + x;
+				if depth <= depths[index] {
+					continue;
+				}
+
+				depths[index] = depth;
+				pixels[index * 4..index * 4 + 4]
+					.copy_from_slice(&[color[0], color[1], color[2], 255]);
+			}
+		}
+	}
+
+	let img = RgbaImage::from_raw(render_size as u32, render_size as u32, pixels)
+		.expect("buffer has the size of the image");
+
+	DynamicImage::ImageRgba8(imageops::resize(
+		&img,
+		size,
+		size,
+		imageops::FilterType::Triangle,
+	))
+}
+
+fn bounding_sphere(triangles: &[Triangle]) -> (Vec3, f32) {
+	let vertices = triangles.iter().flat_map(|triangle| triangle.vertices);
+
+	let (min, max) = vertices.clone().fold(
+		([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+		|(min, max), vertex| {
+			(
+				[
+					min[0].min(vertex[0]),
+					min[1].min(vertex[1]),
+					min[2].min(vertex[2]),
+				],
+				[
+					max[0].max(vertex[0]),
+					max[1].max(vertex[1]),
+					max[2].max(vertex[2]),
+				],
+			)
+		},
+	);
+
+	let center = [
+		(min[0] + max[0]) / 2.0,
+		(min[1] + max[1]) / 2.0,
+		(min[2] + max[2]) / 2.0,
+	];
+
+	let radius = vertices
+		.map(|vertex| length(sub(vertex, center)))
+		.fold(0.0, f32::max);
+
+	// A single point, or a degenerate model, can't be scaled to fit the image
+	(center, if radius > 0.0 { radius } else { 1.0 })
+}
+
+/// Twice the signed area of the triangle `abc`, on the screen plane
+fn edge(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+	(b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+const IDENTITY: Mat4 = [
+	[1.0, 0.0, 0.0, 0.0],
+	[0.0, 1.0, 0.0, 0.0],
+	[0.0, 0.0, 1.0, 0.0],
+	[0.0, 0.0, 0.0, 1.0],
+];
+
+fn mat4_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+	let mut res = [[0.0; 4]; 4];
+	for (col, res_col) in res.iter_mut().enumerate() {
+		for (row, value) in res_col.iter_mut().enumerate() {
+			*value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+		}
+	}
+
+	res
+}
+
+fn transform_point(m: &Mat4, [x, y, z]: Vec3) -> Vec3 {
+	[0, 1, 2].map(|row| m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row])
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+	[a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+	[
+		a[1] * b[2] - a[2] * b[1],
+		a[2] * b[0] - a[0] * b[2],
+		a[0] * b[1] - a[1] * b[0],
+	]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+	a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn length(a: Vec3) -> f32 {
+	dot(a, a).sqrt()
+}
+
+fn normalize(a: Vec3) -> Vec3 {
+	let length = length(a);
+	if length == 0.0 {
+		return a;
+	}
+
+	a.map(|value| value / length)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_render_fills_model_bounds() {
+		// A square facing the camera once rotated, made of two triangles
+		let triangles = [
+			[[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [1.0, 1.0, 0.0]],
+			[[-1.0, -1.0, 0.0], [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0]],
+		]
+		.map(|vertices| Triangle {
+			vertices,
+			color: DEFAULT_COLOR,
+		});
+
+		let img = render(&triangles, 64).to_rgba8();
+		assert_eq!(img.dimensions(), (64, 64));
+
+		// The center is covered by the model, while the corners stay transparent
+		assert_eq!(img.get_pixel(32, 32)[3], 255);
+		assert_eq!(img.get_pixel(0, 0)[3], 0);
+		assert_eq!(img.get_pixel(63, 63)[3], 0);
+	}
+
+	#[test]
+	fn test_transform_point() {
+		let mut translation = IDENTITY;
+		translation[3] = [1.0, 2.0, 3.0, 1.0];
+
+		assert_eq!(
+			transform_point(&mat4_mul(&IDENTITY, &translation), [1.0, 1.0, 1.0]),
+			[2.0, 3.0, 4.0]
+		);
+	}
+}
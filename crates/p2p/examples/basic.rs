@@ -1,6 +1,9 @@
 use std::{collections::HashMap, env, time::Duration};
 
-use sd_p2p::{spacetime::SpaceTimeStream, Event, Keypair, Manager, Metadata, MetadataManager};
+use sd_p2p::{
+	spacetime::{ProtocolInfo, SpaceTimeStream},
+	Event, Keypair, Manager, Metadata, MetadataManager,
+};
 use tokio::{io::AsyncReadExt, time::sleep};
 use tracing::{debug, error, info};
 
@@ -48,9 +51,14 @@ async fn main() {
 		name: "TODO".to_string(),
 	});
 
-	let (manager, mut stream) = Manager::new("p2p-demo", &keypair, metadata_manager)
-		.await
-		.unwrap();
+	let (manager, mut stream) = Manager::new(
+		"p2p-demo",
+		&keypair,
+		metadata_manager,
+		ProtocolInfo::new(1, 1),
+	)
+	.await
+	.unwrap();
 
 	info!(
 		"Node '{}' is now online listening at addresses: {:?}",
@@ -0,0 +1,61 @@
+//! UDP hole punching. QUIC dials go out from the socket the node listens on, so a dial to the
+//! public address of a peer behind a NAT opens a mapping in our own NAT for the packets of that
+//! peer. When both peers dial each other at the same time, agreed on beforehand through a
+//! rendezvous server, the first packets of each are dropped by the NAT of the other but open the
+//! way for the next ones, giving a direct connection between peers that can't reach each other.
+
+use std::{
+	net::SocketAddr,
+	time::{Duration, SystemTime},
+};
+
+use tokio::time::{sleep, Instant};
+use tracing::debug;
+
+use crate::{Manager, ManagerStreamAction, Metadata, PeerId};
+
+/// Time between two dials of a hole punch, NATs usually drop idle mappings after tens of seconds
+const PUNCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the peers keep dialing each other before giving up, covering clocks slightly apart
+const PUNCH_WINDOW: Duration = Duration::from_secs(5);
+
+impl<TMetadata: Metadata> Manager<TMetadata> {
+	/// Dials the peer over and over from `at`, when it is to dial us too, returning whether a direct
+	/// connection was made to it
+	pub async fn hole_punch(
+		&self,
+		peer_id: PeerId,
+		addresses: Vec<SocketAddr>,
+		at: SystemTime,
+	) -> bool {
+		if let Ok(wait) = at.duration_since(SystemTime::now()) {
+			sleep(wait).await;
+		}
+
+		debug!("Hole punching to peer '{peer_id}' at '{addresses:?}'");
+		let deadline = Instant::now() + PUNCH_WINDOW;
+		while Instant::now() < deadline {
+			if self.is_connected(peer_id).await {
+				debug!("Hole punched to peer '{peer_id}'");
+				return true;
+			}
+
+			self.emit(ManagerStreamAction::Dial {
+				peer_id,
+				addresses: addresses.clone(),
+			})
+			.await;
+
+			sleep(PUNCH_INTERVAL).await;
+		}
+
+		self.is_connected(peer_id).await
+	}
+
+	async fn is_connected(&self, peer_id: PeerId) -> bool {
+		self.get_connected_peers()
+			.await
+			.map(|peers| peers.contains(&peer_id))
+			.unwrap_or(false)
+	}
+}
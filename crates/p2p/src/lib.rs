@@ -1,6 +1,7 @@
 //! Rust Peer to Peer Networking Library
 
 mod event;
+mod hole_punch;
 mod manager;
 mod manager_stream;
 mod mdns;
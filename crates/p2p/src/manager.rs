@@ -13,7 +13,7 @@ use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, warn};
 
 use crate::{
-	spacetime::{SpaceTime, UnicastStream},
+	spacetime::{HandshakeError, ProtocolInfo, SpaceTime, UnicastStream},
 	DiscoveredPeer, Keypair, ManagerStream, ManagerStreamAction, Mdns, MdnsState, Metadata,
 	MetadataManager, PeerId,
 };
@@ -25,6 +25,7 @@ pub struct Manager<TMetadata: Metadata> {
 	pub(crate) peer_id: PeerId,
 	pub(crate) application_name: &'static [u8],
 	pub(crate) stream_id: AtomicU64,
+	pub(crate) protocol: ProtocolInfo,
 	event_stream_tx: mpsc::Sender<ManagerStreamAction<TMetadata>>,
 }
 
@@ -34,6 +35,7 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 		application_name: &'static str,
 		keypair: &Keypair,
 		metadata_manager: Arc<MetadataManager<TMetadata>>,
+		protocol: ProtocolInfo,
 	) -> Result<(Arc<Self>, ManagerStream<TMetadata>), ManagerError> {
 		application_name
 			.chars()
@@ -56,6 +58,7 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 					.to_vec(),
 			)),
 			stream_id: AtomicU64::new(0),
+			protocol,
 			peer_id,
 			event_stream_tx,
 		});
@@ -131,18 +134,35 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 	}
 
 	#[allow(clippy::unused_unit)] // TODO: Remove this clippy override once error handling is added
-	pub async fn stream(&self, peer_id: PeerId) -> Result<UnicastStream, ()> {
+	pub async fn stream(&self, peer_id: PeerId) -> Result<UnicastStream, StreamError> {
 		// TODO: With this system you can send to any random peer id. Can I reduce that by requiring `.connect(peer_id).unwrap().send(data)` or something like that.
 		let (tx, rx) = oneshot::channel();
 		self.emit(ManagerStreamAction::StartStream(peer_id, tx))
 			.await;
-		let mut stream = rx.await.map_err(|_| {
+		let io = rx.await.map_err(|_| {
 			warn!("failed to queue establishing stream to peer '{peer_id}'!");
 
-			()
+			StreamError::Queue
 		})?;
-		stream.write_discriminator().await.unwrap(); // TODO: Error handling
-		Ok(stream)
+
+		UnicastStream::connect(io, &self.protocol)
+			.await
+			.map_err(|e| {
+				warn!("handshake with peer '{peer_id}' failed: {e}");
+
+				e.into()
+			})
+	}
+
+	/// What the application on this node speaks
+	pub fn protocol(&self) -> &ProtocolInfo {
+		&self.protocol
+	}
+
+	/// Connects to a peer at addresses learned outside of discovery, like from a relay
+	pub async fn dial(&self, peer_id: PeerId, addresses: Vec<SocketAddr>) {
+		self.emit(ManagerStreamAction::Dial { peer_id, addresses })
+			.await;
 	}
 
 	pub async fn broadcast(&self, data: Vec<u8>) {
@@ -161,6 +181,14 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 	}
 }
 
+#[derive(Error, Debug)]
+pub enum StreamError {
+	#[error("failed to queue establishing the stream")]
+	Queue,
+	#[error(transparent)]
+	Handshake(#[from] HandshakeError),
+}
+
 #[derive(Error, Debug)]
 pub enum ManagerError {
 	#[error(
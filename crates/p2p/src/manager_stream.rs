@@ -12,7 +12,7 @@ use libp2p::{
 	futures::StreamExt,
 	swarm::{
 		dial_opts::{DialOpts, PeerCondition},
-		NotifyHandler, SwarmEvent, ToSwarm,
+		NegotiatedSubstream, NotifyHandler, SwarmEvent, ToSwarm,
 	},
 	Swarm,
 };
@@ -21,7 +21,7 @@ use tracing::{debug, error, info, warn};
 
 use crate::{
 	quic_multiaddr_to_socketaddr, socketaddr_to_quic_multiaddr,
-	spacetime::{OutboundRequest, SpaceTime, UnicastStream},
+	spacetime::{OutboundRequest, SpaceTime},
 	Event, Manager, Mdns, Metadata, PeerId,
 };
 
@@ -37,7 +37,7 @@ pub enum ManagerStreamAction<TMetadata: Metadata> {
 		addresses: Vec<SocketAddr>,
 	},
 	/// TODO
-	StartStream(PeerId, oneshot::Sender<UnicastStream>),
+	StartStream(PeerId, oneshot::Sender<NegotiatedSubstream>),
 	/// TODO
 	BroadcastData(Vec<u8>),
 	/// the node is shutting down. The `ManagerStream` should convert this into `Event::Shutdown`
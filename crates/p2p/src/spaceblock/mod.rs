@@ -18,6 +18,10 @@ use tracing::debug;
 
 use crate::spacetime::{SpaceTimeStream, UnicastStream};
 
+mod parallel;
+
+pub use parallel::*;
+
 /// TODO
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockSize(u32); // Max block size is gonna be 3.9GB which is stupidly overkill
@@ -158,12 +162,13 @@ impl<'a> Block<'a> {
 /// TODO
 pub struct Transfer<'a, F> {
 	req: &'a SpaceblockRequest,
+	/// Called with the amount of bytes transferred so far, after each block
 	on_progress: F,
 }
 
 impl<'a, F> Transfer<'a, F>
 where
-	F: Fn(u8) + 'a,
+	F: Fn(u64) + 'a,
 {
 	pub fn new(req: &'a SpaceblockRequest, on_progress: F) -> Self {
 		Self { req, on_progress }
@@ -181,7 +186,7 @@ where
 		loop {
 			let read = file.read(&mut buf[..]).await.unwrap(); // TODO: Error handling
 			offset += read as u64;
-			(self.on_progress)(((self.req.size / offset) * 100) as u8); // SAFETY: Percent must be between 0 and 100
+			(self.on_progress)(offset);
 
 			if read == 0 {
 				if offset != self.req.size {
@@ -218,7 +223,7 @@ where
 			// TODO: Timeout if nothing is being received
 			let block = Block::from_stream(stream, &mut data_buf).await.unwrap(); // TODO: Error handling
 			offset += block.size;
-			(self.on_progress)(((self.req.size / offset) * 100) as u8); // SAFETY: Percent must be between 0 and 100
+			(self.on_progress)(offset);
 
 			debug!(
 				"Received block at offset {} of size {}",
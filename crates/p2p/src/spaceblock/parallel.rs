@@ -0,0 +1,418 @@
+//! Transfers spread over several streams to the same peer. A single stream is limited by its own
+//! flow control window, which leaves most of the bandwidth of links with a high latency unused.
+//!
+//! The sender starts with one stream and opens more while each new one still makes the transfer
+//! faster, stopping once the link is saturated. Streams take the next block when the previous one
+//! was written, so a congested stream takes fewer of them, and the blocks of a stream that fails
+//! are sent again over the others. Blocks carry their offset, so the receiver writes them where
+//! they belong whatever the stream and the order they arrive in.
+
+use std::{
+	collections::{HashSet, VecDeque},
+	future::Future,
+	io::{self, SeekFrom},
+	path::Path,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use libp2p::futures::stream::{FuturesUnordered, StreamExt};
+use thiserror::Error;
+use tokio::{
+	fs::File,
+	io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, warn};
+
+use super::SpaceblockRequest;
+
+/// Most streams a single transfer is spread over
+pub const MAX_TRANSFER_STREAMS: usize = 8;
+/// How often the sender checks whether another stream would make the transfer faster
+const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+/// How much faster the transfer must have become since the last stream was opened to open another
+const MIN_THROUGHPUT_GAIN: f64 = 1.1;
+
+#[derive(Debug, Error)]
+pub enum TransferError {
+	#[error("io error on transfer stream: {0}")]
+	Io(#[from] io::Error),
+	#[error("io error accessing transferred file: {0}")]
+	File(io::Error),
+	#[error("received block at offset {offset} of size {size} outside of the file")]
+	InvalidBlock { offset: u64, size: u64 },
+	#[error("every stream of the transfer failed")]
+	AllStreamsFailed,
+}
+
+/// Offsets of the blocks left to send, those of failed streams first
+struct BlockQueue {
+	next: u64,
+	retry: VecDeque<u64>,
+}
+
+struct Scheduler {
+	size: u64,
+	block_size: u64,
+	queue: Mutex<BlockQueue>,
+	bytes_sent: AtomicU64,
+}
+
+impl Scheduler {
+	fn next_block(&self) -> Option<(u64, u64)> {
+		let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+
+		let offset = match queue.retry.pop_front() {
+			Some(offset) => offset,
+			None if queue.next < self.size => {
+				let offset = queue.next;
+				queue.next += self.block_size;
+				offset
+			}
+			None => return None,
+		};
+
+		Some((offset, self.block_size.min(self.size - offset)))
+	}
+
+	fn retry(&self, offset: u64) {
+		self.queue
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.retry
+			.push_back(offset);
+	}
+
+	fn is_exhausted(&self) -> bool {
+		let queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+		queue.retry.is_empty() && queue.next >= self.size
+	}
+}
+
+async fn write_block(
+	stream: &mut (impl AsyncWrite + Unpin),
+	offset: u64,
+	data: &[u8],
+) -> io::Result<()> {
+	let mut head = [0u8; 16];
+	head[..8].copy_from_slice(&offset.to_le_bytes());
+	head[8..].copy_from_slice(&(data.len() as u64).to_le_bytes());
+
+	stream.write_all(&head).await?;
+	stream.write_all(data).await
+}
+
+/// Reads the next block into `buf`, returning its offset and size, or `None` once the sender
+/// closed the stream
+async fn read_block(
+	stream: &mut (impl AsyncRead + Unpin),
+	buf: &mut [u8],
+	file_size: u64,
+) -> Result<Option<(u64, u64)>, TransferError> {
+	let mut head = [0u8; 16];
+	match stream.read_exact(&mut head).await {
+		Ok(_) => {}
+		Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(e) => return Err(e.into()),
+	}
+
+	let offset = u64::from_le_bytes(head[..8].try_into().expect("slice of 8 bytes"));
+	let size = u64::from_le_bytes(head[8..].try_into().expect("slice of 8 bytes"));
+
+	if size > buf.len() as u64 || offset.checked_add(size).map_or(true, |end| end > file_size) {
+		return Err(TransferError::InvalidBlock { offset, size });
+	}
+
+	stream.read_exact(&mut buf[..size as usize]).await?;
+
+	Ok(Some((offset, size)))
+}
+
+/// Sends the blocks handed out by the scheduler over one stream, until there are none left
+async fn send_blocks(
+	mut stream: impl AsyncWrite + Unpin,
+	path: &Path,
+	scheduler: &Scheduler,
+	on_progress: &impl Fn(u64),
+) -> Result<(), TransferError> {
+	let mut file = File::open(path).await.map_err(TransferError::File)?;
+	let mut buf = vec![0u8; scheduler.block_size as usize];
+
+	while let Some((offset, size)) = scheduler.next_block() {
+		let data = &mut buf[..size as usize];
+
+		let read = async {
+			file.seek(SeekFrom::Start(offset)).await?;
+			file.read_exact(data).await
+		};
+		if let Err(e) = read.await {
+			scheduler.retry(offset);
+			return Err(TransferError::File(e));
+		}
+
+		if let Err(e) = write_block(&mut stream, offset, data).await {
+			scheduler.retry(offset);
+			return Err(e.into());
+		}
+
+		on_progress(scheduler.bytes_sent.fetch_add(size, Ordering::Relaxed) + size);
+	}
+
+	stream.shutdown().await?;
+
+	Ok(())
+}
+
+/// Sending side of a transfer spread over several streams
+pub struct ParallelTransfer<'a, F> {
+	req: &'a SpaceblockRequest,
+	/// Called with the amount of bytes transferred so far, after each block
+	on_progress: F,
+}
+
+impl<'a, F> ParallelTransfer<'a, F>
+where
+	F: Fn(u64) + 'a,
+{
+	pub fn new(req: &'a SpaceblockRequest, on_progress: F) -> Self {
+		Self { req, on_progress }
+	}
+
+	/// Sends the file at `path` over `stream`, and over the streams `open_stream` opens while they
+	/// make the transfer faster. `open_stream` returning `None` is taken as the peer not accepting
+	/// more streams.
+	pub async fn send<S, O, Fut>(
+		&self,
+		path: &Path,
+		stream: S,
+		mut open_stream: O,
+	) -> Result<(), TransferError>
+	where
+		S: AsyncWrite + Unpin,
+		O: FnMut() -> Fut,
+		Fut: Future<Output = Option<S>>,
+	{
+		let scheduler = Scheduler {
+			size: self.req.size,
+			block_size: self.req.block_size.size() as u64,
+			queue: Mutex::new(BlockQueue {
+				next: 0,
+				retry: VecDeque::new(),
+			}),
+			bytes_sent: AtomicU64::new(0),
+		};
+
+		let mut workers = FuturesUnordered::new();
+		workers.push(send_blocks(stream, path, &scheduler, &self.on_progress));
+		let mut streams = 1;
+
+		let mut probe = interval(PROBE_INTERVAL);
+		probe.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		probe.tick().await;
+		let mut probing = true;
+		let mut last_probe = (Instant::now(), 0u64);
+		let mut last_throughput = 0f64;
+
+		loop {
+			tokio::select! {
+				res = workers.next() => match res {
+					None => break,
+					Some(Ok(())) => {}
+					Some(Err(e)) => {
+						warn!("Transfer stream failed, its blocks go through the others: {e}");
+						streams -= 1;
+						// A failing stream hints at congestion, so no more are added
+						probing = false;
+
+						if workers.is_empty() && !scheduler.is_exhausted() {
+							match open_stream().await {
+								Some(stream) => {
+									workers.push(send_blocks(stream, path, &scheduler, &self.on_progress));
+									streams += 1;
+								}
+								None => return Err(TransferError::AllStreamsFailed),
+							}
+						}
+					}
+				},
+				_ = probe.tick(), if probing => {
+					let bytes_sent = scheduler.bytes_sent.load(Ordering::Relaxed);
+					let throughput =
+						(bytes_sent - last_probe.1) as f64 / last_probe.0.elapsed().as_secs_f64();
+					last_probe = (Instant::now(), bytes_sent);
+
+					if streams >= MAX_TRANSFER_STREAMS
+						|| scheduler.is_exhausted()
+						|| throughput < last_throughput * MIN_THROUGHPUT_GAIN
+					{
+						debug!("Transfer settled on {streams} streams at {throughput:.0} B/s");
+						probing = false;
+						continue;
+					}
+					last_throughput = throughput;
+
+					match open_stream().await {
+						Some(stream) => {
+							workers.push(send_blocks(stream, path, &scheduler, &self.on_progress));
+							streams += 1;
+						}
+						None => probing = false,
+					}
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+struct ReceiveState {
+	file: File,
+	received: HashSet<u64>,
+	bytes_received: u64,
+}
+
+/// Receiving side of a transfer spread over several streams, each given to
+/// [`receive_stream`](Self::receive_stream) as the peer opens it
+pub struct ParallelReceiver {
+	req: SpaceblockRequest,
+	state: tokio::sync::Mutex<ReceiveState>,
+	finished: Notify,
+	/// Called with the amount of bytes transferred so far, after each block
+	on_progress: Box<dyn Fn(u64) + Send + Sync>,
+}
+
+impl ParallelReceiver {
+	pub fn new(
+		req: SpaceblockRequest,
+		file: File,
+		on_progress: impl Fn(u64) + Send + Sync + 'static,
+	) -> Self {
+		Self {
+			req,
+			state: tokio::sync::Mutex::new(ReceiveState {
+				file,
+				received: HashSet::new(),
+				bytes_received: 0,
+			}),
+			finished: Notify::new(),
+			on_progress: Box::new(on_progress),
+		}
+	}
+
+	/// Writes the blocks received over a stream of the transfer, until the sender closes it
+	pub async fn receive_stream(
+		&self,
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<(), TransferError> {
+		let mut buf = vec![0u8; self.req.block_size.size() as usize];
+
+		while let Some((offset, size)) = read_block(stream, &mut buf, self.req.size).await? {
+			let mut state = self.state.lock().await;
+
+			// Blocks of failed streams are sent again, they may have made it the first time
+			if !state.received.insert(offset) {
+				continue;
+			}
+
+			state
+				.file
+				.seek(SeekFrom::Start(offset))
+				.await
+				.map_err(TransferError::File)?;
+			state
+				.file
+				.write_all(&buf[..size as usize])
+				.await
+				.map_err(TransferError::File)?;
+
+			state.bytes_received += size;
+			(self.on_progress)(state.bytes_received);
+
+			if state.bytes_received == self.req.size {
+				state.file.flush().await.map_err(TransferError::File)?;
+				self.finished.notify_waiters();
+			}
+		}
+
+		Ok(())
+	}
+
+	pub async fn is_finished(&self) -> bool {
+		self.state.lock().await.bytes_received == self.req.size
+	}
+
+	/// Waits for every block of the file to be received
+	pub async fn finished(&self) {
+		loop {
+			let notified = self.finished.notified();
+			if self.is_finished().await {
+				return;
+			}
+			notified.await;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use super::*;
+	use crate::spaceblock::BlockSize;
+
+	#[tokio::test]
+	async fn test_parallel_transfer() {
+		let dir = std::env::temp_dir().join(format!("sd-p2p-parallel-{}", std::process::id()));
+		tokio::fs::create_dir_all(&dir).await.unwrap();
+		let source = dir.join("source");
+		let destination = dir.join("destination");
+
+		let data = (0..(1024 * 10 + 7)).map(|i| i as u8).collect::<Vec<_>>();
+		tokio::fs::write(&source, &data).await.unwrap();
+
+		let req = SpaceblockRequest {
+			name: "Demo".to_string(),
+			size: data.len() as u64,
+			block_size: BlockSize::dangerously_new(1024),
+		};
+
+		let receiver = Arc::new(ParallelReceiver::new(
+			req.clone(),
+			File::create(&destination).await.unwrap(),
+			|_| {},
+		));
+
+		let (client, mut server) = tokio::io::duplex(64);
+		let first = tokio::spawn({
+			let receiver = receiver.clone();
+			async move { receiver.receive_stream(&mut server).await }
+		});
+
+		let open_stream = || {
+			let receiver = receiver.clone();
+			async move {
+				let (client, mut server) = tokio::io::duplex(64);
+				tokio::spawn(async move { receiver.receive_stream(&mut server).await });
+				Some(client)
+			}
+		};
+
+		ParallelTransfer::new(&req, |_| {})
+			.send(&source, client, open_stream)
+			.await
+			.unwrap();
+
+		first.await.unwrap().unwrap();
+		receiver.finished().await;
+
+		assert_eq!(tokio::fs::read(&destination).await.unwrap(), data);
+
+		tokio::fs::remove_dir_all(&dir).await.ok();
+	}
+}
@@ -0,0 +1,185 @@
+//! Every unicast stream starts with both peers sending the versions of the protocol of the
+//! application they speak and the optional features they support. The stream then uses the
+//! highest version both speak and the features both support, so nodes running different versions
+//! of the application keep working together, and fails with an explicit error when their versions
+//! are too far apart.
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Most capabilities a peer can advertise, and longest name one can have
+const MAX_CAPABILITIES: usize = u8::MAX as usize;
+
+/// What the application on this node speaks, given to [`Manager::new`](crate::Manager::new)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo {
+	/// Version of the protocol of the application, bumped when it changes
+	pub version: u16,
+	/// Oldest version of the protocol the application can still talk to
+	pub min_version: u16,
+	/// Optional features of the protocol, used only if both peers support them
+	pub capabilities: BTreeSet<String>,
+}
+
+impl ProtocolInfo {
+	pub fn new(version: u16, min_version: u16) -> Self {
+		Self {
+			version,
+			min_version,
+			capabilities: BTreeSet::new(),
+		}
+	}
+
+	pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+		self.capabilities.insert(capability.into());
+		self
+	}
+
+	fn to_bytes(&self) -> Result<Vec<u8>, HandshakeError> {
+		if self.capabilities.len() > MAX_CAPABILITIES {
+			return Err(HandshakeError::TooManyCapabilities);
+		}
+
+		let mut buf = Vec::new();
+		buf.extend_from_slice(&self.version.to_le_bytes());
+		buf.extend_from_slice(&self.min_version.to_le_bytes());
+		buf.push(self.capabilities.len() as u8);
+		for capability in &self.capabilities {
+			if capability.is_empty() || capability.len() > MAX_CAPABILITIES {
+				return Err(HandshakeError::InvalidCapability(capability.clone()));
+			}
+
+			buf.push(capability.len() as u8);
+			buf.extend_from_slice(capability.as_bytes());
+		}
+
+		Ok(buf)
+	}
+
+	async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, HandshakeError> {
+		let version = stream.read_u16_le().await?;
+		let min_version = stream.read_u16_le().await?;
+
+		let mut capabilities = BTreeSet::new();
+		for _ in 0..stream.read_u8().await? {
+			let mut buf = vec![0; stream.read_u8().await? as usize];
+			stream.read_exact(&mut buf).await?;
+
+			capabilities.insert(
+				String::from_utf8(buf)
+					.map_err(|e| HandshakeError::InvalidCapability(e.to_string()))?,
+			);
+		}
+
+		Ok(Self {
+			version,
+			min_version,
+			capabilities,
+		})
+	}
+
+	/// What a stream between a peer speaking `self` and one speaking `remote` uses
+	pub fn negotiate(&self, remote: &Self) -> Result<Negotiated, HandshakeError> {
+		if remote.version < self.min_version || self.version < remote.min_version {
+			return Err(HandshakeError::IncompatibleVersions {
+				local: self.version,
+				local_min: self.min_version,
+				remote: remote.version,
+				remote_min: remote.min_version,
+			});
+		}
+
+		Ok(Negotiated {
+			version: self.version.min(remote.version),
+			remote_version: remote.version,
+			capabilities: self
+				.capabilities
+				.intersection(&remote.capabilities)
+				.cloned()
+				.collect(),
+		})
+	}
+}
+
+/// What a stream was negotiated to use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+	/// Highest version of the protocol of the application both peers speak
+	pub version: u16,
+	/// Version of the protocol of the application the peer speaks, which may be newer than ours
+	pub remote_version: u16,
+	/// Capabilities both peers support
+	pub capabilities: BTreeSet<String>,
+}
+
+impl Negotiated {
+	pub fn supports(&self, capability: &str) -> bool {
+		self.capabilities.contains(capability)
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+	#[error("io error during handshake: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("invalid capability '{0}'")]
+	InvalidCapability(String),
+	#[error("too many capabilities, at most {MAX_CAPABILITIES} are supported")]
+	TooManyCapabilities,
+	#[error("incompatible protocol versions, we speak {local} (down to {local_min}) and the peer speaks {remote} (down to {remote_min})")]
+	IncompatibleVersions {
+		local: u16,
+		local_min: u16,
+		remote: u16,
+		remote_min: u16,
+	},
+}
+
+/// Exchanges the protocol info of both peers over a new stream, returning what it uses
+pub(crate) async fn handshake(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	local: &ProtocolInfo,
+) -> Result<Negotiated, HandshakeError> {
+	stream.write_all(&local.to_bytes()?).await?;
+	stream.flush().await?;
+
+	let remote = ProtocolInfo::from_stream(stream).await?;
+
+	local.negotiate(&remote)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_handshake() {
+		let old = ProtocolInfo::new(1, 1).with_capability("a");
+		let new = ProtocolInfo::new(2, 1)
+			.with_capability("a")
+			.with_capability("b");
+
+		let (mut client, mut server) = tokio::io::duplex(64);
+		let (client, server) =
+			tokio::join!(handshake(&mut client, &new), handshake(&mut server, &old));
+
+		let expected = BTreeSet::from(["a".to_string()]);
+		let client = client.unwrap();
+		assert_eq!(
+			(client.version, client.remote_version, &client.capabilities),
+			(1, 1, &expected)
+		);
+		let server = server.unwrap();
+		assert_eq!(
+			(server.version, server.remote_version, &server.capabilities),
+			(1, 2, &expected)
+		);
+
+		assert!(matches!(
+			ProtocolInfo::new(3, 3).negotiate(&old),
+			Err(HandshakeError::IncompatibleVersions { .. })
+		));
+	}
+}
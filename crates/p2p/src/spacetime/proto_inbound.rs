@@ -5,7 +5,7 @@ use std::{
 };
 
 use libp2p::{core::UpgradeInfo, swarm::NegotiatedSubstream, InboundUpgrade};
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::{Manager, ManagerStreamAction, Metadata, PeerId, PeerMessageEvent};
 
@@ -38,7 +38,11 @@ impl<TMetadata: Metadata> InboundUpgrade<NegotiatedSubstream> for InboundProtoco
 				self.peer_id
 			);
 
-			let stream = SpaceTimeStream::from_stream(io).await;
+			let stream = SpaceTimeStream::from_stream(io, &self.manager.protocol)
+				.await
+				.map_err(|e| {
+					warn!("stream({}, {id}): handshake failed: {e}", self.peer_id);
+				})?;
 			debug!(
 				"stream({}, {id}): stream of type {} accepted",
 				self.peer_id,
@@ -12,12 +12,12 @@ use libp2p::{
 use tokio::sync::oneshot;
 use tracing::error;
 
-use super::{SpaceTimeProtocolName, UnicastStream, BROADCAST_DISCRIMINATOR};
+use super::{SpaceTimeProtocolName, BROADCAST_DISCRIMINATOR};
 
 #[derive(Debug)]
 pub enum OutboundRequest {
 	Broadcast(Vec<u8>),
-	Unicast(oneshot::Sender<UnicastStream>),
+	Unicast(oneshot::Sender<NegotiatedSubstream>),
 }
 
 pub struct OutboundProtocol(pub(crate) &'static [u8], pub(crate) OutboundRequest);
@@ -61,8 +61,8 @@ impl OutboundUpgrade<NegotiatedSubstream> for OutboundProtocol {
 				});
 			}
 			OutboundRequest::Unicast(sender) => {
-				// We write the discriminator to the stream in the `Manager::stream` method before returning the stream to the user to make async a tad nicer.
-				sender.send(UnicastStream::new(io)).unwrap();
+				// We write the discriminator and do the handshake in the `Manager::stream` method before returning the stream to the user to make async a tad nicer.
+				sender.send(io).unwrap();
 			}
 		}
 
@@ -11,6 +11,8 @@ use tokio::io::{
 use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
 use tracing::error;
 
+use super::{handshake, HandshakeError, Negotiated, ProtocolInfo};
+
 pub const BROADCAST_DISCRIMINATOR: u8 = 0;
 pub const UNICAST_DISCRIMINATOR: u8 = 1;
 
@@ -21,12 +23,18 @@ pub enum SpaceTimeStream {
 }
 
 impl SpaceTimeStream {
-	pub(crate) async fn from_stream(io: NegotiatedSubstream) -> Self {
+	pub(crate) async fn from_stream(
+		io: NegotiatedSubstream,
+		protocol: &ProtocolInfo,
+	) -> Result<Self, HandshakeError> {
 		let mut io = io.compat();
 		let discriminator = io.read_u8().await.unwrap(); // TODO: Timeout on this
 		match discriminator {
-			BROADCAST_DISCRIMINATOR => Self::Broadcast(BroadcastStream(Some(io))),
-			UNICAST_DISCRIMINATOR => Self::Unicast(UnicastStream(io)),
+			BROADCAST_DISCRIMINATOR => Ok(Self::Broadcast(BroadcastStream(Some(io)))),
+			UNICAST_DISCRIMINATOR => {
+				let negotiated = handshake(&mut io, protocol).await?;
+				Ok(Self::Unicast(UnicastStream { io, negotiated }))
+			}
 			_ => todo!(), // TODO: Error handling
 		}
 	}
@@ -50,7 +58,7 @@ impl SpaceTimeStream {
 					Ok(())
 				}
 			}
-			Self::Unicast(stream) => stream.0.into_inner().close().await,
+			Self::Unicast(stream) => stream.io.into_inner().close().await,
 		}
 	}
 }
@@ -109,22 +117,34 @@ impl Drop for BroadcastStream {
 
 /// A unicast stream is a direct stream to a specific peer.
 #[derive(Debug)]
-pub struct UnicastStream(Compat<NegotiatedSubstream>);
+pub struct UnicastStream {
+	io: Compat<NegotiatedSubstream>,
+	negotiated: Negotiated,
+}
 
 // TODO: Utils for sending msgpack and stuff over the stream. -> Have a max size of reading buffers so we are less susceptible to DoS attacks.
 
 impl UnicastStream {
-	pub(crate) fn new(io: NegotiatedSubstream) -> Self {
-		Self(io.compat())
+	/// Opens the stream on our side, returning once the peer answered the handshake
+	pub(crate) async fn connect(
+		io: NegotiatedSubstream,
+		protocol: &ProtocolInfo,
+	) -> Result<Self, HandshakeError> {
+		let mut io = io.compat();
+		// TODO: Timeout if the peer doesn't accept the byte quick enough
+		io.write_all(&[UNICAST_DISCRIMINATOR]).await?;
+		let negotiated = handshake(&mut io, protocol).await?;
+
+		Ok(Self { io, negotiated })
 	}
 
-	pub(crate) async fn write_discriminator(&mut self) -> io::Result<()> {
-		// TODO: Timeout if the peer doesn't accept the byte quick enough
-		self.0.write_all(&[UNICAST_DISCRIMINATOR]).await
+	/// Version of the protocol and capabilities the stream uses
+	pub fn negotiated(&self) -> &Negotiated {
+		&self.negotiated
 	}
 
 	pub async fn close(self) -> Result<(), io::Error> {
-		self.0.into_inner().close().await
+		self.io.into_inner().close().await
 	}
 }
 
@@ -134,7 +154,7 @@ impl AsyncRead for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &mut ReadBuf<'_>,
 	) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+		Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
 	}
 }
 
@@ -144,14 +164,14 @@ impl AsyncWrite for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &[u8],
 	) -> Poll<io::Result<usize>> {
-		Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+		Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
 	}
 
 	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_flush(cx)
+		Pin::new(&mut self.get_mut().io).poll_flush(cx)
 	}
 
 	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+		Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
 	}
 }
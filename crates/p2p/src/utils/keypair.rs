@@ -25,6 +25,18 @@ impl Keypair {
 	pub fn inner(&self) -> libp2p::identity::Keypair {
 		self.0.clone().into()
 	}
+
+	/// Signs a message, which anyone knowing the peer id of the node can verify it signed
+	pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+		self.0.sign(msg)
+	}
+
+	/// Secret half of the keypair, to derive keys only this node knows from
+	pub fn secret(&self) -> [u8; 32] {
+		let mut secret = [0; 32];
+		secret.copy_from_slice(self.0.secret().as_ref());
+		secret
+	}
 }
 
 impl Serialize for Keypair {
@@ -0,0 +1,101 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use image::DynamicImage;
+use pdfium_render::prelude::{PdfRenderConfig, Pdfium, PdfiumError};
+use thiserror::Error;
+
+type PdfResult<T> = Result<T, PdfError>;
+
+/// The maximum file size that a document can be in order to have a thumbnail generated.
+///
+/// This value is in MiB.
+const PDF_MAXIMUM_FILE_SIZE: u64 = 1048576 * 100;
+
+/// Name of the LibreOffice binary used to convert office documents to PDF
+const SOFFICE_BIN: &str = if cfg!(target_os = "windows") {
+	"soffice.exe"
+} else {
+	"soffice"
+};
+
+#[derive(Error, Debug)]
+pub enum PdfError {
+	#[error("error with pdfium: {0}")]
+	Pdfium(#[from] PdfiumError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("the document has no pages")]
+	NoPages,
+	#[error("the document provided is too large (over 100MiB)")]
+	TooLarge,
+	#[error("failed to convert the document to PDF: {0}")]
+	Conversion(String),
+}
+
+/// Renders the first page of a PDF, `target_width` pixels wide.
+///
+/// The pdfium library is loaded at runtime, from the current directory first and then from the system.
+pub fn pdf_to_dynamic_image(path: &Path, target_width: u16) -> PdfResult<DynamicImage> {
+	if fs::metadata(path)?.len() > PDF_MAXIMUM_FILE_SIZE {
+		return Err(PdfError::TooLarge);
+	}
+
+	let pdfium = Pdfium::new(
+		Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
+			.or_else(|_| Pdfium::bind_to_system_library())?,
+	);
+
+	let document = pdfium.load_pdf_from_file(path, None)?;
+	let page = document.pages().first().map_err(|_| PdfError::NoPages)?;
+
+	Ok(page
+		.render_with_config(
+			&PdfRenderConfig::new()
+				.set_target_width(target_width)
+				.render_form_data(false)
+				.render_annotations(true),
+		)?
+		.as_image())
+}
+
+/// Converts an office document (DOCX, PPTX, ODT, ...) to PDF with a headless LibreOffice,
+/// writing it to `output_dir` and returning its path.
+///
+/// This runs the conversion synchronously and can take a few seconds.
+pub fn office_to_pdf(path: &Path, output_dir: &Path) -> PdfResult<PathBuf> {
+	if fs::metadata(path)?.len() > PDF_MAXIMUM_FILE_SIZE {
+		return Err(PdfError::TooLarge);
+	}
+
+	let output = Command::new(SOFFICE_BIN)
+		.arg("--headless")
+		.arg("--convert-to")
+		.arg("pdf")
+		.arg("--outdir")
+		.arg(output_dir)
+		.arg(path)
+		.output()?;
+
+	if !output.status.success() {
+		return Err(PdfError::Conversion(
+			String::from_utf8_lossy(&output.stderr).into_owned(),
+		));
+	}
+
+	let pdf_path = output_dir
+		.join(path.file_stem().unwrap_or_default())
+		.with_extension("pdf");
+
+	if !pdf_path.exists() {
+		return Err(PdfError::Conversion(format!(
+			"LibreOffice didn't produce {}",
+			pdf_path.display()
+		)));
+	}
+
+	Ok(pdf_path)
+}
@@ -0,0 +1,208 @@
+use std::{fs, io::Cursor, ops::Range, path::Path};
+
+use image::{io::Reader, DynamicImage, ImageFormat, ImageOutputFormat, RgbImage};
+use thiserror::Error;
+
+type RawResult<T> = Result<T, RawError>;
+
+/// The maximum file size that a RAW photo can be in order to have a thumbnail generated.
+///
+/// This value is in MiB.
+const RAW_MAXIMUM_FILE_SIZE: u64 = 1048576 * 200;
+
+/// Embedded previews smaller than this are only the tiny EXIF thumbnails, which look blurry
+/// once scaled, so the RAW data is demosaiced instead.
+const MINIMUM_PREVIEW_WIDTH: u32 = 640;
+
+/// Bounds of the demosaiced image, decoding at full resolution is way too slow for previews
+const MAXIMUM_DEMOSAIC_DIMENSION: usize = 2560;
+
+/// Quality of the JPEG encoded from demosaiced images
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Error, Debug)]
+pub enum RawError {
+	#[error("error while loading the image (via the `image` crate): {0}")]
+	Image(#[from] image::ImageError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("error while demosaicing the raw data: {0}")]
+	Demosaic(String),
+	#[error("there was an error while converting the image to an `RgbImage`")]
+	RgbImageConversion,
+	#[error("the image provided is too large (over 200MiB)")]
+	TooLarge,
+}
+
+/// Decodes a RAW photo (CR2, CR3, NEF, ARW, DNG, ...), using the JPEG preview embedded by the
+/// camera when there is a big enough one, and demosaicing the RAW data otherwise.
+///
+/// Formats not supported by the demosaic pipeline, like CR3, can only be decoded through their
+/// embedded preview.
+pub fn raw_to_dynamic_image(path: &Path) -> RawResult<DynamicImage> {
+	let bytes = read_raw_file(path)?;
+
+	match find_embedded_jpeg(&bytes) {
+		Some(range) => Ok(image::load_from_memory_with_format(
+			&bytes[range],
+			ImageFormat::Jpeg,
+		)?),
+		None => demosaic(path),
+	}
+}
+
+/// Same as [`raw_to_dynamic_image`], but returns a JPEG, ready to be displayed. The embedded
+/// preview is returned as is, without being re-encoded.
+pub fn raw_to_jpeg(path: &Path) -> RawResult<Vec<u8>> {
+	let bytes = read_raw_file(path)?;
+
+	if let Some(range) = find_embedded_jpeg(&bytes) {
+		return Ok(bytes[range].to_vec());
+	}
+
+	let mut jpeg = Cursor::new(vec![]);
+	demosaic(path)?.write_to(&mut jpeg, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+
+	Ok(jpeg.into_inner())
+}
+
+fn read_raw_file(path: &Path) -> RawResult<Vec<u8>> {
+	if fs::metadata(path)?.len() > RAW_MAXIMUM_FILE_SIZE {
+		return Err(RawError::TooLarge);
+	}
+
+	Ok(fs::read(path)?)
+}
+
+fn demosaic(path: &Path) -> RawResult<DynamicImage> {
+	let decoded =
+		imagepipe::simple_decode_8bit(path, MAXIMUM_DEMOSAIC_DIMENSION, MAXIMUM_DEMOSAIC_DIMENSION)
+			.map_err(RawError::Demosaic)?;
+
+	let rgb_img = RgbImage::from_raw(decoded.width as u32, decoded.height as u32, decoded.data)
+		.ok_or(RawError::RgbImageConversion)?;
+
+	Ok(DynamicImage::ImageRgb8(rgb_img))
+}
+
+/// Finds the biggest JPEG embedded in the file, if it is wide enough to be used as a preview.
+///
+/// RAW formats store their previews in very different ways (TIFF IFDs, ISO BMFF boxes, maker
+/// notes...), so instead of parsing each of them, we look for JPEG streams directly.
+fn find_embedded_jpeg(bytes: &[u8]) -> Option<Range<usize>> {
+	let mut best: Option<(Range<usize>, u32)> = None;
+	let mut offset = 0;
+
+	while let Some(start) = find_jpeg_start(bytes, offset) {
+		let Some(len) = jpeg_len(&bytes[start..]) else {
+			offset = start + 1;
+			continue;
+		};
+
+		let range = start..start + len;
+		if let Ok((width, height)) =
+			Reader::with_format(Cursor::new(&bytes[range.clone()]), ImageFormat::Jpeg)
+				.into_dimensions()
+		{
+			if width >= MINIMUM_PREVIEW_WIDTH
+				&& best
+					.as_ref()
+					.map_or(true, |(_, best_area)| width * height > *best_area)
+			{
+				best = Some((range.clone(), width * height));
+			}
+		}
+
+		// JPEGs nested in this one are its own EXIF thumbnails, so they're skipped
+		offset = range.end;
+	}
+
+	best.map(|(range, _)| range)
+}
+
+fn find_jpeg_start(bytes: &[u8], offset: usize) -> Option<usize> {
+	bytes
+		.get(offset..)?
+		.windows(3)
+		.position(|window| window == [0xFF, 0xD8, 0xFF])
+		.map(|position| offset + position)
+}
+
+/// Length of the JPEG stream at the start of `bytes`, up to and including its EOI marker
+fn jpeg_len(bytes: &[u8]) -> Option<usize> {
+	let mut pos = 2; // SOI
+
+	loop {
+		if *bytes.get(pos)? != 0xFF {
+			return None;
+		}
+
+		let marker = *bytes.get(pos + 1)?;
+		pos += 2;
+
+		match marker {
+			// EOI
+			0xD9 => return Some(pos),
+			// Fill bytes before a marker
+			0xFF => pos -= 1,
+			// Markers without a payload
+			0x01 | 0xD0..=0xD7 => {}
+			_ => {
+				let segment_len =
+					u16::from_be_bytes([*bytes.get(pos)?, *bytes.get(pos + 1)?]) as usize;
+				pos += segment_len;
+
+				// SOS, the entropy coded data that follows ends at the next marker which
+				// isn't a stuffed byte or a restart marker
+				if marker == 0xDA {
+					loop {
+						if *bytes.get(pos)? == 0xFF {
+							match *bytes.get(pos + 1)? {
+								0x00 | 0xD0..=0xD7 => pos += 2,
+								_ => break,
+							}
+						} else {
+							pos += 1;
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn jpeg(width: u32, height: u32) -> Vec<u8> {
+		let mut buf = Cursor::new(vec![]);
+		DynamicImage::ImageRgb8(RgbImage::new(width, height))
+			.write_to(&mut buf, ImageOutputFormat::Jpeg(80))
+			.unwrap();
+
+		buf.into_inner()
+	}
+
+	#[test]
+	fn test_find_embedded_jpeg() {
+		let thumbnail = jpeg(160, 120);
+		let preview = jpeg(1024, 768);
+
+		let mut raw = b"II*\0 some raw data".to_vec();
+		raw.extend_from_slice(&thumbnail);
+		raw.extend_from_slice(&[0x12, 0xFF, 0xD8, 0x34]);
+		let preview_start = raw.len();
+		raw.extend_from_slice(&preview);
+		raw.extend_from_slice(b"more raw data");
+
+		assert_eq!(jpeg_len(&preview), Some(preview.len()));
+		assert_eq!(
+			find_embedded_jpeg(&raw),
+			Some(preview_start..preview_start + preview.len())
+		);
+
+		// Only the EXIF thumbnail, which is too small to be used
+		assert_eq!(find_embedded_jpeg(&thumbnail), None);
+	}
+}
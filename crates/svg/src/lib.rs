@@ -0,0 +1,85 @@
+use std::{fs, path::Path};
+
+use image::{DynamicImage, RgbaImage};
+use once_cell::sync::Lazy;
+use resvg::{
+	tiny_skia::{Pixmap, Transform},
+	usvg::{self, fontdb::Database, ImageHrefResolver, TreeParsing, TreeTextToPath},
+};
+use thiserror::Error;
+
+type SvgResult<T> = Result<T, SvgError>;
+
+/// The maximum file size that an SVG can be in order to have a thumbnail generated.
+///
+/// This value is in MiB.
+const SVG_MAXIMUM_FILE_SIZE: u64 = 1048576 * 20;
+
+/// Fonts installed on the system, used to render text, which is loaded only once as it's slow
+static FONTS: Lazy<Database> = Lazy::new(|| {
+	let mut fonts = Database::new();
+	fonts.load_system_fonts();
+	fonts
+});
+
+#[derive(Error, Debug)]
+pub enum SvgError {
+	#[error("error while parsing the svg: {0}")]
+	Usvg(#[from] usvg::Error),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("the svg has an invalid size")]
+	InvalidSize,
+	#[error("there was an error while converting the image to an `RgbaImage`")]
+	RgbaImageConversion,
+	#[error("the image provided is too large (over 20MiB)")]
+	TooLarge,
+}
+
+/// Rasterizes an SVG, so its biggest side is `max_dimension` pixels long.
+///
+/// External resources, like linked images, are never loaded, so rendering untrusted SVGs can't
+/// read arbitrary files or reach the network. Only images embedded as data URLs are drawn.
+pub fn svg_to_dynamic_image(path: &Path, max_dimension: u32) -> SvgResult<DynamicImage> {
+	if fs::metadata(path)?.len() > SVG_MAXIMUM_FILE_SIZE {
+		return Err(SvgError::TooLarge);
+	}
+
+	let data = fs::read(path)?;
+
+	let options = usvg::Options {
+		resources_dir: None,
+		image_href_resolver: ImageHrefResolver {
+			resolve_string: Box::new(|_: &str, _: &usvg::Options| None),
+			..Default::default()
+		},
+		..Default::default()
+	};
+
+	let mut tree = usvg::Tree::from_data(&data, &options)?;
+	tree.convert_text(&FONTS);
+
+	let tree = resvg::Tree::from_usvg(&tree);
+
+	let size = tree.size.to_int_size();
+	let scale = max_dimension as f32 / size.width().max(size.height()) as f32;
+	let size = size.scale_by(scale).ok_or(SvgError::InvalidSize)?;
+
+	let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or(SvgError::InvalidSize)?;
+	tree.render(Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+	// Pixmaps are stored with premultiplied alpha, while images aren't
+	let data = pixmap
+		.pixels()
+		.iter()
+		.flat_map(|pixel| {
+			let color = pixel.demultiply();
+			[color.red(), color.green(), color.blue(), color.alpha()]
+		})
+		.collect();
+
+	let rgba_img = RgbaImage::from_raw(size.width(), size.height(), data)
+		.ok_or(SvgError::RgbaImageConversion)?;
+
+	Ok(DynamicImage::ImageRgba8(rgba_img))
+}
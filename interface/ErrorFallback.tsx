@@ -1,7 +1,7 @@
 import { captureException } from '@sentry/browser';
 import { FallbackProps } from 'react-error-boundary';
 import { useRouteError } from 'react-router';
-import { useDebugState } from '@sd/client';
+import { errorMessage, useDebugState } from '@sd/client';
 import { Button } from '@sd/ui';
 import { useOperatingSystem, useTheme } from './hooks';
 
@@ -9,7 +9,7 @@ export function RouterErrorBoundary() {
 	const error = useRouteError();
 	return (
 		<ErrorPage
-			message={(error as any).toString()}
+			message={errorMessage(error)}
 			sendReportBtn={() => {
 				captureException(error);
 				location.reload();
@@ -23,7 +23,7 @@ export function RouterErrorBoundary() {
 
 export default ({ error, resetErrorBoundary }: FallbackProps) => (
 	<ErrorPage
-		message={`Error: ${error.message}`}
+		message={`Error: ${errorMessage(error)}`}
 		sendReportBtn={() => {
 			captureException(error);
 			resetErrorBoundary();
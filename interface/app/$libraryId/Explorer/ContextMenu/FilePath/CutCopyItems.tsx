@@ -1,5 +1,5 @@
 import { Copy, Scissors } from 'phosphor-react';
-import { FilePath, useLibraryMutation } from '@sd/client';
+import { FilePath, errorMessage, useLibraryMutation } from '@sd/client';
 import { ContextMenu, ModifierKeys } from '@sd/ui';
 import { showAlertDialog } from '~/components';
 import { useKeybindFactory } from '~/hooks/useKeybindFactory';
@@ -64,7 +64,7 @@ export const CutCopyItems = ({ locationId, filePath }: Props) => {
 					} catch (error) {
 						showAlertDialog({
 							title: 'Error',
-							value: `Failed to duplcate file, due to an error: ${error}`
+							value: `Failed to duplcate file, due to an error: ${errorMessage(error)}`
 						});
 					}
 				}}
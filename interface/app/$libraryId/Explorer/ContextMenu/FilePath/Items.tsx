@@ -1,5 +1,5 @@
 import { Image, Package, Trash, TrashSimple } from 'phosphor-react';
-import { FilePath, useLibraryContext, useLibraryMutation } from '@sd/client';
+import { FilePath, errorMessage, useLibraryContext, useLibraryMutation } from '@sd/client';
 import { ContextMenu, ModifierKeys, dialogManager } from '@sd/ui';
 import { showAlertDialog } from '~/components';
 import { useKeybindFactory } from '~/hooks/useKeybindFactory';
@@ -144,7 +144,7 @@ export const ParentFolderActions = ({
 					} catch (error) {
 						showAlertDialog({
 							title: 'Error',
-							value: `Failed to rescan location, due to an error: ${error}`
+							value: `Failed to rescan location, due to an error: ${errorMessage(error)}`
 						});
 					}
 				}}
@@ -161,7 +161,7 @@ export const ParentFolderActions = ({
 					} catch (error) {
 						showAlertDialog({
 							title: 'Error',
-							value: `Failed to generate thumbnails, due to an error: ${error}`
+							value: `Failed to generate thumbnails, due to an error: ${errorMessage(error)}`
 						});
 					}
 				}}
@@ -198,7 +198,7 @@ export const OpenOrDownload = ({ filePath }: { filePath: FilePath }) => {
 							} catch (error) {
 								showAlertDialog({
 									title: 'Error',
-									value: `Failed to open file, due to an error: ${error}`
+									value: `Failed to open file, due to an error: ${errorMessage(error)}`
 								});
 							}
 						}}
@@ -1,5 +1,11 @@
 import { ArrowBendUpRight, TagSimple } from 'phosphor-react';
-import { FilePath, ObjectKind, Object as ObjectType, useLibraryMutation } from '@sd/client';
+import {
+	FilePath,
+	ObjectKind,
+	Object as ObjectType,
+	errorMessage,
+	useLibraryMutation
+} from '@sd/client';
 import { ContextMenu } from '@sd/ui';
 import { showAlertDialog } from '~/components';
 import AssignTagMenuItems from './AssignTagMenuItems';
@@ -18,7 +24,7 @@ export const RemoveFromRecents = ({ object }: { object: ObjectType }) => {
 						} catch (error) {
 							showAlertDialog({
 								title: 'Error',
-								value: `Failed to remove file from recents, due to an error: ${error}`
+								value: `Failed to remove file from recents, due to an error: ${errorMessage(error)}`
 							});
 						}
 					}}
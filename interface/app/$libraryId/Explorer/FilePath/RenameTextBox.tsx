@@ -9,7 +9,7 @@ import {
 	useState
 } from 'react';
 import { useKey } from 'rooks';
-import { useLibraryMutation, useRspcLibraryContext } from '@sd/client';
+import { errorMessage, useLibraryMutation, useRspcLibraryContext } from '@sd/client';
 import { Tooltip } from '~/../packages/ui/src';
 import { showAlertDialog } from '~/components';
 import { useIsTextTruncated, useOperatingSystem } from '~/hooks';
@@ -237,7 +237,7 @@ export const RenamePathTextBox = ({
 		} catch (e) {
 			showAlertDialog({
 				title: 'Error',
-				value: `Could not rename ${fileName} to ${newName}, due to an error: ${e}`
+				value: `Could not rename ${fileName} to ${newName}, due to an error: ${errorMessage(e)}`
 			});
 		}
 	}
@@ -276,7 +276,7 @@ export const RenameLocationTextBox = (props: Omit<Props, 'renameHandler'>) => {
 		} catch (e) {
 			showAlertDialog({
 				title: 'Error',
-				value: String(e)
+				value: errorMessage(e)
 			});
 		}
 	}
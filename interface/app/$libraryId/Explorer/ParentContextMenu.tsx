@@ -1,6 +1,6 @@
 import { Clipboard, FileX, Image, Plus, Repeat, Share, ShieldCheck } from 'phosphor-react';
 import { PropsWithChildren } from 'react';
-import { useLibraryMutation } from '@sd/client';
+import { errorMessage, useLibraryMutation } from '@sd/client';
 import { ContextMenu as CM, ModifierKeys } from '@sd/ui';
 import { showAlertDialog } from '~/components';
 import { useOperatingSystem } from '~/hooks';
@@ -63,7 +63,7 @@ export default (props: PropsWithChildren) => {
 							} catch (error) {
 								showAlertDialog({
 									title: 'Error',
-									value: `Failed to ${actionType.toLowerCase()} file, due to an error: ${error}`
+									value: `Failed to ${actionType.toLowerCase()} file, due to an error: ${errorMessage(error)}`
 								});
 							}
 						}}
@@ -115,7 +115,7 @@ export default (props: PropsWithChildren) => {
 								} catch (error) {
 									showAlertDialog({
 										title: 'Error',
-										value: `Failed to re-index location, due to an error: ${error}`
+										value: `Failed to re-index location, due to an error: ${errorMessage(error)}`
 									});
 								}
 							}}
@@ -133,7 +133,7 @@ export default (props: PropsWithChildren) => {
 								} catch (error) {
 									showAlertDialog({
 										title: 'Error',
-										value: `Failed to generate thumbanails, due to an error: ${error}`
+										value: `Failed to generate thumbanails, due to an error: ${errorMessage(error)}`
 									});
 								}
 							}}
@@ -151,7 +151,7 @@ export default (props: PropsWithChildren) => {
 								} catch (error) {
 									showAlertDialog({
 										title: 'Error',
-										value: `Failed to generate checksum, due to an error: ${error}`
+										value: `Failed to generate checksum, due to an error: ${errorMessage(error)}`
 									});
 								}
 							}}
@@ -15,6 +15,7 @@ import { createPortal } from 'react-dom';
 import { createSearchParams, useNavigate } from 'react-router-dom';
 import {
 	ExplorerItem,
+	errorMessage,
 	getExplorerItemData,
 	getItemFilePath,
 	getItemLocation,
@@ -262,7 +263,7 @@ const useKeyDownHandlers = ({
 			} catch (error) {
 				showAlertDialog({
 					title: 'Error',
-					value: `Couldn't open file, due to an error: ${error}`
+					value: `Couldn't open file, due to an error: ${errorMessage(error)}`
 				});
 			}
 		},
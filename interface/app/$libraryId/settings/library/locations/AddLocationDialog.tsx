@@ -5,6 +5,7 @@ import { Controller, get } from 'react-hook-form';
 import { useDebouncedCallback } from 'use-debounce';
 import {
 	UnionToTuple,
+	errorMessage,
 	extractInfoRSPCError,
 	useLibraryMutation,
 	useLibraryQuery,
@@ -27,8 +28,11 @@ const REMOTE_ERROR_FORM_MESSAGE = {
 
 type RemoteErrorFormMessage = keyof typeof REMOTE_ERROR_FORM_MESSAGE;
 
-const isRemoteErrorFormMessage = (message: unknown): message is RemoteErrorFormMessage =>
-	typeof message === 'string' && Object.hasOwnProperty.call(REMOTE_ERROR_FORM_MESSAGE, message);
+// Codes of the core errors asking for another method to add the location
+const REMOTE_ERROR_CODE_METHOD: Record<string, RemoteErrorFormMessage | undefined> = {
+	LOCATION_IN_OTHER_LIBRARY: 'ADD_LIBRARY',
+	LOCATION_NEEDS_RELINK: 'NEED_RELINK'
+};
 
 const schema = z.object({
 	path: z.string().min(1),
@@ -135,16 +139,18 @@ export const AddLocationDialog = ({
 			if (!rspcErrorInfo || rspcErrorInfo.code === 500) return false;
 
 			let { message } = rspcErrorInfo;
-			if (rspcErrorInfo.code == 409 && isRemoteErrorFormMessage(message)) {
+			const remoteMethod =
+				rspcErrorInfo.coreError && REMOTE_ERROR_CODE_METHOD[rspcErrorInfo.coreError.code];
+			if (remoteMethod) {
 				/**
 				 * TODO: On NEED_RELINK, we should query the backend for
 				 * the current location indexer_rules_ids, then update the checkboxes
 				 * accordingly. However we don't have the location id at this point.
 				 * Maybe backend could return the location id in the error?
 				 */
-				if (form.getValues().method !== message) {
-					form.setValue('method', message);
-					message = REMOTE_ERROR_FORM_MESSAGE[message];
+				if (form.getValues().method !== remoteMethod) {
+					form.setValue('method', remoteMethod);
+					message = REMOTE_ERROR_FORM_MESSAGE[remoteMethod];
 				} else {
 					message = '';
 				}
@@ -193,7 +199,7 @@ export const AddLocationDialog = ({
 
 			showAlertDialog({
 				title: 'Error',
-				value: String(error) || 'Failed to add location'
+				value: errorMessage(error) || 'Failed to add location'
 			});
 
 			return;
@@ -2,7 +2,7 @@ import clsx from 'clsx';
 import { Trash } from 'phosphor-react';
 import { MouseEventHandler, useState } from 'react';
 import { ControllerRenderProps } from 'react-hook-form';
-import { IndexerRule, useLibraryMutation, useLibraryQuery } from '@sd/client';
+import { IndexerRule, errorMessage, useLibraryMutation, useLibraryQuery } from '@sd/client';
 import { Button, Divider, Label } from '@sd/ui';
 import { InfoText } from '@sd/ui/src/forms';
 import { showAlertDialog } from '~/components';
@@ -52,7 +52,7 @@ export default function IndexerRuleEditor<T extends IndexerRuleIdFieldType>({
 				} catch (error) {
 					showAlertDialog({
 						title: 'Error',
-						value: String(error) || 'Failed to delete rule'
+						value: errorMessage(error) || 'Failed to delete rule'
 					});
 				} finally {
 					setIsDeleting(false);
@@ -1,5 +1,5 @@
 import { useQueryClient } from '@tanstack/react-query';
-import { useBridgeMutation, usePlausibleEvent } from '@sd/client';
+import { errorMessage, useBridgeMutation, usePlausibleEvent } from '@sd/client';
 import { Dialog, UseDialogProps, useDialog, useZodForm, z } from '@sd/ui';
 
 interface Props extends UseDialogProps {
@@ -26,7 +26,7 @@ export default function DeleteLibraryDialog(props: Props) {
 				}
 			});
 		} catch (e) {
-			alert(`Failed to delete library: ${e}`);
+			alert(`Failed to delete library: ${errorMessage(e)}`);
 		}
 	});
 
@@ -75,7 +75,7 @@ function SpacedropDialog(props: UseDialogProps) {
 			onSubmit={form.handleSubmit((data) =>
 				doSpacedrop.mutateAsync({
 					file_path: getSpacedropState().droppedFiles,
-					peer_id: data.target_peer
+					peer_ids: [data.target_peer]
 				})
 			)}
 		>
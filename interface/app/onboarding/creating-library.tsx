@@ -2,6 +2,7 @@ import { useQueryClient } from '@tanstack/react-query';
 import { useEffect, useRef, useState } from 'react';
 import { useNavigate } from 'react-router';
 import {
+	errorMessage,
 	resetOnboardingStore,
 	telemetryStore,
 	useBridgeMutation,
@@ -39,7 +40,7 @@ export default function OnboardingCreatingLibrary() {
 		},
 		onError: (e) => {
 			// resetOnboardingStore();
-			alert(`Failed to create library. Error: ${e.message}`);
+			alert(`Failed to create library. Error: ${errorMessage(e)}`);
 			navigate('/onboarding/privacy');
 		}
 	});
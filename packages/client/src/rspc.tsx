@@ -111,8 +111,39 @@ export function useInvalidateQuery() {
 	});
 }
 
+/**
+ * An error of the core, sent as JSON in the message of rspc errors.
+ * `code` is stable and meant to be matched on, `message` isn't.
+ * The list of codes is returned by `errors.codes`.
+ */
+export interface CoreError {
+	code: string;
+	severity: 'info' | 'warning' | 'error' | 'critical';
+	message: string;
+	hint: string | null;
+}
+
+export function parseCoreError(message: string): CoreError | null {
+	try {
+		const error = JSON.parse(message);
+		return typeof error?.code === 'string' ? error : null;
+	} catch {
+		return null;
+	}
+}
+
 // TODO: Remove/fix this when rspc typesafe errors are working
 export function extractInfoRSPCError(error: unknown) {
 	if (!(error instanceof AlphaRSPCError)) return null;
-	return error;
+
+	// Not every error of the core is coded yet
+	const coreError = parseCoreError(error.message);
+	return { code: error.code, message: coreError?.message ?? error.message, coreError };
+}
+
+/** What to show users of an error, the message of core errors rather than their JSON */
+export function errorMessage(error: unknown) {
+	const rspcErrorInfo = extractInfoRSPCError(error);
+	if (rspcErrorInfo) return rspcErrorInfo.message;
+	return error instanceof Error ? error.message : String(error);
 }